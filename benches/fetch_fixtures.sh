@@ -0,0 +1,30 @@
+#!/bin/sh
+# Download the benchmark inputs listed in fixtures.txt. See that file for details.
+set -eu
+
+here=$(cd "$(dirname "$0")" && pwd)
+dir=${SECOND_OPINION_FIXTURES:-$here/fixtures}
+mkdir -p "$dir"
+
+grep -v '^#' "$here/fixtures.txt" | while read -r file url; do
+    [ -n "$file" ] || continue
+    if [ -e "$dir/$file" ]; then
+        echo "have $file"
+        continue
+    fi
+    echo "fetching $file"
+    curl -fsSL -o "$dir/$file.part" "$url"
+    mv "$dir/$file.part" "$dir/$file"
+done
+
+for mm1 in "$dir"/*.mm1; do
+    [ -e "$mm1" ] || continue
+    mmb=${mm1%.mm1}.mmb
+    [ -e "$mmb" ] && continue
+    if command -v mm0-rs >/dev/null 2>&1; then
+        echo "compiling $(basename "$mmb")"
+        mm0-rs compile "$mm1" "$mmb"
+    else
+        echo "mm0-rs isn't installed, so $(basename "$mmb") can't be built from $(basename "$mm1")"
+    fi
+done
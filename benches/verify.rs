@@ -0,0 +1,87 @@
+//! `cargo bench`. Header and table parsing, and whole-file verification of `short.mmb`,
+//! of two generated files with one big proof each (see `testgen`), and of `peano.mmb`.
+//!
+//! `peano.mmb` is too big to keep in the repository, so it's read from `benches/fixtures/`
+//! (or the directory in `SECOND_OPINION_FIXTURES`), where `benches/fetch_fixtures.sh`
+//! puts it. The benchmarks that need it are skipped if it isn't there.
+
+use std::path::PathBuf;
+use criterion::{ criterion_group, criterion_main, black_box, Criterion, Throughput };
+use second_opinion::{ Outline, verify_serial };
+use second_opinion::fs::FileData;
+use second_opinion::mmb::parse_header;
+use second_opinion::testgen::Fixture;
+
+fn fixtures_dir() -> PathBuf {
+    match std::env::var_os("SECOND_OPINION_FIXTURES") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches").join("fixtures"),
+    }
+}
+
+/// A downloaded fixture, or `None` if it hasn't been fetched.
+fn downloaded(name: &str) -> Option<FileData> {
+    let path = fixtures_dir().join(name);
+    if !path.exists() {
+        eprintln!("skipping {}: {} not found; run benches/fetch_fixtures.sh", name, path.display());
+        return None
+    }
+    Some(FileData::new_from(path, None::<PathBuf>).unwrap())
+}
+
+fn short() -> FileData {
+    FileData::new_from(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_resources/short.mmb"), None::<PathBuf>).unwrap()
+}
+
+/// Every file, by name.
+fn inputs() -> Vec<(&'static str, FileData)> {
+    let mut out = vec![
+        ("short", short()),
+        // One proof of about 4k commands, building a term 2000 deep.
+        ("chain", FileData::new_from_bytes(Fixture::chain(2000).to_bytes(), Vec::new())),
+        // One proof of about 22k commands, most of them conversion.
+        ("unfolding", FileData::new_from_bytes(Fixture::unfolding(2000).to_bytes(), Vec::new())),
+    ];
+    out.extend(downloaded("peano.mmb").map(|fd| ("peano", fd)));
+    out
+}
+
+fn verify(file_data: &FileData) {
+    let outline = Outline::new_from(file_data).unwrap();
+    let errs = verify_serial(&outline, &outline.declarations);
+    assert!(errs.is_empty(), "{:?}", errs.first());
+}
+
+fn parse(c: &mut Criterion, inputs: &[(&str, FileData)]) {
+    let mut group = c.benchmark_group("header");
+    for (name, file_data) in inputs.iter() {
+        group.bench_function(*name, |b| b.iter(|| parse_header(black_box(&file_data.mmb_file)).unwrap()));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("tables");
+    for (name, file_data) in inputs.iter() {
+        let outline = Outline::new_from(file_data).unwrap();
+        group.bench_function(*name, |b| b.iter(|| outline.parse_tables(1).unwrap()));
+    }
+    group.finish();
+}
+
+fn verify_files(c: &mut Criterion, inputs: &[(&str, FileData)]) {
+    let mut group = c.benchmark_group("verify");
+    group.sample_size(20);
+    for (name, file_data) in inputs.iter() {
+        group.throughput(Throughput::Bytes(file_data.mmb_file.len() as u64));
+        group.bench_function(*name, |b| b.iter(|| verify(file_data)));
+    }
+    group.finish();
+}
+
+fn all(c: &mut Criterion) {
+    let inputs = inputs();
+    parse(c, &inputs);
+    verify_files(c, &inputs);
+}
+
+criterion_group!(benches, all);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+// Generate the C header when building with the `capi` feature. It goes in `OUT_DIR`, since a
+// build script shouldn't write into the source tree; the checked-in copy in
+// `include/second_opinion.h` is refreshed with an explicit
+// `cbindgen --config cbindgen.toml --output include/second_opinion.h`.
+fn main() {
+    #[cfg(feature = "capi")]
+    {
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        println!("cargo:rerun-if-changed=src/capi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+            .expect("failed to read cbindgen.toml");
+        // Only the C API module, not every public constant in the crate.
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/capi.rs", crate_dir))
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(format!("{}/second_opinion.h", out_dir));
+    }
+}
@@ -0,0 +1,69 @@
+#ifndef SECOND_OPINION_H
+#define SECOND_OPINION_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * The mmb file verified successfully.
+ */
+#define SO_OK 0
+
+/**
+ * The mmb file was read, but failed to verify; see the result's errors.
+ */
+#define SO_FAILED 1
+
+/**
+ * A null pointer was passed where one isn't allowed; the result is left untouched.
+ */
+#define SO_BAD_ARGS -1
+
+/**
+ * The error messages of a run. Only accessible through `so_result_error`.
+ */
+typedef struct so_errors so_errors;
+
+/**
+ * The outcome of `so_verify`. Must be released with `so_result_free`.
+ */
+typedef struct so_result {
+  bool ok;
+  uint32_t num_sorts;
+  uint32_t num_terms;
+  uint32_t num_thms;
+  size_t num_errors;
+  struct so_errors *errors;
+} so_result;
+
+/**
+ * Verify the mmb file in `data[0..len]` and write the outcome to `out`.
+ * Returns `SO_OK`, `SO_FAILED`, or `SO_BAD_ARGS`.
+ *
+ * # Safety
+ * `data` must point to `len` readable bytes, and `out` must point to writable memory
+ * for one `so_result`.
+ */
+int so_verify(const uint8_t *data, size_t len, struct so_result *out);
+
+/**
+ * The `idx`th error message of a result, or null if `idx` is out of range.
+ * The string is owned by the result and lives until `so_result_free` is called.
+ *
+ * # Safety
+ * `res` must be null or point to a result filled in by `so_verify` that hasn't been freed.
+ */
+const char *so_result_error(const struct so_result *res, size_t idx);
+
+/**
+ * Release the memory held by a result. Freeing a result twice is harmless.
+ *
+ * # Safety
+ * `res` must be null or point to a result filled in by `so_verify`.
+ */
+void so_result_free(struct so_result *res);
+
+#endif /* SECOND_OPINION_H */
@@ -0,0 +1,88 @@
+//! A pool of arenas that workers borrow for the length of a run and hand back at the
+//! end, so that repeated runs (or a run with many short-lived workers) don't keep going
+//! back to the system allocator for fresh arenas.
+//!
+//! A worker keeps the same arena across declarations, resetting it in between, which keeps
+//! its largest chunk. One unusually big declaration would otherwise pin that much memory
+//! for the rest of the run, so an arena that has grown past the high-water mark is dropped
+//! and replaced by a fresh one of the initial capacity.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::util::Shared;
+
+#[derive(Debug, Default)]
+pub struct BumpPool {
+    /// The capacity of newly made arenas, in bytes.
+    pub initial_capacity: usize,
+    /// Arenas that have grown past this many bytes are replaced instead of reused.
+    pub high_water_mark: Option<usize>,
+    free: Shared<Vec<Bump>>,
+}
+
+impl BumpPool {
+    pub fn new(initial_capacity: usize, high_water_mark: Option<usize>) -> Self {
+        BumpPool {
+            initial_capacity,
+            high_water_mark,
+            free: Shared::default(),
+        }
+    }
+
+    fn fresh(&self) -> Bump {
+        Bump::with_capacity(self.initial_capacity)
+    }
+
+    fn too_big(&self, bump: &Bump) -> bool {
+        self.high_water_mark.map(|max| bump.allocated_bytes() > max).unwrap_or(false)
+    }
+
+    /// An empty arena, reused if one is available.
+    pub fn take(&self) -> Bump {
+        self.free.with(|free| free.pop()).unwrap_or_else(|| self.fresh())
+    }
+
+    /// Return an arena to the pool.
+    pub fn give(&self, mut bump: Bump) {
+        if self.too_big(&bump) {
+            bump = self.fresh();
+        } else {
+            bump.reset();
+        }
+        self.free.with(|free| free.push(bump));
+    }
+
+    /// Between declarations: replace `bump` with a fresh arena if it has grown past the
+    /// high-water mark. Otherwise it's left alone, since it gets reset before its next use.
+    pub fn shrink(&self, bump: &mut Bump) {
+        if self.too_big(bump) {
+            *bump = self.fresh();
+        }
+    }
+
+    /// The number of arenas waiting to be reused.
+    pub fn num_free(&self) -> usize {
+        self.free.with(|free| free.len())
+    }
+}
+
+#[test]
+fn bump_pool1() {
+    let pool = BumpPool::new(1 << 10, Some(1 << 16));
+    let mut bump = pool.take();
+    bump.alloc([0u8; 100]);
+    pool.shrink(&mut bump);
+    assert!(bump.allocated_bytes() > 0);
+    pool.give(bump);
+    assert_eq!(pool.num_free(), 1);
+
+    // A big declaration gets its arena swapped out for a small one.
+    let mut bump = pool.take();
+    assert_eq!(pool.num_free(), 0);
+    bump.alloc_slice_fill_copy(1 << 17, 0u8);
+    pool.shrink(&mut bump);
+    assert!(bump.allocated_bytes() < 1 << 16);
+    bump.alloc_slice_fill_copy(1 << 17, 0u8);
+    pool.give(bump);
+    assert!(pool.take().allocated_bytes() < 1 << 16);
+}
@@ -0,0 +1,115 @@
+//! A cache of passing verdicts, so that checking the same release artifact again (as CI
+//! does on every run) takes as long as hashing it. Each entry is the `verification_report`
+//! of a run that passed, in a file named after the key it was stored under:
+//!```text
+//! ~/.cache/second_opinion/<key>.json
+//!```
+//! The key is the SHA-256 of the mmb file, the mm0 files, and everything else that could
+//! change the verdict: the options, the axiom policy, whether the kernel has the last word,
+//! this crate's version, and whatever the caller adds (the command line tool adds `--deny`
+//! and `--tables`).
+//! A hit also needs the stored report to be exactly the one this file gives, so a corrupted
+//! or edited entry is a miss, never a pass.
+//!
+//! Failures aren't cached: they're rare in CI, and the errors are the useful part.
+
+use std::path::PathBuf;
+use sha2::{ Sha256, Digest };
+use crate::Outline;
+use crate::env::hex;
+use crate::util::{ Res, VerifErr };
+use crate::io_err;
+
+/// A directory of cached verdicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cache {
+    pub dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// `$SECOND_OPINION_CACHE` if it's set, and otherwise `second_opinion` in the user's
+    /// cache directory: `$XDG_CACHE_HOME`, or `~/.cache`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let var = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+        if let Some(dir) = var("SECOND_OPINION_CACHE") {
+            return Some(dir)
+        }
+        let base = var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))?;
+        Some(base.join("second_opinion"))
+    }
+
+    /// The key a run of `outline` with `settings` is stored under.
+    pub fn key(outline: &Outline, settings: &str) -> String {
+        let mut h = Sha256::new();
+        h.update(Sha256::digest(outline.mmb()));
+        for mmz in outline.file_data.mmz_files.iter() {
+            h.update(Sha256::digest(mmz.as_bytes()));
+        }
+        h.update(format!(
+            "{} {:?} {:?} kernel={} {}",
+            env!("CARGO_PKG_VERSION"),
+            outline.options,
+            outline.axiom_policy,
+            cfg!(feature = "kernel"),
+            settings
+        ).as_bytes());
+        hex(&h.finalize())
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// Whether a run of every declaration of `outline` with `settings` is known to pass.
+    pub fn lookup(&self, outline: &Outline, settings: &str) -> bool {
+        let stored = match std::fs::read_to_string(self.path(&Cache::key(outline, settings))) {
+            Ok(stored) => stored,
+            Err(_) => return false,
+        };
+        match outline.verification_report(&outline.declarations) {
+            Ok(report) => stored == report.to_string(),
+            Err(_) => false,
+        }
+    }
+
+    /// Record that a run of every declaration of `outline` with `settings` passed. It's up
+    /// to the caller to only call this when it did.
+    pub fn store(&self, outline: &Outline, settings: &str) -> Res<()> {
+        let report = outline.verification_report(&outline.declarations)?;
+        let path = self.path(&Cache::key(outline, settings));
+        let tmp = path.with_extension("tmp");
+        io_err!(std::fs::create_dir_all(&self.dir))?;
+        // Write then rename, so a concurrent run never reads half an entry.
+        io_err!(std::fs::write(&tmp, report.to_string()))?;
+        io_err!(std::fs::rename(&tmp, &path))
+    }
+}
+
+#[test]
+fn cache1() {
+    let dir = std::env::temp_dir().join(format!("second_opinion_cache1_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let cache = Cache::new(&dir);
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", Some("./test_resources/short.mm0")).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    assert!(!cache.lookup(&outline, ""));
+    cache.store(&outline, "").unwrap();
+    assert!(cache.lookup(&outline, ""));
+    // Different settings, options, or files are different keys.
+    assert!(!cache.lookup(&outline, "--deny warnings"));
+    outline.options.strict = true;
+    assert!(!cache.lookup(&outline, ""));
+    outline.options.strict = false;
+    let mm0 = format!("{}\n-- a comment\n", file_data.mmz_files[0]);
+    let other = crate::fs::FileData::new_from_bytes(file_data.mmb_file.clone(), vec![mm0]);
+    assert!(!cache.lookup(&Outline::new_from(&other).unwrap(), ""));
+    // A damaged entry is a miss.
+    let path = cache.path(&Cache::key(&outline, ""));
+    std::fs::write(&path, "{}").unwrap();
+    assert!(!cache.lookup(&outline, ""));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
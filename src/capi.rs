@@ -0,0 +1,154 @@
+//! A C interface, so that toolchains written in other languages can link the verifier directly
+//! instead of shelling out to an external checker. The header is checked in as
+//! `include/second_opinion.h`; the build script generates it into `OUT_DIR` when the `capi`
+//! feature is enabled, and `cbindgen --config cbindgen.toml --output include/second_opinion.h`
+//! refreshes the checked-in copy.
+//!
+//! Usage from C:
+//!```text
+//! so_result res;
+//! if (so_verify(buf, len, &res) != SO_OK) {
+//!     for (size_t i = 0; i < res.num_errors; i++) puts(so_result_error(&res, i));
+//! }
+//! so_result_free(&res);
+//!```
+#![allow(unsafe_code)]
+#![allow(non_camel_case_types)]
+
+use std::ffi::CString;
+use std::os::raw::{ c_char, c_int };
+use std::panic::{ catch_unwind, AssertUnwindSafe };
+use crate::Outline;
+use crate::fs::FileData;
+
+/// The mmb file verified successfully.
+pub const SO_OK: c_int = 0;
+/// The mmb file was read, but failed to verify; see the result's errors.
+pub const SO_FAILED: c_int = 1;
+/// A null pointer was passed where one isn't allowed; the result is left untouched.
+pub const SO_BAD_ARGS: c_int = -1;
+
+/// The error messages of a run. Only accessible through `so_result_error`.
+pub struct so_errors {
+    msgs: Vec<CString>,
+}
+
+/// The outcome of `so_verify`. Must be released with `so_result_free`.
+#[repr(C)]
+pub struct so_result {
+    pub ok: bool,
+    pub num_sorts: u32,
+    pub num_terms: u32,
+    pub num_thms: u32,
+    pub num_errors: usize,
+    pub errors: *mut so_errors,
+}
+
+fn to_cstring(s: String) -> CString {
+    // Interior nul bytes can only come from names in a malformed file.
+    CString::new(s.replace('\0', "\\0")).unwrap_or_default()
+}
+
+fn run(bytes: &[u8]) -> so_result {
+    let file_data = FileData::new_from_bytes(bytes.to_vec(), Vec::new());
+    let (num_sorts, num_terms, num_thms, msgs) = match Outline::new_from(&file_data) {
+        Err(e) => (0, 0, 0, vec![format!("{:?}", e)]),
+        Ok(outline) => {
+            let errs = crate::verify_serial(&outline, outline.declarations.as_slice());
+            (
+                outline.header.num_sorts as u32,
+                outline.header.num_terms,
+                outline.header.num_thms,
+                errs.iter().map(|e| format!("{:?}", e)).collect()
+            )
+        }
+    };
+    let msgs: Vec<CString> = msgs.into_iter().map(to_cstring).collect();
+    so_result {
+        ok: msgs.is_empty(),
+        num_sorts,
+        num_terms,
+        num_thms,
+        num_errors: msgs.len(),
+        errors: Box::into_raw(Box::new(so_errors { msgs })),
+    }
+}
+
+/// Verify the mmb file in `data[0..len]` and write the outcome to `out`.
+/// Returns `SO_OK`, `SO_FAILED`, or `SO_BAD_ARGS`.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes, and `out` must point to writable memory
+/// for one `so_result`.
+#[no_mangle]
+pub unsafe extern "C" fn so_verify(data: *const u8, len: usize, out: *mut so_result) -> c_int {
+    if data.is_null() || out.is_null() {
+        return SO_BAD_ARGS
+    }
+    let bytes = std::slice::from_raw_parts(data, len);
+    // Unwinding across the FFI boundary is undefined behavior, so a panic is reported as an error.
+    let res = catch_unwind(AssertUnwindSafe(|| run(bytes))).unwrap_or_else(|_| {
+        let msgs = vec![to_cstring("verifier panicked".to_string())];
+        so_result {
+            ok: false,
+            num_sorts: 0,
+            num_terms: 0,
+            num_thms: 0,
+            num_errors: 1,
+            errors: Box::into_raw(Box::new(so_errors { msgs })),
+        }
+    });
+    let code = if res.ok { SO_OK } else { SO_FAILED };
+    out.write(res);
+    code
+}
+
+/// The `idx`th error message of a result, or null if `idx` is out of range.
+/// The string is owned by the result and lives until `so_result_free` is called.
+///
+/// # Safety
+/// `res` must be null or point to a result filled in by `so_verify` that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn so_result_error(res: *const so_result, idx: usize) -> *const c_char {
+    match res.as_ref().and_then(|res| res.errors.as_ref()).and_then(|errs| errs.msgs.get(idx)) {
+        None => std::ptr::null(),
+        Some(msg) => msg.as_ptr(),
+    }
+}
+
+/// Release the memory held by a result. Freeing a result twice is harmless.
+///
+/// # Safety
+/// `res` must be null or point to a result filled in by `so_verify`.
+#[no_mangle]
+pub unsafe extern "C" fn so_result_free(res: *mut so_result) {
+    if let Some(res) = res.as_mut() {
+        if !res.errors.is_null() {
+            drop(Box::from_raw(res.errors));
+            res.errors = std::ptr::null_mut();
+        }
+        res.num_errors = 0;
+    }
+}
+
+#[test]
+fn capi_roundtrip1() {
+    let bytes = std::fs::read("./test_resources/short.mmb").unwrap();
+    let mut res = std::mem::MaybeUninit::<so_result>::uninit();
+    unsafe {
+        assert_eq!(so_verify(bytes.as_ptr(), bytes.len(), res.as_mut_ptr()), SO_OK);
+        let mut res = res.assume_init();
+        assert_eq!((res.num_sorts, res.num_terms, res.num_thms), (1, 2, 4));
+        assert!(so_result_error(&res, 0).is_null());
+        so_result_free(&mut res);
+
+        let garbage = [0u8; 16];
+        let mut res = std::mem::MaybeUninit::<so_result>::uninit();
+        assert_eq!(so_verify(garbage.as_ptr(), garbage.len(), res.as_mut_ptr()), SO_FAILED);
+        let mut res = res.assume_init();
+        assert!(!so_result_error(&res, 0).is_null());
+        so_result_free(&mut res);
+        so_result_free(&mut res);
+        assert_eq!(so_verify(std::ptr::null(), 0, &mut res), SO_BAD_ARGS);
+    }
+}
@@ -0,0 +1,280 @@
+//! A minimal CBOR (RFC 8949) reader/writer, the binary counterpart of `json`, for the
+//! `remote` protocol. Only what the protocol uses is supported: unsigned integers, byte and
+//! text strings, arrays, maps with text keys, booleans and null, all with definite lengths.
+//! Anything else (negative numbers, floats, tags, indefinite lengths) is rejected when read.
+
+use crate::prelude::*;
+use crate::util::{ Res, VerifErr };
+
+/// How deeply arrays and maps may nest, so that a hostile message can't exhaust the stack.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cbor {
+    Null,
+    Bool(bool),
+    UInt(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Arr(Vec<Cbor>),
+    /// Keys are kept in insertion order so that output is deterministic.
+    Map(Vec<(String, Cbor)>),
+}
+
+impl Cbor {
+    pub fn map() -> Self {
+        Cbor::Map(Vec::new())
+    }
+
+    /// Builder-style insertion for maps; does nothing for other variants.
+    pub fn with(mut self, key: &str, val: impl Into<Cbor>) -> Self {
+        if let Cbor::Map(fields) = &mut self {
+            fields.push((key.to_string(), val.into()));
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Cbor> {
+        match self {
+            Cbor::Map(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Cbor::Text(s) => Some(s.as_str()),
+            _ => None
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Cbor::Bytes(b) => Some(b.as_slice()),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Cbor::UInt(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Cbor::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_arr(&self) -> Option<&[Cbor]> {
+        match self {
+            Cbor::Arr(a) => Some(a.as_slice()),
+            _ => None
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Cbor::Null => out.push(0xf6),
+            Cbor::Bool(b) => out.push(if *b { 0xf5 } else { 0xf4 }),
+            Cbor::UInt(n) => head(out, 0, *n),
+            Cbor::Bytes(b) => {
+                head(out, 2, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Cbor::Text(s) => text(out, s),
+            Cbor::Arr(items) => {
+                head(out, 4, items.len() as u64);
+                for item in items.iter() {
+                    item.write(out);
+                }
+            }
+            Cbor::Map(fields) => {
+                head(out, 5, fields.len() as u64);
+                for (k, v) in fields.iter() {
+                    text(out, k);
+                    v.write(out);
+                }
+            }
+        }
+    }
+}
+
+/// The initial byte (and following length bytes) of an item of major type `major`.
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8)
+    } else if n <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(n as u8)
+    } else if n <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(n as u16).to_be_bytes())
+    } else if n <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(n as u32).to_be_bytes())
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_be_bytes())
+    }
+}
+
+fn text(out: &mut Vec<u8>, s: &str) {
+    head(out, 3, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+impl From<bool> for Cbor {
+    fn from(b: bool) -> Self { Cbor::Bool(b) }
+}
+
+impl From<u64> for Cbor {
+    fn from(n: u64) -> Self { Cbor::UInt(n) }
+}
+
+impl From<u32> for Cbor {
+    fn from(n: u32) -> Self { Cbor::UInt(n as u64) }
+}
+
+impl From<u8> for Cbor {
+    fn from(n: u8) -> Self { Cbor::UInt(n as u64) }
+}
+
+impl From<usize> for Cbor {
+    fn from(n: usize) -> Self { Cbor::UInt(n as u64) }
+}
+
+impl From<&str> for Cbor {
+    fn from(s: &str) -> Self { Cbor::Text(s.to_string()) }
+}
+
+impl From<String> for Cbor {
+    fn from(s: String) -> Self { Cbor::Text(s) }
+}
+
+impl From<Vec<u8>> for Cbor {
+    fn from(b: Vec<u8>) -> Self { Cbor::Bytes(b) }
+}
+
+impl From<Vec<Cbor>> for Cbor {
+    fn from(items: Vec<Cbor>) -> Self { Cbor::Arr(items) }
+}
+
+impl<T: Into<Cbor>> From<Option<T>> for Cbor {
+    fn from(o: Option<T>) -> Self { o.map(Into::into).unwrap_or(Cbor::Null) }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn err(&self, msg: &str) -> VerifErr {
+        VerifErr::Msg(format!("cbor: {} at byte {}", msg, self.pos))
+    }
+
+    fn take(&mut self, n: u64) -> Res<&'a [u8]> {
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return Err(self.err("unexpected end of input"))
+        }
+        let out = &self.bytes[self.pos..self.pos + n as usize];
+        self.pos += n as usize;
+        Ok(out)
+    }
+
+    /// The major type and argument of the next item.
+    fn head(&mut self) -> Res<(u8, u64)> {
+        let b = self.take(1)?[0];
+        let (major, info) = (b >> 5, b & 0x1f);
+        let n = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => self.take(2)?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            26 => self.take(4)?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            27 => self.take(8)?.iter().fold(0, |n, b| n << 8 | *b as u64),
+            _ => return Err(self.err("unsupported length encoding")),
+        };
+        Ok((major, n))
+    }
+
+    fn text(&mut self, n: u64) -> Res<String> {
+        let bytes = self.take(n)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.err("text isn't UTF-8"))
+    }
+
+    fn item(&mut self, depth: usize) -> Res<Cbor> {
+        if depth > MAX_DEPTH {
+            return Err(self.err("nested too deeply"))
+        }
+        match self.head()? {
+            (0, n) => Ok(Cbor::UInt(n)),
+            (2, n) => Ok(Cbor::Bytes(self.take(n)?.to_vec())),
+            (3, n) => Ok(Cbor::Text(self.text(n)?)),
+            (4, n) => {
+                // Every item is at least a byte, so a length past the end is a lie.
+                let mut items = Vec::with_capacity(n.min((self.bytes.len() - self.pos) as u64) as usize);
+                for _ in 0..n {
+                    items.push(self.item(depth + 1)?);
+                }
+                Ok(Cbor::Arr(items))
+            }
+            (5, n) => {
+                let mut fields = Vec::with_capacity(n.min((self.bytes.len() - self.pos) as u64) as usize);
+                for _ in 0..n {
+                    let key = match self.head()? {
+                        (3, len) => self.text(len)?,
+                        _ => return Err(self.err("map keys have to be text")),
+                    };
+                    fields.push((key, self.item(depth + 1)?));
+                }
+                Ok(Cbor::Map(fields))
+            }
+            (7, 20) => Ok(Cbor::Bool(false)),
+            (7, 21) => Ok(Cbor::Bool(true)),
+            (7, 22) => Ok(Cbor::Null),
+            _ => Err(self.err("unsupported item")),
+        }
+    }
+}
+
+/// Read one item, which has to be all of `bytes`.
+pub fn parse(bytes: &[u8]) -> Res<Cbor> {
+    let mut r = Reader { bytes, pos: 0 };
+    let item = r.item(0)?;
+    if r.pos != bytes.len() {
+        return Err(r.err("trailing bytes"))
+    }
+    Ok(item)
+}
+
+#[test]
+fn cbor1() {
+    // The examples of RFC 8949, appendix A, that are in the subset.
+    assert_eq!(Cbor::UInt(1000000).to_bytes(), [0x1a, 0x00, 0x0f, 0x42, 0x40]);
+    assert_eq!(Cbor::from("IETF").to_bytes(), b"\x64IETF");
+    assert_eq!(Cbor::map().with("a", 1u64).with("b", vec![Cbor::UInt(2), Cbor::UInt(3)]).to_bytes(), [0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03]);
+    let value = Cbor::map()
+        .with("bytes", vec![0u8; 300])
+        .with("big", u64::MAX)
+        .with("flags", vec![Cbor::Bool(true), Cbor::Bool(false), Cbor::Null])
+        .with("name", "ax_mp");
+    assert_eq!(parse(&value.to_bytes()).unwrap(), value);
+    // Truncated, trailing, negative, and a length far past the end.
+    let bytes = value.to_bytes();
+    assert!(parse(&bytes[..bytes.len() - 1]).is_err());
+    assert!(parse(&[bytes.as_slice(), &[0]].concat()).is_err());
+    assert!(parse(&[0x20]).is_err());
+    assert!(parse(&[0x9b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    assert!(parse(&[0x81; MAX_DEPTH + 2]).is_err());
+}
@@ -0,0 +1,133 @@
+//! What a successful verification run established, as data: every sort, term, and
+//! assertion in the file with its statement. Everything in a `Certificate` is owned, so it
+//! outlives the bytes it was read from, and it's only ever built from a file that verified.
+//!
+//! Statements are written the way `env` writes them: binders as types, and hypotheses,
+//! conclusions, and def bodies as s-expressions like `(im v0 (not v1))`, where `v<i>` is
+//! the `i`th argument and `d<i>` the `i`th dummy.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::fs::FileData;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, Type, Mods, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CertSort {
+    pub num: u8,
+    pub name: Option<String>,
+    pub mods: Mods,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CertTerm {
+    pub num: u32,
+    pub name: Option<String>,
+    pub args: Vec<Type>,
+    pub ret: Type,
+    /// The body of a def, or `None` for a term.
+    pub def: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CertThm {
+    pub num: u32,
+    pub name: Option<String>,
+    pub axiom: bool,
+    pub args: Vec<Type>,
+    /// In the order they're declared in.
+    pub hyps: Vec<String>,
+    pub concl: String,
+}
+
+/// The sorts, terms, and assertions of a verified file, each table in declaration order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Certificate {
+    pub sorts: Vec<CertSort>,
+    pub terms: Vec<CertTerm>,
+    pub thms: Vec<CertThm>,
+    /// How long `verify` took, by phase. This isn't something the file established, so
+    /// it's `None` from `Outline::certificate`, and two certificates only compare equal
+    /// with the same timings.
+    pub timing: Option<crate::stats::PhaseTimes>,
+}
+
+impl<'a> Outline<'a> {
+    /// Describe every declaration of the file. Like `verification_report`, this doesn't
+    /// check that verification succeeded; `verify` is the way to get one that did.
+    pub fn certificate(&self) -> Res<Certificate> {
+        let mut cert = Certificate::default();
+        for (stmt, _) in self.declarations.iter() {
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    cert.sorts.push(CertSort {
+                        num,
+                        name: self.sort_name(num).map(|s| s.to_string()),
+                        mods: self.get_sort_mods(num as usize)?,
+                    });
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    let term = self.get_term_by_num(num)?;
+                    cert.terms.push(CertTerm {
+                        num,
+                        name: self.term_name(num).map(|s| s.to_string()),
+                        args: term.args_no_ret().collect(),
+                        ret: term.ret(),
+                        def: self.def_body_sexpr(num)?,
+                    });
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let (hyps, concl) = self.assert_sexprs(num)?;
+                    cert.thms.push(CertThm {
+                        num,
+                        name: self.assert_name(num).map(|s| s.to_string()),
+                        axiom: matches!(stmt, StmtCmd::Axiom { .. }),
+                        args: self.get_assert_by_num(num)?.args().collect(),
+                        hyps,
+                        concl,
+                    });
+                }
+            }
+        }
+        Ok(cert)
+    }
+}
+
+/// Verify an mmb file given as bytes (without an mm0 file), and describe what it
+/// established and how long each phase took. If verification fails, this is the first
+/// error.
+pub fn verify(mmb: &[u8]) -> Result<Certificate, VerifErr> {
+    let file_data = FileData::new_from_bytes(mmb.to_vec(), Vec::new());
+    let mut outline = Outline::new_from(&file_data)?;
+    outline.options.phase_times = true;
+    match crate::verify_serial(&outline, &outline.declarations).into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(Certificate { timing: Some(outline.phase_times.with(|t| t.clone())), ..outline.certificate()? }),
+    }
+}
+
+#[test]
+fn certificate1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let cert = verify(&file_data.mmb_file).unwrap();
+    assert_eq!(cert.sorts.len(), 1);
+    assert_eq!(cert.sorts[0].name.as_deref(), Some("wff"));
+    assert_eq!(cert.terms.iter().map(|t| t.args.len()).collect::<Vec<_>>(), vec![2, 1]);
+    let ax_mp = cert.thms.iter().find(|t| t.name.as_deref() == Some("ax_mp")).unwrap();
+    assert!(ax_mp.axiom);
+    assert_eq!(ax_mp.hyps, vec!["(im v0 v1)".to_string(), "v0".to_string()]);
+    assert_eq!(ax_mp.concl, "v1");
+    assert_eq!(cert.timing.map(|t| t.decls.len()), Some(4));
+
+    let mut fx = crate::testgen::Fixture::valid();
+    fx.stmts.pop();
+    assert!(verify(&fx.to_bytes()).is_err());
+}
@@ -0,0 +1,226 @@
+//! Checkpoints for long runs, so that an interrupted verification of a huge file can pick
+//! up where it left off instead of starting over. A checkpoint is a small JSON file:
+//!```text
+//! {"mmb_sha256":"..","version":"0.1.0","settings_sha256":"..","next_decl":1200,"sorts_done":12,"termdefs_done":300,"asserts_done":888,"digest":".."}
+//!```
+//! `version` is this crate's version and `settings_sha256` the SHA-256 of everything else
+//! that could change the verdict on a declaration (the options, the axiom policy, and
+//! whether the kernel has the last word), as in the cache key. `next_decl` is the position
+//! in `declarations` of the first declaration that hasn't been checked yet, and everything
+//! before it verified without errors. The `_done` fields are the outline's mmb counters at
+//! that point, and `digest` is the SHA-256 of the checkpoint as written before `digest`
+//! itself is added.
+//!
+//! Resuming checks that the mmb file is byte for byte the one the checkpoint was written
+//! for, by the same version with the same settings, that the digest is intact, and that the
+//! counters agree with the declarations before `next_decl`. Only the mmb pass is resumed; the mm0 files are cheap to check compared to
+//! the proofs, so they're always checked from the start.
+
+use crate::prelude::*;
+use core::sync::atomic::AtomicUsize;
+use sha2::{ Sha256, Digest };
+use crate::Outline;
+use crate::env::hex;
+use crate::json::Json;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    pub mmb_sha256: String,
+    pub version: String,
+    pub settings_sha256: String,
+    pub next_decl: usize,
+    pub sorts_done: u8,
+    pub termdefs_done: u32,
+    pub asserts_done: u32,
+}
+
+impl Checkpoint {
+    fn to_json_no_digest(&self) -> Json {
+        Json::obj()
+            .with("mmb_sha256", self.mmb_sha256.as_str())
+            .with("version", self.version.as_str())
+            .with("settings_sha256", self.settings_sha256.as_str())
+            .with("next_decl", self.next_decl)
+            .with("sorts_done", self.sorts_done)
+            .with("termdefs_done", self.termdefs_done)
+            .with("asserts_done", self.asserts_done)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let json = self.to_json_no_digest();
+        let digest = hex(&Sha256::digest(json.to_string().as_bytes()));
+        json.with("digest", digest)
+    }
+
+    pub fn from_json(json: &Json) -> Res<Self> {
+        let field = |key: &str| json.get(key).and_then(|v| v.as_u64())
+            .ok_or_else(|| VerifErr::Msg(format!("checkpoint is missing the number {}", key)));
+        let string = |key: &str| json.get(key).and_then(|v| v.as_str()).map(|s| s.to_string())
+            .ok_or_else(|| VerifErr::Msg(format!("checkpoint is missing the string {}", key)));
+        let cp = Checkpoint {
+            mmb_sha256: string("mmb_sha256")?,
+            version: string("version")?,
+            settings_sha256: string("settings_sha256")?,
+            next_decl: field("next_decl")? as usize,
+            sorts_done: field("sorts_done")? as u8,
+            termdefs_done: field("termdefs_done")? as u32,
+            asserts_done: field("asserts_done")? as u32,
+        };
+        if cp.to_json().get("digest") != json.get("digest") {
+            return Err(VerifErr::Msg("checkpoint digest doesn't match its contents".to_string()))
+        }
+        Ok(cp)
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// The SHA-256 of the settings a checkpoint is only good for.
+    fn checkpoint_settings(&self) -> String {
+        let settings = format!("{:?} {:?} kernel={}", self.options, self.axiom_policy, cfg!(feature = "kernel"));
+        hex(&Sha256::digest(settings.as_bytes()))
+    }
+
+    /// A checkpoint saying that every declaration before `next_decl` has been verified.
+    pub fn checkpoint(&self, next_decl: usize) -> Checkpoint {
+        Checkpoint {
+            mmb_sha256: hex(&Sha256::digest(self.mmb())),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            settings_sha256: self.checkpoint_settings(),
+            next_decl,
+            sorts_done: self.mmb_num_sorts_done(),
+            termdefs_done: self.mmb_num_termdefs_done(),
+            asserts_done: self.mmb_num_asserts_done(),
+        }
+    }
+
+    /// Check that `cp` was written for this file and is consistent with it, and put the mmb
+    /// counters back where they were. Returns the position to resume verification from.
+    pub fn resume_from(&self, cp: &Checkpoint) -> Res<usize> {
+        if cp.mmb_sha256 != hex(&Sha256::digest(self.mmb())) {
+            return Err(VerifErr::Msg("the mmb file has changed since the checkpoint was written".to_string()))
+        }
+        if cp.version != env!("CARGO_PKG_VERSION") {
+            return Err(VerifErr::Msg(format!(
+                "the checkpoint was written by version {} of the verifier, not {}", cp.version, env!("CARGO_PKG_VERSION")
+            )))
+        }
+        if cp.settings_sha256 != self.checkpoint_settings() {
+            return Err(VerifErr::Msg("the checkpoint was written by a run with different settings".to_string()))
+        }
+        let done = none_err!(self.declarations.get(..cp.next_decl))?;
+        let (mut sorts, mut termdefs, mut asserts) = (0u8, 0u32, 0u32);
+        for (stmt, _) in done.iter() {
+            match stmt {
+                StmtCmd::Sort {..} => sorts += 1,
+                StmtCmd::TermDef {..} => termdefs += 1,
+                StmtCmd::Axiom {..} | StmtCmd::Thm {..} => asserts += 1,
+            }
+        }
+        if (sorts, termdefs, asserts) != (cp.sorts_done, cp.termdefs_done, cp.asserts_done) {
+            return Err(VerifErr::Msg(format!(
+                "checkpoint counts ({}, {}, {}) don't match the first {} declarations ({}, {}, {})",
+                cp.sorts_done, cp.termdefs_done, cp.asserts_done, cp.next_decl, sorts, termdefs, asserts
+            )))
+        }
+        self.restore_done(sorts, termdefs, asserts);
+        Ok(cp.next_decl)
+    }
+}
+
+/// Like `verify_serial` over all of `outline.declarations`, but starting from `resume` if
+/// given, and calling `save` with a new checkpoint after every `every` declarations. Once a
+/// declaration fails, no more checkpoints are saved, since resuming would skip the failure.
+pub fn verify_serial_checkpointed<'a>(
+    outline: &'a Outline<'a>,
+    resume: Option<&Checkpoint>,
+    every: usize,
+    mut save: impl FnMut(&Checkpoint) -> Res<()>,
+) -> Vec<VerifErr> {
+    let mut errs = if outline.file_data.mmz_files.is_empty() {
+        Vec::new()
+    } else {
+        crate::verify_mmz(outline)
+    };
+    let start = match resume.map(|cp| outline.resume_from(cp)).transpose() {
+        Ok(start) => start.unwrap_or(0),
+        Err(e) => {
+            errs.push(e);
+            return errs
+        }
+    };
+
+    let mut next_decl = start;
+    for chunk in outline.declarations[start..].chunks(every.max(1)) {
+        let ok_so_far = errs.is_empty();
+        let mut chunk_errs = crate::verify_mmb(outline, chunk, &AtomicUsize::new(0));
+        errs.append(&mut chunk_errs);
+        next_decl += chunk.len();
+        if ok_so_far && errs.is_empty() {
+            if let Err(e) = save(&outline.checkpoint(next_decl)) {
+                errs.push(e);
+            }
+        }
+    }
+
+    outline.finish_checks(&outline.declarations, &mut errs);
+    errs
+}
+
+#[test]
+fn checkpoint1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let mut saved = Vec::new();
+    let errs = verify_serial_checkpointed(&outline, None, 3, |cp| {
+        saved.push(cp.clone());
+        Ok(())
+    });
+    assert!(errs.is_empty());
+    assert_eq!(saved.iter().map(|cp| cp.next_decl).collect::<Vec<_>>(), vec![3, 6, 7]);
+
+    // Pretend the first run was interrupted after the first checkpoint.
+    let cp = Checkpoint::from_json(&crate::json::parse(&saved[0].to_json().to_string()).unwrap()).unwrap();
+    assert_eq!(cp, saved[0]);
+    let fresh = Outline::new_from(&file_data).unwrap();
+    assert!(verify_serial_checkpointed(&fresh, Some(&cp), 3, |_| Ok(())).is_empty());
+
+    let tampered = Checkpoint { sorts_done: 2, ..cp.clone() };
+    assert!(Outline::new_from(&file_data).unwrap().resume_from(&tampered).is_err());
+    let old = Checkpoint { version: "0.0.0".to_string(), ..cp.clone() };
+    assert!(Outline::new_from(&file_data).unwrap().resume_from(&old).is_err());
+    let mut strict = Outline::new_from(&file_data).unwrap();
+    strict.options.strict = true;
+    assert!(strict.resume_from(&cp).is_err());
+    let mut changed = file_data.mmb_file.clone();
+    changed.push(0);
+    let changed = crate::fs::FileData::new_from_bytes(changed, Vec::new());
+    assert!(Outline::new_from(&changed).unwrap().resume_from(&cp).is_err());
+}
+
+#[test]
+fn checkpoint_axioms1() {
+    use crate::util::ErrKind;
+    // The axiom policy applies to a checkpointed run, resumed or not.
+    let fx = crate::testgen::Fixture::valid();
+    let mut saved = Vec::new();
+    fx.with_outline(|outline| {
+        outline.axiom_policy.deny = vec!["0".to_string()];
+        let errs = verify_serial_checkpointed(outline, None, 1, |cp| {
+            saved.push(cp.clone());
+            Ok(())
+        });
+        assert_eq!(errs.iter().filter(|e| e.kind() == ErrKind::AxiomNotAllowed).count(), 1);
+    });
+    // Even from a checkpoint past the theorem that uses the axiom.
+    let cp = saved.last().unwrap();
+    fx.with_outline(|fresh| {
+        assert_eq!(cp.next_decl, fresh.declarations.len());
+        fresh.axiom_policy.deny = vec!["0".to_string()];
+        let errs = verify_serial_checkpointed(fresh, Some(cp), 1, |_| Ok(()));
+        assert_eq!(errs.iter().filter(|e| e.kind() == ErrKind::AxiomNotAllowed).count(), 1);
+    });
+}
@@ -0,0 +1,304 @@
+//! Stable error codes, one per `ErrKind`, so that scripts and CI policies can match on how
+//! something failed without parsing messages, and `--explain` can say which rule of the
+//! mmb format was broken. Codes are never reused or renumbered; a new kind gets a new one.
+//!
+//! The hundreds group them: `E00xx` the file as a whole, `E01xx` binders and types,
+//! `E02xx` proofs and unification, `E03xx` resource limits, `E04xx` strict mode, `E05xx`
+//! the second checker, `E06xx` denied warnings, `E07xx` the axiom policy, and `E09xx` the
+//! internal consistency checks that most malformed proofs end up failing, which only say
+//! where in this crate's source the check is.
+
+use crate::util::{ ErrKind, VerifErr };
+
+/// One error code and what it means.
+#[derive(Debug, Clone, Copy)]
+pub struct ErrCode {
+    pub code: &'static str,
+    pub kind: ErrKind,
+    pub title: &'static str,
+    /// The rule that was broken, and what usually breaks it.
+    pub rule: &'static str,
+}
+
+pub const CODES: &[ErrCode] = &[
+    ErrCode {
+        code: "E0001",
+        kind: ErrKind::IoErr,
+        title: "couldn't read a file",
+        rule: "The mmb or mm0 file couldn't be opened or read. Nothing about its contents was checked.",
+    },
+    ErrCode {
+        code: "E0002",
+        kind: ErrKind::DeclCount,
+        title: "declaration count mismatch",
+        rule: "The header gives the number of sorts, terms and theorems, and the proof stream has to \
+               declare exactly that many of each, in the same order as the tables. A stream that \
+               stops early or declares extra entries breaks this.",
+    },
+    ErrCode {
+        code: "E0003",
+        kind: ErrKind::Conformance,
+        title: "the mm0 and mmb files don't match",
+        rule: "Every sort, term, def, axiom and theorem the mm0 file declares has to be declared by \
+               the mmb file too, in the same order, with the same name, kind and statement, and the \
+               mmb file's public declarations have to be exactly these. Local theorems and defs \
+               are the mmb file's own business, and the mm0 file can't declare them.",
+    },
+    ErrCode {
+        code: "E0101",
+        kind: ErrKind::TooManyBoundVars,
+        title: "too many bound variables",
+        rule: "A `Type` has 56 bits for the bound variables an expression depends on, so a declaration \
+               can have at most 56 bound variables, counting its bound arguments and its dummies.",
+    },
+    ErrCode {
+        code: "E0102",
+        kind: ErrKind::MalformedProof,
+        title: "malformed proof stream",
+        rule: "Before a proof runs, its bytes are checked on their own: each command has to be \
+               a proof command, only `Term`, `Thm`, `Dummy`, `Ref` and `ConvRef` can have a data \
+               field, and the stream has to end with `End` exactly at its declared length. This \
+               is a bug in whatever wrote the file, not in the proof.",
+    },
+    ErrCode {
+        code: "E0103",
+        kind: ErrKind::MalformedUnify,
+        title: "malformed unify stream",
+        rule: "A declaration's unify stream is checked before it's used: every `URef` has to \
+               refer to an argument or to something a `UTermSave` or `UDummy` before it saved, \
+               only a def's stream can have `UDummy` and only an assertion's `UHyp`, and an \
+               assertion has to have as many `UHyp` commands as its proof has `Hyp` commands.",
+    },
+    ErrCode {
+        code: "E0201",
+        kind: ErrKind::BadBackref,
+        title: "backreference past the end of the heap",
+        rule: "`Ref i` and `ConvRef i` in a proof, and `URef i` in a unify stream, read entry `i` of \
+               the heap (or unify heap), which has to exist already: the arguments, then everything \
+               saved so far.",
+    },
+    ErrCode {
+        code: "E0202",
+        kind: ErrKind::WrongItem,
+        title: "wrong kind of item",
+        rule: "Each proof command takes expressions, proofs, conversions or conversion obligations \
+               from the stack, and has to find the kind it needs: `Thm` applies to expressions, \
+               `Conv` needs a proof on top, `Refl` an obligation, and so on.",
+    },
+    ErrCode {
+        code: "E0203",
+        kind: ErrKind::ForwardReference,
+        title: "reference to a later declaration",
+        rule: "A def can only use terms declared before it, and an axiom or theorem only terms and \
+               assertions declared before it; in particular nothing can refer to itself.",
+    },
+    ErrCode {
+        code: "E0204",
+        kind: ErrKind::DefCycle,
+        title: "defs that unfold to each other",
+        rule: "A def's body can only use terms declared before it, so unfolding defs always stops. \
+               Bodies that use each other in a cycle can't have been declared in any order.",
+    },
+    ErrCode {
+        code: "E0205",
+        kind: ErrKind::UnifyDelta,
+        title: "unification failed",
+        rule: "Applying an assertion (or unfolding a def) runs its unify stream against the \
+               expression on the stack, which has to have the same shape: each `UTerm` the same \
+               term, each `URef` the same expression as the one saved. The path says where the \
+               first difference is.",
+    },
+    ErrCode {
+        code: "E0206",
+        kind: ErrKind::DependsOnFailed,
+        title: "uses a declaration that failed",
+        rule: "With `--keep-going`, a declaration whose own proof checks, but that uses a term or \
+               assertion whose declaration failed (or uses one that does). Its proof only relied \
+               on the failed one's statement, which nothing supports.",
+    },
+    ErrCode {
+        code: "E0207",
+        kind: ErrKind::HypNotProvable,
+        title: "hypothesis in a sort that isn't provable",
+        rule: "A `Hyp` command assumes the expression on top of the stack, which has to be in a \
+               sort declared `provable`. Only those sorts have statements that can be assumed or \
+               proved.",
+    },
+    ErrCode {
+        code: "E0208",
+        kind: ErrKind::StackUnderflow,
+        title: "stack underflow",
+        rule: "A proof command takes its operands off the top of the stack, so there have to be \
+               enough there: two for `Conv`, the arguments of a term or theorem for `Term` and \
+               `Thm`, and so on. Running out usually means the exporter that wrote the proof \
+               got an arity or an ordering wrong; the error shows the top of the stack.",
+    },
+    ErrCode {
+        code: "E0301",
+        kind: ErrKind::Timeout,
+        title: "time or step limit",
+        rule: "The declaration ran past `--timeout` or `--max-steps`. It isn't known to be wrong, \
+               only too expensive to check under the limits given.",
+    },
+    ErrCode {
+        code: "E0302",
+        kind: ErrKind::OutOfMemory,
+        title: "memory limit",
+        rule: "The declaration needed more arena memory than `--max-memory` allows. It isn't known \
+               to be wrong, only too expensive to check under the limits given.",
+    },
+    ErrCode {
+        code: "E0303",
+        kind: ErrKind::ExpressionTooLarge,
+        title: "expression size or depth limit",
+        rule: "The declaration's proof built an expression with more nodes (counting shared \
+               subexpressions once per use) than `--max-expr-size` or more levels than \
+               `--max-expr-depth`. It isn't known to be wrong, only too expensive to check under \
+               the limits given.",
+    },
+    ErrCode {
+        code: "E0401",
+        kind: ErrKind::Strict,
+        title: "strict mode violation",
+        rule: "With `--strict`, the file has to be exactly what a well-behaved writer produces: \
+               reserved fields 0, nothing but zeros after the proof stream, no unused table slots, \
+               and backreferences in the smallest data field they fit in. Files that break this \
+               still prove what they say, they just aren't canonical.",
+    },
+    ErrCode {
+        code: "E0501",
+        kind: ErrKind::Disagreement,
+        title: "the two checkers disagree",
+        rule: "With `--dual`, every declaration is also checked by the kernel, and they came to \
+               different verdicts. One of the two checkers has a bug; which one is worth reporting.",
+    },
+    ErrCode {
+        code: "E0502",
+        kind: ErrKind::Kernel,
+        title: "rejected by the kernel",
+        rule: "The trusted kernel rejected a declaration that the main checker accepted. The reason \
+               names the rule broken at the command given.",
+    },
+    ErrCode {
+        code: "E0503",
+        kind: ErrKind::Divergence,
+        title: "verifier divergence",
+        rule: "With `--reference`, the reference verifier came to a different verdict on the files. \
+               If it rejects what this verifier accepts, don't trust the files until you know why; \
+               either way one of the two verifiers has a bug, or they disagree about the format.",
+    },
+    ErrCode {
+        code: "E0601",
+        kind: ErrKind::Warning,
+        title: "a denied warning",
+        rule: "With `--deny warnings`, each warning is an error too. Warnings are about things the \
+               format allows but that are probably mistakes: a hypothesis a proof never uses, a local \
+               theorem nothing uses, a sort with no terms or variables, or two identical defs.",
+    },
+    ErrCode {
+        code: "E0701",
+        kind: ErrKind::AxiomNotAllowed,
+        title: "uses an axiom that isn't allowed",
+        rule: "With `--allow-axioms` or `--deny-axioms`, a theorem whose proof applies an axiom \
+               the policy doesn't permit, or applies a theorem that does. The proof may well be \
+               right; it just rests on something the development isn't supposed to assume.",
+    },
+    ErrCode {
+        code: "E0901",
+        kind: ErrKind::MakeSure,
+        title: "a check failed",
+        rule: "One of the many small checks in the verifier failed; the file and line say which, \
+               and the comment there says what the mmb format requires. Most malformed proofs \
+               (wrong sorts, disjoint variable violations, leftover stack items) end up here.",
+    },
+    ErrCode {
+        code: "E0902",
+        kind: ErrKind::NoneErr,
+        title: "something expected wasn't there",
+        rule: "The verifier looked for something that had to exist (a table entry, the next \
+               command, a stack item) and found nothing; the file and line say what. Usually a \
+               truncated file or an offset that points past the end.",
+    },
+    ErrCode {
+        code: "E0903",
+        kind: ErrKind::ConvErr,
+        title: "a number out of range",
+        rule: "A number in the file doesn't fit where it's used, such as an offset too large for \
+               the platform; the file and line say which.",
+    },
+    ErrCode {
+        code: "E0904",
+        kind: ErrKind::Unreachable,
+        title: "an internal error",
+        rule: "The verifier reached a state it shouldn't be able to reach. This is a bug in the \
+               verifier, whatever the file contains; please report it.",
+    },
+    ErrCode {
+        code: "E0905",
+        kind: ErrKind::Msg,
+        title: "error with a message",
+        rule: "A failure described by its message: a bad magic number or version, a theorem whose \
+               statement doesn't match the mm0 file, a def or term that's declared wrongly, and \
+               other problems without a kind of their own.",
+    },
+];
+
+impl ErrKind {
+    /// The stable code for errors of this kind.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrKind::IoErr => "E0001",
+            ErrKind::DeclCount => "E0002",
+            ErrKind::Conformance => "E0003",
+            ErrKind::TooManyBoundVars => "E0101",
+            ErrKind::MalformedProof => "E0102",
+            ErrKind::MalformedUnify => "E0103",
+            ErrKind::BadBackref => "E0201",
+            ErrKind::WrongItem => "E0202",
+            ErrKind::ForwardReference => "E0203",
+            ErrKind::DefCycle => "E0204",
+            ErrKind::UnifyDelta => "E0205",
+            ErrKind::DependsOnFailed => "E0206",
+            ErrKind::HypNotProvable => "E0207",
+            ErrKind::StackUnderflow => "E0208",
+            ErrKind::Timeout => "E0301",
+            ErrKind::OutOfMemory => "E0302",
+            ErrKind::ExpressionTooLarge => "E0303",
+            ErrKind::Strict => "E0401",
+            ErrKind::Disagreement => "E0501",
+            ErrKind::Kernel => "E0502",
+            ErrKind::Divergence => "E0503",
+            ErrKind::Warning => "E0601",
+            ErrKind::AxiomNotAllowed => "E0701",
+            ErrKind::MakeSure => "E0901",
+            ErrKind::NoneErr => "E0902",
+            ErrKind::ConvErr => "E0903",
+            ErrKind::Unreachable => "E0904",
+            ErrKind::Msg => "E0905",
+        }
+    }
+}
+
+impl VerifErr {
+    /// The stable code for this error (see `codes`).
+    pub fn code(&self) -> &'static str {
+        self.kind().code()
+    }
+}
+
+/// What `code` (like `E0203`, in any case) means, if it's a code.
+pub fn explain(code: &str) -> Option<&'static ErrCode> {
+    CODES.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+#[test]
+fn codes1() {
+    for (i, c) in CODES.iter().enumerate() {
+        assert_eq!(c.kind.code(), c.code);
+        assert!(CODES[..i].iter().all(|d| d.code != c.code));
+        assert_eq!(explain(&c.code.to_lowercase()).unwrap().code, c.code);
+    }
+    assert!(explain("E9999").is_none());
+    let err: Result<(), _> = Err(VerifErr::BadBackref { decl: crate::mmb::stmt::StmtCmd::Sort { num: None }, index: 3, heap_len: 2 });
+    assert_eq!(crate::localize!(err).unwrap_err().code(), "E0201");
+}
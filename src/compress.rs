@@ -0,0 +1,143 @@
+//! Compressed mmb files. Big developments are shipped as `.mmb.gz` or `.mmb.zst`, so
+//! `FileData::new_from` looks at the first bytes of the mmb file and, if they're a gzip
+//! or zstd header, decompresses it on the way into memory. The extension doesn't matter
+//! for reading, except that the default mm0 file for `foo.mmb.gz` is `foo.mm0`.
+//!
+//! The decoders (and the encoders `--optimize --compress` uses) are behind the `compress`
+//! feature. Without it, a compressed file is recognized but rejected with a message
+//! saying so, rather than failing to parse as an mmb file.
+
+use std::io::{ self, Read, BufRead, BufReader };
+use std::path::{ Path, PathBuf };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub const ALL: [Compression; 2] = [Compression::Gzip, Compression::Zstd];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// By name or extension.
+    pub fn from_name(s: &str) -> Option<Self> {
+        Compression::ALL.iter().copied().find(|c| c.name() == s || c.extension() == s)
+    }
+
+    /// The format `bytes` (the start of a file) are compressed with, if any.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "compress")]
+fn decoder<'r>(format: Compression, reader: impl BufRead + 'r) -> io::Result<Box<dyn Read + 'r>> {
+    match format {
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+    }
+}
+
+#[cfg(not(feature = "compress"))]
+fn decoder<'r>(format: Compression, _: impl BufRead + 'r) -> io::Result<Box<dyn Read + 'r>> {
+    Err(unsupported(format))
+}
+
+#[cfg(not(feature = "compress"))]
+fn unsupported(format: Compression) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{} compression needs the `compress` feature", format.name()))
+}
+
+/// Read all of `reader`, decompressing it as it's read if it starts with a gzip or
+/// zstd header. `size_hint` is how much room to make for the result up front.
+pub fn read_maybe_compressed(reader: impl Read, size_hint: usize) -> io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(reader);
+    let mut out = Vec::with_capacity(size_hint);
+    match Compression::detect(reader.fill_buf()?) {
+        None => reader.read_to_end(&mut out)?,
+        Some(format) => decoder(format, reader)?.read_to_end(&mut out)?,
+    };
+    Ok(out)
+}
+
+/// `bytes` compressed with `format`, at the encoder's default level.
+#[cfg(feature = "compress")]
+pub fn compress(bytes: &[u8], format: Compression) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+    match format {
+        Compression::Gzip => {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            enc.write_all(bytes)?;
+            enc.finish()
+        }
+        Compression::Zstd => zstd::stream::encode_all(bytes, 0),
+    }
+}
+
+#[cfg(not(feature = "compress"))]
+pub fn compress(_: &[u8], format: Compression) -> io::Result<Vec<u8>> {
+    Err(unsupported(format))
+}
+
+/// `path` without a `.gz` or `.zst` extension.
+pub fn strip_extension(path: &Path) -> PathBuf {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if Compression::ALL.iter().any(|c| c.extension() == ext) => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The mm0 file that goes with `mmb_path` if none is given: `foo.mm0` for `foo.mmb`,
+/// `foo.mmb.gz` and `foo.mmb.zst`.
+pub fn default_mm0_path(mmb_path: &Path) -> PathBuf {
+    strip_extension(mmb_path).with_extension("mm0")
+}
+
+#[test]
+fn compress1() {
+    assert_eq!(default_mm0_path(Path::new("a/foo.mmb.zst")), Path::new("a/foo.mm0"));
+    assert_eq!(default_mm0_path(Path::new("foo.mmb.gz")), Path::new("foo.mm0"));
+    assert_eq!(default_mm0_path(Path::new("foo.mmb")), Path::new("foo.mm0"));
+    assert_eq!(strip_extension(Path::new("foo.gzip")), Path::new("foo.gzip"));
+
+    let mmb = std::fs::read("./test_resources/short.mmb").unwrap();
+    assert_eq!(read_maybe_compressed(&mmb[..], 0).unwrap(), mmb);
+    #[cfg(not(feature = "compress"))]
+    assert!(read_maybe_compressed(&[0x1f, 0x8b, 8, 0][..], 0).is_err());
+    #[cfg(feature = "compress")]
+    {
+        let dir = std::env::temp_dir().join(format!("second_opinion_compress1_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::copy("./test_resources/short.mm0", dir.join("short.mm0")).unwrap();
+        for format in Compression::ALL.iter() {
+            let packed = compress(&mmb, *format).unwrap();
+            assert_eq!(Compression::detect(&packed), Some(*format));
+            let path = dir.join(format!("short.mmb.{}", format.extension()));
+            std::fs::write(&path, packed).unwrap();
+            let file_data = crate::fs::FileData::new_from(&path, None::<PathBuf>).unwrap();
+            assert_eq!(file_data.mmb_file, mmb);
+            assert_eq!(file_data.mmz_files.len(), 1);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
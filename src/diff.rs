@@ -0,0 +1,221 @@
+//! Comparing two mmb files declaration by declaration, for reviewing updates to a
+//! database. Items are matched up by name (from the index, or by number if a file
+//! doesn't have one), and everything is compared by name rather than by number, so
+//! inserting a declaration doesn't make everything after it look changed.
+//!
+//! A change to what an item says (a sort's modifiers; a term's binders, return sort, or
+//! def body; an assertion's binders, hypotheses, or conclusion) is a statement change.
+//! A proof that changed under an unchanged statement is a proof-only change.
+
+use crate::prelude::*;
+use core::fmt::{ Display, Formatter, Result as FmtResult };
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::{ ProofIter, ProofCmd };
+use crate::mmb::unify::UnifyCmd;
+use crate::util::{ Res, Type, VerifErr, HashMap };
+use crate::none_err;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Change {
+    Added { table: &'static str, name: String },
+    Removed { table: &'static str, name: String },
+    Statement { table: &'static str, name: String },
+    ProofOnly { table: &'static str, name: String },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Change::Added { table, name } => write!(f, "+ {} {}", table, name),
+            Change::Removed { table, name } => write!(f, "- {} {}", table, name),
+            Change::Statement { table, name } => write!(f, "~ {} {}: statement changed", table, name),
+            Change::ProofOnly { table, name } => write!(f, "~ {} {}: proof changed", table, name),
+        }
+    }
+}
+
+/// What's compared for one declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Fingerprint {
+    pub statement: String,
+    pub proof: String,
+}
+
+impl<'a> Outline<'a> {
+    pub(crate) fn key(&self, stmt: StmtCmd) -> Res<String> {
+        Ok(match self.stmt_name(stmt) {
+            Some(name) => name.to_string(),
+            None => match stmt {
+                StmtCmd::Sort { num } => format!("#{}", none_err!(num)?),
+                StmtCmd::TermDef { num, .. } | StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => format!("#{}", none_err!(num)?),
+            }
+        })
+    }
+
+    pub(crate) fn sort_str(&self, sort_num: u8) -> String {
+        self.sort_name(sort_num).map(|s| s.to_string()).unwrap_or_else(|| format!("#{}", sort_num))
+    }
+
+    /// A binder like `{set}` for a bound variable, or `wff[0 2]` for a regular
+    /// variable depending on the first and third bound variables.
+    pub(crate) fn binder_str(&self, ty: Type) -> Res<String> {
+        let sort = self.sort_str(ty.sort());
+        if ty.is_bound() {
+            return Ok(format!("{{{}}}", sort))
+        }
+        let deps = ty.deps()?;
+        if deps == 0 {
+            return Ok(sort)
+        }
+        let idxs: Vec<String> = (0..56).filter(|i| deps & (1 << i) != 0).map(|i| i.to_string()).collect();
+        Ok(format!("{}[{}]", sort, idxs.join(" ")))
+    }
+
+    /// A proof stream with the terms, assertions, and sorts it mentions replaced by their names.
+    fn proof_str(&self, proof: ProofIter) -> Res<String> {
+        let mut out = String::new();
+        if proof.is_null() {
+            return Ok(out)
+        }
+        for maybe_cmd in proof {
+            let s = match maybe_cmd? {
+                ProofCmd::Term { term_num, save } =>
+                    format!("{}{}", if save { "T!" } else { "T" }, self.term_name(term_num).map(|s| s.to_string()).unwrap_or_else(|| format!("#{}", term_num))),
+                ProofCmd::Thm { thm_num, save } =>
+                    format!("{}{}", if save { "A!" } else { "A" }, self.assert_name(thm_num).map(|s| s.to_string()).unwrap_or_else(|| format!("#{}", thm_num))),
+                ProofCmd::Dummy { sort_num } => format!("D{}", self.sort_str(sort_num)),
+                owise => format!("{:?}", owise),
+            };
+            out.push_str(&s);
+            out.push(' ');
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn fingerprint(&self, stmt: StmtCmd, proof: ProofIter) -> Res<Fingerprint> {
+        let statement = match stmt {
+            StmtCmd::Sort { num } => format!("{}", self.get_sort_mods(none_err!(num)? as usize)?.inner),
+            StmtCmd::TermDef { num, .. } => {
+                let num = none_err!(num)?;
+                let term = self.get_term_by_num(num)?;
+                let mut binders = Vec::new();
+                for ty in term.args() {
+                    binders.push(self.binder_str(ty)?);
+                }
+                // The body only names its dummies, so their sorts go in separately.
+                let mut dummies = Vec::new();
+                if term.is_def() {
+                    for cmd in term.unify() {
+                        if let UnifyCmd::Dummy { sort_id } = cmd? {
+                            dummies.push(self.sort_str(sort_id));
+                        }
+                    }
+                }
+                format!("{} ({}) ({}) {:?}", if term.is_def() { "def" } else { "term" }, binders.join(" "), dummies.join(" "), self.def_body_sexpr(num)?)
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                let num = none_err!(num)?;
+                let mut binders = Vec::new();
+                for ty in self.get_assert_by_num(num)?.args() {
+                    binders.push(self.binder_str(ty)?);
+                }
+                let (hyps, concl) = self.assert_sexprs(num)?;
+                let kind = if let StmtCmd::Axiom {..} = stmt { "axiom" } else { "theorem" };
+                format!("{} ({}) {:?} {}", kind, binders.join(" "), hyps, concl)
+            }
+        };
+        Ok(Fingerprint { statement, proof: self.proof_str(proof)? })
+    }
+}
+
+pub(crate) fn table(stmt: StmtCmd) -> &'static str {
+    match stmt {
+        StmtCmd::Sort {..} => "sort",
+        StmtCmd::TermDef {..} => "term",
+        StmtCmd::Axiom {..} | StmtCmd::Thm {..} => "thm",
+    }
+}
+
+/// Every difference between the declarations of `old` and `new`: changed and added items
+/// in the order `new` declares them, then removed items in the order `old` declared them.
+pub fn diff(old: &Outline, new: &Outline) -> Res<Vec<Change>> {
+    let mut old_keyed = HashMap::new();
+    for (stmt, proof) in old.declarations.iter() {
+        if old_keyed.insert((table(*stmt), old.key(*stmt)?), (*stmt, *proof, false)).is_some() {
+            return Err(VerifErr::Msg(format!("the old file declares {} {} twice", table(*stmt), old.key(*stmt)?)))
+        }
+    }
+
+    let mut out = Vec::new();
+    for (stmt, proof) in new.declarations.iter() {
+        let (table, name) = (table(*stmt), new.key(*stmt)?);
+        match old_keyed.get_mut(&(table, name.clone())) {
+            None => out.push(Change::Added { table, name }),
+            Some((old_stmt, old_proof, seen)) => {
+                if *seen {
+                    return Err(VerifErr::Msg(format!("the new file declares {} {} twice", table, name)))
+                }
+                *seen = true;
+                let (before, after) = (old.fingerprint(*old_stmt, *old_proof)?, new.fingerprint(*stmt, *proof)?);
+                if before.statement != after.statement {
+                    out.push(Change::Statement { table, name })
+                } else if before.proof != after.proof {
+                    out.push(Change::ProofOnly { table, name })
+                }
+            }
+        }
+    }
+
+    for (stmt, _) in old.declarations.iter() {
+        let key = (table(*stmt), old.key(*stmt)?);
+        if let Some((_, _, false)) = old_keyed.get(&key) {
+            out.push(Change::Removed { table: key.0, name: key.1 });
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn diff1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(diff(&outline, &outline).unwrap().is_empty());
+
+    // Dropping `not` and `ax_1`/`ax_3` renumbers `ax_2` and `ax_mp`, which shouldn't count as a change.
+    let ax_2 = outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some("ax_2")).unwrap();
+    let ax_mp = outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some("ax_mp")).unwrap();
+    let sliced = crate::fs::FileData::new_from_bytes(outline.slice(&[ax_2, ax_mp]).unwrap(), Vec::new());
+    let sliced = Outline::new_from(&sliced).unwrap();
+    let changes = diff(&outline, &sliced).unwrap();
+    assert_eq!(changes, vec![
+        Change::Removed { table: "term", name: "not".to_string() },
+        Change::Removed { table: "thm", name: "ax_1".to_string() },
+        Change::Removed { table: "thm", name: "ax_3".to_string() },
+    ]);
+    let changes = diff(&sliced, &outline).unwrap();
+    assert_eq!(changes.len(), 3);
+    assert!(changes.iter().all(|c| matches!(c, Change::Added {..})));
+
+    // Flip `wff` from strict to not strict.
+    let mut bytes = file_data.mmb_file.clone();
+    bytes[outline.header.sort_data_start as usize] ^= crate::mmb::SORT_STRICT;
+    let changed = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let changed = Outline::new_from(&changed).unwrap();
+    assert_eq!(diff(&outline, &changed).unwrap(), vec![Change::Statement { table: "sort", name: "wff".to_string() }]);
+}
+
+#[test]
+fn diff_dummies1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_DUMMY, UNIFY_REF };
+    let fx = Fixture::bound_vars(1);
+    // The same body, `all d0 v0`, with a dummy of sort 0 instead of 1.
+    let mut fx2 = fx.clone();
+    let def = fx2.terms.iter_mut().rev().find(|t| t.def.is_some()).unwrap();
+    def.def = Some(stream(&[(UNIFY_TERM, 1), (UNIFY_DUMMY, 0), (UNIFY_REF, 1)]));
+    let changes = fx.with_outline(|outline| fx2.with_outline(|changed| diff(outline, changed).unwrap()));
+    assert_eq!(changes.len(), 1);
+    assert!(matches!(&changes[0], Change::Statement { table: "term", .. }));
+}
@@ -0,0 +1,371 @@
+//! A lightweight description of the environment (sorts, terms, and assertions) declared by
+//! an mmb file, which can be written out as JSON and compared against the same kind of dump
+//! produced by another verifier. This is much cheaper than full differential verification,
+//! but catches the two tools disagreeing about what the file actually says.
+//!
+//! The dump format is:
+//!```text
+//! {
+//!   "sorts": [ { "num": 0, "name": "wff", "mods": 6 }, .. ],
+//!   "terms": [ { "num": 0, "name": "im", "def": false, "hash": "<sha256 hex>" }, .. ],
+//!   "thms":  [ { "num": 0, "name": "ax_1", "axiom": true, "hash": "<sha256 hex>" }, .. ]
+//! }
+//!```
+//! Only `num` is required; every other field is compared when both sides have it.
+//! The hash of a term is the SHA-256 of its sort byte, its argument types (including the return
+//! type) as little-endian u64s, and (for defs) its unify stream including the terminating 0.
+//! The hash of an assertion is the SHA-256 of its argument types and its unify stream.
+
+use crate::prelude::*;
+use core::fmt::{ Display, Formatter, Result as FmtResult };
+use sha2::{ Sha256, Digest };
+use crate::Outline;
+use crate::json::Json;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::unify::{ UnifyIter, UnifyCmd };
+use crate::util::{ Res, VerifErr };
+use crate::{ none_err, make_sure, conv_err };
+use crate::mmz::Prec;
+
+pub fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        out.push_str(&format!("{:02x}", b));
+    }
+    out
+}
+
+pub fn unhex(s: &str) -> Res<Vec<u8>> {
+    make_sure!(s.len() & 1 == 0 && s.is_ascii());
+    (0..s.len())
+        .step_by(2)
+        .map(|i| conv_err!(u8::from_str_radix(&s[i..i + 2], 16)))
+        .collect()
+}
+
+/// The bytes of a unify stream, including the terminating 0 command.
+fn unify_bytes<'a>(unify: UnifyIter<'a>) -> Res<&'a [u8]> {
+    let start = unify.pos;
+    let mut it = unify;
+    for maybe_cmd in &mut it {
+        maybe_cmd?;
+    }
+    none_err!(unify.buf.get(start..it.pos + 1))
+}
+
+impl<'a> Outline<'a> {
+    /// A digest of everything the mmb file says about a term's statement.
+    pub fn term_hash(&self, term_num: u32) -> Res<[u8; 32]> {
+        let term = self.get_term_by_num(term_num)?;
+        let mut h = Sha256::new();
+        h.update([term.sort]);
+        h.update(term.args_start);
+        if term.is_def() {
+            h.update(unify_bytes(term.unify())?);
+        }
+        Ok(h.finalize().into())
+    }
+
+    /// A digest of everything the mmb file says about an assertion's statement.
+    pub fn assert_hash(&self, assert_num: u32) -> Res<[u8; 32]> {
+        let assert = self.get_assert_by_num(assert_num)?;
+        let mut h = Sha256::new();
+        h.update(assert.args_start);
+        h.update(unify_bytes(assert.unify())?);
+        Ok(h.finalize().into())
+    }
+
+    /// An assertion's hypotheses and conclusion as s-expressions like `(im v0 (not v1))`,
+    /// read off its unify stream. Arguments are called `v<i>` and dummies `d<i>`.
+    pub fn assert_sexprs(&self, assert_num: u32) -> Res<(Vec<String>, String)> {
+        self.assert_sexprs_named(assert_num, &|t| self.term_sexpr_name(t), &|i| format!("v{}", i))
+    }
+
+    /// `assert_sexprs`, with terms named by `term` and arguments by `var`, for exporters.
+    pub fn assert_sexprs_named(&self, assert_num: u32, term: &dyn Fn(u32) -> String, var: &dyn Fn(usize) -> String) -> Res<(Vec<String>, String)> {
+        let assert = self.get_assert_by_num(assert_num)?;
+        let mut heap: Vec<String> = (0..assert.num_args() as usize).map(var).collect();
+        let mut num_dummies = 0;
+        let mut it = assert.unify();
+        let concl = self.read_sexpr(&mut it, &mut heap, &mut num_dummies, term)?;
+        let mut hyps = Vec::new();
+        while let Some(maybe_cmd) = it.next() {
+            match maybe_cmd? {
+                UnifyCmd::Hyp => hyps.push(self.read_sexpr(&mut it, &mut heap, &mut num_dummies, term)?),
+                _ => return Err(VerifErr::Msg(format!("expected UHyp in the unify stream of assertion {}", assert_num))),
+            }
+        }
+        // The unify stream lists the hypotheses last to first.
+        hyps.reverse();
+        Ok((hyps, concl))
+    }
+
+    /// The body of a def as an s-expression, in the same style as `assert_sexprs`,
+    /// or `None` for a plain term.
+    pub fn def_body_sexpr(&self, term_num: u32) -> Res<Option<String>> {
+        Ok(self.def_body_sexpr_named(term_num, &|t| self.term_sexpr_name(t), &|i| format!("v{}", i))?.map(|(body, _)| body))
+    }
+
+    /// `def_body_sexpr`, with terms named by `term` and arguments by `var`, and the number
+    /// of dummies in the body.
+    pub fn def_body_sexpr_named(&self, term_num: u32, term: &dyn Fn(u32) -> String, var: &dyn Fn(usize) -> String) -> Res<Option<(String, usize)>> {
+        let def = self.get_term_by_num(term_num)?;
+        if !def.is_def() {
+            return Ok(None)
+        }
+        let mut heap: Vec<String> = (0..def.num_args_no_ret() as usize).map(var).collect();
+        let mut num_dummies = 0;
+        let mut it = def.unify;
+        let body = self.read_sexpr(&mut it, &mut heap, &mut num_dummies, term)?;
+        Ok(Some((body, num_dummies)))
+    }
+
+    /// `assert_sexprs`, but in the mm0 file's notation when `notations` is set.
+    pub fn assert_strs(&self, assert_num: u32) -> Res<(Vec<String>, String)> {
+        let assert = self.get_assert_by_num(assert_num)?;
+        let mut heap = (0..assert.num_args() as usize).map(|i| (format!("v{}", i), Prec::Max)).collect::<Vec<_>>();
+        let mut num_dummies = 0;
+        let mut it = assert.unify();
+        let mut read = |it: &mut UnifyIter<'a>| self.read_unify(it, &mut heap, &mut num_dummies, &|name| (name, Prec::Max), &|t, args| {
+            self.show_app(t, self.term_sexpr_name(t), args)
+        }).map(|(s, _)| s);
+        let concl = read(&mut it)?;
+        let mut hyps = Vec::new();
+        while let Some(maybe_cmd) = it.next() {
+            match maybe_cmd? {
+                UnifyCmd::Hyp => hyps.push(read(&mut it)?),
+                _ => return Err(VerifErr::Msg(format!("expected UHyp in the unify stream of assertion {}", assert_num))),
+            }
+        }
+        hyps.reverse();
+        Ok((hyps, concl))
+    }
+
+    /// `def_body_sexpr`, but in the mm0 file's notation when `notations` is set.
+    pub fn def_body_str(&self, term_num: u32) -> Res<Option<String>> {
+        let def = self.get_term_by_num(term_num)?;
+        if !def.is_def() {
+            return Ok(None)
+        }
+        let mut heap = (0..def.num_args_no_ret() as usize).map(|i| (format!("v{}", i), Prec::Max)).collect::<Vec<_>>();
+        let mut it = def.unify;
+        let (body, _) = self.read_unify(&mut it, &mut heap, &mut 0, &|name| (name, Prec::Max), &|t, args| {
+            self.show_app(t, self.term_sexpr_name(t), args)
+        })?;
+        Ok(Some(body))
+    }
+
+    fn term_sexpr_name(&self, term_num: u32) -> String {
+        self.term_name(term_num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", term_num))
+    }
+
+    fn read_sexpr(&self, it: &mut UnifyIter<'a>, heap: &mut Vec<String>, num_dummies: &mut usize, term: &dyn Fn(u32) -> String) -> Res<String> {
+        self.read_unify(it, heap, num_dummies, &|name| name, &|term_num, args| {
+            let name = term(term_num);
+            if args.is_empty() { name } else { format!("({} {})", name, args.join(" ")) }
+        })
+    }
+
+    /// Read an expression off a unify stream, building it with `var` (for dummies) and
+    /// `app`. Saved subterms and references to them are shared through `heap`.
+    fn read_unify<T: Clone>(
+        &self,
+        it: &mut UnifyIter<'a>,
+        heap: &mut Vec<T>,
+        num_dummies: &mut usize,
+        var: &dyn Fn(String) -> T,
+        app: &dyn Fn(u32, Vec<T>) -> T,
+    ) -> Res<T> {
+        match none_err!(it.next())?? {
+            UnifyCmd::Ref(i) => Ok(none_err!(heap.get(i as usize))?.clone()),
+            UnifyCmd::Dummy { .. } => {
+                let name = var(format!("d{}", num_dummies));
+                *num_dummies += 1;
+                heap.push(name.clone());
+                Ok(name)
+            }
+            UnifyCmd::Term { term_num, save } => {
+                // A saved term gets its heap slot before its subterms do.
+                let slot = heap.len();
+                if save {
+                    heap.push(var(String::new()));
+                }
+                let num_args = self.get_term_by_num(term_num)?.args_no_ret().len();
+                let mut args = Vec::with_capacity(num_args);
+                for _ in 0..num_args {
+                    args.push(self.read_unify(it, heap, num_dummies, var, app)?);
+                }
+                let out = app(term_num, args);
+                if save {
+                    heap[slot] = out.clone();
+                }
+                Ok(out)
+            }
+            UnifyCmd::Hyp => Err(VerifErr::Msg("unexpected UHyp inside an expression".to_string())),
+        }
+    }
+
+    /// Describe the environment declared by the mmb file in the dump format.
+    pub fn env_dump(&self) -> Res<Json> {
+        let mut sorts = Vec::new();
+        let mut terms = Vec::new();
+        let mut thms = Vec::new();
+        for (stmt, _) in self.declarations.iter() {
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    sorts.push(Json::obj()
+                        .with("num", num)
+                        .with("name", self.sort_name(num))
+                        .with("mods", self.get_sort_mods(num as usize)?.inner))
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    terms.push(Json::obj()
+                        .with("num", num)
+                        .with("name", self.term_name(num))
+                        .with("def", self.get_term_by_num(num)?.is_def())
+                        .with("hash", hex(&self.term_hash(num)?)))
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    thms.push(Json::obj()
+                        .with("num", num)
+                        .with("name", self.assert_name(num))
+                        .with("axiom", matches!(stmt, StmtCmd::Axiom {..}))
+                        .with("hash", hex(&self.assert_hash(num)?)))
+                }
+            }
+        }
+        Ok(Json::obj().with("sorts", sorts).with("terms", terms).with("thms", thms))
+    }
+}
+
+/// One way in which two environment dumps disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// The tables have a different number of entries.
+    Count { table: String, ours: usize, theirs: usize },
+    /// An item (identified by name, or by number if unnamed) only one side has.
+    OnlyOurs { table: String, key: String },
+    OnlyTheirs { table: String, key: String },
+    /// Both sides have the item, but disagree about one of its fields.
+    Field { table: String, key: String, field: String, ours: String, theirs: String },
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Divergence::Count { table, ours, theirs } =>
+                write!(f, "{}: we have {} entries, they have {}", table, ours, theirs),
+            Divergence::OnlyOurs { table, key } =>
+                write!(f, "{} {}: only in our environment", table, key),
+            Divergence::OnlyTheirs { table, key } =>
+                write!(f, "{} {}: only in their environment", table, key),
+            Divergence::Field { table, key, field, ours, theirs } =>
+                write!(f, "{} {}: `{}` differs; ours is {}, theirs is {}", table, key, field, ours, theirs),
+        }
+    }
+}
+
+/// Items are matched by name when both sides name them, and by number otherwise.
+fn item_key(item: &Json) -> Res<String> {
+    match item.get("name").and_then(|n| n.as_str()) {
+        Some(name) => Ok(name.to_string()),
+        None => {
+            let num = none_err!(item.get("num").and_then(|n| n.as_u64()))
+                .map_err(|_| VerifErr::Msg(format!("environment dump item without a name or number: {}", item)))?;
+            Ok(format!("#{}", num))
+        }
+    }
+}
+
+/// Compare two environment dumps, reporting every divergence found.
+pub fn compare_envs(ours: &Json, theirs: &Json) -> Res<Vec<Divergence>> {
+    let mut out = Vec::new();
+    for table in ["sorts", "terms", "thms"].iter() {
+        let table_s = table.to_string();
+        let empty = Vec::new();
+        let ours_t = ours.get(table).and_then(|t| t.as_arr()).unwrap_or(&empty);
+        let theirs_t = theirs.get(table).and_then(|t| t.as_arr()).unwrap_or(&empty);
+        if ours_t.len() != theirs_t.len() {
+            out.push(Divergence::Count { table: table_s.clone(), ours: ours_t.len(), theirs: theirs_t.len() });
+        }
+
+        let mut theirs_keyed = Vec::with_capacity(theirs_t.len());
+        for item in theirs_t {
+            theirs_keyed.push((item_key(item)?, item, false));
+        }
+
+        for item in ours_t {
+            let key = item_key(item)?;
+            match theirs_keyed.iter_mut().find(|(k, _, _)| *k == key) {
+                None => out.push(Divergence::OnlyOurs { table: table_s.clone(), key }),
+                Some((_, other, seen)) => {
+                    *seen = true;
+                    if let Json::Obj(fields) = item {
+                        for (field, ours_v) in fields {
+                            match other.get(field) {
+                                Some(theirs_v) if theirs_v != ours_v && *theirs_v != Json::Null && *ours_v != Json::Null => {
+                                    out.push(Divergence::Field {
+                                        table: table_s.clone(),
+                                        key: key.clone(),
+                                        field: field.clone(),
+                                        ours: ours_v.to_string(),
+                                        theirs: theirs_v.to_string(),
+                                    })
+                                }
+                                _ => continue
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for (key, _, seen) in theirs_keyed {
+            if !seen {
+                out.push(Divergence::OnlyTheirs { table: table_s.clone(), key });
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn assert_sexprs1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let (hyps, concl) = outline.assert_sexprs(0).unwrap();
+    assert!(hyps.is_empty());
+    assert_eq!(concl, "(im v0 (im v1 v0))");
+    let (hyps, concl) = outline.assert_sexprs(3).unwrap();
+    assert_eq!(hyps, vec!["(im v0 v1)".to_string(), "v0".to_string()]);
+    assert_eq!(concl, "v1");
+}
+
+#[test]
+fn compare_envs1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let ours = outline.env_dump().unwrap();
+    let reparsed = crate::json::parse(&ours.pretty()).unwrap();
+    assert!(compare_envs(&ours, &reparsed).unwrap().is_empty());
+
+    let theirs = crate::json::parse(r#"{
+        "sorts": [{ "num": 0, "name": "wff", "mods": 7 }],
+        "terms": [{ "num": 0, "name": "im" }, { "num": 1, "name": "not" }, { "num": 2, "name": "and" }],
+        "thms": []
+    }"#).unwrap();
+    let divs = compare_envs(&ours, &theirs).unwrap();
+    assert!(divs.contains(&Divergence::Field {
+        table: "sorts".to_string(),
+        key: "wff".to_string(),
+        field: "mods".to_string(),
+        ours: "6".to_string(),
+        theirs: "7".to_string(),
+    }));
+    assert!(divs.contains(&Divergence::OnlyTheirs { table: "terms".to_string(), key: "and".to_string() }));
+    assert!(divs.contains(&Divergence::Count { table: "thms".to_string(), ours: 4, theirs: 0 }));
+    assert_eq!(divs.iter().filter(|d| matches!(d, Divergence::OnlyOurs {..})).count(), 4);
+}
@@ -0,0 +1,197 @@
+//! Exporting a verified mmb file to Dedukti, to be checked again in an independent
+//! logical framework (`dk check out.dk`).
+//!
+//! The encoding is first-order: each sort is an object of `Sort`, its expressions have
+//! type `El s`, and a proof of `e` in a provable sort `s` has type `Prf s e`. Terms become
+//! constants, defs become Dedukti definitions (so conversions are checked by unfolding
+//! them), axioms become constants, and theorems become `thm`s whose proofs are read off
+//! their proof trees. Bound variables are just variables of their sort: the side conditions
+//! on them were checked by the verifier, and the encoding doesn't repeat them.
+//!
+//! Dummies can't be expressed: a def whose body has them is exported without its body,
+//! and a theorem whose proof uses them is exported as an axiom. Each case gets a comment.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::export::rename::{ NameMap, RenamePolicy };
+use crate::mmb::{ MmbItem, MmbExpr };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::tree::ProofNode;
+use crate::util::{ Res, Type, VerifErr };
+use crate::none_err;
+
+const PRELUDE: &str = "\
+(; MM0 sorts, their expressions, and proofs in provable sorts ;)
+Sort : Type.
+El : Sort -> Type.
+Prf : s : Sort -> El s -> Type.
+";
+
+fn var(i: usize) -> String {
+    format!("v'{}", i)
+}
+
+fn hyp(i: usize) -> String {
+    format!("h'{}", i)
+}
+
+/// `A -> ... -> ret`.
+fn arrows(bs: &[(String, String)], ret: &str) -> String {
+    let mut out = String::new();
+    for (_, ty) in bs.iter() {
+        out.push_str(&format!("{} -> ", ty));
+    }
+    out.push_str(ret);
+    out
+}
+
+/// `x : A -> ... -> ret`, or `x : A => ... => ret` for lambdas.
+fn binders(bs: &[(String, String)], arrow: &str, ret: &str) -> String {
+    let mut out = String::new();
+    for (x, ty) in bs.iter() {
+        out.push_str(&format!("{} : {} {} ", x, ty, arrow));
+    }
+    out.push_str(ret);
+    out
+}
+
+impl<'a> Outline<'a> {
+    fn dk_el(&self, names: &NameMap, ty: Type) -> Res<String> {
+        Ok(format!("(El {})", none_err!(names.sorts.get(ty.sort() as usize))?))
+    }
+
+    fn dk_item(&self, names: &NameMap, num_args: usize, item: &MmbItem) -> Option<String> {
+        match item {
+            MmbItem::Expr(MmbExpr::Var { idx, .. }) if *idx < num_args => Some(var(*idx)),
+            MmbItem::Expr(MmbExpr::App { term_num, args, .. }) => {
+                let name = names.terms.get(*term_num as usize)?.clone();
+                if args.is_empty() {
+                    return Some(name)
+                }
+                let mut out = format!("({}", name);
+                for a in args.iter() {
+                    out.push(' ');
+                    out.push_str(&self.dk_item(names, num_args, a)?);
+                }
+                out.push(')');
+                Some(out)
+            }
+            // A dummy, or something that isn't an expression.
+            _ => None,
+        }
+    }
+
+    fn dk_proof(&self, names: &NameMap, num_args: usize, node: &ProofNode) -> Option<String> {
+        match node {
+            ProofNode::Hyp { idx, .. } => Some(hyp(*idx)),
+            ProofNode::Thm { thm_num, args, hyps, .. } => {
+                let mut out = format!("({}", names.thms.get(*thm_num as usize)?);
+                for a in args.iter() {
+                    out.push(' ');
+                    out.push_str(&self.dk_item(names, num_args, a)?);
+                }
+                for h in hyps.iter() {
+                    out.push(' ');
+                    out.push_str(&self.dk_proof(names, num_args, h)?);
+                }
+                out.push(')');
+                Some(out)
+            }
+            // Dedukti converts by itself, by unfolding defs.
+            ProofNode::Conv { proof, .. } => self.dk_proof(names, num_args, proof),
+        }
+    }
+
+    /// The file as a Dedukti signature, and the names its items got under `policy` (usually
+    /// `RenamePolicy::dedukti()`).
+    pub fn to_dedukti(&'a self, policy: &RenamePolicy) -> Res<(String, NameMap)> {
+        let names = self.export_names(policy.clone())?;
+        let term = |t: u32| names.terms.get(t as usize).cloned().unwrap_or_else(|| format!("t{}", t));
+        let mut out = String::from(PRELUDE);
+        for (stmt, proof) in self.declarations.iter() {
+            out.push('\n');
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    out.push_str(&format!("{} : Sort.\n", none_err!(names.sorts.get(none_err!(num)? as usize))?));
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    let t = self.get_term_by_num(num)?;
+                    let name = none_err!(names.terms.get(num as usize))?;
+                    let mut bs = Vec::new();
+                    for (i, ty) in t.args_no_ret().enumerate() {
+                        bs.push((var(i), self.dk_el(&names, ty)?));
+                    }
+                    let ret = self.dk_el(&names, t.ret())?;
+                    match self.def_body_sexpr_named(num, &term, &var)? {
+                        None => out.push_str(&format!("{} : {}.\n", name, arrows(&bs, &ret))),
+                        Some((_, dummies)) if dummies != 0 => {
+                            out.push_str("(; the body of this def has dummies, which this encoding can't express ;)\n");
+                            out.push_str(&format!("{} : {}.\n", name, arrows(&bs, &ret)));
+                        }
+                        Some((body, _)) => out.push_str(&format!(
+                            "def {} : {} :=\n  {}.\n",
+                            name,
+                            arrows(&bs, &ret),
+                            binders(&bs, "=>", &body)
+                        )),
+                    }
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let name = none_err!(names.thms.get(num as usize))?;
+                    let args = self.get_assert_by_num(num)?.args().collect::<Vec<_>>();
+                    let (hyps, concl) = self.assert_sexprs_named(num, &term, &var)?;
+                    let prf = |e: &str| -> Res<String> {
+                        Ok(format!("(Prf {} {})", none_err!(names.sorts.get(self.export_sort_of(&names, &args, &var, e)? as usize))?, e))
+                    };
+                    let mut bs = Vec::new();
+                    for (i, ty) in args.iter().enumerate() {
+                        bs.push((var(i), self.dk_el(&names, *ty)?));
+                    }
+                    for (i, h) in hyps.iter().enumerate() {
+                        bs.push((hyp(i), prf(h)?));
+                    }
+                    let ty = binders(&bs, "->", &prf(&concl)?);
+                    let proof_term = match stmt {
+                        StmtCmd::Thm { .. } => {
+                            let mut bump = Bump::new();
+                            let tree = self.proof_tree(&mut bump, *stmt, *proof)?;
+                            match self.dk_proof(&names, args.len(), tree) {
+                                Some(p) => Some(p),
+                                None => {
+                                    out.push_str("(; the proof of this theorem uses dummies, which this encoding can't express ;)\n");
+                                    None
+                                }
+                            }
+                        }
+                        _ => None,
+                    };
+                    match proof_term {
+                        None => out.push_str(&format!("{} : {}.\n", name, ty)),
+                        Some(p) => out.push_str(&format!("thm {} : {} :=\n  {}.\n", name, ty, binders(&bs, "=>", &p))),
+                    }
+                }
+            }
+        }
+        Ok((out, names))
+    }
+}
+
+#[test]
+fn dedukti1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let (dk, _) = outline.to_dedukti(&RenamePolicy::dedukti()).unwrap();
+    assert!(dk.starts_with(PRELUDE));
+    assert!(dk.contains("\nwff : Sort.\n"));
+    assert!(dk.contains("\nim : (El wff) -> (El wff) -> (El wff).\n"));
+    assert!(dk.contains("\nax_mp : v'0 : (El wff) -> v'1 : (El wff) -> h'0 : (Prf wff (im v'0 v'1)) -> h'1 : (Prf wff v'0) -> (Prf wff v'1).\n"));
+
+    let fx = crate::testgen::Fixture::valid();
+    fx.with_outline(|outline| {
+        let (dk, _) = outline.to_dedukti(&RenamePolicy::dedukti()).unwrap();
+        assert!(dk.contains("thm a1 : v'0 : (El s0) -> v'1 : (El s0) -> (Prf s0 (t0 (t0 v'0 v'1) (t0 v'0 v'1))) :=\n  v'0 : (El s0) => v'1 : (El s0) => (a0 (t0 v'0 v'1)).\n"));
+    });
+}
@@ -0,0 +1,231 @@
+//! Exporting the statements of a verified mmb file as a Lean 4 file of stubs: every
+//! sort and term becomes an `axiom` constant, axioms stay axioms, and theorems are stated
+//! with `:= sorry`. Nothing is proved on the Lean side; the file is for reading what a
+//! development proves, and as a starting point for bridging it to Lean's own notions.
+//!
+//! A `LeanNotation` says how to show sorts and terms in Lean terms instead: `sort wff Prop`
+//! makes `wff` Lean's `Prop` (so a statement `$ a $` is just `a`), and `term im {0} → {1}`
+//! prints `im a b` as `a → b`. A sort or term with notation isn't declared. Provable sorts
+//! without notation get a predicate `s.Prf` for their statements.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::export::rename::{ NameMap, RenamePolicy };
+use crate::mmb::{ MmbItem, MmbExpr };
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr, HashMap };
+use crate::none_err;
+
+/// How to show sorts and terms in Lean, by MM0 name.
+#[derive(Debug, Clone, Default)]
+pub struct LeanNotation {
+    /// A Lean type for each sort.
+    pub sorts: HashMap<String, String>,
+    /// A template for each term, with `{0}`, `{1}`, ... for its arguments.
+    pub terms: HashMap<String, String>,
+}
+
+impl LeanNotation {
+    /// Read notation from lines `sort <mm0 name> <lean type>` and `term <mm0 name> <template>`,
+    /// where the type or template is the rest of the line. Blank lines and lines starting
+    /// with `--` are skipped.
+    pub fn parse(s: &str) -> Res<Self> {
+        let mut notation = LeanNotation::default();
+        for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with("--")) {
+            let mut words = line.splitn(3, char::is_whitespace);
+            match (words.next(), words.next(), words.next().map(|r| r.trim())) {
+                (Some("sort"), Some(name), Some(ty)) if !ty.is_empty() => { notation.sorts.insert(name.to_string(), ty.to_string()); }
+                (Some("term"), Some(name), Some(tmpl)) if !tmpl.is_empty() => { notation.terms.insert(name.to_string(), tmpl.to_string()); }
+                _ => return Err(VerifErr::Msg(format!("bad Lean notation line: {}", line))),
+            }
+        }
+        Ok(notation)
+    }
+}
+
+/// Whether `s` is wrapped in a single pair of parentheses.
+fn enclosed(s: &str) -> bool {
+    if !s.starts_with('(') {
+        return false
+    }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i == s.len() - 1
+        }
+    }
+    false
+}
+
+fn paren(s: String, arg: bool) -> String {
+    if arg && s.contains(' ') && !enclosed(&s) {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+struct Ctx<'n> {
+    names: NameMap,
+    notation: &'n LeanNotation,
+    /// The MM0 name of each sort and term, for looking up notation.
+    sort_keys: Vec<String>,
+    term_keys: Vec<String>,
+}
+
+impl<'n> Ctx<'n> {
+    fn sort(&self, sort: u8) -> Res<String> {
+        let key = none_err!(self.sort_keys.get(sort as usize))?;
+        match self.notation.sorts.get(key) {
+            Some(ty) => Ok(ty.clone()),
+            None => Ok(none_err!(self.names.sorts.get(sort as usize))?.clone()),
+        }
+    }
+
+    /// An expression, parenthesized if it's an argument and needs it.
+    fn expr(&self, e: &MmbExpr, arg: bool) -> Res<String> {
+        match e {
+            MmbExpr::Var { idx, .. } => Ok(format!("v{}", idx)),
+            MmbExpr::App { term_num, args, .. } => {
+                let mut rendered = Vec::new();
+                for a in args.iter() {
+                    match a {
+                        MmbItem::Expr(a) => rendered.push(self.expr(a, true)?),
+                        _ => return Err(VerifErr::Msg(format!("expected an expression, got {:?}", a))),
+                    }
+                }
+                let key = none_err!(self.term_keys.get(*term_num as usize))?;
+                let out = match self.notation.terms.get(key) {
+                    Some(tmpl) => rendered.iter().enumerate().fold(tmpl.clone(), |s, (i, a)| s.replace(&format!("{{{}}}", i), a)),
+                    None => {
+                        let mut out = none_err!(self.names.terms.get(*term_num as usize))?.clone();
+                        for a in rendered {
+                            out.push(' ');
+                            out.push_str(&a);
+                        }
+                        out
+                    }
+                };
+                Ok(paren(out, arg))
+            }
+        }
+    }
+
+    /// What it means to prove `e`: `e` itself in a sort shown as `Prop`, or `s.Prf e`.
+    fn stmt(&self, e: &MmbExpr) -> Res<String> {
+        let sort = match e {
+            MmbExpr::Var { ty, .. } | MmbExpr::App { ty, .. } => ty.sort(),
+        };
+        if self.sort(sort)? == "Prop" {
+            self.expr(e, false)
+        } else {
+            Ok(format!("{}.Prf {}", none_err!(self.names.sorts.get(sort as usize))?, self.expr(e, true)?))
+        }
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// The file's statements as Lean 4 stubs, and the names their declarations got under
+    /// `policy` (usually `RenamePolicy::lean()`).
+    pub fn to_lean(&'a self, notation: &LeanNotation, policy: &RenamePolicy) -> Res<(String, NameMap)> {
+        let names = self.export_names(policy.clone())?;
+        let sort_keys = (0..names.sorts.len()).map(|n| self.sort_name(n as u8).map(|s| s.to_string()).unwrap_or_else(|| format!("s{}", n))).collect();
+        let term_keys = (0..names.terms.len()).map(|n| self.term_name(n as u32).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", n))).collect();
+        let cx = Ctx { names, notation, sort_keys, term_keys };
+        let reserved = policy.reserved.clone();
+        let mut out = String::from("-- The statements of an MM0 development. The proofs are in the mmb file.\n\n");
+        for (stmt, _) in self.declarations.iter() {
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    let name = none_err!(cx.names.sorts.get(num as usize))?;
+                    if !notation.sorts.contains_key(&cx.sort_keys[num as usize]) {
+                        out.push_str(&format!("axiom {} : Type\n", name));
+                    }
+                    if self.get_sort_mods(num as usize)?.is_provable() && cx.sort(num)? != "Prop" {
+                        out.push_str(&format!("axiom {}.Prf : {} → Prop\n", name, cx.sort(num)?));
+                    }
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    if notation.terms.contains_key(&cx.term_keys[num as usize]) {
+                        continue
+                    }
+                    let t = self.get_term_by_num(num)?;
+                    let mut ty = String::new();
+                    for arg in t.args_no_ret() {
+                        ty.push_str(&format!("{} → ", paren(cx.sort(arg.sort())?, true)));
+                    }
+                    ty.push_str(&cx.sort(t.ret().sort())?);
+                    out.push_str(&format!("axiom {} : {}\n", none_err!(cx.names.terms.get(num as usize))?, ty));
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let bump = Bump::new();
+                    let st = self.statement(&bump, num)?;
+                    let mut decl = format!(
+                        "{} {}",
+                        if let StmtCmd::Axiom { .. } = stmt { "axiom" } else { "theorem" },
+                        none_err!(cx.names.thms.get(num as usize))?
+                    );
+                    for (i, arg) in st.args.iter().enumerate() {
+                        if let MmbExpr::Var { ty, .. } = arg {
+                            decl.push_str(&format!(" (v{} : {})", i, cx.sort(ty.sort())?));
+                        }
+                    }
+                    for (i, (h, name)) in st.hyps.iter().zip(st.hyp_names.iter()).enumerate() {
+                        // Not one that could clash with the arguments' names.
+                        let clashes = |n: &str| reserved.contains(&n) || (n.starts_with('v') && n[1..].parse::<usize>().is_ok());
+                        let name = match name {
+                            Some(n) if !clashes(n) => n.clone(),
+                            _ => format!("h{}", i),
+                        };
+                        decl.push_str(&format!(" ({} : {})", name, cx.stmt(h)?));
+                    }
+                    decl.push_str(&format!(" : {}", cx.stmt(st.concl)?));
+                    if let StmtCmd::Thm { .. } = stmt {
+                        decl.push_str(" := sorry");
+                    }
+                    out.push_str(&decl);
+                    out.push('\n');
+                }
+            }
+        }
+        Ok((out, cx.names))
+    }
+}
+
+#[test]
+fn lean1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let (lean, _) = outline.to_lean(&LeanNotation::default(), &RenamePolicy::lean()).unwrap();
+    assert!(lean.contains("\naxiom wff : Type\naxiom wff.Prf : wff → Prop\n"));
+    assert!(lean.contains("\naxiom im : wff → wff → wff\n"));
+    assert!(lean.contains("\naxiom ax_mp (v0 : wff) (v1 : wff) (h0 : wff.Prf (im v0 v1)) (h1 : wff.Prf v0) : wff.Prf v1\n"));
+
+    let notation = LeanNotation::parse("-- wff as Prop\nsort wff Prop\nterm im {0} → {1}\nterm not ¬{0}\n").unwrap();
+    let (lean, _) = outline.to_lean(&notation, &RenamePolicy::lean()).unwrap();
+    assert!(!lean.contains("axiom wff"));
+    assert!(!lean.contains("axiom im"));
+    assert!(lean.contains("\naxiom ax_mp (v0 : Prop) (v1 : Prop) (h0 : v0 → v1) (h1 : v0) : v1\n"));
+    assert!(lean.contains("\naxiom ax_3 (v0 : Prop) (v1 : Prop) : (¬v0 → ¬v1) → (v1 → v0)\n"));
+    assert!(LeanNotation::parse("term im").is_err());
+
+    let policy = RenamePolicy { prefix: "mm0_".to_string(), ..RenamePolicy::lean() };
+    let (lean, names) = outline.to_lean(&LeanNotation::default(), &policy).unwrap();
+    assert!(lean.contains("\naxiom mm0_im : mm0_wff → mm0_wff → mm0_wff\n"));
+    assert_eq!(names.sorts[0], "mm0_wff");
+
+    let fx = crate::testgen::Fixture::valid();
+    fx.with_outline(|outline| {
+        let (lean, _) = outline.to_lean(&LeanNotation::default(), &RenamePolicy::lean()).unwrap();
+        assert!(lean.contains("\ntheorem a1 (v0 : s0) (v1 : s0) : s0.Prf (t0 (t0 v0 v1) (t0 v0 v1)) := sorry\n"));
+    });
+}
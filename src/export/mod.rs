@@ -0,0 +1,29 @@
+//! Translating a verified mmb file into other systems' formats. Every target has its own
+//! identifier rules, so exporters name declarations through a `rename::Renamer`
+//! instead of using the index names directly.
+
+pub mod rename;
+pub mod dedukti;
+pub mod tptp;
+pub mod opentheory;
+pub mod lean;
+pub mod sexp;
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::export::rename::NameMap;
+use crate::util::{ Res, Type, VerifErr };
+use crate::none_err;
+
+impl<'a> Outline<'a> {
+    /// The sort of an exported s-expression, read off its head: one of the assertion's
+    /// arguments (named by `var`), or a term (named by `names`).
+    pub(crate) fn export_sort_of(&self, names: &NameMap, args: &[Type], var: &dyn Fn(usize) -> String, e: &str) -> Res<u8> {
+        let head = e.trim_start_matches('(').split(' ').next().unwrap_or("");
+        if let Some(ty) = (0..args.len()).find(|i| var(*i) == head).map(|i| args[i]) {
+            return Ok(ty.sort())
+        }
+        let term_num = none_err!(names.terms.iter().position(|t| t == head))?;
+        Ok(self.get_term_by_num(term_num as u32)?.sort())
+    }
+}
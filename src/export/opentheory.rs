@@ -0,0 +1,438 @@
+//! Exporting a verified mmb file as an OpenTheory article (format version 6), so that
+//! the theorems of a HOL-like MM0 theory can be imported into HOL Light, HOL4, or Isabelle.
+//!
+//! Sorts become type operators (provable sorts become `bool`) and terms become constants
+//! of curried function types, named by an `OtMapping` so that an MM0 `im` can be
+//! interpreted as `Data.Bool.==>`. Axioms become the article's assumptions, with their
+//! hypotheses as HOL hypotheses. Theorems are proved from their proof trees with HOL's
+//! primitive rules: applying an assertion is a `subst` instance of its theorem, and each
+//! of its hypotheses is discharged with the derived cut rule
+//! (`deductAntisym` followed by `eqMp`).
+//!
+//! Bound variables are ordinary variables, and binders stay ordinary constants, as in the
+//! Dedukti export. Conversions would need HOL definitions, so a theorem whose proof
+//! converts is left out, along with every theorem that uses it; the article says so in a
+//! comment.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::export::rename::RenamePolicy;
+use crate::mmb::{ MmbItem, MmbExpr };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::tree::ProofNode;
+use crate::util::{ Res, Type, VerifErr, HashMap };
+use crate::none_err;
+
+/// What the sorts and terms are called in the article, by MM0 name.
+#[derive(Debug, Clone, Default)]
+pub struct OtMapping {
+    /// Type operators for sorts. A provable sort that isn't mapped is `bool`, and any other
+    /// sort that isn't mapped keeps its MM0 name.
+    pub types: HashMap<String, String>,
+    /// Constants for terms. A term that isn't mapped keeps its MM0 name.
+    pub consts: HashMap<String, String>,
+}
+
+impl OtMapping {
+    /// Read a mapping from lines `sort <mm0 name> <type operator>` and
+    /// `term <mm0 name> <constant>`. Blank lines and lines starting with `#` are skipped.
+    pub fn parse(s: &str) -> Res<Self> {
+        let mut mapping = OtMapping::default();
+        for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["sort", from, to] => { mapping.types.insert(from.to_string(), to.to_string()); }
+                ["term", from, to] => { mapping.consts.insert(from.to_string(), to.to_string()); }
+                _ => return Err(VerifErr::Msg(format!("bad OpenTheory mapping line: {}", line))),
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+/// An expression, with terms and variables by number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sx {
+    Var(usize),
+    App(u32, Vec<Sx>),
+}
+
+impl Sx {
+    fn from_item(item: &MmbItem, vars: &mut HashMap<usize, Type>) -> Res<Sx> {
+        match item {
+            MmbItem::Expr(MmbExpr::Var { idx, ty }) => {
+                vars.insert(*idx, *ty);
+                Ok(Sx::Var(*idx))
+            }
+            MmbItem::Expr(MmbExpr::App { term_num, args, .. }) => {
+                Ok(Sx::App(*term_num, args.iter().map(|a| Sx::from_item(a, vars)).collect::<Res<_>>()?))
+            }
+            _ => Err(VerifErr::Msg(format!("expected an expression, got {:?}", item))),
+        }
+    }
+
+    /// Read an s-expression from `assert_sexprs_named`, with terms named `t<n>` and
+    /// variables `v<i>`.
+    fn parse(sexpr: &str) -> Res<Sx> {
+        fn go<'s>(toks: &mut core::iter::Peekable<impl Iterator<Item = &'s str>>) -> Res<Sx> {
+            match none_err!(toks.next())? {
+                "(" => {
+                    let head = go(toks)?;
+                    let mut args = Vec::new();
+                    while !matches!(toks.peek(), Some(&")") | None) {
+                        args.push(go(toks)?);
+                    }
+                    toks.next();
+                    match head {
+                        Sx::App(t, _) => Ok(Sx::App(t, args)),
+                        Sx::Var(_) => Err(VerifErr::Msg("a variable applied to arguments".to_string())),
+                    }
+                }
+                tok => match (tok.get(..1), tok.get(1..).and_then(|n| n.parse().ok())) {
+                    (Some("v"), Some(i)) => Ok(Sx::Var(i)),
+                    (Some("t"), Some(t)) => Ok(Sx::App(t as u32, Vec::new())),
+                    _ => Err(VerifErr::Msg(format!("unexpected token {} in an s-expression", tok))),
+                },
+            }
+        }
+        let spaced = sexpr.replace('(', " ( ").replace(')', " ) ");
+        go(&mut spaced.split_whitespace().peekable())
+    }
+}
+
+/// The article being written: one command per line.
+struct Article {
+    out: String,
+    /// The next free key in the article's dictionary. Keys below the number of
+    /// assertions hold the theorem of that assertion.
+    next_key: usize,
+}
+
+impl Article {
+    fn cmd(&mut self, cmd: &str) {
+        self.out.push_str(cmd);
+        self.out.push('\n');
+    }
+
+    fn name(&mut self, name: &str) {
+        self.cmd(&format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+
+    fn num(&mut self, n: usize) {
+        self.cmd(&n.to_string());
+    }
+
+    /// Turn the top `n` objects of the stack into a list.
+    fn list(&mut self, n: usize) {
+        self.cmd("nil");
+        for _ in 0..n {
+            self.cmd("cons");
+        }
+    }
+
+    fn fresh(&mut self) -> usize {
+        self.next_key += 1;
+        self.next_key - 1
+    }
+}
+
+/// The names an article uses, and the types of the variables of the assertion at hand.
+struct Ctx {
+    /// Type operators by sort.
+    types: Vec<String>,
+    /// Constants by term.
+    consts: Vec<String>,
+    /// The argument and return sorts of each term.
+    term_sorts: Vec<(Vec<u8>, u8)>,
+    /// The argument sorts of each assertion.
+    assert_sorts: Vec<Vec<u8>>,
+    vars: HashMap<usize, Type>,
+}
+
+impl Ctx {
+    fn ty(&self, art: &mut Article, sort: u8) -> Res<()> {
+        art.name(none_err!(self.types.get(sort as usize))?);
+        art.cmd("typeOp");
+        art.list(0);
+        art.cmd("opType");
+        Ok(())
+    }
+
+    /// `args[0] -> (args[1] -> ... ret)`.
+    fn fn_ty(&self, art: &mut Article, args: &[u8], ret: u8) -> Res<()> {
+        match args.split_first() {
+            None => self.ty(art, ret),
+            Some((a, rest)) => {
+                art.name("->");
+                art.cmd("typeOp");
+                self.ty(art, *a)?;
+                self.fn_ty(art, rest, ret)?;
+                art.list(2);
+                art.cmd("opType");
+                Ok(())
+            }
+        }
+    }
+
+    fn var(&self, art: &mut Article, idx: usize, sort: u8) -> Res<()> {
+        art.name(&format!("v{}", idx));
+        self.ty(art, sort)?;
+        art.cmd("var");
+        Ok(())
+    }
+
+    fn term(&self, art: &mut Article, e: &Sx) -> Res<()> {
+        match e {
+            Sx::Var(idx) => {
+                self.var(art, *idx, none_err!(self.vars.get(idx))?.sort())?;
+                art.cmd("varTerm");
+            }
+            Sx::App(t, args) => {
+                let (arg_sorts, ret) = none_err!(self.term_sorts.get(*t as usize))?;
+                art.name(none_err!(self.consts.get(*t as usize))?);
+                art.cmd("const");
+                self.fn_ty(art, arg_sorts, *ret)?;
+                art.cmd("constTerm");
+                for a in args.iter() {
+                    self.term(art, a)?;
+                    art.cmd("appTerm");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Push the theorem `h1, ..., hn |- c` or `|- c`, from the theorem on top of the stack
+    /// which is `A |- c`, with the hypothesis `h` added. `h` mustn't be `c`.
+    fn add_assum(&self, art: &mut Article, h: &Sx) -> Res<()> {
+        let th = art.fresh();
+        art.num(th);
+        art.cmd("def");
+        art.cmd("pop");
+        // h = c, from {h} |- h and A |- c.
+        self.term(art, h)?;
+        art.cmd("assume");
+        art.num(th);
+        art.cmd("ref");
+        art.cmd("deductAntisym");
+        // c = h
+        art.num(th);
+        art.cmd("ref");
+        self.term(art, h)?;
+        art.cmd("assume");
+        art.cmd("deductAntisym");
+        art.num(th);
+        art.cmd("remove");
+        art.cmd("eqMp");
+        art.cmd("eqMp");
+        Ok(())
+    }
+
+    /// Push the theorem proved by `node`, and return its hypotheses, or `None` if the
+    /// proof can't be translated.
+    fn proof(&mut self, art: &mut Article, done: &[bool], node: &ProofNode) -> Res<Option<Vec<Sx>>> {
+        match node {
+            ProofNode::Hyp { stmt, .. } => {
+                let e = Sx::from_item(stmt, &mut self.vars)?;
+                self.term(art, &e)?;
+                art.cmd("assume");
+                Ok(Some(vec![e]))
+            }
+            ProofNode::Conv { .. } => Ok(None),
+            ProofNode::Thm { thm_num, args, hyps, .. } => {
+                if !done.get(*thm_num as usize).copied().unwrap_or(false) {
+                    return Ok(None)
+                }
+                // The instance of the assertion: `hyps |- concl`, with the hypotheses
+                // already in the form the subproofs prove them.
+                let arg_sorts = none_err!(self.assert_sorts.get(*thm_num as usize))?.clone();
+                art.list(0);
+                for (j, a) in args.iter().enumerate() {
+                    self.var(art, j, *none_err!(arg_sorts.get(j))?)?;
+                    let a = Sx::from_item(a, &mut self.vars)?;
+                    self.term(art, &a)?;
+                    art.list(2);
+                }
+                art.list(args.len());
+                art.list(2);
+                art.num(*thm_num as usize);
+                art.cmd("ref");
+                art.cmd("subst");
+                let mut out = Vec::new();
+                for h in hyps.iter() {
+                    let h = Sx::from_item(h.stmt(), &mut self.vars)?;
+                    if !out.contains(&h) {
+                        out.push(h);
+                    }
+                }
+                // Cut each hypothesis with its subproof: from `A |- h` and `B |- c`,
+                // `deductAntisym` gives `(A - {c}) u (B - {h}) |- h = c`, and `eqMp`
+                // with `A |- h` gives `A u (B - {h}) |- c`.
+                for sub in hyps.iter() {
+                    let th2 = art.fresh();
+                    art.num(th2);
+                    art.cmd("def");
+                    art.cmd("pop");
+                    let a = match self.proof(art, done, sub)? {
+                        None => return Ok(None),
+                        Some(a) => a,
+                    };
+                    let th1 = art.fresh();
+                    art.num(th1);
+                    art.cmd("def");
+                    art.num(th2);
+                    art.cmd("remove");
+                    art.cmd("deductAntisym");
+                    art.num(th1);
+                    art.cmd("remove");
+                    art.cmd("eqMp");
+                    let h = Sx::from_item(sub.stmt(), &mut self.vars)?;
+                    out.retain(|e| *e != h);
+                    for e in a {
+                        if !out.contains(&e) {
+                            out.push(e);
+                        }
+                    }
+                }
+                Ok(Some(out))
+            }
+        }
+    }
+}
+
+const TERM: fn(u32) -> String = |t| format!("t{}", t);
+const VAR: fn(usize) -> String = |i| format!("v{}", i);
+
+impl<'a> Outline<'a> {
+    /// The file as an OpenTheory article, with names under `policy` (usually
+    /// `RenamePolicy::default()`), and the names of the theorems that had to be left out.
+    pub fn to_opentheory(&'a self, mapping: &OtMapping, policy: &RenamePolicy) -> Res<(String, Vec<String>)> {
+        let names = self.export_names(policy.clone())?;
+        let mut types = Vec::new();
+        for (num, name) in names.sorts.iter().enumerate() {
+            types.push(match mapping.types.get(name) {
+                Some(ty) => ty.clone(),
+                None if self.get_sort_mods(num)?.is_provable() => "bool".to_string(),
+                None => name.clone(),
+            });
+        }
+        let consts = names.terms.iter().map(|t| mapping.consts.get(t).cloned().unwrap_or_else(|| t.clone())).collect();
+        let mut term_sorts = Vec::new();
+        for num in 0..names.terms.len() {
+            let t = self.get_term_by_num(num as u32)?;
+            term_sorts.push((t.args_no_ret().map(|ty| ty.sort()).collect(), t.ret().sort()));
+        }
+        let mut assert_sorts = Vec::new();
+        for num in 0..names.thms.len() {
+            assert_sorts.push(self.get_assert_by_num(num as u32)?.args().map(|ty| ty.sort()).collect());
+        }
+        let mut cx = Ctx { types, consts, term_sorts, assert_sorts, vars: HashMap::new() };
+
+        let mut art = Article { out: String::new(), next_key: names.thms.len() };
+        art.num(6);
+        art.cmd("version");
+        let mut done = vec![false; names.thms.len()];
+        let mut skipped = Vec::new();
+        for (stmt, proof) in self.declarations.iter() {
+            let num = match *stmt {
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => none_err!(num)?,
+                _ => continue,
+            };
+            let name = none_err!(names.thms.get(num as usize))?;
+            let (hyps, concl) = self.assert_sexprs_named(num, &TERM, &VAR)?;
+            let hyps = hyps.iter().map(|h| Sx::parse(h)).collect::<Res<Vec<_>>>()?;
+            let concl = Sx::parse(&concl)?;
+            cx.vars.clear();
+            for (i, ty) in self.get_assert_by_num(num)?.args().enumerate() {
+                cx.vars.insert(i, ty);
+            }
+            let mut body = Article { out: String::new(), next_key: art.next_key };
+            match stmt {
+                StmtCmd::Axiom { .. } => {
+                    for h in hyps.iter() {
+                        cx.term(&mut body, h)?;
+                    }
+                    body.list(hyps.len());
+                    cx.term(&mut body, &concl)?;
+                    body.cmd("axiom");
+                }
+                _ => {
+                    let mut bump = Bump::new();
+                    let tree = self.proof_tree(&mut bump, *stmt, *proof)?;
+                    let proved = match cx.proof(&mut body, &done, tree)? {
+                        Some(proved) => proved,
+                        None => {
+                            art.cmd(&format!("# {} is left out: its proof converts, or uses an assertion that was left out", name));
+                            skipped.push(name.clone());
+                            continue
+                        }
+                    };
+                    let missing = hyps.iter().filter(|h| !proved.contains(h)).collect::<Vec<_>>();
+                    if missing.contains(&&concl) {
+                        // The conclusion is a hypothesis, which proves it outright.
+                        body.out.clear();
+                        cx.term(&mut body, &concl)?;
+                        body.cmd("assume");
+                    }
+                    for h in missing.into_iter().filter(|h| **h != concl) {
+                        cx.add_assum(&mut body, h)?;
+                    }
+                    body.num(num as usize);
+                    body.cmd("def");
+                    for h in hyps.iter() {
+                        cx.term(&mut body, h)?;
+                    }
+                    body.list(hyps.len());
+                    cx.term(&mut body, &concl)?;
+                    body.cmd("thm");
+                    art.out.push_str(&format!("# {}\n", name));
+                    art.out.push_str(&body.out);
+                    art.next_key = body.next_key;
+                    done[num as usize] = true;
+                    continue
+                }
+            }
+            art.out.push_str(&format!("# {}\n", name));
+            art.out.push_str(&body.out);
+            art.num(num as usize);
+            art.cmd("def");
+            art.cmd("pop");
+            art.next_key = body.next_key;
+            done[num as usize] = true;
+        }
+        Ok((art.out, skipped))
+    }
+}
+
+#[test]
+fn opentheory1() {
+    use crate::testgen::{ Fixture, AssertSpec, stream, reg };
+    use crate::mmb::stmt::{ STMT_AXIOM, STMT_THM };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_HYP, PROOF_TERM, PROOF_THM };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF, UNIFY_HYP };
+    let mut fx = Fixture::valid();
+    // `axiom mp (a b: wff) (h: $ a $): $ b $;`
+    fx.asserts.push(AssertSpec { args: vec![reg(0, 0); 2], unify: stream(&[(UNIFY_REF, 1), (UNIFY_HYP, 0), (UNIFY_REF, 0)]) });
+    fx.stmts.push((STMT_AXIOM, stream(&[(PROOF_REF, 0), (PROOF_HYP, 0), (PROOF_REF, 1)])));
+    // `theorem c (a b: wff) (h: $ a $): $ b $ = (mp a b h);`
+    fx.asserts.push(AssertSpec { args: vec![reg(0, 0); 2], unify: stream(&[(UNIFY_REF, 1), (UNIFY_HYP, 0), (UNIFY_REF, 0)]) });
+    fx.stmts.push((STMT_THM, stream(&[(PROOF_REF, 0), (PROOF_HYP, 0), (PROOF_REF, 2), (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_REF, 1), (PROOF_THM, 2)])));
+    // `theorem w (a b: wff) (h: $ b $): $ im a a $ = (ax a);`, which doesn't use `h`.
+    fx.asserts.push(AssertSpec { args: vec![reg(0, 0); 2], unify: stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 0), (UNIFY_HYP, 0), (UNIFY_REF, 1)]) });
+    fx.stmts.push((STMT_THM, stream(&[(PROOF_REF, 1), (PROOF_HYP, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0), (PROOF_THM, 0)])));
+    fx.with_outline(|outline| {
+        assert!(crate::verify_serial(outline, &outline.declarations).is_empty());
+
+        let mut mapping = OtMapping::parse("# wff is bool anyway\nterm t0 Data.Bool.==>\n").unwrap();
+        let (art, skipped) = outline.to_opentheory(&mapping, &RenamePolicy::default()).unwrap();
+        assert!(skipped.is_empty());
+        assert!(art.starts_with("6\nversion\n# a0\n"));
+        assert!(art.contains("\n\"Data.Bool.==>\"\nconst\n\"->\"\ntypeOp\n\"bool\"\ntypeOp\nnil\nopType\n"));
+        assert_eq!(art.matches("\naxiom\n").count(), 2);
+        assert_eq!(art.matches("\nthm\n").count(), 3);
+
+        mapping.types.insert("s0".to_string(), "prop".to_string());
+        assert!(outline.to_opentheory(&mapping, &RenamePolicy::default()).unwrap().0.contains("\n\"prop\"\ntypeOp\n"));
+        assert!(OtMapping::parse("term t0").is_err());
+    });
+}
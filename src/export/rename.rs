@@ -0,0 +1,317 @@
+//! Mapping MM0 index names to identifiers that are legal (and unambiguous) in a target system.
+//! A `RenamePolicy` says what the target accepts; a `Renamer` applies it to every declaration
+//! in an mmb file, resolving collisions by appending `_1`, `_2`, ... and remembering each
+//! name it had to change so the mapping can be reported alongside the export.
+
+use crate::prelude::*;
+use alloc::collections::BTreeSet;
+use crate::Outline;
+use crate::json::Json;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Keep,
+    Lower,
+    Upper,
+    /// `foo_bar`
+    Snake,
+    /// `fooBar`
+    Camel,
+    /// `FooBar`
+    Pascal,
+}
+
+impl Case {
+    /// By name: `keep`, `lower`, `upper`, `snake`, `camel`, or `pascal`.
+    pub fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "keep" => Some(Case::Keep),
+            "lower" => Some(Case::Lower),
+            "upper" => Some(Case::Upper),
+            "snake" => Some(Case::Snake),
+            "camel" => Some(Case::Camel),
+            "pascal" => Some(Case::Pascal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Sort,
+    Term,
+    Thm,
+}
+
+impl ItemKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            ItemKind::Sort => "sort",
+            ItemKind::Term => "term",
+            ItemKind::Thm => "thm",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenamePolicy {
+    /// Put in front of every name, before any other rule is applied.
+    pub prefix: String,
+    pub case: Case,
+    /// Characters the target accepts in an identifier. Anything else becomes `_`.
+    pub is_ident_char: fn(char) -> bool,
+    /// Characters the target accepts at the start of an identifier. If the first
+    /// character isn't one of these, the name is prefixed with `_`.
+    pub is_ident_start: fn(char) -> bool,
+    /// Keywords and other names that can't be used, compared after case conversion.
+    pub reserved: Vec<&'static str>,
+}
+
+fn alnum_underscore(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn alpha_underscore(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+impl Default for RenamePolicy {
+    /// Keep names as they are, except for making them plain ASCII identifiers.
+    fn default() -> Self {
+        RenamePolicy {
+            prefix: String::new(),
+            case: Case::Keep,
+            is_ident_char: alnum_underscore,
+            is_ident_start: alpha_underscore,
+            reserved: Vec::new(),
+        }
+    }
+}
+
+impl RenamePolicy {
+    /// Metamath labels: letters, digits, `-`, `_`, and `.`.
+    pub fn metamath() -> Self {
+        RenamePolicy {
+            is_ident_char: |c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.',
+            is_ident_start: |c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.',
+            ..RenamePolicy::default()
+        }
+    }
+
+    /// Lean 4 identifiers, avoiding the keywords that are likely to clash with MM0 names.
+    pub fn lean() -> Self {
+        RenamePolicy {
+            is_ident_char: |c| c.is_ascii_alphanumeric() || c == '_' || c == '\'',
+            reserved: vec![
+                "axiom", "by", "def", "do", "else", "end", "example", "fun", "have", "if", "import",
+                "in", "inductive", "instance", "let", "match", "namespace", "open", "section",
+                "show", "structure", "then", "theorem", "universe", "variable", "where", "with",
+                "Prop", "Sort", "Type",
+            ],
+            ..RenamePolicy::default()
+        }
+    }
+
+    /// Dedukti identifiers, avoiding its keywords and the names of the prelude that
+    /// `to_dedukti` writes. `'` is left out so that exported locals can't clash.
+    pub fn dedukti() -> Self {
+        RenamePolicy {
+            reserved: vec![
+                "def", "thm", "inj", "defac", "defacu", "private", "injective", "require", "Type",
+                "Sort", "El", "Prf",
+            ],
+            ..RenamePolicy::default()
+        }
+    }
+
+    /// MM0 and MM1 identifiers: `[a-zA-Z_][a-zA-Z0-9_]*`, minus the keywords.
+    pub fn mm0() -> Self {
+        RenamePolicy {
+            reserved: vec![
+                "axiom", "coercion", "def", "delimiter", "free", "import", "infixl", "infixr",
+                "input", "local", "max", "notation", "output", "prefix", "provable", "pub", "pure",
+                "sort", "strict", "term", "theorem",
+            ],
+            ..RenamePolicy::default()
+        }
+    }
+
+    /// Apply the prefix, case conversion, and character rules to one name, without
+    /// checking for collisions or reserved words.
+    pub fn sanitize(&self, name: &str) -> String {
+        let prefixed = format!("{}{}", self.prefix, name);
+        let cased = convert_case(&prefixed, self.case);
+        let mut out: String = cased.chars().map(|c| if (self.is_ident_char)(c) { c } else { '_' }).collect();
+        match out.chars().next() {
+            Some(c) if (self.is_ident_start)(c) => {},
+            _ => out.insert(0, '_'),
+        }
+        out
+    }
+}
+
+/// Split on `_`, `-`, `.`, and lower-to-upper case boundaries.
+fn words(s: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut cur = String::new();
+    let mut prev_lower = false;
+    for c in s.chars() {
+        if c == '_' || c == '-' || c == '.' {
+            if !cur.is_empty() {
+                out.push(core::mem::take(&mut cur));
+            }
+            prev_lower = false;
+            continue
+        }
+        if c.is_uppercase() && prev_lower && !cur.is_empty() {
+            out.push(core::mem::take(&mut cur));
+        }
+        prev_lower = c.is_lowercase() || c.is_ascii_digit();
+        cur.push(c);
+    }
+    if !cur.is_empty() {
+        out.push(cur);
+    }
+    out
+}
+
+fn capitalize(w: &str) -> String {
+    let mut cs = w.chars();
+    match cs.next() {
+        None => String::new(),
+        Some(c) => c.to_uppercase().chain(cs.flat_map(|c| c.to_lowercase())).collect(),
+    }
+}
+
+pub fn convert_case(s: &str, case: Case) -> String {
+    match case {
+        Case::Keep => s.to_string(),
+        Case::Lower => s.to_lowercase(),
+        Case::Upper => s.to_uppercase(),
+        Case::Snake => words(s).iter().map(|w| w.to_lowercase()).collect::<Vec<_>>().join("_"),
+        Case::Pascal => words(s).iter().map(|w| capitalize(w)).collect(),
+        Case::Camel => {
+            let mut out = String::new();
+            for (idx, w) in words(s).iter().enumerate() {
+                if idx == 0 {
+                    out.push_str(&w.to_lowercase())
+                } else {
+                    out.push_str(&capitalize(w))
+                }
+            }
+            out
+        }
+    }
+}
+
+/// A name that came out different from the original.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Renaming {
+    pub kind: ItemKind,
+    pub num: u32,
+    pub from: String,
+    pub to: String,
+}
+
+/// Assigns target names to items, one at a time. Sorts, terms, and theorems share one
+/// namespace, since most targets don't distinguish them.
+#[derive(Debug, Clone)]
+pub struct Renamer {
+    pub policy: RenamePolicy,
+    used: BTreeSet<String>,
+    pub renamings: Vec<Renaming>,
+}
+
+impl Renamer {
+    pub fn new(policy: RenamePolicy) -> Self {
+        Renamer { policy, used: BTreeSet::new(), renamings: Vec::new() }
+    }
+
+    /// Pick a fresh target name for an item, recording it if it differs from `name`.
+    pub fn rename(&mut self, kind: ItemKind, num: u32, name: &str) -> String {
+        let base = self.policy.sanitize(name);
+        let mut candidate = base.clone();
+        let mut suffix = 0;
+        while self.used.contains(&candidate) || self.policy.reserved.contains(&candidate.as_str()) {
+            suffix += 1;
+            candidate = format!("{}_{}", base, suffix);
+        }
+        self.used.insert(candidate.clone());
+        if candidate != name {
+            self.renamings.push(Renaming { kind, num, from: name.to_string(), to: candidate.clone() });
+        }
+        candidate
+    }
+
+    /// The renamings as `[{ kind, num, from, to }, ..]`, for writing out next to an export.
+    pub fn report(&self) -> Json {
+        Json::Arr(self.renamings.iter().map(|r| {
+            Json::obj()
+                .with("kind", r.kind.as_str())
+                .with("num", r.num)
+                .with("from", r.from.as_str())
+                .with("to", r.to.as_str())
+        }).collect())
+    }
+}
+
+/// The target names of every item in an mmb file, by number.
+#[derive(Debug, Clone)]
+pub struct NameMap {
+    pub sorts: Vec<String>,
+    pub terms: Vec<String>,
+    pub thms: Vec<String>,
+    pub renamer: Renamer,
+}
+
+impl<'a> Outline<'a> {
+    /// Name every declaration according to `policy`, in declaration order (so earlier
+    /// declarations keep their names when there's a collision). Items the index doesn't
+    /// name are called `s<num>`, `t<num>`, or `a<num>`.
+    pub fn export_names(&self, policy: RenamePolicy) -> Res<NameMap> {
+        let mut renamer = Renamer::new(policy);
+        let mut sorts = Vec::with_capacity(self.header.num_sorts as usize);
+        let mut terms = Vec::with_capacity(self.header.num_terms as usize);
+        let mut thms = Vec::with_capacity(self.header.num_thms as usize);
+        for (stmt, _) in self.declarations.iter() {
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    let name = self.sort_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("s{}", num));
+                    sorts.push(renamer.rename(ItemKind::Sort, num as u32, &name));
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    let name = self.term_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", num));
+                    terms.push(renamer.rename(ItemKind::Term, num, &name));
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let name = self.assert_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("a{}", num));
+                    thms.push(renamer.rename(ItemKind::Thm, num, &name));
+                }
+            }
+        }
+        Ok(NameMap { sorts, terms, thms, renamer })
+    }
+}
+
+#[test]
+fn rename1() {
+    assert_eq!(convert_case("foo_barBaz", Case::Camel), "fooBarBaz");
+    assert_eq!(convert_case("foo_barBaz", Case::Pascal), "FooBarBaz");
+    assert_eq!(convert_case("fooBar.baz", Case::Snake), "foo_bar_baz");
+
+    let mut r = Renamer::new(RenamePolicy::lean());
+    assert_eq!(r.rename(ItemKind::Term, 0, "im"), "im");
+    assert_eq!(r.rename(ItemKind::Term, 1, "fun"), "fun_1");
+    assert_eq!(r.rename(ItemKind::Thm, 0, "im"), "im_1");
+    assert_eq!(r.rename(ItemKind::Thm, 1, "1.2"), "_1_2");
+    assert_eq!(r.renamings.len(), 3);
+
+    let mut r = Renamer::new(RenamePolicy { prefix: "mm0_".to_string(), case: Case::Upper, ..RenamePolicy::mm0() });
+    assert_eq!(r.rename(ItemKind::Sort, 0, "wff"), "MM0_WFF");
+}
@@ -0,0 +1,182 @@
+//! A canonical s-expression listing of everything an mmb file declares, one declaration
+//! per line in file order, for diffing and for scripts in languages that can read
+//! s-expressions but not mmb files:
+//!```text
+//! (sort wff (strict provable))
+//! (term im ((v0 wff ()) (v1 wff ())) (wff ()))
+//! (def all pub ((v0 set bound) (v1 wff (v0))) (wff ()) ((d0 set)) (ex d0 (not v1)))
+//! (axiom ax_mp ((v0 wff ()) (v1 wff ())) ((im v0 v1) v0) v1)
+//! (theorem id local ((v0 wff ())) () (im v0 v0))
+//!```
+//! A binder is `(name sort deps)`, where `deps` is the list of bound variables the
+//! variable depends on, or `bound` for a bound variable. Arguments are `v<i>` and the
+//! dummies of a def body are `d<i>`. Applications are always parenthesized, even without
+//! arguments, so a term can't be mistaken for a variable. Nothing is verified; the
+//! listing is only as sensible as the file.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::{ MmbItem, MmbExpr };
+use crate::mmb::sort_mods::SortMod;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, Args, Type, VerifErr };
+use crate::none_err;
+
+impl<'a> Outline<'a> {
+    fn sexp_sort(&self, sort: u8) -> String {
+        self.sort_name(sort).map(|s| s.to_string()).unwrap_or_else(|| format!("s{}", sort))
+    }
+
+    /// `(sort deps)`, with the deps named after the bound variables in `args`.
+    fn sexp_type(&self, args: Args, ty: Type) -> Res<String> {
+        if ty.is_bound() {
+            return Ok(format!("{} bound", self.sexp_sort(ty.sort())))
+        }
+        let deps = ty.deps()?;
+        let mut names = Vec::new();
+        for (i, arg) in args.enumerate() {
+            if arg.is_bound() && deps & arg.bound_digit()? != 0 {
+                names.push(format!("v{}", i));
+            }
+        }
+        Ok(format!("{} ({})", self.sexp_sort(ty.sort()), names.join(" ")))
+    }
+
+    fn sexp_binders(&self, args: Args) -> Res<String> {
+        let mut out = Vec::new();
+        for (i, ty) in args.enumerate() {
+            out.push(format!("(v{} {})", i, self.sexp_type(args, ty)?));
+        }
+        Ok(format!("({})", out.join(" ")))
+    }
+
+    /// An expression, with variables from `num_args` on named as dummies.
+    fn sexp_expr(&self, e: &MmbExpr, num_args: usize) -> Res<String> {
+        match e {
+            MmbExpr::Var { idx, .. } if *idx < num_args => Ok(format!("v{}", idx)),
+            MmbExpr::Var { idx, .. } => Ok(format!("d{}", idx - num_args)),
+            MmbExpr::App { term_num, args, .. } => {
+                let mut out = format!("({}", self.term_name(*term_num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", term_num)));
+                for a in args.iter() {
+                    match a {
+                        MmbItem::Expr(a) => {
+                            out.push(' ');
+                            out.push_str(&self.sexp_expr(a, num_args)?);
+                        }
+                        _ => return Err(VerifErr::Msg(format!("expected an expression, got {:?}", a))),
+                    }
+                }
+                out.push(')');
+                Ok(out)
+            }
+        }
+    }
+
+    /// Every declaration as an s-expression, one per line.
+    pub fn to_sexp(&self) -> Res<String> {
+        let mut out = String::new();
+        let bump = Bump::new();
+        for (stmt, _) in self.declarations.iter() {
+            let line = match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    let mods = self.get_sort_mods(num as usize)?.inner;
+                    let mods = SortMod::ALL.iter().filter(|m| mods & m.bit() != 0).map(|m| m.name()).collect::<Vec<_>>();
+                    format!("(sort {} ({}))", self.sexp_sort(num), mods.join(" "))
+                }
+                StmtCmd::TermDef { num, local } => {
+                    let num = none_err!(num)?;
+                    let term = self.get_term_by_num(num)?;
+                    let name = self.term_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", num));
+                    let args = term.args_no_ret();
+                    let head = format!("{} ({})", self.sexp_binders(args)?, self.sexp_type(args, term.ret())?);
+                    if !term.is_def() {
+                        format!("(term {} {})", name, head)
+                    } else {
+                        let num_args = args.len();
+                        let mut heap = Vec::new();
+                        for (idx, ty) in args.enumerate() {
+                            heap.push(Some(&*bump.alloc(MmbExpr::Var { idx, ty })));
+                        }
+                        let mut next_dummy = Some(num_args);
+                        let mut it = term.unify;
+                        let body = self.read_expr(&bump, &mut it, &mut heap, &mut next_dummy)?;
+                        let mut dummies = Vec::new();
+                        for slot in heap.iter().skip(num_args) {
+                            if let Some(MmbExpr::Var { idx, ty }) = slot {
+                                dummies.push(format!("(d{} {})", idx - num_args, self.sexp_sort(ty.sort())));
+                            }
+                        }
+                        format!(
+                            "(def {} {} {} ({}) {})",
+                            name,
+                            if local { "local" } else { "pub" },
+                            head,
+                            dummies.join(" "),
+                            self.sexp_expr(body, num_args)?
+                        )
+                    }
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let st = self.statement(&bump, num)?;
+                    let args = self.get_assert_by_num(num)?.args();
+                    let name = self.assert_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("a{}", num));
+                    let hyps = st.hyps.iter().map(|h| self.sexp_expr(h, args.len())).collect::<Res<Vec<_>>>()?;
+                    let head = match stmt {
+                        StmtCmd::Thm { local: true, .. } => format!("theorem {} local", name),
+                        StmtCmd::Thm { .. } => format!("theorem {} pub", name),
+                        _ => format!("axiom {}", name),
+                    };
+                    format!(
+                        "({} {} ({}) {})",
+                        head,
+                        self.sexp_binders(args)?,
+                        hyps.join(" "),
+                        self.sexp_expr(st.concl, args.len())?
+                    )
+                }
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+#[test]
+fn sexp1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let sexp = outline.to_sexp().unwrap();
+    let lines = sexp.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "(sort wff (strict provable))");
+    assert_eq!(lines[1], "(term im ((v0 wff ()) (v1 wff ())) (wff ()))");
+    assert_eq!(lines[6], "(axiom ax_mp ((v0 wff ()) (v1 wff ())) ((im v0 v1) v0) v1)");
+    assert_eq!(lines.len(), 7);
+
+    use crate::testgen::{ Fixture, TermSpec, stream, reg, bound };
+    use crate::mmb::stmt::STMT_DEF;
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF, UNIFY_DUMMY };
+    // `sort set; term all (x: set) (p: wff x): wff; def k (x: set) (p: wff x): wff = $ all y p $;`
+    let mut fx = Fixture::valid();
+    fx.sorts.push(0);
+    fx.terms.push(TermSpec { args: vec![bound(1, 1), reg(0, 1)], ret: reg(0, 0), def: None });
+    fx.terms.push(TermSpec {
+        args: vec![bound(1, 1), reg(0, 1)],
+        ret: reg(0, 0),
+        def: Some(stream(&[(UNIFY_TERM, 1), (UNIFY_DUMMY, 1), (UNIFY_REF, 1)])),
+    });
+    fx.stmts.insert(1, (crate::mmb::stmt::STMT_SORT, Vec::new()));
+    fx.stmts.insert(3, (STMT_DEF, Vec::new()));
+    fx.stmts.insert(4, (STMT_DEF, Vec::new()));
+    fx.with_outline(|outline| {
+        let sexp = outline.to_sexp().unwrap();
+        let lines = sexp.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "(sort s1 ())");
+        assert_eq!(lines[3], "(term t1 ((v0 s1 bound) (v1 s0 (v0))) (s0 ()))");
+        assert_eq!(lines[4], "(def t2 pub ((v0 s1 bound) (v1 s0 (v0))) (s0 ()) ((d0 s1)) (t1 d0 v1))");
+        assert_eq!(lines[6], "(theorem a1 pub ((v0 s0 ()) (v1 s0 ())) () (t0 (t0 v0 v1) (t0 v0 v1)))");
+    });
+}
@@ -0,0 +1,154 @@
+//! Exporting assertion statements as TPTP problems, so that automated provers can be
+//! pointed at the theorems of an MM0 development.
+//!
+//! The encoding is untyped first-order logic (FOF). Terms become function symbols and
+//! arguments become universally quantified variables `V<i>`. Each provable sort `s` gets
+//! a predicate `prf_s`, so an assertion becomes `![V0, ..]: ((prf_s(h1) & ..) => prf_s(c))`.
+//! Sorts otherwise aren't represented, and neither are bound variable side conditions,
+//! so a prover works in a slightly weaker theory than MM0's: a proof it finds still has to
+//! be translated back and checked.
+//!
+//! There's one problem per theorem: the theorem as the conjecture, with the axioms (and,
+//! if asked, the theorems) declared before it as axioms.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::export::rename::{ NameMap, RenamePolicy };
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TptpOptions {
+    /// Give each problem the axioms declared before its theorem.
+    pub axioms: bool,
+    /// Give each problem the theorems declared before it, as axioms.
+    pub lemmas: bool,
+}
+
+impl Default for TptpOptions {
+    fn default() -> Self {
+        TptpOptions { axioms: true, lemmas: false }
+    }
+}
+
+fn var(i: usize) -> String {
+    format!("V{}", i)
+}
+
+/// A functor or predicate name, quoted unless it's a TPTP lower word.
+fn atom(name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_ascii_lowercase() => name.to_string(),
+        _ => format!("'{}'", name),
+    }
+}
+
+/// `(f a (g b))` as `f(a,g(b))`.
+fn term(sexpr: &str) -> String {
+    fn go<'s>(toks: &mut core::iter::Peekable<impl Iterator<Item = &'s str>>) -> String {
+        match toks.next() {
+            Some("(") => {
+                let head = go(toks);
+                let mut args = Vec::new();
+                while !matches!(toks.peek(), Some(&")") | None) {
+                    args.push(go(toks));
+                }
+                toks.next();
+                if args.is_empty() { head } else { format!("{}({})", head, args.join(",")) }
+            }
+            Some(v) if v.starts_with('V') && v[1..].parse::<usize>().is_ok() => v.to_string(),
+            Some(name) => atom(name),
+            None => String::new(),
+        }
+    }
+    let spaced = sexpr.replace('(', " ( ").replace(')', " ) ");
+    go(&mut spaced.split_whitespace().peekable())
+}
+
+impl<'a> Outline<'a> {
+    /// The names of the `prf_s` predicates, by sort, avoiding the names of the terms.
+    fn tptp_predicates(&self, names: &NameMap) -> Vec<String> {
+        names.sorts.iter().map(|s| {
+            let mut p = format!("prf_{}", s);
+            while names.terms.contains(&p) {
+                p.push('_');
+            }
+            atom(&p)
+        }).collect()
+    }
+
+    /// One assertion as a TPTP formula with the given role (`axiom` or `conjecture`).
+    pub fn tptp_formula(&self, names: &NameMap, assert_num: u32, role: &str) -> Res<String> {
+        let preds = self.tptp_predicates(names);
+        let args = self.get_assert_by_num(assert_num)?.args().collect::<Vec<_>>();
+        let term_name = |t: u32| names.terms.get(t as usize).cloned().unwrap_or_else(|| format!("t{}", t));
+        let (hyps, concl) = self.assert_sexprs_named(assert_num, &term_name, &var)?;
+        let prf = |e: &str| -> Res<String> {
+            Ok(format!("{}({})", none_err!(preds.get(self.export_sort_of(names, &args, &var, e)? as usize))?, term(e)))
+        };
+        let mut body = prf(&concl)?;
+        if !hyps.is_empty() {
+            let hyps = hyps.iter().map(|h| prf(h)).collect::<Res<Vec<_>>>()?;
+            body = format!("(({}) => {})", hyps.join(" & "), body);
+        }
+        if !args.is_empty() {
+            body = format!("![{}]: {}", (0..args.len()).map(var).collect::<Vec<_>>().join(","), body);
+        }
+        Ok(format!("fof({}, {}, {}).", atom(none_err!(names.thms.get(assert_num as usize))?), role, body))
+    }
+
+    /// A problem for every theorem, with the theorem's name under `policy` (usually
+    /// `RenamePolicy::default()`).
+    pub fn tptp_problems(&self, options: TptpOptions, policy: &RenamePolicy) -> Res<Vec<(String, String)>> {
+        let names = self.export_names(policy.clone())?;
+        let mut context = Vec::new();
+        let mut problems = Vec::new();
+        for (stmt, _) in self.declarations.iter() {
+            match *stmt {
+                StmtCmd::Axiom { num } if options.axioms => {
+                    context.push(self.tptp_formula(&names, none_err!(num)?, "axiom")?);
+                }
+                StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let name = none_err!(names.thms.get(num as usize))?.clone();
+                    let mut problem = format!("% {}\n", name);
+                    for f in context.iter() {
+                        problem.push_str(f);
+                        problem.push('\n');
+                    }
+                    problem.push_str(&self.tptp_formula(&names, num, "conjecture")?);
+                    problem.push('\n');
+                    problems.push((name, problem));
+                    if options.lemmas {
+                        context.push(self.tptp_formula(&names, num, "axiom")?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(problems)
+    }
+}
+
+#[test]
+fn tptp1() {
+    assert_eq!(term("(im V0 (not V1))"), "im(V0,not(V1))");
+    assert_eq!(term("V0"), "V0");
+    assert_eq!(term("(Im (t) V0)"), "'Im'(t,V0)");
+
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let names = outline.export_names(RenamePolicy::default()).unwrap();
+    assert_eq!(
+        outline.tptp_formula(&names, 3, "axiom").unwrap(),
+        "fof(ax_mp, axiom, ![V0,V1]: ((prf_wff(im(V0,V1)) & prf_wff(V0)) => prf_wff(V1)))."
+    );
+
+    let fx = crate::testgen::Fixture::valid();
+    fx.with_outline(|outline| {
+        let problems = outline.tptp_problems(TptpOptions::default(), &RenamePolicy::default()).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].1, "% a1\nfof(a0, axiom, ![V0]: prf_s0(t0(V0,V0))).\nfof(a1, conjecture, ![V0,V1]: prf_s0(t0(t0(V0,V1),t0(V0,V1)))).\n");
+    });
+}
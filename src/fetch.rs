@@ -0,0 +1,160 @@
+//! Reading the input files from a URL, for CI jobs that check published artifacts. Behind
+//! the `fetch` feature, which adds no dependencies: URLs are fetched by running `curl`,
+//! which is wherever CI is and has the HTTP and TLS stacks this crate doesn't want to carry.
+//! The body is hashed and decompressed (if it's a compressed mmb file, see `compress`) as
+//! it arrives, so the download is never in memory twice; the verifier needs the whole file
+//! at once, so it's held in memory once.
+//!
+//! `curl` follows up to `MAX_REDIRECTS` redirects, between `http://` and `https://` either
+//! way but to nothing else. Anything other than a success after redirects is an error.
+
+use std::io::{ self, Read };
+use std::process::{ Command, Stdio, Child };
+use sha2::{ Sha256, Digest };
+use crate::env::hex;
+use crate::fs::FileData;
+use crate::util::{ Res, VerifErr };
+use crate::io_err;
+
+pub const MAX_REDIRECTS: usize = 5;
+
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+fn bad(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// `curl`'s output, which is only done once it's exited successfully.
+struct Curl(Child);
+
+impl Read for Curl {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = match self.0.stdout.as_mut() {
+            Some(out) => out.read(buf)?,
+            None => 0,
+        };
+        if n == 0 && !buf.is_empty() {
+            let status = self.0.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("curl failed ({})", status)))
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// The body at `url`, to be read as it arrives.
+pub fn open(url: &str) -> io::Result<Box<dyn Read>> {
+    if !is_url(url) {
+        return Err(bad(format!("not an http or https URL: {}", url)))
+    }
+    let child = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--proto", "=http,https", "--proto-redir", "=http,https", "--max-redirs"])
+        .arg(MAX_REDIRECTS.to_string())
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    Ok(Box::new(Curl(child)))
+}
+
+/// Hashes everything read through it.
+struct Hashing<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// The file at `url`, decompressed if it's compressed, checking that what was downloaded
+/// (before decompression) has the SHA-256 `sha256` (in hex) if it's given.
+pub fn fetch(url: &str, sha256: Option<&str>) -> Res<Vec<u8>> {
+    let mut reader = Hashing { inner: io_err!(open(url))?, hasher: Sha256::new() };
+    let bytes = io_err!(crate::compress::read_maybe_compressed(&mut reader, 0))?;
+    // Whatever the decompressor didn't need still counts.
+    io_err!(io::copy(&mut reader, &mut io::sink()))?;
+    let actual = hex(&reader.hasher.finalize());
+    match sha256 {
+        Some(expected) if !expected.eq_ignore_ascii_case(&actual) =>
+            Err(VerifErr::Msg(format!("{} has SHA-256 {}, but {} was expected", url, actual, expected))),
+        _ => Ok(bytes),
+    }
+}
+
+/// The input files, either of which can be a URL. An mm0 file from a URL is used on its
+/// own, so it can't import others; with no mm0 file, a local mmb file gets its default one
+/// and one from a URL is checked alone.
+pub fn load(mmb: &str, mm0: Option<&str>, sha256: Option<&str>) -> Res<FileData> {
+    let mm0_url = mm0.filter(|p| is_url(p));
+    if !is_url(mmb) && mm0_url.is_none() {
+        if sha256.is_some() {
+            return Err(VerifErr::Msg("a checksum can only be checked for a file from a URL".to_string()))
+        }
+        return FileData::new_from(mmb, mm0)
+    }
+    let mmb_file = if is_url(mmb) {
+        fetch(mmb, sha256)?
+    } else {
+        io_err!(crate::compress::read_maybe_compressed(io_err!(std::fs::File::open(mmb))?, 0))?
+    };
+    let mmz_files = match mm0 {
+        None => Vec::new(),
+        Some(p) if is_url(p) => vec![io_err!(String::from_utf8(fetch(p, None)?).map_err(|e| bad(e.to_string())))?],
+        Some(p) => vec![io_err!(std::fs::read_to_string(p))?],
+    };
+    Ok(FileData::new_from_bytes(mmb_file, mmz_files))
+}
+
+#[test]
+fn fetch1() {
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::TcpListener;
+    if Command::new("curl").arg("--version").output().is_err() {
+        return
+    }
+    let mmb = std::fs::read("./test_resources/short.mmb").unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let (body, base2) = (mmb.clone(), base.clone());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let (mut line, mut header) = (String::new(), String::new());
+            reader.read_line(&mut line).unwrap();
+            // Read the rest of the request, so that closing the connection doesn't reset it.
+            while reader.read_line(&mut header).unwrap() > 2 { header.clear() }
+            let _ = match line.split_whitespace().nth(1).unwrap() {
+                "/plain" => write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).and_then(|_| stream.write_all(&body)),
+                "/chunked" => {
+                    let (a, b) = body.split_at(body.len() / 2);
+                    write!(stream, "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n", a.len())
+                        .and_then(|_| stream.write_all(a))
+                        .and_then(|_| write!(stream, "\r\n{:x}; ext\r\n", b.len()))
+                        .and_then(|_| stream.write_all(b))
+                        .and_then(|_| write!(stream, "\r\n0\r\n\r\n"))
+                }
+                "/moved" => write!(stream, "HTTP/1.1 302 Found\r\nLocation: /chunked\r\nContent-Length: 0\r\n\r\n"),
+                "/elsewhere" => write!(stream, "HTTP/1.1 301 Moved Permanently\r\nLocation: {}/plain\r\nContent-Length: 0\r\n\r\n", base2),
+                _ => write!(stream, "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+            };
+        }
+    });
+    let sha256 = hex(&Sha256::digest(&mmb));
+    for path in ["/plain", "/chunked", "/moved", "/elsewhere"] {
+        assert_eq!(fetch(&format!("{}{}", base, path), Some(&sha256)).unwrap(), mmb);
+    }
+    assert!(fetch(&format!("{}/plain", base), Some("00")).is_err());
+    assert!(fetch(&format!("{}/missing", base), None).is_err());
+    let file_data = load(&format!("{}/plain", base), Some("./test_resources/short.mm0"), Some(&sha256)).unwrap();
+    let outline = crate::Outline::new_from(&file_data).unwrap();
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+}
@@ -1,10 +1,20 @@
+//! Getting the input files into memory. Everything here except `FileData` itself and
+//! `FileData::new_from_bytes` needs a file system, so it's only available with `std`.
+
+#[cfg(feature = "std")]
 use std::path::PathBuf;
+use crate::prelude::*;
+#[cfg(feature = "std")]
 use crate::util::Res;
+#[cfg(feature = "std")]
 use crate::util::VerifErr;
+#[cfg(feature = "std")]
+use crate::io_err;
 
-use std::fmt::{ Debug, Formatter, Result as FmtResult };
+use core::fmt::{ Debug, Formatter, Result as FmtResult };
 
 
+#[cfg(feature = "std")]
 #[derive(Debug, PartialEq, Eq)]
 pub enum ImportGraph {
     NoImports(PathBuf),
@@ -26,29 +36,51 @@ pub struct FileData {
     pub mmz_files: Vec<String>,
     // The structure of the import hierarchy. Might be useful to let users
     // inspect/view this as part of the output.
+    #[cfg(feature = "std")]
     pub mmz_hierarchy: ImportGraph,
+    #[cfg(feature = "std")]
     root_mmz_path: PathBuf,
     // For detecting cycles
+    #[cfg(feature = "std")]
     todos: Vec<PathBuf>,
     // For detecting diamonds
+    #[cfg(feature = "std")]
     done: Vec<PathBuf>,
 }
 
-impl std::default::Default for FileData {
+impl core::default::Default for FileData {
     fn default() -> Self {
         FileData {
             mmb_file: Vec::new(),
             mmz_files: Vec::new(),
+            #[cfg(feature = "std")]
             root_mmz_path: PathBuf::from(""),
+            #[cfg(feature = "std")]
             mmz_hierarchy: ImportGraph::NoImports(PathBuf::from("")),
+            #[cfg(feature = "std")]
             todos: Vec::new(),
+            #[cfg(feature = "std")]
             done: Vec::new(),
         }
     }
 }
 
+impl FileData {
+    /// Build from data that's already in memory, doing no file IO. The mmz files
+    /// should be in the same order `new_from` would produce (imports first), and
+    /// can be empty, in which case only the mmb file gets verified.
+    pub fn new_from_bytes(mmb_file: Vec<u8>, mmz_files: Vec<String>) -> Self {
+        FileData {
+            mmb_file,
+            mmz_files,
+            ..FileData::default()
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 use std::fs::OpenOptions;
-use std::io::Read;
+#[cfg(feature = "std")]
 impl FileData {
     pub fn w_filename(&self, filename: impl AsRef<std::ffi::OsStr>) -> PathBuf {
         let mut new_path = self.root_mmz_path.clone();
@@ -57,31 +89,39 @@ impl FileData {
     }
 
     pub fn new_from(mmb_path: impl Into<PathBuf>,  root_mmz_path: Option<impl Into<PathBuf>>) -> Res<Self> {
-        let mmb_path = mmb_path.into().canonicalize().unwrap();
+        let mmb_path = io_err!(mmb_path.into().canonicalize())?;
         let root_mmz_path = match root_mmz_path {
-            None => {
-                let mut base = mmb_path.clone();
-                base.set_extension("mm0");
-                base
-            },
-            Some(p) => p.into().canonicalize().unwrap()
+            None => crate::compress::default_mm0_path(&mmb_path),
+            Some(p) => io_err!(p.into().canonicalize())?
         };
         
-        let mut mmb_handle = OpenOptions::new()
+        let mmb_handle = io_err!(OpenOptions::new()
             .read(true)
             .truncate(false)
-            .open(&mmb_path)
-            .map_err(|_| VerifErr::Msg(format!("IO err in add_file mmb")))
-            .unwrap();    
+            .open(&mmb_path))?;
 
-        let mut mmb_file = Vec::<u8>::with_capacity(mmb_handle.metadata().unwrap().len() as usize);
-        mmb_handle.read_to_end(&mut mmb_file).unwrap();        
+        let size = io_err!(mmb_handle.metadata())?.len() as usize;
+        let mmb_file = io_err!(crate::compress::read_maybe_compressed(mmb_handle, size))?;
+        FileData::with_mmz(mmb_file, root_mmz_path)
+    }
 
+    /// Like `new_from`, but reading the mmb file from `reader` (like standard input) until
+    /// it's closed. There's no path to find a default mm0 file next to, so with no
+    /// `root_mmz_path` only the mmb file gets verified.
+    pub fn new_from_reader(reader: impl std::io::Read, root_mmz_path: Option<impl Into<PathBuf>>) -> Res<Self> {
+        let mmb_file = io_err!(crate::compress::read_maybe_compressed(reader, 0))?;
+        match root_mmz_path {
+            None => Ok(FileData::new_from_bytes(mmb_file, Vec::new())),
+            Some(p) => FileData::with_mmz(mmb_file, io_err!(p.into().canonicalize())?),
+        }
+    }
+
+    fn with_mmz(mmb_file: Vec<u8>, root_mmz_path: PathBuf) -> Res<Self> {
         let mut data = FileData::default();
         data.mmb_file = mmb_file;
         data.root_mmz_path = root_mmz_path.clone();
         data.mmz_hierarchy = data.add_mmz_aux(root_mmz_path)?;
-        Ok(data)        
+        Ok(data)
     }
 
     /// Form the import graph by mutual recursion with `find_imports`.
@@ -90,7 +130,7 @@ impl FileData {
     fn add_mmz_aux(&mut self, mmz_path: impl Into<PathBuf>) -> Res<ImportGraph> {
         let mmz_path = mmz_path.into();
         self.todos.push(mmz_path.clone());
-        let s = std::fs::read_to_string(&mmz_path).unwrap();
+        let s = io_err!(std::fs::read_to_string(&mmz_path))?;
 
         let imports = self.find_imports(s.split_whitespace())?;
         self.mmz_files.push(s);
@@ -135,10 +175,13 @@ impl Debug for FileData {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         let mut d = f.debug_struct("FileData");
         d.field("mmz_files", &"<omitted>");
-        d.field("mmz_hierarchy", &self.mmz_hierarchy);
-        d.field("todos", &self.todos);
-        d.field("done", &self.done);
-        d.field("root_mmz_file", &self.root_mmz_path);
+        #[cfg(feature = "std")]
+        {
+            d.field("mmz_hierarchy", &self.mmz_hierarchy);
+            d.field("todos", &self.todos);
+            d.field("done", &self.done);
+            d.field("root_mmz_file", &self.root_mmz_path);
+        }
         d.finish()
     }
 }
@@ -178,3 +221,15 @@ fn import_test_diamond1() {
 fn import_test_cycle1() {
     let _ = FileData::new_from("./test_resources/cycle/cycle.mmb", Some("./test_resources/cycle/cycleA.mm0")).unwrap();
 }
+
+#[test]
+fn reader_test1() {
+    let bytes = std::fs::read("./test_resources/a.mmb").unwrap();
+    let from_path = FileData::new_from("./test_resources/a.mmb", Some("./test_resources/a.mm0")).unwrap();
+    let from_reader = FileData::new_from_reader(bytes.as_slice(), Some("./test_resources/a.mm0")).unwrap();
+    assert_eq!(from_reader.mmb_file, from_path.mmb_file);
+    assert_eq!(from_reader.mmz_files, from_path.mmz_files);
+    assert_eq!(from_reader.mmz_hierarchy, from_path.mmz_hierarchy);
+    let alone = FileData::new_from_reader(bytes.as_slice(), None::<PathBuf>).unwrap();
+    assert!(alone.mmz_files.is_empty());
+}
@@ -0,0 +1,365 @@
+//! A minimal JSON reader/writer, just enough for exchanging small metadata files
+//! (environment dumps, reports) with other tools without pulling in a serialization framework.
+
+use crate::prelude::*;
+use core::fmt::{ Display, Formatter, Result as FmtResult, Write };
+use crate::util::{ Res, VerifErr };
+
+/// How deeply arrays and objects may nest, so that hostile input can't exhaust the stack.
+pub const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Non-negative integers are kept exact since we use them for offsets, counts and seeds.
+    UInt(u64),
+    Num(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    /// Keys are kept in insertion order so that output is deterministic.
+    Obj(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn obj() -> Self {
+        Json::Obj(Vec::new())
+    }
+
+    /// Builder-style insertion for objects; does nothing for other variants.
+    pub fn with(mut self, key: &str, val: impl Into<Json>) -> Self {
+        if let Json::Obj(fields) = &mut self {
+            fields.push((key.to_string(), val.into()));
+        }
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Obj(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s.as_str()),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::UInt(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_arr(&self) -> Option<&[Json]> {
+        match self {
+            Json::Arr(xs) => Some(xs.as_slice()),
+            _ => None
+        }
+    }
+
+    /// Render with one field/element per line, for files meant to be read by people too.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        let pad = |out: &mut String, n: usize| for _ in 0..n { out.push_str("  ") };
+        match self {
+            Json::Arr(xs) if !xs.is_empty() => {
+                out.push_str("[\n");
+                for (idx, x) in xs.iter().enumerate() {
+                    pad(out, indent + 1);
+                    x.write_pretty(out, indent + 1);
+                    out.push_str(if idx + 1 == xs.len() { "\n" } else { ",\n" });
+                }
+                pad(out, indent);
+                out.push(']');
+            }
+            Json::Obj(fields) if !fields.is_empty() => {
+                out.push_str("{\n");
+                for (idx, (k, v)) in fields.iter().enumerate() {
+                    pad(out, indent + 1);
+                    let _ = write!(out, "{}: ", Json::Str(k.clone()));
+                    v.write_pretty(out, indent + 1);
+                    out.push_str(if idx + 1 == fields.len() { "\n" } else { ",\n" });
+                }
+                pad(out, indent);
+                out.push('}');
+            }
+            owise => { let _ = write!(out, "{}", owise); }
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self { Json::Bool(b) }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Self { Json::UInt(n) }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Self { Json::UInt(n as u64) }
+}
+
+impl From<u8> for Json {
+    fn from(n: u8) -> Self { Json::UInt(n as u64) }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self { Json::UInt(n as u64) }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Self { Json::Num(n) }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self { Json::Str(s.to_string()) }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self { Json::Str(s) }
+}
+
+impl<A: Into<Json>> From<Option<A>> for Json {
+    fn from(x: Option<A>) -> Self {
+        x.map(|x| x.into()).unwrap_or(Json::Null)
+    }
+}
+
+impl<A: Into<Json>> From<Vec<A>> for Json {
+    fn from(xs: Vec<A>) -> Self {
+        Json::Arr(xs.into_iter().map(|x| x.into()).collect())
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::UInt(n) => write!(f, "{}", n),
+            Json::Num(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Num(_) => write!(f, "null"),
+            Json::Str(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Json::Arr(xs) => {
+                f.write_char('[')?;
+                for (idx, x) in xs.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", x)?;
+                }
+                f.write_char(']')
+            }
+            Json::Obj(fields) => {
+                f.write_char('{')?;
+                for (idx, (k, v)) in fields.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}:{}", Json::Str(k.clone()), v)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Parse a complete JSON document.
+pub fn parse(s: &str) -> Res<Json> {
+    let mut p = Parser { src: s.as_bytes(), pos: 0 };
+    let out = p.value(0)?;
+    p.skip_ws();
+    if p.pos != p.src.len() {
+        return Err(p.err("trailing characters"))
+    }
+    Ok(out)
+}
+
+struct Parser<'a> {
+    src: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn err(&self, msg: &str) -> VerifErr {
+        VerifErr::Msg(format!("json: {} @ {}", msg, self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\n' | b'\r' | b'\t') = self.src.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, lit: &[u8]) -> bool {
+        if self.src[self.pos..].starts_with(lit) {
+            self.pos += lit.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Res<Json> {
+        if depth > MAX_DEPTH {
+            return Err(self.err("nested too deeply"))
+        }
+        self.skip_ws();
+        match self.src.get(self.pos) {
+            None => Err(self.err("unexpected end of input")),
+            Some(b'n') if self.eat(b"null") => Ok(Json::Null),
+            Some(b't') if self.eat(b"true") => Ok(Json::Bool(true)),
+            Some(b'f') if self.eat(b"false") => Ok(Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut xs = Vec::new();
+                self.skip_ws();
+                if self.eat(b"]") {
+                    return Ok(Json::Arr(xs))
+                }
+                loop {
+                    xs.push(self.value(depth + 1)?);
+                    self.skip_ws();
+                    if self.eat(b",") {
+                        continue
+                    } else if self.eat(b"]") {
+                        return Ok(Json::Arr(xs))
+                    } else {
+                        return Err(self.err("expected `,` or `]`"))
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_ws();
+                if self.eat(b"}") {
+                    return Ok(Json::Obj(fields))
+                }
+                loop {
+                    self.skip_ws();
+                    let k = self.string()?;
+                    self.skip_ws();
+                    if !self.eat(b":") {
+                        return Err(self.err("expected `:`"))
+                    }
+                    fields.push((k, self.value(depth + 1)?));
+                    self.skip_ws();
+                    if self.eat(b",") {
+                        continue
+                    } else if self.eat(b"}") {
+                        return Ok(Json::Obj(fields))
+                    } else {
+                        return Err(self.err("expected `,` or `}`"))
+                    }
+                }
+            }
+            Some(_) => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Res<Json> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') = self.src.get(self.pos) {
+            self.pos += 1;
+        }
+        let text = core::str::from_utf8(&self.src[start..self.pos]).map_err(|_| self.err("bad number"))?;
+        if let Ok(n) = text.parse::<u64>() {
+            Ok(Json::UInt(n))
+        } else {
+            text.parse::<f64>().map(Json::Num).map_err(|_| self.err("bad number"))
+        }
+    }
+
+    fn string(&mut self) -> Res<String> {
+        if !self.eat(b"\"") {
+            return Err(self.err("expected string"))
+        }
+        let mut out = Vec::new();
+        loop {
+            match self.src.get(self.pos) {
+                None => return Err(self.err("unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(out).map_err(|_| self.err("invalid utf-8"))
+                }
+                Some(b'\\') => {
+                    let c = match self.src.get(self.pos + 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let hex = self.src.get(self.pos + 2..self.pos + 6).ok_or_else(|| self.err("bad escape"))?;
+                            let hex = core::str::from_utf8(hex).map_err(|_| self.err("bad escape"))?;
+                            let code = u32::from_str_radix(hex, 16).map_err(|_| self.err("bad escape"))?;
+                            self.pos += 4;
+                            core::char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                        _ => return Err(self.err("bad escape")),
+                    };
+                    self.pos += 2;
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                Some(c) => {
+                    out.push(*c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn json_roundtrip1() {
+    let j = Json::obj()
+        .with("name", "a \"quoted\"\nname")
+        .with("n", 18446744073709551615u64)
+        .with("xs", vec![Json::Null, Json::Bool(true), Json::Num(-1.5)])
+        .with("empty", Json::obj());
+    assert_eq!(parse(&j.to_string()).unwrap(), j);
+    assert_eq!(parse(&j.pretty()).unwrap(), j);
+    assert!(parse("{\"a\": 1,}").is_err());
+    assert!(parse("[1] 2").is_err());
+    assert!(parse(&"[".repeat(200_000)).is_err());
+    let deepest = format!("{}{}", "[".repeat(MAX_DEPTH + 2), "]".repeat(MAX_DEPTH + 2));
+    assert!(parse(&deepest[1..deepest.len() - 1]).is_ok() && parse(&deepest).is_err());
+}
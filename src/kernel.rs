@@ -0,0 +1,746 @@
+//! The trusted kernel: a complete checker for one declaration's proof, in this one file,
+//! using nothing from the rest of the crate or any other crate, only `core` and `alloc`.
+//! Everything else (parsing the header and tables, the index and names, the fast checker
+//! in `mmb`, the mm0 side, exporters, the command line tool) sits outside it. With the
+//! `kernel` feature, a declaration is only accepted if the kernel accepts it too, so for
+//! soundness an auditor has to read this file and the `Env` adapter in `mmb::recheck`.
+//!
+//! The kernel is written differently from `MmbState` on purpose, so that a bug in one is
+//! unlikely to be repeated in the other:
+//!
+//! - expressions are hash-consed into a `Store` and referred to by id, so two expressions
+//!   are equal exactly when their ids are, rather than bump-allocated and compared by
+//!   walking them. The `Store` is the only thing that grows with the size of a proof
+//!   besides the stacks, and an embedder with its own memory can bring one in place of
+//!   `VecStore`;
+//! - a unify stream is matched by walking the target expression in the stream's order,
+//!   keeping the subexpressions still to be matched on a work stack, rather than by
+//!   pushing and popping a unify stack of expressions as the commands say, and one for a
+//!   theorem's hypotheses is matched against each hypothesis in turn once the conclusion
+//!   is done;
+//! - commands are decoded here, not by `ProofIter` and `UnifyIter`.
+//!
+//! Comparing by id means `Refl` accepts two copies of the same expression built
+//! separately, as `MmbState` does with `hash_cons` set but not without it. Nothing here
+//! recurses, so however deeply a valid file's expressions nest, checking it only takes
+//! memory, not native stack.
+
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+use alloc::collections::BTreeMap;
+
+const SORT_STRICT: u8 = 2;
+const SORT_PROVABLE: u8 = 4;
+const TYPE_BOUND_MASK: u64 = 1 << 63;
+const TYPE_DEPS_MASK: u64 = (1 << 56) - 1;
+const MAX_BOUND_VARS: u32 = 56;
+
+const PROOF_TERM: u8 = 0x10;
+const PROOF_TERM_SAVE: u8 = 0x11;
+const PROOF_REF: u8 = 0x12;
+const PROOF_DUMMY: u8 = 0x13;
+const PROOF_THM: u8 = 0x14;
+const PROOF_THM_SAVE: u8 = 0x15;
+const PROOF_HYP: u8 = 0x16;
+const PROOF_CONV: u8 = 0x17;
+const PROOF_REFL: u8 = 0x18;
+const PROOF_SYMM: u8 = 0x19;
+const PROOF_CONG: u8 = 0x1A;
+const PROOF_UNFOLD: u8 = 0x1B;
+const PROOF_CONV_CUT: u8 = 0x1C;
+const PROOF_CONV_REF: u8 = 0x1D;
+const PROOF_CONV_SAVE: u8 = 0x1E;
+const PROOF_SAVE: u8 = 0x1F;
+const UNIFY_TERM: u8 = 0x30;
+const UNIFY_TERM_SAVE: u8 = 0x31;
+const UNIFY_REF: u8 = 0x32;
+const UNIFY_DUMMY: u8 = 0x33;
+const UNIFY_HYP: u8 = 0x36;
+
+/// Why the kernel rejected a declaration, and the offset in the file of the command it was
+/// running (or of the declaration's proof, if it hadn't started).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelErr {
+    pub pos: usize,
+    pub reason: &'static str,
+}
+
+type Res<A> = Result<A, KernelErr>;
+
+/// A term's table entry: its sort byte (with the high bit set for a def), its argument
+/// types followed by its return type as little-endian words, and the offset of its unify
+/// stream (which only a def has).
+#[derive(Debug, Clone, Copy)]
+pub struct TermSig<'e> {
+    pub sort: u8,
+    pub args: &'e [u8],
+    pub unify: usize,
+}
+
+/// An assertion's table entry: its argument types as little-endian words, and the offset
+/// of its unify stream.
+#[derive(Debug, Clone, Copy)]
+pub struct ThmSig<'e> {
+    pub args: &'e [u8],
+    pub unify: usize,
+}
+
+/// The tables the kernel reads, from whatever parsed them.
+pub trait Env {
+    /// The whole file, which unify streams and proofs are read from by offset.
+    fn bytes(&self) -> &[u8];
+    fn num_sorts(&self) -> u8;
+    fn num_terms(&self) -> u32;
+    fn num_thms(&self) -> u32;
+    fn sort_mods(&self, sort: u8) -> Option<u8>;
+    fn term(&self, num: u32) -> Option<TermSig<'_>>;
+    fn thm(&self, num: u32) -> Option<ThmSig<'_>>;
+}
+
+/// An expression as the `Store` hands it out: the `n`th variable of the declaration (its
+/// arguments in order, then its dummies as they come up), or a term applied to arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node<'s> {
+    Var(u32),
+    App(u32, &'s [u32]),
+}
+
+/// Where the expressions of the declaration being checked live.
+pub trait Store {
+    /// The id of `node`, which has type `ty`, adding it if it's new. Equal nodes have to
+    /// get equal ids. Failing here (say, when there's no more room) rejects the declaration.
+    fn intern(&mut self, node: Node<'_>, ty: u64) -> Res<u32>;
+    fn node(&self, id: u32) -> Node<'_>;
+    fn ty(&self, id: u32) -> u64;
+    /// Forget everything, before the next declaration.
+    fn clear(&mut self);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Owned {
+    Var(u32),
+    App(u32, Vec<u32>),
+}
+
+/// A `Store` on the global allocator.
+#[derive(Debug, Default)]
+pub struct VecStore {
+    nodes: Vec<(Owned, u64)>,
+    ids: BTreeMap<Owned, u32>,
+}
+
+impl Store for VecStore {
+    fn intern(&mut self, node: Node<'_>, ty: u64) -> Res<u32> {
+        let owned = match node {
+            Node::Var(n) => Owned::Var(n),
+            Node::App(t, args) => Owned::App(t, args.to_vec()),
+        };
+        if let Some(id) = self.ids.get(&owned) {
+            return Ok(*id)
+        }
+        let id = self.nodes.len() as u32;
+        self.nodes.push((owned.clone(), ty));
+        self.ids.insert(owned, id);
+        Ok(id)
+    }
+
+    fn node(&self, id: u32) -> Node<'_> {
+        match &self.nodes[id as usize].0 {
+            Owned::Var(n) => Node::Var(*n),
+            Owned::App(t, args) => Node::App(*t, args),
+        }
+    }
+
+    fn ty(&self, id: u32) -> u64 {
+        self.nodes[id as usize].1
+    }
+
+    fn clear(&mut self) {
+        self.nodes.clear();
+        self.ids.clear();
+    }
+}
+
+/// A declaration to check. Each carries the number of terms and the number of
+/// assertions declared before it, which are all it can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decl {
+    Sort(u8),
+    Term { num: u32, thms_before: u32 },
+    Axiom { num: u32, terms_before: u32 },
+    Thm { num: u32, terms_before: u32 },
+}
+
+/// The command at `pos`: its opcode, its data, and where the next command starts.
+fn decode(bytes: &[u8], pos: usize) -> Option<(u8, u32, usize)> {
+    let b = *bytes.get(pos)?;
+    let size = match b & 0xC0 { 0 => 0, 0x40 => 1, 0x80 => 2, _ => 4 };
+    let data = bytes.get(pos + 1..pos + 1 + size)?;
+    let value = data.iter().rev().fold(0u32, |acc, x| (acc << 8) | *x as u32);
+    Some((b & 0x3F, value, pos + 1 + size))
+}
+
+fn word(bytes: &[u8], i: usize) -> u64 {
+    let mut w = [0u8; 8];
+    w.copy_from_slice(&bytes[8 * i..8 * i + 8]);
+    u64::from_le_bytes(w)
+}
+
+fn is_bound(ty: u64) -> bool {
+    ty & TYPE_BOUND_MASK != 0
+}
+
+fn sort(ty: u64) -> u8 {
+    ((ty >> 56) & 0x7F) as u8
+}
+
+/// Whether something of type `from` can go where `to` is expected: the same sort and
+/// boundness, or a bound variable where an expression of its sort is expected.
+fn compatible(from: u64, to: u64) -> bool {
+    let diff = from ^ to;
+    diff & !TYPE_DEPS_MASK == 0 || (diff & !TYPE_BOUND_MASK & !TYPE_DEPS_MASK == 0 && is_bound(from))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Expr(u32),
+    Proof(u32),
+    Conv(u32, u32),
+    CoConv(u32, u32),
+}
+
+/// Where a theorem's hypotheses come from when its unify stream reaches them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Hyps {
+    /// Applying it: the proofs on the stack.
+    Stack,
+    /// Checking its own proof: the `Hyp`s it made.
+    Own,
+}
+
+struct Kernel<'k, E: Env, S: Store> {
+    env: &'k E,
+    store: &'k mut S,
+    def: bool,
+    term_limit: u32,
+    thm_limit: u32,
+    pos: usize,
+    args: Vec<u32>,
+    num_vars: u32,
+    next_bv: u64,
+    stack: Vec<Item>,
+    heap: Vec<Item>,
+    hyps: Vec<u32>,
+}
+
+impl<'k, E: Env, S: Store> Kernel<'k, E, S> {
+    fn err<A>(&self, reason: &'static str) -> Res<A> {
+        Err(KernelErr { pos: self.pos, reason })
+    }
+
+    fn ensure(&self, cond: bool, reason: &'static str) -> Res<()> {
+        if cond { Ok(()) } else { self.err(reason) }
+    }
+
+    fn sort_mods(&self, s: u8) -> Res<u8> {
+        match self.env.sort_mods(s) {
+            Some(mods) if s < self.env.num_sorts() => Ok(mods),
+            _ => self.err("no such sort"),
+        }
+    }
+
+    fn term_sig(&self, num: u32) -> Res<TermSig<'k>> {
+        self.ensure(num < self.term_limit && num < self.env.num_terms(), "a term used before it's declared")?;
+        match self.env.term(num) {
+            Some(sig) if sig.args.len() >= 8 && sig.args.len() % 8 == 0 => Ok(sig),
+            _ => self.err("a bad term table entry"),
+        }
+    }
+
+    fn thm_sig(&self, num: u32) -> Res<ThmSig<'k>> {
+        self.ensure(num < self.thm_limit && num < self.env.num_thms(), "an assertion used before it's declared")?;
+        match self.env.thm(num) {
+            Some(sig) if sig.args.len() % 8 == 0 => Ok(sig),
+            _ => self.err("a bad assertion table entry"),
+        }
+    }
+
+    fn var(&mut self, ty: u64) -> Res<u32> {
+        self.num_vars += 1;
+        self.store.intern(Node::Var(self.num_vars - 1), ty)
+    }
+
+    /// The bound variables `e` depends on, or the one it is.
+    fn low(&self, e: u32) -> u64 {
+        self.store.ty(e) & TYPE_DEPS_MASK
+    }
+
+    /// Put the arguments (the first `n` of `sig`) on the heap, checking that the bound
+    /// ones are numbered in order and not strict, and that everything else depends only
+    /// on bound variables before it. The rest of `sig` (a term's return type) is checked
+    /// the same way but not put on the heap.
+    fn load_args(&mut self, sig: &[u8], n: usize) -> Res<()> {
+        let mut next_bv = 1u64;
+        for i in 0..sig.len() / 8 {
+            let ty = word(sig, i);
+            if is_bound(ty) {
+                self.ensure(next_bv >> MAX_BOUND_VARS == 0, "too many bound variables")?;
+                self.ensure(ty & TYPE_DEPS_MASK == next_bv, "bound variables out of order")?;
+                self.ensure(self.sort_mods(sort(ty))? & SORT_STRICT == 0, "a bound variable of a strict sort")?;
+                next_bv <<= 1;
+            } else {
+                self.sort_mods(sort(ty))?;
+                self.ensure(ty & TYPE_DEPS_MASK & !(next_bv - 1) == 0, "an argument depends on a later variable")?;
+            }
+            if i < n {
+                let v = self.var(ty)?;
+                self.args.push(v);
+                self.heap.push(Item::Expr(v));
+            }
+        }
+        self.next_bv = next_bv;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Res<Item> {
+        match self.stack.pop() {
+            Some(item) => Ok(item),
+            None => self.err("the stack is empty"),
+        }
+    }
+
+    fn pop_expr(&mut self) -> Res<u32> {
+        match self.pop()? {
+            Item::Expr(e) => Ok(e),
+            _ => self.err("expected an expression"),
+        }
+    }
+
+    fn pop_proof(&mut self) -> Res<u32> {
+        match self.pop()? {
+            Item::Proof(e) => Ok(e),
+            _ => self.err("expected a proof"),
+        }
+    }
+
+    fn pop_coconv(&mut self) -> Res<(u32, u32)> {
+        match self.pop()? {
+            Item::CoConv(e1, e2) => Ok((e1, e2)),
+            _ => self.err("expected a conversion obligation"),
+        }
+    }
+
+    /// The top `n` items of the stack, which have to be expressions.
+    fn pop_exprs(&mut self, n: usize) -> Res<Vec<u32>> {
+        let from = match self.stack.len().checked_sub(n) {
+            Some(from) => from,
+            None => return self.err("the stack is too short"),
+        };
+        let mut out = Vec::with_capacity(n);
+        for item in self.stack.drain(from..) {
+            match item {
+                Item::Expr(e) => out.push(e),
+                _ => return Err(KernelErr { pos: self.pos, reason: "expected an expression" }),
+            }
+        }
+        Ok(out)
+    }
+
+    fn heap_get(&self, i: u32) -> Res<Item> {
+        match self.heap.get(i as usize) {
+            Some(item) => Ok(*item),
+            None => self.err("a backreference past the end of the heap"),
+        }
+    }
+
+    /// Run the proof stream from `start` up to `end`, whose last byte has to be its end.
+    fn run(&mut self, start: usize, end: usize) -> Res<()> {
+        let bytes = self.env.bytes();
+        let mut pos = start;
+        loop {
+            self.pos = pos;
+            let (op, data, next) = match decode(bytes, pos) {
+                Some(cmd) if cmd.2 <= end => cmd,
+                _ => return self.err("the proof stream runs past its end"),
+            };
+            pos = next;
+            match op {
+                0 => return self.ensure(next == end, "the proof stream ends early"),
+                PROOF_REF => {
+                    let item = self.heap_get(data)?;
+                    self.stack.push(item);
+                }
+                PROOF_DUMMY => {
+                    let s = self.ensure(data < 0x80, "no such sort").map(|_| data as u8)?;
+                    self.ensure(self.sort_mods(s)? & SORT_STRICT == 0, "a dummy of a strict sort")?;
+                    let bv = self.next_bv;
+                    self.ensure(bv >> MAX_BOUND_VARS == 0, "too many bound variables")?;
+                    self.next_bv <<= 1;
+                    let v = self.var(TYPE_BOUND_MASK | ((s as u64) << 56) | bv)?;
+                    self.stack.push(Item::Expr(v));
+                    self.heap.push(Item::Expr(v));
+                }
+                PROOF_TERM | PROOF_TERM_SAVE => {
+                    let e = self.term(data)?;
+                    self.stack.push(Item::Expr(e));
+                    if op == PROOF_TERM_SAVE {
+                        self.heap.push(Item::Expr(e));
+                    }
+                }
+                PROOF_THM | PROOF_THM_SAVE => {
+                    let e = self.thm(data)?;
+                    self.stack.push(Item::Proof(e));
+                    if op == PROOF_THM_SAVE {
+                        self.heap.push(Item::Proof(e));
+                    }
+                }
+                PROOF_HYP => {
+                    self.ensure(!self.def, "a hypothesis in a def")?;
+                    let e = self.pop_expr()?;
+                    self.ensure(self.sort_mods(sort(self.store.ty(e)))? & SORT_PROVABLE != 0, "a hypothesis of a sort that isn't provable")?;
+                    self.hyps.push(e);
+                    self.heap.push(Item::Proof(e));
+                }
+                PROOF_CONV => {
+                    let e2 = self.pop_proof()?;
+                    let e1 = self.pop_expr()?;
+                    self.stack.push(Item::Proof(e1));
+                    self.stack.push(Item::CoConv(e1, e2));
+                }
+                PROOF_REFL => {
+                    let (e1, e2) = self.pop_coconv()?;
+                    self.ensure(e1 == e2, "Refl on different expressions")?;
+                }
+                PROOF_SYMM => {
+                    let (e1, e2) = self.pop_coconv()?;
+                    self.stack.push(Item::CoConv(e2, e1));
+                }
+                PROOF_CONG => {
+                    let (e1, e2) = self.pop_coconv()?;
+                    let obligations = match (self.store.node(e1), self.store.node(e2)) {
+                        (Node::App(t1, as1), Node::App(t2, as2)) if t1 == t2 && as1.len() == as2.len() =>
+                            as1.iter().zip(as2.iter()).rev().map(|(a1, a2)| Item::CoConv(*a1, *a2)).collect::<Vec<_>>(),
+                        _ => return self.err("Cong needs applications of the same term"),
+                    };
+                    self.stack.extend(obligations);
+                }
+                PROOF_UNFOLD => {
+                    let e_prime = self.pop_expr()?;
+                    let f = self.pop_expr()?;
+                    let (t, args) = match self.store.node(f) {
+                        Node::App(t, args) => (t, args.to_vec()),
+                        _ => return self.err("Unfold needs a def application"),
+                    };
+                    let sig = self.term_sig(t)?;
+                    self.ensure(sig.sort & 0x80 != 0, "Unfold needs a def application")?;
+                    self.match_all(sig.unify, e_prime, args, None)?;
+                    self.pos = pos;
+                    let (f2, e_doubleprime) = self.pop_coconv()?;
+                    self.ensure(f == f2, "Unfold of the wrong expression")?;
+                    self.stack.push(Item::CoConv(e_prime, e_doubleprime));
+                }
+                PROOF_CONV_CUT => {
+                    let (e1, e2) = self.pop_coconv()?;
+                    self.stack.push(Item::Conv(e1, e2));
+                    self.stack.push(Item::CoConv(e1, e2));
+                }
+                PROOF_CONV_REF => {
+                    let saved = self.heap_get(data)?;
+                    let (e1, e2) = self.pop_coconv()?;
+                    self.ensure(saved == Item::Conv(e1, e2), "ConvRef to the wrong conversion")?;
+                }
+                PROOF_CONV_SAVE => match self.pop()? {
+                    conv @ Item::Conv(..) => self.heap.push(conv),
+                    _ => return self.err("expected a conversion proof"),
+                },
+                PROOF_SAVE => match self.stack.last().copied() {
+                    Some(Item::CoConv(..)) => return self.err("a conversion obligation can't be saved"),
+                    Some(item) => self.heap.push(item),
+                    None => return self.err("the stack is empty"),
+                },
+                _ => return self.err("not a proof command"),
+            }
+        }
+    }
+
+    fn term(&mut self, num: u32) -> Res<u32> {
+        let sig = self.term_sig(num)?;
+        let n = sig.args.len() / 8 - 1;
+        let args = self.pop_exprs(n)?;
+        // The bound variables passed for each bound argument, in order.
+        let mut bound = Vec::new();
+        for (i, a) in args.iter().enumerate() {
+            let s = word(sig.args, i);
+            self.ensure(compatible(self.store.ty(*a), s), "an argument of the wrong sort")?;
+            if is_bound(s) {
+                bound.push(self.low(*a));
+            }
+        }
+        let mut deps = 0;
+        for (i, a) in args.iter().enumerate() {
+            let s = word(sig.args, i);
+            if is_bound(s) {
+                continue
+            }
+            let mut d = self.low(*a);
+            // In a def, what an argument depends on through one of the term's binders
+            // doesn't count.
+            if self.def {
+                for (j, bv) in bound.iter().enumerate() {
+                    if s & (1 << j) != 0 {
+                        d &= !bv;
+                    }
+                }
+            }
+            deps |= d;
+        }
+        let ret = word(sig.args, n);
+        if self.def && !is_bound(ret) {
+            for (j, bv) in bound.iter().enumerate() {
+                if ret & (1 << j) != 0 {
+                    deps |= bv;
+                }
+            }
+        }
+        self.store.intern(Node::App(num, &args), (((sig.sort & 0x7F) as u64) << 56) | deps)
+    }
+
+    fn thm(&mut self, num: u32) -> Res<u32> {
+        let sig = self.thm_sig(num)?;
+        let concl = self.pop_expr()?;
+        let args = self.pop_exprs(sig.args.len() / 8)?;
+        // A variable passed for a bound argument can't be in anything before it ...
+        let mut bound = Vec::new();
+        for (i, a) in args.iter().enumerate() {
+            if is_bound(word(sig.args, i)) {
+                let bv = self.low(*a);
+                self.ensure(args[..i].iter().all(|b| self.low(*b) & bv == 0), "a bound variable that isn't distinct")?;
+                bound.push(bv);
+            }
+        }
+        // ... or in what's passed for an argument that doesn't depend on it.
+        for (i, a) in args.iter().enumerate() {
+            let s = word(sig.args, i);
+            if !is_bound(s) {
+                for (j, bv) in bound.iter().enumerate() {
+                    self.ensure(s & (1 << j) != 0 || self.low(*a) & bv == 0, "a disjoint variable violation")?;
+                }
+            }
+        }
+        let pos = self.pos;
+        self.match_all(sig.unify, concl, args, Some(Hyps::Stack))?;
+        self.pos = pos;
+        Ok(concl)
+    }
+
+    /// Match the unify stream at `start` against `target`, with `uheap` to start with, and
+    /// then against the hypotheses from `hyps` (`None` for a def, which can have dummies
+    /// instead).
+    fn match_all(&mut self, start: usize, target: u32, mut uheap: Vec<u32>, hyps: Option<Hyps>) -> Res<()> {
+        let mut pos = self.match_expr(start, &mut uheap, target, hyps.is_none())?;
+        loop {
+            self.pos = pos;
+            match decode(self.env.bytes(), pos) {
+                Some((0, _, _)) => break,
+                Some((UNIFY_HYP, _, next)) => {
+                    let h = match hyps {
+                        Some(Hyps::Stack) => self.pop_proof()?,
+                        Some(Hyps::Own) => match self.hyps.pop() {
+                            Some(h) => h,
+                            None => return self.err("more hypotheses in the statement than in the proof"),
+                        },
+                        None => return self.err("a def has no hypotheses"),
+                    };
+                    pos = self.match_expr(next, &mut uheap, h, false)?;
+                }
+                _ => return self.err("the unify stream doesn't end after its expressions"),
+            }
+        }
+        if hyps == Some(Hyps::Own) {
+            self.ensure(self.hyps.is_empty(), "fewer hypotheses in the statement than in the proof")?;
+        }
+        Ok(())
+    }
+
+    /// Match the expression at `pos` in a unify stream against `target`, returning where
+    /// the stream goes on after it. The stream lists an expression's subexpressions
+    /// depth first, so the ones still to be matched wait on `todo`, the next one on top.
+    fn match_expr(&mut self, mut pos: usize, uheap: &mut Vec<u32>, target: u32, dummies: bool) -> Res<usize> {
+        let mut todo = alloc::vec![target];
+        while let Some(target) = todo.pop() {
+            self.pos = pos;
+            let (op, data, next) = match decode(self.env.bytes(), pos) {
+                Some(cmd) => cmd,
+                None => return self.err("the unify stream runs off the end of the file"),
+            };
+            pos = next;
+            match op {
+                UNIFY_REF => {
+                    let saved = uheap.get(data as usize).copied();
+                    self.ensure(saved == Some(target), "unification failed")?;
+                }
+                UNIFY_TERM | UNIFY_TERM_SAVE => {
+                    self.term_sig(data)?;
+                    match self.store.node(target) {
+                        Node::App(t, args) if t == data => todo.extend(args.iter().rev()),
+                        _ => return self.err("unification failed"),
+                    }
+                    if op == UNIFY_TERM_SAVE {
+                        uheap.push(target);
+                    }
+                }
+                UNIFY_DUMMY => {
+                    self.ensure(dummies, "a dummy outside a def")?;
+                    let ty = self.store.ty(target);
+                    let is_var = matches!(self.store.node(target), Node::Var(_));
+                    self.ensure(is_var && is_bound(ty) && data < 0x80 && sort(ty) == data as u8, "a dummy that isn't a bound variable of its sort")?;
+                    let bv = self.low(target);
+                    self.ensure(uheap.iter().all(|u| self.store.ty(*u) & bv == 0), "a dummy that isn't fresh")?;
+                    uheap.push(target);
+                }
+                _ => return self.err("not an expression in a unify stream"),
+            }
+        }
+        Ok(pos)
+    }
+}
+
+/// Check `decl`, whose proof (if it has one) is the stream from `proof.0` up to
+/// `proof.1` in `env.bytes()`. `store` is cleared first.
+pub fn check<E: Env, S: Store>(env: &E, store: &mut S, decl: Decl, proof: Option<(usize, usize)>) -> Res<()> {
+    store.clear();
+    let (def, term_limit, thm_limit) = match decl {
+        Decl::Sort(_) => (false, 0, 0),
+        Decl::Term { num, thms_before } => (true, num, thms_before),
+        Decl::Axiom { num, terms_before } | Decl::Thm { num, terms_before } => (false, terms_before, num),
+    };
+    let mut k = Kernel {
+        env,
+        store,
+        def,
+        term_limit,
+        thm_limit,
+        pos: proof.map(|p| p.0).unwrap_or(0),
+        args: Vec::new(),
+        num_vars: 0,
+        next_bv: 1,
+        stack: Vec::new(),
+        heap: Vec::new(),
+        hyps: Vec::new(),
+    };
+    match decl {
+        Decl::Sort(num) => {
+            k.ensure(num < env.num_sorts(), "no such sort")?;
+            k.ensure(proof.is_none(), "a sort with a proof")
+        }
+        Decl::Term { num, .. } => {
+            k.ensure(num < env.num_terms(), "no such term")?;
+            let sig = match env.term(num) {
+                Some(sig) if sig.args.len() >= 8 && sig.args.len() % 8 == 0 => sig,
+                _ => return k.err("a bad term table entry"),
+            };
+            let n = sig.args.len() / 8 - 1;
+            let ret = word(sig.args, n);
+            k.load_args(sig.args, n)?;
+            k.ensure(!is_bound(ret) && sort(ret) == sig.sort & 0x7F, "a bad return type")?;
+            let (start, end) = match proof {
+                None => return k.ensure(sig.sort & 0x80 == 0, "a def without a proof"),
+                Some(_) if sig.sort & 0x80 == 0 => return k.err("a term with a proof"),
+                Some(proof) => proof,
+            };
+            k.run(start, end)?;
+            let value = k.pop_expr()?;
+            k.ensure(k.stack.is_empty(), "a def's proof leaves more than its value")?;
+            k.ensure(compatible(k.store.ty(value), ret), "a def's value has the wrong sort")?;
+            let args = k.args.clone();
+            k.match_all(sig.unify, value, args, None)
+        }
+        Decl::Axiom { num, .. } | Decl::Thm { num, .. } => {
+            k.ensure(num < env.num_thms(), "no such assertion")?;
+            let sig = match env.thm(num) {
+                Some(sig) if sig.args.len() % 8 == 0 => sig,
+                _ => return k.err("a bad assertion table entry"),
+            };
+            k.load_args(sig.args, sig.args.len() / 8)?;
+            let (start, end) = match proof {
+                Some(proof) => proof,
+                None => return k.err("an assertion without a proof"),
+            };
+            k.run(start, end)?;
+            let stmt = match (k.pop()?, decl) {
+                (Item::Proof(e), Decl::Thm {..}) | (Item::Expr(e), Decl::Axiom {..}) => e,
+                _ => return k.err("the proof doesn't end with what it proves"),
+            };
+            k.ensure(k.stack.is_empty(), "the proof leaves more than its result")?;
+            let args = k.args.clone();
+            k.match_all(sig.unify, stmt, args, Some(Hyps::Own))
+        }
+    }
+}
+
+#[test]
+fn kernel1() {
+    // `term a: s;` and `def b: s = $ a $;`, with no file around them.
+    struct Tiny(Vec<u8>);
+    impl Env for Tiny {
+        fn bytes(&self) -> &[u8] { &self.0 }
+        fn num_sorts(&self) -> u8 { 1 }
+        fn num_terms(&self) -> u32 { 2 }
+        fn num_thms(&self) -> u32 { 0 }
+        fn sort_mods(&self, sort: u8) -> Option<u8> { if sort == 0 { Some(0) } else { None } }
+        fn term(&self, num: u32) -> Option<TermSig<'_>> {
+            const RET: [u8; 8] = [0; 8];
+            [TermSig { sort: 0, args: &RET, unify: 0 }, TermSig { sort: 0x80, args: &RET, unify: 2 }].get(num as usize).copied()
+        }
+        fn thm(&self, _: u32) -> Option<ThmSig<'_>> { None }
+    }
+    let env = Tiny(vec![PROOF_TERM, 0, UNIFY_TERM, 0]);
+    let mut store = VecStore::default();
+    let def = |num| Decl::Term { num, thms_before: 0 };
+    assert_eq!(check(&env, &mut store, Decl::Sort(0), None), Ok(()));
+    assert_eq!(check(&env, &mut store, def(0), None), Ok(()));
+    assert_eq!(check(&env, &mut store, def(1), Some((0, 2))), Ok(()));
+    assert_eq!(check(&env, &mut store, def(0), Some((0, 2))).unwrap_err().reason, "a term with a proof");
+    assert_eq!(check(&env, &mut store, def(1), Some((0, 1))).unwrap_err().reason, "the proof stream runs past its end");
+    assert_eq!(check(&env, &mut store, def(1), None).unwrap_err().reason, "a def without a proof");
+}
+
+#[test]
+fn kernel_deep1() {
+    // `term a: s;`, `term f: s > s;` and `def d: s = $ f (f (... (f a))) $;`, nested far
+    // deeper than a native stack would allow one frame per level.
+    const DEPTH: usize = 100_000;
+    struct Deep(Vec<u8>);
+    impl Env for Deep {
+        fn bytes(&self) -> &[u8] { &self.0 }
+        fn num_sorts(&self) -> u8 { 1 }
+        fn num_terms(&self) -> u32 { 3 }
+        fn num_thms(&self) -> u32 { 0 }
+        fn sort_mods(&self, sort: u8) -> Option<u8> { if sort == 0 { Some(0) } else { None } }
+        fn term(&self, num: u32) -> Option<TermSig<'_>> {
+            const RET: [u8; 8] = [0; 8];
+            const ARG_RET: [u8; 16] = [0; 16];
+            let unify = 2 * DEPTH + 2;
+            [
+                TermSig { sort: 0, args: &RET, unify: 0 },
+                TermSig { sort: 0, args: &ARG_RET, unify: 0 },
+                TermSig { sort: 0x80, args: &RET, unify },
+            ].get(num as usize).copied()
+        }
+        fn thm(&self, _: u32) -> Option<ThmSig<'_>> { None }
+    }
+    let mut bytes = vec![PROOF_TERM];
+    for _ in 0..DEPTH {
+        bytes.extend_from_slice(&[PROOF_TERM | 0x40, 1]);
+    }
+    bytes.push(0);
+    let proof_end = bytes.len();
+    for _ in 0..DEPTH {
+        bytes.extend_from_slice(&[UNIFY_TERM | 0x40, 1]);
+    }
+    bytes.extend_from_slice(&[UNIFY_TERM, 0]);
+    let env = Deep(bytes);
+    let decl = Decl::Term { num: 2, thms_before: 0 };
+    assert_eq!(check(&env, &mut VecStore::default(), decl, Some((0, proof_end))), Ok(()));
+}
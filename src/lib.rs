@@ -0,0 +1,248 @@
+// Without `std`, only the parts that need nothing but an allocator are built:
+// parsing, the proof and unify interpreters, and checking against in-memory mm0 text.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unreachable_patterns)]
+#![forbid(unused_mut)]
+#![forbid(unused_variables)]
+#![forbid(unused_must_use)]
+#![forbid(unused_imports)]
+// The wasm-bindgen glue, the C API, and the Python bindings need `unsafe`,
+// so they get an exemption in those modules only.
+#![cfg_attr(not(any(feature = "wasm", feature = "capi", feature = "pyo3")), forbid(unsafe_code))]
+#![cfg_attr(any(feature = "wasm", feature = "capi", feature = "pyo3"), deny(unsafe_code))]
+
+#![allow(unused_parens)]
+// Temporary.
+#![allow(dead_code)]
+
+extern crate alloc;
+
+/// The `alloc` items that `std`'s prelude would provide.
+pub(crate) mod prelude {
+    pub use alloc::{ format, vec };
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{ String, ToString };
+    pub use alloc::vec::Vec;
+}
+
+pub mod kernel;
+pub mod util;
+pub mod codes;
+pub mod mmb;
+pub mod mmz;
+pub mod fs;
+pub mod sample;
+pub mod options;
+pub mod stats;
+pub mod arena;
+pub mod json;
+pub mod env;
+pub mod diff;
+pub mod report;
+pub mod checkpoint;
+pub mod certificate;
+pub mod testgen;
+pub mod export;
+#[cfg(feature = "std")]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod serve;
+#[cfg(feature = "std")]
+pub mod lsp;
+#[cfg(feature = "std")]
+pub mod sarif;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod watch;
+#[cfg(feature = "std")]
+pub mod project;
+#[cfg(feature = "std")]
+pub mod reference;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+#[cfg(feature = "sign")]
+pub mod sign;
+#[cfg(feature = "remote")]
+pub mod cbor;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "pyo3")]
+pub mod python;
+
+use core::sync::atomic::{ AtomicUsize, Ordering::Relaxed };
+use bumpalo::Bump;
+#[cfg(feature = "std")]
+use crossbeam_utils::thread;
+use crate::prelude::*;
+use crate::mmz::MmzMem;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::stats::{ ArenaStats, PhaseTimes, DeclTimes };
+pub use crate::util::{ Outline, VerifErr, Res, MAX_BOUND_VARS };
+pub use crate::certificate::{ Certificate, verify };
+
+/// Workers share one `Outline`, recording completed declarations through atomics.
+#[cfg(feature = "std")]
+const _: fn() = || {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<Outline>();
+};
+
+/// Verify the mmz files (if any were supplied) and then the given mmb declarations on one thread.
+pub fn verify_serial<'a>(outline: &'a Outline<'a>, decls: &[(StmtCmd, ProofIter<'a>)]) -> Vec<VerifErr> {
+    let task_counter = AtomicUsize::new(0);
+    let mut errs = if outline.file_data.mmz_files.is_empty() {
+        Vec::new()
+    } else {
+        verify_mmz(outline)
+    };
+    let mut mmb_errs = verify_mmb(outline, decls, &task_counter);
+    errs.append(&mut mmb_errs);
+    outline.finish_checks(decls, &mut errs);
+    errs
+}
+
+#[cfg(feature = "std")]
+pub fn verify_par<'a>(outline: &'a Outline<'a>, decls: &[(StmtCmd, ProofIter<'a>)], num_threads: usize) -> Vec<VerifErr> {
+    let task_counter = AtomicUsize::new(0);
+
+    thread::scope(|sco| {
+        let mut mmb_tasks = Vec::new();
+
+        for _ in 0..num_threads {
+            mmb_tasks.push(sco.spawn(|_| verify_mmb_tagged(outline, decls, &task_counter)));
+        }
+
+        let mut errs = if outline.file_data.mmz_files.is_empty() {
+            Vec::new()
+        } else {
+            match sco.spawn(|_| verify_mmz(outline)).join() {
+                Err(_) => vec![VerifErr::Msg("mmz thread panicked!".to_string())],
+                Ok(errs) => errs,
+            }
+        };
+
+        let mut mmb_errs = Vec::new();
+        for (idx, mmb_task) in mmb_tasks.into_iter().enumerate() {
+            match mmb_task.join() {
+                Err(_) => { errs.push(VerifErr::Msg(format!("mmb thread {} panicked", idx))); },
+                Ok(mut task_errs) => mmb_errs.append(&mut task_errs),
+            }
+        }
+        // So that the output is the same whichever worker got which declaration. The sort
+        // is stable, so a declaration's own errors stay in the order they were found.
+        mmb_errs.sort_by_key(|(idx, _)| *idx);
+        errs.extend(mmb_errs.into_iter().map(|(_, e)| e));
+        outline.finish_checks(decls, &mut errs);
+        errs
+    }).unwrap()
+}
+
+
+// Parsing/verifying the contents of the mmz file is done in serial
+pub fn verify_mmz<'a>(outline: &'a Outline<'a>) -> Vec<VerifErr> {
+    let started = if outline.options.phase_times { crate::options::clock() } else { None };
+    let mut mem = MmzMem::new_from(outline).unwrap();
+    // Stats need fresh arenas to be reproducible.
+    let mut bump = if outline.options.arena_stats { Bump::new() } else { outline.arenas.take() };
+    let mut errs = Vec::new();
+    for (stmt, _proof) in outline.declarations.iter() {
+        if let Err(e) = mem.verify1(&mut bump, *stmt) {
+            errs.push(e);
+        }
+    }
+    if outline.options.arena_stats {
+        let stats = ArenaStats::measure(&mut bump, None);
+        outline.arena_stats.with(|s| s.merge(&stats));
+    }
+
+    mem.check_rest(&mut errs);
+    outline.assert_mmz_done(&mem, &mut errs);
+    outline.arenas.give(bump);
+    if outline.options.phase_times {
+        outline.phase_times.with(|t| t.mmz += crate::options::since(started));
+    }
+    errs
+}
+
+pub fn verify_mmb<'a>(outline: &'a Outline<'a>, decls: &[(StmtCmd, ProofIter<'a>)], task_counter: &AtomicUsize) -> Vec<VerifErr> {
+    verify_mmb_tagged(outline, decls, task_counter).into_iter().map(|(_, e)| e).collect()
+}
+
+/// `verify_mmb`, with each error tagged with the position in `decls` of the declaration
+/// it's about.
+pub fn verify_mmb_tagged<'a>(outline: &'a Outline<'a>, decls: &[(StmtCmd, ProofIter<'a>)], task_counter: &AtomicUsize) -> Vec<(usize, VerifErr)> {
+    // Stats need fresh arenas to be reproducible.
+    let mut bump = if outline.options.arena_stats { Bump::new() } else { outline.arenas.take() };
+    let mut errs = Vec::new();
+    let mut stats = ArenaStats::default();
+    let mut times = PhaseTimes::default();
+    loop {
+        let idx = task_counter.fetch_add(1, Relaxed);
+        let (stmt, proof) = match decls.get(idx) {
+            None => break,
+            Some(decl) => decl,
+        };
+        let started = if outline.options.phase_times { crate::options::clock() } else { None };
+        let mut unify = Default::default();
+        let res = crate::mmb::MmbState::verify1_timed(outline, &mut bump, *stmt, *proof, &mut unify);
+        if outline.options.phase_times && !proof.is_null() {
+            let proof = crate::options::since(started).saturating_sub(unify);
+            times.add_decl(DeclTimes { idx, decl: *stmt, proof, unify });
+        }
+        let disagreement = if outline.options.dual { outline.compare_verdicts(*stmt, *proof, &res) } else { None };
+        // With the `kernel` feature, nothing is accepted that the kernel rejects (with
+        // `dual` that's already a disagreement).
+        let res = match res {
+            Ok(()) if cfg!(feature = "kernel") && !outline.options.dual => outline.recheck(*stmt, *proof),
+            res => res,
+        };
+        if let Err(e) = res {
+            errs.push((idx, e));
+        }
+        errs.extend(disagreement.map(|e| (idx, e)));
+        if outline.options.arena_stats {
+            stats.merge(&ArenaStats::measure(&mut bump, Some(idx)));
+            bump = Bump::new();
+        } else {
+            outline.arenas.shrink(&mut bump);
+        }
+    }
+    if outline.options.arena_stats {
+        outline.arena_stats.with(|s| s.merge(&stats));
+    }
+    if outline.options.phase_times {
+        outline.phase_times.with(|t| t.merge(&times));
+    }
+    outline.arenas.give(bump);
+
+    errs
+}
+
+#[test]
+fn par_order1() {
+    use crate::testgen::{ Fixture, AssertSpec, reg, stream };
+    use crate::mmb::stmt::STMT_THM;
+    use crate::mmb::proof::PROOF_REF;
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF };
+    // Forty theorems `(a: wff): |- im a a`, each "proved" by `a`.
+    let mut fx = Fixture::valid();
+    for _ in 0..40 {
+        fx.asserts.push(AssertSpec { args: vec![reg(0, 0)], unify: stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 0)]) });
+        fx.stmts.push((STMT_THM, stream(&[(PROOF_REF, 0)])));
+    }
+    let errs = |num_threads| fx.with_outline(|outline| {
+        verify_par(outline, &outline.declarations, num_threads).iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>()
+    });
+    let serial = errs(1);
+    assert!(serial.len() >= 40);
+    for _ in 0..5 {
+        assert_eq!(errs(4), serial);
+    }
+}
+
@@ -0,0 +1,262 @@
+//! A Language Server Protocol server that publishes this verifier's verdict on an mm0/mmb
+//! pair as diagnostics, to run next to mm0-rs's own server so that an editor shows any
+//! place where the two disagree.
+//!
+//! It speaks JSON-RPC over stdin/stdout. When an `.mm0` or `.mmb` file is opened or saved
+//! (or the client reports that one changed on disk), the pair with the same name is
+//! re-verified from disk and the errors are published against the `.mm0` file.
+//!
+//! The mmb format doesn't record positions in the mm0 file, but the index records, for each
+//! declaration, the row and column in the file the mmb was compiled from. Errors that
+//! belong to a declaration are reported there, and errors that don't (like a wrong number of
+//! declarations) at the top of the file. The index is only as good as the compiler that
+//! wrote it, so the message also names the declaration.
+
+use std::io::{ BufRead, Write };
+use std::path::{ Path, PathBuf };
+use core::sync::atomic::AtomicUsize;
+use crate::Outline;
+use crate::fs::FileData;
+use crate::json::{ self, Json };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::options::VerifyOptions;
+use crate::util::VerifErr;
+
+/// A verification error, with the declaration it came from and where the index says
+/// that declaration is (0-based, like LSP positions).
+#[derive(Debug)]
+pub struct Located {
+    pub decl: Option<StmtCmd>,
+    pub line: u32,
+    pub col: u32,
+    pub err: VerifErr,
+}
+
+impl<'a> Outline<'a> {
+    fn locate(&self, decl: Option<StmtCmd>, err: VerifErr) -> Located {
+        let entry = decl.and_then(|stmt| self.stmt_index_entry(stmt));
+        Located {
+            decl,
+            line: entry.as_ref().map(|e| e.row).unwrap_or(0),
+            col: entry.as_ref().map(|e| e.col).unwrap_or(0),
+            err,
+        }
+    }
+
+    /// Verify the mmz files and the given declarations on one thread, like `verify_serial`,
+    /// but keep track of which declaration each error came from: the one whose proof
+    /// failed, or the one the error names.
+    pub fn verify_located(&'a self, decls: &[(StmtCmd, ProofIter<'a>)]) -> Vec<Located> {
+        let mut located = Vec::new();
+        if !self.file_data.mmz_files.is_empty() {
+            located.extend(crate::verify_mmz(self).into_iter().map(|e| self.locate(e.decl(), e)));
+        }
+        for (idx, e) in crate::verify_mmb_tagged(self, decls, &AtomicUsize::new(0)) {
+            located.push(self.locate(decls.get(idx).map(|(stmt, _)| *stmt), e));
+        }
+        let mut rest = Vec::new();
+        self.finish_checks(decls, &mut rest);
+        located.extend(rest.into_iter().map(|e| self.locate(e.decl(), e)));
+        located
+    }
+}
+
+/// Read one message, or `None` at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> std::io::Result<Option<Json>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None)
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break
+        }
+        if let Some(n) = header.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let bad = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+    let mut body = vec![0u8; len.ok_or_else(|| bad("missing Content-Length"))?];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| bad("message isn't UTF-8"))?;
+    json::parse(&body).map(Some).map_err(|e| bad(&format!("{:?}", e)))
+}
+
+pub fn write_message(writer: &mut impl Write, msg: &Json) -> std::io::Result<()> {
+    let body = msg.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// The path of a `file:` URI, with its percent-encoding (RFC 3986) undone. The authority
+/// has to be empty or `localhost`.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None
+    }
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(core::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// A `file:` URI for an absolute path, percent-encoding every byte but `/` and the
+/// unreserved characters of RFC 3986, as editors do.
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(b as char),
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// The mm0 and mmb files of the pair that `path` belongs to, if it's either.
+fn pair_of(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    match path.extension()?.to_str()? {
+        "mm0" | "mmb" => Some((path.with_extension("mm0"), path.with_extension("mmb"))),
+        _ => None,
+    }
+}
+
+fn diagnostic(line: u32, col: u32, code: &str, msg: String) -> Json {
+    let pos = Json::obj().with("line", line).with("character", col);
+    Json::obj()
+        .with("range", Json::obj().with("start", pos.clone()).with("end", pos))
+        .with("severity", 1u32)
+        .with("code", code)
+        .with("source", "second-opinion")
+        .with("message", msg)
+}
+
+/// Verify the pair from disk, and describe the errors as LSP diagnostics.
+pub fn diagnostics(mm0: &Path, mmb: &Path, options: VerifyOptions) -> Vec<Json> {
+    let file_data = match FileData::new_from(mmb, Some(mm0)) {
+        Ok(file_data) => file_data,
+        Err(e) => return vec![diagnostic(0, 0, e.code(), format!("couldn't read {}: {:?}", mmb.display(), e))],
+    };
+    let mut outline = match Outline::new_from(&file_data) {
+        Ok(outline) => outline,
+        Err(e) => return vec![diagnostic(0, 0, e.code(), format!("couldn't parse {}: {:?}", mmb.display(), e))],
+    };
+    outline.options = options;
+    outline.verify_located(&outline.declarations)
+        .into_iter()
+        .map(|l| {
+            let msg = match l.decl.and_then(|stmt| outline.stmt_name(stmt)) {
+                Some(name) => format!("second-opinion rejects {}: {:?}", name, l.err),
+                None => format!("second-opinion rejects this file: {:?}", l.err),
+            };
+            diagnostic(l.line, l.col, l.err.code(), msg)
+        })
+        .collect()
+}
+
+/// Answer messages from `reader` until the client says `exit` or hangs up.
+pub fn run(mut reader: impl BufRead, mut writer: impl Write, options: VerifyOptions) -> std::io::Result<()> {
+    while let Some(msg) = read_message(&mut reader)? {
+        let method = msg.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = msg.get("params").cloned().unwrap_or(Json::Null);
+        let mut changed = Vec::new();
+        match method {
+            "initialize" => {
+                let sync = Json::obj().with("openClose", true).with("save", true);
+                let result = Json::obj().with("capabilities", Json::obj().with("textDocumentSync", sync));
+                respond(&mut writer, &msg, result)?;
+            }
+            "shutdown" => respond(&mut writer, &msg, Json::Null)?,
+            "exit" => return Ok(()),
+            "textDocument/didOpen" | "textDocument/didSave" => {
+                changed.extend(params.get("textDocument").and_then(|d| d.get("uri")).and_then(|u| u.as_str()).map(|u| u.to_string()));
+            }
+            "workspace/didChangeWatchedFiles" => {
+                for change in params.get("changes").and_then(|c| c.as_arr()).unwrap_or(&[]) {
+                    changed.extend(change.get("uri").and_then(|u| u.as_str()).map(|u| u.to_string()));
+                }
+            }
+            _ => {
+                // Requests we don't support still need an answer; notifications don't.
+                if msg.get("id").is_some() {
+                    let err = Json::obj().with("code", -32601.0).with("message", format!("unsupported method {}", method));
+                    write_message(&mut writer, &Json::obj().with("jsonrpc", "2.0").with("id", msg.get("id").cloned()).with("error", err))?;
+                }
+            }
+        }
+        for (mm0, mmb) in changed.iter().filter_map(|uri| pair_of(&uri_to_path(uri)?)) {
+            let params = Json::obj()
+                .with("uri", path_to_uri(&mm0))
+                .with("diagnostics", diagnostics(&mm0, &mmb, options));
+            let note = Json::obj()
+                .with("jsonrpc", "2.0")
+                .with("method", "textDocument/publishDiagnostics")
+                .with("params", params);
+            write_message(&mut writer, &note)?;
+        }
+    }
+    Ok(())
+}
+
+fn respond(writer: &mut impl Write, request: &Json, result: Json) -> std::io::Result<()> {
+    let response = Json::obj()
+        .with("jsonrpc", "2.0")
+        .with("id", request.get("id").cloned())
+        .with("result", result);
+    write_message(writer, &response)
+}
+
+#[test]
+fn lsp1() {
+    let mm0 = std::fs::canonicalize("./test_resources/short.mm0").unwrap();
+    let mut input = Vec::new();
+    for msg in [
+        Json::obj().with("jsonrpc", "2.0").with("id", 1u32).with("method", "initialize"),
+        Json::obj().with("jsonrpc", "2.0").with("method", "textDocument/didOpen")
+            .with("params", Json::obj().with("textDocument", Json::obj().with("uri", path_to_uri(&mm0)))),
+        Json::obj().with("jsonrpc", "2.0").with("id", 2u32).with("method", "shutdown"),
+        Json::obj().with("jsonrpc", "2.0").with("method", "exit"),
+    ].iter() {
+        write_message(&mut input, msg).unwrap();
+    }
+    let mut output = Vec::new();
+    run(input.as_slice(), &mut output, VerifyOptions::default()).unwrap();
+    let mut output = output.as_slice();
+    let mut msgs = Vec::new();
+    while let Some(msg) = read_message(&mut output).unwrap() {
+        msgs.push(msg);
+    }
+    assert_eq!(msgs.len(), 3);
+    assert!(msgs[0].get("result").and_then(|r| r.get("capabilities")).is_some());
+    let params = msgs[1].get("params").unwrap();
+    assert_eq!(params.get("uri").and_then(|u| u.as_str()), Some(path_to_uri(&mm0).as_str()));
+    assert_eq!(params.get("diagnostics").and_then(|d| d.as_arr()).map(|d| d.len()), Some(0));
+
+    let mut fx = crate::testgen::Fixture::valid();
+    fx.stmts.pop();
+    fx.with_outline(|outline| assert!(!outline.verify_located(&outline.declarations).is_empty()));
+}
+
+#[test]
+fn uri1() {
+    let path = Path::new("/home/a b/100%/ü#.mm0");
+    let uri = path_to_uri(path);
+    assert_eq!(uri, "file:///home/a%20b/100%25/%C3%BC%23.mm0");
+    assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+    assert_eq!(uri_to_path("file://localhost/a%2fb").as_deref(), Some(Path::new("/a/b")));
+    assert!(uri_to_path("file:///a%2").is_none());
+    assert!(uri_to_path("file://host/a").is_none());
+    assert!(uri_to_path("https://example.com/a").is_none());
+}
@@ -6,30 +6,56 @@
 #![forbid(unused_imports)]
 
 #![allow(unused_parens)]
-// Temporary.
-#![allow(dead_code)]
-
-mod util;
-mod mmb;
-mod mmz;
-mod fs;
-
-use std::path::PathBuf;
-use std::sync::atomic::{ AtomicUsize, Ordering::Relaxed };
-use bumpalo::Bump;
-use clap::{ Arg, App };
-use crossbeam_utils::thread;
-use crate::mmz::MmzMem;
-use crate::util::VerifErr;
-use crate::fs::FileData;
-use crate::util::Outline;
+
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
+use clap::{ Arg, App, AppSettings, ArgMatches, SubCommand };
+use second_opinion::fs::FileData;
+use second_opinion::util::Outline;
+use second_opinion::export::rename::{ RenamePolicy, Case };
+use second_opinion::mmb::stmt::StmtCmd;
+use second_opinion::mmb::proof::ProofIter;
+use second_opinion::options::VerifyOptions;
+use second_opinion::arena::BumpPool;
+use second_opinion::serve::Server;
+use second_opinion::watch::Incremental;
+use second_opinion::{ verify_serial, verify_par, VerifErr, io_err };
 
 fn main() {
-    let start = std::time::Instant::now();
-    let matches = App::new("Verifier")
+    let matches = app().get_matches();
+
+    if let Some(code) = matches.value_of("explain") {
+        return explain(code)
+    }
+
+    let num_threads = parse_arg::<usize>(&matches, "num_threads", "The number of threads must be a natural number").unwrap_or(1).max(1);
+    let options = verify_options(&matches);
+    let arenas = BumpPool::new(
+        parse_arg(&matches, "arena_capacity", "The arena capacity must be a natural number of bytes").unwrap_or(0),
+        parse_arg(&matches, "arena_high_water", "The arena high-water mark must be a natural number of bytes"),
+    );
+
+    match matches.subcommand() {
+        ("remote-serve", Some(sub)) => remote_serve(sub, options, num_threads, arenas),
+        ("serve", Some(sub)) => serve(sub, options, num_threads, arenas),
+        ("watch", Some(sub)) => watch_command(sub, options),
+        ("check-project", Some(sub)) => check_project(sub, options, num_threads),
+        ("verify-all", Some(sub)) => verify_all(sub, options, num_threads),
+        ("lsp", Some(_)) => or_fail(second_opinion::lsp::run(std::io::stdin().lock(), std::io::stdout(), options), "run the language server"),
+        ("export-sexp", Some(sub)) => export_sexp(sub),
+        ("keygen", Some(sub)) => keygen(sub),
+        ("check-signature", Some(sub)) => check_signature(sub, &matches, options),
+        ("trust", Some(sub)) => trust(sub),
+        _ => verify(&matches, options, num_threads, arenas),
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Verifier")
         .version("0.1")
         .author("ammkrn@tuta.io")
         .about("A metamath zero verifier")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("num_threads")
             .short("t")
@@ -37,10 +63,419 @@ fn main() {
             .help("specify the number of threads to use")
             .takes_value(true)
         )
+        .arg(
+            Arg::with_name("sample")
+            .long("sample")
+            .value_name("p")
+            .help("only verify a random subset of declarations (each picked with probability p) and their dependencies")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("seed")
+            .long("seed")
+            .help("the seed to use with --sample")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("timeout")
+            .long("timeout")
+            .value_name("ms")
+            .help("fail any single declaration that takes longer than this many milliseconds")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max_steps")
+            .long("max-steps")
+            .value_name("n")
+            .help("fail any single declaration that executes more than n proof/unify commands")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max_memory")
+            .long("max-memory")
+            .value_name("bytes")
+            .help("fail any single declaration that needs more than this many bytes of arena")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max_expr_size")
+            .long("max-expr-size")
+            .value_name("nodes")
+            .help("fail any single declaration whose proof builds an expression with more than this many nodes (as a tree)")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max_expr_depth")
+            .long("max-expr-depth")
+            .value_name("levels")
+            .help("fail any single declaration whose proof builds an expression more than this many levels deep")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("dump_env")
+            .long("dump-env")
+            .value_name("json file")
+            .help("write a JSON description of the declared sorts, terms, and assertions")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("compare_env")
+            .long("compare-env")
+            .value_name("json file")
+            .help("compare the declared environment against a JSON dump produced by another tool")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("arena_stats")
+            .long("arena-stats")
+            .help("report how much arena memory verification needed")
+        )
+        .arg(
+            Arg::with_name("timings")
+            .long("timings")
+            .help("report how long parsing the header and tables, parsing the index, checking the mm0 files, running proofs and unifying took")
+        )
+        .arg(
+            Arg::with_name("outlier_factor")
+            .long("outlier-factor")
+            .value_name("n")
+            .takes_value(true)
+            .help("with --timings, flag the declarations that took more than n times the median (default 10)")
+        )
+        .arg(
+            Arg::with_name("verbose")
+            .long("verbose")
+            .short("v")
+            .help("with --timings, also report the proof and unification time of each declaration")
+        )
+        .arg(
+            Arg::with_name("hash_cons")
+            .long("hash-cons")
+            .help("share one allocation between identical subexpressions in a proof")
+        )
+        .arg(
+            Arg::with_name("strict")
+            .long("strict")
+            .help("also reject nonzero reserved fields, junk after the proof stream, unused table slots, and oversized backreferences")
+        )
+        .arg(
+            Arg::with_name("unify_delta")
+            .long("unify-delta")
+            .help("when unification fails, report the path to the first mismatching subterm and the conflicting heads")
+        )
+        .arg(
+            Arg::with_name("dual")
+            .long("dual")
+            .help("check every proof again with a second, separately written checker, and report where the two disagree")
+        )
+        .arg(
+            Arg::with_name("keep_going")
+            .long("keep-going")
+            .help("also report every declaration that uses one that failed, directly or indirectly")
+        )
+        .arg(
+            Arg::with_name("allow_axioms")
+            .long("allow-axioms")
+            .value_name("axioms")
+            .help("fail if a theorem uses an axiom (by name or number) not in this comma-separated list")
+            .takes_value(true)
+            .use_delimiter(true)
+            .multiple(true)
+        )
+        .arg(
+            Arg::with_name("deny_axioms")
+            .long("deny-axioms")
+            .value_name("axioms")
+            .help("fail if a theorem uses any axiom (by name or number) in this comma-separated list, like --deny-axioms ax_ac")
+            .takes_value(true)
+            .use_delimiter(true)
+            .multiple(true)
+        )
+        .arg(
+            Arg::with_name("arena_capacity")
+            .long("arena-capacity")
+            .value_name("bytes")
+            .help("the initial size of each worker's arena")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("arena_high_water")
+            .long("arena-high-water")
+            .value_name("bytes")
+            .help("replace a worker's arena with a fresh one once it grows past this size")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("optimize")
+            .long("optimize")
+            .value_name("mmb file")
+            .help("after a successful run, write a smaller copy of the mmb file with compactly re-encoded proofs, checked before it's written")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("compress")
+            .long("compress")
+            .value_name("gzip|zstd")
+            .help("compress the --optimize output (needs the `compress` feature)")
+            .takes_value(true)
+            .possible_values(&["gzip", "zstd"])
+            .requires("optimize")
+        )
+        .arg(
+            Arg::with_name("tables")
+            .long("tables")
+            .value_name("eager|lazy|off")
+            .help("read the term and theorem tables up front (eager, the default), keep entries once they're first used (lazy), or read an entry on every use (off)")
+            .takes_value(true)
+            .possible_values(&["eager", "lazy", "off"])
+        )
+        .arg(
+            Arg::with_name("report")
+            .long("report")
+            .value_name("json file")
+            .help("after a successful run, write a canonical report of what was verified, with hashes")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("remote")
+            .long("remote")
+            .value_name("address")
+            .help("have the verification server at this address (see remote-serve) verify the files instead, and print its verdict (needs the `remote` feature)")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("sign")
+            .long("sign")
+            .value_name("secret key file")
+            .help("sign the --report with this ed25519 key, written as hex (needs the `sign` feature)")
+            .takes_value(true)
+            .requires("report")
+        )
+        .arg(
+            Arg::with_name("sarif")
+            .long("sarif")
+            .value_name("sarif file")
+            .help("write the errors as a SARIF log, located in the mm0 and mmb files, for code scanning tools (verifies on one thread)")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("cache")
+            .long("cache")
+            .help("skip the file if it passed before with the same settings, and record the verdict if it passes now")
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .value_name("json file")
+            .help("periodically save progress to this file, and resume from it if it already exists")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("checkpoint_every")
+            .long("checkpoint-every")
+            .value_name("n")
+            .help("with --checkpoint, save progress after every n declarations (default 10000)")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("write_index")
+            .long("write-index")
+            .value_name("mmb file")
+            .help("write a copy of an mmb file that has no index, with an index built from the mm0 file's names")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("slice")
+            .long("slice")
+            .value_names(&["name", "mmb file"])
+            .help("write a smaller mmb file with just the named declaration and its dependencies")
+            .takes_value(true)
+            .number_of_values(2)
+        )
+        .arg(
+            Arg::with_name("diff")
+            .long("diff")
+            .value_name("old mmb file")
+            .help("list the declarations added, removed, or changed since an older version of the mmb file")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("link")
+            .long("link")
+            .value_names(&["output file", "mmb files"])
+            .help("link the mmb file with other mmb files, sharing the declarations they have in common, and write the result")
+            .takes_value(true)
+            .min_values(2)
+        )
+        .arg(
+            Arg::with_name("sort_mods")
+            .long("sort-mods")
+            .help("report where each sort modifier constrains the declarations, and which modifiers could be changed")
+        )
+        .arg(
+            Arg::with_name("lint")
+            .long("lint")
+            .help("report wasteful patterns in the proof and unify streams")
+        )
+        .arg(
+            Arg::with_name("coverage")
+            .long("coverage")
+            .help("report unreferenced heap entries and unused saves in each proof, and the bytes dropping them would save")
+        )
+        .arg(
+            Arg::with_name("profile")
+            .long("profile")
+            .help("count the proof and unify commands run and time each declaration, and print the hot spots")
+        )
+        .arg(
+            Arg::with_name("dump")
+            .long("dump")
+            .help("print the mmb file's header, sort table, term table, and theorem table with their offsets")
+        )
+        .arg(
+            Arg::with_name("dump_defs")
+            .long("dump-defs")
+            .help("print every term and def in mm0 syntax, with def bodies read back from their unify streams")
+        )
+        .arg(
+            Arg::with_name("regions")
+            .long("regions")
+            .help("print which byte ranges of the mmb file belong to the header, each table, each entry's data, the proof stream, and the index")
+        )
+        .arg(
+            Arg::with_name("disasm")
+            .long("disasm")
+            .value_name("name")
+            .help("print the unify and proof streams of the named declaration, one command per line with offsets and raw bytes")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("reference")
+            .long("reference")
+            .value_name("command")
+            .help("also run another verifier, like \"mm0-c {mmb}\", and report whether it reaches the same verdict")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("proof_tree")
+            .long("proof-tree")
+            .value_name("name")
+            .help("print the named theorem's proof as a tree of assertion applications")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("trace")
+            .long("trace")
+            .value_name("name")
+            .help("print the stacks and heaps after every step of verifying the named axiom or theorem")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("trace_html")
+            .long("trace-html")
+            .value_name("html file")
+            .help("write the --trace as an HTML table instead of printing it")
+            .takes_value(true)
+            .requires("trace")
+        )
+        .arg(
+            Arg::with_name("html_report")
+            .long("html-report")
+            .value_name("directory")
+            .help("write an HTML page for each declaration to this directory, with its statement, status, time, axioms and dependencies")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("dedukti")
+            .long("dedukti")
+            .value_name("dk file")
+            .help("after a successful run, write the sorts, terms, and assertions with their proofs as a Dedukti file")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("tptp")
+            .long("tptp")
+            .value_name("directory")
+            .help("after a successful run, write a TPTP problem <name>.p for each theorem to this directory")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("opentheory")
+            .long("opentheory")
+            .value_name("article file")
+            .help("after a successful run, write the axioms and the theorems with their proofs as an OpenTheory article")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("opentheory_map")
+            .long("opentheory-map")
+            .value_name("mapping file")
+            .help("name sorts and terms in the OpenTheory article with lines `sort <name> <type operator>` and `term <name> <constant>`")
+            .takes_value(true)
+            .requires("opentheory")
+        )
+        .arg(
+            Arg::with_name("lean")
+            .long("lean")
+            .value_name("lean file")
+            .help("after a successful run, write the statements as Lean 4 axioms and `sorry` theorems")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("lean_notation")
+            .long("lean-notation")
+            .value_name("notation file")
+            .help("show sorts and terms in the Lean file with lines `sort <name> <lean type>` and `term <name> <template>`")
+            .takes_value(true)
+            .requires("lean")
+        )
+        .arg(
+            Arg::with_name("rename_prefix")
+            .long("rename-prefix")
+            .value_name("prefix")
+            .help("put this in front of every name in the Dedukti, TPTP, OpenTheory and Lean exports")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("rename_case")
+            .long("rename-case")
+            .value_name("case")
+            .help("convert every name in the Dedukti, TPTP, OpenTheory and Lean exports to this case")
+            .possible_values(&["keep", "lower", "upper", "snake", "camel", "pascal"])
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("tptp_lemmas")
+            .long("tptp-lemmas")
+            .help("give each TPTP problem the theorems before it as axioms too")
+            .requires("tptp")
+        )
+        .arg(
+            Arg::with_name("deny")
+            .long("deny")
+            .value_name("warnings")
+            .possible_values(&["warnings"])
+            .help("fail if there are any warnings (unused hypotheses and local theorems, empty sorts, duplicate defs)")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("explain")
+            .long("explain")
+            .value_name("code")
+            .help("say what an error code (like E0203) means and which rule of the format it's about, then exit")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("sha256")
+            .long("sha256")
+            .value_name("hex")
+            .help("with the `fetch` feature, the SHA-256 the mmb file downloaded from a URL has to have")
+            .takes_value(true)
+        )
         .arg(
             Arg::with_name("mmb_file")
             .value_name("mmb file")
-            .required(true)
+            .required_unless("explain")
             .takes_value(true)
         )
         .arg(
@@ -49,106 +484,1116 @@ fn main() {
             .required(false)
             .takes_value(true)
         )
-        .get_matches();
-                            
+        .subcommand(
+            serve_command()
+        )
+        .subcommand(
+            SubCommand::with_name("remote-serve")
+            .about("stay running and verify mmb files sent over TCP as length-prefixed CBOR, answering with certificates (needs the `remote` feature)")
+            .arg(
+                Arg::with_name("address")
+                .value_name("address")
+                .help("the TCP address to listen on, like 0.0.0.0:7778")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("max_message")
+                .long("max-message")
+                .value_name("bytes")
+                .help("refuse requests larger than this (default 64 MiB)")
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+            .about("re-verify an mmb file whenever it (or its mm0 file) changes, printing a line per run")
+            .arg(
+                Arg::with_name("interval")
+                .long("interval")
+                .value_name("ms")
+                .help("how often to check whether the files changed (default 500)")
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("mmb_file")
+                .value_name("mmb file")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("mmz_file")
+                .value_name("mmz file")
+                .required(false)
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("check-project")
+            .about("verify every mm0/mmb pair listed in a manifest, and report on all of them together")
+            .arg(
+                Arg::with_name("json")
+                .long("json")
+                .help("print the report as one JSON object instead of a line per file")
+            )
+            .arg(
+                Arg::with_name("manifest")
+                .value_name("manifest")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("verify-all")
+            .about("verify every mmb file in a directory tree whose path matches a glob, in parallel across files, and print a summary table")
+            .arg(
+                Arg::with_name("glob")
+                .long("glob")
+                .value_name("pattern")
+                .takes_value(true)
+                .help("which files to verify, relative to the directory: `**` is any number of directories, `*` any part of a name and `?` one character of it (default **/*.mmb)")
+            )
+            .arg(
+                Arg::with_name("json")
+                .long("json")
+                .help("print the report as one JSON object instead of a table")
+            )
+            .arg(
+                Arg::with_name("dir")
+                .value_name("directory")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("lsp")
+            .about("run a language server on stdin/stdout that publishes verification errors in mm0/mmb pairs as diagnostics")
+        )
+        .subcommand(
+            SubCommand::with_name("export-sexp")
+            .about("print every sort, term, def, and assertion in an mmb file as an s-expression, without verifying anything")
+            .arg(
+                Arg::with_name("mmb_file")
+                .value_name("mmb file")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("trust")
+            .about("list the sorts and axioms of an mmb file, which are everything it takes on faith, without verifying anything")
+            .arg(
+                Arg::with_name("mmb_file")
+                .value_name("mmb file")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("mm0_file")
+                .value_name("mm0 file")
+                .help("write the statements in this mm0 file's notation")
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("keygen")
+            .about("make an ed25519 key for signing reports, write its secret half to a file as hex, and print its public half (needs the `sign` feature)")
+            .arg(
+                Arg::with_name("secret_key_file")
+                .value_name("secret key file")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(
+            SubCommand::with_name("check-signature")
+            .about("check that a signed report was signed by a trusted key, and if an mmb file is given, that it says that file verified against the mm0 file, with --strict, --dual and the axiom policy if they're given (needs the `sign` feature)")
+            .arg(
+                Arg::with_name("signed_report")
+                .value_name("signed report")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("public_key_file")
+                .value_name("public key file")
+                .help("the trusted public keys, one per line as hex")
+                .required(true)
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("mmb_file")
+                .value_name("mmb file")
+                .takes_value(true)
+            )
+            .arg(
+                Arg::with_name("mm0_file")
+                .value_name("mm0 file")
+                .takes_value(true)
+            )
+        )
+}
 
-    let num_threads = match matches.value_of("num_threads") {
-        None => 1,
-        Some(s) => match s.parse::<usize>() {
-            Ok(0) => 1,
-            Ok(n) => n,
-            Err(_) => panic!("The number of threads must be a natural number. got {}", s)
+/// Bad input isn't a bug: say what was wrong and exit with status 2, so it can't be
+/// mistaken for a file that failed to verify (status 1).
+fn fail(msg: impl std::fmt::Display) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(2)
+}
+
+/// The value of `res`, or fail saying what couldn't be done.
+fn or_fail<T, E: std::fmt::Debug>(res: Result<T, E>, what: impl std::fmt::Display) -> T {
+    res.unwrap_or_else(|e| fail(format!("Couldn't {}: {:?}", what, e)))
+}
+
+/// The value of the flag `name`, or fail with `msg` if it doesn't parse.
+fn parse_arg<T: std::str::FromStr>(matches: &ArgMatches, name: &str, msg: &str) -> Option<T> {
+    matches.value_of(name).map(|s| s.parse().unwrap_or_else(|_| fail(format!("{}. got {}", msg, s))))
+}
+
+/// `parse_arg`, for a number that has to be positive.
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(matches: &ArgMatches, name: &str, msg: &str) -> Option<T> {
+    matches.value_of(name).map(|s| match s.parse::<T>() {
+        Ok(n) if n > T::default() => n,
+        _ => fail(format!("{}. got {}", msg, s)),
+    })
+}
+
+fn explain(code: &str) {
+    match second_opinion::codes::explain(code) {
+        Some(c) => println!("{}: {}\n\n{}", c.code, c.title, c.rule),
+        None => {
+            let codes = second_opinion::codes::CODES.iter().map(|c| c.code).collect::<Vec<_>>();
+            fail(format!("No error code {}. The codes are {}", code, codes.join(", ")))
         }
-    };
+    }
+}
 
-    // Safe to unwrap since this is required by the clap app.
-    let mmb_path = matches.value_of("mmb_file").map(|s| PathBuf::from(s)).unwrap();
-    let mmz_path = matches.value_of("mmz_file").map(|s| PathBuf::from(s));
+fn verify_options(matches: &ArgMatches) -> VerifyOptions {
+    VerifyOptions {
+        timeout: parse_arg(matches, "timeout", "The timeout must be a natural number of milliseconds").map(Duration::from_millis),
+        max_steps: parse_arg(matches, "max_steps", "The step limit must be a natural number"),
+        max_memory: parse_arg(matches, "max_memory", "The memory limit must be a natural number of bytes"),
+        max_expr_size: parse_arg(matches, "max_expr_size", "The expression size limit must be a natural number"),
+        max_expr_depth: parse_arg(matches, "max_expr_depth", "The expression depth limit must be a natural number"),
+        arena_stats: matches.is_present("arena_stats"),
+        hash_cons: matches.is_present("hash_cons"),
+        strict: matches.is_present("strict"),
+        unify_delta: matches.is_present("unify_delta"),
+        dual: matches.is_present("dual"),
+        keep_going: matches.is_present("keep_going"),
+        // The HTML report shows each declaration's time.
+        phase_times: matches.is_present("timings") || matches.is_present("html_report"),
+    }
+}
 
+/// Verify the mmb file, with whatever the flags ask for before and after.
+fn verify(matches: &ArgMatches, options: VerifyOptions, num_threads: usize, arenas: BumpPool) {
+    let start = Instant::now();
+    let sample_p = parse_arg::<f64>(matches, "sample", "The sample probability must be a number in (0, 1]");
+    let seed = parse_arg(matches, "seed", "The seed must be a natural number").unwrap_or_else(second_opinion::sample::fresh_seed);
+    let checkpoint_every = parse_positive(matches, "checkpoint_every", "The checkpoint interval must be a positive number of declarations").unwrap_or(10_000);
+    let outlier_factor = parse_positive(matches, "outlier_factor", "The outlier factor must be a positive natural number").unwrap_or(second_opinion::stats::OUTLIER_FACTOR);
 
-    let file_data = FileData::new_from(mmb_path, mmz_path).unwrap();
-    let outline = Outline::new_from(&file_data).unwrap();
+    // Safe to unwrap since this is required by the clap app unless there's a subcommand.
+    let mmb_path = matches.value_of("mmb_file").map(PathBuf::from).unwrap();
+    let mmz_path = matches.value_of("mmz_file").map(PathBuf::from);
+
+    let file_data = load(matches, &mmb_path, mmz_path.as_ref());
+    if let Some(addr) = matches.value_of("remote") {
+        if !remote_verify(addr, &file_data) {
+            std::process::exit(1)
+        }
+        return
+    }
+
+    let mut outline = or_fail(Outline::new_from(&file_data), format_args!("parse {}", mmb_path.display()));
+    match matches.value_of("tables") {
+        Some("off") => {}
+        Some("lazy") => outline.cache_tables(),
+        _ => or_fail(outline.load_tables(num_threads), "read the tables"),
+    }
+    outline.options = options;
+    outline.arenas = arenas;
+    outline.axiom_policy = axiom_policy(matches);
+    if !outline.file_data.mmz_files.is_empty() {
+        outline.notations = Some(outline.read_notations());
+    }
+    let outline = outline;
+
+    check_layout(&outline);
+    tools(matches, &outline);
+
+    let sample = sample_p.map(|p| or_fail(outline.sample(p, seed), "pick a sample"));
+    let decls = match &sample {
+        None => outline.declarations.as_slice(),
+        Some(sample) => sample.declarations.as_slice(),
+    };
+
+    // Only plain runs of the whole file are cached: anything that reports on the run
+    // itself, or vouches for it, needs the run.
+    let uncacheable = ["checkpoint", "sarif", "html_report", "timings", "arena_stats", "report", "sign"];
+    let cache = match matches.is_present("cache") && sample.is_none() && !uncacheable.iter().any(|f| matches.is_present(f)) {
+        true => second_opinion::cache::Cache::default_dir().map(second_opinion::cache::Cache::new),
+        false => None,
+    };
+    // Eager tables check every entry up front, so they can fail a file the others pass.
+    let cache_settings = format!("deny={:?} tables={:?}", matches.value_of("deny"), matches.value_of("tables").unwrap_or("eager"));
+    let cached = cache.as_ref().map(|c| c.lookup(&outline, &cache_settings)).unwrap_or(false);
+
+    // Where a checkpointed run picked up, so the summary doesn't claim it checked everything.
+    let mut resumed_at = None;
 
     // Now that all the file IO is done, we can confidently begin verification.
-    let errs = if num_threads == 1 {
-        verify_serial(&outline)
+    let mut errs = if cached {
+        println!("* this file passed before with the same settings; not verifying it again (leave out --cache to check it anyway)");
+        Vec::new()
+    } else if let Some(path) = matches.value_of("checkpoint") {
+        if num_threads != 1 || sample.is_some() {
+            fail("--checkpoint only works with a single thread and without --sample")
+        }
+        checkpointed(&outline, path, checkpoint_every, &mut resumed_at)
+    } else if let Some(path) = matches.value_of("sarif") {
+        sarif(&outline, decls, path, &mmb_path, mmz_path.as_deref())
+    } else if num_threads == 1 {
+        verify_serial(&outline, decls)
     } else {
-        verify_par(&outline, num_threads)
+        verify_par(&outline, decls, num_threads)
     };
 
+    if outline.options.arena_stats {
+        arena_stats(&outline, decls);
+    }
+
+    if matches.is_present("timings") {
+        timings(matches, &outline, outlier_factor);
+    }
+
+    let warnings = outline.all_warnings();
+    for w in warnings.iter() {
+        println!("warning: {}", w);
+    }
+    if matches.value_of("deny") == Some("warnings") {
+        errs.extend(warnings.into_iter().map(VerifErr::Warning));
+    }
+
+    if let (Some(path), true) = (matches.value_of("report"), errs.is_empty()) {
+        // Only a run that checked every proof itself has anything to sign.
+        let verified_here = !cached && resumed_at.is_none();
+        if let Err(e) = write_report(matches, &outline, decls, path, resumed_at, verified_here) {
+            errs.push(e);
+        }
+    }
+
+    if let Some(dir) = matches.value_of("html_report") {
+        html_report(&outline, decls, dir);
+    }
+
+    if errs.is_empty() {
+        exports(matches, &outline);
+    }
+
+    if let Some(cmd) = matches.value_of("reference") {
+        let mmz_path = mmz_path.clone().unwrap_or_else(|| second_opinion::compress::default_mm0_path(&mmb_path));
+        let theirs = or_fail(second_opinion::reference::run_reference(cmd, &mmb_path, &mmz_path), "run the reference verifier");
+        let comparison = second_opinion::reference::compare(errs.is_empty(), theirs);
+        println!("{}", comparison);
+        errs.extend(comparison.err());
+    }
+
+    if let (Some(cache), true, false) = (&cache, errs.is_empty(), cached) {
+        if let Err(e) = cache.store(&outline, &cache_settings) {
+            println!("* couldn't record the verdict in {}: {:?}", cache.dir.display(), e);
+        }
+    }
+
     if let Some((e, es)) = errs.split_last() {
-        println!("verification was unsuccessful. Terminated with error {:?}\n + {} other errors", e, es.len());
+        println!("verification was unsuccessful. Terminated with error {} {:?}\n + {} other errors", e.code(), e, es.len());
+        std::process::exit(1)
+    } else if let Some(sample) = sample {
+        println!(
+            "\n* PARTIAL: spot-checked {} of {} declarations ({} picked at random with seed {}, plus dependencies) in {}ms",
+            sample.declarations.len(),
+            outline.declarations.len(),
+            sample.num_picked,
+            sample.seed,
+            start.elapsed().as_millis()
+        );
+    } else if let Some(next_decl) = resumed_at {
+        println!(
+            "\n* RESUMED: verified {} sorts, {} terms, and {} assertions in {}ms, picking up at declaration {} of {} from a checkpoint",
+            outline.header.num_sorts,
+            outline.header.num_terms,
+            outline.header.num_thms,
+            start.elapsed().as_millis(),
+            next_decl,
+            outline.declarations.len()
+        );
     } else {
         println!(
-            "\n* verified {} sorts, {} terms, and {} assertions in {}ms", 
-            outline.header.num_sorts, 
-            outline.header.num_terms, 
-            outline.header.num_thms, 
+            "\n* verified {} sorts, {} terms, and {} assertions in {}ms",
+            outline.header.num_sorts,
+            outline.header.num_terms,
+            outline.header.num_thms,
             start.elapsed().as_millis()
         );
     }
 }
 
-fn verify_serial<'a>(outline: &'a Outline<'a>) -> Vec<VerifErr> {
-    let task_counter = AtomicUsize::new(0);
-    let (mut errs) = verify_mmz(outline);
-    let mut mmb_errs = verify_mmb(outline, &task_counter);
-    errs.append(&mut mmb_errs);
-    outline.assert_mmb_done(&mut errs);
+/// Read the files named on the command line: `-` is standard input, for pipelines that
+/// generate the mmb file, and with the `fetch` feature either can be a URL.
+fn load(matches: &ArgMatches, mmb_path: &Path, mmz_path: Option<&PathBuf>) -> FileData {
+    if matches.value_of("mmb_file") == Some("-") {
+        if matches.is_present("reference") || matches.is_present("sha256") {
+            fail("--reference and --sha256 need the mmb file on disk or at a URL, not on standard input")
+        }
+        return or_fail(FileData::new_from_reader(std::io::stdin().lock(), mmz_path), "read the mmb file from standard input")
+    }
+    #[cfg(feature = "fetch")]
+    let data = second_opinion::fetch::load(
+        // Safe to unwrap since this is required by the clap app unless there's a subcommand.
+        matches.value_of("mmb_file").unwrap(),
+        matches.value_of("mmz_file"),
+        matches.value_of("sha256"),
+    );
+    #[cfg(not(feature = "fetch"))]
+    let data = {
+        let is_url = |s: &str| s.starts_with("http://") || s.starts_with("https://");
+        if matches.value_of("mmb_file").into_iter().chain(matches.value_of("mmz_file")).any(is_url) || matches.is_present("sha256") {
+            fail("reading files from URLs (and --sha256) needs the `fetch` feature: cargo build --release --features fetch")
+        }
+        FileData::new_from(mmb_path, mmz_path)
+    };
+    or_fail(data, format_args!("read {}", mmb_path.display()))
+}
+
+/// Report problems with the index and the layout of the file. Neither affects the proofs.
+fn check_layout(outline: &Outline) {
+    let index_errs = outline.check_index();
+    for e in index_errs.iter() {
+        println!("index error: {}", e);
+    }
+    if !index_errs.is_empty() {
+        println!("* the index has {} problems; names reported below may be wrong", index_errs.len());
+    }
+
+    // Layout problems usually explain whatever verification says next.
+    let region_errs = outline.check_regions();
+    for e in region_errs.iter() {
+        println!("layout error: {}", e);
+    }
+    if !region_errs.is_empty() {
+        println!("* the file layout has {} problems; expect confusing errors below", region_errs.len());
+    }
+}
+
+/// The flags that write or print something about the file before it's verified.
+fn tools(matches: &ArgMatches, outline: &Outline) {
+    if let Some(path) = matches.value_of("write_index") {
+        let with_index = or_fail(outline.mmz_names().and_then(|names| outline.with_new_index(&names)), "build an index");
+        write(path, with_index);
+        println!("* wrote a copy with a new index to {}", path);
+    }
+
+    if let Some(mut args) = matches.values_of("slice") {
+        // Safe to unwrap since clap makes sure there are two values.
+        let (name, path) = (args.next().unwrap(), args.next().unwrap());
+        let root = outline.declarations
+            .iter()
+            .position(|(stmt, _)| outline.stmt_name(*stmt) == Some(name))
+            .unwrap_or_else(|| fail(format!("No declaration named {} in the index", name)));
+        write(path, or_fail(outline.slice(&[root]), format_args!("slice {}", name)));
+        println!("* wrote {} and its dependencies to {}", name, path);
+    }
 
+    if let Some(path) = matches.value_of("diff") {
+        diff(outline, path);
+    }
+
+    if let Some(mut args) = matches.values_of("link") {
+        // Safe to unwrap since clap makes sure there are at least two values.
+        let out_path = args.next().unwrap();
+        link(outline, out_path, args.collect());
+    }
+
+    if let Some(path) = matches.value_of("dump_env") {
+        write(path, or_fail(outline.env_dump(), "describe the environment").pretty());
+    }
+
+    if let Some(path) = matches.value_of("compare_env") {
+        compare_env(outline, path);
+    }
+
+    if matches.is_present("dump") {
+        print!("{}", or_fail(outline.dump_tables(), "dump the tables"));
+    }
+
+    if matches.is_present("dump_defs") {
+        print!("{}", or_fail(outline.dump_defs(), "dump the defs"));
+    }
+
+    if matches.is_present("regions") {
+        for region in outline.regions() {
+            println!("{}", region);
+        }
+    }
+
+    if let Some(name) = matches.value_of("disasm") {
+        let (stmt, proof) = find_decl(outline, name);
+        print!("{}", or_fail(outline.disasm(*stmt, *proof), format_args!("disassemble {}", name)));
+    }
+
+    if let Some(name) = matches.value_of("proof_tree") {
+        let (stmt, proof) = find_decl(outline, name);
+        let mut bump = bumpalo::Bump::new();
+        let tree = or_fail(outline.proof_tree(&mut bump, *stmt, *proof), format_args!("build the proof tree of {}", name));
+        println!("{}", outline.tree_sexpr(tree));
+    }
+
+    if let Some(name) = matches.value_of("trace") {
+        trace(matches, outline, name);
+    }
+
+    if matches.is_present("lint") {
+        lint(outline);
+    }
+
+    if matches.is_present("coverage") {
+        coverage(outline);
+    }
+
+    if matches.is_present("profile") {
+        let profile = or_fail(outline.profile(), "profile the file");
+        print!("{}", profile.hot_spots(outline, 10));
+        println!("* profile: {} declarations in {}ms", profile.decls.len(), profile.total_time().as_millis());
+    }
+
+    if matches.is_present("sort_mods") {
+        sort_mods(outline);
+    }
+}
+
+/// Write `contents` to `path`, or fail.
+fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) {
+    or_fail(std::fs::write(path.as_ref(), contents), format_args!("write {}", path.as_ref().display()))
+}
+
+/// Read the mmb file at `path`, or fail.
+fn read_mmb(path: &str) -> FileData {
+    FileData::new_from_bytes(or_fail(std::fs::read(path), format_args!("read {}", path)), Vec::new())
+}
+
+/// The declaration named `name` in the index, or fail.
+fn find_decl<'a, 'b>(outline: &'b Outline<'a>, name: &str) -> &'b (StmtCmd, ProofIter<'a>) {
+    outline.declarations
+        .iter()
+        .find(|(stmt, _)| outline.stmt_name(*stmt) == Some(name))
+        .unwrap_or_else(|| fail(format!("No declaration named {} in the index", name)))
+}
+
+/// A declaration's name, or failing that its command.
+fn decl_name(outline: &Outline, stmt: StmtCmd) -> String {
+    outline.stmt_name(stmt).map(|s| s.to_string()).unwrap_or_else(|| format!("{:?}", stmt))
+}
+
+fn diff(outline: &Outline, path: &str) {
+    let old_data = read_mmb(path);
+    let old = or_fail(Outline::new_from(&old_data), format_args!("parse {}", path));
+    let changes = or_fail(second_opinion::diff::diff(&old, outline), format_args!("compare against {}", path));
+    for c in changes.iter() {
+        println!("{}", c);
+    }
+    println!("* {} changes since {}", changes.len(), path);
+}
+
+fn link(outline: &Outline, out_path: &str, paths: Vec<&str>) {
+    let others = paths.iter().map(|path| read_mmb(path)).collect::<Vec<_>>();
+    let others = others.iter().zip(paths.iter()).map(|(data, path)| or_fail(Outline::new_from(data), format_args!("parse {}", path))).collect::<Vec<_>>();
+    let mut files = vec![outline];
+    files.extend(others.iter());
+    write(out_path, or_fail(second_opinion::mmb::link::link(&files), "link the files"));
+    println!("* linked {} files into {}", files.len(), out_path);
+}
+
+fn compare_env(outline: &Outline, path: &str) {
+    let text = or_fail(std::fs::read_to_string(path), format_args!("read {}", path));
+    let theirs = or_fail(second_opinion::json::parse(&text), format_args!("parse {}", path));
+    let ours = or_fail(outline.env_dump(), "describe the environment");
+    let divergences = or_fail(second_opinion::env::compare_envs(&ours, &theirs), format_args!("compare against {}", path));
+    for d in divergences.iter() {
+        println!("environment divergence: {}", d);
+    }
+    println!("* compared environment against {}: {} divergences", path, divergences.len());
+}
+
+fn trace(matches: &ArgMatches, outline: &Outline, name: &str) {
+    let (stmt, proof) = find_decl(outline, name);
+    let mut bump = bumpalo::Bump::new();
+    let (steps, _) = outline.trace(&mut bump, *stmt, *proof);
+    match matches.value_of("trace_html") {
+        None => print!("{}", second_opinion::mmb::trace::trace_text(&steps)),
+        Some(path) => write(path, second_opinion::mmb::trace::trace_html(name, &steps)),
+    }
+}
+
+fn lint(outline: &Outline) {
+    let report = or_fail(outline.lint(), "lint the file");
+    for kind in second_opinion::mmb::lint::LintKind::ALL.iter() {
+        if report.count(*kind) == 0 {
+            continue
+        }
+        let examples = report.examples
+            .iter()
+            .filter(|h| h.kind == *kind)
+            .map(|h| format!("{} @ {}", decl_name(outline, h.decl), h.pos))
+            .collect::<Vec<_>>();
+        println!("lint {}: {} (e.g. {})", kind.name(), report.count(*kind), examples.join(", "));
+    }
+    println!("* lint: {} findings", report.total());
+}
+
+fn coverage(outline: &Outline) {
+    use second_opinion::mmb::coverage::SlotKind;
+    let report = or_fail(outline.coverage(), "measure proof coverage");
+    let mut savings = 0;
+    for cov in report.iter().filter(|c| !c.is_tight()) {
+        let unreferenced = SlotKind::ALL
+            .iter()
+            .filter(|k| cov.unreferenced(**k) != 0)
+            .map(|k| format!("{} {}", cov.unreferenced(*k), k.name()))
+            .collect::<Vec<_>>();
+        println!(
+            "coverage {}: heap {}, unreferenced {}, unused saves @ [{}], {} dead slots, {} bytes",
+            decl_name(outline, cov.decl),
+            cov.heap,
+            if unreferenced.is_empty() { "none".to_string() } else { unreferenced.join(", ") },
+            cov.unused_saves.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", "),
+            cov.dead_slots,
+            cov.savings
+        );
+        savings += cov.savings;
+    }
+    println!("* coverage: {} of {} proofs could be tighter, saving {} bytes", report.iter().filter(|c| !c.is_tight()).count(), report.len(), savings);
+}
+
+fn sort_mods(outline: &Outline) {
+    use second_opinion::mmb::sort_mods::SortMod;
+    let report = or_fail(outline.check_sort_mods(), "check the sort modifiers");
+    for sort in 0..outline.header.num_sorts {
+        let name = outline.sort_name(sort).map(|s| s.to_string()).unwrap_or_else(|| format!("sort {}", sort));
+        let mods = or_fail(outline.get_sort_mods(sort as usize), format_args!("read the modifiers of {}", name)).inner;
+        for m in SortMod::ALL.iter() {
+            let uses = report.uses_of(sort, *m).collect::<Vec<_>>();
+            let set = mods & m.bit() != 0;
+            let examples = uses.iter().take(3).map(|u| format!("{} ({:?})", decl_name(outline, u.decl), u.role)).collect::<Vec<_>>();
+            let verdict = match (set, m.required(), uses.is_empty()) {
+                (true, true, true) => "set, but nothing needs it".to_string(),
+                (false, true, true) => "not set, and nothing needs it".to_string(),
+                (true, false, true) => "set, and nothing conflicts with it".to_string(),
+                (false, false, true) => "not set, but could be".to_string(),
+                (true, true, false) => format!("needed by {} uses, e.g. {}", uses.len(), examples.join(", ")),
+                (false, false, false) => format!("can't be set because of {} uses, e.g. {}", uses.len(), examples.join(", ")),
+                (_, _, false) => format!("VIOLATED by {} uses, e.g. {}", uses.len(), examples.join(", ")),
+            };
+            println!("sort-mods {} {}: {}", name, m.name(), verdict);
+        }
+    }
+    println!("* sort modifiers: {} constraints, {} violated", report.uses.len(), report.violations().count());
+}
+
+/// Verify on one thread, saving progress to `path` every `every` declarations and
+/// resuming from it if it's there. Sets `resumed_at` to where a resumed run picked up.
+fn checkpointed(outline: &Outline, path: &str, every: usize, resumed_at: &mut Option<usize>) -> Vec<VerifErr> {
+    let resume = std::fs::read_to_string(path).ok().map(|s| {
+        let json = or_fail(second_opinion::json::parse(&s), format_args!("parse {}", path));
+        or_fail(second_opinion::checkpoint::Checkpoint::from_json(&json), format_args!("read the checkpoint in {}", path))
+    });
+    if let Some(cp) = &resume {
+        println!("* resuming from {} at declaration {} of {}", path, cp.next_decl, outline.declarations.len());
+        *resumed_at = Some(cp.next_decl);
+    }
+    let tmp_path = format!("{}.tmp", path);
+    let errs = second_opinion::checkpoint::verify_serial_checkpointed(outline, resume.as_ref(), every, |cp| {
+        // Write then rename, so an interruption never leaves a half-written checkpoint.
+        io_err!(std::fs::write(&tmp_path, cp.to_json().to_string()))?;
+        io_err!(std::fs::rename(&tmp_path, path))
+    });
+    // A finished run doesn't need its checkpoint any more.
+    if errs.is_empty() {
+        let _ = std::fs::remove_file(path);
+    }
     errs
 }
 
-fn verify_par<'a>(outline: &'a Outline<'a>, num_threads: usize) -> Vec<VerifErr> {
-    let task_counter = AtomicUsize::new(0);
+/// Verify on one thread, writing the errors to `path` as a SARIF log.
+fn sarif<'a>(outline: &'a Outline<'a>, decls: &[(StmtCmd, ProofIter<'a>)], path: &str, mmb_path: &Path, mmz_path: Option<&Path>) -> Vec<VerifErr> {
+    let located = outline.verify_located(decls);
+    let mmz_uri = mmz_path.map(|p| p.to_string_lossy());
+    let log = outline.sarif(&located, &mmb_path.to_string_lossy(), mmz_uri.as_deref());
+    write(path, log.to_string());
+    println!("* wrote a SARIF log of {} errors to {}", located.len(), path);
+    located.into_iter().map(|l| l.err).collect()
+}
 
-    thread::scope(|sco| {
-        let mut mmb_tasks = Vec::new();
+fn arena_stats(outline: &Outline, decls: &[(StmtCmd, ProofIter)]) {
+    let stats = outline.arena_stats.with(|s| *s);
+    let peak_decl = stats.peak_decl
+        .and_then(|idx| decls.get(idx))
+        .map(|(stmt, _)| decl_name(outline, *stmt))
+        .unwrap_or_else(|| "none".to_string());
+    println!(
+        "* arena: {} chunks, {} bytes in total; the largest declaration ({}) needed {} bytes",
+        stats.chunks,
+        stats.bytes,
+        peak_decl,
+        stats.peak_decl_bytes
+    );
+}
 
-        for _ in 0..num_threads {
-            mmb_tasks.push(sco.spawn(|_| verify_mmb(outline, &task_counter)));
+fn timings(matches: &ArgMatches, outline: &Outline, factor: u32) {
+    let times = outline.phase_times.with(|t| t.clone());
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    if matches.is_present("verbose") {
+        for d in times.decls.iter() {
+            println!("  {:<30} {:>10.3}ms proof {:>10.3}ms unify", decl_name(outline, d.decl), ms(d.proof), ms(d.unify));
         }
+    }
+    println!(
+        "* time: {:.3}ms header and tables, {:.3}ms index, {:.3}ms mm0, {:.3}ms proofs, {:.3}ms unification",
+        ms(times.tables),
+        ms(times.index),
+        ms(times.mmz),
+        ms(times.proofs),
+        ms(times.unify)
+    );
+    let outliers = times.outliers(factor);
+    if !outliers.is_empty() {
+        println!(
+            "* outliers: {} of {} declarations took more than {}x the median of {:.3}ms:",
+            outliers.len(),
+            times.decls.len(),
+            factor,
+            ms(times.median_decl())
+        );
+        for d in outliers.iter() {
+            println!("  {:<30} {:>10.3}ms ({:.1}x the median)", decl_name(outline, d.decl), ms(d.total()), d.total().as_secs_f64() / times.median_decl().as_secs_f64());
+        }
+    }
+}
+
+/// Write the report of a successful run to `path`, signed if `--sign` is given.
+fn write_report<'a>(
+    matches: &ArgMatches,
+    outline: &Outline<'a>,
+    decls: &[(StmtCmd, ProofIter<'a>)],
+    path: &str,
+    resumed_at: Option<usize>,
+    verified_here: bool,
+) -> second_opinion::Res<()> {
+    // A resumed run only vouches for what it checked itself.
+    let report = match resumed_at {
+        None => outline.verification_report(decls)?,
+        Some(n) => outline.resumed_report(outline.declarations.get(n..).unwrap_or(&[]), n)?,
+    };
+    let digest = report.get("digest").and_then(|d| d.as_str()).unwrap_or("").to_string();
+    let written = match matches.value_of("sign") {
+        None => report,
+        Some(key) => sign_report(outline, decls, key, verified_here)?,
+    };
+    write(path, written.to_string());
+    println!("* wrote report to {} (digest {})", path, digest);
+    Ok(())
+}
 
-        let mut errs = match sco.spawn(|_| verify_mmz(outline)).join() {
-            Err(_) => vec![VerifErr::Msg(format!("mmz thread panicked!"))],
-            Ok(errs) => errs,
-        };
+fn html_report(outline: &Outline, decls: &[(StmtCmd, ProofIter)], dir: &str) {
+    let pages = outline.html_report(decls);
+    or_fail(std::fs::create_dir_all(dir), format_args!("create {}", dir));
+    for (name, page) in pages.iter() {
+        write(Path::new(dir).join(name), page);
+    }
+    println!("* wrote an HTML report of {} declarations to {}", pages.len() - 1, dir);
+}
+
+/// The flags that write the verified file out in another form.
+fn exports(matches: &ArgMatches, outline: &Outline) {
+    if let Some(path) = matches.value_of("dedukti") {
+        let (dk, names) = or_fail(outline.to_dedukti(&rename_policy(matches, RenamePolicy::dedukti())), "translate to Dedukti");
+        write(path, dk);
+        println!("* wrote a Dedukti translation to {} ({} names changed)", path, names.renamer.renamings.len());
+    }
 
-        for (idx, mmb_task) in mmb_tasks.into_iter().enumerate() {
-            match mmb_task.join() {
-                Err(_) => { errs.push(VerifErr::Msg(format!("mmb thread {} panicked", idx))); },
-                Ok(mut mmb_errs) => errs.append(&mut mmb_errs),
+    if let Some(path) = matches.value_of("opentheory") {
+        opentheory(matches, outline, path);
+    }
+
+    if let Some(path) = matches.value_of("lean") {
+        lean(matches, outline, path);
+    }
+
+    if let Some(dir) = matches.value_of("tptp") {
+        tptp(matches, outline, dir);
+    }
+
+    if let Some(path) = matches.value_of("optimize") {
+        optimize(matches, outline, path);
+    }
+}
+
+fn opentheory(matches: &ArgMatches, outline: &Outline, path: &str) {
+    use second_opinion::export::opentheory::OtMapping;
+    let mapping = match matches.value_of("opentheory_map") {
+        None => OtMapping::default(),
+        Some(map) => or_fail(OtMapping::parse(&or_fail(std::fs::read_to_string(map), format_args!("read {}", map))), format_args!("parse {}", map)),
+    };
+    let (article, skipped) = or_fail(outline.to_opentheory(&mapping, &rename_policy(matches, RenamePolicy::default())), "translate to OpenTheory");
+    write(path, article);
+    println!("* wrote an OpenTheory article to {} ({} theorems left out)", path, skipped.len());
+}
+
+fn lean(matches: &ArgMatches, outline: &Outline, path: &str) {
+    use second_opinion::export::lean::LeanNotation;
+    let notation = match matches.value_of("lean_notation") {
+        None => LeanNotation::default(),
+        Some(file) => or_fail(LeanNotation::parse(&or_fail(std::fs::read_to_string(file), format_args!("read {}", file))), format_args!("parse {}", file)),
+    };
+    let (lean, names) = or_fail(outline.to_lean(&notation, &rename_policy(matches, RenamePolicy::lean())), "translate to Lean");
+    write(path, lean);
+    println!("* wrote Lean 4 stubs to {} ({} names changed)", path, names.renamer.renamings.len());
+}
+
+fn tptp(matches: &ArgMatches, outline: &Outline, dir: &str) {
+    let options = second_opinion::export::tptp::TptpOptions { axioms: true, lemmas: matches.is_present("tptp_lemmas") };
+    let problems = or_fail(outline.tptp_problems(options, &rename_policy(matches, RenamePolicy::default())), "translate to TPTP");
+    or_fail(std::fs::create_dir_all(dir), format_args!("create {}", dir));
+    for (name, problem) in problems.iter() {
+        write(Path::new(dir).join(format!("{}.p", name)), problem);
+    }
+    println!("* wrote {} TPTP problems to {}", problems.len(), dir);
+}
+
+fn optimize(matches: &ArgMatches, outline: &Outline, path: &str) {
+    let mut optimized = or_fail(outline.optimized(), "optimize the file");
+    let size = optimized.len();
+    if let Some(format) = matches.value_of("compress").and_then(second_opinion::compress::Compression::from_name) {
+        optimized = or_fail(second_opinion::compress::compress(&optimized, format), "compress the optimized file");
+    }
+    write(path, &optimized);
+    println!("* wrote an optimized copy to {} ({} -> {} bytes, {} on disk)", path, outline.mmb().len(), size, optimized.len());
+}
+
+fn serve(matches: &ArgMatches, options: VerifyOptions, num_threads: usize, arenas: BumpPool) {
+    let mut server = Server::new(options, num_threads, arenas);
+    server.root = matches.value_of("root").map(PathBuf::from);
+    if let Some(max) = max_message(matches) {
+        server.max_message = max;
+    }
+    #[cfg(unix)]
+    if let Some(path) = matches.value_of("unix") {
+        println!("* serving on {}", path);
+        return or_fail(server.serve_unix(path), format_args!("serve on {}", path))
+    }
+    if let Some(addr) = matches.value_of("tcp") {
+        println!("* serving on {}", addr);
+        or_fail(server.serve_tcp(addr), format_args!("serve on {}", addr));
+    }
+}
+
+fn watch_command(matches: &ArgMatches, options: VerifyOptions) {
+    // Safe to unwrap since this is required by the clap app.
+    let mmb_path = matches.value_of("mmb_file").map(PathBuf::from).unwrap();
+    let mmz_path = matches.value_of("mmz_file").map(PathBuf::from).unwrap_or_else(|| second_opinion::compress::default_mm0_path(&mmb_path));
+    let interval = parse_arg(matches, "interval", "The watch interval must be a natural number of milliseconds").unwrap_or(500);
+    watch(&mmb_path, &mmz_path, Duration::from_millis(interval), options)
+}
+
+/// Poll the files' modification times, and verify again whenever they change. Runs until killed.
+fn watch(mmb_path: &Path, mmz_path: &Path, interval: Duration, options: VerifyOptions) -> ! {
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    let mut incremental = Incremental::default();
+    let mut last_seen = None;
+    loop {
+        let stamp = Some((modified(mmb_path), modified(mmz_path)));
+        if stamp != last_seen {
+            last_seen = stamp;
+            let start = Instant::now();
+            let file_data = match FileData::new_from(mmb_path, Some(mmz_path)) {
+                Ok(file_data) => file_data,
+                Err(e) => {
+                    println!("FAIL couldn't read {}: {:?}", mmb_path.display(), e);
+                    std::thread::sleep(interval);
+                    continue
+                }
+            };
+            match Outline::new_from(&file_data) {
+                Err(e) => println!("FAIL couldn't parse {}: {:?}", mmb_path.display(), e),
+                Ok(mut outline) => {
+                    outline.options = options;
+                    let (errs, checked) = incremental.verify(&outline);
+                    match errs.first() {
+                        None => println!(
+                            "ok   {} sorts, {} terms, {} assertions ({} checked) in {}ms",
+                            outline.header.num_sorts,
+                            outline.header.num_terms,
+                            outline.header.num_thms,
+                            checked,
+                            start.elapsed().as_millis()
+                        ),
+                        Some(first) => println!(
+                            "FAIL {} errors ({} checked) in {}ms; first in {}: {:?}",
+                            errs.len(),
+                            checked,
+                            start.elapsed().as_millis(),
+                            first.decl.and_then(|stmt| outline.stmt_name(stmt)).unwrap_or("the file"),
+                            first.err
+                        ),
+                    }
+                }
             }
         }
+        std::thread::sleep(interval);
+    }
+}
+
+fn check_project(matches: &ArgMatches, options: VerifyOptions, num_threads: usize) {
+    // Safe to unwrap since this is required by the clap app.
+    let manifest = PathBuf::from(matches.value_of("manifest").unwrap());
+    let text = or_fail(std::fs::read_to_string(&manifest), format_args!("read {}", manifest.display()));
+    let base = manifest.parent().unwrap_or_else(|| Path::new("."));
+    let files = or_fail(second_opinion::project::parse_manifest(&text, base, options), format_args!("parse {}", manifest.display()));
+    let reports = files.iter().map(|f| second_opinion::project::check_file(f, num_threads)).collect::<Vec<_>>();
+    if matches.is_present("json") {
+        println!("{}", second_opinion::project::project_json(&reports));
+    } else {
+        for r in reports.iter() {
+            match r.errors.first() {
+                None => println!("OK   {} ({} sorts, {} terms, {} assertions in {}ms)", r.mmb.display(), r.sorts, r.terms, r.thms, r.time.as_millis()),
+                Some(e) => println!("FAIL {} ({} errors; first {} {:?})", r.mmb.display(), r.errors.len(), e.code(), e),
+            }
+        }
+        println!("* project: {} of {} files verified", reports.iter().filter(|r| r.ok()).count(), reports.len());
+    }
+    if reports.iter().any(|r| !r.ok()) {
+        std::process::exit(1)
+    }
+}
 
-        errs
-    }).unwrap()
+fn verify_all(matches: &ArgMatches, options: VerifyOptions, num_threads: usize) {
+    // Safe to unwrap since this is required by the clap app.
+    let dir = PathBuf::from(matches.value_of("dir").unwrap());
+    let pattern = matches.value_of("glob").unwrap_or("**/*.mmb");
+    let files = or_fail(second_opinion::project::discover(&dir, pattern, options), format_args!("search {}", dir.display()));
+    let reports = second_opinion::project::check_files(&files, num_threads);
+    if matches.is_present("json") {
+        println!("{}", second_opinion::project::project_json(&reports));
+    } else {
+        print!("{}", second_opinion::project::summary_table(&reports));
+        println!("* verify-all: {} of {} files verified", reports.iter().filter(|r| r.ok()).count(), reports.len());
+    }
+    if reports.iter().any(|r| !r.ok()) {
+        std::process::exit(1)
+    }
 }
 
+fn export_sexp(matches: &ArgMatches) {
+    // Safe to unwrap since this is required by the clap app.
+    let mmb_path = matches.value_of("mmb_file").map(PathBuf::from).unwrap();
+    let file_data = or_fail(FileData::new_from(&mmb_path, None::<PathBuf>), format_args!("read {}", mmb_path.display()));
+    let outline = or_fail(Outline::new_from(&file_data), format_args!("parse {}", mmb_path.display()));
+    print!("{}", or_fail(outline.to_sexp(), "write the s-expressions"));
+}
 
-// Parsing/verifying the contents of the mmz file is done in serial
-fn verify_mmz<'a>(outline: &'a Outline<'a>) -> Vec<VerifErr> {
-    let mut mem = MmzMem::new_from(outline).unwrap();
-    let mut bump = Bump::new();
-    let mut errs = Vec::new();
-    for (stmt, _proof) in outline.declarations.iter() {
-        if let Err(e) = mem.verify1(&mut bump, *stmt) {
-            errs.push(e);
-        }
+fn trust(matches: &ArgMatches) {
+    // Safe to unwrap since this is required by the clap app.
+    let mmb_path = matches.value_of("mmb_file").map(PathBuf::from).unwrap();
+    let mm0_path = matches.value_of("mm0_file").map(PathBuf::from);
+    let file_data = or_fail(FileData::new_from(&mmb_path, mm0_path.as_ref()), format_args!("read {}", mmb_path.display()));
+    let mut outline = or_fail(Outline::new_from(&file_data), format_args!("parse {}", mmb_path.display()));
+    if mm0_path.is_some() {
+        outline.notations = Some(outline.read_notations());
     }
+    print!("{}", or_fail(outline.trust_report(), "list what the file trusts"));
+}
 
-    outline.assert_mmz_done(&mem, &mut errs);
-    errs
+/// `policy`, the export's own, with the prefix and case from the command line.
+fn rename_policy(matches: &ArgMatches, policy: RenamePolicy) -> RenamePolicy {
+    RenamePolicy {
+        prefix: matches.value_of("rename_prefix").unwrap_or("").to_string(),
+        case: matches.value_of("rename_case").and_then(Case::from_name).unwrap_or(policy.case),
+        ..policy
+    }
 }
 
-fn verify_mmb<'a>(outline: &'a Outline<'a>, task_counter: &AtomicUsize) -> Vec<VerifErr> {
-    let mut bump = Bump::new();
-    let mut errs = Vec::new();
-    while let Some((stmt, proof)) = outline.declarations.get(task_counter.fetch_add(1, Relaxed)) {
-        if let Err(e) = crate::mmb::MmbState::verify1(outline, &mut bump, *stmt, *proof) {
-            errs.push(e);
+/// `--max-message`, for `serve` and `remote-serve`.
+fn max_message(matches: &ArgMatches) -> Option<u32> {
+    parse_positive(matches, "max_message", "The largest message must be a positive number of bytes")
+}
+
+/// The `serve` subcommand. `--unix` is only there on Unix.
+fn serve_command<'a, 'b>() -> App<'a, 'b> {
+    let tcp = Arg::with_name("tcp")
+        .long("tcp")
+        .value_name("address")
+        .help("listen on a TCP address, like 127.0.0.1:7777")
+        .takes_value(true);
+    #[cfg(unix)]
+    let tcp = tcp.required_unless("unix");
+    #[cfg(not(unix))]
+    let tcp = tcp.required(true);
+    let serve = SubCommand::with_name("serve")
+        .about("stay running and answer line-delimited JSON verification requests over a socket")
+        .arg(tcp)
+        .arg(
+            Arg::with_name("root")
+            .long("root")
+            .value_name("dir")
+            .help("answer requests naming files by path, as long as they're under this directory")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("max_message")
+            .long("max-message")
+            .value_name("bytes")
+            .help("refuse request lines longer than this (default 64 MiB)")
+            .takes_value(true)
+        );
+    #[cfg(unix)]
+    let serve = serve.arg(
+        Arg::with_name("unix")
+        .long("unix")
+        .value_name("socket path")
+        .help("listen on a Unix socket")
+        .takes_value(true)
+        .conflicts_with("tcp")
+    );
+    serve
+}
+
+fn axiom_policy(matches: &ArgMatches) -> second_opinion::mmb::axioms::AxiomPolicy {
+    second_opinion::mmb::axioms::AxiomPolicy {
+        allow: matches.values_of("allow_axioms").map(|vs| vs.map(|v| v.to_string()).collect()),
+        deny: matches.values_of("deny_axioms").into_iter().flatten().map(|v| v.to_string()).collect(),
+    }
+}
+
+#[cfg(feature = "sign")]
+fn read_key(path: &str) -> second_opinion::Res<[u8; 32]> {
+    second_opinion::sign::parse_key(&io_err!(std::fs::read_to_string(path))?)
+}
+
+#[cfg(feature = "sign")]
+fn sign_report<'a>(outline: &Outline<'a>, decls: &[(StmtCmd, ProofIter<'a>)], key_path: &str, verified_here: bool) -> second_opinion::Res<second_opinion::json::Json> {
+    Ok(second_opinion::sign::SignedReport::sign(outline.attestation(decls, verified_here)?, &read_key(key_path)?).to_json())
+}
+
+#[cfg(feature = "sign")]
+fn keygen(matches: &ArgMatches) {
+    use std::io::Read;
+    // Safe to unwrap since this is required by the clap app.
+    let path = matches.value_of("secret_key_file").unwrap();
+    let mut secret = [0u8; 32];
+    or_fail(std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut secret)), "read /dev/urandom");
+    let mut file = std::fs::OpenOptions::new();
+    // Never overwrite a key, and only let the owner read a new one.
+    file.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut file, 0o600);
+    let mut file = or_fail(file.open(path), format_args!("create {}", path));
+    or_fail(std::io::Write::write_all(&mut file, format!("{}\n", second_opinion::env::hex(&secret)).as_bytes()), format_args!("write {}", path));
+    println!("{}", second_opinion::env::hex(&second_opinion::sign::public_key(&secret)));
+}
+
+#[cfg(feature = "sign")]
+fn check_signature(matches: &ArgMatches, top: &ArgMatches, options: VerifyOptions) {
+    // Safe to unwrap since these are required by the clap app.
+    let signed_path = matches.value_of("signed_report").unwrap();
+    let keys_path = matches.value_of("public_key_file").unwrap();
+    let signed = or_fail(std::fs::read_to_string(signed_path), format_args!("read {}", signed_path));
+    let keys = or_fail(std::fs::read_to_string(keys_path), format_args!("read {}", keys_path));
+    let file_data = matches.value_of("mmb_file").map(|mmb| or_fail(FileData::new_from(mmb, matches.value_of("mm0_file")), format_args!("read {}", mmb)));
+    let outline = file_data.as_ref().map(|file_data| {
+        let mut outline = or_fail(Outline::new_from(file_data), "parse the mmb file");
+        outline.options = options;
+        outline.axiom_policy = axiom_policy(top);
+        outline
+    });
+    let checked = second_opinion::json::parse(&signed)
+        .and_then(|json| second_opinion::sign::SignedReport::from_json(&json))
+        .and_then(|signed| {
+            let trusted = keys.lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(second_opinion::sign::parse_key)
+                .collect::<second_opinion::Res<Vec<_>>>()?;
+            match &outline {
+                None => signed.check(&trusted),
+                Some(outline) => signed.check_for(&trusted, outline),
+            }
+        });
+    match checked {
+        Ok(()) => println!("* the signature is good"),
+        Err(e) => {
+            println!("the signature was rejected: {:?}", e);
+            std::process::exit(1)
         }
     }
+}
 
-    errs
+#[cfg(not(feature = "sign"))]
+fn sign_report(_: &Outline, _: &[(StmtCmd, ProofIter)], _: &str, _: bool) -> second_opinion::Res<second_opinion::json::Json> {
+    fail("--sign needs the `sign` feature: cargo build --release --features sign")
+}
+
+#[cfg(not(feature = "sign"))]
+fn keygen(_: &ArgMatches) {
+    fail("keygen needs the `sign` feature: cargo build --release --features sign")
+}
+
+#[cfg(not(feature = "sign"))]
+fn check_signature(_: &ArgMatches, _: &ArgMatches, _: VerifyOptions) {
+    fail("check-signature needs the `sign` feature: cargo build --release --features sign")
+}
+
+#[cfg(feature = "remote")]
+fn remote_serve(matches: &ArgMatches, options: VerifyOptions, num_threads: usize, arenas: BumpPool) {
+    // Safe to unwrap since this is required by the clap app.
+    let addr = matches.value_of("address").unwrap();
+    let mut server = second_opinion::remote::Server::new(options, num_threads, arenas);
+    if let Some(max) = max_message(matches) {
+        server.core.max_message = max;
+    }
+    println!("* serving remote verification on {}", addr);
+    or_fail(server.serve_tcp(addr), format_args!("serve on {}", addr))
+}
+
+/// Whether the server verified the files.
+#[cfg(feature = "remote")]
+fn remote_verify(addr: &str, file_data: &FileData) -> bool {
+    let start = Instant::now();
+    let mut client = or_fail(second_opinion::remote::Client::connect(addr), format_args!("connect to {}", addr));
+    let verdict = or_fail(client.verify(&file_data.mmb_file, &file_data.mmz_files), format_args!("get a verdict from {}", addr));
+    for (code, e) in verdict.errors.iter() {
+        println!("error {} {}", code, e);
+    }
+    match &verdict.certificate {
+        None => println!("verification on {} was unsuccessful, with {} errors", addr, verdict.errors.len()),
+        Some(cert) => println!(
+            "\n* {} verified {} sorts, {} terms, and {} assertions{} in {}ms",
+            addr,
+            cert.sorts.len(),
+            cert.terms.len(),
+            cert.thms.len(),
+            if verdict.cached { " (it had already)" } else { "" },
+            start.elapsed().as_millis()
+        ),
+    }
+    verdict.certificate.is_some()
+}
+
+#[cfg(not(feature = "remote"))]
+fn remote_serve(_: &ArgMatches, _: VerifyOptions, _: usize, _: BumpPool) {
+    fail("remote-serve needs the `remote` feature: cargo build --release --features remote")
+}
+
+#[cfg(not(feature = "remote"))]
+fn remote_verify(_: &str, _: &FileData) -> bool {
+    fail("--remote needs the `remote` feature: cargo build --release --features remote")
 }
@@ -0,0 +1,181 @@
+//! An assembler for the text `disasm` prints, for writing unify and proof streams by hand
+//! and for making small changes to existing ones when testing how a verifier deals with
+//! bad input. It reads `disasm`'s listings as they are, but the offsets and raw bytes are
+//! optional (and ignored), so this is enough:
+//!```text
+//! unify
+//!   URef 1
+//!   UHyp
+//!   End
+//! proof
+//!   Ref 0
+//!   Term im
+//!   Bytes 2f 00
+//!```
+//! Terms, assertions, and sorts can be given by name (looked up in the index), as `#n`, or
+//! as `name (#n)` like `disasm` writes them, in which case the number wins. `Bytes` writes
+//! the bytes it's given as is, and nothing is added that isn't written; in particular a
+//! stream without `End` doesn't get one. Everything after a `;` is a comment.
+//!
+//! Commands are written with the smallest data field that fits, which is what the mm1
+//! compiler does, so a file it wrote disassembles and assembles back to the same bytes.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::write::push_cmd;
+use crate::mmb::proof::{
+    PROOF_TERM, PROOF_TERM_SAVE, PROOF_REF, PROOF_DUMMY, PROOF_THM, PROOF_THM_SAVE, PROOF_HYP, PROOF_CONV,
+    PROOF_REFL, PROOF_SYMM, PROOF_CONG, PROOF_UNFOLD, PROOF_CONV_CUT, PROOF_CONV_REF, PROOF_CONV_SAVE, PROOF_SAVE,
+};
+use crate::mmb::unify::{ UNIFY_TERM, UNIFY_TERM_SAVE, UNIFY_REF, UNIFY_DUMMY, UNIFY_HYP };
+use crate::util::{ Res, VerifErr };
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assembled {
+    pub unify: Option<Vec<u8>>,
+    pub proof: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Unify,
+    Proof,
+}
+
+fn asm_err(line_num: usize, msg: String) -> VerifErr {
+    VerifErr::Msg(format!("asm line {}: {}", line_num, msg))
+}
+
+/// The number in `#n`, or in a trailing `(#n)`.
+fn explicit_num(operand: &str) -> Option<&str> {
+    let operand = operand.trim();
+    if let Some(n) = operand.strip_prefix('#') {
+        return Some(n)
+    }
+    operand.strip_suffix(')')?.rsplit_once("(#").map(|(_, n)| n)
+}
+
+impl<'a> Outline<'a> {
+    fn asm_operand<T: core::str::FromStr>(&self, operand: &str, find: impl Fn(&str) -> Option<T>) -> Option<T> {
+        match explicit_num(operand) {
+            Some(n) => n.parse().ok(),
+            None => find(operand.trim()),
+        }
+    }
+
+    /// Assemble the `unify` and `proof` sections of `text`.
+    pub fn assemble(&self, text: &str) -> Res<Assembled> {
+        let mut out = Assembled::default();
+        let mut section = None;
+        for (idx, line) in text.lines().enumerate() {
+            let line_num = idx + 1;
+            let line = line.split(';').next().unwrap_or("");
+            let mut tokens = line.split_whitespace().peekable();
+            match tokens.peek() {
+                None => continue,
+                Some(&"unify") => {
+                    section = Some(Section::Unify);
+                    out.unify.get_or_insert_with(Vec::new);
+                    continue
+                }
+                Some(&"proof") => {
+                    section = Some(Section::Proof);
+                    out.proof.get_or_insert_with(Vec::new);
+                    continue
+                }
+                Some(_) => {}
+            }
+            // Skip `disasm`'s offset and raw bytes.
+            while let Some(tok) = tokens.peek() {
+                if tok.starts_with(|c: char| c.is_ascii_uppercase()) || *tok == "error:" {
+                    break
+                }
+                tokens.next();
+            }
+            let mnemonic = tokens.next().ok_or_else(|| asm_err(line_num, "expected a command".to_string()))?;
+            let operand = tokens.collect::<Vec<_>>().join(" ");
+            let buf = match section {
+                Some(Section::Unify) => out.unify.as_mut(),
+                Some(Section::Proof) => out.proof.as_mut(),
+                None => None,
+            }.ok_or_else(|| asm_err(line_num, "commands have to come after `unify` or `proof`".to_string()))?;
+
+            let num = |what: &str| operand.trim().parse::<u32>()
+                .map_err(|_| asm_err(line_num, format!("expected {} after {}, got {:?}", what, mnemonic, operand)));
+            let term = || self.asm_operand(&operand, |s| self.find_term(s))
+                .ok_or_else(|| asm_err(line_num, format!("unknown term {:?}", operand)));
+            let assert = || self.asm_operand(&operand, |s| self.find_assert(s))
+                .ok_or_else(|| asm_err(line_num, format!("unknown assertion {:?}", operand)));
+            let sort = || self.asm_operand(&operand, |s| self.find_sort(s))
+                .map(u32::from)
+                .ok_or_else(|| asm_err(line_num, format!("unknown sort {:?}", operand)));
+
+            let (cmd, data) = match (section, mnemonic) {
+                (_, "End") => (0, 0),
+                (_, "Bytes") => {
+                    for byte in operand.split_whitespace() {
+                        let byte = u8::from_str_radix(byte, 16)
+                            .map_err(|_| asm_err(line_num, format!("expected a hex byte, got {:?}", byte)))?;
+                        buf.push(byte);
+                    }
+                    continue
+                }
+                (_, "error:") => return Err(asm_err(line_num, "the listing has a decoding error".to_string())),
+                (Some(Section::Unify), "UTerm") => (UNIFY_TERM, term()?),
+                (Some(Section::Unify), "UTermSave") => (UNIFY_TERM_SAVE, term()?),
+                (Some(Section::Unify), "URef") => (UNIFY_REF, num("a heap index")?),
+                (Some(Section::Unify), "UDummy") => (UNIFY_DUMMY, sort()?),
+                (Some(Section::Unify), "UHyp") => (UNIFY_HYP, 0),
+                (Some(Section::Proof), "Term") => (PROOF_TERM, term()?),
+                (Some(Section::Proof), "TermSave") => (PROOF_TERM_SAVE, term()?),
+                (Some(Section::Proof), "Ref") => (PROOF_REF, num("a heap index")?),
+                (Some(Section::Proof), "Dummy") => (PROOF_DUMMY, sort()?),
+                (Some(Section::Proof), "Thm") => (PROOF_THM, assert()?),
+                (Some(Section::Proof), "ThmSave") => (PROOF_THM_SAVE, assert()?),
+                (Some(Section::Proof), "Hyp") => (PROOF_HYP, 0),
+                (Some(Section::Proof), "Conv") => (PROOF_CONV, 0),
+                (Some(Section::Proof), "Refl") => (PROOF_REFL, 0),
+                (Some(Section::Proof), "Sym") => (PROOF_SYMM, 0),
+                (Some(Section::Proof), "Cong") => (PROOF_CONG, 0),
+                (Some(Section::Proof), "Unfold") => (PROOF_UNFOLD, 0),
+                (Some(Section::Proof), "ConvCut") => (PROOF_CONV_CUT, 0),
+                (Some(Section::Proof), "ConvRef") => (PROOF_CONV_REF, num("a heap index")?),
+                (Some(Section::Proof), "ConvSave") => (PROOF_CONV_SAVE, 0),
+                (Some(Section::Proof), "Save") => (PROOF_SAVE, 0),
+                _ => return Err(asm_err(line_num, format!("unknown command {}", mnemonic))),
+            };
+            push_cmd(buf, cmd, data);
+        }
+        Ok(out)
+    }
+}
+
+#[test]
+fn assemble1() {
+    for path in ["./test_resources/short.mmb", "./test_resources/a.mmb"].iter() {
+        let file_data = crate::fs::FileData::new_from(path, None::<String>).unwrap();
+        let outline = Outline::new_from(&file_data).unwrap();
+        let mmb = outline.mmb();
+        for (stmt, proof) in outline.declarations.iter() {
+            let listing = outline.disasm(*stmt, *proof).unwrap();
+            let assembled = outline.assemble(&listing).unwrap();
+            if let Some(unify) = assembled.unify {
+                let start = listing.lines().next().unwrap()["unify @ ".len()..].parse::<usize>().unwrap();
+                assert_eq!(&mmb[start..start + unify.len()], unify.as_slice());
+                assert_eq!(unify.last(), Some(&0));
+            }
+            if let Some(bytes) = assembled.proof {
+                assert_eq!(&mmb[proof.pos..proof.ends_at], bytes.as_slice());
+            }
+        }
+    }
+
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let assembled = outline.assemble("proof\n  Ref 1 ; comment\n  Term im (#0)\n  Thm #3\n  Dummy wff\n  Bytes 2f 00\n").unwrap();
+    assert_eq!(assembled.unify, None);
+    assert_eq!(assembled.proof, Some(vec![0x52, 1, 0x10, 0x54, 3, 0x13, 0x2f, 0]));
+    assert!(outline.assemble("Ref 0").is_err());
+    assert!(outline.assemble("unify\n  Ref 0").is_err());
+    assert!(outline.assemble("proof\n  Term nope").is_err());
+}
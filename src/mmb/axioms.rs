@@ -0,0 +1,142 @@
+//! Which axioms each theorem rests on, and a policy saying which ones it may. A theorem
+//! uses an axiom if its proof applies it, or applies a theorem that uses it; this only
+//! follows the `Thm` commands in the proof streams, so it's known before anything is
+//! verified, and it's the same whether or not the proofs check.
+//!
+//! With a policy, every theorem that uses an axiom the policy doesn't permit is an error,
+//! so `--deny-axioms ax_ac` checks that a development is choice-free, and `--allow-axioms`
+//! lists all it may use. Axioms are named as in the index, or by number.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::util::{ Res, VerifErr };
+
+/// Which axioms theorems may use. The default permits everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AxiomPolicy {
+    /// If set, only these axioms are permitted.
+    pub allow: Option<Vec<String>>,
+    /// These axioms are never permitted, even if `allow` lists them.
+    pub deny: Vec<String>,
+}
+
+/// Whether `entry` in a policy means axiom `num`, named `name`: either the name, or the
+/// number with or without a `#`.
+fn names(entry: &str, name: Option<&str>, num: u32) -> bool {
+    name == Some(entry) || entry.trim_start_matches('#').parse::<u32>() == Ok(num)
+}
+
+impl AxiomPolicy {
+    /// Whether this policy permits everything, so there's nothing to check.
+    pub fn is_permissive(&self) -> bool {
+        self.allow.is_none() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, name: Option<&str>, num: u32) -> bool {
+        let allowed = match &self.allow {
+            None => true,
+            Some(allow) => allow.iter().any(|e| names(e, name, num)),
+        };
+        allowed && !self.deny.iter().any(|e| names(e, name, num))
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// The axioms (by assertion number, in increasing order) each assertion uses, by
+    /// assertion number. An axiom uses only itself.
+    pub fn axioms_used(&self) -> Res<Vec<Vec<u32>>> {
+        let mut used: Vec<Vec<u32>> = vec![Vec::new(); self.header.num_thms as usize];
+        for (stmt, proof) in self.declarations.iter() {
+            let (num, axioms) = match *stmt {
+                StmtCmd::Axiom { num: Some(num) } => (num, vec![num]),
+                StmtCmd::Thm { num: Some(num), .. } => {
+                    let mut axioms = Vec::new();
+                    // Declaration order means everything a theorem uses is already filled in.
+                    for thm in self.direct_deps(*stmt, *proof)?.thms {
+                        axioms.extend(used.get(thm as usize).into_iter().flatten().copied());
+                    }
+                    axioms.sort_unstable();
+                    axioms.dedup();
+                    (num, axioms)
+                }
+                _ => continue,
+            };
+            if let Some(slot) = used.get_mut(num as usize) {
+                *slot = axioms;
+            }
+        }
+        Ok(used)
+    }
+
+    /// Report each theorem in `decls` that uses an axiom `axiom_policy` doesn't permit,
+    /// and each axiom the policy names that the file doesn't have, which is most likely a typo.
+    pub fn check_axioms(&self, decls: &[(StmtCmd, ProofIter<'a>)], errs: &mut Vec<VerifErr>) {
+        let policy = &self.axiom_policy;
+        if policy.is_permissive() {
+            return
+        }
+        let axioms = self.declarations.iter().filter_map(|(stmt, _)| match stmt {
+            StmtCmd::Axiom { num: Some(num) } => Some(*num),
+            _ => None,
+        }).collect::<Vec<u32>>();
+        for entry in policy.allow.iter().flatten().chain(policy.deny.iter()) {
+            if !axioms.iter().any(|num| names(entry, self.assert_name(*num), *num)) {
+                errs.push(VerifErr::Msg(format!("the axiom policy names {}, which isn't an axiom of this file", entry)));
+            }
+        }
+        let used = match self.axioms_used() {
+            Ok(used) => used,
+            Err(e) => return errs.push(e),
+        };
+        for (stmt, _) in decls.iter() {
+            if let StmtCmd::Thm { num: Some(num), .. } = *stmt {
+                let denied = used.get(num as usize).into_iter().flatten()
+                    .filter(|ax| !policy.permits(self.assert_name(**ax), **ax))
+                    .map(|ax| self.assert_name(*ax).map(|s| s.to_string()).unwrap_or_else(|| format!("#{}", ax)))
+                    .collect::<Vec<_>>();
+                if !denied.is_empty() {
+                    errs.push(VerifErr::AxiomNotAllowed { decl: *stmt, axioms: denied });
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn check_axioms1() {
+    use crate::testgen::{ Fixture, AssertSpec, stream, reg };
+    use crate::mmb::stmt::STMT_THM;
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM, PROOF_SAVE, PROOF_THM };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_TERM_SAVE, UNIFY_REF };
+    use crate::util::ErrKind;
+    // `valid`, with `th2 (a: wff): $ im (im a a) (im a a) $ = (th a a)`, which only uses
+    // `ax` through `th`.
+    let mut fx = Fixture::valid();
+    fx.asserts.push(AssertSpec {
+        args: vec![reg(0, 0)],
+        unify: stream(&[(UNIFY_TERM, 0), (UNIFY_TERM_SAVE, 0), (UNIFY_REF, 0), (UNIFY_REF, 0), (UNIFY_REF, 1)]),
+    });
+    fx.stmts.push((STMT_THM, stream(&[
+        (PROOF_REF, 0), (PROOF_REF, 0),
+        (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0), (PROOF_SAVE, 0),
+        (PROOF_REF, 1), (PROOF_TERM, 0),
+        (PROOF_THM, 1),
+    ])));
+    assert_eq!(fx.with_outline(|outline| outline.axioms_used().unwrap()), [vec![0], vec![0], vec![0]]);
+    let run = |allow: Option<&[&str]>, deny: &[&str]| fx.with_outline(|outline| {
+        outline.axiom_policy = AxiomPolicy {
+            allow: allow.map(|a| a.iter().map(|s| s.to_string()).collect()),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        };
+        let errs = crate::verify_serial(outline, &outline.declarations);
+        (errs.iter().filter(|e| e.kind() == ErrKind::AxiomNotAllowed).count(), errs.len())
+    });
+    assert_eq!(run(None, &[]), (0, 0));
+    assert_eq!(run(Some(&["#0"]), &[]), (0, 0));
+    assert_eq!(run(None, &["0"]), (2, 2));
+    assert_eq!(run(Some(&[]), &[]), (2, 2));
+    // Not an axiom: the first theorem is assertion 1.
+    assert_eq!(run(None, &["1"]), (0, 1));
+}
@@ -0,0 +1,394 @@
+//! Writing an mmb file from scratch. A `Builder` takes the sorts, terms, defs, axioms and
+//! theorems in declaration order, with their unify and proof streams given as commands,
+//! and lays them out the way the mm1 compiler does (see `write`), with the header filled
+//! in and optionally an index. Each declaration is checked as it's added: its binders,
+//! and that the sorts, terms and theorems its streams refer to were declared before it.
+//! The proofs themselves aren't checked, so a file that should verify has to be verified.
+//!
+//! `layout` does the writing without any of the checks, for `testgen`, whose files are
+//! mostly broken on purpose.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::fs::FileData;
+use crate::mmb::MM0B_MAGIC;
+use crate::mmb::write::push_stmt;
+use crate::mmb::index_gen::IndexNames;
+use crate::mmb::validate::{ SORT_MODS_MASK, check_binders, check_type_words };
+use crate::mmb::stmt::{ StmtCmd, STMT_SORT, STMT_DEF, STMT_AXIOM, STMT_THM, STMT_LOCAL };
+use crate::mmb::proof::ProofCmd;
+use crate::mmb::unify::UnifyCmd;
+use crate::util::{ Res, VerifErr, Type };
+use crate::make_sure;
+use crate::none_err;
+
+#[derive(Debug, Clone)]
+pub struct TermSpec {
+    pub args: Vec<Type>,
+    pub ret: Type,
+    /// The unify stream of a def; `None` for a term.
+    pub def: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AssertSpec {
+    pub args: Vec<Type>,
+    pub unify: Vec<u8>,
+}
+
+fn push_u32(out: &mut Vec<u8>, n: usize) {
+    out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn push_args(out: &mut Vec<u8>, args: &[Type]) {
+    out.resize((out.len() + 7) & !7, 0);
+    for ty in args.iter() {
+        out.extend_from_slice(&ty.bits().to_le_bytes());
+    }
+}
+
+/// Write a file with no index out of its tables and statements (each the command, like
+/// `STMT_SORT`, and the proof stream, which is empty for sorts and terms), as given.
+pub fn layout(
+    magic: u32,
+    version: u8,
+    sorts: &[u8],
+    terms: &[TermSpec],
+    asserts: &[AssertSpec],
+    stmts: &[(u8, Vec<u8>)],
+) -> Res<Vec<u8>> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&magic.to_le_bytes());
+    out.extend_from_slice(&[version, sorts.len() as u8, 0, 0]);
+    push_u32(&mut out, terms.len());
+    push_u32(&mut out, asserts.len());
+    // The term, theorem, and proof stream offsets and the index are filled in below.
+    out.resize(crate::mmb::format::HEADER_SIZE, 0);
+    out.extend_from_slice(sorts);
+    out.resize((out.len() + 7) & !7, 0);
+    // Each table is followed by the data its entries point to, as the mm1 compiler does it.
+    let terms_start = out.len();
+    out.resize(terms_start + 8 * terms.len(), 0);
+    for (num, term) in terms.iter().enumerate() {
+        push_args(&mut out, &term.args);
+        let args_at = out.len() - 8 * term.args.len();
+        out.extend_from_slice(&term.ret.bits().to_le_bytes());
+        if let Some(unify) = &term.def {
+            out.extend_from_slice(unify);
+        }
+        let entry = terms_start + 8 * num;
+        out[entry..entry + 2].copy_from_slice(&(term.args.len() as u16).to_le_bytes());
+        out[entry + 2] = term.ret.sort() | if term.def.is_some() { 0x80 } else { 0 };
+        out[entry + 4..entry + 8].copy_from_slice(&(args_at as u32).to_le_bytes());
+    }
+    out.resize((out.len() + 7) & !7, 0);
+    let thms_start = out.len();
+    out.resize(thms_start + 8 * asserts.len(), 0);
+    for (num, assert) in asserts.iter().enumerate() {
+        push_args(&mut out, &assert.args);
+        let args_at = out.len() - 8 * assert.args.len();
+        out.extend_from_slice(&assert.unify);
+        let entry = thms_start + 8 * num;
+        out[entry..entry + 2].copy_from_slice(&(assert.args.len() as u16).to_le_bytes());
+        out[entry + 4..entry + 8].copy_from_slice(&(args_at as u32).to_le_bytes());
+    }
+
+    let proof_stream_start = out.len();
+    for (cmd, proof) in stmts.iter() {
+        push_stmt(&mut out, *cmd, proof.len())?;
+        out.extend_from_slice(proof);
+    }
+    out.push(0);
+
+    out[16..20].copy_from_slice(&(terms_start as u32).to_le_bytes());
+    out[20..24].copy_from_slice(&(thms_start as u32).to_le_bytes());
+    out[24..28].copy_from_slice(&(proof_stream_start as u32).to_le_bytes());
+    Ok(out)
+}
+
+/// A proof stream, one command at a time.
+#[derive(Debug, Clone, Default)]
+pub struct ProofStream {
+    pub cmds: Vec<ProofCmd>,
+}
+
+impl ProofStream {
+    pub fn new() -> Self {
+        ProofStream::default()
+    }
+
+    pub fn push(&mut self, cmd: ProofCmd) -> &mut Self {
+        self.cmds.push(cmd);
+        self
+    }
+
+    /// The encoded stream, with the final `End`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for cmd in self.cmds.iter() {
+            cmd.encode(&mut out);
+        }
+        out.push(0);
+        out
+    }
+}
+
+/// A unify stream, one command at a time.
+#[derive(Debug, Clone, Default)]
+pub struct UnifyStream {
+    pub cmds: Vec<UnifyCmd>,
+}
+
+impl UnifyStream {
+    pub fn new() -> Self {
+        UnifyStream::default()
+    }
+
+    pub fn push(&mut self, cmd: UnifyCmd) -> &mut Self {
+        self.cmds.push(cmd);
+        self
+    }
+
+    /// The encoded stream, with the final `End`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for cmd in self.cmds.iter() {
+            cmd.encode(&mut out);
+        }
+        out.push(0);
+        out
+    }
+}
+
+/// An mmb file being written, declaration by declaration.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    pub sorts: Vec<u8>,
+    pub terms: Vec<TermSpec>,
+    pub asserts: Vec<AssertSpec>,
+    pub stmts: Vec<(u8, Vec<u8>)>,
+    /// The name of every sort, term and assertion, for the index.
+    pub names: IndexNames,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    fn check_args(&self, args: &[Type]) -> Res<u64> {
+        let words = args.iter().flat_map(|ty| ty.bits().to_le_bytes()).collect::<Vec<u8>>();
+        check_type_words(&words, self.sorts.len() as u8)?;
+        check_binders(args.iter().copied(), |sort| Ok(self.sorts[sort as usize]))
+    }
+
+    fn check_unify(&self, unify: &UnifyStream) -> Res<()> {
+        for cmd in unify.cmds.iter() {
+            match *cmd {
+                UnifyCmd::Term { term_num, .. } => make_sure!((term_num as usize) < self.terms.len()),
+                UnifyCmd::Dummy { sort_id } => make_sure!((sort_id as usize) < self.sorts.len()),
+                UnifyCmd::Ref(_) | UnifyCmd::Hyp => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn check_proof(&self, proof: &ProofStream) -> Res<()> {
+        for cmd in proof.cmds.iter() {
+            match *cmd {
+                ProofCmd::Term { term_num, .. } => make_sure!((term_num as usize) < self.terms.len()),
+                ProofCmd::Thm { thm_num, .. } => make_sure!((thm_num as usize) < self.asserts.len()),
+                ProofCmd::Dummy { sort_num } => make_sure!((sort_num as usize) < self.sorts.len()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Declare a sort with modifiers `mods` (`SORT_PURE` and so on), returning its number.
+    pub fn sort(&mut self, name: &str, mods: u8) -> Res<u8> {
+        if self.sorts.len() >= 128 {
+            return Err(VerifErr::Msg("an mmb file can have at most 128 sorts".to_string()))
+        }
+        make_sure!(mods & !SORT_MODS_MASK == 0);
+        self.sorts.push(mods);
+        self.stmts.push((STMT_SORT, Vec::new()));
+        self.names.sorts.push(Some(name.to_string()));
+        Ok(self.sorts.len() as u8 - 1)
+    }
+
+    fn push_term(&mut self, name: &str, args: &[Type], ret: Type, def: Option<Vec<u8>>, cmd: u8, proof: Vec<u8>) -> Res<u32> {
+        let bvs = self.check_args(args)?;
+        make_sure!((ret.sort() as usize) < self.sorts.len());
+        make_sure!(!ret.is_bound());
+        make_sure!(ret.deps()? & !bvs == 0);
+        self.terms.push(TermSpec { args: args.to_vec(), ret, def });
+        self.stmts.push((cmd, proof));
+        self.names.terms.push(Some(name.to_string()));
+        Ok(self.terms.len() as u32 - 1)
+    }
+
+    /// Declare a term, returning its number.
+    pub fn term(&mut self, name: &str, args: &[Type], ret: Type) -> Res<u32> {
+        self.push_term(name, args, ret, None, STMT_DEF, Vec::new())
+    }
+
+    /// Declare a def whose value is built by `proof` and matched by `unify`, returning
+    /// its number.
+    pub fn def(&mut self, name: &str, args: &[Type], ret: Type, unify: &UnifyStream, proof: &ProofStream, local: bool) -> Res<u32> {
+        self.check_unify(unify)?;
+        self.check_proof(proof)?;
+        let cmd = if local { STMT_DEF | STMT_LOCAL } else { STMT_DEF };
+        self.push_term(name, args, ret, Some(unify.to_bytes()), cmd, proof.to_bytes())
+    }
+
+    fn push_assert(&mut self, name: &str, args: &[Type], unify: &UnifyStream, cmd: u8, proof: &ProofStream) -> Res<u32> {
+        self.check_args(args)?;
+        self.check_unify(unify)?;
+        self.check_proof(proof)?;
+        self.asserts.push(AssertSpec { args: args.to_vec(), unify: unify.to_bytes() });
+        self.stmts.push((cmd, proof.to_bytes()));
+        self.names.thms.push(Some(name.to_string()));
+        Ok(self.asserts.len() as u32 - 1)
+    }
+
+    /// Declare an axiom whose statement and hypotheses are built by `proof` and matched by
+    /// `unify`, returning its number.
+    pub fn axiom(&mut self, name: &str, args: &[Type], unify: &UnifyStream, proof: &ProofStream) -> Res<u32> {
+        self.push_assert(name, args, unify, STMT_AXIOM, proof)
+    }
+
+    /// Declare a theorem, returning its number.
+    pub fn theorem(&mut self, name: &str, args: &[Type], unify: &UnifyStream, proof: &ProofStream, local: bool) -> Res<u32> {
+        let cmd = if local { STMT_THM | STMT_LOCAL } else { STMT_THM };
+        self.push_assert(name, args, unify, cmd, proof)
+    }
+
+    /// The declarations of `outline`, decoded, for writing it out again (changed or not).
+    /// Nothing is checked; names come from the index, if there is one.
+    pub fn from_outline(outline: &Outline) -> Res<Builder> {
+        let mut b = Builder::new();
+        for (stmt, proof) in outline.declarations.iter() {
+            let mut body = ProofStream::new();
+            if !proof.is_null() {
+                for cmd in *proof {
+                    body.push(cmd?);
+                }
+            }
+            let body = if proof.is_null() { Vec::new() } else { body.to_bytes() };
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    make_sure!(num as usize == b.sorts.len());
+                    b.sorts.push(outline.get_sort_mods(num as usize)?.inner);
+                    b.stmts.push((STMT_SORT, body));
+                    b.names.sorts.push(outline.sort_name(num).map(|s| s.to_string()));
+                }
+                StmtCmd::TermDef { num, local } => {
+                    let num = none_err!(num)?;
+                    make_sure!(num as usize == b.terms.len());
+                    let term = outline.get_term_by_num(num)?;
+                    let def = if term.is_def() {
+                        let mut unify = UnifyStream::new();
+                        for cmd in term.unify() {
+                            unify.push(cmd?);
+                        }
+                        Some(unify.to_bytes())
+                    } else {
+                        None
+                    };
+                    b.terms.push(TermSpec { args: term.args_no_ret().collect(), ret: term.ret(), def });
+                    b.stmts.push((if local { STMT_DEF | STMT_LOCAL } else { STMT_DEF }, body));
+                    b.names.terms.push(outline.term_name(num).map(|s| s.to_string()));
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    make_sure!(num as usize == b.asserts.len());
+                    let assert = outline.get_assert_by_num(num)?;
+                    let mut unify = UnifyStream::new();
+                    for cmd in assert.unify() {
+                        unify.push(cmd?);
+                    }
+                    b.asserts.push(AssertSpec { args: assert.args().collect(), unify: unify.to_bytes() });
+                    let cmd = match *stmt {
+                        StmtCmd::Thm { local: true, .. } => STMT_THM | STMT_LOCAL,
+                        StmtCmd::Thm { .. } => STMT_THM,
+                        _ => STMT_AXIOM,
+                    };
+                    b.stmts.push((cmd, body));
+                    b.names.thms.push(outline.assert_name(num).map(|s| s.to_string()));
+                }
+            }
+        }
+        Ok(b)
+    }
+
+    /// The file, with an index of the names given if `with_index` is set.
+    pub fn to_bytes(&self, with_index: bool) -> Res<Vec<u8>> {
+        let out = layout(MM0B_MAGIC, 1, &self.sorts, &self.terms, &self.asserts, &self.stmts)?;
+        if !with_index {
+            return Ok(out)
+        }
+        let file_data = FileData::new_from_bytes(out, Vec::new());
+        Outline::new_from(&file_data)?.with_new_index(&self.names)
+    }
+}
+
+#[test]
+fn build1() {
+    use crate::mmb::SORT_PROVABLE;
+    use crate::testgen::{ Fixture, reg, bound };
+    let wff = reg(0, 0);
+    let mut b = Builder::new();
+    let s = b.sort("wff", SORT_PROVABLE).unwrap();
+    let im = b.term("im", &[wff, wff], reg(s, 0)).unwrap();
+    let mut unify = UnifyStream::new();
+    unify.push(UnifyCmd::Term { term_num: im, save: false }).push(UnifyCmd::Ref(0)).push(UnifyCmd::Ref(0));
+    let mut proof = ProofStream::new();
+    proof.push(ProofCmd::Ref(0)).push(ProofCmd::Ref(0)).push(ProofCmd::Term { term_num: im, save: false });
+    let ax = b.axiom("ax", &[wff], &unify, &proof).unwrap();
+    let mut unify = UnifyStream::new();
+    unify.push(UnifyCmd::Term { term_num: im, save: false });
+    for _ in 0..2 {
+        unify.push(UnifyCmd::Term { term_num: im, save: false }).push(UnifyCmd::Ref(0)).push(UnifyCmd::Ref(1));
+    }
+    let mut proof = ProofStream::new();
+    proof
+        .push(ProofCmd::Ref(0)).push(ProofCmd::Ref(1)).push(ProofCmd::Term { term_num: im, save: true })
+        .push(ProofCmd::Ref(2)).push(ProofCmd::Ref(2)).push(ProofCmd::Term { term_num: im, save: false })
+        .push(ProofCmd::Thm { thm_num: ax, save: false });
+    b.theorem("th", &[wff, wff], &unify, &proof, false).unwrap();
+    // The same file `testgen` writes by hand.
+    assert_eq!(b.to_bytes(false).unwrap(), Fixture::valid().to_bytes());
+
+    let bytes = b.to_bytes(true).unwrap();
+    let file_data = FileData::new_from_bytes(bytes, Vec::new());
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+    assert_eq!(outline.assert_name(1), Some("th"));
+
+    // A theorem can't refer to itself, and a bound variable has to be numbered in order.
+    let mut proof = ProofStream::new();
+    proof.push(ProofCmd::Thm { thm_num: 2, save: false });
+    assert!(b.theorem("bad", &[wff], &unify, &proof, false).is_err());
+    assert!(b.term("bad", &[bound(s, 2)], wff).is_err());
+    assert_eq!(b.asserts.len(), 2);
+}
+
+#[test]
+fn round_trip1() {
+    use crate::testgen::random_env;
+    for seed in 0..64 {
+        let (b, expected) = random_env(seed);
+        let bytes = b.to_bytes(true).unwrap();
+        let file_data = FileData::new_from_bytes(bytes.clone(), Vec::new());
+        let outline = Outline::new_from(&file_data).unwrap();
+        let errs = crate::verify_serial(&outline, &outline.declarations);
+        assert!(errs.is_empty(), "seed {}: {:?}", seed, errs);
+        assert_eq!(outline.certificate().unwrap(), expected, "seed {}", seed);
+        // Reading the file back and writing it again changes nothing.
+        let again = Builder::from_outline(&outline).unwrap();
+        assert_eq!(again.to_bytes(true).unwrap(), bytes, "seed {}", seed);
+        assert_eq!(again.to_bytes(false).unwrap(), b.to_bytes(false).unwrap(), "seed {}", seed);
+    }
+}
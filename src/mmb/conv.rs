@@ -0,0 +1,192 @@
+//! Definitional equality outside of any proof: whether two expressions are the same up to
+//! unfolding definitions, for tools that want to ask the question of a file directly
+//! instead of writing a conversion proof and running it.
+//!
+//! A proof's `Conv`/`Unfold` steps say which definitions to unfold; here we have to find
+//! that out ourselves. When the heads differ (or the arguments don't match), the side whose
+//! head is the later definition is unfolded, which terminates since a definition can only
+//! mention terms declared before it. Unfolding can introduce dummy variables, which are
+//! matched up to renaming.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+use crate::Outline;
+use crate::mmb::{ MmbExpr, MmbItem };
+use crate::util::{ Res, Type, VerifErr };
+use crate::make_sure;
+
+struct ConvCx<'b> {
+    bump: &'b Bump,
+    /// Variables numbered at least this high are dummies from unfolding.
+    first_dummy: usize,
+    next_dummy: Option<usize>,
+    /// Dummies on the left matched with dummies on the right so far.
+    renaming: Vec<(usize, usize)>,
+}
+
+impl<'b> ConvCx<'b> {
+    fn same_var(&mut self, l: usize, r: usize) -> bool {
+        if l < self.first_dummy || r < self.first_dummy {
+            return l == r
+        }
+        match self.renaming.iter().find(|(x, y)| *x == l || *y == r) {
+            Some(pair) => *pair == (l, r),
+            None => {
+                self.renaming.push((l, r));
+                true
+            }
+        }
+    }
+}
+
+fn as_expr<'b>(item: &'b MmbItem<'b>) -> Res<&'b MmbExpr<'b>> {
+    match item {
+        MmbItem::Expr(e) => Ok(e),
+        _ => Err(VerifErr::Msg("expected an expression".to_string())),
+    }
+}
+
+fn max_var(e: &MmbExpr) -> Res<usize> {
+    match e {
+        MmbExpr::Var { idx, .. } => Ok(*idx),
+        MmbExpr::App { args, .. } => args.iter().try_fold(0, |acc, arg| Ok(acc.max(max_var(as_expr(arg)?)?))),
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// The application of `term_num` to `args`, with the type the verifier would give it.
+    pub fn app<'b>(&self, bump: &'b Bump, term_num: u32, args: &[&'b MmbExpr<'b>]) -> Res<&'b MmbExpr<'b>> {
+        let term = self.get_term_by_num(term_num)?;
+        term.args_no_ret().expect_len(args.len(), format_args!("term {}", term_num))?;
+        let mut items = BumpVec::new_in(bump);
+        let mut ty = Type::new_with_sort(term.sort());
+        for (sig, e) in term.args_no_ret().zip(args.iter()) {
+            let item = &*bump.alloc(MmbItem::Expr(e));
+            make_sure!(item.get_ty()?.coercible_to_binder(sig));
+            if !sig.is_bound() {
+                ty |= item.get_deps().or(item.get_bound_digit())?;
+            }
+            items.push(item);
+        }
+        Ok(&*bump.alloc(MmbExpr::App { term_num, args: items.into_bump_slice(), ty }))
+    }
+
+    /// The body of a def applied to `args`, or `None` if `e` isn't an application of a def.
+    /// Dummies in the body become bound variables numbered from `next_dummy`.
+    pub fn unfold<'b>(&self, bump: &'b Bump, e: &'b MmbExpr<'b>, next_dummy: &mut Option<usize>) -> Res<Option<&'b MmbExpr<'b>>> {
+        let (term_num, args) = match e {
+            MmbExpr::App { term_num, args, .. } => (*term_num, *args),
+            MmbExpr::Var { .. } => return Ok(None),
+        };
+        let term = self.get_term_by_num(term_num)?;
+        if !term.is_def() {
+            return Ok(None)
+        }
+        let mut heap = Vec::with_capacity(args.len());
+        for arg in args.iter() {
+            heap.push(Some(as_expr(arg)?));
+        }
+        let mut it = term.unify;
+        self.read_expr(bump, &mut it, &mut heap, next_dummy).map(Some)
+    }
+
+    /// Whether `lhs` and `rhs` are equal up to unfolding definitions and renaming the dummy
+    /// variables unfolding introduces. Other variables are equal if their `idx`s are.
+    pub fn check_conv<'b>(&self, bump: &'b Bump, lhs: &'b MmbExpr<'b>, rhs: &'b MmbExpr<'b>) -> Res<bool> {
+        let first_dummy = max_var(lhs)?.max(max_var(rhs)?) + 1;
+        let mut cx = ConvCx {
+            bump,
+            first_dummy,
+            next_dummy: Some(first_dummy),
+            renaming: Vec::new(),
+        };
+        self.conv(&mut cx, lhs, rhs)
+    }
+
+    fn def_head(&self, e: &MmbExpr) -> Res<Option<u32>> {
+        match e {
+            MmbExpr::App { term_num, .. } if self.get_term_by_num(*term_num)?.is_def() => Ok(Some(*term_num)),
+            _ => Ok(None),
+        }
+    }
+
+    fn conv<'b>(&self, cx: &mut ConvCx<'b>, lhs: &'b MmbExpr<'b>, rhs: &'b MmbExpr<'b>) -> Res<bool> {
+        if core::ptr::eq(lhs, rhs) {
+            return Ok(true)
+        }
+        match (lhs, rhs) {
+            (MmbExpr::Var { idx: l, .. }, MmbExpr::Var { idx: r, .. }) => return Ok(cx.same_var(*l, *r)),
+            (MmbExpr::App { term_num: t1, args: as1, .. }, MmbExpr::App { term_num: t2, args: as2, .. })
+            if t1 == t2 && as1.len() == as2.len() => {
+                let saved = cx.renaming.len();
+                let mut same = true;
+                for (l, r) in as1.iter().zip(as2.iter()) {
+                    if !self.conv(cx, as_expr(l)?, as_expr(r)?)? {
+                        same = false;
+                        break
+                    }
+                }
+                if same {
+                    return Ok(true)
+                }
+                cx.renaming.truncate(saved);
+            }
+            _ => {}
+        }
+        let unfold_lhs = match (self.def_head(lhs)?, self.def_head(rhs)?) {
+            (None, None) => return Ok(false),
+            (Some(l), Some(r)) => l >= r,
+            (l, _) => l.is_some(),
+        };
+        if unfold_lhs {
+            let lhs = self.unfold(cx.bump, lhs, &mut cx.next_dummy)?.ok_or(VerifErr::Unreachable(file!(), line!()))?;
+            self.conv(cx, lhs, rhs)
+        } else {
+            let rhs = self.unfold(cx.bump, rhs, &mut cx.next_dummy)?.ok_or(VerifErr::Unreachable(file!(), line!()))?;
+            self.conv(cx, lhs, rhs)
+        }
+    }
+}
+
+#[test]
+fn check_conv1() {
+    // sort s; term im (a b: s): s; def nn (a: s): s = im a a; def nn2 (a: s): s = nn a;
+    let mut mmb = Vec::new();
+    for word in [0x4230_4D4D_u32, 0x0000_0101, 3, 0, 48, 72, 144, 0, 0, 0].iter() {
+        mmb.extend_from_slice(&word.to_le_bytes());
+    }
+    mmb.extend_from_slice(&[crate::mmb::SORT_PROVABLE, 0, 0, 0, 0, 0, 0, 0]);
+    // The term table: number of arguments, sort (with the def bit), and where the arguments are.
+    mmb.extend_from_slice(&[2, 0, 0x00, 0, 72, 0, 0, 0]);
+    mmb.extend_from_slice(&[1, 0, 0x80, 0, 96, 0, 0, 0]);
+    mmb.extend_from_slice(&[1, 0, 0x80, 0, 120, 0, 0, 0]);
+    mmb.extend_from_slice(&[0; 24]);
+    mmb.extend_from_slice(&[0; 16]);
+    mmb.extend_from_slice(&[0x30, 0x32, 0x32, 0, 0, 0, 0, 0]);
+    mmb.extend_from_slice(&[0; 16]);
+    mmb.extend_from_slice(&[0x70, 1, 0x32, 0, 0, 0, 0, 0]);
+    assert_eq!(mmb.len(), 144);
+    // The proof stream: the sort, `im`, and the proofs of the two defs' bodies.
+    mmb.extend_from_slice(&[0x44, 2, 0x45, 2]);
+    mmb.extend_from_slice(&[0x45, 6, 0x12, 0x12, 0x10, 0]);
+    mmb.extend_from_slice(&[0x45, 6, 0x12, 0x50, 1, 0]);
+    mmb.push(0);
+
+    let file_data = crate::fs::FileData::new_from_bytes(mmb, Vec::new());
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+
+    let bump = Bump::new();
+    let x = &*bump.alloc(MmbExpr::Var { idx: 0, ty: Type { inner: 0 } });
+    let y = &*bump.alloc(MmbExpr::Var { idx: 1, ty: Type { inner: 0 } });
+    let im_xx = outline.app(&bump, 0, &[x, x]).unwrap();
+    let nn_x = outline.app(&bump, 1, &[x]).unwrap();
+    let nn2_x = outline.app(&bump, 2, &[x]).unwrap();
+    assert!(outline.check_conv(&bump, nn_x, im_xx).unwrap());
+    assert!(outline.check_conv(&bump, im_xx, nn2_x).unwrap());
+    assert!(outline.check_conv(&bump, outline.app(&bump, 0, &[nn_x, y]).unwrap(), outline.app(&bump, 0, &[nn2_x, y]).unwrap()).unwrap());
+    assert!(!outline.check_conv(&bump, nn_x, outline.app(&bump, 1, &[y]).unwrap()).unwrap());
+    assert!(!outline.check_conv(&bump, nn2_x, outline.app(&bump, 0, &[x, y]).unwrap()).unwrap());
+    assert!(outline.app(&bump, 0, &[x]).is_err());
+}
@@ -0,0 +1,208 @@
+//! Heap coverage for proof streams: for each declaration, which heap entries are never
+//! `Ref`'d, which saves could be dropped, and how many bytes the proof stream would
+//! shrink by if they were. Where `lint` counts wasteful patterns across the whole file,
+//! this is per declaration, for an exporter author looking for the proofs to fix first.
+//!
+//! An unreferenced entry below a referenced one is a dead slot: it makes every later `Ref`
+//! index bigger than it has to be, which can push a `Ref` into a longer encoding. The
+//! savings count the dropped `Save` commands and the bytes of any `Ref` or `ConvRef` that
+//! would get shorter once the dropped entries are renumbered away. Arguments, dummies and
+//! hypotheses are always on the heap, so they're reported but never dropped; neither are
+//! `ConvSave`s, which also pop the stack.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::{ Res, VerifErr };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::{ ProofIter, ProofCmd };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SlotKind {
+    Arg,
+    Dummy,
+    Hyp,
+    /// Put there by `Save`, `TermSave`, `ThmSave` or `ConvSave`.
+    Save,
+}
+
+impl SlotKind {
+    pub const ALL: [SlotKind; 4] = [SlotKind::Arg, SlotKind::Dummy, SlotKind::Hyp, SlotKind::Save];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SlotKind::Arg => "arg",
+            SlotKind::Dummy => "dummy",
+            SlotKind::Hyp => "hyp",
+            SlotKind::Save => "saved",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeclCoverage {
+    pub decl: StmtCmd,
+    /// The size the heap grows to.
+    pub heap: usize,
+    /// How many entries of each kind are never referenced, indexed by `SlotKind as usize`.
+    pub unreferenced: [usize; 4],
+    /// The offsets of the saves whose entries are never referenced.
+    pub unused_saves: Vec<usize>,
+    /// Unreferenced entries with a referenced entry above them.
+    pub dead_slots: usize,
+    /// How many bytes dropping the unused saves would take off the proof stream.
+    pub savings: usize,
+}
+
+impl DeclCoverage {
+    pub fn unreferenced(&self, kind: SlotKind) -> usize {
+        self.unreferenced[kind as usize]
+    }
+
+    /// True if there's nothing to report.
+    pub fn is_tight(&self) -> bool {
+        self.unused_saves.is_empty() && self.dead_slots == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    kind: SlotKind,
+    /// For an explicit save that could be dropped: where it is, and how many bytes
+    /// dropping it saves by itself.
+    droppable: Option<(usize, usize)>,
+    refs: u32,
+}
+
+impl Slot {
+    fn new(kind: SlotKind, droppable: Option<(usize, usize)>) -> Self {
+        Slot { kind, droppable, refs: 0 }
+    }
+}
+
+/// The length of a command's data field, as `write::push_cmd` encodes it.
+fn data_len(data: u32) -> usize {
+    match data {
+        0 => 0,
+        1..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        _ => 4,
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// How many arguments are on the heap when `stmt`'s proof starts, or `None` if it
+    /// doesn't have a proof to run.
+    pub(crate) fn heap_args(&self, stmt: StmtCmd) -> Res<Option<usize>> {
+        match stmt {
+            StmtCmd::Sort {..} => Ok(None),
+            StmtCmd::TermDef { num, .. } => {
+                let term = self.get_term_by_num(none_err!(num)?)?;
+                Ok(if term.is_def() { Some(term.args_no_ret().len()) } else { None })
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => Ok(Some(self.get_assert_by_num(none_err!(num)?)?.args().len())),
+        }
+    }
+
+    /// The coverage of every def, axiom and theorem proof, in file order.
+    pub fn coverage(&self) -> Res<Vec<DeclCoverage>> {
+        let mut out = Vec::new();
+        for (stmt, proof) in self.declarations.iter() {
+            if let (false, Some(num_args)) = (proof.is_null(), self.heap_args(*stmt)?) {
+                out.push(self.proof_coverage(*stmt, *proof, num_args)?);
+            }
+        }
+        Ok(out)
+    }
+
+    /// The coverage of one proof stream, which starts with `num_args` arguments on the heap.
+    pub fn proof_coverage(&self, stmt: StmtCmd, proof: ProofIter, num_args: usize) -> Res<DeclCoverage> {
+        let mut heap = vec![Slot::new(SlotKind::Arg, None); num_args];
+        // Every `Ref` and `ConvRef` index, for renumbering.
+        let mut refs = Vec::new();
+        let mut it = proof;
+        loop {
+            let pos = it.pos;
+            let cmd = match it.next() {
+                None => break,
+                Some(cmd) => cmd?,
+            };
+            match cmd {
+                ProofCmd::Ref(i) | ProofCmd::ConvRef(i) => {
+                    none_err!(heap.get_mut(i as usize))?.refs += 1;
+                    refs.push(i);
+                }
+                ProofCmd::Dummy {..} => heap.push(Slot::new(SlotKind::Dummy, None)),
+                ProofCmd::Hyp => heap.push(Slot::new(SlotKind::Hyp, None)),
+                ProofCmd::Save => heap.push(Slot::new(SlotKind::Save, Some((pos, it.pos - pos)))),
+                ProofCmd::ConvSave => heap.push(Slot::new(SlotKind::Save, None)),
+                // Dropping the save flag leaves a command of the same length.
+                ProofCmd::Term { save: true, .. } | ProofCmd::Thm { save: true, .. } => {
+                    heap.push(Slot::new(SlotKind::Save, Some((pos, 0))))
+                }
+                _ => {}
+            }
+        }
+
+        let mut unreferenced = [0; 4];
+        let mut unused_saves = Vec::new();
+        let mut savings = 0;
+        // For each slot, how many dropped slots are below it.
+        let mut dropped_below = Vec::with_capacity(heap.len());
+        let mut dropped = 0;
+        for slot in heap.iter() {
+            dropped_below.push(dropped);
+            if slot.refs != 0 {
+                continue
+            }
+            unreferenced[slot.kind as usize] += 1;
+            if slot.kind == SlotKind::Save {
+                if let Some((pos, bytes)) = slot.droppable {
+                    unused_saves.push(pos);
+                    savings += bytes;
+                    dropped += 1;
+                }
+            }
+        }
+        for i in refs {
+            savings += data_len(i) - data_len(i - dropped_below[i as usize]);
+        }
+        let live_until = heap.iter().rposition(|s| s.refs != 0).map(|n| n + 1).unwrap_or(0);
+        let dead_slots = heap[..live_until].iter().filter(|s| s.refs == 0).count();
+        Ok(DeclCoverage { decl: stmt, heap: heap.len(), unreferenced, unused_saves, dead_slots, savings })
+    }
+}
+
+#[test]
+fn coverage1() {
+    use crate::testgen::stream;
+    use crate::mmb::proof::{ PROOF_REF, PROOF_SAVE, PROOF_HYP, PROOF_THM_SAVE, PROOF_DUMMY, PROOF_TERM_SAVE };
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let report = outline.coverage().unwrap();
+    assert_eq!(report.len(), 4);
+    assert!(report.iter().all(|c| c.is_tight()));
+
+    let stmt = StmtCmd::Thm { num: Some(0), local: false };
+    // With one argument on the heap, one byte per command:
+    let buf = stream(&[
+        (PROOF_REF, 0), (PROOF_SAVE, 0), (PROOF_HYP, 0), (PROOF_SAVE, 0),
+        (PROOF_THM_SAVE, 0), (PROOF_DUMMY, 0), (PROOF_THM_SAVE, 0),
+    ]);
+    let proof = ProofIter::new(&buf, 0, buf.len());
+    let cov = outline.proof_coverage(stmt, proof, 1).unwrap();
+    assert_eq!(cov.heap, 7);
+    assert_eq!(SlotKind::ALL.iter().map(|k| cov.unreferenced(*k)).collect::<Vec<_>>(), vec![0, 1, 1, 4]);
+    assert_eq!(cov.unused_saves, vec![1, 3, 4, 6]);
+    assert_eq!((cov.dead_slots, cov.savings), (0, 2));
+
+    // Without the first save, `Ref 1` becomes `Ref 0`, which has no data byte.
+    let buf = stream(&[(PROOF_TERM_SAVE, 0), (PROOF_SAVE, 0), (PROOF_REF, 1)]);
+    let proof = ProofIter::new(&buf, 0, buf.len());
+    let cov = outline.proof_coverage(stmt, proof, 0).unwrap();
+    assert_eq!(cov.unused_saves, vec![0]);
+    assert_eq!((cov.dead_slots, cov.savings), (1, 1));
+    assert!(!cov.is_tight());
+}
@@ -0,0 +1,113 @@
+//! Walking the declarations of an mmb file without verifying them, for tools that want
+//! to read a file rather than check it. Each declaration comes with a `DeclRef` giving typed
+//! access to the parts of it that live in the sort, term, and theorem tables and in the
+//! proof stream. Nothing here has been checked; a file read this way is only known to be
+//! well-formed once it has been verified.
+
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::query::DeclKind;
+use crate::mmb::proof::ProofIter;
+use crate::mmb::unify::UnifyIter;
+use crate::util::{ Res, Type, Args, Mods, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeclRef<'a> {
+    pub kind: DeclKind,
+    /// The sort, term, or assertion number.
+    pub num: u32,
+    /// From the index, if there is one.
+    pub name: Option<&'a str>,
+    /// The arguments, not including a term's return type. Empty for sorts.
+    pub args: Args<'a>,
+    /// The return type of a term or def.
+    pub ret: Option<Type>,
+    /// The modifiers of a sort.
+    pub mods: Option<Mods>,
+    /// The unify stream of a def, axiom, or theorem.
+    pub unify: Option<UnifyIter<'a>>,
+    /// The proof stream; `None` for sorts and terms, which don't have one.
+    pub proof: Option<ProofIter<'a>>,
+}
+
+impl<'a> Outline<'a> {
+    /// The declaration `stmt`, whose proof stream is `proof`.
+    pub fn decl_ref(&self, stmt: StmtCmd, proof: ProofIter<'a>) -> Res<DeclRef<'a>> {
+        let proof = if proof.is_null() { None } else { Some(proof) };
+        let name = self.stmt_name(stmt);
+        Ok(match stmt {
+            StmtCmd::Sort { num } => {
+                let num = none_err!(num)?;
+                DeclRef {
+                    kind: DeclKind::Sort,
+                    num: num as u32,
+                    name,
+                    args: Args::default(),
+                    ret: None,
+                    mods: Some(self.get_sort_mods(num as usize)?),
+                    unify: None,
+                    proof,
+                }
+            }
+            StmtCmd::TermDef { num, .. } => {
+                let num = none_err!(num)?;
+                let term = self.get_term_by_num(num)?;
+                DeclRef {
+                    kind: if term.is_def() { DeclKind::Def } else { DeclKind::Term },
+                    num,
+                    name,
+                    args: term.args_no_ret(),
+                    ret: Some(term.ret()),
+                    mods: None,
+                    unify: if term.is_def() { Some(term.unify) } else { None },
+                    proof,
+                }
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                let num = none_err!(num)?;
+                let assert = self.get_assert_by_num(num)?;
+                DeclRef {
+                    kind: if let StmtCmd::Axiom { .. } = stmt { DeclKind::Axiom } else { DeclKind::Thm },
+                    num,
+                    name,
+                    args: assert.args(),
+                    ret: None,
+                    mods: None,
+                    unify: Some(assert.unify()),
+                    proof,
+                }
+            }
+        })
+    }
+
+    /// Every declaration in the order it appears in the proof stream, without verifying
+    /// anything. An item is an error if the declaration points outside the file.
+    pub fn declarations<'o>(&'o self) -> impl Iterator<Item = Res<(StmtCmd, DeclRef<'a>)>> + 'o {
+        self.declarations.iter().map(move |(stmt, proof)| Ok((*stmt, self.decl_ref(*stmt, *proof)?)))
+    }
+}
+
+#[test]
+fn declarations1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let decls = outline.declarations().collect::<Res<Vec<_>>>().unwrap();
+    assert_eq!(decls.len(), 7);
+
+    let (_, wff) = decls[0];
+    assert_eq!((wff.kind, wff.name), (DeclKind::Sort, Some("wff")));
+    assert_eq!(wff.mods, outline.get_sort_mods(0).ok());
+    assert_eq!(wff.args.count(), 0);
+
+    let (_, im) = decls[1];
+    assert_eq!((im.kind, im.num, im.name), (DeclKind::Term, 0, Some("im")));
+    assert_eq!(im.args.count(), 2);
+    assert_eq!(im.ret.map(|t| t.sort()), Some(0));
+    assert!(im.unify.is_none() && im.proof.is_none());
+
+    let (_, ax_mp) = decls[6];
+    assert_eq!((ax_mp.kind, ax_mp.num, ax_mp.name), (DeclKind::Axiom, 3, Some("ax_mp")));
+    assert_eq!(ax_mp.args.count(), 2);
+    assert!(ax_mp.unify.is_some() && ax_mp.proof.is_some());
+}
@@ -0,0 +1,253 @@
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::Res;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::{ ProofIter, ProofCmd };
+use crate::mmb::unify::{ UnifyIter, UnifyCmd };
+use crate::none_err;
+use crate::util::VerifErr;
+
+/// The terms and assertions that a single declaration refers to, either from
+/// its proof stream or from its own unify stream. Sorts are not tracked since
+/// every sort declaration is trivially checkable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Deps {
+    pub terms: Vec<u32>,
+    pub thms: Vec<u32>,
+}
+
+impl Deps {
+    fn add_term(&mut self, term_num: u32) {
+        if !self.terms.contains(&term_num) {
+            self.terms.push(term_num)
+        }
+    }
+
+    fn add_thm(&mut self, thm_num: u32) {
+        if !self.thms.contains(&thm_num) {
+            self.thms.push(thm_num)
+        }
+    }
+
+    fn add_unify(&mut self, unify: UnifyIter) -> Res<()> {
+        for maybe_cmd in unify {
+            if let UnifyCmd::Term { term_num, .. } = maybe_cmd? {
+                self.add_term(term_num)
+            }
+        }
+        Ok(())
+    }
+
+    fn add_proof(&mut self, proof: ProofIter) -> Res<()> {
+        // A null iterator has no commands, not even the terminating one.
+        if proof.is_null() {
+            return Ok(())
+        }
+        for maybe_cmd in proof {
+            match maybe_cmd? {
+                ProofCmd::Term { term_num, .. } => self.add_term(term_num),
+                ProofCmd::Thm { thm_num, .. } => self.add_thm(thm_num),
+                _ => continue
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Collect the direct dependencies of a declaration without verifying it.
+    pub fn direct_deps(&self, stmt: StmtCmd, proof: ProofIter<'a>) -> Res<Deps> {
+        let mut deps = Deps::default();
+        match stmt {
+            StmtCmd::Sort {..} => {},
+            StmtCmd::TermDef { num, .. } => {
+                let term = self.get_term_by_num(none_err!(num)?)?;
+                // Only defs have a unify stream after their arguments.
+                if term.is_def() {
+                    deps.add_unify(term.unify())?;
+                    deps.add_proof(proof)?;
+                }
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                let assert = self.get_assert_by_num(none_err!(num)?)?;
+                deps.add_unify(assert.unify())?;
+                deps.add_proof(proof)?;
+            }
+        }
+        Ok(deps)
+    }
+
+    /// Close a set of declarations (given by their positions in `declarations`) under
+    /// dependencies, returning which declarations are in the closure.
+    pub fn with_deps(&self, roots: impl IntoIterator<Item = usize>) -> Res<Vec<bool>> {
+        let (term_pos, thm_pos) = self.decl_positions();
+        let mut selected = vec![false; self.declarations.len()];
+        let mut todo: Vec<usize> = roots.into_iter().collect();
+        while let Some(idx) = todo.pop() {
+            if *none_err!(selected.get(idx))? {
+                continue
+            }
+            selected[idx] = true;
+            let (stmt, proof) = self.declarations[idx];
+            let deps = self.direct_deps(stmt, proof)?;
+            for term_num in deps.terms {
+                todo.push(*none_err!(term_pos.get(term_num as usize))?);
+            }
+            for thm_num in deps.thms {
+                todo.push(*none_err!(thm_pos.get(thm_num as usize))?);
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Maps each term number and assertion number to the position of the
+    /// declaration that introduces it in `declarations`.
+    pub fn decl_positions(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut terms = Vec::with_capacity(self.header.num_terms as usize);
+        let mut thms = Vec::with_capacity(self.header.num_thms as usize);
+        for (idx, (stmt, _)) in self.declarations.iter().enumerate() {
+            match stmt {
+                StmtCmd::Sort {..} => {},
+                StmtCmd::TermDef {..} => terms.push(idx),
+                StmtCmd::Axiom {..} | StmtCmd::Thm {..} => thms.push(idx),
+            }
+        }
+        (terms, thms)
+    }
+
+    /// With `options.keep_going`, treat each declaration in `decls` that failed as
+    /// unavailable, and report every later one that uses it, directly or through another
+    /// one reported here. Each declaration is checked against the table entries of what
+    /// it uses, not their proofs, so without this a theorem that uses a broken lemma is
+    /// accepted as if the lemma were fine. `decls` has to be in declaration order.
+    pub fn flag_dependents(&self, decls: &[(StmtCmd, ProofIter<'a>)], errs: &mut Vec<VerifErr>) {
+        // The failed declaration each term and assertion is unavailable because of.
+        let mut terms: Vec<Option<StmtCmd>> = vec![None; self.header.num_terms as usize];
+        let mut thms: Vec<Option<StmtCmd>> = vec![None; self.header.num_thms as usize];
+        for (stmt, proof) in decls.iter() {
+            let failed = if !self.is_declar_done(*stmt) {
+                Some(*stmt)
+            } else {
+                let deps = match self.direct_deps(*stmt, *proof) {
+                    Ok(deps) => deps,
+                    Err(_) => continue,
+                };
+                let failed = deps.terms.iter().find_map(|n| terms.get(*n as usize).copied().flatten())
+                    .or_else(|| deps.thms.iter().find_map(|n| thms.get(*n as usize).copied().flatten()));
+                if let Some(failed) = failed {
+                    errs.push(VerifErr::DependsOnFailed { decl: *stmt, failed });
+                }
+                failed
+            };
+            let slot = match stmt {
+                StmtCmd::Sort {..} => None,
+                StmtCmd::TermDef { num, .. } => num.and_then(|n| terms.get_mut(n as usize)),
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => num.and_then(|n| thms.get_mut(n as usize)),
+            };
+            if let Some(slot) = slot {
+                *slot = failed;
+            }
+        }
+    }
+
+    /// A cycle of defs each of whose bodies uses the next, with the last one's using the
+    /// first, by term number, if there is one. Declaring everything before it's used
+    /// already rules this out, but this only looks at the term table, so it doesn't
+    /// depend on how the proof stream is checked. The search keeps its own stack, since a
+    /// hostile file can make the chains of defs as long as it likes.
+    pub fn def_cycle(&self) -> Res<Option<Vec<u32>>> {
+        let mut bodies = Vec::with_capacity(self.header.num_terms as usize);
+        for num in 0..self.header.num_terms {
+            let term = self.get_term_by_num(num)?;
+            let mut deps = Deps::default();
+            if term.is_def() {
+                deps.add_unify(term.unify())?;
+            }
+            bodies.push(deps.terms);
+        }
+        // 0 if not reached yet, 1 if on the current path, 2 if done.
+        let mut state = vec![0u8; bodies.len()];
+        for root in 0..bodies.len() {
+            if state[root] != 0 {
+                continue
+            }
+            state[root] = 1;
+            // Each def on the path, and how many of its body's terms have been followed.
+            let mut path = vec![(root, 0usize)];
+            while let Some(&(node, next)) = path.last() {
+                let t = match bodies[node].get(next) {
+                    Some(t) => *t as usize,
+                    None => {
+                        state[node] = 2;
+                        path.pop();
+                        continue
+                    }
+                };
+                if let Some(last) = path.last_mut() {
+                    last.1 += 1;
+                }
+                // A term past the end of the table is reported when the def is checked.
+                match state.get(t) {
+                    Some(0) => {
+                        state[t] = 1;
+                        path.push((t, 0));
+                    }
+                    Some(1) => {
+                        let start = none_err!(path.iter().position(|(p, _)| *p == t))?;
+                        return Ok(Some(path[start..].iter().map(|(p, _)| *p as u32).collect()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Report a cycle found by `def_cycle`.
+    pub fn check_def_cycles(&self, errs: &mut Vec<VerifErr>) {
+        match self.def_cycle() {
+            Ok(None) => {}
+            Ok(Some(cycle)) => errs.push(VerifErr::DefCycle { cycle }),
+            Err(e) => errs.push(e),
+        }
+    }
+}
+
+#[test]
+fn def_cycle1() {
+    use crate::testgen::{ Fixture, TermSpec, stream, reg };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF };
+    use crate::util::ErrKind;
+    let cycle = |fx: &Fixture| fx.with_outline(|outline| {
+        let errs = crate::verify_serial(outline, &outline.declarations);
+        assert_eq!(errs.iter().any(|e| e.kind() == ErrKind::DefCycle), outline.def_cycle().unwrap().is_some());
+        outline.def_cycle().unwrap()
+    });
+    let mut fx = Fixture::unfolding(1);
+    assert_eq!(cycle(&fx), None);
+    // `d` unfolds to itself.
+    fx.terms[1].def = Some(stream(&[(UNIFY_TERM, 1), (UNIFY_REF, 0), (UNIFY_REF, 1)]));
+    assert_eq!(cycle(&fx), Some(vec![1]));
+    // `d` unfolds to `e`, which unfolds back to `d`.
+    let wff = reg(0, 0);
+    fx.terms[1].def = Some(stream(&[(UNIFY_TERM, 2), (UNIFY_REF, 0), (UNIFY_REF, 1)]));
+    fx.terms.push(TermSpec { args: vec![wff, wff], ret: wff, def: Some(stream(&[(UNIFY_TERM, 1), (UNIFY_REF, 1), (UNIFY_REF, 0)])) });
+    assert_eq!(cycle(&fx), Some(vec![1, 2]));
+}
+
+#[test]
+fn flag_dependents1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM };
+    use crate::util::ErrKind;
+    let run = |fx: &Fixture, keep_going: bool| fx.with_outline(|outline| {
+        outline.options.keep_going = keep_going;
+        crate::verify_serial(outline, &outline.declarations).iter().filter(|e| e.kind() == ErrKind::DependsOnFailed).count()
+    });
+    assert_eq!(run(&Fixture::valid(), true), 0);
+    // The axiom leaves an extra `a` on the stack, and the theorem uses it.
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0)]);
+    assert_eq!(run(&fx, false), 0);
+    assert_eq!(run(&fx, true), 1);
+}
@@ -0,0 +1,152 @@
+//! A disassembler for the unify and proof streams of a declaration, for debugging
+//! exporters. Each command is printed on its own line with its offset in the file, its
+//! raw bytes, its mnemonic, and its operand, with the name of whatever the operand refers
+//! to when the index has one:
+//!```text
+//! proof @ 319
+//!       319  12              Ref 0
+//!       320  52 01           Ref 1
+//!       322  10              Term im (#0)
+//!```
+//! A command that can't be decoded ends the listing with an `error` line at its offset,
+//! since there's no telling where the next one would start.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::{ ProofIter, ProofCmd };
+use crate::mmb::unify::{ UnifyIter, UnifyCmd };
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn push_instr(out: &mut String, pos: usize, bytes: &[u8], text: &str) {
+    out.push_str(&format!("  {:>8}  {:<14}  {}\n", pos, hex_bytes(bytes), text));
+}
+
+impl<'a> Outline<'a> {
+    fn term_operand(&self, term_num: u32) -> String {
+        match self.term_name(term_num) {
+            Some(name) => format!("{} (#{})", name, term_num),
+            None => format!("#{}", term_num),
+        }
+    }
+
+    fn assert_operand(&self, thm_num: u32) -> String {
+        match self.assert_name(thm_num) {
+            Some(name) => format!("{} (#{})", name, thm_num),
+            None => format!("#{}", thm_num),
+        }
+    }
+
+    pub(crate) fn unify_text(&self, cmd: UnifyCmd) -> String {
+        match cmd {
+            UnifyCmd::Term { term_num, save: false } => format!("UTerm {}", self.term_operand(term_num)),
+            UnifyCmd::Term { term_num, save: true } => format!("UTermSave {}", self.term_operand(term_num)),
+            UnifyCmd::Ref(i) => format!("URef {}", i),
+            UnifyCmd::Dummy { sort_id } => format!("UDummy {}", self.sort_str(sort_id)),
+            UnifyCmd::Hyp => "UHyp".to_string(),
+        }
+    }
+
+    pub(crate) fn proof_text(&self, cmd: ProofCmd) -> String {
+        match cmd {
+            ProofCmd::Term { term_num, save: false } => format!("Term {}", self.term_operand(term_num)),
+            ProofCmd::Term { term_num, save: true } => format!("TermSave {}", self.term_operand(term_num)),
+            ProofCmd::Ref(i) => format!("Ref {}", i),
+            ProofCmd::Dummy { sort_num } => format!("Dummy {}", self.sort_str(sort_num)),
+            ProofCmd::Thm { thm_num, save: false } => format!("Thm {}", self.assert_operand(thm_num)),
+            ProofCmd::Thm { thm_num, save: true } => format!("ThmSave {}", self.assert_operand(thm_num)),
+            ProofCmd::ConvRef(i) => format!("ConvRef {}", i),
+            // The rest have no operand, and their `Debug` names are the mnemonics.
+            owise => format!("{:?}", owise),
+        }
+    }
+
+    /// The unify stream starting at `it`, including the final `End`.
+    pub fn disasm_unify(&self, mut it: UnifyIter) -> String {
+        let mut out = format!("unify @ {}\n", it.pos);
+        loop {
+            let pos = it.pos;
+            match it.next() {
+                None => {
+                    push_instr(&mut out, pos, it.buf.get(pos..=pos).unwrap_or(&[]), "End");
+                    return out
+                }
+                Some(Ok(cmd)) => push_instr(&mut out, pos, &it.buf[pos..it.pos], &self.unify_text(cmd)),
+                Some(Err(e)) => {
+                    out.push_str(&format!("  {:>8}  error: {:?}\n", pos, e));
+                    return out
+                }
+            }
+        }
+    }
+
+    /// The proof stream `proof`, including the final `End`.
+    pub fn disasm_proof(&self, mut proof: ProofIter) -> String {
+        let mut out = format!("proof @ {}\n", proof.pos);
+        loop {
+            let pos = proof.pos;
+            match proof.next() {
+                None => {
+                    push_instr(&mut out, pos, proof.buf.get(pos..=pos).unwrap_or(&[]), "End");
+                    return out
+                }
+                Some(Ok(cmd)) => push_instr(&mut out, pos, &proof.buf[pos..proof.pos], &self.proof_text(cmd)),
+                Some(Err(e)) => {
+                    out.push_str(&format!("  {:>8}  error: {:?}\n", pos, e));
+                    return out
+                }
+            }
+        }
+    }
+
+    /// The unify stream (for defs, axioms, and theorems) and the proof stream (if there is
+    /// one) of the declaration `stmt`.
+    pub fn disasm(&self, stmt: StmtCmd, proof: ProofIter) -> Res<String> {
+        let mut out = String::new();
+        match stmt {
+            StmtCmd::Sort { .. } => {}
+            StmtCmd::TermDef { num, .. } => {
+                let term = self.get_term_by_num(none_err!(num)?)?;
+                if term.is_def() {
+                    out.push_str(&self.disasm_unify(term.unify()));
+                }
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                out.push_str(&self.disasm_unify(self.get_assert_by_num(none_err!(num)?)?.unify()));
+            }
+        }
+        if !proof.is_null() {
+            out.push_str(&self.disasm_proof(proof));
+        }
+        Ok(out)
+    }
+}
+
+#[test]
+fn disasm1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let (stmt, proof) = outline.declarations[6];
+    let listing = outline.disasm(stmt, proof).unwrap();
+    let lines = listing.lines().collect::<Vec<_>>();
+    assert!(lines[0].starts_with("unify @ "));
+    assert!(lines.iter().any(|l| l.ends_with("UHyp")));
+    assert!(lines.iter().any(|l| l.trim_end().ends_with("UTerm im (#0)")));
+    assert!(lines.iter().any(|l| l.starts_with("proof @ ")));
+    assert!(lines.last().unwrap().ends_with("End"));
+    assert!(!listing.contains("error"));
+
+    // Break the first command of the proof stream.
+    let mut bytes = file_data.mmb_file.clone();
+    bytes[proof.pos] = 0x2F;
+    let broken = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let broken = Outline::new_from(&broken).unwrap();
+    let (stmt, proof) = broken.declarations[6];
+    let listing = broken.disasm(stmt, proof).unwrap();
+    assert!(listing.lines().last().unwrap().trim_start().starts_with(&format!("{}  error", proof.pos)));
+}
@@ -0,0 +1,207 @@
+//! A plain text listing of an mmb file's header and tables, with the offset of every entry
+//! and of the data it points to, for looking at a file without a hex editor and the spec:
+//!```text
+//! terms @ 48
+//!   #0 im @ 48: term (wff wff): wff; args @ 64
+//!   #1 not @ 56: term (wff): wff; args @ 88
+//!```
+//! Binders are written the way `diff` writes them: `{set}` for a bound variable and
+//! `wff[0 2]` for a variable that depends on the first and third bound variables. Nothing
+//! is verified; the listing is only as sensible as the file.
+//!
+//! `dump_defs` is the readable counterpart: every term and def in mm0 syntax, with each
+//! def's body read back from its unify stream (in the mm0 file's notation, if the outline
+//! has it).
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::sort_mods::SortMod;
+use crate::mmb::unify::UnifyCmd;
+use crate::util::{ Res, Args, Type, VerifErr, parse_u32 };
+use crate::none_err;
+
+fn push_line(out: &mut String, line: String) {
+    out.push_str(&line);
+    out.push('\n');
+}
+
+fn name_or_num(name: Option<&str>, num: u32) -> String {
+    name.map(|s| s.to_string()).unwrap_or_else(|| format!("#{}", num))
+}
+
+impl<'a> Outline<'a> {
+    /// The header, sort table, term table, and theorem table, one entry per line.
+    pub fn dump_tables(&self) -> Res<String> {
+        let h = &self.header;
+        let mut out = String::new();
+        push_line(&mut out, "header @ 0".to_string());
+        push_line(&mut out, format!("  magic              {:#010x}", h.magic));
+        push_line(&mut out, format!("  version            {}", h.version));
+        push_line(&mut out, format!("  num_sorts          {}", h.num_sorts));
+        push_line(&mut out, format!("  num_terms          {}", h.num_terms));
+        push_line(&mut out, format!("  num_thms           {}", h.num_thms));
+        push_line(&mut out, format!("  terms_start        {}", h.terms_start));
+        push_line(&mut out, format!("  thms_start         {}", h.thms_start));
+        push_line(&mut out, format!("  proof_stream_start {}", h.proof_stream_start));
+        push_line(&mut out, format!("  index_start        {}", h.index_start));
+
+        push_line(&mut out, format!("sorts @ {}", h.sort_data_start));
+        for num in 0..h.num_sorts {
+            let mods = self.get_sort_mods(num as usize)?.inner;
+            let names = SortMod::ALL.iter().filter(|m| mods & m.bit() != 0).map(|m| m.name()).collect::<Vec<_>>();
+            push_line(&mut out, format!(
+                "  #{} {} @ {}: {:#04x} {}",
+                num,
+                name_or_num(self.sort_name(num), num as u32),
+                h.sort_data_start as usize + num as usize,
+                mods,
+                names.join(" ")
+            ));
+        }
+
+        push_line(&mut out, format!("terms @ {}", h.terms_start));
+        for num in 0..h.num_terms {
+            let entry = h.terms_start as usize + num as usize * 8;
+            let term = self.get_term_by_num(num)?;
+            let mut line = format!(
+                "  #{} {} @ {}: {} ({}): {}; args @ {}",
+                num,
+                name_or_num(self.term_name(num), num),
+                entry,
+                if term.is_def() { "def" } else { "term" },
+                self.binders(term.args_no_ret())?,
+                self.binder_str(term.ret())?,
+                self.args_ptr(entry)?
+            );
+            if term.is_def() {
+                line.push_str(&format!(", unify @ {}", term.unify.pos));
+            }
+            push_line(&mut out, line);
+        }
+
+        push_line(&mut out, format!("theorems @ {}", h.thms_start));
+        for num in 0..h.num_thms {
+            let entry = h.thms_start as usize + num as usize * 8;
+            let assert = self.get_assert_by_num(num)?;
+            push_line(&mut out, format!(
+                "  #{} {} @ {}: ({}); args @ {}, unify @ {}",
+                num,
+                name_or_num(self.assert_name(num), num),
+                entry,
+                self.binders(assert.args())?,
+                self.args_ptr(entry)?,
+                assert.unify.pos
+            ));
+        }
+        Ok(out)
+    }
+
+    /// Every term and def in mm0 syntax, one per line, like `def foo (v0: wff) {v1: set}
+    /// (.d0: set): wff v1 = (al d0 v0);`. The index only names sorts and terms, so the
+    /// arguments are `v0`, `v1`, ... and a def's dummies `d0`, `d1`, ...
+    pub fn dump_defs(&self) -> Res<String> {
+        let mut out = String::new();
+        for num in 0..self.header.num_terms {
+            push_line(&mut out, self.termdef_str(num)?);
+        }
+        Ok(out)
+    }
+
+    /// Term or def `num` as `dump_defs` writes it.
+    pub(crate) fn termdef_str(&self, num: u32) -> Res<String> {
+        let term = self.get_term_by_num(num)?;
+        let (mut binders, bvs) = self.mm0_binders(term.args_no_ret());
+        // Named the way the bodies name them.
+        let name = self.term_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", num));
+        let ret = self.type_with_deps(term.ret(), &bvs);
+        Ok(match self.def_body_str(num)? {
+            None => format!("term {}{}: {};", name, binders.concat(), ret),
+            Some(body) => {
+                let dummies = term.unify().filter_map(|cmd| match cmd {
+                    Ok(UnifyCmd::Dummy { sort_id }) => Some(sort_id),
+                    _ => None,
+                });
+                for (i, sort) in dummies.enumerate() {
+                    binders.push(format!(" (.d{}: {})", i, self.sort_str(sort)));
+                }
+                format!("def {}{}: {} = {};", name, binders.concat(), ret, self.math_str(body))
+            }
+        })
+    }
+
+    /// Binders in mm0 syntax, each with a space before it, like ` {v0: set} (v1: wff v0)`,
+    /// and the argument each bound variable (counting from 1) is.
+    pub(crate) fn mm0_binders(&self, args: Args) -> (Vec<String>, Vec<usize>) {
+        let mut bvs = Vec::new();
+        let mut binders = Vec::new();
+        for (i, ty) in args.enumerate() {
+            if ty.is_bound() {
+                bvs.push(i);
+                binders.push(format!(" {{v{}: {}}}", i, self.sort_str(ty.sort())));
+            } else {
+                binders.push(format!(" (v{}: {})", i, self.type_with_deps(ty, &bvs)));
+            }
+        }
+        (binders, bvs)
+    }
+
+    /// A regular variable's sort followed by the arguments it depends on, given which
+    /// argument each bound variable is.
+    fn type_with_deps(&self, ty: Type, bvs: &[usize]) -> String {
+        let mut s = self.sort_str(ty.sort());
+        for dep in ty.deps_iter() {
+            match bvs.get(dep as usize - 1) {
+                Some(i) => s.push_str(&format!(" v{}", i)),
+                None => s.push_str(&format!(" ?{}", dep)),
+            }
+        }
+        s
+    }
+
+    /// Where an entry of the term or theorem table says its arguments are.
+    pub(crate) fn args_ptr(&self, entry: usize) -> Res<u32> {
+        Ok(parse_u32(none_err!(self.mmb().get(entry + 4..))?)?.0)
+    }
+
+    fn binders(&self, args: Args) -> Res<String> {
+        let mut binders = Vec::new();
+        for ty in args {
+            binders.push(self.binder_str(ty)?);
+        }
+        Ok(binders.join(" "))
+    }
+}
+
+#[test]
+fn dump_tables1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let listing = outline.dump_tables().unwrap();
+    let lines = listing.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "header @ 0");
+    assert!(lines.contains(&"sorts @ 40"));
+    assert!(lines.iter().any(|l| l.starts_with("  #0 wff @ 40: 0x06 strict provable")));
+    assert!(lines.iter().any(|l| l.starts_with("  #0 im @ ") && l.contains(": term (wff wff): wff; args @ ")));
+    assert!(lines.iter().any(|l| l.starts_with("  #3 ax_mp @ ") && l.contains(": (wff wff); args @ ")));
+    assert_eq!(lines.len(), 10 + 2 + 3 + 5);
+}
+
+#[test]
+fn dump_defs1() {
+    use crate::testgen::{ Fixture, TermSpec, reg, bound, stream };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF, UNIFY_DUMMY };
+    // `all {x: set} (p: wff x): wff` and `def ex (p: wff): wff = all d0 p`.
+    let (wff, set) = (reg(0, 0), bound(1, 1));
+    let mut fx = Fixture::valid();
+    fx.sorts.push(0);
+    fx.terms.push(TermSpec { args: vec![set, reg(0, 1)], ret: wff, def: None });
+    fx.terms.push(TermSpec { args: vec![wff], ret: wff, def: Some(stream(&[(UNIFY_TERM, 1), (UNIFY_DUMMY, 1), (UNIFY_REF, 0)])) });
+    fx.with_outline(|outline| {
+        let listing = outline.dump_defs().unwrap();
+        assert_eq!(listing.lines().collect::<Vec<_>>(), [
+            "term t0 (v0: #0) (v1: #0): #0;",
+            "term t1 {v0: #1} (v1: #0 v0): #0;",
+            "def t2 (v0: #0) (.d0: #1): #0 = (t1 d0 v0);",
+        ]);
+    });
+}
@@ -0,0 +1,195 @@
+//! Writing a theorem's proof stream from its proof tree (see `tree`), for exporters from
+//! other systems that have a derivation and don't want to deal with the stack machine.
+//! The stream starts by building each hypothesis and adding it with `Hyp`, then builds the
+//! tree bottom up: an application's hypotheses' proofs, its arguments, and its conclusion,
+//! then `Thm`. Expressions and subproofs used more than once are saved to the heap the
+//! first time they're built (with `TermSave` and `ThmSave`) and referred to with `Ref`
+//! after that; dummy variables are allocated with `Dummy` when they first come up.
+//!
+//! Sharing is by address, so equal subterms are only saved once if they're the same
+//! allocation, as they are in a tree built from a stream that saved them or with
+//! `hash_cons` set. `Conv` nodes don't record their conversion proofs, so a tree with one
+//! can't be encoded.
+
+use crate::prelude::*;
+use crate::mmb::{ MmbItem, MmbExpr };
+use crate::mmb::tree::ProofNode;
+use crate::mmb::build::ProofStream;
+use crate::mmb::proof::ProofCmd;
+use crate::util::{ Res, VerifErr, HashMap };
+use crate::none_err;
+
+fn addr<T>(x: &T) -> usize {
+    x as *const T as usize
+}
+
+struct Encoder {
+    num_args: usize,
+    /// How many times each expression and subproof is built, by address, not counting
+    /// the ones inside something that's referred to rather than built again.
+    uses: HashMap<usize, u32>,
+    /// Where each saved expression, subproof, and dummy is on the heap, by address.
+    saved: HashMap<usize, u32>,
+    /// Where each hypothesis is on the heap.
+    hyps: Vec<u32>,
+    dummies: HashMap<usize, u32>,
+    next_heap: u32,
+    out: ProofStream,
+}
+
+impl Encoder {
+    /// Count a use, returning whether it's the first.
+    fn count(&mut self, key: usize) -> bool {
+        let n = self.uses.entry(key).or_insert(0);
+        *n += 1;
+        *n == 1
+    }
+
+    fn count_expr(&mut self, e: &MmbItem) {
+        if let MmbItem::Expr(MmbExpr::App { args, .. }) = e {
+            if self.count(addr(e)) {
+                for a in args.iter() {
+                    self.count_expr(a)
+                }
+            }
+        }
+    }
+
+    fn count_proof(&mut self, node: &ProofNode) -> Res<()> {
+        match node {
+            ProofNode::Hyp { .. } => Ok(()),
+            ProofNode::Thm { args, hyps, concl, .. } => {
+                if self.count(addr(node)) {
+                    for h in hyps.iter() {
+                        self.count_proof(h)?;
+                    }
+                    for a in args.iter() {
+                        self.count_expr(a);
+                    }
+                    self.count_expr(concl);
+                }
+                Ok(())
+            }
+            ProofNode::Conv { .. } => Err(VerifErr::Msg("a proof tree with a conversion can't be encoded".to_string())),
+        }
+    }
+
+    fn save(&mut self, key: usize) -> u32 {
+        self.saved.insert(key, self.next_heap);
+        self.next_heap += 1;
+        self.next_heap - 1
+    }
+
+    fn shared(&self, key: usize) -> bool {
+        self.uses.get(&key).map(|n| *n > 1).unwrap_or(false)
+    }
+
+    fn expr(&mut self, e: &MmbItem) -> Res<()> {
+        match e {
+            MmbItem::Expr(MmbExpr::Var { idx, .. }) if *idx < self.num_args => {
+                self.out.push(ProofCmd::Ref(*idx as u32));
+            }
+            MmbItem::Expr(MmbExpr::Var { idx, ty }) => match self.dummies.get(idx) {
+                Some(i) => { self.out.push(ProofCmd::Ref(*i)); }
+                None => {
+                    self.dummies.insert(*idx, self.next_heap);
+                    self.next_heap += 1;
+                    self.out.push(ProofCmd::Dummy { sort_num: ty.sort() });
+                }
+            },
+            MmbItem::Expr(MmbExpr::App { term_num, args, .. }) => match self.saved.get(&addr(e)) {
+                Some(i) => { self.out.push(ProofCmd::Ref(*i)); }
+                None => {
+                    for a in args.iter() {
+                        self.expr(a)?;
+                    }
+                    let save = self.shared(addr(e));
+                    if save {
+                        self.save(addr(e));
+                    }
+                    self.out.push(ProofCmd::Term { term_num: *term_num, save });
+                }
+            },
+            _ => return Err(VerifErr::Msg(format!("expected an expression in a proof tree, found {}", e.kind_name()))),
+        }
+        Ok(())
+    }
+
+    fn proof(&mut self, node: &ProofNode) -> Res<()> {
+        match node {
+            ProofNode::Hyp { idx, .. } => {
+                let i = none_err!(self.hyps.get(*idx).copied())?;
+                self.out.push(ProofCmd::Ref(i));
+            }
+            ProofNode::Thm { thm_num, args, hyps, concl } => match self.saved.get(&addr(node)) {
+                Some(i) => { self.out.push(ProofCmd::Ref(*i)); }
+                None => {
+                    for h in hyps.iter() {
+                        self.proof(h)?;
+                    }
+                    for a in args.iter() {
+                        self.expr(a)?;
+                    }
+                    self.expr(concl)?;
+                    let save = self.shared(addr(node));
+                    if save {
+                        self.save(addr(node));
+                    }
+                    self.out.push(ProofCmd::Thm { thm_num: *thm_num, save });
+                }
+            },
+            ProofNode::Conv { .. } => return Err(VerifErr::Msg("a proof tree with a conversion can't be encoded".to_string())),
+        }
+        Ok(())
+    }
+}
+
+/// The proof stream of a theorem with `num_args` arguments and hypotheses `hyps` (their
+/// statements, in order) proved by `root`.
+pub fn encode_proof(num_args: usize, hyps: &[&MmbItem], root: &ProofNode) -> Res<ProofStream> {
+    let mut enc = Encoder {
+        num_args,
+        uses: HashMap::new(),
+        saved: HashMap::new(),
+        hyps: Vec::new(),
+        dummies: HashMap::new(),
+        next_heap: num_args as u32,
+        out: ProofStream::new(),
+    };
+    for h in hyps.iter() {
+        enc.count_expr(h);
+    }
+    enc.count_proof(root)?;
+    for h in hyps.iter() {
+        enc.expr(h)?;
+        enc.out.push(ProofCmd::Hyp);
+        enc.hyps.push(enc.next_heap);
+        enc.next_heap += 1;
+    }
+    enc.proof(root)?;
+    Ok(enc.out)
+}
+
+#[test]
+fn encode1() {
+    use bumpalo::Bump;
+    use crate::testgen::Fixture;
+    let reencode = |fx: &Fixture, decl: usize| fx.with_outline(|outline| {
+        let mut bump = Bump::new();
+        let (stmt, proof) = outline.declarations[decl];
+        let num_args = outline.heap_args(stmt).unwrap().unwrap();
+        let tree = outline.proof_tree(&mut bump, stmt, proof).unwrap();
+        encode_proof(num_args, &[], tree).map(|s| s.to_bytes())
+    });
+    // The same proof, since `th` saves exactly what's used twice.
+    let fx = Fixture::valid();
+    assert_eq!(reencode(&fx, 3).unwrap(), fx.stmts[3].1);
+    // `chain` saves with a separate `Save`; the encoding is shorter but still checks.
+    let mut fx = Fixture::chain(5);
+    let bytes = reencode(&fx, 4).unwrap();
+    assert!(bytes.len() < fx.stmts[4].1.len());
+    fx.stmts[4].1 = bytes;
+    fx.with_outline(|outline| assert!(crate::verify_serial(outline, &outline.declarations).is_empty()));
+    // `unfolding` is proved by conversion.
+    assert!(reencode(&Fixture::unfolding(2), 5).is_err());
+}
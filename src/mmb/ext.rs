@@ -0,0 +1,125 @@
+//! An extension point for statement commands that aren't part of the mmb spec.
+//! Without a handler, an unknown statement is an error. Experimental format
+//! extensions can register a handler for their command code, which decides
+//! whether the statement checks out, should be skipped with a warning, or rejected.
+//! Handlers only see the raw bytes, so they're responsible for their own parsing.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::{ Res, VerifErr };
+
+/// A statement with a command code this verifier doesn't know about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtStmt<'a> {
+    /// The command code, without the data size bits.
+    pub cmd: u8,
+    /// The position of the command byte in the mmb file.
+    pub pos: usize,
+    /// The number of regular declarations that come before this statement.
+    pub decls_before: usize,
+    /// The bytes after the command and its data field, up to the next statement.
+    pub body: &'a [u8],
+}
+
+/// What to do with an extension statement.
+#[derive(Debug)]
+pub enum ExtVerdict {
+    /// The handler checked the statement itself, and it's fine.
+    Verified,
+    /// The statement isn't checked; the string is reported as a warning.
+    Skip(String),
+    /// The statement is wrong (or not allowed); verification fails.
+    Reject(VerifErr),
+}
+
+pub trait StmtHandler: Send + Sync {
+    fn check(&self, outline: &Outline, stmt: &ExtStmt) -> ExtVerdict;
+}
+
+impl<F> StmtHandler for F
+where F: Fn(&Outline, &ExtStmt) -> ExtVerdict + Send + Sync {
+    fn check(&self, outline: &Outline, stmt: &ExtStmt) -> ExtVerdict {
+        self(outline, stmt)
+    }
+}
+
+/// The registered handlers, keyed by command code.
+#[derive(Default)]
+pub struct StmtExtensions {
+    handlers: Vec<(u8, Box<dyn StmtHandler>)>,
+}
+
+impl core::fmt::Debug for StmtExtensions {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.handlers.iter().map(|(cmd, _)| cmd)).finish()
+    }
+}
+
+impl StmtExtensions {
+    /// Register a handler for `cmd`, replacing any previous handler for it.
+    /// The known statement codes can't be overridden.
+    pub fn register(&mut self, cmd: u8, handler: impl StmtHandler + 'static) -> Res<()> {
+        use core::convert::TryFrom;
+        if cmd == 0 || crate::mmb::stmt::StmtCmd::try_from(cmd).is_ok() {
+            return Err(VerifErr::Msg(format!("statement code {:#04x} is reserved by the mmb spec", cmd)))
+        }
+        self.handlers.retain(|(c, _)| *c != cmd);
+        self.handlers.push((cmd, Box::new(handler)));
+        Ok(())
+    }
+
+    pub fn get(&self, cmd: u8) -> Option<&dyn StmtHandler> {
+        self.handlers.iter().find(|(c, _)| *c == cmd).map(|(_, h)| h.as_ref())
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Run the registered handlers over every extension statement in the file.
+    /// Skipped statements are recorded as warnings.
+    pub fn verify_ext_stmts(&self, errs: &mut Vec<VerifErr>) {
+        for stmt in self.ext_stmts.iter() {
+            let verdict = match self.extensions.get(stmt.cmd) {
+                None => ExtVerdict::Reject(VerifErr::Msg(format!(
+                    "unknown statement code {:#04x} @ pos {}, and no handler is registered for it",
+                    stmt.cmd,
+                    stmt.pos
+                ))),
+                Some(handler) => handler.check(self, stmt),
+            };
+            match verdict {
+                ExtVerdict::Verified => {},
+                ExtVerdict::Skip(msg) => self.warn(format!("skipped statement {:#04x} @ pos {}: {}", stmt.cmd, stmt.pos, msg)),
+                ExtVerdict::Reject(e) => errs.push(e),
+            }
+        }
+    }
+}
+
+#[test]
+fn ext_stmts1() {
+    let mut file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    // Turn the last axiom into an unknown statement, keeping its length. Statements are
+    // contiguous, so it starts where the one before it ends.
+    let pos = {
+        let outline = Outline::new_from(&file_data).unwrap();
+        let n = outline.declarations.len();
+        outline.declarations[n - 2].1.ends_at
+    };
+    file_data.mmb_file[pos] = (file_data.mmb_file[pos] & crate::util::cmd::DATA_MASK) | 0x07;
+
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    assert_eq!(outline.declarations.len(), 6);
+    assert_eq!(outline.ext_stmts.len(), 1);
+    assert_eq!((outline.ext_stmts[0].cmd, outline.ext_stmts[0].pos, outline.ext_stmts[0].decls_before), (0x07, pos, 6));
+
+    let mut errs = Vec::new();
+    outline.verify_ext_stmts(&mut errs);
+    assert_eq!(errs.len(), 1);
+
+    assert!(outline.extensions.register(crate::mmb::stmt::STMT_THM, |_: &Outline, _: &ExtStmt| ExtVerdict::Verified).is_err());
+    outline.extensions.register(0x07, |_: &Outline, _: &ExtStmt| ExtVerdict::Skip("experimental".to_string())).unwrap();
+    let mut errs = Vec::new();
+    outline.verify_ext_stmts(&mut errs);
+    assert!(errs.is_empty());
+    assert_eq!(outline.take_warnings().len(), 1);
+}
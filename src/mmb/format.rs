@@ -0,0 +1,227 @@
+//! The constants of the MM0B binary format in one place: the header layout, the sort
+//! modifier flags, how a `Type` packs into a `u64`, and the command bytes of the statement,
+//! proof and unify streams. The rest of the crate uses these (the old paths, like
+//! `mmb::proof::PROOF_TERM`, are re-exports), so a writer or fuzzer that uses them agrees
+//! with the verifier by construction.
+//!
+//! All multi-byte values are little endian.
+
+use crate::prelude::*;
+
+/// The first four bytes of every mmb file, `MM0B` read as a little endian `u32`.
+pub const MM0B_MAGIC: u32 = 0x42304D4D;
+/// The format version this verifier reads.
+pub const MM0B_VERSION: u8 = 1;
+
+// Header fields, by byte offset.
+/// `u32`: `MM0B_MAGIC`.
+pub const HEADER_MAGIC: usize = 0;
+/// `u8`: the format version.
+pub const HEADER_VERSION: usize = 4;
+/// `u8`: the number of sorts.
+pub const HEADER_NUM_SORTS: usize = 5;
+/// `u16`: reserved.
+pub const HEADER_RESERVED: usize = 6;
+/// `u32`: the number of terms and defs.
+pub const HEADER_NUM_TERMS: usize = 8;
+/// `u32`: the number of axioms and theorems.
+pub const HEADER_NUM_THMS: usize = 12;
+/// `u32`: the offset of the term table.
+pub const HEADER_TERMS_START: usize = 16;
+/// `u32`: the offset of the theorem table.
+pub const HEADER_THMS_START: usize = 20;
+/// `u32`: the offset of the statement stream.
+pub const HEADER_PROOF_STREAM_START: usize = 24;
+/// `u32`: reserved.
+pub const HEADER_RESERVED2: usize = 28;
+/// `u64`: the offset of the index, or 0 if there isn't one.
+pub const HEADER_INDEX_START: usize = 32;
+/// The size of the header, which is followed directly by one modifier byte per sort.
+pub const HEADER_SIZE: usize = 40;
+
+/// Entries of the term and theorem tables are 8 bytes: a `u16` argument count, then for a
+/// term its sort (with `TERM_DEF_BIT` for a def) and a reserved byte, and for a theorem two
+/// reserved bytes, then the `u32` offset of the argument types, which the unify stream
+/// follows. A term's arguments are followed by its return type.
+pub const TABLE_ENTRY_SIZE: usize = 8;
+/// Set in a term table entry's sort byte for a def.
+pub const TERM_DEF_BIT: u8 = 0x80;
+
+// Sort modifiers: each sort has one byte of these. The high four bits are unused.
+pub const SORT_PURE     : u8 = 1;
+pub const SORT_STRICT   : u8 = 2;
+pub const SORT_PROVABLE : u8 = 4;
+pub const SORT_FREE     : u8 = 8;
+/// The bits a sort modifier byte can have.
+pub const SORT_MODS_MASK: u8 = SORT_PURE | SORT_STRICT | SORT_PROVABLE | SORT_FREE;
+
+/// bound mask: 10000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000
+pub const TYPE_BOUND_MASK: u64 = 1 << 63;
+/// deps mask: 00000000_11111111_11111111_11111111_11111111_11111111_11111111_11111111
+pub const TYPE_DEPS_MASK: u64 = (1 << 56) - 1;
+/// Where the sort number (7 bits) starts in a `Type`.
+pub const TYPE_SORT_SHIFT: u32 = 56;
+
+/// `DATA_8 = 0x40`, used as a command mask for an 8 bit data field
+pub const DATA_8: u8 = 0x40;
+/// `DATA_16 = 0x80`, used as a command mask for a 16 bit data field
+pub const DATA_16: u8 = 0x80;
+/// `DATA_32 = 0xC0`, used as a command mask for a 32 bit data field
+pub const DATA_32: u8 = 0xC0;
+/// `DATA_MASK = 0xC0`, selects one of `DATA_8`, `DATA_16`, or `DATA_32` for data size
+pub const DATA_MASK: u8 = 0xC0;
+/// `CMD_END = 0x00`, the last command of every statement, proof and unify stream.
+pub const CMD_END: u8 = 0x00;
+
+/// `STMT_AXIOM = 0x02`, starts an `axiom` declaration
+pub const STMT_AXIOM: u8 = 0x02;
+/// `STMT_SORT = 0x04`, starts a `sort` declaration
+pub const STMT_SORT: u8 = 0x04;
+/// `STMT_DEF = 0x05`, starts a `def` declaration. (This is the same as
+/// `STMT_TERM` because the actual indication of whether this is a
+/// def is in the term header)
+pub const STMT_DEF: u8 = 0x05;
+/// `STMT_THM = 0x06`, starts a `theorem` declaration
+pub const STMT_THM: u8 = 0x06;
+/// `STMT_LOCAL = 0x08`, starts a `local` declaration
+/// (a bit mask to be combined with `STMT_THM` or `STMT_DEF`)
+pub const STMT_LOCAL: u8 = 0x08;
+/// `STMT_LOCAL_DEF = 0x0D`
+pub const STMT_LOCAL_DEF: u8 = STMT_LOCAL | STMT_DEF;
+/// `STMT_LOCAL_THM = 0x0E`
+pub const STMT_LOCAL_THM: u8 = STMT_LOCAL | STMT_THM;
+
+/// `PROOF_TERM = 0x10`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_TERM: u8 = 0x10;
+/// `PROOF_TERM_SAVE = 0x11`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_TERM_SAVE: u8 = 0x11;
+/// `PROOF_REF = 0x12`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_REF: u8 = 0x12;
+/// `PROOF_DUMMY = 0x13`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_DUMMY: u8 = 0x13;
+/// `PROOF_THM = 0x14`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_THM: u8 = 0x14;
+/// `PROOF_THM_SAVE = 0x15`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_THM_SAVE: u8 = 0x15;
+/// `PROOF_HYP = 0x16`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_HYP: u8 = 0x16;
+/// `PROOF_CONV = 0x17`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_CONV: u8 = 0x17;
+/// `PROOF_REFL = 0x18`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_REFL: u8 = 0x18;
+/// `PROOF_SYMM = 0x19`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_SYMM: u8 = 0x19;
+/// `PROOF_CONG = 0x1A`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_CONG: u8 = 0x1A;
+/// `PROOF_UNFOLD = 0x1B`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_UNFOLD: u8 = 0x1B;
+/// `PROOF_CONV_CUT = 0x1C`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_CONV_CUT: u8 = 0x1C;
+/// `PROOF_CONV_REF = 0x1D`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_CONV_REF: u8 = 0x1D;
+/// `PROOF_CONV_SAVE = 0x1E`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_CONV_SAVE: u8 = 0x1E;
+/// `PROOF_SAVE = 0x1F`: See [`ProofCmd`](super::ProofCmd).
+pub const PROOF_SAVE: u8 = 0x1F;
+
+/// `UNIFY_TERM = 0x30`: See [`UnifyCmd`](super::UnifyCmd).
+pub const UNIFY_TERM: u8 = 0x30;
+/// `UNIFY_TERM_SAVE = 0x31`: See [`UnifyCmd`](super::UnifyCmd).
+pub const UNIFY_TERM_SAVE: u8 = 0x31;
+/// `UNIFY_REF = 0x32`: See [`UnifyCmd`](super::UnifyCmd).
+pub const UNIFY_REF: u8 = 0x32;
+/// `UNIFY_DUMMY = 0x33`: See [`UnifyCmd`](super::UnifyCmd).
+pub const UNIFY_DUMMY: u8 = 0x33;
+/// `UNIFY_HYP = 0x36`: See [`UnifyCmd`](super::UnifyCmd).
+pub const UNIFY_HYP: u8 = 0x36;
+
+/// Append command `cmd` (without data size bits) with the smallest data field that fits
+/// `data`, or none if it's 0.
+///
+/// ```
+/// use second_opinion::mmb::format::*;
+/// let mut out = Vec::new();
+/// encode_cmd(&mut out, PROOF_SAVE, 0);
+/// encode_cmd(&mut out, PROOF_REF, 7);
+/// encode_cmd(&mut out, PROOF_THM, 300);
+/// assert_eq!(out, [PROOF_SAVE, PROOF_REF | DATA_8, 7, PROOF_THM | DATA_16, 44, 1]);
+/// ```
+pub fn encode_cmd(out: &mut Vec<u8>, cmd: u8, data: u32) {
+    if data == 0 {
+        out.push(cmd);
+    } else if data <= 0xFF {
+        out.push(cmd | DATA_8);
+        out.push(data as u8);
+    } else if data <= 0xFFFF {
+        out.push(cmd | DATA_16);
+        out.extend_from_slice(&(data as u16).to_le_bytes());
+    } else {
+        out.push(cmd | DATA_32);
+        out.extend_from_slice(&data.to_le_bytes());
+    }
+}
+
+/// The command at `pos` in `bytes`, without its data size bits, its data (0 if it has no
+/// data field), and the position after it; or `None` if it runs past the end.
+///
+/// ```
+/// use second_opinion::mmb::format::*;
+/// let bytes = [PROOF_REF | DATA_8, 7, CMD_END];
+/// assert_eq!(decode_cmd(&bytes, 0), Some((PROOF_REF, 7, 2)));
+/// assert_eq!(decode_cmd(&bytes, 2), Some((CMD_END, 0, 3)));
+/// assert_eq!(decode_cmd(&bytes[..1], 0), None);
+/// ```
+pub fn decode_cmd(bytes: &[u8], pos: usize) -> Option<(u8, u32, usize)> {
+    let cmd = *bytes.get(pos)?;
+    let len = match cmd & DATA_MASK {
+        0 => 0,
+        DATA_8 => 1,
+        DATA_16 => 2,
+        _ => 4,
+    };
+    let field = bytes.get(pos + 1..pos + 1 + len)?;
+    let mut data = [0u8; 4];
+    data[..len].copy_from_slice(field);
+    Some((cmd & !DATA_MASK, u32::from_le_bytes(data), pos + 1 + len))
+}
+
+/// A `Type` as it's stored: the sort, whether it's a bound variable, and either the bound
+/// variable's bit (for a bound variable) or the bound variables it depends on. This
+/// doesn't check anything; `Type::from_parts` does.
+///
+/// ```
+/// use second_opinion::mmb::format::*;
+/// let ty = encode_type(3, true, 1 << 4);
+/// assert_eq!(ty, TYPE_BOUND_MASK | (3 << TYPE_SORT_SHIFT) | 16);
+/// assert_eq!(decode_type(ty), (3, true, 16));
+/// ```
+pub fn encode_type(sort: u8, bound: bool, vars: u64) -> u64 {
+    (if bound { TYPE_BOUND_MASK } else { 0 }) | ((sort as u64 & 0x7F) << TYPE_SORT_SHIFT) | (vars & TYPE_DEPS_MASK)
+}
+
+/// The parts of a `Type` as `encode_type` takes them.
+pub fn decode_type(ty: u64) -> (u8, bool, u64) {
+    (((ty >> TYPE_SORT_SHIFT) & 0x7F) as u8, ty & TYPE_BOUND_MASK != 0, ty & TYPE_DEPS_MASK)
+}
+
+#[test]
+fn format1() {
+    let mmb = std::fs::read("./test_resources/short.mmb").unwrap();
+    let h = crate::mmb::parse_header(&mmb).unwrap();
+    let u32_at = |i: usize| u32::from_le_bytes([mmb[i], mmb[i + 1], mmb[i + 2], mmb[i + 3]]);
+    assert_eq!(u32_at(HEADER_MAGIC), MM0B_MAGIC);
+    assert_eq!((mmb[HEADER_VERSION], mmb[HEADER_NUM_SORTS]), (MM0B_VERSION, h.num_sorts));
+    assert_eq!((u32_at(HEADER_NUM_TERMS), u32_at(HEADER_NUM_THMS)), (h.num_terms, h.num_thms));
+    assert_eq!((u32_at(HEADER_TERMS_START), u32_at(HEADER_THMS_START)), (h.terms_start, h.thms_start));
+    assert_eq!(u32_at(HEADER_PROOF_STREAM_START), h.proof_stream_start);
+    assert_eq!(u32_at(HEADER_INDEX_START) as u64 | (u32_at(HEADER_INDEX_START + 4) as u64) << 32, h.index_start);
+    assert_eq!(h.sort_data_start as usize, HEADER_SIZE);
+    for data in [0, 1, 0xFF, 0x100, 0xFFFF, 0x10000, u32::MAX] {
+        let mut out = Vec::new();
+        encode_cmd(&mut out, UNIFY_REF, data);
+        assert_eq!(decode_cmd(&out, 0), Some((UNIFY_REF, data, out.len())));
+        assert_eq!(crate::util::parse_cmd(&out, 0).unwrap(), (UNIFY_REF, data, out.len()));
+    }
+    let ty = crate::util::Type::from_parts(5, false, 0b101).unwrap();
+    assert_eq!(encode_type(5, false, 0b101), ty.bits());
+}
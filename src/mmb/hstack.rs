@@ -0,0 +1,91 @@
+//! The hypothesis stack. Each `Hyp` command in a theorem's proof takes an expression off
+//! the main stack, checks that its sort is provable, and pushes it here (and a proof of it
+//! onto the heap, for the rest of the proof to use). The theorem's own unify stream runs at
+//! the end, and each of its `UHyp` commands pops one of these to match the next hypothesis
+//! of the statement; the statement's hypotheses are unified last to first, so a proof has
+//! to introduce them in declaration order. Once the unify stream is done, the stack has to
+//! be empty: a proof can't assume something the statement doesn't.
+//!
+//! An axiom's stream uses it the same way, introducing the hypotheses of its statement
+//! without proving its conclusion. A def's stream can't have `Hyp` commands.
+
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+use crate::mmb::MmbItem;
+
+pub struct HypStack<'b> {
+    items: BumpVec<'b, &'b MmbItem<'b>>,
+}
+
+impl<'b> HypStack<'b> {
+    pub fn new_in(bump: &'b Bump) -> Self {
+        HypStack { items: BumpVec::new_in(bump) }
+    }
+
+    /// Add the hypothesis a `Hyp` command introduced. The caller checks its sort.
+    pub fn push(&mut self, e: &'b MmbItem<'b>) {
+        self.items.push(e)
+    }
+
+    /// The last hypothesis introduced and not yet matched, for a `UHyp` command.
+    pub fn pop(&mut self) -> Option<&'b MmbItem<'b>> {
+        self.items.pop()
+    }
+
+    /// The number of hypotheses introduced and not yet matched.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Hypothesis number `idx`, counting from the first one introduced.
+    pub fn get(&self, idx: usize) -> Option<&'b MmbItem<'b>> {
+        self.items.get(idx).copied()
+    }
+
+    /// The hypotheses still to be matched, first introduced first.
+    pub fn as_slice(&self) -> &[&'b MmbItem<'b>] {
+        &self.items
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'b MmbItem<'b>> + '_ {
+        self.items.iter().copied()
+    }
+}
+
+#[test]
+fn hstack1() {
+    use crate::util::VerifErr;
+    use crate::mmb::MmbState;
+    use crate::testgen::{ Fixture, AssertSpec, reg, stream };
+    use crate::mmb::stmt::STMT_THM;
+    use crate::mmb::proof::{ PROOF_REF, PROOF_HYP };
+    use crate::mmb::unify::{ UNIFY_HYP, UNIFY_REF };
+    // A second sort that isn't provable, and `(a: nat) (h: a): |- a`.
+    let mut fx = Fixture::valid();
+    fx.sorts.push(0);
+    fx.stmts.insert(1, (crate::mmb::stmt::STMT_SORT, Vec::new()));
+    fx.asserts.push(AssertSpec { args: vec![reg(1, 0)], unify: stream(&[(UNIFY_REF, 0), (UNIFY_HYP, 0), (UNIFY_REF, 0)]) });
+    fx.stmts.push((STMT_THM, stream(&[(PROOF_REF, 0), (PROOF_HYP, 0), (PROOF_REF, 1)])));
+    fx.with_outline(|outline| {
+        let (stmt, proof) = *outline.declarations.last().unwrap();
+        match MmbState::verify1(outline, &mut Bump::new(), stmt, proof) {
+            Err(VerifErr::HypNotProvable { decl, sort, mods, .. }) => {
+                assert_eq!(decl, stmt);
+                assert_eq!((sort, mods), (1, 0));
+            }
+            owise => panic!("expected an unprovable hypothesis, got {:?}", owise),
+        }
+    });
+
+    let bump = Bump::new();
+    let x = &*bump.alloc(MmbItem::Expr(bump.alloc(crate::mmb::MmbExpr::Var { idx: 0, ty: reg(0, 0) })));
+    let mut hstack = HypStack::new_in(&bump);
+    hstack.push(x);
+    assert_eq!((hstack.len(), hstack.get(0), hstack.iter().count()), (1, Some(x), 1));
+    assert_eq!(hstack.pop(), Some(x));
+    assert!(hstack.is_empty() && hstack.pop().is_none());
+}
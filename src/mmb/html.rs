@@ -0,0 +1,215 @@
+//! A browsable report of a run: an `index.html` listing every declaration, and a page for
+//! each one with its statement (in the mm0 file's notation, if the outline has it), whether
+//! it was verified, how long its proof and unification took (if `options.phase_times` was
+//! set), the axioms it rests on, and links to the declarations it uses and that use it.
+//! Pages are named by table and number (`sort-0.html`, `term-3.html`, `thm-7.html`), since
+//! names aren't always safe in a file name, or there at all.
+//!
+//! The report is written from whatever state the outline is in, so run verification first;
+//! a declaration that failed is in it like any other, which is what the report is for.
+
+use crate::prelude::*;
+use core::time::Duration;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::mmb::trace::escape;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; }\n\
+pre { background: #f4f4f4; padding: 6px; white-space: pre-wrap; }\n\
+table { border-collapse: collapse; }\n\
+td, th { border: 1px solid #ccc; padding: 2px 6px; text-align: left; }\n\
+.verified { color: #080; }\n\
+.failed { color: #c00; font-weight: bold; }\n\
+.unchecked { color: #888; }\n";
+
+/// The table a declaration is in (0 for sorts, 1 for terms and defs, 2 for assertions) and
+/// its number in it.
+fn key(stmt: StmtCmd) -> Option<(usize, usize)> {
+    match stmt {
+        StmtCmd::Sort { num } => num.map(|n| (0, n as usize)),
+        StmtCmd::TermDef { num, .. } => num.map(|n| (1, n as usize)),
+        StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => num.map(|n| (2, n as usize)),
+    }
+}
+
+fn page_name((table, num): (usize, usize)) -> String {
+    format!("{}-{}.html", ["sort", "term", "thm"][table], num)
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.3}ms", d.as_secs_f64() * 1000.0)
+}
+
+/// What's known about one declaration.
+struct Entry {
+    key: (usize, usize),
+    kind: &'static str,
+    name: String,
+    status: &'static str,
+    times: Option<(Duration, Duration)>,
+}
+
+impl Entry {
+    fn link(&self) -> String {
+        format!("<a href=\"{}\">{}</a>", page_name(self.key), escape(&self.name))
+    }
+
+    fn status_html(&self) -> String {
+        let class = if self.status == "not checked" { "unchecked" } else { self.status };
+        format!("<span class=\"{}\">{}</span>", class, self.status)
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// The report for a run that verified `decls`, as (file name, page) pairs with
+    /// `index.html` first. Declarations not in `decls` are listed as not checked.
+    pub fn html_report(&self, decls: &[(StmtCmd, ProofIter<'a>)]) -> Vec<(String, String)> {
+        let sizes = [self.header.num_sorts as usize, self.header.num_terms as usize, self.header.num_thms as usize];
+        let mut checked: Vec<Vec<bool>> = sizes.iter().map(|n| vec![false; *n]).collect();
+        for (stmt, _) in decls.iter() {
+            if let Some((table, num)) = key(*stmt) {
+                if let Some(slot) = checked[table].get_mut(num) {
+                    *slot = true;
+                }
+            }
+        }
+        let mut times: Vec<Vec<Option<(Duration, Duration)>>> = sizes.iter().map(|n| vec![None; *n]).collect();
+        for d in self.phase_times.with(|t| t.decls.clone()) {
+            if let Some((table, num)) = key(d.decl) {
+                if let Some(slot) = times[table].get_mut(num) {
+                    *slot = Some((d.proof, d.unify));
+                }
+            }
+        }
+
+        let mut entries: Vec<Entry> = Vec::new();
+        // Where each declaration is in `entries`, by table and number.
+        let mut pos: Vec<Vec<Option<usize>>> = sizes.iter().map(|n| vec![None; *n]).collect();
+        let mut uses: Vec<Vec<(usize, usize)>> = Vec::new();
+        for (stmt, proof) in self.declarations.iter() {
+            let (table, num) = match key(*stmt) {
+                Some(k) if k.1 < sizes[k.0] => k,
+                _ => continue,
+            };
+            let (kind, name) = match *stmt {
+                StmtCmd::Sort {..} => ("sort", self.sort_str(num as u8)),
+                StmtCmd::TermDef {..} => {
+                    let is_def = self.get_term_by_num(num as u32).map(|t| t.is_def()).unwrap_or(false);
+                    (if is_def { "def" } else { "term" }, self.term_name(num as u32).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", num)))
+                }
+                StmtCmd::Axiom {..} => ("axiom", self.assert_name(num as u32).map(|s| s.to_string()).unwrap_or_else(|| format!("a{}", num))),
+                StmtCmd::Thm {..} => ("theorem", self.assert_name(num as u32).map(|s| s.to_string()).unwrap_or_else(|| format!("a{}", num))),
+            };
+            let status = match (checked[table][num], self.is_declar_done(*stmt)) {
+                (false, _) => "not checked",
+                (true, true) => "verified",
+                (true, false) => "failed",
+            };
+            let deps = self.direct_deps(*stmt, *proof).unwrap_or_default();
+            uses.push(deps.terms.iter().map(|n| (1, *n as usize)).chain(deps.thms.iter().map(|n| (2, *n as usize))).collect());
+            pos[table][num] = Some(entries.len());
+            entries.push(Entry { key: (table, num), kind, name, status, times: times[table][num] });
+        }
+        let mut used_by: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+        for (i, deps) in uses.iter().enumerate() {
+            for (table, num) in deps.iter() {
+                if let Some(j) = pos[*table].get(*num).copied().flatten() {
+                    used_by[j].push(i);
+                }
+            }
+        }
+        let axioms_used = self.axioms_used().ok();
+        let links = |keys: &mut dyn Iterator<Item = (usize, usize)>| -> String {
+            let items = keys
+                .filter_map(|(table, num)| pos[table].get(num).copied().flatten())
+                .map(|i| format!("<li>{} {}</li>\n", entries[i].kind, entries[i].link()))
+                .collect::<String>();
+            if items.is_empty() { "<p>none</p>\n".to_string() } else { format!("<ul>\n{}</ul>\n", items) }
+        };
+
+        let mut pages = Vec::with_capacity(entries.len() + 1);
+        let mut rows = String::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let (table, num) = entry.key;
+            let time = entry.times.map(|(proof, unify)| ms(proof + unify)).unwrap_or_default();
+            rows.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n", entry.kind, entry.link(), entry.status_html(), time));
+
+            let statement = match table {
+                0 => self.sort_decl_str(num as u8),
+                1 => self.termdef_str(num as u32),
+                _ => self.assert_decl_str(entry.kind, num as u32),
+            }.unwrap_or_else(|e| format!("(the statement can't be read: {:?})", e));
+            let mut body = format!("<h1>{} {}</h1>\n<pre>{}</pre>\n", entry.kind, escape(&entry.name), escape(&statement));
+            body.push_str(&format!("<p>Status: {}</p>\n", entry.status_html()));
+            if let Some((proof, unify)) = entry.times {
+                body.push_str(&format!("<p>Time: {} proof, {} unification</p>\n", ms(proof), ms(unify)));
+            }
+            if table == 2 {
+                body.push_str("<h2>Axioms used</h2>\n");
+                match axioms_used.as_ref().and_then(|used| used.get(num)) {
+                    Some(axioms) => body.push_str(&links(&mut axioms.iter().map(|n| (2, *n as usize)))),
+                    None => body.push_str("<p>unknown</p>\n"),
+                }
+            }
+            if table != 0 {
+                body.push_str("<h2>Uses</h2>\n");
+                body.push_str(&links(&mut uses[i].iter().copied()));
+                body.push_str("<h2>Used by</h2>\n");
+                body.push_str(&links(&mut used_by[i].iter().map(|j| entries[*j].key)));
+            }
+            body.push_str("<p><a href=\"index.html\">All declarations</a></p>\n");
+            pages.push((page_name(entry.key), page(&format!("{} {}", entry.kind, entry.name), &body)));
+        }
+
+        let count = |status: &str| entries.iter().filter(|e| e.status == status).count();
+        let index = format!(
+            "<h1>Verification report</h1>\n<p>{} declarations: {} verified, {} failed, {} not checked.</p>\n\
+            <table>\n<tr><th>kind</th><th>name</th><th>status</th><th>time</th></tr>\n{}</table>\n",
+            entries.len(),
+            count("verified"),
+            count("failed"),
+            count("not checked"),
+            rows
+        );
+        pages.insert(0, ("index.html".to_string(), page("Verification report", &index)));
+        pages
+    }
+}
+
+#[test]
+fn html_report1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM };
+    let run = |fx: &Fixture| fx.with_outline(|outline| {
+        outline.options.phase_times = true;
+        crate::verify_serial(outline, &outline.declarations);
+        outline.html_report(&outline.declarations)
+    });
+    let pages = run(&Fixture::valid());
+    let names = pages.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["index.html", "sort-0.html", "term-0.html", "thm-0.html", "thm-1.html"]);
+    assert!(pages[0].1.contains("4 declarations: 4 verified, 0 failed, 0 not checked."));
+    // The theorem uses the axiom, and the axiom links back to it.
+    let th = &pages[4].1;
+    assert!(th.contains("<h1>theorem a1</h1>"), "{}", th);
+    assert!(th.contains("Time: "));
+    assert!(th.contains("<h2>Axioms used</h2>\n<ul>\n<li>axiom <a href=\"thm-0.html\">a0</a></li>\n</ul>"), "{}", th);
+    assert!(pages[3].1.contains("<h2>Used by</h2>\n<ul>\n<li>theorem <a href=\"thm-1.html\">a1</a></li>\n</ul>"));
+    // The axiom leaves an extra `a` on the stack.
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0)]);
+    let pages = run(&fx);
+    assert!(pages[0].1.contains("4 declarations: 3 verified, 1 failed, 0 not checked."));
+    assert!(pages[3].1.contains("<span class=\"failed\">failed</span>"));
+}
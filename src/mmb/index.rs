@@ -1,4 +1,5 @@
-use std::fmt::{ Debug, Formatter, Result as FmtResult };
+use crate::prelude::*;
+use core::fmt::{ Debug, Formatter, Result as FmtResult };
 use crate::mmb::Header;
 use crate::util::{
     parse_u8,
@@ -35,7 +36,7 @@ impl<'a> Debug for Index<'a> {
     }
 }
 
-impl<'a> std::default::Default for Index<'a> {
+impl<'a> core::default::Default for Index<'a> {
     fn default() -> Index<'a> {
         Index {
             mmb: b"",
@@ -69,27 +70,63 @@ pub struct IndexEntry<'a> {
 
 use crate::Outline;
 impl<'a> Outline<'a> {
+    #[inline]
+    pub fn sort_index_entry(&self, sort_num: u8) -> Option<IndexEntry<'a>> {
+        let entry = *self.index.sorts.get(sort_num as usize)?;
+        self.index_entry(entry as usize)
+    }
+
     #[inline]
     pub fn term_index_entry(&self, term_num: u32) -> Option<IndexEntry<'a>> {
-        let entry = *&self.index.terms[term_num as usize];
+        let entry = *self.index.terms.get(term_num as usize)?;
         self.index_entry(entry as usize)
     }
 
     #[inline]
     pub fn assert_index_entry(&self, assert_num: u32) -> Option<IndexEntry<'a>> {
-        let entry = *&self.index.thms[assert_num as usize];
+        let entry = *self.index.thms.get(assert_num as usize)?;
         self.index_entry(entry as usize)
     }
 
+    /// The name of a sort according to the index, if there is one.
+    pub fn sort_name(&self, sort_num: u8) -> Option<&'a str> {
+        core::str::from_utf8(self.sort_index_entry(sort_num)?.charbuff).ok()
+    }
+
+    /// The name of a term or def according to the index, if there is one.
+    pub fn term_name(&self, term_num: u32) -> Option<&'a str> {
+        core::str::from_utf8(self.term_index_entry(term_num)?.charbuff).ok()
+    }
+
+    /// The name of an axiom or theorem according to the index, if there is one.
+    pub fn assert_name(&self, assert_num: u32) -> Option<&'a str> {
+        core::str::from_utf8(self.assert_index_entry(assert_num)?.charbuff).ok()
+    }
+
+    /// The index entry of the item a declaration introduces.
+    pub fn stmt_index_entry(&self, stmt: crate::mmb::stmt::StmtCmd) -> Option<IndexEntry<'a>> {
+        use crate::mmb::stmt::StmtCmd;
+        match stmt {
+            StmtCmd::Sort { num } => self.sort_index_entry(num?),
+            StmtCmd::TermDef { num, .. } => self.term_index_entry(num?),
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => self.assert_index_entry(num?),
+        }
+    }
+
+    /// The name of the item a declaration introduces, according to the index.
+    pub fn stmt_name(&self, stmt: crate::mmb::stmt::StmtCmd) -> Option<&'a str> {
+        core::str::from_utf8(self.stmt_index_entry(stmt)?.charbuff).ok()
+    }
+
     #[inline]
     pub fn index_entry(&self, start_at: usize) -> Option<IndexEntry<'a>> {
-        let (left, rest) = parse_u64(&self.mmb()[start_at..]).unwrap();
-        let (right, rest) = parse_u64(rest).unwrap();
-        let (row, rest) = parse_u32(rest).unwrap();
-        let (col, rest) = parse_u32(rest).unwrap();
-        let (proof, rest) = parse_u64(rest).unwrap();
-        let (ix, rest) = parse_u32(rest).unwrap();
-        let (kind, rest) = parse_u8(rest).unwrap();
+        let (left, rest) = parse_u64(self.mmb().get(start_at..)?).ok()?;
+        let (right, rest) = parse_u64(rest).ok()?;
+        let (row, rest) = parse_u32(rest).ok()?;
+        let (col, rest) = parse_u32(rest).ok()?;
+        let (proof, rest) = parse_u64(rest).ok()?;
+        let (ix, rest) = parse_u32(rest).ok()?;
+        let (kind, rest) = parse_u8(rest).ok()?;
         let charbuff = parse_cstr(rest)?;
         Some(IndexEntry {
             left,
@@ -111,14 +148,15 @@ fn parse_cstr<'a>(source: &'a [u8]) -> Option<&'a [u8]> {
 }
 
 pub fn parse_index<'a>(mmb: &'a [u8], header: Header) -> Res<Index<'a>> {
-    let (root, rest) = parse_u64(&mmb[header.index_start as usize..])
-        .expect("Failed to get u64 for index root");
-    let (sorts, rest) = prefix_u64(rest, header.num_sorts as usize)
-        .expect("Failed to get sorts in index");
-    let (terms, rest) = prefix_u64(rest, header.num_terms as usize)
-        .expect("Failed to get num terms");
-    let (thms, _) = prefix_u64(rest, header.num_thms as usize)
-        .expect("Failed to get num thms in index");
+    // The index is optional; a zero pointer means there isn't one.
+    if header.index_start == 0 {
+        return Ok(Index { mmb, ..Index::default() })
+    }
+    let truncated = |_| VerifErr::Msg(format!("the index @ {} runs past the end of the file", header.index_start));
+    let (root, rest) = parse_u64(none_err!(mmb.get(header.index_start as usize..))?).map_err(truncated)?;
+    let (sorts, rest) = prefix_u64(rest, header.num_sorts as usize).map_err(truncated)?;
+    let (terms, rest) = prefix_u64(rest, header.num_terms as usize).map_err(truncated)?;
+    let (thms, _) = prefix_u64(rest, header.num_thms as usize).map_err(truncated)?;
     Ok(Index {
         mmb,
         root,
@@ -130,7 +168,7 @@ pub fn parse_index<'a>(mmb: &'a [u8], header: Header) -> Res<Index<'a>> {
 
 #[inline]
 pub fn prefix_u64(mut bytes: &[u8], num_elems: usize) -> Res<(Vec<u64>, &[u8])> {
-    let split_point = none_err!{ std::mem::size_of::<u64>().checked_mul(num_elems) }?;
+    let split_point = none_err!{ core::mem::size_of::<u64>().checked_mul(num_elems) }?;
 
     if split_point <= bytes.len() {
         let mut v = Vec::with_capacity(num_elems);
@@ -145,3 +183,209 @@ pub fn prefix_u64(mut bytes: &[u8], num_elems: usize) -> Res<(Vec<u64>, &[u8])>
     }
 }
 
+
+
+/// A way in which the index disagrees with the rest of the file. These are kept apart
+/// from verification errors; the index is only used for names, so a corrupt one doesn't
+/// make the proofs wrong, but it does make anything reported by name untrustworthy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexErr {
+    /// The table entry doesn't point at a readable index entry.
+    BadEntry { table: &'static str, num: u32, ptr: u64 },
+    /// The entry says it's for a different item of the table.
+    WrongNumber { table: &'static str, num: u32, ix: u32 },
+    /// The entry's name isn't a valid identifier.
+    BadName { table: &'static str, num: u32, name: String },
+    /// Two items in the same namespace have the same name.
+    DuplicateName { table: &'static str, name: String },
+    /// The entry's declaration pointer doesn't point at the statement that declares the item.
+    WrongDecl { table: &'static str, num: u32, ptr: u64 },
+    /// The entry's kind isn't the command of the statement that declares the item.
+    WrongKind { table: &'static str, num: u32, kind: u8, expected: u8 },
+    /// The binary search tree over names is malformed.
+    BadTree(String),
+}
+
+impl core::fmt::Display for IndexErr {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            IndexErr::BadEntry { table, num, ptr } =>
+                write!(f, "{} {}: the entry pointer {} doesn't point at an index entry", table, num, ptr),
+            IndexErr::WrongNumber { table, num, ix } =>
+                write!(f, "{} {}: the entry is for {} {}", table, num, table, ix),
+            IndexErr::BadName { table, num, name } =>
+                write!(f, "{} {}: {:?} is not a valid identifier", table, num, name),
+            IndexErr::DuplicateName { table, name } =>
+                write!(f, "{}: the name {} is used more than once", table, name),
+            IndexErr::WrongDecl { table, num, ptr } =>
+                write!(f, "{} {}: the declaration pointer {} doesn't point at its statement", table, num, ptr),
+            IndexErr::WrongKind { table, num, kind, expected } =>
+                write!(f, "{} {}: the entry has kind {}, but is declared by a statement of kind {}", table, num, kind, expected),
+            IndexErr::BadTree(msg) => write!(f, "name tree: {}", msg),
+        }
+    }
+}
+
+/// MM0 identifiers are `[a-zA-Z_][a-zA-Z0-9_]*`.
+pub(crate) fn is_ident(name: &[u8]) -> bool {
+    match name.split_first() {
+        Some((fst, rest)) =>
+            (fst.is_ascii_alphabetic() || *fst == b'_')
+            && rest.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_'),
+        None => false
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Where each sort's, term's, and assertion's declaring statement starts, and the
+    /// command code of that statement; this is what index entries point at.
+    pub(crate) fn stmt_starts(&self) -> [Vec<(u64, u8)>; 3] {
+        use core::convert::TryFrom;
+        use crate::mmb::stmt::StmtCmd;
+        let mut out = [Vec::new(), Vec::new(), Vec::new()];
+        let mut pos = self.header.proof_stream_start as usize;
+        while let Ok((cmd, data, _)) = crate::util::parse_cmd(self.mmb(), pos) {
+            if cmd == 0 {
+                break
+            }
+            match StmtCmd::try_from(cmd) {
+                Ok(StmtCmd::Sort { .. }) => out[0].push((pos as u64, cmd)),
+                Ok(StmtCmd::TermDef { .. }) => out[1].push((pos as u64, cmd)),
+                Ok(StmtCmd::Axiom { .. }) | Ok(StmtCmd::Thm { .. }) => out[2].push((pos as u64, cmd)),
+                Err(_) => {}
+            }
+            if data == 0 {
+                break
+            }
+            pos += data as usize;
+        }
+        out
+    }
+
+    /// Check the index against the rest of the file, returning everything that's wrong
+    /// with it. A file without an index has nothing to check.
+    pub fn check_index(&self) -> Vec<IndexErr> {
+        let mut errs = Vec::new();
+        if self.header.index_start == 0 {
+            return errs
+        }
+
+        let [sort_decls, term_decls, thm_decls] = self.stmt_starts();
+
+        let tables = [
+            ("sort", &self.index.sorts, &sort_decls),
+            ("term", &self.index.terms, &term_decls),
+            ("thm", &self.index.thms, &thm_decls),
+        ];
+        // Sorts have their own namespace; terms and theorems share one.
+        let mut sort_names = crate::util::HashMap::new();
+        let mut term_thm_names = crate::util::HashMap::new();
+        for (table, ptrs, decls) in tables.iter() {
+            for (num, ptr) in ptrs.iter().enumerate() {
+                let num = num as u32;
+                let entry = match self.index_entry(*ptr as usize) {
+                    Some(entry) => entry,
+                    None => {
+                        errs.push(IndexErr::BadEntry { table, num, ptr: *ptr });
+                        continue
+                    }
+                };
+                if entry.ix != num {
+                    errs.push(IndexErr::WrongNumber { table, num, ix: entry.ix });
+                }
+                if !is_ident(entry.charbuff) {
+                    let name = String::from_utf8_lossy(entry.charbuff).to_string();
+                    errs.push(IndexErr::BadName { table, num, name });
+                } else {
+                    let names = if *table == "sort" { &mut sort_names } else { &mut term_thm_names };
+                    if names.insert(entry.charbuff, ()).is_some() {
+                        let name = String::from_utf8_lossy(entry.charbuff).to_string();
+                        errs.push(IndexErr::DuplicateName { table, name });
+                    }
+                }
+                match decls.get(num as usize) {
+                    Some((decl_pos, _)) if *decl_pos != entry.proof =>
+                        errs.push(IndexErr::WrongDecl { table, num, ptr: entry.proof }),
+                    Some((_, cmd)) if *cmd != entry.kind =>
+                        errs.push(IndexErr::WrongKind { table, num, kind: entry.kind, expected: *cmd }),
+                    None => errs.push(IndexErr::WrongDecl { table, num, ptr: entry.proof }),
+                    _ => {}
+                }
+            }
+        }
+
+        self.check_name_tree(&mut errs);
+        errs
+    }
+
+    /// The tree has to reach every entry in the tables exactly once, and nothing else,
+    /// with names in order.
+    fn check_name_tree(&self, errs: &mut Vec<IndexErr>) {
+        let expected: crate::util::HashMap<u64, ()> = self.index.sorts
+            .iter()
+            .chain(self.index.terms.iter())
+            .chain(self.index.thms.iter())
+            .map(|ptr| (*ptr, ()))
+            .collect();
+        let mut seen = crate::util::HashMap::new();
+        let mut prev: Option<&'a [u8]> = None;
+        let mut stack = Vec::new();
+        let mut cur = self.index.root;
+        // An in-order walk, stopping at the first problem since the rest of
+        // the tree can't be trusted after that.
+        loop {
+            while cur != 0 {
+                if seen.insert(cur, ()).is_some() {
+                    return errs.push(IndexErr::BadTree(format!("the node @ {} is reachable more than once", cur)))
+                }
+                if !expected.contains_key(&cur) {
+                    return errs.push(IndexErr::BadTree(format!("the node @ {} isn't an entry of any table", cur)))
+                }
+                match self.index_entry(cur as usize) {
+                    Some(entry) => {
+                        stack.push((cur, entry.charbuff, entry.right));
+                        cur = entry.left;
+                    }
+                    None => return errs.push(IndexErr::BadTree(format!("the node @ {} can't be read", cur))),
+                }
+            }
+            match stack.pop() {
+                None => break,
+                Some((node, name, right)) => {
+                    if let Some(prev) = prev {
+                        if prev > name {
+                            return errs.push(IndexErr::BadTree(format!("the node @ {} is out of order", node)))
+                        }
+                    }
+                    prev = Some(name);
+                    cur = right;
+                }
+            }
+        }
+        if seen.len() != expected.len() {
+            errs.push(IndexErr::BadTree(format!("{} entries aren't reachable from the root", expected.len() - seen.len())))
+        }
+    }
+}
+
+#[test]
+fn check_index1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(outline.check_index().is_empty());
+
+    // Swap the entries for `ax_1` and `ax_2`.
+    let mut outline = outline;
+    outline.index.thms.swap(0, 1);
+    let errs = outline.check_index();
+    assert!(errs.contains(&IndexErr::WrongNumber { table: "thm", num: 0, ix: 1 }));
+    assert!(errs.contains(&IndexErr::WrongDecl { table: "thm", num: 1, ptr: 272 }));
+
+    let mut bytes = file_data.mmb_file.clone();
+    let name_at = outline.index.sorts[0] as usize + 37;
+    assert_eq!(&bytes[name_at..name_at + 3], b"wff");
+    bytes[name_at] = b'1';
+    let corrupt = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let outline = Outline::new_from(&corrupt).unwrap();
+    assert_eq!(outline.check_index()[0], IndexErr::BadName { table: "sort", num: 0, name: "1ff".to_string() });
+}
@@ -0,0 +1,202 @@
+//! Writing a fresh index for an mmb file that doesn't have one, for pipelines that strip
+//! indexes to save space and want them back later. The index gets the names it's given
+//! (usually the ones from the mm0 files), a balanced name tree, and zeroes for the
+//! line/column information, since the mmb file doesn't know where anything came from.
+//!
+//! The new index is appended to a copy of the file, aligned to 8 bytes, and the
+//! header's `index_start` is pointed at it. Nothing else in the file moves.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::index::is_ident;
+use crate::util::{ Res, VerifErr, HashMap };
+use crate::make_sure;
+
+/// The offset of `index_start` in the header.
+const INDEX_START_OFFSET: usize = 32;
+/// The size of an index entry, not counting its name.
+const ENTRY_HEADER_SIZE: usize = 37;
+
+/// Names for the items of each table, by number. Items without one (local
+/// declarations don't appear in the mm0 files, for example) get a made up name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexNames {
+    pub sorts: Vec<Option<String>>,
+    pub terms: Vec<Option<String>>,
+    pub thms: Vec<Option<String>>,
+}
+
+struct NewEntry {
+    pos: usize,
+    name: Vec<u8>,
+    proof: u64,
+    ix: u32,
+    kind: u8,
+    left: u64,
+    right: u64,
+}
+
+fn align8(n: usize) -> usize {
+    (n + 7) & !7
+}
+
+impl<'a> Outline<'a> {
+    /// The names of the public sorts, terms, and assertions, taken from the mm0 files.
+    /// This parses the mm0 files the same way verification does, so they have to match the mmb file.
+    pub fn mmz_names(&'a self) -> Res<IndexNames> {
+        let mut mem = crate::mmz::MmzMem::new_from(self)?;
+        let mut bump = bumpalo::Bump::new();
+        for (stmt, _) in self.declarations.iter() {
+            mem.verify1(&mut bump, *stmt)?;
+        }
+        let mut names = IndexNames {
+            sorts: vec![None; self.header.num_sorts as usize],
+            terms: vec![None; self.header.num_terms as usize],
+            thms: vec![None; self.header.num_thms as usize],
+        };
+        let to_string = |s: &crate::util::Str| String::from_utf8_lossy(s.as_bytes()).to_string();
+        for (num, ident) in mem.sort_idents().iter().enumerate() {
+            if let Some(slot) = names.sorts.get_mut(num) {
+                *slot = Some(to_string(ident));
+            }
+        }
+        for (ident, num) in mem.nonlocal_termdefs.iter() {
+            if let Some(slot) = names.terms.get_mut(*num as usize) {
+                *slot = Some(to_string(ident));
+            }
+        }
+        for (ident, num) in mem.nonlocal_asserts.iter() {
+            if let Some(slot) = names.thms.get_mut(*num as usize) {
+                *slot = Some(to_string(ident));
+            }
+        }
+        Ok(names)
+    }
+
+    /// A copy of the mmb file with a new index built from `names`.
+    pub fn with_new_index(&self, names: &IndexNames) -> Res<Vec<u8>> {
+        make_sure!(self.header.index_start == 0);
+        let [sort_decls, term_decls, thm_decls] = self.stmt_starts();
+        make_sure!(sort_decls.len() == self.header.num_sorts as usize);
+        make_sure!(term_decls.len() == self.header.num_terms as usize);
+        make_sure!(thm_decls.len() == self.header.num_thms as usize);
+
+        let mut out = self.mmb().to_vec();
+        out.resize(align8(out.len()), 0);
+        let index_start = out.len();
+        let num_entries = sort_decls.len() + term_decls.len() + thm_decls.len();
+        let mut pos = index_start + 8 * (1 + num_entries);
+
+        // Sorts have their own namespace; terms and theorems share one.
+        let mut sort_names = HashMap::new();
+        let mut term_thm_names = HashMap::new();
+        let mut entries = Vec::with_capacity(num_entries);
+        let tables = [
+            ("sort", &names.sorts, &sort_decls),
+            ("term", &names.terms, &term_decls),
+            ("thm", &names.thms, &thm_decls),
+        ];
+        for (table, given, decls) in tables.iter() {
+            let used = if *table == "sort" { &mut sort_names } else { &mut term_thm_names };
+            for (num, (proof, kind)) in decls.iter().enumerate() {
+                let name = match given.get(num).cloned().flatten() {
+                    Some(name) => {
+                        if !is_ident(name.as_bytes()) {
+                            return Err(VerifErr::Msg(format!("{} {}: {:?} is not a valid identifier", table, num, name)))
+                        }
+                        if used.contains_key(&name) {
+                            return Err(VerifErr::Msg(format!("{} {}: the name {} is used more than once", table, num, name)))
+                        }
+                        name
+                    }
+                    None => {
+                        let mut name = format!("_{}{}", table, num);
+                        while used.contains_key(&name) {
+                            name.push('_');
+                        }
+                        name
+                    }
+                };
+                used.insert(name.clone(), ());
+                let size = ENTRY_HEADER_SIZE + name.len() + 1;
+                entries.push(NewEntry {
+                    pos,
+                    name: name.into_bytes(),
+                    proof: *proof,
+                    ix: num as u32,
+                    kind: *kind,
+                    left: 0,
+                    right: 0,
+                });
+                pos = align8(pos + size);
+            }
+        }
+
+        let mut by_name: Vec<usize> = (0..entries.len()).collect();
+        by_name.sort_by(|x, y| entries[*x].name.cmp(&entries[*y].name));
+        let root = link_tree(&mut entries, &by_name);
+
+        out.extend_from_slice(&root.to_le_bytes());
+        for e in entries.iter() {
+            out.extend_from_slice(&(e.pos as u64).to_le_bytes());
+        }
+        for e in entries.iter() {
+            out.resize(e.pos, 0);
+            out.extend_from_slice(&e.left.to_le_bytes());
+            out.extend_from_slice(&e.right.to_le_bytes());
+            // Row and column.
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&0u32.to_le_bytes());
+            out.extend_from_slice(&e.proof.to_le_bytes());
+            out.extend_from_slice(&e.ix.to_le_bytes());
+            out.push(e.kind);
+            out.extend_from_slice(&e.name);
+            out.push(0);
+        }
+        out.resize(align8(out.len()), 0);
+        out[INDEX_START_OFFSET..INDEX_START_OFFSET + 8].copy_from_slice(&(index_start as u64).to_le_bytes());
+        Ok(out)
+    }
+}
+
+/// Make a balanced binary search tree out of the entries in `sorted` (which are sorted by
+/// name), returning the position of the root, or 0 if there are no entries.
+fn link_tree(entries: &mut [NewEntry], sorted: &[usize]) -> u64 {
+    if sorted.is_empty() {
+        return 0
+    }
+    let mid = sorted.len() / 2;
+    let left = link_tree(entries, &sorted[..mid]);
+    let right = link_tree(entries, &sorted[mid + 1..]);
+    let e = &mut entries[sorted[mid]];
+    e.left = left;
+    e.right = right;
+    e.pos as u64
+}
+
+#[test]
+fn index_gen1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let names = outline.mmz_names().unwrap();
+    assert_eq!(names.terms, vec![Some("im".to_string()), Some("not".to_string())]);
+
+    // Strip the index, then put a new one back.
+    let mut stripped = file_data.mmb_file[..outline.header.index_start as usize].to_vec();
+    stripped[INDEX_START_OFFSET..INDEX_START_OFFSET + 8].copy_from_slice(&0u64.to_le_bytes());
+    let stripped = crate::fs::FileData::new_from_bytes(stripped, file_data.mmz_files.clone());
+    let stripped_outline = Outline::new_from(&stripped).unwrap();
+    assert!(stripped_outline.term_name(0).is_none());
+    let mut partial = names.clone();
+    partial.thms[3] = None;
+    let regenerated = crate::fs::FileData::new_from_bytes(stripped_outline.with_new_index(&partial).unwrap(), Vec::new());
+    let regenerated = Outline::new_from(&regenerated).unwrap();
+
+    assert!(regenerated.check_index().is_empty());
+    assert!(crate::verify_serial(&regenerated, &regenerated.declarations).is_empty());
+    for (stmt, _) in outline.declarations.iter().take(6) {
+        assert_eq!(regenerated.stmt_name(*stmt), outline.stmt_name(*stmt));
+    }
+    assert_eq!(regenerated.assert_name(3), Some("_thm3"));
+    assert!(regenerated.with_new_index(&names).is_err());
+}
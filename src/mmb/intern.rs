@@ -0,0 +1,83 @@
+//! Optional hash-consing of the expressions built by a proof, turned on with
+//! `VerifyOptions::hash_cons`. Big proofs tend to rebuild the same subexpressions over and
+//! over; with hash-consing, `proof_term` hands back the existing `MmbExpr::App` instead of
+//! allocating a new one, which keeps the arena smaller.
+//!
+//! Every application is built by `proof_term`, and every variable is allocated exactly once,
+//! so when this is on, two expressions are equal exactly when they're the same allocation.
+//! The table is keyed by the term, the type, and the addresses of the arguments (which have
+//! been hash-consed already), so looking something up never walks an expression.
+
+use crate::prelude::*;
+use crate::mmb::{ MmbItem, MmbExpr };
+use crate::util::{ Res, Type, VerifErr, HashMap };
+
+#[derive(Debug, Default)]
+pub struct Interner<'b> {
+    /// Applications by a hash of their key. Collisions are resolved by comparing keys.
+    table: HashMap<u64, Vec<&'b MmbItem<'b>>>,
+    /// How many applications were shared rather than allocated.
+    pub hits: u64,
+}
+
+fn mix(h: u64, x: u64) -> u64 {
+    (h.rotate_left(5) ^ x).wrapping_mul(0x517c_c1b7_2722_0a95)
+}
+
+fn key(term_num: u32, ty: Type, args: &[&MmbItem]) -> u64 {
+    args.iter().fold(mix(term_num as u64, ty.inner), |h, arg| mix(h, *arg as *const MmbItem as usize as u64))
+}
+
+fn same_args(xs: &[&MmbItem], ys: &[&MmbItem]) -> bool {
+    xs.len() == ys.len() && xs.iter().zip(ys.iter()).all(|(x, y)| core::ptr::eq(*x, *y))
+}
+
+impl<'b> Interner<'b> {
+    /// The existing application of `term_num` to `args` with type `ty`, if there is one.
+    pub fn get(&mut self, term_num: u32, ty: Type, args: &[&'b MmbItem<'b>]) -> Option<&'b MmbItem<'b>> {
+        let found = self.table.get(&key(term_num, ty, args))?.iter().copied().find(|item| match item {
+            MmbItem::Expr(MmbExpr::App { term_num: n, ty: t, args: a }) =>
+                *n == term_num && *t == ty && same_args(a, args),
+            _ => false,
+        });
+        if found.is_some() {
+            self.hits += 1;
+        }
+        found
+    }
+
+    /// Add a newly built application.
+    pub fn insert(&mut self, item: &'b MmbItem<'b>) -> Res<()> {
+        match item {
+            MmbItem::Expr(MmbExpr::App { term_num, ty, args }) => {
+                self.table.entry(key(*term_num, *ty, args)).or_default().push(item);
+                Ok(())
+            }
+            _ => Err(VerifErr::Unreachable(file!(), line!())),
+        }
+    }
+}
+
+#[test]
+fn intern1() {
+    let bump = bumpalo::Bump::new();
+    let ty = Type { inner: 0 };
+    let x: &MmbItem = bump.alloc(MmbItem::Expr(bump.alloc(MmbExpr::Var { idx: 0, ty })));
+    let y: &MmbItem = bump.alloc(MmbItem::Expr(bump.alloc(MmbExpr::Var { idx: 1, ty })));
+    let args = bump.alloc_slice_copy(&[x, y]);
+    let xy: &MmbItem = bump.alloc(MmbItem::Expr(bump.alloc(MmbExpr::App { term_num: 0, args, ty })));
+    let mut interner = Interner::default();
+    interner.insert(xy).unwrap();
+    assert!(core::ptr::eq(interner.get(0, ty, &[x, y]).unwrap(), xy));
+    assert!(interner.get(0, ty, &[y, x]).is_none());
+    assert!(interner.get(1, ty, &[x, y]).is_none());
+    assert_eq!(interner.hits, 1);
+    assert!(interner.insert(x).is_err());
+
+    for path in ["./test_resources/short.mmb", "./test_resources/a.mmb"].iter() {
+        let file_data = crate::fs::FileData::new_from(path, None::<String>).unwrap();
+        let mut outline = crate::Outline::new_from(&file_data).unwrap();
+        outline.options.hash_cons = true;
+        assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Linking several mmb files into one, for developments whose parts are compiled and
+//! verified separately. The files can share declarations (typically a common prefix,
+//! like the same base library) as long as they agree on what those declarations say:
+//! items are matched up by name, so every file needs an index, and an item declared
+//! by more than one file has to have the same statement in each (compared by names,
+//! as in `crate::diff`). Shared items are written once, with the proof from the first
+//! file declaring them; everything else is written in order, file by file.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::write::{ Renumbering, Source, write_mmb };
+use crate::util::{ Res, VerifErr, HashMap };
+use crate::none_err;
+
+/// Where an item first came from and its new number.
+#[derive(Debug, Clone, Copy)]
+struct Linked {
+    file: usize,
+    decl: usize,
+    new_num: u32,
+}
+
+/// Link `files` into a single mmb file, in order.
+pub fn link(files: &[&Outline]) -> Res<Vec<u8>> {
+    let mut sorts: HashMap<String, Linked> = HashMap::new();
+    // Terms and theorems share a namespace.
+    let mut terms_thms: HashMap<String, (bool, Linked)> = HashMap::new();
+    let (mut num_sorts, mut num_terms, mut num_thms) = (0u32, 0u32, 0u32);
+    let mut sources = Vec::with_capacity(files.len());
+
+    for (file_idx, outline) in files.iter().enumerate() {
+        let mut renum = Renumbering::new_for(outline);
+        let mut keep = vec![false; outline.declarations.len()];
+        for (decl_idx, (stmt, proof)) in outline.declarations.iter().enumerate() {
+            let name = match outline.stmt_name(*stmt) {
+                Some(name) => name.to_string(),
+                None => return Err(VerifErr::Msg(format!("file {}: linking needs names, but {:?} doesn't have one in the index", file_idx, stmt))),
+            };
+            let is_term = matches!(stmt, StmtCmd::TermDef {..});
+            let existing = match stmt {
+                StmtCmd::Sort {..} => sorts.get(&name).copied(),
+                _ => match terms_thms.get(&name) {
+                    Some((was_term, linked)) if *was_term == is_term => Some(*linked),
+                    Some(_) => return Err(VerifErr::Msg(format!("file {}: {} is a term in one file and a theorem in another", file_idx, name))),
+                    None => None,
+                }
+            };
+
+            let new_num = match existing {
+                Some(linked) => {
+                    let other = files[linked.file];
+                    let (other_stmt, other_proof) = other.declarations[linked.decl];
+                    let same = match (stmt, other_stmt) {
+                        (StmtCmd::Sort { num }, StmtCmd::Sort { num: other_num }) =>
+                            outline.get_sort_mods(none_err!(*num)? as usize)? == other.get_sort_mods(none_err!(other_num)? as usize)?,
+                        _ => outline.fingerprint(*stmt, *proof)?.statement == other.fingerprint(other_stmt, other_proof)?.statement,
+                    };
+                    if !same {
+                        return Err(VerifErr::Msg(format!("files {} and {} disagree about what {} says", linked.file, file_idx, name)))
+                    }
+                    linked.new_num
+                }
+                None => {
+                    keep[decl_idx] = true;
+                    let counter = match stmt {
+                        StmtCmd::Sort {..} => &mut num_sorts,
+                        StmtCmd::TermDef {..} => &mut num_terms,
+                        StmtCmd::Axiom {..} | StmtCmd::Thm {..} => &mut num_thms,
+                    };
+                    let linked = Linked { file: file_idx, decl: decl_idx, new_num: *counter };
+                    *counter += 1;
+                    match stmt {
+                        StmtCmd::Sort {..} => { sorts.insert(name, linked); }
+                        _ => { terms_thms.insert(name, (is_term, linked)); }
+                    }
+                    linked.new_num
+                }
+            };
+
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    *none_err!(renum.sorts.get_mut(none_err!(num)? as usize))? = Some(new_num as u8);
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    *none_err!(renum.terms.get_mut(none_err!(num)? as usize))? = Some(new_num);
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    *none_err!(renum.thms.get_mut(none_err!(num)? as usize))? = Some(new_num);
+                }
+            }
+        }
+        sources.push(Source { outline, keep, renum, compact: false });
+    }
+    write_mmb(&sources)
+}
+
+#[test]
+fn link1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let pos = |name| outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some(name)).unwrap();
+
+    // Two slices sharing `wff` and `im`.
+    let a = crate::fs::FileData::new_from_bytes(outline.slice(&[pos("ax_mp")]).unwrap(), Vec::new());
+    let a = Outline::new_from(&a).unwrap();
+    let b = crate::fs::FileData::new_from_bytes(outline.slice(&[pos("ax_3")]).unwrap(), Vec::new());
+    let b = Outline::new_from(&b).unwrap();
+    let linked = crate::fs::FileData::new_from_bytes(link(&[&a, &b]).unwrap(), Vec::new());
+    let linked = Outline::new_from(&linked).unwrap();
+    assert_eq!((linked.header.num_sorts, linked.header.num_terms, linked.header.num_thms), (1, 2, 2));
+    assert!(linked.check_index().is_empty());
+    assert!(crate::verify_serial(&linked, &linked.declarations).is_empty());
+    let changes = crate::diff::diff(&outline, &linked).unwrap();
+    assert_eq!(changes, vec![
+        crate::diff::Change::Removed { table: "thm", name: "ax_1".to_string() },
+        crate::diff::Change::Removed { table: "thm", name: "ax_2".to_string() },
+    ]);
+
+    // A file with a different `wff` can't be linked with the original.
+    let mut bytes = file_data.mmb_file.clone();
+    bytes[outline.header.sort_data_start as usize] ^= crate::mmb::SORT_STRICT;
+    let changed = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let changed = Outline::new_from(&changed).unwrap();
+    assert!(link(&[&outline, &changed]).is_err());
+}
@@ -0,0 +1,236 @@
+//! Lints for generated proof streams. None of these make a file invalid, but each one
+//! is a few wasted bytes (and interpreter steps) that an exporter could have avoided.
+//! The linter only reads the streams; it doesn't check that the proofs are correct.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::{ Res, VerifErr };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::{ ProofIter, ProofCmd };
+use crate::mmb::unify::{ UnifyIter, UnifyCmd };
+use crate::none_err;
+
+/// How many example offsets to keep for each kind of lint.
+pub const MAX_EXAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LintKind {
+    /// A `Save` (or `TermSave`/`ThmSave`/`ConvSave`) whose heap entry is never `Ref`'d,
+    /// though something saved after it is.
+    UnusedSave,
+    /// A `Save` of something that's cheap to rebuild: a variable (which is already on the
+    /// heap) or a term with no arguments.
+    LeafSave,
+    /// A `UTermSave` in a unify stream that's never `URef`'d, or that saves a term with no arguments.
+    RedundantUnifySave,
+    /// The proof ends with saved heap entries that are never used.
+    DeadHeapTail,
+}
+
+impl LintKind {
+    pub const ALL: [LintKind; 4] = [
+        LintKind::UnusedSave,
+        LintKind::LeafSave,
+        LintKind::RedundantUnifySave,
+        LintKind::DeadHeapTail,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LintKind::UnusedSave => "unused-save",
+            LintKind::LeafSave => "leaf-save",
+            LintKind::RedundantUnifySave => "redundant-unify-save",
+            LintKind::DeadHeapTail => "dead-heap-tail",
+        }
+    }
+
+    fn idx(self) -> usize {
+        self as usize
+    }
+}
+
+/// One occurrence of a lint, at the offset of the offending command in the mmb file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LintHit {
+    pub kind: LintKind,
+    pub decl: StmtCmd,
+    pub pos: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LintReport {
+    /// Indexed by `LintKind as usize`.
+    pub counts: [usize; 4],
+    /// The first `MAX_EXAMPLES` hits of each kind, in file order.
+    pub examples: Vec<LintHit>,
+}
+
+impl LintReport {
+    pub fn count(&self, kind: LintKind) -> usize {
+        self.counts[kind.idx()]
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    fn add(&mut self, kind: LintKind, decl: StmtCmd, pos: usize) {
+        if self.counts[kind.idx()] < MAX_EXAMPLES {
+            self.examples.push(LintHit { kind, decl, pos });
+        }
+        self.counts[kind.idx()] += 1;
+    }
+}
+
+/// What the linter knows about a heap entry.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// The offset of the command that saved it, if it was saved explicitly
+    /// (rather than being an argument, dummy, or hypothesis).
+    saved_at: Option<usize>,
+    leaf: bool,
+    refs: u32,
+}
+
+impl Slot {
+    fn implicit() -> Self {
+        Slot { saved_at: None, leaf: false, refs: 0 }
+    }
+
+    fn saved(pos: usize, leaf: bool) -> Self {
+        Slot { saved_at: Some(pos), leaf, refs: 0 }
+    }
+}
+
+fn referenced(heap: &mut [Slot], i: u32) -> Res<()> {
+    none_err!(heap.get_mut(i as usize))?.refs += 1;
+    Ok(())
+}
+
+impl<'a> Outline<'a> {
+    /// Lint the proof and unify streams of every declaration.
+    pub fn lint(&self) -> Res<LintReport> {
+        let mut report = LintReport::default();
+        for (stmt, proof) in self.declarations.iter() {
+            match *stmt {
+                StmtCmd::Sort {..} => {},
+                StmtCmd::TermDef { num, .. } => {
+                    let term = self.get_term_by_num(none_err!(num)?)?;
+                    if term.is_def() {
+                        let num_args = term.args_no_ret().len();
+                        self.lint_proof(&mut report, *stmt, *proof, num_args)?;
+                        self.lint_unify(&mut report, *stmt, term.unify(), num_args)?;
+                    }
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let assert = self.get_assert_by_num(none_err!(num)?)?;
+                    let num_args = assert.args().len();
+                    self.lint_proof(&mut report, *stmt, *proof, num_args)?;
+                    self.lint_unify(&mut report, *stmt, assert.unify(), num_args)?;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn lint_proof(&self, report: &mut LintReport, stmt: StmtCmd, proof: ProofIter, num_args: usize) -> Res<()> {
+        if proof.is_null() {
+            return Ok(())
+        }
+        let mut heap = vec![Slot::implicit(); num_args];
+        // The previous command, if it pushed something that's already on the heap.
+        let mut prev_on_heap = false;
+        for maybe_cmd in proof.spanned() {
+            let (pos, cmd) = maybe_cmd?;
+            let mut on_heap = false;
+            match cmd {
+                ProofCmd::Ref(i) => {
+                    referenced(&mut heap, i)?;
+                    on_heap = true;
+                }
+                ProofCmd::ConvRef(i) => referenced(&mut heap, i)?,
+                ProofCmd::Dummy {..} => {
+                    heap.push(Slot::implicit());
+                    on_heap = true;
+                }
+                ProofCmd::Hyp => heap.push(Slot::implicit()),
+                ProofCmd::Save => heap.push(Slot::saved(pos, prev_on_heap)),
+                ProofCmd::ConvSave => heap.push(Slot::saved(pos, false)),
+                ProofCmd::Term { term_num, save: true } => {
+                    let leaf = self.get_term_by_num(term_num)?.args_no_ret().len() == 0;
+                    heap.push(Slot::saved(pos, leaf))
+                }
+                ProofCmd::Thm { save: true, .. } => heap.push(Slot::saved(pos, false)),
+                _ => {}
+            }
+            prev_on_heap = on_heap;
+        }
+
+        let live_until = heap.iter().rposition(|s| s.refs != 0 || s.saved_at.is_none()).map(|n| n + 1).unwrap_or(0);
+        for (idx, slot) in heap.iter().enumerate() {
+            if let Some(pos) = slot.saved_at {
+                if slot.leaf {
+                    report.add(LintKind::LeafSave, stmt, pos)
+                } else if slot.refs == 0 && idx >= live_until {
+                    report.add(LintKind::DeadHeapTail, stmt, pos)
+                } else if slot.refs == 0 {
+                    report.add(LintKind::UnusedSave, stmt, pos)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lint_unify(&self, report: &mut LintReport, stmt: StmtCmd, unify: UnifyIter, num_args: usize) -> Res<()> {
+        let mut heap = vec![Slot::implicit(); num_args];
+        for maybe_cmd in unify.spanned() {
+            let (pos, cmd) = maybe_cmd?;
+            match cmd {
+                UnifyCmd::Ref(i) => referenced(&mut heap, i)?,
+                UnifyCmd::Dummy {..} => heap.push(Slot::implicit()),
+                UnifyCmd::Term { term_num, save: true } => {
+                    let leaf = self.get_term_by_num(term_num)?.args_no_ret().len() == 0;
+                    heap.push(Slot::saved(pos, leaf))
+                }
+                _ => {}
+            }
+        }
+        for slot in heap.iter() {
+            if let Some(pos) = slot.saved_at {
+                if slot.leaf || slot.refs == 0 {
+                    report.add(LintKind::RedundantUnifySave, stmt, pos)
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn lint_proof1() {
+    use crate::testgen::stream;
+    use crate::mmb::proof::{ PROOF_REF, PROOF_SAVE, PROOF_HYP, PROOF_THM_SAVE, PROOF_DUMMY };
+    let file_data = crate::fs::FileData::new_from("./test_resources/a.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert_eq!(outline.lint().unwrap().total(), 0);
+
+    // With one argument on the heap, one byte per command:
+    let buf = stream(&[
+        (PROOF_REF, 0), (PROOF_SAVE, 0), (PROOF_HYP, 0), (PROOF_SAVE, 0),
+        (PROOF_THM_SAVE, 0), (PROOF_DUMMY, 0), (PROOF_THM_SAVE, 0),
+    ]);
+    let proof = ProofIter::new(&buf, 0, buf.len());
+    let stmt = StmtCmd::Thm { num: Some(0), local: false };
+    let mut report = LintReport::default();
+    outline.lint_proof(&mut report, stmt, proof, 1).unwrap();
+    let hits: Vec<(LintKind, usize)> = report.examples.iter().map(|h| (h.kind, h.pos)).collect();
+    assert_eq!(hits, vec![
+        (LintKind::LeafSave, 1),
+        (LintKind::UnusedSave, 3),
+        (LintKind::UnusedSave, 4),
+        (LintKind::DeadHeapTail, 6),
+    ]);
+}
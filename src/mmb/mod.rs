@@ -1,9 +1,11 @@
-use std::convert::TryFrom;
+use crate::prelude::*;
+use core::convert::TryFrom;
+use crate::options::Instant;
 use bumpalo::Bump;
 use bumpalo::collections::Vec as BumpVec;
 use crate::make_sure;
 use crate::Outline;
-use crate::util::{ Res, VerifErr };
+use crate::util::{ Res, VerifErr, HashMap, MAX_BOUND_VARS };
 use crate::mmb::proof::{ ProofIter };
 use crate::util::{ 
     Type,
@@ -17,34 +19,59 @@ use crate::util::{
 };
 use crate::mmb::stmt::StmtCmd;
 use crate::conv_err;
-use crate::none_err;
 
+pub mod format;
 pub mod proof;
 pub mod unify;
+pub mod hstack;
 pub mod index;
 pub mod stmt;
-
-const MM0B_MAGIC: u32 = 0x42304D4D;
-
-// Each sort has one byte associated to it, which
-// contains flags for the sort modifiers.
-// The high four bits are unused.
-pub const SORT_PURE     : u8 = 1;
-pub const SORT_STRICT   : u8 = 2;
-pub const SORT_PROVABLE : u8 = 4;
-pub const SORT_FREE     : u8 = 8;
-
-/// bound mask: 10000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000
-pub const TYPE_BOUND_MASK: u64 = 1 << 63;
-
-
-/// deps mask: 00000000_11111111_11111111_11111111_11111111_11111111_11111111_11111111
-pub const TYPE_DEPS_MASK: u64 = (1 << 56) - 1;
+pub mod deps;
+pub mod axioms;
+pub mod ext;
+pub mod lint;
+pub mod warn;
+pub mod coverage;
+pub mod statement;
+pub mod query;
+pub mod index_gen;
+pub mod write;
+pub mod build;
+pub mod slice;
+pub mod optimize;
+pub mod link;
+pub mod intern;
+pub mod conv;
+pub mod sort_mods;
+pub mod decls;
+pub mod dump;
+pub mod trust;
+pub mod disasm;
+pub mod asm;
+pub mod strict;
+pub mod regions;
+pub mod tables;
+pub mod validate;
+pub mod tree;
+pub mod encode;
+pub mod trace;
+pub mod html;
+pub mod visit;
+pub mod sandbox;
+pub mod recheck;
+#[cfg(feature = "std")]
+pub mod profile;
+
+pub use proof::ProofCmd;
+pub use unify::UnifyCmd;
+
+pub use format::{ MM0B_MAGIC, SORT_PURE, SORT_STRICT, SORT_PROVABLE, SORT_FREE, TYPE_BOUND_MASK, TYPE_DEPS_MASK };
 
 
 // Returns true if a value with type 'from' can be cast to a value of type 'to'.
 // This requires that the sorts be the same, and additionally if 'to' is a
-// name then so is 'from'.
+// name then so is 'from'. Kept as the bit-level reference that
+// `Type::coercible_to` is checked against in debug builds.
 pub fn sorts_compatible(from: Type, to: Type) -> bool {
   let (from, to) = (from.inner, to.inner);
   let diff = from ^ to;
@@ -55,6 +82,7 @@ pub fn sorts_compatible(from: Type, to: Type) -> bool {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum MmbExpr<'b> {
     Var {
         idx: usize,
@@ -67,8 +95,34 @@ pub enum MmbExpr<'b> {
     },
 }
 
+/// The size and depth of the expression `item`, looked up in `sizes` by its address, or
+/// worked out from those of its arguments and added. The size is the number of nodes in
+/// the expression written out as a tree; since subexpressions are shared, this can be
+/// exponentially more than the memory it takes up, so it stops at `u64::MAX`. The depth
+/// is the number of nodes on the longest path from the root to a variable.
+fn measure<'b>(sizes: &mut HashMap<usize, (u64, u32)>, item: &'b MmbItem<'b>) -> (u64, u32) {
+    let args = match item {
+        MmbItem::Expr(MmbExpr::App { args, .. }) => args,
+        _ => return (1, 1),
+    };
+    let key = item as *const MmbItem as usize;
+    if let Some(m) = sizes.get(&key) {
+        return *m
+    }
+    let (mut size, mut depth) = (1u64, 0u32);
+    for arg in args.iter() {
+        let (s, d) = measure(sizes, arg);
+        size = size.saturating_add(s);
+        depth = depth.max(d);
+    }
+    let m = (size, depth.saturating_add(1));
+    sizes.insert(key, m);
+    m
+}
+
 // Stack item
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum MmbItem<'b> {
     Expr(&'b MmbExpr<'b>),
     Proof(&'b MmbItem<'b>),
@@ -77,6 +131,16 @@ pub enum MmbItem<'b> {
 }
 
 impl<'b> MmbItem<'b> {
+    /// What kind of item this is, for error messages.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            MmbItem::Expr(_) => "an expression",
+            MmbItem::Proof(_) => "a proof",
+            MmbItem::Conv(..) => "a conversion proof",
+            MmbItem::CoConv(..) => "a conversion obligation",
+        }
+    }
+
     pub fn get_ty(&self) -> Res<Type> {
         match self {
             | MmbItem::Expr(MmbExpr::Var { ty, .. })
@@ -102,7 +166,8 @@ impl<'b> MmbItem<'b> {
     }    
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     /// "= MM0B_VERSION"
     pub magic: u32,
@@ -130,28 +195,11 @@ pub struct Header {
     pub sort_data_start: u32,
 }
 
-impl std::default::Default for Header {
-    fn default() -> Header {
-        Header {
-            magic: 0,
-            version: 0,
-            num_sorts: 0,
-            reserved: 0,
-            num_terms: 0,
-            num_thms: 0,
-            terms_start: 0,
-            thms_start: 0,
-            proof_stream_start: 0,
-            reserved2: 0,
-            index_start: 0,
-            sort_data_start: 0
-        }
-    }
-}
-
 pub fn parse_header(mmb: &[u8]) -> Res<Header> {
     let (magic, source) = parse_u32(mmb)?;
-    assert_eq!(magic, MM0B_MAGIC);
+    if magic != MM0B_MAGIC {
+        return Err(VerifErr::Msg(format!("bad magic number {:#010x}; expected {:#010x}", magic, MM0B_MAGIC)))
+    }
     let (version, source) = parse_u8(source)?;
     let (num_sorts, source) = parse_u8(source)?;
     let (reserved, source) = parse_u16(source)?;
@@ -187,13 +235,38 @@ pub struct MmbState<'b, 'a: 'b> {
     pub heap: BumpVec<'b, &'b MmbItem<'b>>,
     pub ustack: BumpVec<'b, &'b MmbItem<'b>>,
     pub uheap: BumpVec<'b, &'b MmbItem<'b>>,
-    pub hstack: BumpVec<'b, &'b MmbItem<'b>>,     
-
-    pub next_bv: u64    
+    /// The hypotheses a theorem's proof has introduced; see `hstack`.
+    pub hstack: hstack::HypStack<'b>,
+    /// Only set if `options.unify_delta` is: the argument path to each entry of `ustack`
+    /// from the root of the expression it came from.
+    pub upaths: Option<Vec<Vec<u32>>>,
+
+    pub next_bv: u64,
+    /// The declaration being checked, for error reporting.
+    pub stmt: StmtCmd,
+    /// The offset of the proof command being run, for error reporting.
+    pub pos: usize,
+    /// The proof command being run, for error reporting, or `None` once the proof is done.
+    pub cmd: Option<proof::ProofCmd>,
+    /// Number of proof and unify commands executed so far for this declaration.
+    pub steps: u64,
+    /// Only set if there's a timeout, so we don't have to read the clock otherwise.
+    pub started: Option<Instant>,
+    /// Only set if `options.hash_cons` is.
+    pub interner: Option<intern::Interner<'b>>,
+    /// Only set when building a proof tree with `Outline::proof_tree`.
+    pub tree: Option<tree::TreeRecorder<'b>>,
+    /// Only set when something is watching, as with `Outline::visit`.
+    pub visitor: Option<&'b mut dyn visit::ProofVisitor>,
+    /// Only set if `options.phase_times` is: the time spent in `run_unify` so far.
+    pub unify_time: Option<core::time::Duration>,
+    /// Only set if `options.max_expr_size` or `options.max_expr_depth` is: the size and
+    /// depth of each application the proof has built, by address (see `check_expr_limits`).
+    pub expr_sizes: Option<HashMap<usize, (u64, u32)>>,
 }
 
 impl<'b, 'a: 'b> MmbState<'b, 'a> {
-    pub fn new_from(outline: &'a Outline, bump: &'b mut Bump) -> MmbState<'b, 'a> {
+    pub fn new_from(outline: &'a Outline, bump: &'b mut Bump, stmt: StmtCmd) -> MmbState<'b, 'a> {
         bump.reset();
         MmbState {
             outline,
@@ -202,12 +275,41 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             heap: BumpVec::new_in(&*bump),
             ustack: BumpVec::new_in(&*bump),
             uheap: BumpVec::new_in(&*bump),
-            hstack: BumpVec::new_in(&*bump),
-            next_bv: 1u64            
+            hstack: hstack::HypStack::new_in(&*bump),
+            upaths: if outline.options.unify_delta { Some(Vec::new()) } else { None },
+            next_bv: 1u64,
+            stmt,
+            pos: 0,
+            cmd: None,
+            steps: 0,
+            started: crate::options::start_clock(&outline.options),
+            interner: if outline.options.hash_cons { Some(intern::Interner::default()) } else { None },
+            tree: None,
+            visitor: None,
+            unify_time: if outline.options.phase_times { Some(Default::default()) } else { None },
+            expr_sizes: match outline.options.max_expr_size.is_some() || outline.options.max_expr_depth.is_some() {
+                true => Some(HashMap::new()),
+                false => None,
+            },
         }
     }    
 
     pub fn verify1(outline: &'a Outline<'a>, bump: &mut Bump, stmt: StmtCmd, proof: ProofIter<'a>) -> Res<()> {
+        MmbState::verify1_timed(outline, bump, stmt, proof, &mut Default::default())
+    }
+
+    /// `verify1`, adding the time spent unifying to `unify_time` if `options.phase_times`
+    /// is set.
+    pub fn verify1_timed(
+        outline: &'a Outline<'a>,
+        bump: &mut Bump,
+        stmt: StmtCmd,
+        proof: ProofIter<'a>,
+        unify_time: &mut core::time::Duration,
+    ) -> Res<()> {
+        // Anything past the end of its table is reported by `check_decl_counts`.
+        make_sure!(outline.in_table(stmt));
+        let shape = proof.prescan(stmt)?;
         match stmt {
             StmtCmd::Sort {..} => { 
                 if !proof.is_null() {
@@ -216,52 +318,238 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             },
             StmtCmd::TermDef { num, .. } => {
                 let term = outline.get_term_by_num(num.unwrap())?;
-                if !term.is_def() && !proof.is_null() {
-                    return Err(VerifErr::Msg(format!("mmb terms must have null proof iterators")));
+                if !term.is_def() {
+                    if !proof.is_null() {
+                        return Err(VerifErr::Msg("mmb terms must have null proof iterators".to_string()));
+                    }
+                    // There's nothing to run, so there's no need for a machine or the arena.
+                    check_args(outline, stmt, term.args())?;
+                } else {
+                    term.unify().prescan(stmt, term.num_args_no_ret() as usize)?;
+                    let mut state = MmbState::new_from(outline, bump, stmt);
+                    let res = state.verify_termdef(stmt, term, proof);
+                    *unify_time += state.unify_time.unwrap_or_default();
+                    res?;
                 }
-                MmbState::new_from(outline, bump).verify_termdef(stmt, term, proof)?;
             }
             StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
                 let assert = outline.get_assert_by_num(num.unwrap())?;
-                MmbState::new_from(outline, bump).verify_assert(stmt, assert, proof)?;
+                let ushape = assert.unify().prescan(stmt, assert.args().len())?;
+                if ushape.hyps != shape.hyps {
+                    let reason = format!("the statement has {} hypotheses, but the proof introduces {}", ushape.hyps, shape.hyps);
+                    return Err(VerifErr::MalformedUnify { decl: stmt, pos: assert.unify().pos, reason })
+                }
+                // An axiom with an empty proof has no statement to unify, which the machine
+                // would only find out after setting itself up.
+                if matches!(stmt, StmtCmd::Axiom {..}) && shape.cmds == 0 {
+                    check_args(outline, stmt, assert.args())?;
+                    // As the machine reports it, having run no command to take a position from.
+                    let opcode = "end of proof".to_string();
+                    return Err(VerifErr::StackUnderflow { decl: stmt, opcode, pos: 0, needed: 1, depth: 0, top: Vec::new() })
+                }
+                let mut state = MmbState::new_from(outline, bump, stmt);
+                let res = state.verify_assert(stmt, assert, proof);
+                *unify_time += state.unify_time.unwrap_or_default();
+                res?;
             }            
         }
-        Ok(outline.add_declar(stmt))
+        outline.add_declar(stmt)
     }    
 
  
+    /// Expression equality. With hash-consing, equal expressions are the same allocation.
+    pub fn same_expr(&self, x: &'b MmbItem<'b>, y: &'b MmbItem<'b>) -> bool {
+        core::ptr::eq(x, y) || (self.interner.is_none() && x == y)
+    }
+
     pub fn alloc<A>(&self, item: A) -> &'b A {
         &*self.bump.alloc(item)
     }
+
+    /// Enforce `options.max_expr_size` and `options.max_expr_depth` on an expression the
+    /// proof has just built, recording its size and depth in `expr_sizes`. Without either
+    /// limit there's no table, and this does nothing.
+    pub fn check_expr_limits(&mut self, e: &'b MmbItem<'b>) -> Res<()> {
+        let (size, depth) = match self.expr_sizes.as_mut() {
+            Some(sizes) => measure(sizes, e),
+            None => return Ok(()),
+        };
+        let options = &self.outline.options;
+        let too_big = options.max_expr_size.map(|max| size > max).unwrap_or(false);
+        let too_deep = options.max_expr_depth.map(|max| depth > max).unwrap_or(false);
+        if too_big || too_deep {
+            return Err(VerifErr::ExpressionTooLarge { decl: self.stmt, size, depth })
+        }
+        Ok(())
+    }
+
+    /// Count one proof or unify command against this declaration's budget.
+    /// The clock is only consulted every so often since reading it isn't free.
+    pub fn tick(&mut self) -> Res<()> {
+        self.steps += 1;
+        if let Some(max) = self.outline.options.max_memory {
+            // The arena was reset for this declaration, so everything but the unused end
+            // of the current chunk is this declaration's.
+            let bytes = self.bump.allocated_bytes().saturating_sub(self.bump.chunk_capacity());
+            if bytes > max {
+                return Err(VerifErr::OutOfMemory { decl: self.stmt, bytes })
+            }
+        }
+        let out_of_steps = self.outline.options.max_steps.map(|max| self.steps > max).unwrap_or(false);
+        let out_of_time = match (self.started, self.outline.options.timeout) {
+            (Some(started), Some(timeout)) if self.steps & 1023 == 0 || out_of_steps => started.elapsed() > timeout,
+            _ => false
+        };
+
+        if out_of_steps || out_of_time {
+            Err(VerifErr::Timeout {
+                decl: self.stmt,
+                steps: self.steps,
+                elapsed_ms: self.started.map(|s| s.elapsed().as_millis()),
+            })
+        } else {
+            Ok(())
+        }
+    }
 }
 
+/// Check the arguments of a term or assertion (and, for a term, its return type) as they
+/// go on the heap at the start of its proof, returning the next bound variable. This
+/// doesn't need an `MmbState`, so a term with no proof to run is checked by this alone.
+fn check_args(outline: &Outline, stmt: StmtCmd, args: Args) -> Res<u64> {
+    // Tables loaded up front have had these checked already, for every entry.
+    let checked = outline.types_checked();
+    let mut next_bv = 1u64;
+    for arg in args {
+        if arg.is_bound() {
+            // b/c we have a bound var, assert the arg's sort is not strict
+            if !checked {
+                make_sure!(outline.get_sort_mods(arg.sort() as usize)?.inner & SORT_STRICT == 0);
+            }
+            if next_bv >> MAX_BOUND_VARS != 0 {
+                return Err(VerifErr::TooManyBoundVars { decl: stmt, var: next_bv.trailing_zeros() + 1 })
+            }
+            // assert that the mmb file has the right/sequential bv idx for this bound var
+            if !checked {
+                make_sure!(arg.bound_digit()? == next_bv);
+            }
+            next_bv *= 2;
+        } else if !checked {
+            // assert that this doesn't have any dependencies with a bit pos/idx greater
+            // than the number of bvs that have been declared/seen.
+            make_sure!(0 == (arg.deps()? & !(next_bv - 1)));
+        }
+    }
+    Ok(next_bv)
+}
+
+/// How many items from the top of the stack a `StackUnderflow` error shows.
+pub const STACK_DUMP_LEN: usize = 8;
+
 impl<'b, 'a: 'b> MmbState<'b, 'a> {
-    pub fn take_next_bv(&mut self) -> u64 {
+    pub(crate) fn underflow(&self, needed: usize) -> VerifErr {
+        let opcode = match self.cmd {
+            Some(cmd) => self.outline.proof_text(cmd),
+            None => "end of proof".to_string(),
+        };
+        let shown = self.stack.len().saturating_sub(STACK_DUMP_LEN);
+        let top = self.stack[shown..].iter().map(|e| self.outline.item_str(e)).collect();
+        VerifErr::StackUnderflow { decl: self.stmt, opcode, pos: self.pos, needed, depth: self.stack.len(), top }
+    }
+
+    /// Take the top item off the stack.
+    pub(crate) fn pop(&mut self) -> Res<&'b MmbItem<'b>> {
+        match self.stack.pop() {
+            Some(e) => Ok(e),
+            None => Err(self.underflow(1)),
+        }
+    }
+
+    /// Where the top `n` items of the stack start.
+    pub(crate) fn stack_top(&self, n: usize) -> Res<usize> {
+        match self.stack.len().checked_sub(n) {
+            Some(i) => Ok(i),
+            None => Err(self.underflow(n)),
+        }
+    }
+
+    pub(crate) fn wrong_item(&self, expected: &'static str, found: &MmbItem) -> VerifErr {
+        VerifErr::WrongItem { decl: self.stmt, pos: self.pos, expected, found: found.kind_name() }
+    }
+
+    /// Check that the declaration being checked can refer to term `term_num`: a def only
+    /// to the terms before it, and an assertion to the terms declared before it. Something
+    /// checked outside the proof stream (with no number) can use every term.
+    pub(crate) fn check_term_ref(&self, term_num: u32) -> Res<()> {
+        let limit = match self.stmt {
+            StmtCmd::TermDef { num: Some(n), .. } => n,
+            StmtCmd::Axiom { num: Some(n) } | StmtCmd::Thm { num: Some(n), .. } =>
+                self.outline.terms_before.get(n as usize).copied().unwrap_or(0),
+            _ => self.outline.header.num_terms,
+        };
+        if term_num < limit {
+            return Ok(())
+        }
+        let referenced = self.outline.declarations.iter().map(|(s, _)| *s)
+            .find(|s| matches!(s, StmtCmd::TermDef { num: Some(n), .. } if *n == term_num))
+            .unwrap_or(StmtCmd::TermDef { num: Some(term_num), local: false });
+        Err(VerifErr::ForwardReference { decl: self.stmt, referenced })
+    }
+
+    /// Like `check_term_ref`, for assertion `thm_num`: a theorem can only use the
+    /// assertions before it.
+    pub(crate) fn check_thm_ref(&self, thm_num: u32) -> Res<()> {
+        let limit = match self.stmt {
+            StmtCmd::TermDef { num: Some(n), .. } => self.outline.thms_before.get(n as usize).copied().unwrap_or(0),
+            StmtCmd::Axiom { num: Some(n) } | StmtCmd::Thm { num: Some(n), .. } => n,
+            _ => self.outline.header.num_thms,
+        };
+        if thm_num < limit {
+            return Ok(())
+        }
+        let referenced = self.outline.declarations.iter().map(|(s, _)| *s)
+            .find(|s| matches!(s, StmtCmd::Axiom { num: Some(n) } | StmtCmd::Thm { num: Some(n), .. } if *n == thm_num))
+            .unwrap_or(StmtCmd::Thm { num: Some(thm_num), local: false });
+        Err(VerifErr::ForwardReference { decl: self.stmt, referenced })
+    }
+
+    pub(crate) fn expect_expr(&self, item: &'b MmbItem<'b>) -> Res<&'b MmbExpr<'b>> {
+        match item {
+            MmbItem::Expr(e) => Ok(e),
+            _ => Err(self.wrong_item("an expression", item)),
+        }
+    }
+
+    /// The statement `item` proves.
+    pub(crate) fn expect_proof(&self, item: &'b MmbItem<'b>) -> Res<&'b MmbItem<'b>> {
+        match item {
+            MmbItem::Proof(e) => Ok(e),
+            _ => Err(self.wrong_item("a proof", item)),
+        }
+    }
+
+    /// The two sides of conversion obligation `item`.
+    pub(crate) fn expect_coconv(&self, item: &'b MmbItem<'b>) -> Res<(&'b MmbItem<'b>, &'b MmbItem<'b>)> {
+        match item {
+            MmbItem::CoConv(e1, e2) => Ok((e1, e2)),
+            _ => Err(self.wrong_item("a conversion obligation", item)),
+        }
+    }
+
+    pub fn take_next_bv(&mut self) -> Res<u64> {
         let outgoing = self.next_bv;
-        // Assert we're under the limit of 55 bound variables.
-        assert!(outgoing >> 56 == 0);
+        if outgoing >> MAX_BOUND_VARS != 0 {
+            return Err(VerifErr::TooManyBoundVars { decl: self.stmt, var: outgoing.trailing_zeros() + 1 })
+        }
         self.next_bv *= 2;
-        outgoing
+        Ok(outgoing)
     }    
 
-    fn load_args(&mut self, args: Args<'a>, stmt: StmtCmd) -> Res<()> {
+    fn load_args(&mut self, args: Args, stmt: StmtCmd) -> Res<()> {
         make_sure!(self.heap.len() == 0);
         make_sure!(self.next_bv == 1);
-
+        self.next_bv = check_args(self.outline, stmt, args)?;
         for (idx, arg) in args.enumerate() {
-            if arg.is_bound() {
-                // b/c we have a bound var, assert the arg's sort is not strict
-                make_sure!(self.outline.get_sort_mods(arg.sort() as usize).unwrap().inner & SORT_STRICT == 0);
-                // increment the bv counter/checker
-                let this_bv = self.take_next_bv();
-                // assert that the mmb file has the right/sequential bv idx for this bound var
-                make_sure!(arg.bound_digit()? == this_bv);
-            } else {
-                // assert that this doesn't have any dependencies with a bit pos/idx greater
-                // than the number of bvs that have been declared/seen.
-                make_sure!(0 == (arg.deps().unwrap() & !(self.next_bv - 1)));
-            }
-
             self.heap.push(self.alloc(MmbItem::Expr(self.alloc(MmbExpr::Var { idx, ty: arg }))));
         }
         // For termdefs, pop the last item (which is the return) off the stack.
@@ -280,10 +568,10 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         self.load_args(term.args(), stmt)?;
         if term.is_def() {
             self.run_proof(crate::mmb::proof::Mode::Def, proof)?;
-            let final_val = none_err!(self.stack.pop())?;
+            let final_val = self.pop()?;
             let ty = final_val.get_ty()?;
             make_sure!(self.stack.is_empty());
-            make_sure!(sorts_compatible(ty, term.ret()));
+            make_sure!(ty.coercible_to_binder(term.ret()));
             make_sure!(self.uheap.is_empty());
             for arg in self.heap.iter().take(term.num_args_no_ret() as usize) {
                 self.uheap.push(*arg);
@@ -297,17 +585,21 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
     pub fn verify_assert(
         &mut self, 
         stmt: StmtCmd,
-        assert: Assert<'a>,
+        assert: Assert,
         proof: ProofIter,
     ) -> Res<()> {
         self.load_args(assert.args(), stmt)?;
         self.run_proof(crate::mmb::proof::Mode::Thm, proof)?;
 
-        let final_val = match none_err!(self.stack.pop())? {
+        let top = self.pop()?;
+        let final_val = match top {
             MmbItem::Proof(p) if matches!(stmt, StmtCmd::Thm {..}) => p,
             owise if matches!(stmt, StmtCmd::Axiom {..}) => owise,
-            owise => return Err(VerifErr::Msg(format!("Expected a proof; got {:?}", owise)))
+            owise => return Err(self.wrong_item("a proof", owise))
         };
+        if let Some(tree) = self.tree.as_mut() {
+            tree.root = Some(tree.get(top)?);
+        }
 
         make_sure!(self.stack.is_empty());
         make_sure!(self.uheap.is_empty());
@@ -319,3 +611,125 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
 }
 
 
+
+#[test]
+fn term_fast_path1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let mut bump = Bump::new();
+    let terms = outline.declarations.iter().filter(|(s, _)| matches!(s, StmtCmd::TermDef {..})).collect::<Vec<_>>();
+    assert_eq!(terms.len(), 2);
+    for (stmt, proof) in terms {
+        MmbState::verify1(&outline, &mut bump, *stmt, *proof).unwrap();
+    }
+    // Terms that aren't defs don't touch the arena.
+    assert_eq!(bump.allocated_bytes(), 0);
+}
+
+#[test]
+fn axiom_fast_path1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::stmt::STMT_AXIOM;
+    // An axiom whose proof is just the End command.
+    let mut fx = Fixture::valid();
+    fx.stmts[3] = (STMT_AXIOM, stream(&[]));
+    fx.with_outline(|outline| {
+        let (stmt, proof) = outline.declarations[3];
+        let assert = outline.get_assert_by_num(1).unwrap();
+        let mut bump = Bump::new();
+        let slow = MmbState::new_from(outline, &mut bump, stmt).verify_assert(stmt, assert, proof).unwrap_err();
+        let mut fresh = Bump::new();
+        let fast = MmbState::verify1(outline, &mut fresh, stmt, proof).unwrap_err();
+        assert_eq!(format!("{:?}", fast), format!("{:?}", slow));
+        assert_eq!(fresh.allocated_bytes(), 0);
+    });
+}
+
+#[test]
+fn max_steps1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    outline.options.max_steps = Some(3);
+    let mut bump = Bump::new();
+    let (stmt, proof) = *outline.declarations.iter().find(|(s, _)| matches!(s, StmtCmd::Axiom {..})).unwrap();
+    match MmbState::verify1(&outline, &mut bump, stmt, proof) {
+        Err(VerifErr::Timeout { decl, steps, .. }) => {
+            assert_eq!(decl, stmt);
+            assert_eq!(steps, 4);
+        }
+        owise => panic!("expected a timeout, got {:?}", owise),
+    }
+}
+
+#[test]
+fn max_memory1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    let (stmt, proof) = *outline.declarations.iter().find(|(s, _)| matches!(s, StmtCmd::Axiom {..})).unwrap();
+    // What an earlier declaration left in a reused arena doesn't count.
+    let mut bump = Bump::new();
+    bump.alloc([0u8; 1 << 16]);
+    outline.options.max_memory = Some(1 << 12);
+    assert!(MmbState::verify1(&outline, &mut bump, stmt, proof).is_ok());
+
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    outline.options.max_memory = Some(1);
+    match MmbState::verify1(&outline, &mut bump, stmt, proof) {
+        Err(VerifErr::OutOfMemory { decl, bytes }) => {
+            assert_eq!(decl, stmt);
+            assert!(bytes > 1);
+        }
+        owise => panic!("expected to run out of memory, got {:?}", owise),
+    }
+}
+
+#[test]
+fn expr_limits1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM_SAVE };
+    // `x0 = a`, and `x(k+1) = im xk xk`, which has 2^(k+2) - 1 nodes.
+    let mut fx = Fixture::valid();
+    let mut cmds = vec![(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM_SAVE, 0)];
+    for k in 2..70 {
+        cmds.extend_from_slice(&[(PROOF_REF, k), (PROOF_REF, k), (PROOF_TERM_SAVE, 0)]);
+    }
+    fx.stmts[3].1 = stream(&cmds);
+    let run = |size: Option<u64>, depth: Option<u32>| fx.with_outline(|outline| {
+        outline.options.max_expr_size = size;
+        outline.options.max_expr_depth = depth;
+        let (stmt, proof) = outline.declarations[3];
+        (stmt, MmbState::verify1(outline, &mut Bump::new(), stmt, proof))
+    });
+    match run(Some(1000), None) {
+        (stmt, Err(VerifErr::ExpressionTooLarge { decl, size, depth })) => {
+            assert_eq!(decl, stmt);
+            assert_eq!((size, depth), (1023, 10));
+        }
+        (_, owise) => panic!("expected a too large expression, got {:?}", owise),
+    }
+    assert!(matches!(run(None, Some(20)).1, Err(VerifErr::ExpressionTooLarge { depth: 21, .. })));
+    // Without limits nothing is measured, and the proof is just wrong.
+    fx.with_outline(|outline| {
+        assert!(MmbState::new_from(outline, &mut Bump::new(), outline.declarations[3].0).expr_sizes.is_none())
+    });
+    assert!(!matches!(run(None, None).1, Ok(()) | Err(VerifErr::ExpressionTooLarge { .. })));
+}
+
+#[test]
+fn bound_var_limit1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let mut bump = Bump::new();
+    let (stmt, _) = outline.declarations[3];
+    let mut st = MmbState::new_from(&outline, &mut bump, stmt);
+    for i in 0..MAX_BOUND_VARS {
+        assert_eq!(st.take_next_bv().unwrap(), 1 << i);
+    }
+    match st.take_next_bv() {
+        Err(VerifErr::TooManyBoundVars { decl, var }) => {
+            assert_eq!(decl, stmt);
+            assert_eq!(var, MAX_BOUND_VARS + 1);
+        }
+        owise => panic!("expected too many bound variables, got {:?}", owise),
+    }
+}
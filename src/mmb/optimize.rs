@@ -0,0 +1,83 @@
+//! Re-encoding an mmb file to make it smaller without changing what it proves: every
+//! proof command gets the shortest encoding of its data, saves that are never referenced
+//! (see `coverage`) are dropped or turned into plain `Term`s and `Thm`s, and heap
+//! references are renumbered to match. Sorts, terms, assertions, and their unify streams
+//! are written as they are, and the index is rebuilt with the same names.
+//!
+//! The result is only returned once it has been verified and found to declare the same
+//! statements as the original, so the original should have been verified first. If it
+//! wouldn't be any smaller (a rebuilt index can be a few bytes bigger than the one the
+//! mm1 compiler wrote), the original comes back unchanged.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::diff::Change;
+use crate::mmb::write::{ Renumbering, Source, write_mmb };
+use crate::util::{ Res, VerifErr };
+
+impl<'a> Outline<'a> {
+    /// A smaller copy of this file, with the same declarations in the same order.
+    pub fn optimized(&self) -> Res<Vec<u8>> {
+        let mut renum = Renumbering::new_for(self);
+        for (i, n) in renum.sorts.iter_mut().enumerate() {
+            *n = Some(i as u8);
+        }
+        for (i, n) in renum.terms.iter_mut().enumerate() {
+            *n = Some(i as u32);
+        }
+        for (i, n) in renum.thms.iter_mut().enumerate() {
+            *n = Some(i as u32);
+        }
+        let keep = vec![true; self.declarations.len()];
+        let bytes = write_mmb(&[Source { outline: self, keep, renum, compact: true }])?;
+        if bytes.len() >= self.mmb().len() {
+            return Ok(self.mmb().to_vec())
+        }
+
+        let file_data = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+        let new = Outline::new_from(&file_data).map_err(|e| VerifErr::Msg(format!("the optimized file doesn't parse: {:?}", e)))?;
+        if let Some(e) = crate::verify_serial(&new, &new.declarations).first() {
+            return Err(VerifErr::Msg(format!("the optimized file doesn't verify: {:?}", e)))
+        }
+        for change in crate::diff::diff(self, &new)? {
+            if let Change::ProofOnly { .. } = change {
+                continue
+            }
+            return Err(VerifErr::Msg(format!("the optimized file declares something else: {}", change)))
+        }
+        Ok(file_data.mmb_file)
+    }
+}
+
+#[test]
+fn optimized1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::proof::{ ProofCmd, PROOF_REF, PROOF_SAVE, PROOF_TERM, PROOF_TERM_SAVE, PROOF_THM };
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let optimized = outline.optimized().unwrap();
+    // Nothing to drop, and the new index would be a little bigger than the old one.
+    assert_eq!(optimized, file_data.mmb_file);
+
+    // `a1` saves its first argument for nothing, so the saved `t0 v0 v1` moves down from 3 to 2.
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[
+        (PROOF_REF, 0), (PROOF_SAVE, 0), (PROOF_REF, 1), (PROOF_TERM_SAVE, 0),
+        (PROOF_REF, 3), (PROOF_REF, 3), (PROOF_TERM, 0),
+        (PROOF_THM, 0),
+    ]);
+    fx.with_outline(|outline| {
+        assert!(crate::verify_serial(outline, &outline.declarations).is_empty());
+        let optimized = outline.optimized().unwrap();
+        assert_eq!(optimized.len(), outline.mmb().len() - 1);
+        let file_data = crate::fs::FileData::new_from_bytes(optimized, Vec::new());
+        let new = Outline::new_from(&file_data).unwrap();
+        let (_, proof) = new.declarations[3];
+        assert_eq!(proof.map(|cmd| cmd.unwrap()).collect::<Vec<_>>(), vec![
+            ProofCmd::Ref(0), ProofCmd::Ref(1), ProofCmd::Term { term_num: 0, save: true },
+            ProofCmd::Ref(2), ProofCmd::Ref(2), ProofCmd::Term { term_num: 0, save: false },
+            ProofCmd::Thm { thm_num: 0, save: false },
+        ]);
+        assert!(new.coverage().unwrap().iter().all(|c| c.is_tight()));
+    });
+}
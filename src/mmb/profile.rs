@@ -0,0 +1,183 @@
+//! Instruction-level profiling: how many of each proof and unify command were run, and how
+//! long each declaration took, for finding what dominates verification time. Each
+//! declaration is run again with a `ProofVisitor` counting its commands, so it's a separate
+//! pass after verification rather than something that slows down every run. The times
+//! include the visitor's overhead, which is the same for every command, so they're good
+//! for comparing declarations rather than as absolute numbers.
+
+use std::time::{ Duration, Instant };
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::MmbState;
+use crate::mmb::proof::ProofCmd;
+use crate::mmb::unify::UnifyCmd;
+use crate::mmb::visit::ProofVisitor;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::Res;
+
+/// The proof command mnemonics, as `disasm` writes them, in the order `proof_op` numbers them.
+pub const PROOF_OPS: [&str; 16] = [
+    "Term", "TermSave", "Ref", "Dummy", "Thm", "ThmSave", "Hyp", "Conv",
+    "Refl", "Sym", "Cong", "Unfold", "ConvCut", "ConvRef", "ConvSave", "Save",
+];
+
+/// The unify command mnemonics, in the order `unify_op` numbers them.
+pub const UNIFY_OPS: [&str; 5] = ["UTerm", "UTermSave", "URef", "UDummy", "UHyp"];
+
+/// An index into `PROOF_OPS`.
+pub fn proof_op(cmd: ProofCmd) -> usize {
+    match cmd {
+        ProofCmd::Term { save: false, .. } => 0,
+        ProofCmd::Term { save: true, .. } => 1,
+        ProofCmd::Ref(_) => 2,
+        ProofCmd::Dummy { .. } => 3,
+        ProofCmd::Thm { save: false, .. } => 4,
+        ProofCmd::Thm { save: true, .. } => 5,
+        ProofCmd::Hyp => 6,
+        ProofCmd::Conv => 7,
+        ProofCmd::Refl => 8,
+        ProofCmd::Sym => 9,
+        ProofCmd::Cong => 10,
+        ProofCmd::Unfold => 11,
+        ProofCmd::ConvCut => 12,
+        ProofCmd::ConvRef(_) => 13,
+        ProofCmd::ConvSave => 14,
+        ProofCmd::Save => 15,
+    }
+}
+
+/// An index into `UNIFY_OPS`.
+pub fn unify_op(cmd: UnifyCmd) -> usize {
+    match cmd {
+        UnifyCmd::Term { save: false, .. } => 0,
+        UnifyCmd::Term { save: true, .. } => 1,
+        UnifyCmd::Ref(_) => 2,
+        UnifyCmd::Dummy { .. } => 3,
+        UnifyCmd::Hyp => 4,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeclProfile {
+    pub decl: StmtCmd,
+    pub time: Duration,
+    pub proof_cmds: u64,
+    pub unify_cmds: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// How many times each proof command ran, indexed like `PROOF_OPS`.
+    pub proof_ops: [u64; 16],
+    /// How many times each unify command ran, indexed like `UNIFY_OPS`.
+    pub unify_ops: [u64; 5],
+    /// Every def, axiom and theorem with a proof, in file order.
+    pub decls: Vec<DeclProfile>,
+}
+
+#[derive(Default)]
+struct Counter {
+    proof_ops: [u64; 16],
+    unify_ops: [u64; 5],
+}
+
+impl ProofVisitor for Counter {
+    fn proof_cmd(&mut self, _: &MmbState, cmd: ProofCmd, _: &Res<()>) {
+        self.proof_ops[proof_op(cmd)] += 1;
+    }
+
+    fn unify_cmd(&mut self, _: &MmbState, cmd: UnifyCmd, _: &Res<()>) {
+        self.unify_ops[unify_op(cmd)] += 1;
+    }
+}
+
+impl Profile {
+    pub fn total_time(&self) -> Duration {
+        self.decls.iter().map(|d| d.time).sum()
+    }
+
+    /// The `n` declarations that took longest, longest first.
+    pub fn slowest(&self, n: usize) -> Vec<&DeclProfile> {
+        let mut decls = self.decls.iter().collect::<Vec<_>>();
+        decls.sort_by_key(|d| std::cmp::Reverse(d.time));
+        decls.truncate(n);
+        decls
+    }
+
+    /// A table of the commands run, most frequent first, and the `n` slowest declarations.
+    pub fn hot_spots(&self, outline: &Outline, n: usize) -> String {
+        let mut out = String::new();
+        for (title, names, counts) in [("proof", &PROOF_OPS[..], &self.proof_ops[..]), ("unify", &UNIFY_OPS[..], &self.unify_ops[..])] {
+            let total = counts.iter().sum::<u64>().max(1);
+            let mut rows = names.iter().zip(counts.iter()).filter(|(_, c)| **c != 0).collect::<Vec<_>>();
+            rows.sort_by(|a, b| b.1.cmp(a.1));
+            out.push_str(&format!("profile: {} commands\n", title));
+            for (name, count) in rows {
+                out.push_str(&format!("  {:<10} {:>12} {:>6.1}%\n", name, count, 100.0 * *count as f64 / total as f64));
+            }
+        }
+        let total = self.total_time().as_secs_f64().max(f64::MIN_POSITIVE);
+        out.push_str("profile: slowest declarations\n");
+        for d in self.slowest(n) {
+            let name = outline.stmt_name(d.decl).map(|s| s.to_string()).unwrap_or_else(|| format!("{:?}", d.decl));
+            out.push_str(&format!(
+                "  {:<30} {:>10.3}ms {:>6.1}%  {} proof, {} unify\n",
+                name,
+                d.time.as_secs_f64() * 1000.0,
+                100.0 * d.time.as_secs_f64() / total,
+                d.proof_cmds,
+                d.unify_cmds
+            ));
+        }
+        out
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Run every def, axiom and theorem proof again, counting commands and timing each one.
+    pub fn profile(&'a self) -> Res<Profile> {
+        let mut profile = Profile::default();
+        let mut bump = Bump::new();
+        for (stmt, proof) in self.declarations.iter() {
+            if proof.is_null() || self.heap_args(*stmt)?.is_none() {
+                continue
+            }
+            let mut counter = Counter::default();
+            let started = Instant::now();
+            self.visit(&mut bump, *stmt, *proof, &mut counter)?;
+            let time = started.elapsed();
+            bump.reset();
+            for (total, n) in profile.proof_ops.iter_mut().zip(counter.proof_ops.iter()) {
+                *total += n;
+            }
+            for (total, n) in profile.unify_ops.iter_mut().zip(counter.unify_ops.iter()) {
+                *total += n;
+            }
+            profile.decls.push(DeclProfile {
+                decl: *stmt,
+                time,
+                proof_cmds: counter.proof_ops.iter().sum(),
+                unify_cmds: counter.unify_ops.iter().sum(),
+            });
+        }
+        Ok(profile)
+    }
+}
+
+#[test]
+fn profile1() {
+    crate::testgen::Fixture::unfolding(3).with_outline(|outline| {
+        let profile = outline.profile().unwrap();
+        // The def `d`, the axiom, `th`, and `unfold`.
+        assert_eq!(profile.decls.len(), 4);
+        let op = |name| PROOF_OPS.iter().position(|n| *n == name).unwrap();
+        assert_eq!(profile.proof_ops[op("Unfold")], 3);
+        assert_eq!(profile.proof_ops[op("Refl")], 4);
+        assert_eq!(profile.proof_ops[op("ConvRef")], 1);
+        assert_eq!(profile.decls.iter().map(|d| d.proof_cmds).sum::<u64>(), profile.proof_ops.iter().sum::<u64>());
+        assert_eq!(profile.decls[3].proof_cmds, 14 + 11 * 3);
+        let table = profile.hot_spots(outline, 2);
+        assert!(table.contains("Unfold"));
+        assert_eq!(table.lines().filter(|l| l.ends_with("unify")).count(), 2);
+    });
+}
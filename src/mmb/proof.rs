@@ -1,10 +1,11 @@
+use crate::prelude::*;
 use bumpalo::collections::Vec as BumpVec;
 
-use crate::mmb::sorts_compatible;
 use crate::mmb::unify::UMode;
 use crate::util::{ 
     VerifErr,
     try_next_cmd,
+    parse_cmd,
     Res,
     Type,
 };
@@ -13,51 +14,26 @@ use crate::mmb::{
     MmbItem,
     MmbExpr
 };
+use crate::mmb::tree::ProofNode;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::write::push_cmd;
+use crate::util::cmd::DATA_MASK;
 
-pub const TYPE_BOUND_MASK: u64 = 1 << 63;
+pub use crate::mmb::format::TYPE_BOUND_MASK;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
     Def,
     Thm,
 }
-use crate::none_err;
 use crate::localize;
 use crate::make_sure;
 
 
-/// `PROOF_TERM = 0x10`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_TERM: u8 = 0x10;
-/// `PROOF_TERM_SAVE = 0x11`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_TERM_SAVE: u8 = 0x11;
-/// `PROOF_REF = 0x12`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_REF: u8 = 0x12;
-/// `PROOF_DUMMY = 0x13`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_DUMMY: u8 = 0x13;
-/// `PROOF_THM = 0x14`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_THM: u8 = 0x14;
-/// `PROOF_THM_SAVE = 0x15`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_THM_SAVE: u8 = 0x15;
-/// `PROOF_HYP = 0x16`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_HYP: u8 = 0x16;
-/// `PROOF_CONV = 0x17`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_CONV: u8 = 0x17;
-/// `PROOF_REFL = 0x18`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_REFL: u8 = 0x18;
-/// `PROOF_SYMM = 0x19`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_SYMM: u8 = 0x19;
-/// `PROOF_CONG = 0x1A`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_CONG: u8 = 0x1A;
-/// `PROOF_UNFOLD = 0x1B`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_UNFOLD: u8 = 0x1B;
-/// `PROOF_CONV_CUT = 0x1C`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_CONV_CUT: u8 = 0x1C;
-/// `PROOF_CONV_REF = 0x1D`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_CONV_REF: u8 = 0x1D;
-/// `PROOF_CONV_SAVE = 0x1E`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_CONV_SAVE: u8 = 0x1E;
-/// `PROOF_SAVE = 0x1F`: See [`ProofCmd`](super::ProofCmd).
-pub const PROOF_SAVE: u8 = 0x1F;
+pub use crate::mmb::format::{
+    PROOF_TERM, PROOF_TERM_SAVE, PROOF_REF, PROOF_DUMMY, PROOF_THM, PROOF_THM_SAVE, PROOF_HYP, PROOF_CONV,
+    PROOF_REFL, PROOF_SYMM, PROOF_CONG, PROOF_UNFOLD, PROOF_CONV_CUT, PROOF_CONV_REF, PROOF_CONV_SAVE, PROOF_SAVE,
+};
 
 
 
@@ -176,7 +152,7 @@ pub enum ProofCmd {
     Save,
 }
 
-impl std::convert::TryFrom<(u8, u32)> for ProofCmd {
+impl core::convert::TryFrom<(u8, u32)> for ProofCmd {
     type Error = VerifErr;
     fn try_from((cmd, data): (u8, u32)) -> Result<Self, Self::Error> {
         Ok(match cmd {
@@ -213,14 +189,82 @@ impl std::convert::TryFrom<(u8, u32)> for ProofCmd {
             PROOF_CONV_REF => ProofCmd::ConvRef(data),
             PROOF_CONV_SAVE => ProofCmd::ConvSave,
             PROOF_SAVE => ProofCmd::Save,
-            owise => {
-                println!("Failed to convert {:?}\n", owise);
-                return Err(VerifErr::Msg("try_from for ProofCmd failed match".to_string()));
-            }
+            owise => return Err(VerifErr::Msg(format!("try_from for ProofCmd failed match; got {:?}", owise))),
         })
     }
 }
 
+/// A command with no data field, which means its data is 0.
+impl core::convert::TryFrom<u8> for ProofCmd {
+    type Error = VerifErr;
+    fn try_from(cmd: u8) -> Result<Self, Self::Error> {
+        if cmd & DATA_MASK != 0 {
+            return Err(VerifErr::Msg(format!("proof command {:#04x} has a data field", cmd)))
+        }
+        ProofCmd::try_from((cmd, 0))
+    }
+}
+
+impl ProofCmd {
+    /// The command byte, without the data size bits.
+    pub fn opcode(self) -> u8 {
+        match self {
+            ProofCmd::Term { save: false, .. } => PROOF_TERM,
+            ProofCmd::Term { save: true, .. } => PROOF_TERM_SAVE,
+            ProofCmd::Ref(_) => PROOF_REF,
+            ProofCmd::Dummy { .. } => PROOF_DUMMY,
+            ProofCmd::Thm { save: false, .. } => PROOF_THM,
+            ProofCmd::Thm { save: true, .. } => PROOF_THM_SAVE,
+            ProofCmd::Hyp => PROOF_HYP,
+            ProofCmd::Conv => PROOF_CONV,
+            ProofCmd::Refl => PROOF_REFL,
+            ProofCmd::Sym => PROOF_SYMM,
+            ProofCmd::Cong => PROOF_CONG,
+            ProofCmd::Unfold => PROOF_UNFOLD,
+            ProofCmd::ConvCut => PROOF_CONV_CUT,
+            ProofCmd::ConvRef(_) => PROOF_CONV_REF,
+            ProofCmd::ConvSave => PROOF_CONV_SAVE,
+            ProofCmd::Save => PROOF_SAVE,
+        }
+    }
+
+    /// False for the commands without a data field.
+    pub fn takes_data(self) -> bool {
+        matches!(self, ProofCmd::Term { .. } | ProofCmd::Thm { .. } | ProofCmd::Dummy { .. } | ProofCmd::Ref(_) | ProofCmd::ConvRef(_))
+    }
+
+    /// The data field: a term, theorem, or sort number, a heap index, or 0.
+    pub fn data(self) -> u32 {
+        match self {
+            ProofCmd::Term { term_num, .. } => term_num,
+            ProofCmd::Thm { thm_num, .. } => thm_num,
+            ProofCmd::Dummy { sort_num } => sort_num as u32,
+            ProofCmd::Ref(i) | ProofCmd::ConvRef(i) => i,
+            _ => 0,
+        }
+    }
+
+    /// Append the command, with the smallest data field that fits.
+    pub fn encode(self, out: &mut Vec<u8>) {
+        push_cmd(out, self.opcode(), self.data())
+    }
+
+    /// The command at `pos` in `bytes` and the position of the next one, or `None` if
+    /// it's the `End` of the stream.
+    pub fn decode(bytes: &[u8], pos: usize) -> Res<Option<(ProofCmd, usize)>> {
+        try_next_cmd(bytes, pos)
+    }
+}
+
+/// What a stream's `prescan` found, without running it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Shape {
+    /// The number of commands, not counting the `End`.
+    pub cmds: usize,
+    /// The number of `Hyp` (or `UHyp`) commands.
+    pub hyps: usize,
+}
+
 /// An iterator over a proof command stream.
 #[derive(Debug, Clone, Copy)]
 pub struct ProofIter<'a> {
@@ -234,9 +278,59 @@ pub struct ProofIter<'a> {
     /// Mark `ends_at` instead of giving ProofIter a truncated slice just so
     /// the behvaior wrt `try_next_cmd` is identical.
     pub ends_at: usize,
+    /// Set once the stream has ended or failed to decode, after which there's nothing more.
+    done: bool,
 }
 
 impl<'a> ProofIter<'a> {
+    /// The proof stream in `buf` from `pos` up to (not including) `ends_at`, whose last
+    /// byte should be the `End` command.
+    pub fn new(buf: &'a [u8], pos: usize, ends_at: usize) -> Self {
+        ProofIter { buf, pos, ends_at, done: false }
+    }
+
+    /// The commands with the offset each one starts at.
+    pub fn spanned(self) -> Spanned<Self> {
+        Spanned(self)
+    }
+
+    /// Check that the stream is well formed without running it: every command is a proof
+    /// command with a data field it can use, the last byte is the `End` command, and no
+    /// command runs into it. Errors found here are about the encoding; anything the
+    /// interpreter finds after this passes is about the proof.
+    pub fn prescan(&self, decl: StmtCmd) -> Res<Shape> {
+        let mut shape = Shape::default();
+        if self.is_null() {
+            return Ok(shape)
+        }
+        let malformed = |pos, reason| VerifErr::MalformedProof { decl, pos, reason };
+        let stream = match self.buf.get(..self.ends_at) {
+            Some(stream) => stream,
+            None => return Err(malformed(self.pos, "the stream runs past the end of the file")),
+        };
+        let mut pos = self.pos;
+        while pos < stream.len() {
+            let (kind, data, next) = parse_cmd(stream, pos)
+                .map_err(|_| malformed(pos, "the command's data runs past the end of the stream"))?;
+            let has_data = stream[pos] & DATA_MASK != 0;
+            if kind == 0 {
+                return match (has_data, next == stream.len()) {
+                    (true, _) => Err(malformed(pos, "the End command has a data field")),
+                    (false, false) => Err(malformed(pos, "the End command comes before the end of the stream")),
+                    (false, true) => Ok(shape),
+                }
+            }
+            let cmd = <ProofCmd as core::convert::TryFrom<_>>::try_from((kind, data)).map_err(|_| malformed(pos, "not a proof command, or its data is out of range"))?;
+            if has_data && !cmd.takes_data() {
+                return Err(malformed(pos, "a data field on a command that takes none"))
+            }
+            pos = next;
+            shape.cmds += 1;
+            shape.hyps += (cmd == ProofCmd::Hyp) as usize;
+        }
+        Err(malformed(pos, "the stream doesn't end with an End command"))
+    }
+
     /// True if this iterator is "null", meaning that it has zero commands.
     /// This is not the same as being empty, which happens when there is one command
     /// which is the terminating `CMD_END` command.
@@ -249,7 +343,10 @@ impl<'a> ProofIter<'a> {
 impl<'a> Iterator for ProofIter<'a> {
     type Item = Result<ProofCmd, VerifErr>;
     fn next(&mut self) -> Option<Self::Item> {
-        match try_next_cmd(self.buf, self.pos) {
+        if self.done || self.is_null() {
+            return None
+        }
+        let res = match try_next_cmd(self.buf, self.pos) {
             // An actual error.
             Err(e) => Some(Err(e)),
             // `try_next_cmd` got `Ok(None)` by receiving a 0 command at the correct position
@@ -259,51 +356,95 @@ impl<'a> Iterator for ProofIter<'a> {
             // `try_next_cmd` parsed a new command.
             Ok(Some((stmt, rest))) => {
                 self.pos = rest;
-                Some(Ok(stmt))
+                return Some(Ok(stmt))
             }
+        };
+        self.done = true;
+        res
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done || self.is_null() {
+            (0, Some(0))
+        } else {
+            // Every command is at least a byte, and the last byte is the `End`; but a
+            // stream that doesn't decode ends with an error instead.
+            (0, Some(self.ends_at.saturating_sub(self.pos).max(1)))
         }
     }
 }
 
+impl<'a> core::iter::FusedIterator for ProofIter<'a> {}
+
+/// A proof or unify stream's commands, each with the offset it starts at: see
+/// `ProofIter::spanned` and `UnifyIter::spanned`.
+#[derive(Debug, Clone, Copy)]
+pub struct Spanned<I>(pub I);
+
+impl<'a> Iterator for Spanned<ProofIter<'a>> {
+    type Item = Result<(usize, ProofCmd), VerifErr>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.0.pos;
+        self.0.next().map(|res| res.map(|cmd| (pos, cmd)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> core::iter::FusedIterator for Spanned<ProofIter<'a>> {}
+
 impl<'b, 'a: 'b> MmbState<'b, 'a> {
     pub fn run_proof(
         &mut self, 
         mode: Mode,
         proof: ProofIter
     ) -> Res<()> {    
-        for maybe_cmd in proof {
-            match maybe_cmd? {
-                ProofCmd::Ref(i) => self.proof_ref(i)?,
-                ProofCmd::Dummy { sort_num } => self.proof_dummy(sort_num)?,
-                ProofCmd::Term { term_num, save } => self.proof_term(mode, term_num, save)?,
-                ProofCmd::Thm { thm_num, save } => self.proof_thm(thm_num, save)?,
-                ProofCmd::Hyp => self.proof_hyp(mode)?,
-                ProofCmd::Conv => self.proof_conv()?,
-                ProofCmd::Refl=> self.proof_refl()?,
-                ProofCmd::Sym => self.proof_sym()?,
-                ProofCmd::Cong => self.proof_cong()?,
-                ProofCmd::Unfold => self.proof_unfold()?,
-                ProofCmd::ConvCut => self.proof_conv_cut()?,
-                ProofCmd::ConvRef(i) => self.proof_conv_ref(i)?,
-                ProofCmd::ConvSave => self.proof_conv_save()?,
-                ProofCmd::Save => self.proof_save()?,
-            }
+        for maybe_cmd in proof.spanned() {
+            self.tick()?;
+            let (pos, cmd) = maybe_cmd?;
+            self.pos = pos;
+            self.cmd = Some(cmd);
+            let res = match cmd {
+                ProofCmd::Ref(i) => self.proof_ref(i),
+                ProofCmd::Dummy { sort_num } => self.proof_dummy(sort_num),
+                ProofCmd::Term { term_num, save } => self.proof_term(mode, term_num, save),
+                ProofCmd::Thm { thm_num, save } => self.proof_thm(thm_num, save),
+                ProofCmd::Hyp => self.proof_hyp(mode),
+                ProofCmd::Conv => self.proof_conv(),
+                ProofCmd::Refl=> self.proof_refl(),
+                ProofCmd::Sym => self.proof_sym(),
+                ProofCmd::Cong => self.proof_cong(),
+                ProofCmd::Unfold => self.proof_unfold(),
+                ProofCmd::ConvCut => self.proof_conv_cut(),
+                ProofCmd::ConvRef(i) => self.proof_conv_ref(i),
+                ProofCmd::ConvSave => self.proof_conv_save(),
+                ProofCmd::Save => self.proof_save(),
+            };
+            self.visit_proof(cmd, &res);
+            res?;
         }
+        self.cmd = None;
         Ok(())
     }    
 
+    fn heap_get(&self, i: u32) -> Res<&'b MmbItem<'b>> {
+        match self.heap.get(i as usize) {
+            Some(item) => Ok(item),
+            None => Err(VerifErr::BadBackref { decl: self.stmt, index: i, heap_len: self.heap.len() }),
+        }
+    }
+
     fn proof_ref(&mut self, i: u32) -> Res<()> {
-        let heap_elem = *&self.heap[i as usize];
+        let heap_elem = self.heap_get(i)?;
         Ok(self.stack.push(heap_elem))
     }
 
     fn proof_dummy(&mut self, sort_num: u8) -> Res<()> {
         make_sure!(sort_num < self.outline.header.num_sorts);
         make_sure!(self.outline.get_sort_mods(sort_num as usize).unwrap().inner & crate::mmb::SORT_STRICT == 0);
-        // Owise too many bound variables.
-        make_sure!(self.next_bv >> 56 == 0);
-
-        let ty = Type { inner: TYPE_BOUND_MASK | ((sort_num as u64) << 56) | self.take_next_bv() };
+        let ty = Type { inner: TYPE_BOUND_MASK | ((sort_num as u64) << 56) | self.take_next_bv()? };
 
         let e = self.alloc(MmbItem::Expr(self.alloc(MmbExpr::Var { idx: self.heap.len(), ty })));
         self.stack.push(e);
@@ -318,13 +459,17 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         save: bool
     ) -> Res<()> {
         make_sure!(term_num < self.outline.header.num_terms);
+        self.check_term_ref(term_num)?;
         let termref = self.outline.get_term_by_num(term_num)?;
         
         // remove ebar from the stack; either variables or applications.
         // We don't actually drain the elements from the stack until the end
         // in order to avoid an allocation.
-        let drain_from = self.stack.len() - (termref.num_args_no_ret() as usize);
+        let drain_from = self.stack_top(termref.num_args_no_ret() as usize)?;
         let stack_args = &self.stack[drain_from..];
+        for arg in stack_args.iter() {
+            self.expect_expr(arg)?;
+        }
 
         // (sig_args, stack_args)
         let all_args = || { termref.args_no_ret().zip(stack_args.iter()) };
@@ -341,7 +486,7 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
 
         // For all of the args, make sure the stack and sig items have compatible sorts.
         for (sig_arg, stack_arg) in all_args() {
-            make_sure!(sorts_compatible(stack_arg.get_ty()?, sig_arg)) 
+            make_sure!(stack_arg.get_ty()?.coercible_to_binder(sig_arg))
         }
 
         // Start building the new return type now that we know we have the right sort.
@@ -370,8 +515,18 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             }
         }        
 
+        let stack = &self.stack;
+        if let Some(t) = self.interner.as_mut().and_then(|i| i.get(term_num, new_type_accum, &stack[drain_from..])) {
+            self.stack.truncate(drain_from);
+            if save {
+                self.heap.push(t);
+            }
+            self.stack.push(t);
+            return Ok(())
+        }
+
         // I think this will get around it.
-        let drain = self.stack.drain((self.stack.len() - (termref.num_args_no_ret() as usize))..);
+        let drain = self.stack.drain(drain_from..);
         let mut stack_args_out = BumpVec::new_in(self.bump);
         for elem in drain {
             stack_args_out.push(elem);
@@ -382,6 +537,10 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             ty: new_type_accum,
             args: self.alloc(stack_args_out),
         })));
+        self.check_expr_limits(t)?;
+        if let Some(interner) = self.interner.as_mut() {
+            interner.insert(t)?;
+        }
 
         if save {
             self.heap.push(t);
@@ -395,14 +554,21 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         save: bool
     ) -> Res<()> {
         make_sure!(thm_num < self.outline.header.num_thms);
+        self.check_thm_ref(thm_num)?;
         let thmref = self.outline.get_assert_by_num(thm_num)?;
         let sig_args = thmref.args();
 
-        let a = none_err!(self.stack.pop())?;
+        // The arguments, then the statement.
+        self.stack_top(sig_args.len() + 1)?;
+        let a = self.pop()?;
+        self.expect_expr(a)?;
 
         // Wait to remove these in order to save an allocation.
-        let drain_from = self.stack.len() - sig_args.len();
+        let drain_from = self.stack_top(sig_args.len())?;
         let stack_args = &self.stack[drain_from..];
+        for arg in stack_args.iter() {
+            self.expect_expr(arg)?;
+        }
 
         let bound_by_sig = sig_args.zip(stack_args).enumerate().filter(|(_, (sig, _))| sig.is_bound());
 
@@ -427,11 +593,33 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             }
         }
 
+        // Keep the arguments for the proof tree before they're gone.
+        let tree_args = match self.tree.as_mut() {
+            None => None,
+            Some(tree) => {
+                tree.consumed.clear();
+                Some(&*self.bump.alloc_slice_copy(&self.stack[drain_from..]))
+            }
+        };
         // Now we actually remove the stack_args from the stack
         self.stack.truncate(drain_from);
-        self.run_unify(UMode::UThm, thmref.unify(), a)?;
+        // The unify stream goes through the conclusion and then the hypotheses, so a
+        // failure after a `UHyp` is that hypothesis's fault.
+        let mut hyps_seen = 0;
+        if let Err(err) = self.run_unify_counted(UMode::UThm, thmref.unify(), a, &mut hyps_seen) {
+            return Err(if hyps_seen == 0 { err } else { self.outline.hyp_err(thm_num, hyps_seen, err) })
+        }
 
         let proof = self.alloc(MmbItem::Proof(a));
+        if let (Some(args), Some(tree)) = (tree_args, self.tree.as_ref()) {
+            // The unifier took the hypotheses last to first.
+            let mut hyps = BumpVec::new_in(self.bump);
+            for h in tree.consumed.iter().rev() {
+                hyps.push(tree.get(h)?);
+            }
+            let node = ProofNode::Thm { thm_num, args, hyps: hyps.into_bump_slice(), concl: a };
+            self.record_node(proof, node);
+        }
         if save {
             self.heap.push(proof);
         }
@@ -444,76 +632,79 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         mode: Mode,
     ) -> Res<()> {
         make_sure!(mode != Mode::Def);
-        let e = none_err!(self.stack.pop())?;
-        //assert that e is in a provable sort since it's a hyp
-        let e_sort_numx = e.get_ty()?.sort();
-        let e_sort_mods = self.outline.get_sort_mods(e_sort_numx as usize).unwrap().inner;
-        make_sure!(e_sort_mods & crate::mmb::SORT_PROVABLE != 0);
+        let e = self.pop()?;
+        self.expect_expr(e)?;
+        // Only something in a provable sort can be assumed.
+        let sort = e.get_ty()?.sort();
+        let mods = self.outline.get_sort_mods(sort as usize)?;
+        if !mods.is_provable() {
+            return Err(VerifErr::HypNotProvable { decl: self.stmt, pos: self.pos, sort, mods: mods.inner })
+        }
         self.hstack.push(e);
         let proof = self.alloc(MmbItem::Proof(e));
+        if self.tree.is_some() {
+            self.record_node(proof, ProofNode::Hyp { idx: self.hstack.len() - 1, stmt: e });
+        }
         Ok(self.heap.push(proof))
     }      
 
 
     fn proof_conv(&mut self) -> Res<()> {
-        let e2proof = none_err!(self.stack.pop())?;
-        let e1 = none_err!(self.stack.pop())?;
-        match e2proof {
-            MmbItem::Proof(conc) => {
-                let e1proof = self.alloc(MmbItem::Proof(e1));
-                self.stack.push(e1proof);
-                let coconv_e1_e2 = self.alloc(MmbItem::CoConv(e1, conc));
-                Ok(self.stack.push(coconv_e1_e2))
-            },
-            _ => return Err(VerifErr::Unreachable(file!(), line!()))
-        }        
+        self.stack_top(2)?;
+        let e2proof = self.pop()?;
+        let e1 = self.pop()?;
+        let conc = self.expect_proof(e2proof)?;
+        self.expect_expr(e1)?;
+        let e1proof = self.alloc(MmbItem::Proof(e1));
+        if let Some(tree) = self.tree.as_ref() {
+            let node = ProofNode::Conv { stmt: e1, proof: tree.get(e2proof)? };
+            self.record_node(e1proof, node);
+        }
+        self.stack.push(e1proof);
+        let coconv_e1_e2 = self.alloc(MmbItem::CoConv(e1, conc));
+        self.stack.push(coconv_e1_e2);
+        Ok(())
     }      
 
     fn proof_refl(&mut self) -> Res<()> {
-        let e = none_err!(self.stack.pop())?;
-        if let MmbItem::CoConv(cc1, cc2) = e {
-            Ok(make_sure!((*cc1) as *const _ == (*cc2) as *const _))
-        } else {
-            return Err(VerifErr::Unreachable(file!(), line!()));
-        }
+        let e = self.pop()?;
+        let (cc1, cc2) = self.expect_coconv(e)?;
+        make_sure!(core::ptr::eq(cc1, cc2));
+        Ok(())
     }      
 
     fn proof_sym(&mut self) -> Res<()> {
-        let e = none_err!(self.stack.pop())?;
-        if let MmbItem::CoConv(cc1, cc2) = e {
-            let swapped = self.alloc(MmbItem::CoConv(cc2, cc1));
-            Ok(self.stack.push(swapped))
-        } else {
-            return Err(VerifErr::Unreachable(file!(), line!()));
-        }
+        let e = self.pop()?;
+        let (cc1, cc2) = self.expect_coconv(e)?;
+        let swapped = self.alloc(MmbItem::CoConv(cc2, cc1));
+        self.stack.push(swapped);
+        Ok(())
     }      
 
     fn proof_cong(&mut self) -> Res<()> {
-        let e = none_err!(self.stack.pop())?;
-        if let MmbItem::CoConv(cc1, cc2) = e  {
-            match (cc1, cc2) {
-                (MmbItem::Expr(MmbExpr::App { term_num: n1, args: as1, .. }), MmbItem::Expr(MmbExpr::App { term_num: n2, args: as2, .. })) => {
-                    make_sure!(n1 == n2);
-                    make_sure!(as1.len() == as2.len());
-                    for (lhs, rhs) in as1.iter().zip(as2.iter()).rev() {
-                        let cc = self.alloc(MmbItem::CoConv(lhs, rhs));
-                        self.stack.push(cc);
-                    }
-                    Ok(())
-                },
-                _ => return Err(VerifErr::Unreachable(file!(), line!()))
-            }
-        } else {
-            return Err(VerifErr::Unreachable(file!(), line!()));
+        let e = self.pop()?;
+        match self.expect_coconv(e)? {
+            (MmbItem::Expr(MmbExpr::App { term_num: n1, args: as1, .. }), MmbItem::Expr(MmbExpr::App { term_num: n2, args: as2, .. })) => {
+                make_sure!(n1 == n2);
+                make_sure!(as1.len() == as2.len());
+                for (lhs, rhs) in as1.iter().zip(as2.iter()).rev() {
+                    let cc = self.alloc(MmbItem::CoConv(lhs, rhs));
+                    self.stack.push(cc);
+                }
+                Ok(())
+            },
+            _ => Err(VerifErr::Msg("Cong needs a term application on both sides".to_string()))
         }
     }      
 
     fn proof_unfold(&mut self) -> Res<()> {
-        let e_prime = none_err!(self.stack.pop())?;
-        let f_ebar = none_err!(self.stack.pop())?;
-        let (term_num, ebar) = match f_ebar {
-            MmbItem::Expr(MmbExpr::App{ term_num, args, .. }) => (term_num, args.clone()),
-            _ => return Err(VerifErr::Unreachable(file!(), line!()))
+        self.stack_top(2)?;
+        let e_prime = self.pop()?;
+        let f_ebar = self.pop()?;
+        self.expect_expr(e_prime)?;
+        let (term_num, ebar) = match self.expect_expr(f_ebar)? {
+            MmbExpr::App{ term_num, args, .. } => (term_num, *args),
+            _ => return Err(VerifErr::Msg("Unfold needs a def application".to_string()))
         };
 
         make_sure!(self.uheap.is_empty());
@@ -525,51 +716,124 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             e_prime,
         )?;
 
-        let cc = none_err!(self.stack.pop())?;
-        if let MmbItem::CoConv(f_ebar2, e_doubleprime) = cc {
-                make_sure!(f_ebar == *f_ebar2);
-                let coconv = self.alloc(MmbItem::CoConv(e_prime, e_doubleprime));
-                Ok(self.stack.push(coconv))
-        } else {
-            return Err(VerifErr::Unreachable(file!(), line!()));
-        }
+        let cc = self.pop()?;
+        let (f_ebar2, e_doubleprime) = self.expect_coconv(cc)?;
+        make_sure!(self.same_expr(f_ebar, f_ebar2));
+        let coconv = self.alloc(MmbItem::CoConv(e_prime, e_doubleprime));
+        self.stack.push(coconv);
+        Ok(())
     }      
 
     fn proof_conv_cut(&mut self) -> Res<()> {
-        let p = none_err!(self.stack.pop())?;
-        if let MmbItem::CoConv(cc1, cc2) = p {
-            let p1 = self.alloc(MmbItem::Conv(cc1, cc2));
-            self.stack.push(p1);
-            Ok(self.stack.push(p))
-        } else {
-            return Err(VerifErr::Unreachable(file!(), line!()));
-        }
+        let p = self.pop()?;
+        let (cc1, cc2) = self.expect_coconv(p)?;
+        let p1 = self.alloc(MmbItem::Conv(cc1, cc2));
+        self.stack.push(p1);
+        self.stack.push(p);
+        Ok(())
     }      
 
     fn proof_conv_ref(&mut self, i: u32) -> Res<()> {
-        let heap_conv = none_err!(self.heap.get(i as usize).copied())?;
-        let stack_coconv = none_err!(self.stack.pop())?;
-        if let (MmbItem::Conv(c1, c2), MmbItem::CoConv(cc1, cc2)) = (heap_conv, stack_coconv) {
-            make_sure!(c1 == cc1);
-            Ok(make_sure!(c2 == cc2))
+        let heap_conv = self.heap_get(i)?;
+        let stack_coconv = self.pop()?;
+        let (cc1, cc2) = self.expect_coconv(stack_coconv)?;
+        if let MmbItem::Conv(c1, c2) = heap_conv {
+            make_sure!(c1 == &cc1);
+            make_sure!(c2 == &cc2);
+            Ok(())
         } else {
-            return Err(VerifErr::Unreachable(file!(), line!()));
+            Err(self.wrong_item("a conversion proof", heap_conv))
         }
     }    
 
     fn proof_conv_save(&mut self) -> Res<()> {
-        let p = localize!(none_err!(self.stack.pop()))?;
-        make_sure!(matches!(p, MmbItem::Conv {..}));
+        let p = localize!(self.pop())?;
+        if !matches!(p, MmbItem::Conv {..}) {
+            return Err(self.wrong_item("a conversion proof", p))
+        }
         Ok(self.heap.push(p))
     }    
 
     fn proof_save(&mut self) -> Res<()> {
-        let last = none_err!(self.stack.last().copied())?;
+        let last = self.stack[self.stack_top(1)?];
         match last {
-            MmbItem::CoConv {..} => Err(VerifErr::Msg(format!("Can't save co-conv"))),
+            MmbItem::CoConv {..} => Err(self.wrong_item("an expression, proof, or conversion proof", last)),
             _ => Ok(self.heap.push(last))
         }        
     }    
 }
 
 
+
+#[test]
+fn opcodes1() {
+    use core::convert::TryFrom;
+    use crate::mmb::unify::UnifyCmd;
+    let cmds = [
+        ProofCmd::Term { term_num: 3, save: true }, ProofCmd::Ref(300), ProofCmd::Dummy { sort_num: 1 },
+        ProofCmd::Thm { thm_num: 70000, save: false }, ProofCmd::Hyp, ProofCmd::Conv, ProofCmd::Refl,
+        ProofCmd::Sym, ProofCmd::Cong, ProofCmd::Unfold, ProofCmd::ConvCut, ProofCmd::ConvRef(0),
+        ProofCmd::ConvSave, ProofCmd::Save,
+    ];
+    let mut bytes = Vec::new();
+    for cmd in cmds.iter() {
+        cmd.encode(&mut bytes);
+    }
+    bytes.push(0);
+    let mut pos = 0;
+    let mut decoded = Vec::new();
+    while let Some((cmd, next)) = ProofCmd::decode(&bytes, pos).unwrap() {
+        decoded.push(cmd);
+        pos = next;
+    }
+    assert_eq!(decoded, cmds);
+    assert_eq!(ProofCmd::try_from(PROOF_REFL).unwrap(), ProofCmd::Refl);
+    assert_eq!(ProofCmd::try_from(PROOF_REF).unwrap(), ProofCmd::Ref(0));
+    assert!(ProofCmd::try_from(PROOF_REF | 0x40).is_err());
+    assert!(ProofCmd::try_from(0x30).is_err());
+
+    let cmd = UnifyCmd::Term { term_num: 5, save: true };
+    let mut bytes = Vec::new();
+    cmd.encode(&mut bytes);
+    assert_eq!(UnifyCmd::decode(&bytes, 0).unwrap().map(|(c, _)| c), Some(cmd));
+    assert_eq!(UnifyCmd::try_from(0x36).unwrap(), UnifyCmd::Hyp);
+}
+
+#[test]
+fn spanned1() {
+    let bytes = [PROOF_REFL, PROOF_REF | 0x40, 7, PROOF_SAVE, 0];
+    let cmds = ProofIter::new(&bytes, 0, bytes.len()).spanned().collect::<Res<Vec<_>>>().unwrap();
+    assert_eq!(cmds, [(0, ProofCmd::Refl), (1, ProofCmd::Ref(7)), (3, ProofCmd::Save)]);
+    assert!(ProofIter::new(&bytes, 0, bytes.len()).size_hint().1.unwrap() >= 3);
+    assert_eq!(ProofIter::new(&bytes, 0, 0).count(), 0);
+    // A command that doesn't decode ends the stream.
+    let bytes = [PROOF_REFL, 0x05, PROOF_SAVE, 0];
+    let mut it = ProofIter::new(&bytes, 0, bytes.len());
+    assert!(matches!(it.next(), Some(Ok(ProofCmd::Refl))));
+    assert!(matches!(it.next(), Some(Err(_))));
+    assert!(it.next().is_none());
+    assert!(it.next().is_none());
+    assert_eq!(it.size_hint(), (0, Some(0)));
+}
+
+#[test]
+fn prescan1() {
+    let decl = StmtCmd::Axiom { num: Some(0) };
+    let reason = |bytes: &[u8], ends_at: usize| match ProofIter::new(bytes, 0, ends_at).prescan(decl) {
+        Err(VerifErr::MalformedProof { pos, reason, .. }) => Some((pos, reason)),
+        Err(e) => panic!("{:?}", e),
+        Ok(_) => None,
+    };
+    let bytes = [PROOF_REFL, PROOF_REF | 0x40, 7, PROOF_SAVE, 0];
+    assert_eq!(ProofIter::new(&bytes, 0, bytes.len()).prescan(decl).unwrap(), Shape { cmds: 3, hyps: 0 });
+    assert_eq!(ProofIter::new(&bytes, 0, 0).prescan(decl).unwrap(), Shape::default());
+    assert_eq!(reason(&bytes, bytes.len() + 1).unwrap().0, 0);
+    // The data of the `Ref` crosses the declared end.
+    assert_eq!(reason(&bytes, 2).unwrap().0, 1);
+    assert_eq!(reason(&bytes, 4).unwrap(), (4, "the stream doesn't end with an End command"));
+    assert_eq!(reason(&[PROOF_REFL, 0x05, 0], 3).unwrap().0, 1);
+    assert_eq!(reason(&[PROOF_HYP | 0x40, 0, 0], 3).unwrap().0, 0);
+    assert_eq!(reason(&[0, PROOF_REFL, 0], 3).unwrap(), (0, "the End command comes before the end of the stream"));
+    assert_eq!(reason(&[PROOF_REFL, 0x40, 0], 3).unwrap(), (1, "the End command has a data field"));
+    assert_eq!(reason(&[PROOF_DUMMY | 0x80, 0, 1, 0], 4).unwrap().0, 0);
+}
@@ -0,0 +1,269 @@
+//! Searching the declarations of an mmb file, for using the crate as an inspection
+//! library rather than as a verifier. Queries are built up from filters which all have
+//! to match, for example:
+//!```text
+//! outline.query().uses_term("wceq").kind(DeclKind::Thm).run()
+//!```
+//! Names come from the index, so a file without one only matches queries that
+//! don't mention any names.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::unify::{ UnifyIter, UnifyCmd };
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeclKind {
+    Sort,
+    Term,
+    Def,
+    Axiom,
+    Thm,
+}
+
+impl<'a> Outline<'a> {
+    pub fn decl_kind(&self, stmt: StmtCmd) -> Res<DeclKind> {
+        Ok(match stmt {
+            StmtCmd::Sort { .. } => DeclKind::Sort,
+            StmtCmd::TermDef { num, .. } => {
+                if self.get_term_by_num(none_err!(num)?)?.is_def() {
+                    DeclKind::Def
+                } else {
+                    DeclKind::Term
+                }
+            }
+            StmtCmd::Axiom { .. } => DeclKind::Axiom,
+            StmtCmd::Thm { .. } => DeclKind::Thm,
+        })
+    }
+
+    pub fn query<'o>(&'o self) -> Query<'o, 'a> {
+        Query {
+            outline: self,
+            names: Vec::new(),
+            kinds: Vec::new(),
+            concl_sorts: Vec::new(),
+            uses_terms: Vec::new(),
+        }
+    }
+
+    pub(crate) fn find_sort(&self, name: &str) -> Option<u8> {
+        (0..self.header.num_sorts).find(|n| self.sort_name(*n) == Some(name))
+    }
+
+    pub(crate) fn find_term(&self, name: &str) -> Option<u32> {
+        (0..self.header.num_terms).find(|n| self.term_name(*n) == Some(name))
+    }
+
+    pub(crate) fn find_assert(&self, name: &str) -> Option<u32> {
+        (0..self.header.num_thms).find(|n| self.assert_name(*n) == Some(name))
+    }
+
+    /// The sort of a term's return value, or of an assertion's conclusion.
+    fn concl_sort(&self, stmt: StmtCmd) -> Res<Option<u8>> {
+        match stmt {
+            StmtCmd::Sort { .. } => Ok(None),
+            StmtCmd::TermDef { num, .. } => Ok(Some(self.get_term_by_num(none_err!(num)?)?.sort())),
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                let assert = self.get_assert_by_num(none_err!(num)?)?;
+                // The conclusion is the first thing in the unify stream, and its head
+                // is either an argument or a term.
+                match none_err!(assert.unify().next())?? {
+                    UnifyCmd::Ref(i) => Ok(Some(none_err!(assert.args().get(i as usize))?.sort())),
+                    UnifyCmd::Term { term_num, .. } => Ok(Some(self.get_term_by_num(term_num)?.sort())),
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Whether `term_num` appears in an assertion's statement or a def's body.
+    fn stmt_uses_term(&self, stmt: StmtCmd, term_num: u32) -> Res<bool> {
+        match stmt {
+            StmtCmd::Sort { .. } => Ok(false),
+            StmtCmd::TermDef { num, .. } => {
+                let term = self.get_term_by_num(none_err!(num)?)?;
+                if term.is_def() {
+                    mentions_term(term.unify(), term_num)
+                } else {
+                    Ok(false)
+                }
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } =>
+                mentions_term(self.get_assert_by_num(none_err!(num)?)?.unify(), term_num),
+        }
+    }
+}
+
+fn mentions_term(unify: UnifyIter, term_num: u32) -> Res<bool> {
+    for maybe_cmd in unify {
+        if let UnifyCmd::Term { term_num: t, .. } = maybe_cmd? {
+            if t == term_num {
+                return Ok(true)
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// A search over the declarations of an `Outline`. Each kind of filter can be given
+/// more than once; a declaration matches if it satisfies every filter.
+#[derive(Clone)]
+pub struct Query<'o, 'a> {
+    outline: &'o Outline<'a>,
+    names: Vec<String>,
+    kinds: Vec<DeclKind>,
+    concl_sorts: Vec<String>,
+    uses_terms: Vec<String>,
+}
+
+impl<'o, 'a> Query<'o, 'a> {
+    /// Only declarations whose name matches `pattern`, where `*` matches any run of
+    /// characters and `?` matches any single character.
+    pub fn name(mut self, pattern: &str) -> Self {
+        self.names.push(pattern.to_string());
+        self
+    }
+
+    /// Only declarations of this kind. Giving several kinds matches any of them.
+    pub fn kind(mut self, kind: DeclKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Only terms returning, or assertions concluding, something in this sort.
+    pub fn concl_sort(mut self, sort_name: &str) -> Self {
+        self.concl_sorts.push(sort_name.to_string());
+        self
+    }
+
+    /// Only assertions whose statement, or defs whose body, mention this term.
+    pub fn uses_term(mut self, term_name: &str) -> Self {
+        self.uses_terms.push(term_name.to_string());
+        self
+    }
+
+    /// The matching declarations, in the order they appear in the file.
+    pub fn run(&self) -> Res<Vec<StmtCmd>> {
+        let outline = self.outline;
+        // A filter naming a sort or term the file doesn't have can't match anything.
+        let mut concl_sorts = Vec::with_capacity(self.concl_sorts.len());
+        for name in self.concl_sorts.iter() {
+            match outline.find_sort(name) {
+                Some(n) => concl_sorts.push(n),
+                None => return Ok(Vec::new()),
+            }
+        }
+        let mut uses_terms = Vec::with_capacity(self.uses_terms.len());
+        for name in self.uses_terms.iter() {
+            match outline.find_term(name) {
+                Some(n) => uses_terms.push(n),
+                None => return Ok(Vec::new()),
+            }
+        }
+
+        let mut out = Vec::new();
+        'decls: for (stmt, _) in outline.declarations.iter() {
+            let stmt = *stmt;
+            if !self.kinds.is_empty() && !self.kinds.contains(&outline.decl_kind(stmt)?) {
+                continue
+            }
+            if !self.names.is_empty() {
+                match outline.stmt_name(stmt) {
+                    Some(name) if self.names.iter().all(|p| glob_match(p, name)) => {}
+                    _ => continue
+                }
+            }
+            if !concl_sorts.is_empty() {
+                match outline.concl_sort(stmt)? {
+                    Some(s) if concl_sorts.iter().all(|want| *want == s) => {}
+                    _ => continue
+                }
+            }
+            for t in uses_terms.iter() {
+                if !outline.stmt_uses_term(stmt, *t)? {
+                    continue 'decls
+                }
+            }
+            out.push(stmt);
+        }
+        Ok(out)
+    }
+}
+
+/// Match `path`, with `/` between components, against a glob `pattern`: `**` is any number
+/// of components, `*` any part of one, and `?` any one character of it. A declaration
+/// name is a path of one component, so `ax_*` matches `ax_mp`; `discover` in `project`
+/// matches file paths like `**/*.mmb`.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pat = pattern.split('/').filter(|c| !c.is_empty()).collect::<Vec<_>>();
+    let path = path.split('/').filter(|c| !c.is_empty()).collect::<Vec<_>>();
+    components_match(&pat, &path)
+}
+
+fn components_match(pat: &[&str], path: &[&str]) -> bool {
+    match pat.split_first() {
+        None => path.is_empty(),
+        // Any number of components, including none.
+        Some((&"**", rest)) => (0..=path.len()).any(|i| components_match(rest, &path[i..])),
+        Some((p, rest)) => match path.split_first() {
+            Some((name, path_rest)) => component_match(p, name) && components_match(rest, path_rest),
+            None => false,
+        }
+    }
+}
+
+/// Match one component `name` against a `pattern` with `*` and `?` wildcards.
+fn component_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*` if the current attempt fails.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    backtrack = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false
+            }
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[test]
+fn query1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let names = |q: Query| q.run().unwrap().into_iter().map(|s| outline.stmt_name(s).unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(names(outline.query().uses_term("not")), vec!["ax_3"]);
+    assert_eq!(names(outline.query().uses_term("im").name("ax_?")), vec!["ax_1", "ax_2", "ax_3"]);
+    assert_eq!(names(outline.query().concl_sort("wff").kind(DeclKind::Term)), vec!["im", "not"]);
+    assert_eq!(names(outline.query().name("*_mp").kind(DeclKind::Axiom)), vec!["ax_mp"]);
+    assert!(names(outline.query().uses_term("nonexistent")).is_empty());
+    assert!(names(outline.query().kind(DeclKind::Thm)).is_empty());
+
+    assert!(glob_match("a*b*c", "aXbYbc"));
+    assert!(!glob_match("a*b?", "ab"));
+    assert!(glob_match("**/*.mmb", "short.mmb"));
+    assert!(glob_match("**/*.mmb", "a/b/short.mmb"));
+    assert!(glob_match("a/**/s?ort.mmb", "a/short.mmb"));
+    assert!(!glob_match("*.mmb", "a/short.mmb"));
+    assert!(!glob_match("**/*.mmb", "short.mm0"));
+}
@@ -0,0 +1,117 @@
+//! The glue between the trusted kernel (see `kernel`) and the rest of the crate: the tables
+//! the kernel reads come from the `Outline`, and its verdicts become `VerifErr`s. This and
+//! `kernel` are all the code that has to be right for a declaration the kernel accepts to
+//! be sound. With `options.dual` (or the `kernel` feature) it checks every declaration
+//! after `MmbState` has.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::kernel::{ self, Env, TermSig, ThmSig, Decl, VecStore };
+use crate::mmb::proof::ProofIter;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr };
+use crate::make_sure;
+use crate::none_err;
+
+impl<'a> Env for Outline<'a> {
+    fn bytes(&self) -> &[u8] {
+        self.mmb()
+    }
+
+    fn num_sorts(&self) -> u8 {
+        self.header.num_sorts
+    }
+
+    fn num_terms(&self) -> u32 {
+        self.header.num_terms
+    }
+
+    fn num_thms(&self) -> u32 {
+        self.header.num_thms
+    }
+
+    fn sort_mods(&self, sort: u8) -> Option<u8> {
+        self.get_sort_mods(sort as usize).ok().map(|mods| mods.inner)
+    }
+
+    fn term(&self, num: u32) -> Option<TermSig<'_>> {
+        let term = self.get_term_by_num(num).ok()?;
+        Some(TermSig { sort: term.sort, args: term.args_start, unify: term.unify.pos })
+    }
+
+    fn thm(&self, num: u32) -> Option<ThmSig<'_>> {
+        let assert = self.get_assert_by_num(num).ok()?;
+        Some(ThmSig { args: assert.args_start, unify: assert.unify.pos })
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Check one declaration with the kernel, independently of `MmbState::verify1` (and
+    /// without recording it as verified).
+    pub fn recheck(&self, stmt: StmtCmd, proof: ProofIter) -> Res<()> {
+        make_sure!(self.in_table(stmt));
+        let decl = match stmt {
+            StmtCmd::Sort { num } => Decl::Sort(none_err!(num)?),
+            StmtCmd::TermDef { num, .. } => {
+                let num = none_err!(num)?;
+                Decl::Term { num, thms_before: *none_err!(self.thms_before.get(num as usize))? }
+            }
+            StmtCmd::Axiom { num } => {
+                let num = none_err!(num)?;
+                Decl::Axiom { num, terms_before: *none_err!(self.terms_before.get(num as usize))? }
+            }
+            StmtCmd::Thm { num, .. } => {
+                let num = none_err!(num)?;
+                Decl::Thm { num, terms_before: *none_err!(self.terms_before.get(num as usize))? }
+            }
+        };
+        let proof = if proof.is_null() { None } else { Some((proof.pos, proof.ends_at)) };
+        kernel::check(self, &mut VecStore::default(), decl, proof)
+            .map_err(|e| VerifErr::Kernel { decl: stmt, pos: e.pos, reason: e.reason })
+    }
+
+    /// With `options.dual`, check `stmt` again with `recheck` and report it if the two
+    /// checkers came to different verdicts; `first` is the first checker's.
+    pub fn compare_verdicts(&self, stmt: StmtCmd, proof: ProofIter, first: &Res<()>) -> Option<VerifErr> {
+        let second = self.recheck(stmt, proof);
+        if first.is_ok() == second.is_ok() {
+            return None
+        }
+        Some(VerifErr::Disagreement { decl: stmt, second: second.err().map(Box::new) })
+    }
+}
+
+#[test]
+fn recheck1() {
+    use crate::fs::FileData;
+    use crate::util::ErrKind;
+    use crate::testgen::{ corpus, random_env, Fixture, stream };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM, PROOF_TERM_SAVE, PROOF_THM, PROOF_CONV, PROOF_REFL };
+    let dual = |mmb: Vec<u8>| {
+        let file_data = FileData::new_from_bytes(mmb, Vec::new());
+        let mut outline = Outline::new_from(&file_data).ok()?;
+        outline.options.dual = true;
+        Some(crate::verify_serial(&outline, &outline.declarations).iter().filter(|e| e.kind() == ErrKind::Disagreement).count())
+    };
+    // The two agree on everything in the corpus, broken or not, and on random files.
+    for case in corpus() {
+        assert_eq!(dual(case.mmb).unwrap_or(0), 0, "{}", case.name);
+    }
+    for seed in 0..16 {
+        assert_eq!(dual(random_env(seed).0.to_bytes(false).unwrap()), Some(0));
+    }
+    let file_data = FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    for (stmt, proof) in outline.declarations.iter() {
+        outline.recheck(*stmt, *proof).unwrap();
+    }
+    // `th` proved by converting from a separately built copy of its statement, which only
+    // the second checker takes as the same expression without `hash_cons`.
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[
+        (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM, 0), (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM, 0), (PROOF_TERM, 0),
+        (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM_SAVE, 0), (PROOF_REF, 2), (PROOF_REF, 2), (PROOF_TERM, 0),
+        (PROOF_THM, 0), (PROOF_CONV, 0), (PROOF_REFL, 0),
+    ]);
+    assert_eq!(dual(fx.to_bytes()), Some(1));
+}
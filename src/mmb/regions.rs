@@ -0,0 +1,196 @@
+//! Which parts of an mmb file belong to what. Every pointer in the header and the tables
+//! claims a byte range, and in a well-formed file those ranges don't overlap, stay inside
+//! the file, and leave nothing unaccounted for but alignment padding. An exporter that gets
+//! an offset wrong usually breaks one of these, and it's much easier to see here than in
+//! the proof error it causes later on.
+//!
+//! The regions are the header, the sort data, the term and theorem tables, the data each
+//! table entry points to (its arguments, return type, and unify stream), the proof stream,
+//! and the index (its pointer tables and each of its entries).
+
+use crate::prelude::*;
+use core::fmt::{ Display, Formatter, Result as FmtResult };
+use crate::Outline;
+use crate::util::{ parse_cmd, parse_u16, parse_u32 };
+
+/// A byte range `start..end` of the file and what it holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{} @ {}..{}", self.name, self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegionErr {
+    /// `second` starts before `first` ends.
+    Overlap { first: Region, second: Region },
+    /// The region ends after the last byte of the file.
+    PastEof { region: Region, len: usize },
+    /// Bytes that no region claims, other than zeros padding the next region to 8 bytes.
+    Gap { start: usize, end: usize },
+}
+
+impl Display for RegionErr {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RegionErr::Overlap { first, second } => write!(f, "{} overlaps {}", second, first),
+            RegionErr::PastEof { region, len } => write!(f, "{} runs past the end of the file ({} bytes)", region, len),
+            RegionErr::Gap { start, end } => write!(f, "bytes {}..{} don't belong to anything", start, end),
+        }
+    }
+}
+
+/// Where the unify stream starting at `pos` ends, just after its `End` command. A stream
+/// that runs off the end of the file is taken to end one byte past it.
+fn stream_end(mmb: &[u8], mut pos: usize) -> usize {
+    loop {
+        match parse_cmd(mmb, pos) {
+            Ok((0, _, next)) => return next,
+            Ok((_, _, next)) => pos = next,
+            Err(_) => return mmb.len() + 1,
+        }
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Every region the header and tables point to, sorted by where they start. Regions
+    /// come from the raw table entries rather than from `get_term_by_num` and friends, so
+    /// an entry pointing outside the file still has a region.
+    pub fn regions(&self) -> Vec<Region> {
+        let h = &self.header;
+        let mmb = self.mmb();
+        let mut out = Vec::new();
+        let mut add = |name: String, start: usize, end: usize| out.push(Region { name, start, end });
+
+        add("header".to_string(), 0, 40);
+        add("sort data".to_string(), h.sort_data_start as usize, h.sort_data_start as usize + h.num_sorts as usize);
+        let terms_start = h.terms_start as usize;
+        add("term table".to_string(), terms_start, terms_start + 8 * h.num_terms as usize);
+        let thms_start = h.thms_start as usize;
+        add("theorem table".to_string(), thms_start, thms_start + 8 * h.num_thms as usize);
+
+        for num in 0..h.num_terms as usize {
+            let entry = terms_start + 8 * num;
+            let (num_args, is_def, args_at) = match mmb.get(entry..entry + 8) {
+                Some(bytes) => (
+                    parse_u16(bytes).map(|(n, _)| n as usize).unwrap_or(0),
+                    bytes[2] & 0x80 != 0,
+                    parse_u32(&bytes[4..]).map(|(p, _)| p as usize).unwrap_or(0),
+                ),
+                None => continue
+            };
+            // The arguments, then the return type, then a def's unify stream.
+            let args_end = args_at + 8 * (num_args + 1);
+            let end = if is_def { stream_end(mmb, args_end) } else { args_end };
+            add(format!("term {} data", num), args_at, end);
+        }
+        for num in 0..h.num_thms as usize {
+            let entry = thms_start + 8 * num;
+            let (num_args, args_at) = match mmb.get(entry..entry + 8) {
+                Some(bytes) => (
+                    parse_u16(bytes).map(|(n, _)| n as usize).unwrap_or(0),
+                    parse_u32(&bytes[4..]).map(|(p, _)| p as usize).unwrap_or(0),
+                ),
+                None => continue
+            };
+            add(format!("theorem {} data", num), args_at, stream_end(mmb, args_at + 8 * num_args));
+        }
+
+        add("proof stream".to_string(), h.proof_stream_start as usize, self.proof_stream_end + 1);
+
+        if h.index_start != 0 {
+            let index_start = h.index_start as usize;
+            let num_ptrs = 1 + h.num_sorts as usize + h.num_terms as usize + h.num_thms as usize;
+            add("index tables".to_string(), index_start, index_start + 8 * num_ptrs);
+            let tables = [("sort", &self.index.sorts), ("term", &self.index.terms), ("theorem", &self.index.thms)];
+            for (table, ptrs) in tables.iter() {
+                for (num, ptr) in ptrs.iter().enumerate() {
+                    // Two child pointers, the row and column, the declaration pointer, the
+                    // item number, the kind, and then the name with its terminating 0.
+                    if let Some(entry) = self.index_entry(*ptr as usize) {
+                        let start = *ptr as usize;
+                        add(format!("index entry of {} {}", table, num), start, start + 37 + entry.charbuff.len() + 1);
+                    }
+                }
+            }
+        }
+
+        out.retain(|r| r.start < r.end);
+        out.sort_by_key(|r| (r.start, r.end));
+        out
+    }
+
+    /// Check that the regions fit together: no overlaps, nothing past the end of the
+    /// file, and no unclaimed bytes except zero padding.
+    pub fn check_regions(&self) -> Vec<RegionErr> {
+        let mmb = self.mmb();
+        let mut errs = Vec::new();
+        let is_padding = |start: usize, end: usize| {
+            end - start < 8 && (end & 7 == 0 || end == mmb.len()) && mmb[start..end].iter().all(|b| *b == 0)
+        };
+        let regions = self.regions();
+        // The region reaching furthest into the file so far.
+        let mut furthest: Option<&Region> = None;
+        for region in regions.iter() {
+            if region.end > mmb.len() {
+                errs.push(RegionErr::PastEof { region: region.clone(), len: mmb.len() });
+            }
+            let covered = furthest.map(|r| r.end).unwrap_or(0);
+            if let Some(prev) = furthest.filter(|prev| region.start < prev.end) {
+                errs.push(RegionErr::Overlap { first: prev.clone(), second: region.clone() });
+            } else if region.start > covered && covered < mmb.len() && !is_padding(covered, region.start.min(mmb.len())) {
+                errs.push(RegionErr::Gap { start: covered, end: region.start });
+            }
+            if region.end > covered {
+                furthest = Some(region);
+            }
+        }
+        let covered = furthest.map(|r| r.end).unwrap_or(0);
+        if covered < mmb.len() && !is_padding(covered, mmb.len()) {
+            errs.push(RegionErr::Gap { start: covered, end: mmb.len() });
+        }
+        errs
+    }
+}
+
+#[test]
+fn regions1() {
+    use crate::testgen::Fixture;
+    let check = |bytes: Vec<u8>| {
+        let file_data = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+        Outline::new_from(&file_data).unwrap().check_regions()
+    };
+    for path in ["./test_resources/short.mmb", "./test_resources/a.mmb"].iter() {
+        let file_data = crate::fs::FileData::new_from(path, None::<String>).unwrap();
+        assert_eq!(check(file_data.mmb_file.clone()), Vec::new());
+    }
+    assert_eq!(check(Fixture::valid().to_bytes()), Vec::new());
+
+    // Point the second theorem's arguments back at the first theorem's.
+    let mut bytes = Fixture::valid().to_bytes();
+    let thms_start = u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]) as usize;
+    let first_args = bytes[thms_start + 4..thms_start + 8].to_vec();
+    bytes[thms_start + 12..thms_start + 16].copy_from_slice(&first_args);
+    let errs = check(bytes);
+    let names = |r: &Region| r.name.clone();
+    assert!(errs.iter().any(|e| match e {
+        RegionErr::Overlap { first, second } => {
+            let mut pair = [names(first), names(second)];
+            pair.sort();
+            pair == ["theorem 0 data", "theorem 1 data"]
+        }
+        _ => false
+    }));
+    assert!(errs.iter().any(|e| matches!(e, RegionErr::Gap { .. })));
+
+    let mut bytes = Fixture::valid().to_bytes();
+    bytes.extend_from_slice(&[0; 16]);
+    assert_eq!(check(bytes.clone()), vec![RegionErr::Gap { start: bytes.len() - 16, end: bytes.len() }]);
+}
@@ -0,0 +1,87 @@
+//! Checking a theorem that isn't in the file. A service holding a verified database can
+//! take candidate proofs of new statements from clients and check each one against the
+//! database's terms and theorems, without writing a new mmb file: the statement is given
+//! the same way the theorem table gives one (its binders and a unify stream for its
+//! hypotheses and conclusion), and the proof the same way the proof stream gives one.
+//!
+//! A detached proof doesn't become part of the `Outline`: it isn't marked as verified, no
+//! other proof can refer to it, and the same one can be checked any number of times.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::MmbState;
+use crate::mmb::proof::ProofIter;
+use crate::mmb::unify::UnifyIter;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr, Assert };
+use crate::make_sure;
+
+/// A theorem and its proof, laid out as in an mmb file.
+#[derive(Debug, Clone, Copy)]
+pub struct DetachedProof<'p> {
+    /// The binders, as little-endian `u64` types like a theorem table entry's.
+    pub args: &'p [u8],
+    /// The unify stream of the hypotheses and conclusion, ending with a 0 byte.
+    pub unify: &'p [u8],
+    /// The proof stream, ending with a 0 byte.
+    pub proof: &'p [u8],
+}
+
+impl<'a> Outline<'a> {
+    /// Check `thm` against this file's terms and theorems. Every declaration in the file
+    /// has to have been verified first, so the proof can only build on what's been checked.
+    pub fn verify_detached(&'a self, bump: &mut Bump, thm: DetachedProof) -> Res<()> {
+        let mut errs = Vec::new();
+        self.check_decl_counts(&mut errs);
+        self.assert_mmb_done(&mut errs);
+        if let Some(e) = errs.into_iter().next() {
+            return Err(e)
+        }
+        make_sure!(thm.unify.last() == Some(&0));
+        make_sure!(thm.proof.last() == Some(&0));
+        let assert = Assert {
+            assert_num: self.header.num_thms,
+            args_start: thm.args,
+            unify: UnifyIter::new(thm.unify, 0),
+        };
+        // Its binders weren't in the tables, so they haven't been checked yet.
+        self.validate_assert(&assert)?;
+        let proof = ProofIter::new(thm.proof, 0, thm.proof.len());
+        let stmt = StmtCmd::Thm { num: None, local: true };
+        MmbState::new_from(self, bump, stmt).verify_assert(stmt, assert, proof)
+    }
+}
+
+#[test]
+fn sandbox1() {
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM, PROOF_TERM_SAVE, PROOF_THM };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF };
+    use crate::testgen::{ Fixture, stream, reg };
+    Fixture::valid().with_outline(|outline| {
+        let mut bump = Bump::new();
+        // `(a b: wff): $ im (im b a) (im b a) $`, from the second axiom.
+        let args = [reg(0, 0), reg(0, 0)].iter().flat_map(|t| t.inner.to_le_bytes()).collect::<Vec<u8>>();
+        let unify = stream(&[
+            (UNIFY_TERM, 0),
+            (UNIFY_TERM, 0), (UNIFY_REF, 1), (UNIFY_REF, 0),
+            (UNIFY_TERM, 0), (UNIFY_REF, 1), (UNIFY_REF, 0),
+        ]);
+        let proof = stream(&[
+            (PROOF_REF, 1), (PROOF_REF, 0), (PROOF_TERM_SAVE, 0),
+            (PROOF_REF, 2), (PROOF_REF, 2), (PROOF_TERM, 0),
+            (PROOF_THM, 0),
+        ]);
+        let thm = DetachedProof { args: &args, unify: &unify, proof: &proof };
+        // Nothing has been verified yet.
+        assert!(outline.verify_detached(&mut bump, thm).is_err());
+        assert!(crate::verify_serial(outline, &outline.declarations).is_empty());
+        assert!(outline.verify_detached(&mut bump, thm).is_ok());
+        assert!(outline.verify_detached(&mut bump, thm).is_ok());
+        // A proof of something else.
+        let wrong = stream(&[(PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM_SAVE, 0), (PROOF_REF, 2), (PROOF_REF, 2), (PROOF_TERM, 0), (PROOF_THM, 0)]);
+        assert!(outline.verify_detached(&mut bump, DetachedProof { proof: &wrong, ..thm }).is_err());
+        // A proof with no end.
+        assert!(outline.verify_detached(&mut bump, DetachedProof { proof: &proof[..proof.len() - 1], ..thm }).is_err());
+    });
+}
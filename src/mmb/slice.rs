@@ -0,0 +1,66 @@
+//! Cutting a smaller mmb file out of a bigger one: a chosen set of declarations plus
+//! everything they depend on, for minimizing bug reports or sharing a single theorem.
+//!
+//! Every sort is kept (there are at most 128, and dependencies on sorts aren't tracked),
+//! but terms and assertions are renumbered, so the unify and proof streams are re-encoded
+//! with the new numbers. Statements outside the spec are dropped. If the original file has
+//! an index, the slice gets a new one with the same names. The slice can be verified on
+//! its own, but the mm0 files for the original won't match it.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::write::{ Renumbering, Source, write_mmb };
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+impl<'a> Outline<'a> {
+    /// A new mmb file with just the declarations at `roots` (positions in `declarations`)
+    /// and their dependencies.
+    pub fn slice(&self, roots: &[usize]) -> Res<Vec<u8>> {
+        let mut keep = self.with_deps(roots.iter().copied())?;
+        let mut renum = Renumbering::new_for(self);
+        let (mut num_sorts, mut num_terms, mut num_thms) = (0, 0, 0);
+        for ((stmt, _), keep) in self.declarations.iter().zip(keep.iter_mut()) {
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    *keep = true;
+                    *none_err!(renum.sorts.get_mut(none_err!(num)? as usize))? = Some(num_sorts);
+                    num_sorts += 1;
+                }
+                StmtCmd::TermDef { num, .. } if *keep => {
+                    *none_err!(renum.terms.get_mut(none_err!(num)? as usize))? = Some(num_terms);
+                    num_terms += 1;
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } if *keep => {
+                    *none_err!(renum.thms.get_mut(none_err!(num)? as usize))? = Some(num_thms);
+                    num_thms += 1;
+                }
+                _ => {}
+            }
+        }
+        write_mmb(&[Source { outline: self, keep, renum, compact: false }])
+    }
+}
+
+#[test]
+fn slice1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let ax_3 = outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some("ax_3")).unwrap();
+    let ax_mp = outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some("ax_mp")).unwrap();
+
+    let sliced = crate::fs::FileData::new_from_bytes(outline.slice(&[ax_mp]).unwrap(), Vec::new());
+    let sliced = Outline::new_from(&sliced).unwrap();
+    assert_eq!((sliced.header.num_sorts, sliced.header.num_terms, sliced.header.num_thms), (1, 1, 1));
+    assert_eq!(sliced.term_name(0), Some("im"));
+    assert_eq!(sliced.assert_name(0), Some("ax_mp"));
+    assert!(sliced.check_index().is_empty());
+    assert!(crate::verify_serial(&sliced, &sliced.declarations).is_empty());
+    assert_eq!(sliced.assert_sexprs(0).unwrap(), outline.assert_sexprs(3).unwrap());
+
+    let sliced = crate::fs::FileData::new_from_bytes(outline.slice(&[ax_3, ax_mp]).unwrap(), Vec::new());
+    let sliced = Outline::new_from(&sliced).unwrap();
+    assert_eq!((sliced.header.num_terms, sliced.header.num_thms), (2, 2));
+    assert!(crate::verify_serial(&sliced, &sliced.declarations).is_empty());
+}
@@ -0,0 +1,200 @@
+//! A report of every place a sort modifier matters, for spec authors wondering why a sort
+//! needs (or doesn't need) one. The verifier checks the modifiers one `make_sure!` at a
+//! time as it runs into them; this walks all the declarations without checking proofs and
+//! records each constraint, whether or not it's satisfied:
+//!
+//! * `pure` sorts can't be the target of a term or def.
+//! * `strict` sorts can't be used for bound variables, including dummies.
+//! * `provable` sorts are the only ones hypotheses and conclusions can be in.
+//! * `free` sorts can't have variables that depend on bound variables.
+//!
+//! So `pure`, `strict`, and `free` are only possible if nothing uses the sort in the ways
+//! above, and `provable` is only needed if something does.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::{ SORT_PURE, SORT_STRICT, SORT_PROVABLE, SORT_FREE };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofCmd;
+use crate::mmb::unify::UnifyCmd;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SortMod {
+    Pure,
+    Strict,
+    Provable,
+    Free,
+}
+
+impl SortMod {
+    pub const ALL: [SortMod; 4] = [SortMod::Pure, SortMod::Strict, SortMod::Provable, SortMod::Free];
+
+    pub fn bit(self) -> u8 {
+        match self {
+            SortMod::Pure => SORT_PURE,
+            SortMod::Strict => SORT_STRICT,
+            SortMod::Provable => SORT_PROVABLE,
+            SortMod::Free => SORT_FREE,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortMod::Pure => "pure",
+            SortMod::Strict => "strict",
+            SortMod::Provable => "provable",
+            SortMod::Free => "free",
+        }
+    }
+
+    /// `provable` has to be set for its uses; the others have to be unset.
+    pub fn required(self) -> bool {
+        self == SortMod::Provable
+    }
+}
+
+/// How a declaration uses a sort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModRole {
+    /// The return sort of a term or def.
+    TermTarget,
+    /// A bound variable among the arguments.
+    BoundArg(usize),
+    /// A dummy variable in a def's body or a theorem's proof.
+    Dummy,
+    /// A variable among the arguments with dependencies.
+    DepsArg(usize),
+    /// A hypothesis of an axiom or theorem, counting from 0.
+    Hyp(usize),
+    Concl,
+}
+
+/// One place where `modifier` constrains `sort`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModUse {
+    pub decl: StmtCmd,
+    pub sort: u8,
+    pub modifier: SortMod,
+    pub role: ModRole,
+    /// Whether the sort's modifiers satisfy the constraint.
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SortModReport {
+    pub uses: Vec<ModUse>,
+}
+
+impl SortModReport {
+    pub fn violations(&self) -> impl Iterator<Item = &ModUse> {
+        self.uses.iter().filter(|u| !u.ok)
+    }
+
+    /// The uses of one modifier of one sort.
+    pub fn uses_of(&self, sort: u8, modifier: SortMod) -> impl Iterator<Item = &ModUse> {
+        self.uses.iter().filter(move |u| u.sort == sort && u.modifier == modifier)
+    }
+}
+
+struct Collector<'o, 'a> {
+    outline: &'o Outline<'a>,
+    report: SortModReport,
+}
+
+impl<'o, 'a> Collector<'o, 'a> {
+    fn add(&mut self, decl: StmtCmd, sort: u8, modifier: SortMod, role: ModRole) -> Res<()> {
+        let set = self.outline.get_sort_mods(sort as usize)?.inner & modifier.bit() != 0;
+        let ok = set == modifier.required();
+        self.report.uses.push(ModUse { decl, sort, modifier, role, ok });
+        Ok(())
+    }
+
+    fn add_args(&mut self, decl: StmtCmd, args: crate::util::Args) -> Res<()> {
+        for (idx, ty) in args.enumerate() {
+            if ty.is_bound() {
+                self.add(decl, ty.sort(), SortMod::Strict, ModRole::BoundArg(idx))?;
+            } else if ty.has_deps() {
+                self.add(decl, ty.sort(), SortMod::Free, ModRole::DepsArg(idx))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Every place a sort modifier constrains a declaration. Proofs aren't checked.
+    pub fn check_sort_mods(&self) -> Res<SortModReport> {
+        let mut c = Collector { outline: self, report: SortModReport::default() };
+        let bump = Bump::new();
+        for (stmt, proof) in self.declarations.iter() {
+            let stmt = *stmt;
+            match stmt {
+                StmtCmd::Sort { .. } => continue,
+                StmtCmd::TermDef { num, .. } => {
+                    let term = self.get_term_by_num(none_err!(num)?)?;
+                    c.add(stmt, term.sort(), SortMod::Pure, ModRole::TermTarget)?;
+                    c.add_args(stmt, term.args_no_ret())?;
+                    if term.is_def() {
+                        for cmd in term.unify() {
+                            if let UnifyCmd::Dummy { sort_id } = cmd? {
+                                c.add(stmt, sort_id, SortMod::Strict, ModRole::Dummy)?;
+                            }
+                        }
+                    }
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    c.add_args(stmt, self.get_assert_by_num(num)?.args())?;
+                    let statement = self.statement(&bump, num)?;
+                    for (idx, hyp) in statement.hyps.iter().enumerate() {
+                        c.add(stmt, expr_sort(hyp), SortMod::Provable, ModRole::Hyp(idx))?;
+                    }
+                    c.add(stmt, expr_sort(statement.concl), SortMod::Provable, ModRole::Concl)?;
+                }
+            }
+            if !proof.is_null() {
+                for cmd in *proof {
+                    if let ProofCmd::Dummy { sort_num } = cmd? {
+                        c.add(stmt, sort_num, SortMod::Strict, ModRole::Dummy)?;
+                    }
+                }
+            }
+        }
+        Ok(c.report)
+    }
+}
+
+fn expr_sort(e: &crate::mmb::MmbExpr) -> u8 {
+    match e {
+        | crate::mmb::MmbExpr::Var { ty, .. }
+        | crate::mmb::MmbExpr::App { ty, .. } => ty.sort(),
+    }
+}
+
+#[test]
+fn check_sort_mods1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let report = outline.check_sort_mods().unwrap();
+    assert_eq!(report.violations().count(), 0);
+    // Both terms target wff; there are four conclusions, and ax_mp has two hypotheses.
+    assert_eq!(report.uses_of(0, SortMod::Pure).count(), 2);
+    assert_eq!(report.uses_of(0, SortMod::Provable).count(), 6);
+    assert_eq!(report.uses_of(0, SortMod::Strict).count(), 0);
+
+    // Without `provable`, every hypothesis and conclusion is a violation.
+    let mut bytes = file_data.mmb_file.clone();
+    bytes[outline.header.sort_data_start as usize] ^= SORT_PROVABLE | SORT_PURE;
+    let changed = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let changed = Outline::new_from(&changed).unwrap();
+    let report = changed.check_sort_mods().unwrap();
+    assert_eq!(report.violations().filter(|u| u.modifier == SortMod::Provable).count(), 6);
+    assert_eq!(report.violations().filter(|u| u.role == ModRole::TermTarget).count(), 2);
+}
@@ -0,0 +1,192 @@
+//! Reading an assertion's statement (its hypotheses and conclusion) straight off its
+//! unify stream, without checking any proofs. Useful for tools that only want to look at
+//! what a file claims, and don't need (or can't afford) full verification.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+use crate::Outline;
+use crate::mmb::{ MmbExpr, MmbItem };
+use crate::mmb::unify::{ UnifyIter, UnifyCmd };
+use crate::util::{ Res, Type, VerifErr };
+use crate::none_err;
+use crate::make_sure;
+
+/// The hypotheses and conclusion of an axiom or theorem as expression trees.
+/// Variables are `MmbExpr::Var`s indexed by their position in the assertion's
+/// arguments, with the argument's type; applications have the type the verifier
+/// would give them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Statement<'b> {
+    pub assert_num: u32,
+    /// One `Var` per argument of the assertion, in order.
+    pub args: Vec<&'b MmbExpr<'b>>,
+    /// In the order they're declared in, which is the reverse of the unify stream.
+    pub hyps: Vec<&'b MmbExpr<'b>>,
+    /// The name of each hypothesis, if the mm0 file has been checked and gives it one.
+    pub hyp_names: Vec<Option<String>>,
+    pub concl: &'b MmbExpr<'b>,
+}
+
+impl<'a> Outline<'a> {
+    /// Decode the statement of assertion `assert_num`, allocating the expressions in `bump`.
+    /// Only checks what's needed to build well-formed trees; a statement read this way
+    /// is not known to be valid until the file has been verified.
+    pub fn statement<'b>(&self, bump: &'b Bump, assert_num: u32) -> Res<Statement<'b>> {
+        let assert = self.get_assert_by_num(assert_num)?;
+        let args: Vec<&'b MmbExpr<'b>> = assert.args()
+            .enumerate()
+            .map(|(idx, ty)| &*bump.alloc(MmbExpr::Var { idx, ty }))
+            .collect();
+        let mut heap: Vec<Option<&'b MmbExpr<'b>>> = args.iter().copied().map(Some).collect();
+        let mut it = assert.unify();
+        let concl = self.read_expr(bump, &mut it, &mut heap, &mut None)?;
+        let mut hyps = Vec::new();
+        while let Some(maybe_cmd) = it.next() {
+            match maybe_cmd? {
+                UnifyCmd::Hyp => hyps.push(self.read_expr(bump, &mut it, &mut heap, &mut None)?),
+                _ => return Err(VerifErr::Msg(format!("expected UHyp in the unify stream of assertion {}", assert_num))),
+            }
+        }
+        hyps.reverse();
+        let hyp_names = (0..hyps.len()).map(|i| self.hyp_name(assert_num, i)).collect();
+        Ok(Statement { assert_num, args, hyps, hyp_names, concl })
+    }
+
+    /// The name the mm0 file gives hypothesis `hyp` (counting from 0 in declaration order)
+    /// of assertion `assert_num`. Only known once the mm0 file has been checked.
+    pub fn hyp_name(&self, assert_num: u32, hyp: usize) -> Option<String> {
+        self.hyp_names.with(|names| names.get(&assert_num).and_then(|ns| ns.get(hyp).cloned().flatten()))
+    }
+
+    /// Blame `err` on the hypothesis of `assert_num` being unified after `hyps_seen` `UHyp`
+    /// commands. The unify stream lists the hypotheses last to first.
+    pub(crate) fn hyp_err(&self, assert_num: u32, hyps_seen: usize, err: VerifErr) -> VerifErr {
+        let num_hyps = match self.get_assert_by_num(assert_num) {
+            Ok(assert) => assert.unify().filter(|cmd| matches!(cmd, Ok(UnifyCmd::Hyp))).count(),
+            Err(_) => return err,
+        };
+        let hyp = match num_hyps.checked_sub(hyps_seen) {
+            Some(hyp) => hyp,
+            None => return err,
+        };
+        VerifErr::Hyp { thm: assert_num, hyp, name: self.hyp_name(assert_num, hyp), err: Box::new(err) }
+    }
+
+    /// Read one expression off a unify stream. Dummies are only allowed if `next_dummy`
+    /// is given (as in def bodies); each one becomes a new bound `Var` numbered from it.
+    pub(crate) fn read_expr<'b>(
+        &self,
+        bump: &'b Bump,
+        it: &mut UnifyIter<'a>,
+        heap: &mut Vec<Option<&'b MmbExpr<'b>>>,
+        next_dummy: &mut Option<usize>,
+    ) -> Res<&'b MmbExpr<'b>> {
+        match none_err!(it.next())?? {
+            // A ref to a slot that's still `None` is a term referring to itself.
+            UnifyCmd::Ref(i) => none_err!(heap.get(i as usize).copied().flatten()),
+            UnifyCmd::Dummy { sort_id } => match next_dummy {
+                None => Err(VerifErr::Msg("assertions can't have dummy variables".to_string())),
+                Some(idx) => {
+                    let mut ty = Type::new_bound();
+                    ty.add_sort(sort_id);
+                    let e = &*bump.alloc(MmbExpr::Var { idx: *idx, ty });
+                    *idx += 1;
+                    heap.push(Some(e));
+                    Ok(e)
+                }
+            },
+            UnifyCmd::Hyp => Err(VerifErr::Msg("unexpected UHyp inside an expression".to_string())),
+            UnifyCmd::Term { term_num, save } => {
+                make_sure!(term_num < self.header.num_terms);
+                let termref = self.get_term_by_num(term_num)?;
+                // A saved term gets its heap slot before its subterms do.
+                let slot = heap.len();
+                if save {
+                    heap.push(None);
+                }
+                let mut args = BumpVec::new_in(bump);
+                let mut ty = Type::new_with_sort(termref.sort());
+                for sig in termref.args_no_ret() {
+                    let e = self.read_expr(bump, it, heap, next_dummy)?;
+                    let item = &*bump.alloc(MmbItem::Expr(e));
+                    // As in `proof_term`, only the arguments in non-bound positions
+                    // contribute their dependencies.
+                    if !sig.is_bound() {
+                        ty |= item.get_deps().or(item.get_bound_digit())?;
+                    }
+                    args.push(item);
+                }
+                let e = &*bump.alloc(MmbExpr::App { term_num, args: args.into_bump_slice(), ty });
+                if save {
+                    heap[slot] = Some(e);
+                }
+                Ok(e)
+            }
+        }
+    }
+}
+
+#[test]
+fn statement1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let bump = Bump::new();
+    let stmt = outline.statement(&bump, 3).unwrap();
+    assert_eq!(stmt.args.len(), 2);
+    let (v0, v1) = (stmt.args[0], stmt.args[1]);
+    assert_eq!(stmt.concl, v1);
+    assert_eq!(stmt.hyps.len(), 2);
+    assert_eq!(stmt.hyps[1], v0);
+    match stmt.hyps[0] {
+        MmbExpr::App { term_num, args, .. } => {
+            assert_eq!(outline.term_name(*term_num), Some("im"));
+            assert_eq!(args, &[&MmbItem::Expr(v0), &MmbItem::Expr(v1)]);
+        }
+        owise => panic!("expected an application, got {:?}", owise),
+    }
+    assert!(outline.statement(&bump, outline.header.num_thms).is_err());
+}
+
+#[test]
+fn hyp_names1() {
+    use crate::testgen::{ Fixture, AssertSpec, stream, reg };
+    use crate::mmb::stmt::STMT_THM;
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM, PROOF_THM, PROOF_HYP };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF, UNIFY_HYP };
+    let wff = reg(0, 0);
+    // `ax` becomes modus ponens, and `th` applies it with a proof of `b` for `h2: $ a $`.
+    let mut fx = Fixture::valid();
+    fx.asserts[0] = AssertSpec {
+        args: vec![wff, wff],
+        unify: stream(&[(UNIFY_REF, 1), (UNIFY_HYP, 0), (UNIFY_REF, 0), (UNIFY_HYP, 0), (UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1)]),
+    };
+    fx.asserts[1] = AssertSpec {
+        args: vec![wff, wff],
+        unify: stream(&[(UNIFY_REF, 1), (UNIFY_HYP, 0), (UNIFY_REF, 1), (UNIFY_HYP, 0), (UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1)]),
+    };
+    fx.stmts[2].1 = stream(&[
+        (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM, 0), (PROOF_HYP, 0),
+        (PROOF_REF, 0), (PROOF_HYP, 0),
+        (PROOF_REF, 1),
+    ]);
+    fx.stmts[3] = (STMT_THM, stream(&[
+        (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM, 0), (PROOF_HYP, 0),
+        (PROOF_REF, 1), (PROOF_HYP, 0),
+        (PROOF_REF, 2), (PROOF_REF, 3), (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_REF, 1), (PROOF_THM, 0),
+    ]));
+    let mm0 = "provable sort wff;\nterm im (a b: wff): wff;\n\
+        axiom ax (a b: wff) (h1: $ im a b $) (h2: $ a $): $ b $;\n\
+        theorem th (a b: wff) (h: $ im a b $) (k: $ b $): $ b $;\n";
+    let file_data = crate::fs::FileData::new_from_bytes(fx.to_bytes(), vec![mm0.to_string()]);
+    let outline = Outline::new_from(&file_data).unwrap();
+    let errs = crate::verify_serial(&outline, &outline.declarations);
+    match errs.first() {
+        Some(VerifErr::Hyp { thm: 0, hyp: 1, name: Some(name), .. }) => assert_eq!(name, "h2"),
+        owise => panic!("expected h2 to fail, got {:?}", owise),
+    }
+    assert!(format!("{:?}", errs[0]).contains("hypothesis 'h2' of assertion 0 failed to unify"));
+    let bump = Bump::new();
+    assert_eq!(outline.statement(&bump, 1).unwrap().hyp_names, vec![Some("h".to_string()), Some("k".to_string())]);
+}
@@ -1,29 +1,12 @@
 
-/// `STMT_AXIOM = 0x02`, starts an `axiom` declaration
-pub const STMT_AXIOM: u8 = 0x02;
-/// `STMT_SORT = 0x04`, starts a `sort` declaration
-pub const STMT_SORT: u8 = 0x04;
-// `STMT_TERM = 0x05`, starts a `term` declaration
-//pub const STMT_TERM: u8 = 0x05;
-/// `STMT_DEF = 0x05`, starts a `def` declaration. (This is the same as
-/// `STMT_TERM` because the actual indication of whether this is a
-/// def is in the term header)
-pub const STMT_DEF: u8 = 0x05;
-/// `STMT_THM = 0x06`, starts a `theorem` declaration
-pub const STMT_THM: u8 = 0x06;
-/// `STMT_LOCAL = 0x08`, starts a `local` declaration
-/// (a bit mask to be combined with `STMT_THM` or `STMT_DEF`)
-pub const STMT_LOCAL: u8 = 0x08;
-/// `STMT_LOCAL_DEF = 0x0D`
-pub const STMT_LOCAL_DEF: u8 = STMT_LOCAL | STMT_DEF;
-/// `STMT_LOCAL_THM = 0x0E`
-pub const STMT_LOCAL_THM: u8 = STMT_LOCAL | STMT_THM;
+pub use crate::mmb::format::{ STMT_AXIOM, STMT_SORT, STMT_DEF, STMT_THM, STMT_LOCAL, STMT_LOCAL_DEF, STMT_LOCAL_THM };
 
 
 /// The main part of the proof consists of a sequence of declarations,
 /// and these commands denote the different kind of declaration that can
 /// be introduced.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StmtCmd {
     /// A new sort. Equivalent to `sort foo;`. This is followed by no data,
     /// as the sort data is stored in the header.
@@ -52,7 +35,7 @@ impl StmtCmd {
     }
 }
 
-impl std::convert::TryFrom<u8> for StmtCmd {
+impl core::convert::TryFrom<u8> for StmtCmd {
     type Error = ();
     fn try_from(cmd: u8) -> Result<Self, ()> {
         Ok(match cmd {
@@ -0,0 +1,166 @@
+//! The extra checks of strict mode (`VerifyOptions::strict`), for archiving files that should
+//! be exactly what a well-behaved writer produces. The format tolerates all of these, and
+//! none of them can change what a file proves, so they're off by default:
+//!
+//! * Reserved fields in the header and in the term and theorem tables have to be 0.
+//! * Nothing but zeros can follow the proof stream (up to the index, if there is one).
+//! * The tables can't have unused slots: the term table starts right after the sort data
+//!   (rounded up to 8 bytes), and the data of each table's first entry starts right after
+//!   the table.
+//! * `Ref`, `ConvRef`, and `URef` have to use the smallest data field their index fits in.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::proof::{ PROOF_REF, PROOF_CONV_REF };
+use crate::mmb::unify::{ UNIFY_REF, UnifyIter };
+use crate::util::{ VerifErr, parse_cmd };
+
+fn strict_err(pos: usize, msg: String) -> VerifErr {
+    VerifErr::Strict { pos, msg }
+}
+
+/// The size of a command with the smallest data field that holds `data`.
+fn min_cmd_size(data: u32) -> usize {
+    if data == 0 {
+        1
+    } else if data <= 0xFF {
+        2
+    } else if data <= 0xFFFF {
+        3
+    } else {
+        5
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Push an error for each thing strict mode rejects.
+    pub fn check_strict(&self, errs: &mut Vec<VerifErr>) {
+        self.check_reserved(errs);
+        self.check_trailing(errs);
+        self.check_table_slots(errs);
+        self.check_backrefs(errs);
+    }
+
+    fn check_reserved(&self, errs: &mut Vec<VerifErr>) {
+        if self.header.reserved != 0 {
+            errs.push(strict_err(6, format!("reserved header field is {:#x}", self.header.reserved)));
+        }
+        if self.header.reserved2 != 0 {
+            errs.push(strict_err(28, format!("reserved header field is {:#x}", self.header.reserved2)));
+        }
+        let mmb = self.mmb();
+        for num in 0..self.header.num_terms as usize {
+            let pos = self.header.terms_start as usize + 8 * num + 3;
+            if let Some(byte) = mmb.get(pos).filter(|b| **b != 0) {
+                errs.push(strict_err(pos, format!("reserved byte of term {} is {:#x}", num, byte)));
+            }
+        }
+        for num in 0..self.header.num_thms as usize {
+            let pos = self.header.thms_start as usize + 8 * num + 2;
+            if let Some(bytes) = mmb.get(pos..pos + 2).filter(|b| *b != [0, 0]) {
+                errs.push(strict_err(pos, format!("reserved field of theorem {} is {:02x?}", num, bytes)));
+            }
+        }
+    }
+
+    fn check_trailing(&self, errs: &mut Vec<VerifErr>) {
+        let mmb = self.mmb();
+        let start = self.proof_stream_end + 1;
+        let end = match self.header.index_start as usize {
+            0 => mmb.len(),
+            index_start => index_start.min(mmb.len()),
+        };
+        if let Some(pos) = mmb.get(start..end).and_then(|rest| rest.iter().position(|b| *b != 0)) {
+            errs.push(strict_err(start + pos, "nonzero bytes after the end of the proof stream".to_string()));
+        }
+    }
+
+    fn check_table_slots(&self, errs: &mut Vec<VerifErr>) {
+        let h = &self.header;
+        let sorts_end = (h.sort_data_start as usize + h.num_sorts as usize + 7) & !7;
+        if h.terms_start as usize != sorts_end {
+            errs.push(strict_err(sorts_end, format!("the term table starts at {} instead of right after the sort data", h.terms_start)));
+        }
+        let terms_end = h.terms_start as usize + 8 * h.num_terms as usize;
+        if (h.thms_start as usize) < terms_end {
+            errs.push(strict_err(h.thms_start as usize, format!("the theorem table overlaps the term table, which ends at {}", terms_end)));
+        }
+        let thms_end = h.thms_start as usize + 8 * h.num_thms as usize;
+        // The data of the first entry in each table comes right after the table.
+        let tables = [("term", h.num_terms, h.terms_start as usize, terms_end), ("theorem", h.num_thms, h.thms_start as usize, thms_end)];
+        for (table, num, start, end) in tables.iter() {
+            if *num == 0 {
+                continue
+            }
+            if let Some(data) = self.args_ptr(*start).ok().filter(|pos| *pos as usize != *end) {
+                errs.push(strict_err(*end, format!("the first {} entry's data is at {} instead of right after the {} table", table, data, table)));
+            }
+        }
+    }
+
+    fn check_backrefs(&self, errs: &mut Vec<VerifErr>) {
+        let mmb = self.mmb();
+        let mut check_stream = |mut pos: usize, end: Option<usize>, refs: &[u8]| {
+            while end.map(|end| pos < end).unwrap_or(true) {
+                let (cmd, data, next) = match parse_cmd(mmb, pos) {
+                    Ok((0, ..)) | Err(_) => return,
+                    Ok(cmd) => cmd,
+                };
+                if refs.contains(&cmd) && next - pos != min_cmd_size(data) {
+                    errs.push(strict_err(pos, format!("backreference to {} uses a {} byte command", data, next - pos)));
+                }
+                pos = next;
+            }
+        };
+        let mut unify_streams: Vec<UnifyIter> = Vec::new();
+        for num in 0..self.header.num_terms {
+            if let Ok(term) = self.get_term_by_num(num) {
+                if term.is_def() {
+                    unify_streams.push(term.unify);
+                }
+            }
+        }
+        for num in 0..self.header.num_thms {
+            if let Ok(assert) = self.get_assert_by_num(num) {
+                unify_streams.push(assert.unify);
+            }
+        }
+        for unify in unify_streams.iter() {
+            check_stream(unify.pos, None, &[UNIFY_REF]);
+        }
+        for (_, proof) in self.declarations.iter() {
+            if !proof.is_null() {
+                check_stream(proof.pos, Some(proof.ends_at), &[PROOF_REF, PROOF_CONV_REF]);
+            }
+        }
+    }
+}
+
+#[test]
+fn strict1() {
+    use crate::testgen::Fixture;
+    use crate::util::ErrKind;
+    let strict_errs = |bytes: Vec<u8>| {
+        let file_data = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+        let outline = Outline::new_from(&file_data).unwrap();
+        assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+        let mut outline = Outline::new_from(&file_data).unwrap();
+        outline.options.strict = true;
+        let errs = crate::verify_serial(&outline, &outline.declarations);
+        assert!(errs.iter().all(|e| e.kind() == ErrKind::Strict));
+        errs.len()
+    };
+    let short = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    assert_eq!(strict_errs(short.mmb_file.clone()), 0);
+    assert_eq!(strict_errs(Fixture::valid().to_bytes()), 0);
+
+    let mut bytes = Fixture::valid().to_bytes();
+    bytes[28] = 1;
+    bytes.extend_from_slice(&[0, 0, 7]);
+    assert_eq!(strict_errs(bytes), 2);
+
+    // The theorem's proof with `Ref 0` written with an 8 bit data field.
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = vec![0x52, 0, 0x52, 1, 0x11, 0x52, 2, 0x52, 2, 0x10, 0x14, 0];
+    assert_eq!(strict_errs(fx.to_bytes()), 1);
+}
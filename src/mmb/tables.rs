@@ -0,0 +1,222 @@
+//! How term and theorem table entries are looked up. By default an entry is parsed each
+//! time it's looked up, and a bad entry is only noticed by the first proof that uses it.
+//! `load_tables` parses and checks every entry before any proof is checked, split over
+//! several threads, so a corrupt table is reported right away, and afterwards lookups
+//! are just indexing into a `Vec`. `cache_tables` is in between: each entry is parsed
+//! the first time it's looked up and kept, so memory goes only to the entries that some
+//! proof uses, and a bad entry is still only found when it's used.
+//!
+//! The checks are that the table entry and the arguments it points to lie inside the
+//! file, and the ones in `validate` on the sort data and each entry's argument types.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::{ Res, VerifErr, Term, Assert };
+use crate::mmb::unify::UnifyIter;
+use crate::none_err;
+
+#[derive(Debug, Clone, Default)]
+pub struct Tables<'a> {
+    pub terms: Vec<Term<'a>>,
+    pub asserts: Vec<Assert<'a>>,
+}
+
+/// A slot filled in by the first lookup. Without `std` there's only one thread.
+#[cfg(feature = "std")]
+pub type Once<A> = std::sync::OnceLock<A>;
+#[cfg(not(feature = "std"))]
+pub type Once<A> = core::cell::OnceCell<A>;
+
+/// Where a table entry's arguments are in the file, which is all a lookup needs to
+/// rebuild it. (Keeping the `Term` itself would tie the cache to the file's lifetime.)
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    /// The sort byte, for terms.
+    pub sort: u8,
+    /// Where the arguments end and the unify stream starts.
+    pub args_end: usize,
+    pub args_len: usize,
+}
+
+impl Entry {
+    fn of_term(term: &Term) -> Self {
+        Entry { sort: term.sort, args_end: term.unify.pos, args_len: term.args_start.len() }
+    }
+
+    fn of_assert(assert: &Assert) -> Self {
+        Entry { sort: 0, args_end: assert.unify.pos, args_len: assert.args_start.len() }
+    }
+
+    fn args<'a>(&self, mmb: &'a [u8]) -> Res<&'a [u8]> {
+        none_err!(mmb.get(self.args_end - self.args_len..self.args_end))
+    }
+}
+
+#[derive(Debug, Default)]
+pub enum TableCache<'a> {
+    /// Parse the entry on every lookup.
+    #[default]
+    Off,
+    /// Parse each entry on its first lookup, and keep it.
+    Lazy {
+        terms: Vec<Once<Entry>>,
+        asserts: Vec<Once<Entry>>,
+    },
+    /// Every entry, parsed and checked up front.
+    Eager(Tables<'a>),
+}
+
+/// `f` on `0..n`, in order, on up to `num_threads` threads.
+#[cfg(feature = "std")]
+fn map_par<T: Send>(n: u32, num_threads: usize, f: impl Fn(u32) -> Res<T> + Sync) -> Res<Vec<T>> {
+    if num_threads <= 1 || n < 2 {
+        return (0..n).map(f).collect()
+    }
+    let chunk = (n as usize).div_ceil(num_threads);
+    crossbeam_utils::thread::scope(|sco| {
+        let f = &f;
+        let tasks = (0..n as usize)
+            .step_by(chunk)
+            .map(|lo| sco.spawn(move |_| (lo..(lo + chunk).min(n as usize)).map(|i| f(i as u32)).collect::<Res<Vec<T>>>()))
+            .collect::<Vec<_>>();
+        let mut out = Vec::with_capacity(n as usize);
+        // Joining in order, so the error reported is the one for the lowest entry.
+        for task in tasks {
+            out.append(&mut task.join().map_err(|_| VerifErr::Msg("table thread panicked".to_string()))??);
+        }
+        Ok(out)
+    }).map_err(|_| VerifErr::Msg("table thread panicked".to_string()))?
+}
+
+#[cfg(not(feature = "std"))]
+fn map_par<T>(n: u32, _: usize, f: impl Fn(u32) -> Res<T>) -> Res<Vec<T>> {
+    (0..n).map(f).collect()
+}
+
+impl<'a> Outline<'a> {
+    pub(crate) fn lookup_term(&self, term_num: u32) -> Res<Term<'a>> {
+        match &self.tables {
+            TableCache::Off => self.parse_term(term_num),
+            TableCache::Lazy { terms, .. } => {
+                let slot = none_err!(terms.get(term_num as usize))?;
+                if let Some(e) = slot.get() {
+                    let unify = UnifyIter::new(self.mmb(), e.args_end);
+                    return Ok(Term { term_num, sort: e.sort, args_start: e.args(self.mmb())?, unify })
+                }
+                // Errors aren't kept; a bad entry fails again on every lookup.
+                let term = self.parse_term(term_num)?;
+                slot.get_or_init(|| Entry::of_term(&term));
+                Ok(term)
+            }
+            TableCache::Eager(tables) => none_err!(tables.terms.get(term_num as usize).copied()),
+        }
+    }
+
+    pub(crate) fn lookup_assert(&self, assert_num: u32) -> Res<Assert<'a>> {
+        match &self.tables {
+            TableCache::Off => self.parse_assert(assert_num),
+            TableCache::Lazy { asserts, .. } => {
+                let slot = none_err!(asserts.get(assert_num as usize))?;
+                if let Some(e) = slot.get() {
+                    let unify = UnifyIter::new(self.mmb(), e.args_end);
+                    return Ok(Assert { assert_num, args_start: e.args(self.mmb())?, unify })
+                }
+                let assert = self.parse_assert(assert_num)?;
+                slot.get_or_init(|| Entry::of_assert(&assert));
+                Ok(assert)
+            }
+            TableCache::Eager(tables) => none_err!(tables.asserts.get(assert_num as usize).copied()),
+        }
+    }
+
+    fn check_term(&self, term_num: u32) -> Res<Term<'a>> {
+        let term = self.parse_term(term_num)?;
+        self.validate_term(&term)?;
+        Ok(term)
+    }
+
+    fn check_assert(&self, assert_num: u32) -> Res<Assert<'a>> {
+        let assert = self.parse_assert(assert_num)?;
+        self.validate_assert(&assert)?;
+        Ok(assert)
+    }
+
+    /// Parse and check every entry of the term and theorem tables.
+    pub fn parse_tables(&self, num_threads: usize) -> Res<Tables<'a>> {
+        self.check_sort_data()?;
+        let terms = map_par(self.header.num_terms, num_threads, |n| {
+            self.check_term(n).map_err(|e| VerifErr::Msg(format!("bad term table entry {}: {:?}", n, e)))
+        })?;
+        let asserts = map_par(self.header.num_thms, num_threads, |n| {
+            self.check_assert(n).map_err(|e| VerifErr::Msg(format!("bad theorem table entry {}: {:?}", n, e)))
+        })?;
+        Ok(Tables { terms, asserts })
+    }
+
+    /// True once `load_tables` has checked every entry's types (see `validate`).
+    pub fn types_checked(&self) -> bool {
+        matches!(self.tables, TableCache::Eager(_))
+    }
+
+    /// `parse_tables`, keeping the result for lookups.
+    pub fn load_tables(&mut self, num_threads: usize) -> Res<()> {
+        let started = crate::options::clock();
+        self.tables = TableCache::Eager(self.parse_tables(num_threads)?);
+        self.phase_times.with(|t| t.tables += crate::options::since(started));
+        Ok(())
+    }
+
+    /// Keep each entry once it's been looked up.
+    pub fn cache_tables(&mut self) {
+        self.tables = TableCache::Lazy {
+            terms: (0..self.header.num_terms).map(|_| Once::new()).collect(),
+            asserts: (0..self.header.num_thms).map(|_| Once::new()).collect(),
+        };
+    }
+}
+
+#[test]
+fn tables1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    let lazy = (0..4).map(|n| outline.get_assert_by_num(n).unwrap().args_start.as_ptr()).collect::<Vec<_>>();
+    outline.load_tables(3).unwrap();
+    let tables = match &outline.tables {
+        TableCache::Eager(tables) => tables,
+        _ => panic!("tables not loaded"),
+    };
+    assert_eq!((tables.terms.len(), tables.asserts.len()), (2, 4));
+    assert_eq!(tables.asserts.iter().map(|a| a.args_start.as_ptr()).collect::<Vec<_>>(), lazy);
+    assert_eq!(outline.get_term_by_num(1).unwrap().term_num, 1);
+    assert!(outline.get_term_by_num(2).is_err());
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    outline.cache_tables();
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+    if let TableCache::Lazy { terms, asserts } = &outline.tables {
+        // Every entry was looked up while verifying.
+        assert!(asserts.iter().all(|a| a.get().is_some()));
+        assert!(terms.iter().all(|t| t.get().is_some()));
+    }
+    assert_eq!(outline.get_assert_by_num(3).unwrap().args_start.as_ptr(), lazy[3]);
+    assert!(outline.get_assert_by_num(4).is_err());
+
+    let fx = crate::testgen::Fixture::valid();
+    let good = fx.to_bytes();
+    let terms_start = crate::mmb::parse_header(&good).unwrap().terms_start as usize;
+    // The term's arguments start past the end of the file.
+    let mut bytes = good.clone();
+    bytes[terms_start + 4..terms_start + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+    let file_data = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(outline.parse_tables(2).is_err());
+    // Its first argument has sort 5 of 1.
+    let mut bytes = good;
+    let args_at = crate::util::parse_u32(&bytes[terms_start + 4..]).unwrap().0 as usize;
+    bytes[args_at + 7] = 5;
+    let file_data = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let outline = Outline::new_from(&file_data).unwrap();
+    let e = outline.parse_tables(1).unwrap_err();
+    assert!(format!("{:?}", e).contains("bad term table entry 0"));
+}
@@ -0,0 +1,190 @@
+//! Step-by-step traces of the verifier's stack machine, for learning how an mmb proof
+//! runs: after every proof and unify command, the proof stack, the heap, and the unify
+//! machine's stack, heap, and hypothesis stack, as s-expressions (or in the mm0 file's
+//! notation, when the outline has it).
+//!
+//! Steps are listed in the order they finish. A `Thm` unifies its target with the
+//! assertion's statement before it's done, so its unify steps come just before it; the
+//! unify steps at the end check the declaration's own statement.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::{ MmbState, MmbItem };
+use crate::mmb::proof::{ ProofCmd, ProofIter };
+use crate::mmb::unify::UnifyCmd;
+use crate::mmb::visit::ProofVisitor;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Proof,
+    Unify,
+}
+
+/// The machine's state after one command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub phase: Phase,
+    /// The command, as the disassembler writes it.
+    pub cmd: String,
+    /// What went wrong, if the command failed. That's the last step.
+    pub error: Option<String>,
+    pub stack: Vec<String>,
+    pub heap: Vec<String>,
+    pub ustack: Vec<String>,
+    pub uheap: Vec<String>,
+    pub hstack: Vec<String>,
+}
+
+/// Snapshots the machine after every command.
+#[derive(Default)]
+struct Tracer {
+    steps: Vec<Snapshot>,
+}
+
+impl Tracer {
+    fn snapshot(&mut self, state: &MmbState, phase: Phase, cmd: String, res: &Res<()>) {
+        let show = |items: &[&MmbItem]| items.iter().map(|e| state.outline.item_str(e)).collect();
+        self.steps.push(Snapshot {
+            phase,
+            cmd,
+            error: res.as_ref().err().map(|e| format!("{:?}", e)),
+            stack: show(&state.stack),
+            heap: show(&state.heap),
+            ustack: show(&state.ustack),
+            uheap: show(&state.uheap),
+            hstack: show(state.hstack.as_slice()),
+        });
+    }
+}
+
+impl ProofVisitor for Tracer {
+    fn proof_cmd(&mut self, state: &MmbState, cmd: ProofCmd, res: &Res<()>) {
+        self.snapshot(state, Phase::Proof, state.outline.proof_text(cmd), res)
+    }
+
+    fn unify_cmd(&mut self, state: &MmbState, cmd: UnifyCmd, res: &Res<()>) {
+        self.snapshot(state, Phase::Unify, state.outline.unify_text(cmd), res)
+    }
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+impl<'a> Outline<'a> {
+    /// Verify axiom or theorem `stmt`, recording every step. The trace ends early if
+    /// verification fails.
+    pub fn trace(&'a self, bump: &mut Bump, stmt: StmtCmd, proof: ProofIter<'a>) -> (Vec<Snapshot>, Res<()>) {
+        let num = match stmt {
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => num,
+            _ => return (Vec::new(), Err(VerifErr::Msg(format!("only axioms and theorems can be traced, not {:?}", stmt)))),
+        };
+        if let Err(e) = none_err!(num) {
+            return (Vec::new(), Err(e))
+        }
+        let mut tracer = Tracer::default();
+        let res = self.visit(bump, stmt, proof, &mut tracer);
+        (tracer.steps, res)
+    }
+}
+
+/// A trace as text: one block per step, with unify steps indented.
+pub fn trace_text(steps: &[Snapshot]) -> String {
+    let mut out = String::new();
+    for (i, step) in steps.iter().enumerate() {
+        let indent = if step.phase == Phase::Unify { "  " } else { "" };
+        out.push_str(&format!("[{}] {}{}\n", i + 1, indent, step.cmd));
+        let rows = [("stack", &step.stack), ("heap", &step.heap), ("ustack", &step.ustack), ("uheap", &step.uheap), ("hstack", &step.hstack)];
+        for (label, items) in rows.iter() {
+            // The unify machine's parts are only shown while they're in use.
+            if items.is_empty() && !matches!(*label, "stack" | "heap") {
+                continue
+            }
+            let line = format!("    {}{:<7} {}", indent, format!("{}:", label), items.join(" | "));
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        if let Some(e) = &step.error {
+            out.push_str(&format!("    {}error: {}\n", indent, e));
+        }
+    }
+    out
+}
+
+/// A trace as a standalone HTML page with a table row per step.
+pub fn trace_html(title: &str, steps: &[Snapshot]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+        body {{ font-family: sans-serif; }}\n\
+        table {{ border-collapse: collapse; }}\n\
+        td, th {{ border: 1px solid #ccc; padding: 2px 6px; vertical-align: top; font-family: monospace; }}\n\
+        tr.unify {{ background: #f4f4ff; }}\n\
+        tr.error {{ background: #ffe0e0; }}\n\
+        </style>\n</head>\n<body>\n<h1>{}</h1>\n<table>\n\
+        <tr><th>#</th><th>command</th><th>stack</th><th>heap</th><th>ustack</th><th>uheap</th><th>hstack</th></tr>\n",
+        escape(title),
+        escape(title)
+    );
+    for (i, step) in steps.iter().enumerate() {
+        let class = match (&step.error, step.phase) {
+            (Some(_), _) => "error",
+            (None, Phase::Unify) => "unify",
+            (None, Phase::Proof) => "proof",
+        };
+        let mut cmd = escape(&step.cmd);
+        if let Some(e) = &step.error {
+            cmd.push_str(&format!("<br>{}", escape(e)));
+        }
+        out.push_str(&format!("<tr class=\"{}\"><td>{}</td><td>{}</td>", class, i + 1, cmd));
+        for items in [&step.stack, &step.heap, &step.ustack, &step.uheap, &step.hstack].iter() {
+            out.push_str(&format!("<td>{}</td>", items.iter().map(|e| escape(e)).collect::<Vec<_>>().join("<br>")));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+#[test]
+fn trace1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF };
+    let fx = Fixture::valid();
+    let mut bump = Bump::new();
+    let (steps, res) = fx.with_outline(|outline| {
+        let (stmt, proof) = outline.declarations[3];
+        outline.trace(&mut bump, stmt, proof)
+    });
+    assert!(res.is_ok());
+    // Seven proof commands, the three of `a0`'s statement, and the seven of `a1`'s.
+    assert_eq!(steps.len(), 7 + 3 + 7);
+    assert_eq!((steps[0].phase, steps[0].cmd.as_str()), (Phase::Proof, "Ref 0"));
+    assert_eq!(steps[0].stack, vec!["v0"]);
+    assert_eq!(steps[2].heap, vec!["v0", "v1", "(t0 v0 v1)"]);
+    assert_eq!(steps[7].phase, Phase::Unify);
+    assert_eq!((steps[9].phase, steps[9].cmd.as_str()), (Phase::Proof, "Thm #0"));
+    assert_eq!(steps[9].stack, vec!["|- (t0 (t0 v0 v1) (t0 v0 v1))"]);
+    assert!(steps.iter().all(|s| s.error.is_none()));
+    let text = trace_text(&steps);
+    assert!(text.starts_with("[1] Ref 0\n    stack:  v0\n    heap:   v0 | v1\n"));
+    assert!(trace_html("a1", &steps).contains("<tr class=\"unify\"><td>8</td>"));
+
+    // With `a1` claiming `t0 (t0 a b) (t0 b a)`, the last unify step fails.
+    let mut fx = Fixture::valid();
+    fx.asserts[1].unify = stream(&[
+        (UNIFY_TERM, 0),
+        (UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1),
+        (UNIFY_TERM, 0), (UNIFY_REF, 1), (UNIFY_REF, 0),
+    ]);
+    let (steps, res) = fx.with_outline(|outline| {
+        let (stmt, proof) = outline.declarations[3];
+        outline.trace(&mut bump, stmt, proof)
+    });
+    assert!(res.is_err());
+    assert!(steps.last().unwrap().error.is_some());
+    assert!(steps[..steps.len() - 1].iter().all(|s| s.error.is_none()));
+}
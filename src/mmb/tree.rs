@@ -0,0 +1,202 @@
+//! Recording a theorem's proof as an explicit derivation tree while it's checked: each
+//! step is the application of an assertion to instantiated arguments and subproofs of its
+//! hypotheses, bottoming out in the theorem's own hypotheses. This is what a translation
+//! to another proof system works from.
+//!
+//! Nodes live in the declaration's arena, next to the expressions they point to.
+//! Conversion proofs are checked as usual but not recorded; a `Conv` node only says that
+//! its subproof was converted to a definitionally equal statement.
+
+use crate::prelude::*;
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::{ MmbState, MmbItem, MmbExpr };
+use crate::mmb::proof::ProofIter;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr, HashMap };
+use crate::none_err;
+use crate::mmz::Prec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofNode<'b> {
+    /// The theorem's hypothesis number `idx` (counting from 0), which says `stmt`.
+    Hyp {
+        idx: usize,
+        stmt: &'b MmbItem<'b>,
+    },
+    /// Assertion `thm_num` applied to `args`, with `hyps` proving its hypotheses in the
+    /// order they're declared in, proving `concl`.
+    Thm {
+        thm_num: u32,
+        args: &'b [&'b MmbItem<'b>],
+        hyps: &'b [&'b ProofNode<'b>],
+        concl: &'b MmbItem<'b>,
+    },
+    /// `proof` proves something that's convertible to `stmt`.
+    Conv {
+        stmt: &'b MmbItem<'b>,
+        proof: &'b ProofNode<'b>,
+    },
+}
+
+impl<'b> ProofNode<'b> {
+    /// The statement this node proves.
+    pub fn stmt(&self) -> &'b MmbItem<'b> {
+        match self {
+            ProofNode::Hyp { stmt, .. } | ProofNode::Conv { stmt, .. } => stmt,
+            ProofNode::Thm { concl, .. } => concl,
+        }
+    }
+
+    /// The subproofs this node is built from.
+    pub fn children(&self) -> &[&'b ProofNode<'b>] {
+        match self {
+            ProofNode::Hyp { .. } => &[],
+            ProofNode::Thm { hyps, .. } => hyps,
+            ProofNode::Conv { proof, .. } => core::slice::from_ref(proof),
+        }
+    }
+
+    /// The number of nodes in the tree, counting shared subproofs once per use.
+    pub fn size(&self) -> usize {
+        1 + self.children().iter().map(|c| c.size()).sum::<usize>()
+    }
+}
+
+/// What `MmbState` needs to build the tree as it goes: the node for every proof on the
+/// stack or heap, keyed by the address of its `MmbItem::Proof`.
+#[derive(Debug, Default)]
+pub struct TreeRecorder<'b> {
+    nodes: HashMap<usize, &'b ProofNode<'b>>,
+    /// Proofs popped by `UHyp` while applying the current assertion, last hypothesis first.
+    pub(crate) consumed: Vec<&'b MmbItem<'b>>,
+    /// The proof of the theorem's conclusion, once it's been checked.
+    pub root: Option<&'b ProofNode<'b>>,
+}
+
+impl<'b> TreeRecorder<'b> {
+    pub(crate) fn insert(&mut self, proof: &'b MmbItem<'b>, node: &'b ProofNode<'b>) {
+        self.nodes.insert(proof as *const _ as usize, node);
+    }
+
+    pub(crate) fn get(&self, proof: &'b MmbItem<'b>) -> Res<&'b ProofNode<'b>> {
+        none_err!(self.nodes.get(&(proof as *const _ as usize)).copied())
+    }
+}
+
+impl<'b, 'a: 'b> MmbState<'b, 'a> {
+    pub(crate) fn record_node(&mut self, proof: &'b MmbItem<'b>, node: ProofNode<'b>) {
+        let node = &*self.bump.alloc(node);
+        if let Some(tree) = self.tree.as_mut() {
+            tree.insert(proof, node);
+        }
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Verify theorem `stmt` and return its proof as a tree, allocated in `bump`.
+    pub fn proof_tree<'b>(&'a self, bump: &'b mut Bump, stmt: StmtCmd, proof: ProofIter<'a>) -> Res<&'b ProofNode<'b>> where 'a: 'b {
+        let num = match stmt {
+            StmtCmd::Thm { num, .. } => none_err!(num)?,
+            _ => return Err(VerifErr::Msg(format!("only theorems have proof trees, not {:?}", stmt))),
+        };
+        let assert = self.get_assert_by_num(num)?;
+        let mut st = MmbState::new_from(self, bump, stmt);
+        st.tree = Some(TreeRecorder::default());
+        st.verify_assert(stmt, assert, proof)?;
+        none_err!(st.tree.and_then(|t| t.root))
+    }
+
+    /// An expression as an s-expression, with variables (arguments and dummies alike)
+    /// named `v<i>` after their place on the heap.
+    pub fn item_sexpr(&self, item: &MmbItem) -> String {
+        match item {
+            MmbItem::Expr(MmbExpr::Var { idx, .. }) => format!("v{}", idx),
+            MmbItem::Expr(MmbExpr::App { term_num, args, .. }) => {
+                let name = self.term_name(*term_num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", term_num));
+                if args.is_empty() {
+                    return name
+                }
+                let args = args.iter().map(|a| self.item_sexpr(a)).collect::<Vec<_>>();
+                format!("({} {})", name, args.join(" "))
+            }
+            MmbItem::Proof(e) => format!("|- {}", self.item_sexpr(e)),
+            MmbItem::Conv(l, r) => format!("{} = {}", self.item_sexpr(l), self.item_sexpr(r)),
+            MmbItem::CoConv(l, r) => format!("{} =?= {}", self.item_sexpr(l), self.item_sexpr(r)),
+        }
+    }
+
+    /// `item_sexpr`, but in the mm0 file's notation when `notations` is set.
+    pub fn item_str(&self, item: &MmbItem) -> String {
+        match item {
+            MmbItem::Expr(_) => self.expr_str(item).0,
+            MmbItem::Proof(e) => format!("|- {}", self.item_str(e)),
+            MmbItem::Conv(l, r) => format!("{} = {}", self.item_str(l), self.item_str(r)),
+            MmbItem::CoConv(l, r) => format!("{} =?= {}", self.item_str(l), self.item_str(r)),
+        }
+    }
+
+    fn expr_str(&self, item: &MmbItem) -> (String, Prec) {
+        match item {
+            MmbItem::Expr(MmbExpr::Var { idx, .. }) => (format!("v{}", idx), Prec::Max),
+            MmbItem::Expr(MmbExpr::App { term_num, args, .. }) => {
+                let name = self.term_name(*term_num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", term_num));
+                self.show_app(*term_num, name, args.iter().map(|a| self.expr_str(a)).collect())
+            }
+            _ => (self.item_str(item), Prec::Max),
+        }
+    }
+
+    /// A proof tree as an s-expression: `h<i>` for hypotheses, `(thm args... subproofs...)`
+    /// for applications, and `(:conv stmt subproof)` for conversions.
+    pub fn tree_sexpr(&self, node: &ProofNode) -> String {
+        match node {
+            ProofNode::Hyp { idx, .. } => format!("h{}", idx),
+            ProofNode::Thm { thm_num, args, hyps, .. } => {
+                let mut out = format!("({}", self.assert_name(*thm_num).map(|s| s.to_string()).unwrap_or_else(|| format!("a{}", thm_num)));
+                for a in args.iter() {
+                    out.push(' ');
+                    out.push_str(&self.item_sexpr(a));
+                }
+                for h in hyps.iter() {
+                    out.push(' ');
+                    out.push_str(&self.tree_sexpr(h));
+                }
+                out.push(')');
+                out
+            }
+            ProofNode::Conv { stmt, proof } => format!("(:conv {} {})", self.item_sexpr(stmt), self.tree_sexpr(proof)),
+        }
+    }
+}
+
+#[test]
+fn proof_tree1() {
+    use crate::testgen::{ Fixture, AssertSpec, stream, reg };
+    use crate::mmb::stmt::STMT_THM;
+    use crate::mmb::proof::{ PROOF_REF, PROOF_HYP };
+    use crate::mmb::unify::{ UNIFY_REF, UNIFY_HYP };
+    let mut fx = Fixture::valid();
+    // `h (a: wff) (h: $ a $): $ a $`, proved by its hypothesis.
+    fx.asserts.push(AssertSpec { args: vec![reg(0, 0)], unify: stream(&[(UNIFY_REF, 0), (UNIFY_HYP, 0), (UNIFY_REF, 0)]) });
+    fx.stmts.push((STMT_THM, stream(&[(PROOF_REF, 0), (PROOF_HYP, 0), (PROOF_REF, 1)])));
+    fx.with_outline(|outline| {
+        assert!(crate::verify_serial(outline, &outline.declarations).is_empty());
+
+        let mut bump = Bump::new();
+        let (stmt, proof) = outline.declarations[3];
+        let tree = outline.proof_tree(&mut bump, stmt, proof).unwrap();
+        assert!(matches!(tree, ProofNode::Thm { thm_num: 0, hyps: [], .. }));
+        assert_eq!(outline.tree_sexpr(tree), "(a0 (t0 v0 v1))");
+        assert_eq!(outline.item_sexpr(tree.stmt()), "(t0 (t0 v0 v1) (t0 v0 v1))");
+
+        let mut bump = Bump::new();
+        let (stmt, proof) = outline.declarations[4];
+        let tree = outline.proof_tree(&mut bump, stmt, proof).unwrap();
+        assert!(matches!(tree, ProofNode::Hyp { idx: 0, .. }));
+        assert_eq!(tree.size(), 1);
+
+        let (stmt, proof) = outline.declarations[2];
+        assert!(outline.proof_tree(&mut bump, stmt, proof).is_err());
+    });
+}
@@ -0,0 +1,65 @@
+//! Everything an mmb file takes on faith, and nothing else: its sorts with their
+//! modifiers, and its axioms with their statements. Whoever decides whether to trust a
+//! development has to read exactly these; the theorems are what the verifier is for.
+//!```text
+//! strict provable sort wff;
+//! axiom ax_mp (v0: wff) (v1: wff): (im v0 v1) > v0 > v1;
+//!```
+//! Statements are written as in `dump_defs`, with the hypotheses before the conclusion.
+//! Nothing is verified, so this is only what the file claims until it has been checked.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::sort_mods::SortMod;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::Res;
+
+impl<'a> Outline<'a> {
+    /// The sorts and axioms, one per line in mm0 syntax, in declaration order.
+    pub fn trust_report(&self) -> Res<String> {
+        let mut out = String::new();
+        for (stmt, _) in self.declarations.iter() {
+            let line = match *stmt {
+                StmtCmd::Sort { num: Some(num) } => self.sort_decl_str(num)?,
+                StmtCmd::Axiom { num: Some(num) } => self.assert_decl_str("axiom", num)?,
+                _ => continue,
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    /// Sort `num` with its modifiers, like `strict provable sort wff;`.
+    pub(crate) fn sort_decl_str(&self, num: u8) -> Res<String> {
+        let mods = self.get_sort_mods(num as usize)?.inner;
+        let mut line = String::new();
+        for m in SortMod::ALL.iter().filter(|m| mods & m.bit() != 0) {
+            line.push_str(m.name());
+            line.push(' ');
+        }
+        Ok(format!("{}sort {};", line, self.sort_str(num)))
+    }
+
+    /// Assertion `num` in mm0 syntax after `keyword` (`axiom` or `theorem`), without a proof.
+    pub(crate) fn assert_decl_str(&self, keyword: &str, num: u32) -> Res<String> {
+        let (binders, _) = self.mm0_binders(self.get_assert_by_num(num)?.args());
+        let (mut parts, concl) = self.assert_strs(num)?;
+        parts.push(concl);
+        let name = self.assert_name(num).map(|s| s.to_string()).unwrap_or_else(|| format!("a{}", num));
+        let parts = parts.into_iter().map(|p| self.math_str(p)).collect::<Vec<_>>();
+        Ok(format!("{} {}{}: {};", keyword, name, binders.concat(), parts.join(" > ")))
+    }
+}
+
+#[test]
+fn trust_report1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let report = outline.trust_report().unwrap();
+    let lines = report.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "strict provable sort wff;");
+    assert!(lines.contains(&"axiom ax_mp (v0: wff) (v1: wff): (im v0 v1) > v0 > v1;"), "{}", report);
+    // The theorems and terms are left out.
+    assert!(lines[1..].iter().all(|l| l.starts_with("axiom ")));
+}
@@ -1,3 +1,4 @@
+use crate::prelude::*;
 use bumpalo::collections::Vec as BumpVec;
 use crate::util::{
     VerifErr,
@@ -10,20 +11,15 @@ use crate::mmb::{
 };
 
 use crate::util::try_next_cmd;
+use crate::util::cmd::DATA_MASK;
+use crate::mmb::write::push_cmd;
+use crate::mmb::proof::{ Spanned, Shape };
+use crate::mmb::stmt::StmtCmd;
 use crate::none_err;
 use crate::make_sure;
 
 
-/// `UNIFY_TERM = 0x30`: See [`UnifyCmd`](super::UnifyCmd).
-pub const UNIFY_TERM: u8 = 0x30;
-/// `UNIFY_TERM_SAVE = 0x31`: See [`UnifyCmd`](super::UnifyCmd).
-pub const UNIFY_TERM_SAVE: u8 = 0x31;
-/// `UNIFY_REF = 0x32`: See [`UnifyCmd`](super::UnifyCmd).
-pub const UNIFY_REF: u8 = 0x32;
-/// `UNIFY_DUMMY = 0x33`: See [`UnifyCmd`](super::UnifyCmd).
-pub const UNIFY_DUMMY: u8 = 0x33;
-/// `UNIFY_HYP = 0x36`: See [`UnifyCmd`](super::UnifyCmd).
-pub const UNIFY_HYP: u8 = 0x36;
+pub use crate::mmb::format::{ UNIFY_TERM, UNIFY_TERM_SAVE, UNIFY_REF, UNIFY_DUMMY, UNIFY_HYP };
 
 /// Unify commands appear in the header data for a `def` or `axiom`/`theorem`.
 /// They are executed by the [`ProofCmd::Thm`] command in order to perform
@@ -40,7 +36,7 @@ pub const UNIFY_HYP: u8 = 0x36;
 ///   substitute for the variable names in the theorem being applied, but
 ///   it can be extended in order to support substitutions with sharing
 ///   as well as dummy variables.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnifyCmd {
     /// ```text
     /// UTerm t: S, (t e1 ... en) --> S, en, ..., e1
@@ -87,7 +83,7 @@ pub enum UnifyCmd {
     Hyp,
 }
 
-impl std::convert::TryFrom<(u8, u32)> for UnifyCmd {
+impl core::convert::TryFrom<(u8, u32)> for UnifyCmd {
     type Error = VerifErr;
     fn try_from((cmd, data): (u8, u32)) -> Result<Self, VerifErr> {
         Ok(match cmd {
@@ -112,6 +108,51 @@ impl std::convert::TryFrom<(u8, u32)> for UnifyCmd {
     }
 }
 
+/// A command with no data field, which means its data is 0.
+impl core::convert::TryFrom<u8> for UnifyCmd {
+    type Error = VerifErr;
+    fn try_from(cmd: u8) -> Result<Self, VerifErr> {
+        if cmd & DATA_MASK != 0 {
+            return Err(VerifErr::Msg(format!("unify command {:#04x} has a data field", cmd)))
+        }
+        UnifyCmd::try_from((cmd, 0))
+    }
+}
+
+impl UnifyCmd {
+    /// The command byte, without the data size bits.
+    pub fn opcode(self) -> u8 {
+        match self {
+            UnifyCmd::Term { save: false, .. } => UNIFY_TERM,
+            UnifyCmd::Term { save: true, .. } => UNIFY_TERM_SAVE,
+            UnifyCmd::Ref(_) => UNIFY_REF,
+            UnifyCmd::Dummy { .. } => UNIFY_DUMMY,
+            UnifyCmd::Hyp => UNIFY_HYP,
+        }
+    }
+
+    /// The data field: a term or sort number, a heap index, or 0.
+    pub fn data(self) -> u32 {
+        match self {
+            UnifyCmd::Term { term_num, .. } => term_num,
+            UnifyCmd::Ref(i) => i,
+            UnifyCmd::Dummy { sort_id } => sort_id as u32,
+            UnifyCmd::Hyp => 0,
+        }
+    }
+
+    /// Append the command, with the smallest data field that fits.
+    pub fn encode(self, out: &mut Vec<u8>) {
+        push_cmd(out, self.opcode(), self.data())
+    }
+
+    /// The command at `pos` in `bytes` and the position of the next one, or `None` if
+    /// it's the `End` of the stream.
+    pub fn decode(bytes: &[u8], pos: usize) -> Res<Option<(UnifyCmd, usize)>> {
+        try_next_cmd(bytes, pos)
+    }
+}
+
 /// An iterator over a unify command stream.
 #[derive(Debug, Clone, Copy)]
 pub struct UnifyIter<'a> {
@@ -119,13 +160,54 @@ pub struct UnifyIter<'a> {
     pub buf: &'a [u8],
     /// The index of the current declaration in the file.
     pub pos: usize,
+    /// Set once the stream has ended or failed to decode, after which there's nothing more.
+    done: bool,
 }
 
+impl<'a> UnifyIter<'a> {
+    /// The unify stream starting at `pos` in `buf`, up to its `End` command.
+    pub fn new(buf: &'a [u8], pos: usize) -> Self {
+        UnifyIter { buf, pos, done: false }
+    }
+
+    /// The commands with the offset each one starts at.
+    pub fn spanned(self) -> Spanned<Self> {
+        Spanned(self)
+    }
+
+    /// Check the stream without a target expression: every command decodes, `UDummy`
+    /// only appears in a def's stream and `UHyp` only in an assertion's, and every `URef`
+    /// is to something on the heap by then, which starts with the `args` arguments and
+    /// grows with each `UTermSave` and `UDummy`.
+    pub fn prescan(&self, decl: StmtCmd, args: usize) -> Res<Shape> {
+        let is_def = matches!(decl, StmtCmd::TermDef { .. });
+        let malformed = |pos, reason| VerifErr::MalformedUnify { decl, pos, reason };
+        let (mut shape, mut heap_len) = (Shape::default(), args);
+        for cmd in self.spanned() {
+            let (pos, cmd) = cmd.map_err(|_| malformed(self.pos, "a command doesn't decode, or the stream has no End".to_string()))?;
+            match cmd {
+                UnifyCmd::Ref(i) if i as usize >= heap_len =>
+                    return Err(malformed(pos, format!("URef {}, but the unify heap has {} entries", i, heap_len))),
+                UnifyCmd::Term { save: true, .. } => heap_len += 1,
+                UnifyCmd::Dummy { .. } if !is_def => return Err(malformed(pos, "UDummy in an assertion".to_string())),
+                UnifyCmd::Dummy { .. } => heap_len += 1,
+                UnifyCmd::Hyp if is_def => return Err(malformed(pos, "UHyp in a def".to_string())),
+                UnifyCmd::Hyp => shape.hyps += 1,
+                _ => {}
+            }
+            shape.cmds += 1;
+        }
+        Ok(shape)
+    }
+}
 
 impl<'a> Iterator for UnifyIter<'a> {
     type Item = Result<UnifyCmd, VerifErr>;
     fn next(&mut self) -> Option<Self::Item> {
-        match try_next_cmd(self.buf, self.pos) {
+        if self.done {
+            return None
+        }
+        let res = match try_next_cmd(self.buf, self.pos) {
             // err
             Err(e) => Some(Err(e)),
             // Exhausted
@@ -133,12 +215,39 @@ impl<'a> Iterator for UnifyIter<'a> {
             // next
             Ok(Some((stmt, rest))) => {
                 self.pos = rest;
-                Some(Ok(stmt))
+                return Some(Ok(stmt))
             }            
+        };
+        self.done = true;
+        res
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            // Every command is at least a byte; one that doesn't decode is an error instead.
+            (0, Some(self.buf.len().saturating_sub(self.pos).max(1)))
         }
     }
 }
 
+impl<'a> core::iter::FusedIterator for UnifyIter<'a> {}
+
+impl<'a> Iterator for Spanned<UnifyIter<'a>> {
+    type Item = Result<(usize, UnifyCmd), VerifErr>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.0.pos;
+        self.0.next().map(|res| res.map(|cmd| (pos, cmd)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<'a> core::iter::FusedIterator for Spanned<UnifyIter<'a>> {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UMode {
     UThm,
@@ -153,15 +262,52 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         unify: UnifyIter,
         tgt: &'b MmbItem<'b>,
     ) -> Res<()> {    
+        self.run_unify_counted(mode, unify, tgt, &mut 0)
+    }
+
+    /// `run_unify`, counting the `UHyp` commands it gets through in `hyps_seen`, so that
+    /// a failure can be pinned on the hypothesis being unified at the time.
+    pub fn run_unify_counted(
+        &mut self,
+        mode: UMode,
+        unify: UnifyIter,
+        tgt: &'b MmbItem<'b>,
+        hyps_seen: &mut usize,
+    ) -> Res<()> {
+        let started = self.unify_time.and_then(|_| crate::options::clock());
+        let res = self.run_unify_untimed(mode, unify, tgt, hyps_seen);
+        if let Some(time) = &mut self.unify_time {
+            *time += crate::options::since(started);
+        }
+        res
+    }
+
+    fn run_unify_untimed(
+        &mut self,
+        mode: UMode,
+        unify: UnifyIter,
+        tgt: &'b MmbItem<'b>,
+        hyps_seen: &mut usize,
+    ) -> Res<()> {
         self.ustack.push(tgt);
+        if let Some(paths) = &mut self.upaths {
+            paths.push(Vec::new());
+        }
 
         for maybe_cmd in unify {
-            match maybe_cmd? {
-                UnifyCmd::Ref(i) => self.unify_ref(i)?,
-                UnifyCmd::Term { term_num, save } => self.unify_term(term_num, save)?,
-                UnifyCmd::Dummy { sort_id } => self.unify_dummy(mode, sort_id)?,
-                UnifyCmd::Hyp => self.unify_hyp(mode)?,
-            }
+            self.tick()?;
+            let cmd = maybe_cmd?;
+            let res = match cmd {
+                UnifyCmd::Ref(i) => self.unify_ref(i),
+                UnifyCmd::Term { term_num, save } => self.unify_term(term_num, save),
+                UnifyCmd::Dummy { sort_id } => self.unify_dummy(mode, sort_id),
+                UnifyCmd::Hyp => {
+                    *hyps_seen += 1;
+                    self.unify_hyp(mode)
+                }
+            };
+            self.visit_unify(cmd, &res);
+            res?;
         }
 
         make_sure!(self.ustack.is_empty());
@@ -172,15 +318,43 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
     }
 
     fn unify_ref(&mut self, i: u32) -> Res<()> {
-        let heap_elem = none_err!(self.uheap.get(i as usize).copied())?;
+        let heap_elem = match self.uheap.get(i as usize) {
+            Some(item) => *item,
+            None => return Err(VerifErr::BadBackref { decl: self.stmt, index: i, heap_len: self.uheap.len() }),
+        };
         let ustack_elem = none_err!(self.ustack.pop())?;
-        if heap_elem != ustack_elem {
-            Err(VerifErr::Msg(format!("Bad unify ref")))
+        let path = self.upaths.as_mut().and_then(|paths| paths.pop());
+        if !self.same_expr(heap_elem, ustack_elem) {
+            let delta = path.and_then(|mut path| {
+                first_difference(heap_elem, ustack_elem, &mut path).map(|(expected, actual)| (path, expected, actual))
+            });
+            match delta {
+                Some((path, expected, actual)) => Err(self.unify_delta(path, self.head_str(expected), actual)),
+                None => Err(VerifErr::Msg("Bad unify ref".to_string()))
+            }
         } else {
             Ok(())
         }
     }
 
+    fn unify_delta(&self, path: Vec<u32>, expected: String, actual: &MmbItem) -> VerifErr {
+        VerifErr::UnifyDelta { path, expected, actual: self.head_str(actual) }
+    }
+
+    /// The head of an expression: its term's name, or `v<i>` for a variable.
+    fn head_str(&self, item: &MmbItem) -> String {
+        match item {
+            MmbItem::Expr(MmbExpr::Var { idx, .. }) => format!("v{}", idx),
+            MmbItem::Expr(MmbExpr::App { term_num, .. }) => self.term_str(*term_num),
+            MmbItem::Proof(_) => "a proof".to_string(),
+            MmbItem::Conv(..) | MmbItem::CoConv(..) => "a conversion".to_string(),
+        }
+    }
+
+    fn term_str(&self, term_num: u32) -> String {
+        self.outline.term_name(term_num).map(|s| s.to_string()).unwrap_or_else(|| format!("t{}", term_num))
+    }
+
     pub fn push_ustack_rev_mmb(&mut self, items: &'b BumpVec<'b, &'b MmbItem<'b>>) {
         for elem in items.iter().rev() {
             self.ustack.push(elem)
@@ -192,12 +366,24 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         term_num: u32,
         save: bool
     ) -> Res<()> {
+        self.check_term_ref(term_num)?;
         let p = none_err!(self.ustack.pop())?;
+        let path = self.upaths.as_mut().and_then(|paths| paths.pop());
+        if let Some(path) = path.as_ref().filter(|_| !matches!(p, MmbItem::Expr(MmbExpr::App { term_num: id2, .. }) if *id2 == term_num)) {
+            return Err(self.unify_delta(path.clone(), self.term_str(term_num), p))
+        }
         if let MmbItem::Expr(MmbExpr::App { term_num:id2, args, .. }) = p {
             make_sure!(term_num == *id2);
             for arg in args.iter().rev() {
                 self.ustack.push(arg)
             }
+            if let (Some(paths), Some(path)) = (&mut self.upaths, path) {
+                for i in (0..args.len() as u32).rev() {
+                    let mut child = path.clone();
+                    child.push(i);
+                    paths.push(child);
+                }
+            }
             if save {
                 self.uheap.push(p)
             }
@@ -213,7 +399,10 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
         sort_id: u8,
     ) -> Res<()> {
         make_sure!(mode == UMode::UDef);
-        let p = self.ustack.pop().unwrap();
+        let p = none_err!(self.ustack.pop())?;
+        if let Some(paths) = &mut self.upaths {
+            paths.pop();
+        }
         if let MmbItem::Expr(MmbExpr::Var { ty, .. }) = p {
             make_sure!(sort_id == ty.sort());
             // assert that ty is bound, and get its bv idx (0-55);
@@ -221,7 +410,7 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
             // ty has no dependencies
             for heap_elem in self.uheap.iter() {
                 let ty = heap_elem.get_ty().unwrap();
-                make_sure!(ty.inner & bound_idx == 0);
+                make_sure!(ty.var_bits() & bound_idx == 0);
             }
 
             Ok(self.uheap.push(p))
@@ -232,15 +421,22 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
 
     fn unify_hyp(&mut self, mode: UMode) -> Res<()> {
         if let UMode::UThm = mode {
-            let proof = none_err!(self.stack.pop())?;
-            if let MmbItem::Proof(e) = proof {
-                Ok(self.ustack.push(e))
-            } else {
-                return Err(VerifErr::Unreachable(file!(), line!()));
+            let proof = self.pop()?;
+            if let Some(paths) = &mut self.upaths {
+                paths.push(Vec::new());
+            }
+            if let Some(tree) = self.tree.as_mut() {
+                tree.consumed.push(proof);
             }
+            let e = self.expect_proof(proof)?;
+            self.ustack.push(e);
+            Ok(())
         } else if let UMode::UThmEnd = mode {
             make_sure!(self.ustack.is_empty());
-            let elem = self.hstack.pop().unwrap();
+            let elem = none_err!(self.hstack.pop())?;
+            if let Some(paths) = &mut self.upaths {
+                paths.push(Vec::new());
+            }
             Ok(self.ustack.push(elem))
         } else {
             return Err(VerifErr::Unreachable(file!(), line!()));
@@ -248,3 +444,60 @@ impl<'b, 'a: 'b> MmbState<'b, 'a> {
     }    
 
 }
+
+/// Where `expected` and `actual` first differ, extending `path` with the argument
+/// positions leading there, and the two subterms found at that point. `None` if they're equal.
+fn first_difference<'b>(expected: &'b MmbItem<'b>, actual: &'b MmbItem<'b>, path: &mut Vec<u32>) -> Option<(&'b MmbItem<'b>, &'b MmbItem<'b>)> {
+    if expected == actual {
+        return None
+    }
+    if let (
+        MmbItem::Expr(MmbExpr::App { term_num: t1, args: args1, .. }),
+        MmbItem::Expr(MmbExpr::App { term_num: t2, args: args2, .. }),
+    ) = (expected, actual) {
+        if t1 == t2 && args1.len() == args2.len() {
+            if let Some(i) = args1.iter().zip(args2.iter()).position(|(x, y)| x != y) {
+                path.push(i as u32);
+                return first_difference(args1[i], args2[i], path)
+            }
+        }
+    }
+    Some((expected, actual))
+}
+
+#[test]
+fn unify_delta1() {
+    use crate::util::ErrKind;
+    let case = crate::testgen::corpus().into_iter().find(|c| c.name == "wrong_concl").unwrap();
+    let file_data = crate::fs::FileData::new_from_bytes(case.mmb, Vec::new());
+    let mut outline = crate::Outline::new_from(&file_data).unwrap();
+    outline.options.unify_delta = true;
+    let errs = crate::verify_serial(&outline, &outline.declarations);
+    // `th` proves `im (im a a) (im a a)` where it claims `im (im a b) (im a b)`.
+    match errs.first() {
+        Some(VerifErr::UnifyDelta { path, expected, actual }) => {
+            assert_eq!(path, &[0, 1]);
+            assert_eq!((expected.as_str(), actual.as_str()), ("v1", "v0"));
+        }
+        owise => panic!("expected a unify delta, got {:?}", owise),
+    }
+    assert_eq!(errs[0].kind(), ErrKind::UnifyDelta);
+}
+
+#[test]
+fn prescan1() {
+    let thm = StmtCmd::Thm { num: Some(0), local: false };
+    let def = StmtCmd::TermDef { num: Some(0), local: false };
+    let scan = |decl, cmds: &[(u8, u32)], args| UnifyIter::new(&crate::testgen::stream(cmds), 0).prescan(decl, args);
+    // `UTermSave 0, URef 1, UHyp, URef 0` with one argument.
+    let shape = scan(thm, &[(UNIFY_TERM_SAVE, 0), (UNIFY_REF, 1), (UNIFY_HYP, 0), (UNIFY_REF, 0)], 1).unwrap();
+    assert_eq!(shape, Shape { cmds: 4, hyps: 1 });
+    match scan(thm, &[(UNIFY_TERM, 0), (UNIFY_REF, 1)], 1) {
+        Err(VerifErr::MalformedUnify { pos, .. }) => assert_eq!(pos, 1),
+        owise => panic!("{:?}", owise),
+    }
+    assert!(scan(def, &[(UNIFY_DUMMY, 0), (UNIFY_REF, 2)], 2).is_ok());
+    assert!(scan(thm, &[(UNIFY_DUMMY, 0)], 2).is_err());
+    assert!(scan(def, &[(UNIFY_HYP, 0)], 2).is_err());
+    assert!(UnifyIter::new(&[UNIFY_REF], 0).prescan(thm, 1).is_err());
+}
@@ -0,0 +1,138 @@
+//! Bulk validation of the sort data and of the `Type` words in the term and theorem
+//! tables, checking up front everything about them that can be checked without running a
+//! proof:
+//!
+//! - every sort modifier byte has only the four defined bits;
+//! - every type has a sort the header declares;
+//! - the bound variables among an entry's arguments are numbered in order and don't have
+//!   strict sorts, every other argument depends only on bound variables before it, and a
+//!   term's return type isn't bound and depends only on its arguments.
+//!
+//! The per-word checks are branch-free over fixed-size chunks of words so the compiler
+//! can vectorize them; the dependency checks need each entry's bound variables in order,
+//! so they're a scalar pass over the same words. `load_tables` runs all of these, and once
+//! it has, the argument checks `MmbState::load_args` would make at the start of every
+//! def, axiom and theorem are skipped.
+
+use crate::prelude::*;
+use core::convert::TryInto;
+use crate::Outline;
+use crate::mmb::SORT_STRICT;
+use crate::util::{ Res, VerifErr, Type, Term, Assert };
+use crate::make_sure;
+use crate::none_err;
+
+pub use crate::mmb::format::SORT_MODS_MASK;
+
+/// How many words are checked at once.
+const LANES: usize = 8;
+
+fn word(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap_or([0xFF; 8]))
+}
+
+/// Check that every type in `words` (little-endian `u64`s) has a sort below `num_sorts`.
+pub fn check_type_words(words: &[u8], num_sorts: u8) -> Res<()> {
+    make_sure!(words.len().is_multiple_of(8));
+    let mut max_sort = [0u64; LANES];
+    let mut chunks = words.chunks_exact(8 * LANES);
+    for chunk in &mut chunks {
+        for (lane, w) in chunk.chunks_exact(8).enumerate() {
+            max_sort[lane] = max_sort[lane].max((word(w) >> 56) & 0x7F);
+        }
+    }
+    for (lane, w) in chunks.remainder().chunks_exact(8).enumerate() {
+        max_sort[lane] = max_sort[lane].max((word(w) >> 56) & 0x7F);
+    }
+    let max_sort = max_sort.iter().copied().max().unwrap_or(0);
+    // With no sorts at all, there can't be any types either.
+    make_sure!(words.is_empty() || max_sort < num_sorts as u64);
+    Ok(())
+}
+
+/// Check the bound variables and dependencies of one entry's arguments, returning the
+/// bound variables. `sort_mods` gives the modifiers of a sort.
+pub fn check_binders(args: impl IntoIterator<Item = Type>, sort_mods: impl Fn(u8) -> Res<u8>) -> Res<u64> {
+    let mut next_bv = 1u64;
+    for ty in args {
+        if ty.is_bound() {
+            make_sure!(ty.var_bits() == next_bv);
+            make_sure!(sort_mods(ty.sort())? & SORT_STRICT == 0);
+            next_bv <<= 1;
+        } else {
+            make_sure!(ty.var_bits() & !(next_bv - 1) == 0);
+        }
+    }
+    Ok(next_bv - 1)
+}
+
+impl<'a> Outline<'a> {
+    /// Check that every sort modifier byte has only defined bits.
+    pub fn check_sort_data(&self) -> Res<()> {
+        let start = self.header.sort_data_start as usize;
+        let sorts = none_err!(self.mmb().get(start..start + self.header.num_sorts as usize))?;
+        let extra = sorts.iter().fold(0, |acc, mods| acc | (mods & !SORT_MODS_MASK));
+        if extra != 0 {
+            let sort = none_err!(sorts.iter().position(|mods| mods & !SORT_MODS_MASK != 0))?;
+            return Err(VerifErr::Msg(format!("sort {} has undefined modifier bits {:#04x}", sort, sorts[sort])))
+        }
+        Ok(())
+    }
+
+    pub(crate) fn validate_term(&self, term: &Term) -> Res<()> {
+        make_sure!(term.sort() < self.header.num_sorts);
+        check_type_words(term.args_start, self.header.num_sorts)?;
+        let bvs = check_binders(term.args_no_ret(), |sort| Ok(self.get_sort_mods(sort as usize)?.inner))?;
+        let ret: Type = term.ret();
+        make_sure!(!ret.is_bound());
+        make_sure!(ret.var_bits() & !bvs == 0);
+        Ok(())
+    }
+
+    pub(crate) fn validate_assert(&self, assert: &Assert) -> Res<()> {
+        check_type_words(assert.args_start, self.header.num_sorts)?;
+        check_binders(assert.args(), |sort| Ok(self.get_sort_mods(sort as usize)?.inner))?;
+        Ok(())
+    }
+}
+
+#[test]
+fn validate1() {
+    use crate::testgen::{ Fixture, TermSpec, reg, bound };
+    let words = |tys: &[Type]| tys.iter().flat_map(|t| t.inner.to_le_bytes()).collect::<Vec<u8>>();
+    let many = (0..21).map(|n| reg(n % 3, 0)).collect::<Vec<_>>();
+    assert!(check_type_words(&words(&many), 3).is_ok());
+    assert!(check_type_words(&words(&many), 2).is_err());
+    for i in [0, 15, 20] {
+        let mut tys = many.clone();
+        tys[i] = reg(3, 0);
+        assert!(check_type_words(&words(&tys), 3).is_err());
+    }
+    // The last bound variable's bit is as good as any other.
+    assert!(check_type_words(&words(&[bound(1, 56), reg(0, 1 << 55)]), 3).is_ok());
+    assert!(check_type_words(&[], 0).is_ok());
+
+    let run = |fx: &Fixture| fx.with_outline(|outline| outline.load_tables(2).map(|_| outline.types_checked()));
+    assert!(run(&Fixture::valid()).unwrap());
+    // An undefined sort modifier.
+    let mut fx = Fixture::valid();
+    fx.sorts[0] |= 0x10;
+    assert!(run(&fx).is_err());
+    // `all (x: set) (p: wff x): wff`, with `x` numbered as the second bound variable.
+    let mut fx = Fixture::valid();
+    fx.sorts.push(0);
+    fx.terms.push(TermSpec { args: vec![bound(1, 2), reg(0, 1)], ret: reg(0, 0), def: None });
+    assert!(run(&fx).is_err());
+    // ... numbered right, but depending on a variable that comes later.
+    fx.terms[1].args = vec![reg(0, 1), bound(1, 1)];
+    assert!(run(&fx).is_err());
+    // ... with a return type depending on a variable it doesn't have.
+    fx.terms[1].args = vec![bound(1, 1), reg(0, 1)];
+    fx.terms[1].ret = reg(0, 2);
+    assert!(run(&fx).is_err());
+    fx.terms[1].ret = reg(0, 1);
+    assert!(run(&fx).is_ok());
+    // A bound variable of a strict sort.
+    fx.sorts[1] = SORT_STRICT;
+    assert!(run(&fx).is_err());
+}
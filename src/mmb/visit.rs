@@ -0,0 +1,101 @@
+//! Hooks into the verifier's stack machine, for tools that want to watch a proof run
+//! without changing the verifier: statistics, tracing, coverage and the like. A
+//! `ProofVisitor` is called after every proof and unify command with the machine's state
+//! and the command's result, and can look at anything in `MmbState`. `Outline::trace` is
+//! built this way.
+//!
+//! A visitor only watches. Verification carries on (or stops) exactly as it would without
+//! one, so a visitor can't make a bad proof pass.
+
+use bumpalo::Bump;
+use crate::Outline;
+use crate::mmb::MmbState;
+use crate::mmb::proof::{ ProofCmd, ProofIter };
+use crate::mmb::unify::UnifyCmd;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+pub trait ProofVisitor {
+    /// Called after each proof command, including one that failed, which is the last.
+    fn proof_cmd(&mut self, _state: &MmbState, _cmd: ProofCmd, _res: &Res<()>) {}
+
+    /// Called after each unify command, including one that failed, which is the last.
+    /// Unify commands run for each `Thm` step and once more at the end of a theorem or
+    /// def, to check its statement.
+    fn unify_cmd(&mut self, _state: &MmbState, _cmd: UnifyCmd, _res: &Res<()>) {}
+}
+
+impl<'b, 'a: 'b> MmbState<'b, 'a> {
+    pub(crate) fn visit_proof(&mut self, cmd: ProofCmd, res: &Res<()>) {
+        if let Some(visitor) = self.visitor.take() {
+            visitor.proof_cmd(self, cmd, res);
+            self.visitor = Some(visitor);
+        }
+    }
+
+    pub(crate) fn visit_unify(&mut self, cmd: UnifyCmd, res: &Res<()>) {
+        if let Some(visitor) = self.visitor.take() {
+            visitor.unify_cmd(self, cmd, res);
+            self.visitor = Some(visitor);
+        }
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Verify def, axiom or theorem `stmt` with `visitor` watching. Like `verify1`, but
+    /// the declaration isn't marked as done, so it can be visited again.
+    pub fn visit(&'a self, bump: &mut Bump, stmt: StmtCmd, proof: ProofIter<'a>, visitor: &mut dyn ProofVisitor) -> Res<()> {
+        match stmt {
+            StmtCmd::Sort { .. } => Ok(()),
+            StmtCmd::TermDef { num, .. } => {
+                let term = self.get_term_by_num(none_err!(num)?)?;
+                let mut st = MmbState::new_from(self, bump, stmt);
+                st.visitor = Some(visitor);
+                st.verify_termdef(stmt, term, proof)
+            }
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                let assert = self.get_assert_by_num(none_err!(num)?)?;
+                let mut st = MmbState::new_from(self, bump, stmt);
+                st.visitor = Some(visitor);
+                st.verify_assert(stmt, assert, proof)
+            }
+        }
+    }
+}
+
+#[test]
+fn visitor1() {
+    use crate::testgen::Fixture;
+    #[derive(Default)]
+    struct Counter {
+        thms: usize,
+        proof: usize,
+        unify: usize,
+        max_stack: usize,
+    }
+    impl ProofVisitor for Counter {
+        fn proof_cmd(&mut self, state: &MmbState, cmd: ProofCmd, _: &Res<()>) {
+            self.proof += 1;
+            if let ProofCmd::Thm { .. } = cmd {
+                self.thms += 1;
+            }
+            self.max_stack = self.max_stack.max(state.stack.len());
+        }
+        fn unify_cmd(&mut self, _: &MmbState, _: UnifyCmd, _: &Res<()>) {
+            self.unify += 1;
+        }
+    }
+    let fx = Fixture::valid();
+    fx.with_outline(|outline| {
+        let mut bump = Bump::new();
+        let mut counter = Counter::default();
+        let (stmt, proof) = outline.declarations[3];
+        outline.visit(&mut bump, stmt, proof, &mut counter).unwrap();
+        assert_eq!((counter.thms, counter.proof, counter.unify), (1, 7, 3 + 7));
+        assert_eq!(counter.max_stack, 3);
+        // Visiting again sees the same run.
+        outline.visit(&mut bump, stmt, proof, &mut counter).unwrap();
+        assert_eq!(counter.proof, 14);
+    });
+}
@@ -0,0 +1,172 @@
+//! Warnings: things that are legal but probably not what the author meant, found by looking
+//! over the whole file once it's been read. None of them affect what the file proves, so
+//! they're reported separately from errors, unless they're denied (`--deny warnings`).
+//!
+//! - a theorem whose proof never uses one of its hypotheses, which could be dropped;
+//! - a local theorem that no other proof uses, which nothing outside the file can see
+//!   either;
+//! - a sort that no term returns and no variable has;
+//! - a def with the same binders and body as an earlier one.
+//!
+//! Like the linter, this only reads the streams; it doesn't check the proofs.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofCmd;
+use crate::util::{ Res, VerifErr, HashMap };
+use crate::none_err;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Warning {
+    /// Hypothesis number `hyp` (from 0, in declaration order) of `thm` is never referred to
+    /// in its proof.
+    UnusedHyp { thm: StmtCmd, hyp: usize },
+    /// A local theorem that no proof uses.
+    UnusedThm { thm: StmtCmd },
+    /// A sort that no term returns and no binder has.
+    EmptySort { sort: u8 },
+    /// A def whose binders and body are the same as `same_as`'s.
+    DuplicateDef { def: u32, same_as: u32 },
+}
+
+impl Warning {
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::UnusedHyp {..} => "unused-hyp",
+            Warning::UnusedThm {..} => "unused-theorem",
+            Warning::EmptySort {..} => "empty-sort",
+            Warning::DuplicateDef {..} => "duplicate-def",
+        }
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Every warning about the file, in the order of the declarations they're about.
+    pub fn warnings(&self) -> Res<Vec<Warning>> {
+        let mut out = Vec::new();
+        let mut thm_used = vec![false; self.header.num_thms as usize];
+        let mut sort_used = vec![false; self.header.num_sorts as usize];
+        let mut defs: HashMap<Vec<u8>, u32> = HashMap::new();
+        for term_num in 0..self.header.num_terms {
+            let term = self.get_term_by_num(term_num)?;
+            *none_err!(sort_used.get_mut(term.sort() as usize))? = true;
+            for ty in term.args() {
+                *none_err!(sort_used.get_mut(ty.sort() as usize))? = true;
+            }
+            if term.is_def() {
+                let mut key = term.args_start.to_vec();
+                for cmd in term.unify() {
+                    cmd?.encode(&mut key);
+                }
+                if let Some(same_as) = defs.get(&key) {
+                    out.push(Warning::DuplicateDef { def: term_num, same_as: *same_as });
+                } else {
+                    defs.insert(key, term_num);
+                }
+            }
+        }
+        for thm_num in 0..self.header.num_thms {
+            for ty in self.get_assert_by_num(thm_num)?.args() {
+                *none_err!(sort_used.get_mut(ty.sort() as usize))? = true;
+            }
+        }
+        for (stmt, proof) in self.declarations.iter() {
+            let num_args = match stmt {
+                StmtCmd::Thm { num, .. } => self.get_assert_by_num(none_err!(*num)?)?.args().len(),
+                _ => 0,
+            };
+            // The heap index of each hypothesis, and whether it's used.
+            let mut hyps = Vec::new();
+            let mut heap_len = num_args;
+            for cmd in *proof {
+                match cmd? {
+                    ProofCmd::Thm { thm_num, save } => {
+                        *none_err!(thm_used.get_mut(thm_num as usize))? = true;
+                        heap_len += save as usize;
+                    }
+                    ProofCmd::Hyp => {
+                        hyps.push((heap_len, false));
+                        heap_len += 1;
+                    }
+                    ProofCmd::Ref(i) => {
+                        if let Some(h) = hyps.iter_mut().find(|(at, _)| *at == i as usize) {
+                            h.1 = true;
+                        }
+                    }
+                    ProofCmd::Term { save: true, .. } | ProofCmd::Dummy {..} | ProofCmd::Save | ProofCmd::ConvSave => heap_len += 1,
+                    _ => {}
+                }
+            }
+            if let StmtCmd::Thm {..} = stmt {
+                out.extend(hyps.iter().enumerate().filter(|(_, (_, used))| !used).map(|(hyp, _)| Warning::UnusedHyp { thm: *stmt, hyp }));
+            }
+        }
+        for (stmt, _) in self.declarations.iter() {
+            if let StmtCmd::Thm { num: Some(n), local: true } = stmt {
+                if !thm_used[*n as usize] {
+                    out.push(Warning::UnusedThm { thm: *stmt });
+                }
+            }
+        }
+        out.extend((0..self.header.num_sorts).filter(|s| !sort_used[*s as usize]).map(|sort| Warning::EmptySort { sort }));
+        Ok(out)
+    }
+
+    /// `w` as a sentence, with names from the index where there are any.
+    pub fn describe_warning(&self, w: Warning) -> String {
+        let stmt = |stmt: StmtCmd| self.stmt_name(stmt).map(|s| s.to_string()).unwrap_or_else(|| format!("{:?}", stmt));
+        let term = |n: u32| self.term_name(n).map(|s| s.to_string()).unwrap_or_else(|| format!("term {}", n));
+        match w {
+            Warning::UnusedHyp { thm, hyp } => format!("{}: hypothesis {} of {} is never used", w.name(), hyp, stmt(thm)),
+            Warning::UnusedThm { thm } => format!("{}: the local theorem {} is never used", w.name(), stmt(thm)),
+            Warning::EmptySort { sort } => format!(
+                "{}: the sort {} has no terms and no variables",
+                w.name(),
+                self.sort_name(sort).map(|s| s.to_string()).unwrap_or_else(|| format!("{}", sort))
+            ),
+            Warning::DuplicateDef { def, same_as } => format!("{}: {} is the same def as {}", w.name(), term(def), term(same_as)),
+        }
+    }
+
+    /// Every warning as a sentence: these, then the ones recorded while verifying. A file
+    /// too broken to look for these in has errors that say so.
+    pub fn all_warnings(&self) -> Vec<String> {
+        let mut out = self.warnings()
+            .map(|ws| ws.into_iter().map(|w| self.describe_warning(w)).collect())
+            .unwrap_or_else(|_| Vec::new());
+        out.extend(self.take_warnings());
+        out
+    }
+}
+
+#[test]
+fn warnings1() {
+    use crate::testgen::{ Fixture, AssertSpec, reg, stream };
+    use crate::mmb::stmt::{ STMT_SORT, STMT_DEF, STMT_LOCAL_THM };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM, PROOF_THM, PROOF_HYP };
+    use crate::mmb::unify::{ UNIFY_TERM, UNIFY_REF, UNIFY_HYP };
+    let warnings = |fx: &Fixture| fx.with_outline(|outline| {
+        assert!(crate::verify_serial(outline, &outline.declarations).is_empty());
+        outline.warnings().unwrap()
+    });
+    assert_eq!(warnings(&Fixture::valid()), vec![]);
+    let wff = reg(0, 0);
+    let mut fx = Fixture::valid();
+    // `(a b: wff) (h: b): |- im a a`, by `ax a`.
+    fx.asserts.push(AssertSpec {
+        args: vec![wff, wff],
+        unify: stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 0), (UNIFY_HYP, 0), (UNIFY_REF, 1)]),
+    });
+    fx.stmts.push((STMT_LOCAL_THM, stream(&[(PROOF_REF, 1), (PROOF_HYP, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0), (PROOF_THM, 0)])));
+    // A def the same as the other (which `unfolding` adds), and an unused sort.
+    let mut fx2 = Fixture::unfolding(1);
+    fx2.terms.push(fx2.terms[1].clone());
+    fx2.stmts.insert(3, (STMT_DEF, fx2.stmts[2].1.clone()));
+    fx2.sorts.push(0);
+    fx2.stmts.insert(1, (STMT_SORT, Vec::new()));
+    let thm = StmtCmd::Thm { num: Some(2), local: true };
+    assert_eq!(warnings(&fx), vec![Warning::UnusedHyp { thm, hyp: 0 }, Warning::UnusedThm { thm }]);
+    assert_eq!(warnings(&fx2), vec![Warning::DuplicateDef { def: 2, same_as: 1 }, Warning::EmptySort { sort: 1 }]);
+}
@@ -0,0 +1,300 @@
+//! Writing a new mmb file out of declarations taken from existing ones, renumbering the
+//! sorts, terms, and assertions they refer to along the way. This is what the slicer and
+//! the linker have in common; they only differ in which declarations they keep and how
+//! the numbers are assigned.
+//!
+//! The layout follows the one the mm1 compiler uses: the header, the sort data, the term
+//! and theorem tables, the (8 byte aligned) arguments and unify stream of each term and
+//! assertion, and then the proof stream.
+
+use crate::prelude::*;
+use core::convert::TryFrom;
+use crate::Outline;
+use crate::mmb::stmt::{ StmtCmd, STMT_SORT, STMT_AXIOM, STMT_DEF, STMT_THM, STMT_LOCAL };
+use crate::mmb::proof::{
+    PROOF_TERM, PROOF_TERM_SAVE, PROOF_THM, PROOF_THM_SAVE, PROOF_DUMMY,
+    PROOF_REF, PROOF_CONV_REF, PROOF_SAVE, PROOF_CONV_SAVE, PROOF_HYP,
+};
+use crate::mmb::unify::{ UNIFY_TERM, UNIFY_TERM_SAVE, UNIFY_DUMMY };
+use crate::mmb::index_gen::IndexNames;
+use crate::util::{ Res, Type, VerifErr, parse_cmd, parse_u64, cmd::{ DATA_8, DATA_16, DATA_32 } };
+use crate::none_err;
+use crate::make_sure;
+
+use crate::mmb::format::HEADER_SIZE;
+
+fn align8(out: &mut Vec<u8>) {
+    out.resize((out.len() + 7) & !7, 0);
+}
+
+/// The command and data field of a statement whose proof stream is `len` bytes long.
+/// A statement's data is its length including the command itself, so the size of the
+/// data field depends on the data.
+pub(crate) fn push_stmt(out: &mut Vec<u8>, cmd: u8, len: usize) -> Res<()> {
+    if len + 2 <= 0xFF {
+        out.push(cmd | DATA_8);
+        out.push((len + 2) as u8);
+    } else if len + 3 <= 0xFFFF {
+        out.push(cmd | DATA_16);
+        out.extend_from_slice(&((len + 3) as u16).to_le_bytes());
+    } else {
+        let total = u32::try_from(len + 5).map_err(|_| VerifErr::Msg("statement too long for the mmb format".to_string()))?;
+        out.push(cmd | DATA_32);
+        out.extend_from_slice(&total.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Write a command with the smallest data field that fits.
+pub(crate) use crate::mmb::format::encode_cmd as push_cmd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stream {
+    Unify,
+    Proof,
+}
+
+/// Old sort, term, and assertion numbers to new ones. Everything a kept declaration
+/// refers to needs a new number, whether or not it's declared by the same source.
+#[derive(Debug, Clone)]
+pub struct Renumbering {
+    pub sorts: Vec<Option<u8>>,
+    pub terms: Vec<Option<u32>>,
+    pub thms: Vec<Option<u32>>,
+}
+
+impl Renumbering {
+    /// Nothing mapped yet, with room for everything `outline` declares.
+    pub fn new_for(outline: &Outline) -> Self {
+        Renumbering {
+            sorts: vec![None; outline.header.num_sorts as usize],
+            terms: vec![None; outline.header.num_terms as usize],
+            thms: vec![None; outline.header.num_thms as usize],
+        }
+    }
+
+    fn sort(&self, old: u32) -> Res<u32> {
+        none_err!(self.sorts.get(old as usize).copied().flatten()).map(|n| n as u32)
+    }
+
+    fn term(&self, old: u32) -> Res<u32> {
+        none_err!(self.terms.get(old as usize).copied().flatten())
+    }
+
+    fn thm(&self, old: u32) -> Res<u32> {
+        none_err!(self.thms.get(old as usize).copied().flatten())
+    }
+
+    /// Copy argument types, changing the sort of each.
+    fn rewrite_args(&self, mut args: &[u8], out: &mut Vec<u8>) -> Res<()> {
+        while !args.is_empty() {
+            let (inner, rest) = parse_u64(args)?;
+            let mut ty = Type::from_bits(inner)?;
+            ty.add_sort(self.sort(ty.sort() as u32)? as u8);
+            out.extend_from_slice(&ty.bits().to_le_bytes());
+            args = rest;
+        }
+        Ok(())
+    }
+
+    /// The new data of a command that refers to a sort, term, or assertion.
+    fn renumber(&self, stream: Stream, cmd: u8, data: u32) -> Res<Option<u32>> {
+        match (stream, cmd) {
+            | (Stream::Unify, UNIFY_TERM)
+            | (Stream::Unify, UNIFY_TERM_SAVE)
+            | (Stream::Proof, PROOF_TERM)
+            | (Stream::Proof, PROOF_TERM_SAVE) => self.term(data).map(Some),
+            | (Stream::Proof, PROOF_THM)
+            | (Stream::Proof, PROOF_THM_SAVE) => self.thm(data).map(Some),
+            | (Stream::Unify, UNIFY_DUMMY)
+            | (Stream::Proof, PROOF_DUMMY) => self.sort(data).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Copy a command stream starting at `pos` up to and including its terminating 0,
+    /// renumbering the sorts, terms, and assertions it refers to.
+    fn rewrite(&self, mmb: &[u8], mut pos: usize, stream: Stream, out: &mut Vec<u8>) -> Res<()> {
+        loop {
+            let (cmd, data, next) = parse_cmd(mmb, pos)?;
+            match self.renumber(stream, cmd, data)? {
+                Some(data) if cmd != 0 => push_cmd(out, cmd, data),
+                _ => out.extend_from_slice(none_err!(mmb.get(pos..next))?),
+            }
+            if cmd == 0 {
+                return Ok(())
+            }
+            pos = next;
+        }
+    }
+
+    /// `rewrite` for a proof stream that starts with `num_args` arguments on the heap,
+    /// giving every command its shortest encoding and leaving out the saves at the
+    /// (sorted) offsets in `dropped`. Heap references are renumbered around the dropped
+    /// entries, which mustn't be referenced.
+    fn rewrite_compact(&self, mmb: &[u8], mut pos: usize, num_args: usize, dropped: &[usize], out: &mut Vec<u8>) -> Res<()> {
+        // The new index of each heap entry so far.
+        let mut heap: Vec<Option<u32>> = (0..num_args as u32).map(Some).collect();
+        let mut next_entry = num_args as u32;
+        loop {
+            let (cmd, data, next) = parse_cmd(mmb, pos)?;
+            let drop = dropped.binary_search(&pos).is_ok();
+            match cmd {
+                0 => {
+                    out.push(0);
+                    return Ok(())
+                }
+                PROOF_REF | PROOF_CONV_REF => push_cmd(out, cmd, none_err!(heap.get(data as usize).copied().flatten())?),
+                PROOF_SAVE if drop => {}
+                PROOF_TERM_SAVE if drop => push_cmd(out, PROOF_TERM, self.term(data)?),
+                PROOF_THM_SAVE if drop => push_cmd(out, PROOF_THM, self.thm(data)?),
+                _ => push_cmd(out, cmd, self.renumber(Stream::Proof, cmd, data)?.unwrap_or(data)),
+            }
+            if matches!(cmd, PROOF_SAVE | PROOF_TERM_SAVE | PROOF_THM_SAVE | PROOF_CONV_SAVE | PROOF_DUMMY | PROOF_HYP) {
+                if drop {
+                    heap.push(None);
+                } else {
+                    heap.push(Some(next_entry));
+                    next_entry += 1;
+                }
+            }
+            pos = next;
+        }
+    }
+}
+
+/// Some of the declarations of an existing file, and what their new numbers are.
+pub struct Source<'o, 'a> {
+    pub outline: &'o Outline<'a>,
+    /// Which of `outline.declarations` to write.
+    pub keep: Vec<bool>,
+    pub renum: Renumbering,
+    /// Re-encode the proof streams as compactly as possible: see `Outline::optimized`.
+    pub compact: bool,
+}
+
+impl<'o, 'a> Source<'o, 'a> {
+    fn kept(&self) -> impl Iterator<Item = &(StmtCmd, crate::mmb::proof::ProofIter<'a>)> {
+        self.outline.declarations.iter().zip(self.keep.iter()).filter(|(_, k)| **k).map(|(d, _)| d)
+    }
+}
+
+/// Write the kept declarations of each source, in order. The new numbers of the items
+/// declared have to count up from 0 in that order, so that each item is numbered by
+/// its position in the new file. If every source has an index, so does the new file.
+pub fn write_mmb(sources: &[Source]) -> Res<Vec<u8>> {
+    let first = none_err!(sources.first())?.outline;
+    // (source, old number) for each new sort, term, and assertion, in order.
+    let mut sorts = Vec::new();
+    let mut terms = Vec::new();
+    let mut thms = Vec::new();
+    for (src_idx, src) in sources.iter().enumerate() {
+        for (stmt, _) in src.kept() {
+            match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    make_sure!(src.renum.sort(num as u32)? == sorts.len() as u32);
+                    sorts.push((src_idx, num));
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    make_sure!(src.renum.term(num)? == terms.len() as u32);
+                    terms.push((src_idx, num));
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    make_sure!(src.renum.thm(num)? == thms.len() as u32);
+                    thms.push((src_idx, num));
+                }
+            }
+        }
+    }
+    let num_sorts = u8::try_from(sorts.len()).ok().filter(|n| *n <= 128)
+        .ok_or_else(|| VerifErr::Msg(format!("an mmb file can have at most 128 sorts; this one would have {}", sorts.len())))?;
+
+    // The header is filled in at the end, once we know where everything went.
+    let mut out = vec![0; HEADER_SIZE];
+    for (src_idx, num) in sorts.iter() {
+        out.push(sources[*src_idx].outline.get_sort_mods(*num as usize)?.inner);
+    }
+    align8(&mut out);
+
+    let terms_start = out.len();
+    out.resize(terms_start + 8 * terms.len(), 0);
+    let thms_start = out.len();
+    out.resize(thms_start + 8 * thms.len(), 0);
+
+    for (new_num, (src_idx, old_num)) in terms.iter().enumerate() {
+        let src = &sources[*src_idx];
+        let term = src.outline.get_term_by_num(*old_num)?;
+        align8(&mut out);
+        let args_at = out.len();
+        src.renum.rewrite_args(term.args_start, &mut out)?;
+        // Only defs have a unify stream after their arguments.
+        if term.is_def() {
+            src.renum.rewrite(src.outline.mmb(), term.unify.pos, Stream::Unify, &mut out)?;
+        }
+        let entry = terms_start + 8 * new_num;
+        out[entry..entry + 2].copy_from_slice(&term.num_args_no_ret().to_le_bytes());
+        out[entry + 2] = (term.sort & 0x80) | src.renum.sort(term.sort() as u32)? as u8;
+        out[entry + 4..entry + 8].copy_from_slice(&(args_at as u32).to_le_bytes());
+    }
+
+    for (new_num, (src_idx, old_num)) in thms.iter().enumerate() {
+        let src = &sources[*src_idx];
+        let assert = src.outline.get_assert_by_num(*old_num)?;
+        align8(&mut out);
+        let args_at = out.len();
+        src.renum.rewrite_args(assert.args_start, &mut out)?;
+        src.renum.rewrite(src.outline.mmb(), assert.unify.pos, Stream::Unify, &mut out)?;
+        let entry = thms_start + 8 * new_num;
+        out[entry..entry + 2].copy_from_slice(&assert.num_args().to_le_bytes());
+        out[entry + 4..entry + 8].copy_from_slice(&(args_at as u32).to_le_bytes());
+    }
+
+    let proof_stream_start = out.len();
+    let mut body = Vec::new();
+    for src in sources.iter() {
+        for (stmt, proof) in src.kept() {
+            body.clear();
+            if !proof.is_null() {
+                match (src.compact, src.outline.heap_args(*stmt)?) {
+                    (true, Some(num_args)) => {
+                        let dropped = src.outline.proof_coverage(*stmt, *proof, num_args)?.unused_saves;
+                        src.renum.rewrite_compact(src.outline.mmb(), proof.pos, num_args, &dropped, &mut body)?;
+                    }
+                    _ => src.renum.rewrite(src.outline.mmb(), proof.pos, Stream::Proof, &mut body)?,
+                }
+            }
+            let cmd = match *stmt {
+                StmtCmd::Sort {..} => STMT_SORT,
+                StmtCmd::TermDef { local, .. } => if local { STMT_DEF | STMT_LOCAL } else { STMT_DEF },
+                StmtCmd::Axiom {..} => STMT_AXIOM,
+                StmtCmd::Thm { local, .. } => if local { STMT_THM | STMT_LOCAL } else { STMT_THM },
+            };
+            push_stmt(&mut out, cmd, body.len())?;
+            out.extend_from_slice(&body);
+        }
+    }
+    out.push(0);
+
+    out[0..4].copy_from_slice(&first.header.magic.to_le_bytes());
+    out[4] = first.header.version;
+    out[5] = num_sorts;
+    out[8..12].copy_from_slice(&(terms.len() as u32).to_le_bytes());
+    out[12..16].copy_from_slice(&(thms.len() as u32).to_le_bytes());
+    out[16..20].copy_from_slice(&(terms_start as u32).to_le_bytes());
+    out[20..24].copy_from_slice(&(thms_start as u32).to_le_bytes());
+    out[24..28].copy_from_slice(&(proof_stream_start as u32).to_le_bytes());
+
+    if sources.iter().any(|src| src.outline.header.index_start == 0) {
+        return Ok(out)
+    }
+    let names = IndexNames {
+        sorts: sorts.iter().map(|(s, n)| sources[*s].outline.sort_name(*n).map(|s| s.to_string())).collect(),
+        terms: terms.iter().map(|(s, n)| sources[*s].outline.term_name(*n).map(|s| s.to_string())).collect(),
+        thms: thms.iter().map(|(s, n)| sources[*s].outline.assert_name(*n).map(|s| s.to_string())).collect(),
+    };
+    let file_data = crate::fs::FileData::new_from_bytes(out, Vec::new());
+    Outline::new_from(&file_data)?.with_new_index(&names)
+}
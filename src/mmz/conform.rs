@@ -0,0 +1,179 @@
+//! Keeping the mm0 file and the mmb file's public declarations in step. Each public mmb
+//! declaration is matched with the next declaration of the mm0 file, which has to have
+//! the same kind (a `def` isn't a `term`, an `axiom` isn't a `theorem`), the same name as
+//! the index gives (if there is an index), and the same statement. Local theorems and defs
+//! aren't matched with anything, so the mm0 file declaring one is an error too.
+//!
+//! When a statement doesn't match, its name is still registered and the mm0 file is
+//! picked up again after it, so that each problem is reported once, against the
+//! declaration it's about, instead of every later declaration failing with it.
+
+use crate::prelude::*;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmz::{ MmzMem, MmzState };
+use crate::mmz::parse::{ wc, ident_start, ident_rest };
+use crate::util::{ Res, Str, VerifErr, Mismatch };
+
+const SORT_MODS: [&[u8]; 4] = [b"pure", b"strict", b"provable", b"free"];
+
+impl<'a> MmzMem<'a> {
+    /// Skip whitespace and comments.
+    fn skip_space(&mut self) {
+        while let Some(c) = self.cur() {
+            if self.cur_slice().starts_with(b"--") {
+                while self.cur().map(|c| c != b'\n').unwrap_or(false) {
+                    self.advance(1)
+                }
+            } else if wc(c) {
+                self.advance(1)
+            } else {
+                break
+            }
+        }
+    }
+
+    fn word(&mut self) -> &'a [u8] {
+        self.skip_space();
+        let start = self.mmz_pos;
+        if self.cur().map(ident_start).unwrap_or(false) {
+            while self.cur().map(ident_rest).unwrap_or(false) {
+                self.advance(1)
+            }
+        }
+        &self.mmz[start..self.mmz_pos]
+    }
+
+    /// The keyword of the statement starting here (`sort` for a sort, whatever its
+    /// modifiers), and the name it declares, if it has one.
+    fn stmt_head(&mut self) -> (&'a [u8], Option<Str<'a>>) {
+        let mut keyword = self.word();
+        while SORT_MODS.contains(&keyword) {
+            keyword = self.word();
+        }
+        let ident = self.word();
+        (keyword, if ident.is_empty() { None } else { Some(Str(ident)) })
+    }
+
+    /// Move past the `;` that ends the statement starting here, not counting any in math
+    /// strings or comments.
+    fn skip_stmt(&mut self) {
+        let mut in_math = false;
+        while let Some(c) = self.cur() {
+            if !in_math && self.cur_slice().starts_with(b"--") {
+                self.skip_space();
+                continue
+            }
+            self.advance(1);
+            match c {
+                b'$' => in_math = !in_math,
+                b';' if !in_math => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// The local declaration the index gives the name `ident`, if there is one.
+    fn local_named(&self, ident: Str<'a>) -> Option<StmtCmd> {
+        self.outline.declarations
+            .iter()
+            .map(|(stmt, _)| *stmt)
+            .find(|stmt| stmt.is_local() && self.outline.stmt_name(*stmt).map(str::as_bytes) == Some(ident.as_bytes()))
+    }
+
+    /// Turn the result of matching `stmt` with the mm0 statement at `start` into a
+    /// `Conformance` error. If it failed, move past the statement and declare its name as
+    /// `stmt`, so later statements can use it and are matched with the declarations after
+    /// `stmt`.
+    pub(crate) fn conform(&mut self, start: usize, stmt: StmtCmd, res: Res<()>) -> Res<()> {
+        let err = match res {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        self.mmz_pos = start;
+        let (keyword, ident) = self.stmt_head();
+        self.mmz_pos = start;
+        self.skip_stmt();
+        let name = ident.map(|s| String::from_utf8_lossy(s.as_bytes()).to_string());
+        if let Some(ident) = ident {
+            if let Some(local) = self.local_named(ident) {
+                return Err(VerifErr::Conformance { decl: Some(local), name, problem: Mismatch::Local })
+            }
+            match (keyword, stmt) {
+                (b"sort", StmtCmd::Sort {..}) if !self.sorts.contains(&ident) => { self.add_sort(ident); }
+                (b"term" | b"def", StmtCmd::TermDef {..}) if !self.nonlocal_termdefs.contains_key(&ident) => { self.add_termdef(ident); }
+                (b"axiom" | b"theorem", StmtCmd::Axiom {..} | StmtCmd::Thm {..}) if !self.nonlocal_asserts.contains_key(&ident) => { self.add_assert(ident); }
+                _ => {}
+            }
+        }
+        Err(VerifErr::Conformance { decl: Some(stmt), name, problem: Mismatch::Statement(Box::new(err)) })
+    }
+
+    /// Once every mmb declaration has been matched, report each declaration left in the
+    /// mm0 files.
+    pub fn check_rest(&mut self, errs: &mut Vec<VerifErr>) {
+        loop {
+            self.skip_space();
+            if self.is_empty() {
+                match self.next_mmz_file() {
+                    Ok(()) => continue,
+                    Err(_) => return,
+                }
+            }
+            let start = self.mmz_pos;
+            let (keyword, ident) = self.stmt_head();
+            self.mmz_pos = start;
+            self.skip_stmt();
+            if let b"sort" | b"term" | b"def" | b"axiom" | b"theorem" = keyword {
+                let name = ident.map(|s| String::from_utf8_lossy(s.as_bytes()).to_string());
+                let (decl, problem) = match ident.and_then(|ident| self.local_named(ident)) {
+                    Some(local) => (Some(local), Mismatch::Local),
+                    None => (None, Mismatch::MissingFromMmb),
+                };
+                errs.push(VerifErr::Conformance { decl, name, problem });
+            }
+        }
+    }
+}
+
+impl<'b, 'a: 'b> MmzState<'b, 'a> {
+    /// Check that the mm0 file's name for the declaration being matched is the index's.
+    pub(crate) fn check_name(&self, ident: Str<'a>) -> Res<()> {
+        match self.mem.outline.stmt_name(self.stmt) {
+            Some(name) if name.as_bytes() != ident.as_bytes() => Err(VerifErr::Msg(format!(
+                "the mm0 file calls this {}, but the mmb index {}",
+                String::from_utf8_lossy(ident.as_bytes()),
+                name
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn conform1() {
+    use crate::util::ErrKind;
+    let mmb = std::fs::read("./test_resources/short.mmb").unwrap();
+    let mm0 = std::fs::read_to_string("./test_resources/short.mm0").unwrap();
+    let run = |mm0: String| {
+        let file_data = crate::fs::FileData::new_from_bytes(mmb.clone(), vec![mm0]);
+        let outline = crate::Outline::new_from(&file_data).unwrap();
+        crate::verify_serial(&outline, &outline.declarations)
+            .iter()
+            .map(|e| match e {
+                VerifErr::Conformance { name, problem, .. } => format!("{} {}", problem.name(), name.as_deref().unwrap_or("?")),
+                e => format!("{:?}", e.kind()),
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(run(mm0.clone()).is_empty());
+    // Each problem is reported once, and the declarations after it still match.
+    assert_eq!(run(mm0.replace("axiom ax_2", "theorem ax_2")), ["statement ax_2"]);
+    assert_eq!(run(mm0.replace("term not", "def not")), ["statement not"]);
+    assert_eq!(run(mm0.replace("axiom ax_1 (a b: wff): $ a -> b -> a $", "axiom ax_one (a b: wff): $ a -> b -> a $")), ["statement ax_one"]);
+    assert_eq!(run(mm0.replace("$ a -> b -> a $", "$ b -> a -> a $")), ["statement ax_1"]);
+    // `ax_mp` left out of the mm0 file, or another axiom added to it.
+    let cut = mm0.find("--| Modus ponens").unwrap();
+    assert_eq!(run(mm0[..cut].to_string()), ["missing-from-mm0 ax_mp"]);
+    assert_eq!(run(format!("{}\naxiom ax_4 (a: wff): $ a $;\n", mm0)), ["missing-from-mmb ax_4"]);
+    assert_eq!(ErrKind::Conformance.code(), "E0003");
+}
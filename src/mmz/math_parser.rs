@@ -1,6 +1,7 @@
 //! This module implements an operator precedence parser which aims to turn `$`-delimited math strings 
 //! (sometimes also called "formulas") into mm0 expressions while allowing for the use of user-defined notation.
 
+use crate::prelude::*;
 use bumpalo::collections::Vec as BumpVec;
 use crate::mmz::parse::{ wc, trim };
 use crate::none_err;
@@ -281,7 +282,7 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
                 // Make sure the variable in the math string has the sort called
                 // for by the variable in the notation declaration.
                 NotationLit::Var { pos, prec } => {
-                    let sig_e = none_err!(term.args().nth(*pos))?;
+                    let sig_e = none_err!(term.args().get(*pos))?;
                     let e = self.expr(*prec, None)?;
                     let coerced = self.coerce(e, sig_e.sort())?;
                     match math_args.get_mut(*pos) {
@@ -1,9 +1,12 @@
 pub mod parse;
 pub mod math_parser;
+pub mod conform;
+pub mod notation;
 
-use std::convert::TryFrom;
-use std::collections::HashMap;
-use std::sync::Arc;
+use crate::prelude::*;
+use core::convert::TryFrom;
+use crate::util::{ HashMap, MAX_BOUND_VARS };
+use alloc::sync::Arc;
 
 use bumpalo::Bump;
 use bumpalo::collections::Vec as BumpVec;
@@ -81,7 +84,7 @@ impl<'a> MmzVar<'a> {
 //    pub consts: Vec<(Str<'a>, Option<Fix>)>
 //}
 
-//impl<'a> std::default::Default for DeclaredNotation<'a> {
+//impl<'a> core::default::Default for DeclaredNotation<'a> {
 //    fn default() -> Self {
 //        DeclaredNotation {
 //            has_coe: false,
@@ -338,15 +341,20 @@ impl<'a> MmzMem<'a> {
                     Some(R(assert))
                 }
             };
-            self.parse_until(bump, stmt, item)?;
+            // Counted either way: `conform` has moved past a statement that doesn't match.
+            let res = self.parse_until(bump, stmt, item);
+            self.add_declar(stmt);
+            return res
         }
         Ok(self.add_declar(stmt))
     }
 
     pub fn next_mmz_file(&mut self) -> Res<()> {
+        // Past the last file, stay at the end of it.
+        self.mmz = none_err!(self.mmz_files.get(self.mmz_file_num + 1))?.as_bytes();
         self.mmz_file_num += 1;
         self.mmz_pos = 0;
-        Ok(self.mmz = none_err!(self.mmz_files.get(self.mmz_file_num))?.as_bytes())
+        Ok(())
     }
 
 
@@ -373,6 +381,11 @@ impl<'a> MmzMem<'a> {
         conv_err!(u8::try_from(idx))
     }
 
+    /// The names of the sorts declared so far, by sort number.
+    pub fn sort_idents(&self) -> &[SortIdent<'a>] {
+        self.sorts.as_slice()
+    }
+
     pub fn add_sort(&mut self, ident: Str<'a>) -> SortNum {
         let idx = self.sorts.len();
         assert!(!self.sorts.iter().any(|x| *x == ident));
@@ -425,12 +438,13 @@ pub struct MmzState<'b, 'a: 'b> {
     hyps: BumpVec<'b, MmzHyp<'b>>,
     ustack: BumpVec<'b, MmzExpr<'b>>,
     uheap: BumpVec<'b, MmzExpr<'b>>,
-    pub next_bv: u64    
-
+    pub next_bv: u64,
+    /// The declaration being matched, for error reporting.
+    pub stmt: StmtCmd,
 }
 
 impl<'b, 'a: 'b> MmzState<'b, 'a> {
-    pub fn new_from(mem: &'b mut MmzMem<'a>, bump: &'b mut Bump) -> MmzState<'b, 'a> {
+    pub fn new_from(mem: &'b mut MmzMem<'a>, bump: &'b mut Bump, stmt: StmtCmd) -> MmzState<'b, 'a> {
         bump.reset();
         MmzState {
             mem,
@@ -440,7 +454,8 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
             hyps: BumpVec::new_in(bump),
             ustack: BumpVec::new_in(bump),
             uheap: BumpVec::new_in(bump),
-            next_bv: 1u64            
+            next_bv: 1u64,
+            stmt,
         }
     }
 
@@ -448,12 +463,13 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
         &*self.bump.alloc(item)
     }        
 
-    pub fn take_next_bv(&mut self) -> u64 {
+    pub fn take_next_bv(&mut self) -> Res<u64> {
         let outgoing = self.next_bv;
-        // Assert we're under the limit of 55 bound variables.
-        assert!(outgoing >> 56 == 0);
+        if outgoing >> MAX_BOUND_VARS != 0 {
+            return Err(VerifErr::TooManyBoundVars { decl: self.stmt, var: outgoing.trailing_zeros() + 1 })
+        }
         self.next_bv *= 2;
-        outgoing
+        Ok(outgoing)
     }    
 
     /// Produce the list of variables that have been parsed which are not dummy variables
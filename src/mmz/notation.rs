@@ -0,0 +1,171 @@
+//! The mm0 file's notation, kept after it has been checked, for writing expressions the
+//! way the mm0 file does: `a -> ~b` instead of `(im a (not b))`. Infix, prefix and general
+//! notations are used with their precedences, adding parentheses only where the parser
+//! would need them, and coercions are left out. A term without notation is written as an
+//! application, `foo a b`.
+//!
+//! This is only for reading: nothing checks that the result parses back to the same
+//! expression (a notation can be ambiguous in ways the mm0 file gets away with).
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::HashMap;
+use crate::mmz::{ MmzMem, Prec, Coe, NotationLit };
+
+/// The precedence of an application `foo a b`, whose arguments are at `Prec::Max`.
+pub const APP_PREC: Prec = Prec::Num(1024);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Const(String),
+    /// The argument at `pos`, which needs at least `prec`.
+    Var { pos: usize, prec: Prec },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notation {
+    /// The parts in order, and the precedence of the whole.
+    Mixfix { prec: Prec, parts: Vec<Part> },
+    /// A coercion, which is written as its argument.
+    Coercion,
+}
+
+/// The notation of each term that has one, by term number.
+#[derive(Debug, Clone, Default)]
+pub struct Notations {
+    pub terms: HashMap<u32, Notation>,
+}
+
+impl Notations {
+    /// Write `name` applied to `args`, each written out with its precedence, using term
+    /// `term_num`'s notation if it has one. Returns the precedence of the result.
+    pub fn app(&self, term_num: u32, name: String, args: Vec<(String, Prec)>) -> (String, Prec) {
+        match self.terms.get(&term_num) {
+            Some(Notation::Coercion) => if let [arg] = args.as_slice() {
+                return arg.clone()
+            },
+            Some(Notation::Mixfix { prec, parts }) => {
+                let tokens = parts.iter().map(|part| match part {
+                    Part::Const(c) => Some(c.clone()),
+                    Part::Var { pos, prec } => args.get(*pos).map(|arg| paren(arg, *prec)),
+                }).collect::<Option<Vec<_>>>();
+                if let Some(tokens) = tokens {
+                    return (tokens.join(" "), *prec)
+                }
+            }
+            None => {}
+        }
+        // No notation, or one that doesn't fit the term.
+        if args.is_empty() {
+            return (name, Prec::Max)
+        }
+        let mut out = name;
+        for arg in args.iter() {
+            out.push(' ');
+            out.push_str(&paren(arg, Prec::Max));
+        }
+        (out, APP_PREC)
+    }
+}
+
+/// `expr`, in parentheses if it binds less tightly than `need`.
+pub fn paren(expr: &(String, Prec), need: Prec) -> String {
+    if expr.1 < need {
+        format!("({})", expr.0)
+    } else {
+        expr.0.clone()
+    }
+}
+
+fn part(lit: &NotationLit) -> Part {
+    match lit {
+        NotationLit::Const(c) => Part::Const(String::from_utf8_lossy(c.as_bytes()).to_string()),
+        NotationLit::Var { pos, prec } => Part::Var { pos: *pos, prec: *prec },
+    }
+}
+
+impl<'a> MmzMem<'a> {
+    /// The notation declared so far.
+    pub fn notations(&self) -> Notations {
+        let mut terms = HashMap::new();
+        // A prefix (or general notation) has its first token as its key instead of in `lits`.
+        for (tok, info) in self.prefixes.iter() {
+            let prec = self.consts.get(tok).copied().unwrap_or(Prec::Max);
+            let mut parts = vec![Part::Const(String::from_utf8_lossy(tok.as_bytes()).to_string())];
+            parts.extend(info.lits.iter().map(part));
+            terms.insert(info.term_num, Notation::Mixfix { prec, parts });
+        }
+        for (tok, info) in self.infixes.iter() {
+            let prec = self.consts.get(tok).copied().unwrap_or(Prec::Max);
+            terms.insert(info.term_num, Notation::Mixfix { prec, parts: info.lits.iter().map(part).collect() });
+        }
+        for coes in self.coes.values() {
+            for coe in coes.values() {
+                if let Coe::Single { term_num } = coe {
+                    terms.insert(*term_num, Notation::Coercion);
+                }
+            }
+        }
+        Notations { terms }
+    }
+}
+
+impl<'a> Outline<'a> {
+    /// Read the notation out of the mm0 files, going through them the way `verify_mmz`
+    /// does. Statements that don't check are skipped, so this gets whatever notation it
+    /// can; without mm0 files there is none.
+    pub fn read_notations(&self) -> Notations {
+        let mut mem = match MmzMem::new_from(self) {
+            Ok(mem) => mem,
+            Err(_) => return Notations::default(),
+        };
+        let mut bump = bumpalo::Bump::new();
+        for (stmt, _) in self.declarations.iter() {
+            let _ = mem.verify1(&mut bump, *stmt);
+        }
+        mem.notations()
+    }
+
+    /// An expression as a listing writes it: in `$ $` if it's in the mm0 file's notation,
+    /// which only parses inside them, and as it is if it's an s-expression.
+    pub(crate) fn math_str(&self, expr: String) -> String {
+        match self.notations {
+            Some(_) => format!("$ {} $", expr),
+            None => expr,
+        }
+    }
+
+    /// `name` applied to `args`, with the notation in `notations` if it's set, and as an
+    /// s-expression if not.
+    pub(crate) fn show_app(&self, term_num: u32, name: String, args: Vec<(String, Prec)>) -> (String, Prec) {
+        match &self.notations {
+            Some(notations) => notations.app(term_num, name, args),
+            None if args.is_empty() => (name, Prec::Max),
+            None => {
+                let args = args.into_iter().map(|(a, _)| a).collect::<Vec<_>>();
+                (format!("({} {})", name, args.join(" ")), Prec::Max)
+            }
+        }
+    }
+}
+
+#[test]
+fn notations1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", Some("./test_resources/short.mm0")).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    let notations = outline.read_notations();
+    // `im` is `->`, infixr at 25, and `not` is `~`, prefix at 41.
+    assert_eq!(notations.terms.get(&0), Some(&Notation::Mixfix {
+        prec: Prec::Num(25),
+        parts: vec![Part::Var { pos: 0, prec: Prec::Num(26) }, Part::Const("->".to_string()), Part::Var { pos: 1, prec: Prec::Num(25) }],
+    }));
+    outline.notations = Some(notations);
+    let (_, concl) = outline.assert_strs(1).unwrap();
+    assert_eq!(concl, "(v0 -> v1 -> v2) -> (v0 -> v1) -> v0 -> v2");
+    let (_, concl) = outline.assert_strs(2).unwrap();
+    assert_eq!(concl, "(~ v0 -> ~ v1) -> v1 -> v0");
+    let (hyps, concl) = outline.assert_strs(3).unwrap();
+    assert_eq!((hyps, concl), (vec!["v0 -> v1".to_string(), "v0".to_string()], "v1".to_string()));
+    outline.notations = None;
+    assert_eq!(outline.assert_strs(2).unwrap().1, "(im (im (not v0) (not v1)) (im v1 v0))");
+}
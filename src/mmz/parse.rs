@@ -1,5 +1,6 @@
-use std::sync::Arc;
-use std::collections::HashMap;
+use crate::prelude::*;
+use alloc::sync::Arc;
+use crate::util::HashMap;
 use bumpalo::collections::Vec as BumpVec;
 use crate::mmb::stmt::StmtCmd;
 use crate::mmz::{
@@ -28,6 +29,7 @@ use crate::util::{
     Assert, 
     Either::*, 
     Args,
+    Mismatch,
 };
 use crate::make_sure;
 use crate::localize;
@@ -113,46 +115,48 @@ impl<'b, 'a: 'b> MmzMem<'a> {
     ) -> Res<()> {
         'outer: loop {
             'inner: loop {
-                let mut mmz_st = MmzState::new_from(&mut *self, bump);
+                let mut mmz_st = MmzState::new_from(&mut *self, bump, stmt_cmd);
                 if mmz_st.cur().is_none() {
                     break 'inner
                 }
             
-                match { mmz_st.skip_ws(); mmz_st.peek_word() } {
-                    b"provable" | b"strict" | b"free" | b"pure" | b"sort" => return mmz_st.parse_sort(),
-                    b"term" | b"def" => {
-                        if let Some(L(t)) = item {
-                            return mmz_st.parse_termdef(t)
-
-                        } else {
-                            return Err(VerifErr::Unreachable(file!(), line!()));
+                let keyword = { mmz_st.skip_ws(); mmz_st.peek_word() };
+                let start = mmz_st.mem.mmz_pos;
+                let res = match (keyword, &item) {
+                    (b"provable" | b"strict" | b"free" | b"pure" | b"sort", _) => mmz_st.parse_sort(),
+                    (b"term" | b"def", Some(L(t))) => mmz_st.parse_termdef(*t),
+                    (b"axiom" | b"theorem", Some(R(assert))) => mmz_st.parse_assert(*assert),
+                    (b"term" | b"def" | b"axiom" | b"theorem", _) => Err(VerifErr::Msg(format!(
+                        "the mm0 file has a {} here, but the mmb file declares {:?}",
+                        String::from_utf8_lossy(keyword),
+                        stmt_cmd
+                    ))),
+                    _ => {
+                        match keyword {
+                            b"delimiter" => mmz_st.delims()?,
+                            b"prefix" | b"infixl" | b"infixr" => mmz_st.simple_notation()?,
+                            b"notation" => mmz_st.gen_notation()?,
+                            b"coercion" => mmz_st.coercion()?,
+                            b"import" => mmz_st.parse_import()?,
+                            b"input" => return Err(VerifErr::Msg("`input` statements are not currently supported".to_string())),
+                            b"output" => return Err(VerifErr::Msg("`output` statements are not currently supported".to_string())),
+                            _ => break 'inner,
                         }
+                        continue 'inner
                     }
-                    b"axiom" | b"theorem" => {
-                        make_sure!(matches!(stmt_cmd, StmtCmd::Axiom {..}) || matches!(stmt_cmd, StmtCmd::Thm {..}));
-                        if let Some(R(assert)) = item {
-                            return mmz_st.parse_assert(assert)
-                        } else {
-                            return Err(VerifErr::Unreachable(file!(), line!()));
-                        }
-                     },
-                     b"delimiter" => mmz_st.delims()?,
-                     b"prefix" | b"infixl" | b"infixr" => mmz_st.simple_notation()?,
-                     b"notation" => mmz_st.gen_notation()?,
-                     b"coercion" => mmz_st.coercion()?,
-                     b"import" => mmz_st.parse_import()?,
-                     b"input" => return Err(VerifErr::Msg(format!("`input` statements are not currently suppprted"))),
-                     b"output" => return Err(VerifErr::Msg(format!("`output` statements are not currently supported"))),
-                     _ => break 'inner,
-                }                
+                };
+                drop(mmz_st);
+                return self.conform(start, stmt_cmd, res)
             }
 
             if let Ok(()) = self.next_mmz_file() {
                 continue 'outer
             } else {
-                return Err(VerifErr::Msg(
-                    format!("`parse_until` ran out of input with remaining verification obligations.")
-                ))
+                return Err(VerifErr::Conformance {
+                    decl: Some(stmt_cmd),
+                    name: self.outline.stmt_name(stmt_cmd).map(|s| s.to_string()),
+                    problem: Mismatch::MissingFromMm0,
+                })
             }
         }
     }    
@@ -561,13 +565,12 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
     // x |-> nat
     // You have a diamond.
     fn update_provs(&mut self) -> Res<()> {
-        let mut provs = HashMap::with_hasher(Default::default());
+        let mut provs = HashMap::new();
         for (s1, m) in self.mem.coes.iter() {
             for s2 in m.keys() {
                 if self.mem.outline.get_sort_mods(*s2 as usize)?.is_provable() {
                     if let Some(_s2) = provs.insert(*s1, *s2) {
-                        println!("Coercion diamond to provable detected");
-                        panic!();
+                        panic!("Coercion diamond to provable detected");
                     }
                 }
             }
@@ -859,6 +862,7 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
 
     pub fn parse_sort(&mut self) -> Res<()> {
         let (ident, mods) = self.sort()?;
+        self.check_name(ident)?;
         let mmb_mods = self.mem.outline.get_sort_mods(self.mem.num_sorts_done() as usize)?;
         make_sure!(mods == mmb_mods);
         self.mem.add_sort(ident);
@@ -869,8 +873,14 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
         &mut self, 
         term: Term<'a>,
     ) -> Res<()> {
-        make_sure!(self.kw(b"term ").or(self.kw(b"def ")).is_some());
+        let kw = self.kw(b"term ").or(self.kw(b"def "));
+        make_sure!(kw.is_some());
         let ident = self.ident()?;
+        self.check_name(ident)?;
+        if (kw == Some(b"def ")) != term.is_def() {
+            let (mm0, mmb) = if term.is_def() { ("term", "def") } else { ("def", "term") };
+            return Err(VerifErr::Msg(format!("the mm0 file declares a {}, but the mmb file a {}", mm0, mmb)))
+        }
 
         let mode = if term.is_def() { "def" } else { "term" };
         self.binders(term.args(), mode)?;
@@ -892,17 +902,30 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
         &mut self, 
         assert: Assert<'a>,
     ) -> Res<()> {
-        make_sure!(self.kw(b"axiom ").or(self.kw(b"theorem ")).is_some());
-        let ident = self.ident().unwrap();
+        let kw = self.kw(b"axiom ").or(self.kw(b"theorem "));
+        make_sure!(kw.is_some());
+        let ident = self.ident()?;
+        self.check_name(ident)?;
+        if (kw == Some(b"axiom ")) != matches!(self.stmt, StmtCmd::Axiom {..}) {
+            let (mm0, mmb) = if kw == Some(b"axiom ") { ("an axiom", "a theorem") } else { ("a theorem", "an axiom") };
+            return Err(VerifErr::Msg(format!("the mm0 file declares {}, but the mmb file {}", mm0, mmb)))
+        }
         let _binders = self.binders(assert.args(), "assert")?;
         let tgt = self.hyps.pop().unwrap().expr;
+        let hyp_names = self.hyps
+            .iter()
+            .map(|h| h.ident.map(|s| String::from_utf8_lossy(s.as_bytes()).to_string()))
+            .collect::<Vec<_>>();
         self.check_expr(
             assert.unify(),
             tgt,
             UMode::UThm
         )?;
         localize!(self.guard(b';'))?;
-        self.mem.add_assert(ident);
+        let assert_num = self.mem.add_assert(ident);
+        if hyp_names.iter().any(|name| name.is_some()) {
+            self.mem.outline.hyp_names.with(|names| names.insert(assert_num, hyp_names));
+        }
         Ok(())
     }
 
@@ -973,7 +996,7 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
         Ok(())
     }
 
-    fn binder_group(&mut self, args_todo: std::iter::Skip<Args<'a>>, mode: &str) -> Res<()> {
+    fn binder_group(&mut self, args_todo: core::iter::Skip<Args<'a>>, mode: &str) -> Res<()> {
         let opener = localize!(self.guard(b'(').or(self.guard(b'{')))?;
         let num_non_dummy_before = self.non_dummy_vars().count();
         // Get the variable idents/names, put them in `vars_todo` until we know their type
@@ -1030,6 +1053,8 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
                 expr: math_expr
             })
         }        
+        // Hypotheses aren't variables, so their names mustn't be picked up by the next group.
+        self.vars_todo.truncate(self.vars_done.len());
         Ok(())
     }
 
@@ -1041,7 +1066,7 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
         let mut ty_accum = Type::new(bound);
         ty_accum.add_sort(sort_num);
         if bound {
-            ty_accum.inner |= self.take_next_bv();
+            ty_accum.inner |= self.take_next_bv()?;
         } else {
             while let Ok(dep_ident) = self.ident() {
                 make_sure!(!bound);
@@ -1062,7 +1087,7 @@ impl<'b, 'a: 'b> MmzState<'b, 'a> {
         Ok(())
     }
 
-    fn return_ty(&mut self, mut arrow_args: std::iter::Skip<Args<'a>>, mode: &str) -> Res<()> {
+    fn return_ty(&mut self, mut arrow_args: core::iter::Skip<Args<'a>>, mode: &str) -> Res<()> {
         while let Some(_) = self.cur() {
             // If it's a hypothesis
             if let Some(b'$') = { self.skip_ws(); self.cur() } {
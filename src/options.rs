@@ -0,0 +1,88 @@
+use core::time::Duration;
+
+/// Timeouts need a clock, and only `std` has one. Without it, `timeout` is ignored.
+#[cfg(feature = "std")]
+pub use std::time::Instant;
+
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy)]
+pub enum Instant {}
+
+#[cfg(not(feature = "std"))]
+impl Instant {
+    pub fn elapsed(&self) -> Duration {
+        match *self {}
+    }
+}
+
+/// When a declaration starts, note the time if there's a timeout to enforce.
+#[cfg(feature = "std")]
+pub fn start_clock(options: &VerifyOptions) -> Option<Instant> {
+    options.timeout.map(|_| Instant::now())
+}
+
+#[cfg(not(feature = "std"))]
+pub fn start_clock(_: &VerifyOptions) -> Option<Instant> {
+    None
+}
+
+/// The time now, if there's a clock. For `PhaseTimes`, which are all zero without one.
+#[cfg(feature = "std")]
+pub fn clock() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(not(feature = "std"))]
+pub fn clock() -> Option<Instant> {
+    None
+}
+
+/// The time since `started`, or zero if there was no clock.
+pub fn since(started: Option<Instant>) -> Duration {
+    started.map(|s| s.elapsed()).unwrap_or_default()
+}
+
+/// Knobs controlling how much work the verifier is willing to do, and how picky it is.
+/// These live in the `Outline` so that every worker sees the same settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyOptions {
+    /// Abandon a single declaration once it has been running for this long.
+    pub timeout: Option<Duration>,
+    /// Abandon a single declaration after it has executed this many proof and unify commands.
+    pub max_steps: Option<u64>,
+    /// Abandon a single declaration once it's using more than this many bytes of arena,
+    /// instead of letting the allocator abort the process when the system runs out.
+    pub max_memory: Option<usize>,
+    /// Abandon a single declaration once its proof builds an expression with more than
+    /// this many nodes, written out as a tree. Sharing lets a short proof build an
+    /// expression exponentially larger than itself, which the checks after it can take
+    /// exponentially long over.
+    pub max_expr_size: Option<u64>,
+    /// Abandon a single declaration once its proof builds an expression more than this
+    /// many levels deep.
+    pub max_expr_depth: Option<u32>,
+    /// Collect `ArenaStats`. This gives every declaration a fresh arena, which costs
+    /// some speed but makes the numbers reproducible.
+    pub arena_stats: bool,
+    /// Share one allocation between all copies of an expression within a declaration,
+    /// which also makes comparing expressions a pointer comparison. See `mmb::intern`.
+    pub hash_cons: bool,
+    /// Also reject things the format tolerates but a well-behaved writer never produces:
+    /// nonzero reserved fields, junk after the proof stream, unused table slots, and
+    /// backreferences with oversized data fields. See `mmb::strict`.
+    pub strict: bool,
+    /// When unification fails, say where: the path from the root of the expression being
+    /// unified to the first subterm that doesn't match, and the heads that conflict there.
+    /// This costs a path per entry of the unify stack, so it's off by default.
+    pub unify_delta: bool,
+    /// Check every def, axiom and theorem a second time with the trusted kernel (see
+    /// `kernel`), and report any declaration it and `MmbState` disagree about.
+    pub dual: bool,
+    /// Once everything is checked, treat the declarations that failed as unavailable and
+    /// report each one that uses them (see `Outline::flag_dependents`), so one run gives
+    /// every declaration that can't be trusted, not just the ones that are wrong.
+    pub keep_going: bool,
+    /// Collect `PhaseTimes`: how long proofs and unification took, separately and for each
+    /// declaration. This reads the clock around every unification.
+    pub phase_times: bool,
+}
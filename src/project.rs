@@ -0,0 +1,401 @@
+//! Checking a whole project, several mm0/mmb pairs listed in a manifest, in one run. The
+//! manifest is a small subset of TOML:
+//!
+//! ```text
+//! # Options for every file.
+//! [options]
+//! strict = true
+//! max_steps = 1000000
+//!
+//! [[file]]
+//! mmb = "peano.mmb"
+//! mm0 = "peano.mm0"
+//! sha256 = "5f1d..."
+//!
+//! [[file]]
+//! mmb = "set.mmb"
+//! dual = true
+//! ```
+//!
+//! Each `[[file]]` names an mmb file and optionally its mm0 file (which defaults the way it
+//! does on the command line) and the SHA-256 the mmb file should have (as the verifier
+//! sees it, so after decompression). Paths are relative to the manifest. The options are
+//! `timeout_ms`, `max_steps`, `max_memory`, `max_expr_size`, `max_expr_depth`,
+//! `hash_cons`, `strict`, `unify_delta`, `dual` and `keep_going`; ones in `[options]` apply to every file after them, and ones in a
+//! `[[file]]` to that file only. Strings are in double quotes, with `\"` and `\\`;
+//! anything else TOML has is an error.
+//!
+//! Without a manifest, `discover` finds the mmb files in a directory tree whose paths match
+//! a glob, each checked against the mm0 file next to it, and `check_files` verifies them
+//! in parallel, a file per thread.
+
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{ AtomicUsize, Ordering::Relaxed };
+use sha2::{ Sha256, Digest };
+use crate::Outline;
+use crate::fs::FileData;
+use crate::env::hex;
+use crate::json::Json;
+use crate::options::VerifyOptions;
+use crate::mmb::query::glob_match;
+use crate::util::{ Res, VerifErr };
+
+/// One mm0/mmb pair from a manifest.
+#[derive(Debug, Clone)]
+pub struct ProjectFile {
+    pub mmb: PathBuf,
+    pub mm0: Option<PathBuf>,
+    /// The SHA-256 the mmb file should have, in hex.
+    pub sha256: Option<String>,
+    pub options: VerifyOptions,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Str(String),
+    Bool(bool),
+    Int(u64),
+}
+
+fn manifest_err(line: usize, msg: &str) -> VerifErr {
+    VerifErr::Msg(format!("manifest line {}: {}", line, msg))
+}
+
+fn parse_value(line: usize, s: &str) -> Res<Value> {
+    match s {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    return match chars.as_str().trim() {
+                        "" => Ok(Value::Str(out)),
+                        _ => Err(manifest_err(line, "something after a string")),
+                    }
+                }
+                '\\' => match chars.next() {
+                    Some(c @ ('"' | '\\')) => out.push(c),
+                    _ => return Err(manifest_err(line, "only \\\" and \\\\ can be escaped")),
+                },
+                c => out.push(c),
+            }
+        }
+        return Err(manifest_err(line, "a string with no closing quote"))
+    }
+    s.replace('_', "").parse::<u64>().map(Value::Int).map_err(|_| manifest_err(line, "expected a string, a boolean, or a natural number"))
+}
+
+/// Set option `key` in `options`, returning whether it's an option.
+fn set_option(options: &mut VerifyOptions, line: usize, key: &str, value: &Value) -> Res<bool> {
+    let flag = |v: &Value| match v {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(manifest_err(line, &format!("{} has to be true or false", key))),
+    };
+    let num = |v: &Value| match v {
+        Value::Int(n) => Ok(*n),
+        _ => Err(manifest_err(line, &format!("{} has to be a natural number", key))),
+    };
+    match key {
+        "timeout_ms" => options.timeout = Some(Duration::from_millis(num(value)?)),
+        "max_steps" => options.max_steps = Some(num(value)?),
+        "max_memory" => options.max_memory = Some(num(value)? as usize),
+        "max_expr_size" => options.max_expr_size = Some(num(value)?),
+        "max_expr_depth" => options.max_expr_depth = Some(num(value)?.min(u32::MAX as u64) as u32),
+        "hash_cons" => options.hash_cons = flag(value)?,
+        "strict" => options.strict = flag(value)?,
+        "unify_delta" => options.unify_delta = flag(value)?,
+        "dual" => options.dual = flag(value)?,
+        "keep_going" => options.keep_going = flag(value)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// The files listed in `text`, a manifest in the directory `base`, with `defaults` for
+/// the options neither `[options]` nor the file sets.
+pub fn parse_manifest(text: &str, base: &Path, defaults: VerifyOptions) -> Res<Vec<ProjectFile>> {
+    let mut options = defaults;
+    let mut files: Vec<ProjectFile> = Vec::new();
+    // Whether the keys so far go in the last file, rather than `[options]`.
+    let mut in_file = false;
+    for (idx, raw) in text.lines().enumerate() {
+        let line = idx + 1;
+        let l = raw.trim();
+        if l.is_empty() || l.starts_with('#') {
+            continue
+        }
+        match l {
+            "[options]" => {
+                in_file = false;
+                continue
+            }
+            "[[file]]" => {
+                in_file = true;
+                files.push(ProjectFile { mmb: PathBuf::new(), mm0: None, sha256: None, options });
+                continue
+            }
+            _ if l.starts_with('[') => return Err(manifest_err(line, "the only tables are [options] and [[file]]")),
+            _ => {}
+        }
+        let (key, value) = match l.split_once('=') {
+            Some((key, value)) => (key.trim(), parse_value(line, value.trim())?),
+            None => return Err(manifest_err(line, "expected `key = value`")),
+        };
+        let target = match (in_file, files.last_mut()) {
+            (true, Some(file)) => file,
+            _ => {
+                if set_option(&mut options, line, key, &value)? {
+                    continue
+                }
+                return Err(manifest_err(line, &format!("{} isn't an option", key)))
+            }
+        };
+        if set_option(&mut target.options, line, key, &value)? {
+            continue
+        }
+        let path = match value {
+            Value::Str(s) => s,
+            _ => return Err(manifest_err(line, &format!("{} has to be a string", key))),
+        };
+        match key {
+            "mmb" => target.mmb = base.join(path),
+            "mm0" => target.mm0 = Some(base.join(path)),
+            "sha256" => target.sha256 = Some(path.to_lowercase()),
+            _ => return Err(manifest_err(line, &format!("{} isn't a file key or an option", key))),
+        }
+    }
+    if let Some(n) = files.iter().position(|f| f.mmb.as_os_str().is_empty()) {
+        return Err(VerifErr::Msg(format!("manifest: file {} has no mmb path", n + 1)))
+    }
+    Ok(files)
+}
+
+/// How checking one file of a project went.
+#[derive(Debug)]
+pub struct FileReport {
+    pub mmb: PathBuf,
+    /// The SHA-256 of the mmb file, if it could be read.
+    pub sha256: Option<String>,
+    pub sorts: u8,
+    pub terms: u32,
+    pub thms: u32,
+    /// Including a hash that isn't the expected one, or a file that couldn't be read.
+    pub errors: Vec<VerifErr>,
+    pub time: Duration,
+}
+
+impl FileReport {
+    pub fn ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Verify one file of a project with `num_threads` threads.
+pub fn check_file(file: &ProjectFile, num_threads: usize) -> FileReport {
+    let start = Instant::now();
+    let mut report = FileReport { mmb: file.mmb.clone(), sha256: None, sorts: 0, terms: 0, thms: 0, errors: Vec::new(), time: Duration::default() };
+    let file_data = FileData::new_from(&file.mmb, file.mm0.as_ref());
+    let outline = file_data.as_ref().map_err(|e| format!("{:?}", e)).and_then(|d| Outline::new_from(d).map_err(|e| format!("{:?}", e)));
+    match outline {
+        Err(msg) => report.errors.push(VerifErr::Msg(format!("couldn't load {}: {}", file.mmb.display(), msg))),
+        Ok(mut outline) => {
+            let sha256 = hex(&Sha256::digest(outline.mmb()));
+            if let Some(expected) = &file.sha256 {
+                if *expected != sha256 {
+                    report.errors.push(VerifErr::Msg(format!("{} has SHA-256 {}, but the manifest expects {}", file.mmb.display(), sha256, expected)));
+                }
+            }
+            outline.options = file.options;
+            report.errors.extend(if num_threads > 1 {
+                crate::verify_par(&outline, &outline.declarations, num_threads)
+            } else {
+                crate::verify_serial(&outline, &outline.declarations)
+            });
+            report.sha256 = Some(sha256);
+            report.sorts = outline.header.num_sorts;
+            report.terms = outline.header.num_terms;
+            report.thms = outline.header.num_thms;
+        }
+    }
+    report.time = start.elapsed();
+    report
+}
+
+/// The files under `dir` whose paths relative to it match `pattern`, in order, each with
+/// `options` and the mm0 file next to it. Symbolic links to directories aren't followed.
+pub fn discover(dir: &Path, pattern: &str, options: VerifyOptions) -> std::io::Result<Vec<ProjectFile>> {
+    fn walk(dir: &Path, rel: &str, pattern: &str, options: VerifyOptions, out: &mut Vec<ProjectFile>) -> std::io::Result<()> {
+        let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rel = if rel.is_empty() { name } else { format!("{}/{}", rel, name) };
+            if entry.file_type()?.is_dir() {
+                walk(&entry.path(), &rel, pattern, options, out)?;
+            } else if entry.path().is_file() && glob_match(pattern, &rel) {
+                out.push(ProjectFile { mmb: entry.path(), mm0: None, sha256: None, options });
+            }
+        }
+        Ok(())
+    }
+    let mut out = Vec::new();
+    walk(dir, "", pattern, options, &mut out)?;
+    Ok(out)
+}
+
+/// Verify `files` on `num_threads` threads, each taking the next file not yet started,
+/// and return their reports in the order of `files`. A file whose check panics is
+/// reported as failing, and the rest of the batch goes on.
+pub fn check_files(files: &[ProjectFile], num_threads: usize) -> Vec<FileReport> {
+    run_files(files, num_threads, |file| check_file(file, 1))
+}
+
+fn run_files(files: &[ProjectFile], num_threads: usize, check: impl Fn(&ProjectFile) -> FileReport + Sync) -> Vec<FileReport> {
+    let next = AtomicUsize::new(0);
+    let mut reports = crossbeam_utils::thread::scope(|sco| {
+        let workers = (0..num_threads.max(1)).map(|_| sco.spawn(|_| {
+            let mut done = Vec::new();
+            loop {
+                let idx = next.fetch_add(1, Relaxed);
+                match files.get(idx) {
+                    Some(file) => done.push((idx, check_caught(file, &check))),
+                    None => return done,
+                }
+            }
+        })).collect::<Vec<_>>();
+        workers.into_iter().flat_map(|w| w.join().unwrap()).collect::<Vec<_>>()
+    }).unwrap();
+    reports.sort_by_key(|(idx, _)| *idx);
+    reports.into_iter().map(|(_, r)| r).collect()
+}
+
+fn check_caught(file: &ProjectFile, check: impl Fn(&ProjectFile) -> FileReport) -> FileReport {
+    let start = Instant::now();
+    match std::panic::catch_unwind(AssertUnwindSafe(|| check(file))) {
+        Ok(report) => report,
+        Err(_) => FileReport {
+            mmb: file.mmb.clone(),
+            sha256: None,
+            sorts: 0,
+            terms: 0,
+            thms: 0,
+            errors: vec![VerifErr::Msg(format!("verifying {} panicked", file.mmb.display()))],
+            time: start.elapsed(),
+        }
+    }
+}
+
+/// A table with a row per file: whether it passed, the path, the table sizes, the time,
+/// and the first error.
+pub fn summary_table(reports: &[FileReport]) -> String {
+    let width = reports.iter().map(|r| r.mmb.display().to_string().len()).max().unwrap_or(0).max(4);
+    let mut out = format!("{:<6}{:<width$}  {:>6} {:>7} {:>10} {:>9}  {}\n", "", "file", "sorts", "terms", "assertions", "ms", "first error", width = width);
+    for r in reports.iter() {
+        let first = r.errors.first().map(|e| format!("{} {:?}", e.code(), e)).unwrap_or_default();
+        let row = format!(
+            "{:<6}{:<width$}  {:>6} {:>7} {:>10} {:>9}  {}",
+            if r.ok() { "OK" } else { "FAIL" },
+            r.mmb.display().to_string(),
+            r.sorts,
+            r.terms,
+            r.thms,
+            r.time.as_millis(),
+            first,
+            width = width
+        );
+        out.push_str(row.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Every file's report as one JSON object, `{"ok": .., "files": [..]}`, with each error
+/// given by its code and its `Debug` output.
+pub fn project_json(reports: &[FileReport]) -> Json {
+    let files: Vec<Json> = reports.iter().map(|r| {
+        let errors: Vec<Json> = r.errors.iter().map(|e| Json::obj().with("code", e.code()).with("error", format!("{:?}", e))).collect();
+        Json::obj()
+            .with("mmb", r.mmb.display().to_string())
+            .with("ok", r.ok())
+            .with("sha256", r.sha256.clone())
+            .with("sorts", r.sorts)
+            .with("terms", r.terms)
+            .with("thms", r.thms)
+            .with("errors", errors)
+            .with("ms", r.time.as_millis() as u64)
+    }).collect();
+    Json::obj()
+        .with("ok", reports.iter().all(|r| r.ok()))
+        .with("files", files)
+}
+
+#[test]
+fn project1() {
+    let base = Path::new("./test_resources");
+    let sha256 = hex(&Sha256::digest(std::fs::read(base.join("short.mmb")).unwrap()));
+    let manifest = format!(r#"
+        # Options for every file.
+        [options]
+        max_steps = 1_000_000
+
+        [[file]]
+        mmb = "short.mmb"
+        mm0 = "short.mm0"
+        sha256 = "{}"
+        dual = true
+
+        [[file]]
+        mmb = "short.mmb"
+        sha256 = "00"
+    "#, sha256);
+    let files = parse_manifest(&manifest, base, VerifyOptions::default()).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].options.max_steps, Some(1_000_000));
+    assert!(files[0].options.dual && !files[1].options.dual);
+    let reports = files.iter().map(|f| check_file(f, 1)).collect::<Vec<_>>();
+    assert!(reports[0].ok());
+    assert_eq!(reports[1].errors.len(), 1);
+    let json = project_json(&reports);
+    assert_eq!(json.get("ok").and_then(|v| v.as_bool()), Some(false));
+
+    let bad = |text: &str| parse_manifest(text, base, VerifyOptions::default()).is_err();
+    assert!(bad("[[file]]\nmmb = \"a.mmb"));
+    assert!(bad("[[file]]\nmmb = 3"));
+    assert!(bad("[options]\nstrict = 1"));
+    assert!(bad("[[file]]\nmm0 = \"a.mm0\""));
+    assert!(bad("[package]"));
+}
+
+#[test]
+fn verify_all1() {
+    let files = discover(Path::new("./test_resources"), "**/short.mmb", VerifyOptions::default()).unwrap();
+    assert_eq!(files.len(), 1);
+    let twice = [files[0].clone(), files[0].clone()];
+    let reports = check_files(&twice, 2);
+    assert!(reports.iter().all(|r| r.ok()));
+    let table = summary_table(&reports);
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.lines().nth(1).unwrap().starts_with("OK    "));
+}
+
+#[test]
+fn check_files_panic1() {
+    let files = discover(Path::new("./test_resources"), "**/short.mmb", VerifyOptions::default()).unwrap();
+    let three = [files[0].clone(), files[0].clone(), files[0].clone()];
+    let count = AtomicUsize::new(0);
+    let reports = run_files(&three, 2, |file| {
+        if count.fetch_add(1, Relaxed) == 1 { panic!("injected") }
+        check_file(file, 1)
+    });
+    assert_eq!(reports.len(), 3);
+    assert_eq!(reports.iter().filter(|r| r.ok()).count(), 2);
+    let failed = reports.iter().find(|r| !r.ok()).unwrap();
+    assert!(format!("{:?}", failed.errors[0]).contains("panicked"));
+}
@@ -0,0 +1,113 @@
+//! Python bindings, built with the `pyo3` feature (for example with `maturin develop --features pyo3`).
+//!```text
+//! import second_opinion
+//! second_opinion.verify("peano.mmb", "peano.mm0")   # {'ok': True, 'sorts': 4, ...}
+//! second_opinion.verify_bytes(open("peano.mmb", "rb").read())
+//! for d in second_opinion.declarations("peano.mmb"):
+//!     print(d["kind"], d["name"], d.get("conclusion"))
+//!```
+#![allow(unsafe_code)]
+// The code `#[pyfunction]` generates for the return types trips this lint.
+#![allow(clippy::useless_conversion)]
+
+use std::path::PathBuf;
+use pyo3::prelude::*;
+use pyo3::exceptions::{ PyIOError, PyValueError };
+use pyo3::types::{ PyBytes, PyDict, PyList };
+use crate::Outline;
+use crate::fs::FileData;
+use crate::mmb::stmt::StmtCmd;
+use crate::util::VerifErr;
+
+fn to_py_err(e: VerifErr) -> PyErr {
+    match e {
+        VerifErr::IoErr(..) => PyIOError::new_err(format!("{:?}", e)),
+        _ => PyValueError::new_err(format!("{:?}", e)),
+    }
+}
+
+/// Accept either the contents of an mmb file (`bytes`) or a path to one.
+fn load(src: &Bound<'_, PyAny>) -> PyResult<FileData> {
+    if let Ok(bytes) = src.downcast::<PyBytes>() {
+        return Ok(FileData::new_from_bytes(bytes.as_bytes().to_vec(), Vec::new()))
+    }
+    FileData::new_from(src.extract::<PathBuf>()?, None::<PathBuf>).map_err(to_py_err)
+}
+
+fn run<'py>(py: Python<'py>, file_data: FileData) -> PyResult<Bound<'py, PyDict>> {
+    // Verification doesn't touch any Python objects, so other Python threads can run meanwhile.
+    let (counts, errs) = py.allow_threads(|| -> Result<_, VerifErr> {
+        let outline = Outline::new_from(&file_data)?;
+        let errs = crate::verify_serial(&outline, outline.declarations.as_slice());
+        let counts = (outline.header.num_sorts, outline.header.num_terms, outline.header.num_thms);
+        Ok((counts, errs.iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>()))
+    }).map_err(to_py_err)?;
+    let out = PyDict::new_bound(py);
+    out.set_item("ok", errs.is_empty())?;
+    out.set_item("sorts", counts.0)?;
+    out.set_item("terms", counts.1)?;
+    out.set_item("thms", counts.2)?;
+    out.set_item("errors", errs)?;
+    Ok(out)
+}
+
+/// Verify an mmb file, and the mm0 file it should match if one is given.
+/// Returns a dict `{ ok, sorts, terms, thms, errors }`.
+#[pyfunction]
+#[pyo3(signature = (mmb_path, mm0_path = None))]
+fn verify(py: Python<'_>, mmb_path: PathBuf, mm0_path: Option<PathBuf>) -> PyResult<Bound<'_, PyDict>> {
+    run(py, FileData::new_from(mmb_path, mm0_path).map_err(to_py_err)?)
+}
+
+/// Verify an mmb file given as bytes. Only the mmb file is checked.
+#[pyfunction]
+fn verify_bytes<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyDict>> {
+    run(py, FileData::new_from_bytes(data.to_vec(), Vec::new()))
+}
+
+/// The declarations of an mmb file (a path or bytes), in order, without verifying them.
+/// Each is a dict with `kind`, `num`, and `name` (`None` if the file has no index);
+/// axioms and theorems also have `hyps` and `conclusion` as s-expressions.
+#[pyfunction]
+fn declarations<'py>(py: Python<'py>, src: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyList>> {
+    let file_data = load(src)?;
+    let outline = Outline::new_from(&file_data).map_err(to_py_err)?;
+    let out = PyList::empty_bound(py);
+    for (stmt, _) in outline.declarations.iter() {
+        let d = PyDict::new_bound(py);
+        match *stmt {
+            StmtCmd::Sort { num: Some(num) } => {
+                d.set_item("kind", "sort")?;
+                d.set_item("num", num)?;
+                d.set_item("name", outline.sort_name(num))?;
+            }
+            StmtCmd::TermDef { num: Some(num), local } => {
+                let term = outline.get_term_by_num(num).map_err(to_py_err)?;
+                d.set_item("kind", if term.is_def() { "def" } else { "term" })?;
+                d.set_item("num", num)?;
+                d.set_item("name", outline.term_name(num))?;
+                d.set_item("local", local)?;
+            }
+            StmtCmd::Axiom { num: Some(num) } | StmtCmd::Thm { num: Some(num), .. } => {
+                let (hyps, concl) = outline.assert_sexprs(num).map_err(to_py_err)?;
+                d.set_item("kind", if let StmtCmd::Axiom {..} = stmt { "axiom" } else { "theorem" })?;
+                d.set_item("num", num)?;
+                d.set_item("name", outline.assert_name(num))?;
+                d.set_item("local", stmt.is_local())?;
+                d.set_item("hyps", hyps)?;
+                d.set_item("conclusion", concl)?;
+            }
+            _ => return Err(to_py_err(VerifErr::Unreachable(file!(), line!()))),
+        }
+        out.append(d)?;
+    }
+    Ok(out)
+}
+
+#[pymodule]
+fn second_opinion(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(declarations, m)?)?;
+    Ok(())
+}
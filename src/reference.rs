@@ -0,0 +1,105 @@
+//! Differential verification: running another verifier (like mm0-c, or mm0-rs) on the same
+//! files and comparing its verdict with ours. A second opinion is only worth something if
+//! it's compared with the first, so a disagreement is reported as its own kind of result.
+//!
+//! The reference verifier is given as a command line, split on whitespace (there's no
+//! shell involved), in which `{mmb}` and `{mm0}` are replaced by the paths of the files. If
+//! the command doesn't mention `{mm0}`, the mm0 file is piped to its standard input
+//! instead, which is how mm0-c takes it (`mm0-c {mmb}`). The verdict is the exit status:
+//! zero means the reference verifier accepted the files. A divergence fails the run, as a
+//! `VerifErr::Divergence`, whichever verifier accepted the files.
+
+use std::fmt::{ Display, Formatter, Result as FmtResult };
+use std::path::Path;
+use std::process::{ Command, Stdio };
+use crate::util::VerifErr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub accepted: bool,
+    /// What the reference verifier printed, stdout then stderr.
+    pub output: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// Both verifiers accepted the files, or both rejected them.
+    Agree { accepted: bool },
+    /// One accepted and the other rejected.
+    Divergence { ours: bool, theirs: Verdict },
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let verdict = |accepted: bool| if accepted { "accepts" } else { "rejects" };
+        match self {
+            Comparison::Agree { accepted } => write!(f, "the reference verifier agrees: both {}", if *accepted { "accept" } else { "reject" }),
+            Comparison::Divergence { ours, theirs } => {
+                write!(f, "verifier divergence: second_opinion {} but the reference verifier {}", verdict(*ours), verdict(theirs.accepted))?;
+                match theirs.output.lines().find(|l| !l.trim().is_empty()) {
+                    None => Ok(()),
+                    Some(line) => write!(f, " (it said: {})", line.trim()),
+                }
+            }
+        }
+    }
+}
+
+/// Run the reference verifier `cmd` on the files and collect its verdict.
+pub fn run_reference(cmd: &str, mmb: &Path, mm0: &Path) -> std::io::Result<Verdict> {
+    let fill = |word: &str| word.replace("{mmb}", &mmb.to_string_lossy()).replace("{mm0}", &mm0.to_string_lossy());
+    let mut words = cmd.split_whitespace().map(fill);
+    let program = words.next().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "the reference command is empty"))?;
+    let mut command = Command::new(program);
+    command.args(words).stdout(Stdio::piped()).stderr(Stdio::piped());
+    if cmd.contains("{mm0}") {
+        command.stdin(Stdio::null());
+    } else {
+        command.stdin(std::fs::File::open(mm0)?);
+    }
+    let out = command.output()?;
+    Ok(Verdict {
+        accepted: out.status.success(),
+        output: format!("{}{}", String::from_utf8_lossy(&out.stdout), String::from_utf8_lossy(&out.stderr)),
+    })
+}
+
+impl Comparison {
+    /// A divergence as an error, so that it fails the run like any other.
+    pub fn err(&self) -> Option<VerifErr> {
+        match self {
+            Comparison::Agree { .. } => None,
+            Comparison::Divergence { ours, theirs } => Some(VerifErr::Divergence {
+                ours: *ours,
+                theirs: theirs.accepted,
+                said: theirs.output.lines().find(|l| !l.trim().is_empty()).map(|l| l.trim().to_string()),
+            }),
+        }
+    }
+}
+
+pub fn compare(ours: bool, theirs: Verdict) -> Comparison {
+    if ours == theirs.accepted {
+        Comparison::Agree { accepted: ours }
+    } else {
+        Comparison::Divergence { ours, theirs }
+    }
+}
+
+#[test]
+fn reference1() {
+    let (mmb, mm0) = (Path::new("./test_resources/short.mmb"), Path::new("./test_resources/short.mm0"));
+    let theirs = run_reference("true {mmb}", mmb, mm0).unwrap();
+    assert_eq!(compare(true, theirs), Comparison::Agree { accepted: true });
+    // Without `{mm0}` in the command, the mm0 file comes in on stdin.
+    let theirs = run_reference("cat", mmb, mm0).unwrap();
+    assert_eq!(theirs.output, std::fs::read_to_string(mm0).unwrap());
+    let theirs = run_reference("false {mm0}", mmb, mm0).unwrap();
+    assert!(!theirs.accepted);
+    let cmp = compare(true, theirs);
+    assert!(matches!(cmp, Comparison::Divergence { ours: true, .. }));
+    assert!(cmp.to_string().starts_with("verifier divergence"));
+    assert_eq!(cmp.err().unwrap().code(), "E0503");
+    assert!(compare(false, Verdict { accepted: false, output: String::new() }).err().is_none());
+    assert!(run_reference("", mmb, mm0).is_err());
+}
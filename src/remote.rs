@@ -0,0 +1,433 @@
+//! Verification over the network, for farms that check large databases centrally: a
+//! server that verifies the mmb files it's sent and answers with their certificates, and a
+//! client for it. Messages are CBOR (see `cbor`), each preceded by its length as four
+//! big-endian bytes, over TCP. A request is a map that either sends the files:
+//!
+//! ```text
+//! {"id": 1, "mmb": h'4d4d3042..', "mm0": ["sort wff; ..", ..]}
+//! ```
+//!
+//! or names them by `submission_key`, the SHA-256 of the files' SHA-256s, to ask whether
+//! the server has already verified them:
+//!
+//! ```text
+//! {"id": 2, "sha256": h'..'}
+//! ```
+//!
+//! `mm0` is optional. The server answers each request with one map:
+//!
+//! ```text
+//! {"id": 1, "known": true, "ok": true, "errors": [], "certificate": {..}, "ms": 4}
+//! ```
+//!
+//! `errors` are maps `{"code": "E0203", "error": ".."}` (see `codes`), and the certificate
+//! is `null` unless verification passed. `known` is `false` only for a hash the server
+//! hasn't verified (it only remembers the latest `max_verified` files that passed), in which case the client sends
+//! the files themselves; `Client::verify` does both steps.
+//!
+//! Verification itself is `serve::Server::verify_with`, so requests are handled one at a
+//! time, and connections one after another, reusing the same arenas; this module is only
+//! the CBOR transport. A message longer than the receiver's `max_message` is refused, and
+//! the server drops a connection that goes quiet for `read_timeout` in the middle of one
+//! (or between them), as `serve` does, so one slow or hostile client can't hold it up for long.
+
+use core::convert::TryFrom;
+use std::collections::VecDeque;
+use std::io::{ Read, Write };
+use std::net::{ TcpListener, TcpStream, ToSocketAddrs };
+use std::time::Instant;
+use sha2::{ Sha256, Digest };
+use crate::cbor::{ self, Cbor };
+use crate::certificate::{ Certificate, CertSort, CertTerm, CertThm };
+use crate::fs::FileData;
+use crate::arena::BumpPool;
+use crate::options::VerifyOptions;
+use crate::util::{ Res, VerifErr, HashMap, Type, Mods };
+use crate::{ io_err, none_err };
+
+pub use crate::serve::{ DEFAULT_MAX_MESSAGE, DEFAULT_READ_TIMEOUT };
+
+/// How many passing submissions the server remembers by default.
+pub const DEFAULT_MAX_VERIFIED: usize = 1 << 12;
+
+/// Write `msg` with its length in front.
+pub fn write_message(writer: &mut impl Write, msg: &Cbor) -> std::io::Result<()> {
+    let bytes = msg.to_bytes();
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "message too large"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+/// Read one message of at most `max` bytes, or `None` if the other side hung up between
+/// messages.
+pub fn read_message(reader: &mut impl Read, max: u32) -> std::io::Result<Option<Cbor>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        res => res?,
+    }
+    let bad = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let len = u32::from_be_bytes(len);
+    if len > max {
+        return Err(bad(format!("a message of {} bytes is too large", len)))
+    }
+    let mut body = vec![0u8; len as usize];
+    reader.read_exact(&mut body)?;
+    cbor::parse(&body).map(Some).map_err(|e| bad(format!("{:?}", e)))
+}
+
+/// The key a submission of `mmb` and `mm0` is known by.
+pub fn submission_key(mmb: &[u8], mm0: &[String]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    h.update(Sha256::digest(mmb));
+    for mmz in mm0.iter() {
+        h.update(Sha256::digest(mmz.as_bytes()));
+    }
+    h.finalize().into()
+}
+
+fn types(args: &[Type]) -> Cbor {
+    Cbor::Arr(args.iter().map(|t| Cbor::UInt(t.bits())).collect())
+}
+
+pub fn certificate_to_cbor(cert: &Certificate) -> Cbor {
+    let sorts = cert.sorts.iter().map(|s| Cbor::map()
+        .with("num", s.num)
+        .with("name", s.name.clone())
+        .with("mods", s.mods.inner)
+    ).collect::<Vec<_>>();
+    let terms = cert.terms.iter().map(|t| Cbor::map()
+        .with("num", t.num)
+        .with("name", t.name.clone())
+        .with("args", types(&t.args))
+        .with("ret", t.ret.bits())
+        .with("def", t.def.clone())
+    ).collect::<Vec<_>>();
+    let thms = cert.thms.iter().map(|t| Cbor::map()
+        .with("num", t.num)
+        .with("name", t.name.clone())
+        .with("axiom", t.axiom)
+        .with("args", types(&t.args))
+        .with("hyps", t.hyps.iter().map(|h| Cbor::from(h.as_str())).collect::<Vec<_>>())
+        .with("concl", t.concl.as_str())
+    ).collect::<Vec<_>>();
+    Cbor::map().with("sorts", sorts).with("terms", terms).with("thms", thms)
+}
+
+pub fn certificate_from_cbor(c: &Cbor) -> Res<Certificate> {
+    fn arr<'c>(c: &'c Cbor, key: &str) -> Res<&'c [Cbor]> {
+        c.get(key).and_then(|v| v.as_arr()).ok_or_else(|| VerifErr::Msg(format!("certificate is missing the array {}", key)))
+    }
+    let num = |c: &Cbor, key: &str| c.get(key).and_then(|v| v.as_u64())
+        .ok_or_else(|| VerifErr::Msg(format!("certificate is missing the number {}", key)));
+    let text = |c: &Cbor, key: &str| -> Res<Option<String>> {
+        match c.get(key) {
+            Some(Cbor::Text(s)) => Ok(Some(s.clone())),
+            Some(Cbor::Null) => Ok(None),
+            _ => Err(VerifErr::Msg(format!("certificate is missing the string {}", key))),
+        }
+    };
+    let args = |c: &Cbor| -> Res<Vec<Type>> {
+        arr(c, "args")?.iter().map(|t| Type::from_bits(none_err!(t.as_u64())?)).collect()
+    };
+    let mut cert = Certificate::default();
+    for s in arr(c, "sorts")? {
+        cert.sorts.push(CertSort { num: num(s, "num")? as u8, name: text(s, "name")?, mods: Mods { inner: num(s, "mods")? as u8 } });
+    }
+    for t in arr(c, "terms")? {
+        cert.terms.push(CertTerm {
+            num: num(t, "num")? as u32,
+            name: text(t, "name")?,
+            args: args(t)?,
+            ret: Type::from_bits(num(t, "ret")?)?,
+            def: text(t, "def")?,
+        });
+    }
+    for t in arr(c, "thms")? {
+        cert.thms.push(CertThm {
+            num: num(t, "num")? as u32,
+            name: text(t, "name")?,
+            axiom: none_err!(t.get("axiom").and_then(|a| a.as_bool()))?,
+            args: args(t)?,
+            hyps: arr(t, "hyps")?.iter().map(|h| h.as_str().map(|s| s.to_string()).ok_or(VerifErr::Msg("a hypothesis isn't a string".to_string()))).collect::<Res<_>>()?,
+            concl: none_err!(text(t, "concl")?)?,
+        });
+    }
+    Ok(cert)
+}
+
+pub struct Server {
+    /// What verifies each submission, with the limits on requests.
+    pub core: crate::serve::Server,
+    /// The certificate of each of the latest `max_verified` submissions that passed, by
+    /// `submission_key`.
+    pub verified: HashMap<[u8; 32], Certificate>,
+    /// The keys in `verified`, oldest first.
+    order: VecDeque<[u8; 32]>,
+    pub max_verified: usize,
+}
+
+/// An error as it goes in a response.
+fn error_to_cbor(e: &VerifErr) -> Cbor {
+    Cbor::map().with("code", e.code()).with("error", format!("{:?}", e))
+}
+
+impl Server {
+    pub fn new(options: VerifyOptions, num_threads: usize, arenas: BumpPool) -> Self {
+        Server {
+            core: crate::serve::Server::new(options, num_threads, arenas),
+            verified: HashMap::new(),
+            order: VecDeque::new(),
+            max_verified: DEFAULT_MAX_VERIFIED,
+        }
+    }
+
+    /// The certificate if `file_data` verifies, and otherwise its errors.
+    fn verify(&mut self, file_data: &FileData) -> Result<Certificate, Vec<Cbor>> {
+        let res = self.core.verify_with(file_data, |outline, errs| match errs.is_empty() {
+            true => outline.certificate().map_err(|e| vec![error_to_cbor(&e)]),
+            false => Err(errs.iter().map(error_to_cbor).collect()),
+        });
+        res.unwrap_or_else(|e| Err(vec![error_to_cbor(&e)]))
+    }
+
+    /// Answer one request.
+    pub fn handle(&mut self, request: &Cbor) -> Cbor {
+        let start = Instant::now();
+        let id = request.get("id").cloned().unwrap_or(Cbor::Null);
+        let response = |known: bool, errs: Vec<Cbor>, cert: Option<&Certificate>| Cbor::map()
+            .with("id", id.clone())
+            .with("known", known)
+            .with("ok", known && errs.is_empty())
+            .with("errors", errs)
+            .with("certificate", cert.map(certificate_to_cbor))
+            .with("ms", start.elapsed().as_millis() as u64);
+        if let Some(hash) = request.get("sha256").and_then(|h| h.as_bytes()) {
+            return match self.verified.get(hash) {
+                Some(cert) => response(true, Vec::new(), Some(cert)),
+                None => response(false, Vec::new(), None),
+            }
+        }
+        let mmb = match request.get("mmb").and_then(|m| m.as_bytes()) {
+            Some(mmb) => mmb.to_vec(),
+            None => {
+                let e = VerifErr::Msg("a request needs either an `mmb` or a `sha256` field".to_string());
+                return response(true, vec![error_to_cbor(&e)], None)
+            }
+        };
+        let mm0 = request.get("mm0").and_then(|m| m.as_arr()).unwrap_or(&[]).iter().filter_map(|m| m.as_str()).map(|m| m.to_string()).collect::<Vec<_>>();
+        let key = submission_key(&mmb, &mm0);
+        match self.verify(&FileData::new_from_bytes(mmb, mm0)) {
+            Ok(cert) => {
+                let out = response(true, Vec::new(), Some(&cert));
+                self.remember(key, cert);
+                out
+            }
+            Err(errs) => response(true, errs, None),
+        }
+    }
+
+    /// Add a submission that passed to `verified`, forgetting the oldest ones past
+    /// `max_verified`.
+    fn remember(&mut self, key: [u8; 32], cert: Certificate) {
+        if self.verified.insert(key, cert).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.max_verified {
+            if let Some(old) = self.order.pop_front() {
+                self.verified.remove(&old);
+            }
+        }
+    }
+
+    /// Answer requests on `stream` until the client hangs up.
+    pub fn serve_stream(&mut self, mut stream: impl Read + Write) -> std::io::Result<()> {
+        while let Some(request) = read_message(&mut stream, self.core.max_message)? {
+            let response = self.handle(&request);
+            write_message(&mut stream, &response)?;
+        }
+        Ok(())
+    }
+
+    pub fn serve_tcp(&mut self, addr: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            // A client that hangs up early, stalls, or sends garbage shouldn't take the
+            // server down.
+            let _ = self.serve_connection(stream?);
+        }
+        Ok(())
+    }
+
+    /// `serve_stream` on a TCP connection, with the read timeout.
+    pub fn serve_connection(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(self.core.read_timeout)?;
+        self.serve_stream(stream)
+    }
+}
+
+/// A server's verdict on a submission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    /// The code and description of each error.
+    pub errors: Vec<(String, String)>,
+    /// Present if verification passed.
+    pub certificate: Option<Certificate>,
+    /// Whether the server answered from what it had already verified.
+    pub cached: bool,
+}
+
+pub struct Client<S> {
+    pub stream: S,
+    /// The largest response accepted, in bytes.
+    pub max_message: u32,
+    next_id: u64,
+}
+
+impl Client<TcpStream> {
+    pub fn connect(addr: impl ToSocketAddrs) -> Res<Self> {
+        Ok(Client::new(io_err!(TcpStream::connect(addr))?))
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    pub fn new(stream: S) -> Self {
+        Client { stream, max_message: DEFAULT_MAX_MESSAGE, next_id: 0 }
+    }
+
+    /// Send `request` (with an `id` added) and wait for the answer.
+    pub fn request(&mut self, request: Cbor) -> Res<Cbor> {
+        self.next_id += 1;
+        let request = match request {
+            Cbor::Map(fields) => Cbor::Map(Some(("id".to_string(), Cbor::UInt(self.next_id))).into_iter().chain(fields).collect()),
+            other => other,
+        };
+        io_err!(write_message(&mut self.stream, &request))?;
+        let response = none_err!(io_err!(read_message(&mut self.stream, self.max_message))?)?;
+        if response.get("id") != Some(&Cbor::UInt(self.next_id)) {
+            return Err(VerifErr::Msg("the server answered a different request".to_string()))
+        }
+        Ok(response)
+    }
+
+    /// Have the server verify `mmb` against `mm0`, asking by hash first so that a file
+    /// it has already verified isn't sent again.
+    pub fn verify(&mut self, mmb: &[u8], mm0: &[String]) -> Res<Verdict> {
+        let key = submission_key(mmb, mm0).to_vec();
+        let mut response = self.request(Cbor::map().with("sha256", key))?;
+        let cached = response.get("known").and_then(|k| k.as_bool()) == Some(true);
+        if !cached {
+            let mm0 = mm0.iter().map(|m| Cbor::from(m.as_str())).collect::<Vec<_>>();
+            response = self.request(Cbor::map().with("mmb", mmb.to_vec()).with("mm0", mm0))?;
+        }
+        let errors = response.get("errors").and_then(|e| e.as_arr()).unwrap_or(&[]).iter().map(|e| (
+            e.get("code").and_then(|c| c.as_str()).unwrap_or("").to_string(),
+            e.get("error").and_then(|c| c.as_str()).unwrap_or("").to_string(),
+        )).collect();
+        let certificate = match response.get("certificate") {
+            None | Some(Cbor::Null) => None,
+            Some(cert) => Some(certificate_from_cbor(cert)?),
+        };
+        Ok(Verdict { errors, certificate, cached })
+    }
+}
+
+#[test]
+fn remote1() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let mut server = Server::new(VerifyOptions::default(), 1, BumpPool::default());
+        let (stream, _) = listener.accept().unwrap();
+        server.serve_stream(stream).unwrap();
+        server.verified.len()
+    });
+    let file_data = FileData::new_from("./test_resources/short.mmb", Some("./test_resources/short.mm0")).unwrap();
+    let mut client = Client::connect(addr).unwrap();
+    let first = client.verify(&file_data.mmb_file, &file_data.mmz_files).unwrap();
+    assert!(first.errors.is_empty() && !first.cached);
+    // The certificate comes back as it was made.
+    let outline = crate::Outline::new_from(&file_data).unwrap();
+    assert_eq!(first.certificate, Some(outline.certificate().unwrap()));
+    let second = client.verify(&file_data.mmb_file, &file_data.mmz_files).unwrap();
+    assert!(second.cached);
+    assert_eq!(second.certificate, first.certificate);
+    // A file that fails has errors and no certificate.
+    let mut fx = crate::testgen::Fixture::valid();
+    fx.stmts.pop();
+    let failed = client.verify(&fx.to_bytes(), &[]).unwrap();
+    assert!(!failed.errors.is_empty() && failed.certificate.is_none());
+    drop(client);
+    assert_eq!(server.join().unwrap(), 1);
+}
+
+#[test]
+fn remote_par1() {
+    // `mm0` is optional, whatever the number of threads.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let mut server = Server::new(VerifyOptions::default(), 2, BumpPool::default());
+        let (stream, _) = listener.accept().unwrap();
+        server.serve_stream(stream).unwrap();
+    });
+    let mut client = Client::connect(addr).unwrap();
+    let verdict = client.verify(&crate::testgen::Fixture::valid().to_bytes(), &[]).unwrap();
+    assert!(verdict.errors.is_empty() && verdict.certificate.is_some());
+    drop(client);
+    server.join().unwrap();
+}
+
+#[test]
+fn remote_limits1() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let mut server = Server::new(VerifyOptions::default(), 1, BumpPool::default());
+        server.core.max_message = 1 << 10;
+        server.core.read_timeout = Some(std::time::Duration::from_millis(50));
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            results.push(server.serve_connection(stream).map_err(|e| e.kind()));
+        }
+        results
+    });
+    // A file bigger than the server takes is refused before it's read.
+    let mut client = Client::connect(addr).unwrap();
+    assert!(client.request(Cbor::map().with("mmb", vec![0u8; 1 << 11])).is_err());
+    // A client that stops in the middle of a message is dropped.
+    let mut stalled = TcpStream::connect(addr).unwrap();
+    stalled.write_all(&[0, 0]).unwrap();
+    let results = server.join().unwrap();
+    assert_eq!(results[0], Err(std::io::ErrorKind::InvalidData));
+    assert!(matches!(results[1], Err(std::io::ErrorKind::WouldBlock) | Err(std::io::ErrorKind::TimedOut)));
+    drop(stalled);
+}
+
+#[test]
+fn remote_remember1() {
+    let mut server = Server::new(VerifyOptions::default(), 1, BumpPool::default());
+    server.max_verified = 2;
+    for i in 0..4u8 {
+        server.remember([i; 32], Certificate::default());
+    }
+    server.remember([3; 32], Certificate::default());
+    assert_eq!(server.verified.len(), 2);
+    assert!(server.verified.contains_key(&[2; 32]) && server.verified.contains_key(&[3; 32]));
+
+    // Types that aren't well-formed don't make it into a certificate.
+    let cert = |ret: u64| Cbor::map()
+        .with("sorts", Vec::<Cbor>::new())
+        .with("terms", vec![Cbor::map()
+            .with("num", 0u64)
+            .with("name", Cbor::Null)
+            .with("args", Vec::<Cbor>::new())
+            .with("ret", ret)
+            .with("def", Cbor::Null)])
+        .with("thms", Vec::<Cbor>::new());
+    assert!(certificate_from_cbor(&cert(0)).is_ok());
+    assert!(certificate_from_cbor(&cert(1 << 63)).is_err());
+}
@@ -0,0 +1,104 @@
+//! A canonical description of what a verification run checked, which independent verifiers
+//! can produce and compare byte for byte. The report is compact JSON of the form
+//!```text
+//! {"mmb_sha256":"..","complete":true,"declarations":[{"kind":"sort","num":0,"name":"wff","hash":".."},..],"digest":".."}
+//!```
+//! `declarations` holds every declaration that was verified, sorted by kind (sorts, then
+//! terms and defs, then axioms and theorems) and then by number. The hashes are the statement
+//! hashes from `env` (for sorts, the SHA-256 of the modifier byte). `digest` is the SHA-256
+//! of the report as written before `digest` itself is added.
+//!
+//! A run that picked up from a checkpoint only lists what it verified itself, is never
+//! `complete`, and says where it started with `"resumed_at": <declaration index>`: a
+//! checkpoint is only as trustworthy as whoever could have written it.
+
+use crate::prelude::*;
+use sha2::{ Sha256, Digest };
+use crate::Outline;
+use crate::env::hex;
+use crate::json::Json;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+impl<'a> Outline<'a> {
+    /// The report for a run that verified `decls`. This doesn't check that verification
+    /// actually succeeded; that's up to the caller.
+    pub fn verification_report(&self, decls: &[(StmtCmd, ProofIter<'a>)]) -> Res<Json> {
+        self.report_from(decls, None)
+    }
+
+    /// The report for a run that resumed from a checkpoint at declaration `resumed_at`
+    /// and then verified `decls`.
+    pub fn resumed_report(&self, decls: &[(StmtCmd, ProofIter<'a>)], resumed_at: usize) -> Res<Json> {
+        self.report_from(decls, Some(resumed_at))
+    }
+
+    fn report_from(&self, decls: &[(StmtCmd, ProofIter<'a>)], resumed_at: Option<usize>) -> Res<Json> {
+        let mut entries = Vec::with_capacity(decls.len());
+        for (stmt, _) in decls.iter() {
+            let entry = match *stmt {
+                StmtCmd::Sort { num } => {
+                    let num = none_err!(num)?;
+                    let mods = self.get_sort_mods(num as usize)?.inner;
+                    (0u8, num as u32, "sort", self.sort_name(num), Sha256::digest([mods]).into())
+                }
+                StmtCmd::TermDef { num, .. } => {
+                    let num = none_err!(num)?;
+                    let kind = if self.get_term_by_num(num)?.is_def() { "def" } else { "term" };
+                    (1, num, kind, self.term_name(num), self.term_hash(num)?)
+                }
+                StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => {
+                    let num = none_err!(num)?;
+                    let kind = if let StmtCmd::Axiom {..} = stmt { "axiom" } else { "theorem" };
+                    (2, num, kind, self.assert_name(num), self.assert_hash(num)?)
+                }
+            };
+            entries.push(entry);
+        }
+        entries.sort_by_key(|(table, num, ..)| (*table, *num));
+
+        let declarations: Vec<Json> = entries.into_iter().map(|(_, num, kind, name, hash)| {
+            Json::obj()
+                .with("kind", kind)
+                .with("num", num)
+                .with("name", name)
+                .with("hash", hex(&hash))
+        }).collect();
+
+        let mut report = Json::obj()
+            .with("mmb_sha256", hex(&Sha256::digest(self.mmb())))
+            .with("complete", resumed_at.is_none() && decls.len() == self.declarations.len());
+        if let Some(n) = resumed_at {
+            report = report.with("resumed_at", n);
+        }
+        let report = report.with("declarations", declarations);
+        let digest = hex(&Sha256::digest(report.to_string().as_bytes()));
+        Ok(report.with("digest", digest))
+    }
+}
+
+#[test]
+fn verification_report1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let full = outline.verification_report(&outline.declarations).unwrap();
+    assert_eq!(full.get("complete"), Some(&Json::Bool(true)));
+    assert_eq!(full.get("declarations").and_then(|d| d.as_arr()).map(|d| d.len()), Some(7));
+
+    // The order declarations were checked in doesn't matter.
+    let mut shuffled = outline.declarations.clone();
+    shuffled.reverse();
+    assert_eq!(outline.verification_report(&shuffled).unwrap().to_string(), full.to_string());
+
+    let partial = outline.verification_report(&outline.declarations[..3]).unwrap();
+    assert_eq!(partial.get("complete"), Some(&Json::Bool(false)));
+    assert_ne!(partial.get("digest"), full.get("digest"));
+    assert_eq!(partial.get("mmb_sha256"), full.get("mmb_sha256"));
+
+    // Resuming, even from the very start, doesn't count as checking everything.
+    let resumed = outline.resumed_report(&outline.declarations, 0).unwrap();
+    assert_eq!(resumed.get("complete"), Some(&Json::Bool(false)));
+    assert_eq!(resumed.get("resumed_at"), Some(&Json::UInt(0)));
+}
@@ -0,0 +1,111 @@
+//! Random spot-checking. Instead of verifying every declaration in the mmb file, pick
+//! each one with probability `p` and verify the picked declarations together with
+//! everything they (transitively) depend on. The result is only a smoke test, so
+//! callers should make it clear to users that the verification was partial.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::util::{ Res, VerifErr };
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+
+/// A small seedable PRNG (splitmix64). We only need something cheap and reproducible
+/// so that a reported seed can be used to rerun exactly the same sample.
+#[derive(Debug, Clone, Copy)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A float uniformly distributed in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Make a seed from the clock for when the user didn't ask for one.
+#[cfg(feature = "std")]
+pub fn fresh_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample<'a> {
+    pub seed: u64,
+    /// The number of declarations picked at random, before adding dependencies.
+    pub num_picked: usize,
+    /// The picked declarations plus their dependencies, in declaration order.
+    pub declarations: Vec<(StmtCmd, ProofIter<'a>)>,
+}
+
+impl<'a> Outline<'a> {
+    /// Pick each term/def/assertion with probability `p`, then close the selection
+    /// under dependencies. Sorts are always included.
+    pub fn sample(&self, p: f64, seed: u64) -> Res<Sample<'a>> {
+        if !(p > 0.0 && p <= 1.0) {
+            return Err(VerifErr::Msg(format!("sample probability must be in (0, 1]; got {}", p)))
+        }
+        let mut rng = SplitMix64::new(seed);
+        let mut picked = Vec::new();
+        let mut num_picked = 0;
+        for (idx, (stmt, _)) in self.declarations.iter().enumerate() {
+            if let StmtCmd::Sort {..} = stmt {
+                picked.push(idx);
+            } else if rng.next_f64() < p {
+                num_picked += 1;
+                picked.push(idx);
+            }
+        }
+        let selected = self.with_deps(picked)?;
+
+        let declarations = self.declarations
+            .iter()
+            .zip(selected)
+            .filter(|(_, keep)| *keep)
+            .map(|(decl, _)| *decl)
+            .collect();
+
+        Ok(Sample { seed, num_picked, declarations })
+    }
+}
+
+#[test]
+fn splitmix_reproducible1() {
+    let mut a = SplitMix64::new(42);
+    let mut b = SplitMix64::new(42);
+    for _ in 0..100 {
+        let x = a.next_f64();
+        assert!((0.0..1.0).contains(&x));
+        assert_eq!(x, b.next_f64());
+    }
+}
+
+#[test]
+fn sample_closed1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let full = outline.sample(1.0, 0).unwrap();
+    assert_eq!(full.declarations.len(), outline.declarations.len());
+    // Every axiom in `short` mentions `im`, so any sample that picks an axiom must also
+    // contain the term declaration for `im`.
+    let some = outline.sample(0.5, 7).unwrap();
+    let (term_pos, _) = outline.decl_positions();
+    let has_axiom = some.declarations.iter().any(|(s, _)| matches!(s, StmtCmd::Axiom {..}));
+    let has_im = some.declarations.iter().any(|(s, _)| *s == outline.declarations[term_pos[0]].0);
+    assert!(!has_axiom || has_im);
+}
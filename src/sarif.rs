@@ -0,0 +1,108 @@
+//! Errors as a SARIF log (the Static Analysis Results Interchange Format, version 2.1.0),
+//! which GitHub code scanning and other tools read, so a failed verification in CI shows up
+//! in their UIs like any other analysis. Each error code is a rule, with `--explain`'s text
+//! as its description, and each error a result:
+//!```text
+//! {"ruleId":"E0201","level":"error","message":{"text":"ax_mp: .."},"locations":[..]}
+//!```
+//! An error about a declaration is located in the mm0 file at the row and column the index
+//! gives for it (1-based, as SARIF counts), and in the mmb file at the byte offset of the
+//! declaration's proof. Other errors are located in the mmb file as a whole.
+
+use crate::Outline;
+use crate::codes::CODES;
+use crate::json::Json;
+use crate::lsp::Located;
+
+fn artifact(uri: &str) -> Json {
+    Json::obj().with("uri", uri)
+}
+
+fn rule(idx: usize) -> Json {
+    let code = &CODES[idx];
+    Json::obj()
+        .with("id", code.code)
+        .with("name", format!("{:?}", code.kind))
+        .with("shortDescription", Json::obj().with("text", code.title))
+        .with("fullDescription", Json::obj().with("text", code.rule))
+        .with("defaultConfiguration", Json::obj().with("level", "error"))
+}
+
+impl<'a> Outline<'a> {
+    /// `located` (from `verify_located`) as a SARIF log, with the mmb and mm0 files
+    /// referred to by `mmb_uri` and `mm0_uri` (relative paths are fine).
+    pub fn sarif(&self, located: &[Located], mmb_uri: &str, mm0_uri: Option<&str>) -> Json {
+        let results = located.iter().map(|l| {
+            let code = l.err.code();
+            let name = l.decl.and_then(|stmt| self.stmt_name(stmt));
+            let msg = match name {
+                Some(name) => format!("{}: {:?}", name, l.err),
+                None => format!("{:?}", l.err),
+            };
+            let mut locations = Vec::new();
+            let proof_pos = l.decl.and_then(|stmt| self.declarations.iter().find(|(s, _)| *s == stmt)).map(|(_, proof)| proof.pos);
+            if let (Some(mm0_uri), true) = (mm0_uri, l.decl.and_then(|stmt| self.stmt_index_entry(stmt)).is_some()) {
+                let region = Json::obj().with("startLine", l.line + 1).with("startColumn", l.col + 1);
+                locations.push(Json::obj().with("physicalLocation", Json::obj()
+                    .with("artifactLocation", artifact(mm0_uri))
+                    .with("region", region)));
+            }
+            let mut mmb = Json::obj().with("artifactLocation", artifact(mmb_uri));
+            if let Some(pos) = proof_pos {
+                mmb = mmb.with("region", Json::obj().with("byteOffset", pos));
+            }
+            let mut location = Json::obj().with("physicalLocation", mmb);
+            if let Some(name) = name {
+                location = location.with("logicalLocations", vec![Json::obj().with("name", name)]);
+            }
+            locations.push(location);
+            let mut result = Json::obj()
+                .with("ruleId", code)
+                .with("level", "error")
+                .with("message", Json::obj().with("text", msg))
+                .with("locations", locations);
+            if let Some(idx) = CODES.iter().position(|c| c.code == code) {
+                result = result.with("ruleIndex", idx);
+            }
+            result
+        }).collect::<Vec<_>>();
+
+        let driver = Json::obj()
+            .with("name", "second_opinion")
+            .with("version", env!("CARGO_PKG_VERSION"))
+            .with("rules", (0..CODES.len()).map(rule).collect::<Vec<_>>());
+        let run = Json::obj()
+            .with("tool", Json::obj().with("driver", driver))
+            .with("results", results);
+        Json::obj()
+            .with("$schema", "https://json.schemastore.org/sarif-2.1.0.json")
+            .with("version", "2.1.0")
+            .with("runs", vec![run])
+    }
+}
+
+#[test]
+fn sarif1() {
+    use crate::testgen::{ Fixture, stream };
+    use crate::mmb::proof::{ PROOF_REF, PROOF_TERM };
+    let mut fx = Fixture::valid();
+    // The axiom leaves an extra `a` on the stack.
+    fx.stmts[2].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0)]);
+    fx.with_outline(|outline| {
+        let located = outline.verify_located(&outline.declarations);
+        let log = outline.sarif(&located, "fx.mmb", None);
+        let log = crate::json::parse(&log.to_string()).unwrap();
+        assert_eq!(log.get("version").and_then(|v| v.as_str()), Some("2.1.0"));
+        let run = &log.get("runs").and_then(|r| r.as_arr()).unwrap()[0];
+        let rules = run.get("tool").and_then(|t| t.get("driver")).and_then(|d| d.get("rules")).and_then(|r| r.as_arr()).unwrap();
+        assert_eq!(rules.len(), CODES.len());
+        let results = run.get("results").and_then(|r| r.as_arr()).unwrap();
+        assert_eq!(results.len(), located.len());
+        let result = &results[0];
+        let idx = result.get("ruleIndex").and_then(|i| i.as_u64()).unwrap() as usize;
+        assert_eq!(rules[idx].get("id").and_then(|i| i.as_str()), result.get("ruleId").and_then(|i| i.as_str()));
+        // Located at the axiom's proof in the mmb file.
+        let region = result.get("locations").and_then(|l| l.as_arr()).unwrap()[0].get("physicalLocation").and_then(|p| p.get("region")).unwrap();
+        assert_eq!(region.get("byteOffset").and_then(|o| o.as_u64()), Some(outline.declarations[2].1.pos as u64));
+    });
+}
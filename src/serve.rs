@@ -0,0 +1,286 @@
+//! A long-running verifier that answers requests over a socket, for editor integrations
+//! that want to re-verify a file after every save without paying for process startup.
+//!
+//! The protocol is line-delimited JSON. Each request is one object on one line, naming
+//! the file to check either by path or by contents:
+//!
+//! ```text
+//! {"id": 1, "path": "foo.mmb", "mm0": "foo.mm0"}
+//! {"id": 2, "hex": "4d4d3042..."}
+//! ```
+//!
+//! `mm0` is optional, and defaults the way it does on the command line. A file given as
+//! `hex` is checked without an mm0 file. The `id` is copied into every response line, so
+//! it can be anything. The server answers each request with one line per error, like
+//! `{"id": 1, "code": "E0203", "error": "..."}` (see `codes`), as they're found, and then a summary line:
+//!
+//! ```text
+//! {"id": 1, "ok": false, "sorts": 1, "terms": 2, "thms": 3, "errors": 1, "ms": 4}
+//! ```
+//!
+//! A `path` is only read over a Unix socket, where the socket's permissions decide who
+//! can ask, or from under the server's `root` directory; otherwise anyone who can reach a
+//! TCP port could have the server read any file it can.
+//!
+//! Requests are handled one at a time, and connections one after another, so that every
+//! request can reuse the arenas the previous one warmed up. So that one client can't hold
+//! everyone else up for long, or run the server out of memory, a request line longer than
+//! `max_message` is refused, and a connection that goes quiet for `read_timeout` is
+//! dropped. `Server::verify_with` is the part that doesn't depend on the protocol, which
+//! `remote` speaks CBOR around.
+
+use std::io::{ BufRead, BufReader, Read, Write };
+use std::net::{ TcpListener, TcpStream };
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, Instant };
+use crate::Outline;
+use crate::fs::FileData;
+use crate::json::{ self, Json };
+use crate::arena::BumpPool;
+use crate::options::VerifyOptions;
+use crate::util::{ Res, VerifErr };
+use crate::env::unhex;
+use crate::io_err;
+
+/// The largest request accepted by default, in bytes.
+pub const DEFAULT_MAX_MESSAGE: u32 = 64 << 20;
+
+/// How long to wait for the next part of a request by default.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct Server {
+    pub options: VerifyOptions,
+    pub num_threads: usize,
+    /// Handed to each request's `Outline` and taken back afterwards.
+    pub arenas: BumpPool,
+    /// The largest request accepted, in bytes.
+    pub max_message: u32,
+    /// How long to wait for the next part of a request before dropping the connection.
+    pub read_timeout: Option<Duration>,
+    /// The directory `path` requests may read from. Without one, they're only answered
+    /// over a Unix socket.
+    pub root: Option<PathBuf>,
+    /// Set while serving a Unix socket.
+    local: bool,
+}
+
+/// `path`, resolved against `root`, as long as that doesn't leave it.
+fn under_root(root: &Path, path: &str) -> Res<PathBuf> {
+    let root = io_err!(root.canonicalize())?;
+    let full = io_err!(root.join(path).canonicalize())?;
+    if full.starts_with(&root) {
+        Ok(full)
+    } else {
+        Err(VerifErr::Msg(format!("{} is outside the server's root", path)))
+    }
+}
+
+/// Read one line of at most `max` bytes, without its line ending, or `None` at the end
+/// of the input.
+fn read_line(reader: &mut impl BufRead, max: u32) -> std::io::Result<Option<String>> {
+    let bad = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let mut line = Vec::new();
+    if reader.by_ref().take(u64::from(max) + 1).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None)
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() > max as usize {
+        return Err(bad(format!("a request of more than {} bytes is too large", max)))
+    }
+    String::from_utf8(line).map(Some).map_err(|_| bad("a request isn't UTF-8".to_string()))
+}
+
+impl Server {
+    pub fn new(options: VerifyOptions, num_threads: usize, arenas: BumpPool) -> Self {
+        Server {
+            options,
+            num_threads,
+            arenas,
+            max_message: DEFAULT_MAX_MESSAGE,
+            read_timeout: Some(DEFAULT_READ_TIMEOUT),
+            root: None,
+            local: false,
+        }
+    }
+
+    fn load(&self, request: &Json) -> Res<FileData> {
+        if let Some(hex) = request.get("hex").and_then(|h| h.as_str()) {
+            return Ok(FileData::new_from_bytes(unhex(hex)?, Vec::new()))
+        }
+        let path = match request.get("path").and_then(|p| p.as_str()) {
+            None => return Err(VerifErr::Msg("a request needs either a `path` or `hex` field".to_string())),
+            Some(path) => path,
+        };
+        let mm0 = request.get("mm0").and_then(|p| p.as_str());
+        match &self.root {
+            Some(root) => {
+                let path = under_root(root, path)?;
+                let mm0 = match mm0 {
+                    Some(mm0) => Some(under_root(root, mm0)?),
+                    None => {
+                        // The mm0 file next to it could be a link to somewhere else.
+                        let default = crate::compress::default_mm0_path(&path);
+                        if default.exists() {
+                            under_root(root, &default.to_string_lossy())?;
+                        }
+                        None
+                    }
+                };
+                FileData::new_from(path, mm0)
+            }
+            None if self.local => FileData::new_from(path, mm0),
+            None => Err(VerifErr::Msg("this server only reads files by path over a Unix socket, or from under its root".to_string())),
+        }
+    }
+
+    /// Verify `file_data` with the server's options, threads and arenas, and pass the
+    /// outline and its errors to `f`. Only a file too malformed to outline is an error.
+    pub fn verify_with<T>(&mut self, file_data: &FileData, f: impl FnOnce(&Outline, &[VerifErr]) -> T) -> Res<T> {
+        let mut outline = Outline::new_from(file_data)?;
+        outline.options = self.options;
+        outline.arenas = core::mem::take(&mut self.arenas);
+        let errs = if self.num_threads > 1 {
+            crate::verify_par(&outline, &outline.declarations, self.num_threads)
+        } else {
+            crate::verify_serial(&outline, &outline.declarations)
+        };
+        let out = f(&outline, &errs);
+        drop(errs);
+        self.arenas = core::mem::take(&mut outline.arenas);
+        Ok(out)
+    }
+
+    /// Answer one request, passing each response line to `emit` as it's ready.
+    pub fn handle(&mut self, request: &str, mut emit: impl FnMut(Json)) {
+        let start = Instant::now();
+        let request = json::parse(request);
+        let id = request.as_ref().ok().and_then(|r| r.get("id")).cloned().unwrap_or(Json::Null);
+        let res = request.and_then(|r| self.load(&r)).and_then(|file_data| self.verify_with(&file_data, |outline, errs| {
+            for e in errs.iter() {
+                emit(Json::obj().with("id", id.clone()).with("code", e.code()).with("error", format!("{:?}", e)));
+            }
+            Json::obj()
+                .with("id", id.clone())
+                .with("ok", errs.is_empty())
+                .with("sorts", outline.header.num_sorts)
+                .with("terms", outline.header.num_terms)
+                .with("thms", outline.header.num_thms)
+                .with("errors", errs.len())
+                .with("ms", start.elapsed().as_millis() as u64)
+        }));
+        match res {
+            Ok(summary) => emit(summary),
+            Err(e) => {
+                emit(Json::obj().with("id", id.clone()).with("code", e.code()).with("error", format!("{:?}", e)));
+                emit(Json::obj().with("id", id).with("ok", false).with("errors", 1u64));
+            }
+        }
+    }
+
+    /// Answer requests from `reader` until it's closed, writing responses to `writer`.
+    pub fn serve_stream(&mut self, mut reader: impl BufRead, mut writer: impl Write) -> std::io::Result<()> {
+        while let Some(line) = read_line(&mut reader, self.max_message)? {
+            if line.trim().is_empty() {
+                continue
+            }
+            let mut result = Ok(());
+            self.handle(&line, |response| {
+                if result.is_ok() {
+                    result = writeln!(writer, "{}", response).and_then(|_| writer.flush());
+                }
+            });
+            result?;
+        }
+        Ok(())
+    }
+
+    pub fn serve_tcp(&mut self, addr: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            // A client that hangs up early, stalls, or sends garbage shouldn't take the
+            // server down.
+            let _ = self.serve_connection(stream?);
+        }
+        Ok(())
+    }
+
+    /// `serve_stream` on a TCP connection, with the read timeout.
+    pub fn serve_connection(&mut self, stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(self.read_timeout)?;
+        self.serve_stream(BufReader::new(stream.try_clone()?), stream)
+    }
+
+    #[cfg(unix)]
+    pub fn serve_unix(&mut self, path: &str) -> std::io::Result<()> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        self.local = true;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            stream.set_read_timeout(self.read_timeout)?;
+            let _ = self.serve_stream(BufReader::new(stream.try_clone()?), stream);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn serve1() {
+    let bytes = crate::testgen::Fixture::valid().to_bytes();
+    let requests = format!(
+        "{{\"id\": 1, \"hex\": \"{}\"}}\n\n{{\"id\": \"b\", \"path\": \"./test_resources/short.mmb\"}}\n{{\"id\": 3}}\n",
+        crate::env::hex(&bytes)
+    );
+    let mut server = Server::new(VerifyOptions::default(), 1, BumpPool::default());
+    server.root = Some(PathBuf::from("."));
+    let mut out = Vec::new();
+    server.serve_stream(requests.as_bytes(), &mut out).unwrap();
+    let lines = String::from_utf8(out).unwrap()
+        .lines()
+        .map(|l| json::parse(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0].get("id"), Some(&Json::UInt(1)));
+    assert_eq!(lines[0].get("ok").and_then(|b| b.as_bool()), Some(true));
+    assert_eq!(lines[1].get("id").and_then(|i| i.as_str()), Some("b"));
+    assert_eq!(lines[1].get("ok").and_then(|b| b.as_bool()), Some(true));
+    assert!(lines[2].get("error").is_some());
+    assert_eq!(lines[3].get("ok").and_then(|b| b.as_bool()), Some(false));
+    assert!(server.arenas.num_free() > 0);
+}
+
+#[test]
+fn serve_par1() {
+    // Without an mm0 file, there's no mmz pass for the extra threads to run alongside.
+    let bytes = crate::testgen::Fixture::valid().to_bytes();
+    let file_data = FileData::new_from_bytes(bytes.clone(), Vec::new());
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(crate::verify_par(&outline, &outline.declarations, 2).is_empty());
+    let mut server = Server::new(VerifyOptions::default(), 2, BumpPool::default());
+    let mut out = Vec::new();
+    server.serve_stream(format!("{{\"id\": 1, \"hex\": \"{}\"}}\n", crate::env::hex(&bytes)).as_bytes(), &mut out).unwrap();
+    let summary = json::parse(String::from_utf8(out).unwrap().trim()).unwrap();
+    assert_eq!(summary.get("ok").and_then(|b| b.as_bool()), Some(true));
+}
+
+#[test]
+fn serve_limits1() {
+    let mut server = Server::new(VerifyOptions::default(), 1, BumpPool::default());
+    // No paths from just anyone, and none outside the root.
+    let answer = |server: &mut Server, request: &str| {
+        let mut out = Vec::new();
+        server.serve_stream(request.as_bytes(), &mut out).unwrap();
+        json::parse(String::from_utf8(out).unwrap().lines().next().unwrap()).unwrap()
+    };
+    let first = answer(&mut server, "{\"id\": 1, \"path\": \"./test_resources/short.mmb\"}\n");
+    assert!(first.get("error").and_then(|e| e.as_str()).unwrap().contains("Unix socket"));
+    server.root = Some(PathBuf::from("./test_resources"));
+    assert_eq!(answer(&mut server, "{\"id\": 1, \"path\": \"short.mmb\"}\n").get("ok").and_then(|b| b.as_bool()), Some(true));
+    let outside = answer(&mut server, "{\"id\": 1, \"path\": \"../Cargo.toml\"}\n");
+    assert!(outside.get("error").and_then(|e| e.as_str()).unwrap().contains("outside the server's root"));
+
+    // A line longer than the limit is refused without being read in full.
+    server.max_message = 16;
+    let err = server.serve_stream(&[b'['; 100][..], &mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
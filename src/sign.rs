@@ -0,0 +1,174 @@
+//! Verification reports signed with an ed25519 key, so that a verification farm can attest
+//! its results and whoever trusts the farm's key can rely on them without checking the
+//! proofs again. A signed report is JSON:
+//!```text
+//! {"report":{"mmb_sha256":"..","complete":true,..},"public_key":"..","signature":".."}
+//!```
+//! `report` is an `attestation`: a `verification_report` together with the SHA-256 of each
+//! mm0 file the run checked the mmb file against, and the settings that decide what passing
+//! means (`strict`, `dual`, whether the kernel had the last word, the axiom policy, and the
+//! verifier's version). `signature` is the signature of the report as written (compact, as
+//! `Json::to_string` writes it) by the key whose public half is `public_key`. Keys are 32
+//! bytes, stored as hex: a secret key is the ed25519 seed.
+//!
+//! A signature only says that the holder of the key vouches for the report; checking one
+//! means checking that the key is one you trust, which `check` does, and that the report is
+//! about the files you have, checked at least as carefully as you'd check them yourself,
+//! which `check_for` does too.
+
+use crate::prelude::*;
+use core::convert::TryInto;
+use ed25519_dalek::{ Signature, Signer, SigningKey, Verifier, VerifyingKey };
+use sha2::{ Sha256, Digest };
+use crate::env::{ hex, unhex };
+use crate::Outline;
+use crate::json::Json;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::util::{ Res, VerifErr };
+use crate::none_err;
+
+/// Read a 32-byte key written as hex, ignoring surrounding whitespace.
+pub fn parse_key(s: &str) -> Res<[u8; 32]> {
+    let bytes = unhex(s.trim())?;
+    bytes.as_slice().try_into().map_err(|_| VerifErr::Msg(format!("a key is 32 bytes, not {}", bytes.len())))
+}
+
+/// The public key of the secret key `secret`.
+pub fn public_key(secret: &[u8; 32]) -> [u8; 32] {
+    SigningKey::from_bytes(secret).verifying_key().to_bytes()
+}
+
+impl<'a> Outline<'a> {
+    /// The SHA-256 of each mm0 file, in order.
+    fn mm0_digests(&self) -> Vec<Json> {
+        self.file_data.mmz_files.iter().map(|mmz| Json::from(hex(&Sha256::digest(mmz.as_bytes())))).collect()
+    }
+
+    /// The settings a run of this outline passes or fails under.
+    fn attested_settings(&self) -> Json {
+        let names = |v: &[String]| v.iter().map(|s| Json::from(s.as_str())).collect::<Vec<_>>();
+        Json::obj()
+            .with("strict", self.options.strict)
+            .with("dual", self.options.dual)
+            .with("kernel", cfg!(feature = "kernel"))
+            .with("allow_axioms", self.axiom_policy.allow.as_deref().map(names))
+            .with("deny_axioms", names(&self.axiom_policy.deny))
+            .with("version", env!("CARGO_PKG_VERSION"))
+    }
+
+    /// What a signature vouches for: `verification_report(decls)`, with the digests of the
+    /// mm0 files and the settings added. `verified_here` is the caller's word that this
+    /// process ran every proof in `decls` itself and they all passed, rather than taking a
+    /// cached verdict or resuming from a checkpoint; without it there's nothing to attest.
+    pub fn attestation(&self, decls: &[(StmtCmd, ProofIter<'a>)], verified_here: bool) -> Res<Json> {
+        if !verified_here {
+            return Err(VerifErr::Msg("only a run that verified every declaration itself can be attested".to_string()))
+        }
+        Ok(self.verification_report(decls)?
+            .with("mm0_sha256", self.mm0_digests())
+            .with("settings", self.attested_settings()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedReport {
+    pub report: Json,
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl SignedReport {
+    /// Sign `report` with the secret key `secret`.
+    pub fn sign(report: Json, secret: &[u8; 32]) -> Self {
+        let key = SigningKey::from_bytes(secret);
+        let signature = key.sign(report.to_string().as_bytes()).to_bytes();
+        SignedReport { report, public_key: key.verifying_key().to_bytes(), signature }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::obj()
+            .with("report", self.report.clone())
+            .with("public_key", hex(&self.public_key))
+            .with("signature", hex(&self.signature))
+    }
+
+    pub fn from_json(json: &Json) -> Res<Self> {
+        let field = |key: &str| json.get(key).and_then(|v| v.as_str())
+            .ok_or_else(|| VerifErr::Msg(format!("signed report is missing the string {}", key)));
+        let signature = unhex(field("signature")?)?;
+        Ok(SignedReport {
+            report: none_err!(json.get("report"))?.clone(),
+            public_key: parse_key(field("public_key")?)?,
+            signature: signature.as_slice().try_into().map_err(|_| VerifErr::Msg(format!("a signature is 64 bytes, not {}", signature.len())))?,
+        })
+    }
+
+    /// Check that the report was signed by one of the keys in `trusted`.
+    pub fn check(&self, trusted: &[[u8; 32]]) -> Res<()> {
+        if !trusted.contains(&self.public_key) {
+            return Err(VerifErr::Msg(format!("the report is signed by {}, which isn't a trusted key", hex(&self.public_key))))
+        }
+        let key = VerifyingKey::from_bytes(&self.public_key).map_err(|_| VerifErr::Msg("the public key isn't a valid ed25519 key".to_string()))?;
+        key.verify(self.report.to_string().as_bytes(), &Signature::from_bytes(&self.signature))
+            .map_err(|_| VerifErr::Msg("the signature doesn't match the report".to_string()))
+    }
+
+    /// `check`, and also that the report says every declaration of `outline`'s mmb file was
+    /// verified against its mm0 files, with settings at least as demanding as `outline`'s:
+    /// `strict`, `dual` and the kernel if they're on here, and the same axiom policy.
+    pub fn check_for(&self, trusted: &[[u8; 32]], outline: &Outline<'_>) -> Res<()> {
+        self.check(trusted)?;
+        if self.report.get("mmb_sha256").and_then(|v| v.as_str()) != Some(hex(&Sha256::digest(outline.mmb())).as_str()) {
+            return Err(VerifErr::Msg("the report is about a different mmb file".to_string()))
+        }
+        if self.report.get("mm0_sha256").and_then(|v| v.as_arr()) != Some(outline.mm0_digests().as_slice()) {
+            return Err(VerifErr::Msg("the report is about different mm0 files".to_string()))
+        }
+        if self.report.get("complete") != Some(&Json::Bool(true)) {
+            return Err(VerifErr::Msg("the report is about a partial run".to_string()))
+        }
+        let signed = none_err!(self.report.get("settings"))?;
+        let wanted = outline.attested_settings();
+        for key in ["strict", "dual", "kernel"].iter() {
+            if wanted.get(key) == Some(&Json::Bool(true)) && signed.get(key) != Some(&Json::Bool(true)) {
+                return Err(VerifErr::Msg(format!("the report is about a run without {}", key)))
+            }
+        }
+        if signed.get("allow_axioms") != wanted.get("allow_axioms") || signed.get("deny_axioms") != wanted.get("deny_axioms") {
+            return Err(VerifErr::Msg("the report is about a run with a different axiom policy".to_string()))
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn sign1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", Some("./test_resources/short.mm0")).unwrap();
+    let outline = crate::Outline::new_from(&file_data).unwrap();
+    assert!(outline.attestation(&outline.declarations, false).is_err());
+    let report = outline.attestation(&outline.declarations, true).unwrap();
+    let secret = [7u8; 32];
+    let public = public_key(&secret);
+    assert_eq!(parse_key(&format!(" {}\n", hex(&public))).unwrap(), public);
+
+    let signed = SignedReport::sign(report, &secret);
+    let signed = SignedReport::from_json(&crate::json::parse(&signed.to_json().to_string()).unwrap()).unwrap();
+    signed.check_for(&[public], &outline).unwrap();
+    // Another key, another file, or a changed report.
+    assert!(signed.check(&[public_key(&[8u8; 32])]).is_err());
+    let mut forged = signed.clone();
+    forged.report = forged.report.with("complete", false);
+    assert!(forged.check(&[public]).is_err());
+    crate::testgen::Fixture::valid().with_outline(|other| assert!(signed.check_for(&[public], other).is_err()));
+    // The same mmb file without its mm0 file, or checked more strictly.
+    let no_spec = crate::fs::FileData::new_from_bytes(file_data.mmb_file.clone(), Vec::new());
+    let no_spec = SignedReport::sign(crate::Outline::new_from(&no_spec).unwrap().attestation(&outline.declarations, true).unwrap(), &secret);
+    assert!(no_spec.check_for(&[public], &outline).is_err());
+    let mut strict = crate::Outline::new_from(&file_data).unwrap();
+    strict.options.strict = true;
+    assert!(signed.check_for(&[public], &strict).is_err());
+    let mut denying = crate::Outline::new_from(&file_data).unwrap();
+    denying.axiom_policy.deny = vec!["ax_1".to_string()];
+    assert!(signed.check_for(&[public], &denying).is_err());
+}
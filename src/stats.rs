@@ -0,0 +1,199 @@
+//! Statistics about a verification run, for capacity planning rather than correctness.
+
+use core::time::Duration;
+use bumpalo::Bump;
+use crate::prelude::*;
+use crate::mmb::stmt::StmtCmd;
+
+/// How much memory the arenas (`Bump`s) used. When these are collected, every declaration
+/// gets a fresh arena, so the numbers don't depend on the number of threads or on which
+/// declarations happened to share an arena.
+///
+/// Single allocations aren't tracked: the arena doesn't report them, and the largest one is
+/// never more than the arena of the declaration that made it, `peak_decl_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArenaStats {
+    /// The number of chunks the arenas got from the system allocator.
+    pub chunks: u64,
+    /// The total size of those chunks.
+    pub bytes: u64,
+    /// The most arena memory a single declaration needed. This is the number to size a
+    /// worker by, since each one only holds on to one declaration's arena at a time.
+    pub peak_decl_bytes: u64,
+    /// The position of the declaration that needed `peak_decl_bytes` in the list of
+    /// declarations that was verified.
+    pub peak_decl: Option<usize>,
+}
+
+impl ArenaStats {
+    /// Measure one arena. `decl` says which declaration it was used for, if it was only one.
+    pub fn measure(bump: &mut Bump, decl: Option<usize>) -> Self {
+        let chunks = bump.iter_allocated_chunks().count() as u64;
+        let bytes = bump.allocated_bytes() as u64;
+        ArenaStats {
+            chunks,
+            bytes,
+            peak_decl_bytes: if decl.is_some() { bytes } else { 0 },
+            peak_decl: decl,
+        }
+    }
+
+    /// Combine the stats of two sets of arenas. Ties for the peak go to the earlier
+    /// declaration, so merging in any order gives the same result.
+    pub fn merge(&mut self, other: &ArenaStats) {
+        self.chunks += other.chunks;
+        self.bytes += other.bytes;
+        let other_wins = match (self.peak_decl, other.peak_decl) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(a), Some(b)) => (other.peak_decl_bytes, core::cmp::Reverse(b)) > (self.peak_decl_bytes, core::cmp::Reverse(a)),
+        };
+        if other_wins {
+            self.peak_decl_bytes = other.peak_decl_bytes;
+            self.peak_decl = other.peak_decl;
+        }
+    }
+}
+
+/// Where the time went, by phase. Parsing the header and the index is always timed; the
+/// rest is only filled in when `options.phase_times` is set. Without `std` there's no
+/// clock, and these are all zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhaseTimes {
+    /// Parsing the header and the list of declarations, and loading the tables.
+    pub tables: Duration,
+    /// Parsing the index.
+    pub index: Duration,
+    /// Checking the mm0 files against the mmb file.
+    pub mmz: Duration,
+    /// Running proof streams, not counting the unification they do. With several threads,
+    /// this and `unify` are summed over all of them.
+    pub proofs: Duration,
+    /// Unifying theorem applications and declarations' statements.
+    pub unify: Duration,
+    /// Each def, axiom and theorem with a proof, in the order they were given.
+    pub decls: Vec<DeclTimes>,
+}
+
+/// How many times the median a declaration has to take to be an outlier, by default.
+pub const OUTLIER_FACTOR: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeclTimes {
+    /// The position of the declaration in the list that was verified.
+    pub idx: usize,
+    pub decl: StmtCmd,
+    pub proof: Duration,
+    pub unify: Duration,
+}
+
+impl DeclTimes {
+    pub fn total(&self) -> Duration {
+        self.proof + self.unify
+    }
+}
+
+impl PhaseTimes {
+    pub fn total(&self) -> Duration {
+        self.tables + self.index + self.mmz + self.proofs + self.unify
+    }
+
+    /// The median time a declaration took, proof and unification together, or zero if
+    /// none were timed. With an even number, it's the lower of the middle two.
+    pub fn median_decl(&self) -> Duration {
+        let mut totals = self.decls.iter().map(DeclTimes::total).collect::<Vec<_>>();
+        totals.sort();
+        match totals.len() {
+            0 => Duration::ZERO,
+            n => totals[(n - 1) / 2],
+        }
+    }
+
+    /// The declarations that took more than `factor` times the median, slowest first. These
+    /// are the proofs that will dominate the cost of verifying the file as it grows. There
+    /// are none if the median is zero, since then there's nothing to compare against.
+    pub fn outliers(&self, factor: u32) -> Vec<DeclTimes> {
+        let median = self.median_decl();
+        if median == Duration::ZERO {
+            return Vec::new()
+        }
+        let mut out = self.decls.iter().filter(|d| d.total() > median * factor).copied().collect::<Vec<_>>();
+        out.sort_by_key(|d| (core::cmp::Reverse(d.total()), d.idx));
+        out
+    }
+
+    /// Add one declaration's times.
+    pub fn add_decl(&mut self, decl: DeclTimes) {
+        self.proofs += decl.proof;
+        self.unify += decl.unify;
+        self.decls.push(decl);
+    }
+
+    /// Combine the times of two workers, keeping `decls` in order.
+    pub fn merge(&mut self, other: &PhaseTimes) {
+        self.tables += other.tables;
+        self.index += other.index;
+        self.mmz += other.mmz;
+        self.proofs += other.proofs;
+        self.unify += other.unify;
+        self.decls.extend_from_slice(&other.decls);
+        self.decls.sort_by_key(|d| d.idx);
+    }
+}
+
+#[test]
+fn arena_stats_deterministic1() {
+    use crate::Outline;
+    let file_data = crate::fs::FileData::new_from("./test_resources/a.mmb", None::<String>).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    outline.options.arena_stats = true;
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+    let serial = outline.arena_stats.with(|s| *s);
+    assert!(serial.chunks > 0 && serial.peak_decl.is_some());
+
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    outline.options.arena_stats = true;
+    assert!(crate::verify_par(&outline, &outline.declarations, 3).is_empty());
+    assert_eq!(outline.arena_stats.with(|s| *s), serial);
+}
+
+#[test]
+fn phase_times1() {
+    use crate::Outline;
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let mut outline = Outline::new_from(&file_data).unwrap();
+    outline.options.phase_times = true;
+    assert!(crate::verify_par(&outline, &outline.declarations, 3).is_empty());
+    let times = outline.phase_times.with(|t| t.clone());
+    let with_proofs = outline.declarations.iter().filter(|(_, proof)| !proof.is_null()).count();
+    assert_eq!(times.decls.len(), with_proofs);
+    assert!(times.decls.windows(2).all(|w| w[0].idx < w[1].idx));
+    assert_eq!(times.decls.iter().map(|d| d.proof + d.unify).sum::<Duration>(), times.proofs + times.unify);
+    assert!(times.total() > Duration::ZERO);
+
+    let outline = Outline::new_from(&file_data).unwrap();
+    assert!(crate::verify_serial(&outline, &outline.declarations).is_empty());
+    assert!(outline.phase_times.with(|t| t.decls.is_empty() && t.unify == Duration::ZERO));
+}
+
+#[test]
+fn outliers1() {
+    let decl = |idx: usize, ms: u64| DeclTimes {
+        idx,
+        decl: StmtCmd::Thm { num: Some(idx as u32), local: false },
+        proof: Duration::from_millis(ms),
+        unify: Duration::from_millis(1),
+    };
+    let mut times = PhaseTimes::default();
+    assert!(times.outliers(OUTLIER_FACTOR).is_empty());
+    for (idx, ms) in [1, 2, 1, 500, 3, 1, 40, 2].iter().enumerate() {
+        times.add_decl(decl(idx, *ms));
+    }
+    assert_eq!(times.median_decl(), Duration::from_millis(3));
+    let outliers = times.outliers(OUTLIER_FACTOR).iter().map(|d| d.idx).collect::<Vec<_>>();
+    assert_eq!(outliers, [3, 6]);
+    assert_eq!(times.outliers(100).iter().map(|d| d.idx).collect::<Vec<_>>(), [3]);
+}
@@ -0,0 +1,558 @@
+//! Small mmb files built from scratch, valid and deliberately broken, with what the verifier
+//! should say about each. The checked-in fixtures were all written by the mm1 compiler, so
+//! they're all valid and can't show that a malformed file gets rejected (or how).
+//!
+//! A `Fixture` is a file described table by table, with the unify and proof streams given
+//! as bytes (`stream` writes them from commands). `corpus` starts from one valid fixture
+//! and breaks it in one way per case; `check` runs the verifier on a case and compares
+//! the outcome with what the case expects.
+//!
+//! `random_env` is the other way around: a random valid file written with `mmb::build`,
+//! with the statements it should be found to have, for round-trip tests of the reader and
+//! the writer.
+
+use crate::prelude::*;
+use crate::Outline;
+use crate::fs::FileData;
+use crate::mmb::{ MM0B_MAGIC, SORT_PROVABLE, SORT_STRICT };
+use crate::mmb::write::push_cmd;
+use crate::mmb::build::layout;
+pub use crate::mmb::build::{ TermSpec, AssertSpec };
+use crate::mmb::stmt::{ STMT_SORT, STMT_DEF, STMT_AXIOM, STMT_THM };
+use crate::mmb::proof::{
+    PROOF_TERM, PROOF_TERM_SAVE, PROOF_REF, PROOF_DUMMY, PROOF_THM, PROOF_THM_SAVE, PROOF_SAVE, PROOF_HYP, PROOF_CONV, PROOF_REFL, PROOF_CONG,
+    PROOF_UNFOLD, PROOF_CONV_CUT, PROOF_CONV_REF, PROOF_CONV_SAVE,
+};
+use crate::mmb::unify::{ UNIFY_TERM, UNIFY_TERM_SAVE, UNIFY_REF, UNIFY_DUMMY, UNIFY_HYP };
+use crate::mmb::build::{ Builder, ProofStream, UnifyStream };
+use crate::mmb::proof::ProofCmd;
+use crate::mmb::unify::UnifyCmd;
+use crate::certificate::{ Certificate, CertSort, CertTerm, CertThm };
+use crate::sample::SplitMix64;
+use crate::util::{ Type, Mods, ErrKind, MAX_BOUND_VARS };
+
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub magic: u32,
+    pub version: u8,
+    /// The modifiers of each sort.
+    pub sorts: Vec<u8>,
+    pub terms: Vec<TermSpec>,
+    pub asserts: Vec<AssertSpec>,
+    /// The statements in order: the command (`STMT_SORT`, ...) and the proof stream,
+    /// which is empty for sorts and terms.
+    pub stmts: Vec<(u8, Vec<u8>)>,
+}
+
+/// A unify or proof stream made of `cmds`, with the final `End`.
+pub fn stream(cmds: &[(u8, u32)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (cmd, data) in cmds.iter() {
+        push_cmd(&mut out, *cmd, *data);
+    }
+    out.push(0);
+    out
+}
+
+/// A regular variable of sort `sort`, depending on the bound variables in `deps`.
+pub fn reg(sort: u8, deps: u64) -> Type {
+    Type::from_parts(sort, false, deps).unwrap()
+}
+
+/// The `n`th bound variable (counting from 1) of sort `sort`.
+pub fn bound(sort: u8, n: u32) -> Type {
+    Type::from_parts(sort, true, 1 << (n - 1)).unwrap()
+}
+
+impl Fixture {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        layout(self.magic, self.version, &self.sorts, &self.terms, &self.asserts, &self.stmts)
+            .expect("statement too long for the mmb format")
+    }
+
+    /// `f` of the outline of this file, without an mm0 file.
+    pub fn with_outline<R>(&self, f: impl FnOnce(&mut Outline) -> R) -> R {
+        let file_data = FileData::new_from_bytes(self.to_bytes(), Vec::new());
+        let mut outline = Outline::new_from(&file_data).unwrap();
+        f(&mut outline)
+    }
+
+    /// ```text
+    /// provable sort wff;
+    /// term im (a b: wff): wff;
+    /// axiom ax (a: wff): $ im a a $;
+    /// theorem th (a b: wff): $ im (im a b) (im a b) $ = (ax (im a b));
+    /// ```
+    pub fn valid() -> Self {
+        let wff = reg(0, 0);
+        Fixture {
+            magic: MM0B_MAGIC,
+            version: 1,
+            sorts: vec![SORT_PROVABLE],
+            terms: vec![TermSpec { args: vec![wff, wff], ret: wff, def: None }],
+            asserts: vec![
+                AssertSpec {
+                    args: vec![wff],
+                    unify: stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 0)]),
+                },
+                AssertSpec {
+                    args: vec![wff, wff],
+                    unify: stream(&[
+                        (UNIFY_TERM, 0),
+                        (UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1),
+                        (UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1),
+                    ]),
+                },
+            ],
+            stmts: vec![
+                (STMT_SORT, Vec::new()),
+                (STMT_DEF, Vec::new()),
+                (STMT_AXIOM, stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0)])),
+                (STMT_THM, stream(&[
+                    (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM_SAVE, 0),
+                    (PROOF_REF, 2), (PROOF_REF, 2), (PROOF_TERM, 0),
+                    (PROOF_THM, 0),
+                ])),
+            ],
+        }
+    }
+
+    /// `valid`, with the theorem's proof saved and then used as an argument to `im`.
+    pub fn proof_as_expr() -> Self {
+        let mut fx = Fixture::valid();
+        fx.stmts[3].1 = stream(&[
+            (PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM_SAVE, 0),
+            (PROOF_REF, 2), (PROOF_REF, 2), (PROOF_TERM, 0),
+            (PROOF_THM_SAVE, 0),
+            (PROOF_REF, 3), (PROOF_REF, 3), (PROOF_TERM, 0),
+        ]);
+        fx
+    }
+
+    /// `valid`, with a theorem `chain (a: wff): $ im x x $ = (ax x)` added, where `x` is
+    /// `im (im (.. (im a a) ..) a) a` with `n` `im`s (`n >= 1`). Its proof builds `x` one
+    /// level at a time, so the proof and statement grow with `n` but nothing is unfolded.
+    pub fn chain(n: u32) -> Self {
+        let wff = reg(0, 0);
+        let mut fx = Fixture::valid();
+        let mut unify = vec![(UNIFY_TERM, 0), (UNIFY_TERM_SAVE, 0)];
+        unify.extend((1..n).map(|_| (UNIFY_TERM, 0)));
+        unify.extend((0..=n).map(|_| (UNIFY_REF, 0)));
+        unify.push((UNIFY_REF, 1));
+        fx.asserts.push(AssertSpec { args: vec![wff], unify: stream(&unify) });
+        let mut proof = vec![(PROOF_REF, 0)];
+        for _ in 0..n {
+            proof.extend_from_slice(&[(PROOF_REF, 0), (PROOF_TERM, 0)]);
+        }
+        proof.extend_from_slice(&[(PROOF_SAVE, 0), (PROOF_REF, 1), (PROOF_REF, 1), (PROOF_TERM, 0), (PROOF_THM, 0)]);
+        fx.stmts.push((STMT_THM, stream(&proof)));
+        fx
+    }
+
+    /// `valid`, with a def `d (a b: wff): wff = $ im a b $` and a theorem
+    /// `unfold (a: wff): $ im y y $`, where `y` is `d (d (.. (d a a) ..) a) a` with `n` `d`s
+    /// (`n >= 1`). It's proved from `ax` applied to `y` with every `d` unfolded, so the
+    /// proof is mostly conversion: one `Unfold` and `Cong` for each `d`, inside a
+    /// `ConvCut` whose result is used again with `ConvRef`.
+    pub fn unfolding(n: u32) -> Self {
+        let wff = reg(0, 0);
+        let mut fx = Fixture::valid();
+        fx.terms.push(TermSpec {
+            args: vec![wff, wff],
+            ret: wff,
+            def: Some(stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1)])),
+        });
+        fx.stmts.insert(2, (STMT_DEF, stream(&[(PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM, 0)])));
+        let mut unify = vec![(UNIFY_TERM, 0), (UNIFY_TERM_SAVE, 1)];
+        unify.extend((1..n).map(|_| (UNIFY_TERM, 1)));
+        unify.extend((0..=n).map(|_| (UNIFY_REF, 0)));
+        unify.push((UNIFY_REF, 1));
+        fx.asserts.push(AssertSpec { args: vec![wff], unify: stream(&unify) });
+
+        // The heap: `a`, then `y` at each depth (1 to n), then its unfolding (n + 1 to 2n).
+        let mut proof = vec![(PROOF_REF, 0)];
+        for _ in 0..n {
+            proof.extend_from_slice(&[(PROOF_REF, 0), (PROOF_TERM_SAVE, 1)]);
+        }
+        proof.extend_from_slice(&[(PROOF_REF, n), (PROOF_TERM, 0), (PROOF_REF, 0)]);
+        for _ in 0..n {
+            proof.extend_from_slice(&[(PROOF_REF, 0), (PROOF_TERM_SAVE, 0)]);
+        }
+        proof.extend_from_slice(&[(PROOF_REF, 2 * n), (PROOF_REF, 2 * n), (PROOF_TERM, 0), (PROOF_THM, 0)]);
+        // im y y =?= im x x, where x is y unfolded: one side by unfolding each `d` in
+        // turn, and the other by the same conversion, saved.
+        proof.extend_from_slice(&[(PROOF_CONV, 0), (PROOF_CONG, 0), (PROOF_CONV_CUT, 0)]);
+        for k in (1..=n).rev() {
+            proof.extend_from_slice(&[
+                (PROOF_REF, k), (PROOF_REF, k - 1), (PROOF_REF, 0), (PROOF_TERM, 0),
+                (PROOF_UNFOLD, 0), (PROOF_CONG, 0),
+            ]);
+        }
+        // `a =?= a` for the innermost `d` and the second argument of every `d`.
+        proof.extend((0..=n).map(|_| (PROOF_REFL, 0)));
+        proof.extend_from_slice(&[(PROOF_CONV_SAVE, 0), (PROOF_CONV_REF, 2 * n + 1)]);
+        fx.stmts.push((STMT_THM, stream(&proof)));
+        fx
+    }
+
+    /// `valid`, with a second sort `set` with modifiers `mods` and a term
+    /// `all (x: set) (p: wff x): wff` whose bound variable is numbered `x_bv`.
+    fn with_binder(mods: u8, x_bv: u32) -> Self {
+        let mut fx = Fixture::valid();
+        fx.sorts.push(mods);
+        fx.terms.push(TermSpec { args: vec![bound(1, x_bv), reg(0, 1)], ret: reg(0, 0), def: None });
+        fx.stmts.insert(1, (STMT_SORT, Vec::new()));
+        fx.stmts.insert(3, (STMT_DEF, Vec::new()));
+        fx
+    }
+
+    /// `with_binder(0, 1)`, with a def `d (x1 .. xn: set) (a: wff): wff = $ all y a $`,
+    /// where `y` is a dummy, so `d` has `n + 1` bound variables.
+    pub fn bound_vars(n: u32) -> Self {
+        let mut fx = Fixture::with_binder(0, 1);
+        let mut args = (1..=n).map(|i| bound(1, i)).collect::<Vec<_>>();
+        args.push(reg(0, 0));
+        fx.terms.push(TermSpec {
+            args,
+            ret: reg(0, 0),
+            def: Some(stream(&[(UNIFY_TERM, 1), (UNIFY_DUMMY, 1), (UNIFY_REF, n)])),
+        });
+        fx.stmts.insert(4, (STMT_DEF, stream(&[(PROOF_DUMMY, 1), (PROOF_REF, n), (PROOF_TERM, 1)])));
+        fx
+    }
+}
+
+/// An expression in a `random_env` statement: argument `i`, or a term applied to expressions.
+#[derive(Debug, Clone)]
+enum Expr {
+    Var(u32),
+    App(u32, Vec<Expr>),
+}
+
+struct RandomEnv {
+    rng: SplitMix64,
+    b: Builder,
+    cert: Certificate,
+}
+
+impl RandomEnv {
+    fn below(&mut self, n: usize) -> usize {
+        (self.rng.next_u64() % n as u64) as usize
+    }
+
+    fn sorts(&mut self, max: usize) -> Vec<u8> {
+        let n = 1 + self.below(max);
+        (0..n).map(|_| self.below(self.b.sorts.len()) as u8).collect()
+    }
+
+    /// An expression of sort `sort` over variables of sorts `vars`.
+    fn expr(&mut self, sort: u8, depth: u32, vars: &[u8]) -> Expr {
+        let var_choices = (0..vars.len()).filter(|i| vars[*i] == sort).collect::<Vec<_>>();
+        if !var_choices.is_empty() && (depth == 0 || self.below(3) == 0) {
+            return Expr::Var(var_choices[self.below(var_choices.len())] as u32)
+        }
+        // Every sort has a constant, declared first, which is all there is at depth 0.
+        let terms = (0..self.b.terms.len())
+            .filter(|t| self.b.terms[*t].ret.sort() == sort && (depth > 0 || self.b.terms[*t].args.is_empty()))
+            .collect::<Vec<_>>();
+        let t = terms[self.below(terms.len())];
+        let args = self.b.terms[t].args.iter().map(|ty| ty.sort()).collect::<Vec<_>>();
+        Expr::App(t as u32, args.iter().map(|s| self.expr(*s, depth - 1, vars)).collect())
+    }
+
+    fn sexpr(&self, e: &Expr) -> String {
+        match e {
+            Expr::Var(i) => format!("v{}", i),
+            Expr::App(t, args) => {
+                let name = self.cert.terms[*t as usize].name.clone().unwrap_or_default();
+                if args.is_empty() {
+                    return name
+                }
+                let args = args.iter().map(|a| self.sexpr(a)).collect::<Vec<_>>();
+                format!("({} {})", name, args.join(" "))
+            }
+        }
+    }
+
+    fn unify(e: &Expr, out: &mut UnifyStream) {
+        match e {
+            Expr::Var(i) => { out.push(UnifyCmd::Ref(*i)); }
+            Expr::App(t, args) => {
+                out.push(UnifyCmd::Term { term_num: *t, save: false });
+                for a in args.iter() {
+                    RandomEnv::unify(a, out);
+                }
+            }
+        }
+    }
+
+    fn proof(e: &Expr, out: &mut ProofStream) {
+        match e {
+            Expr::Var(i) => { out.push(ProofCmd::Ref(*i)); }
+            Expr::App(t, args) => {
+                for a in args.iter() {
+                    RandomEnv::proof(a, out);
+                }
+                out.push(ProofCmd::Term { term_num: *t, save: false });
+            }
+        }
+    }
+
+    fn term(&mut self, name: String, args: &[u8], ret: u8, body: Option<Expr>) {
+        let args = args.iter().map(|s| reg(*s, 0)).collect::<Vec<_>>();
+        let num = match &body {
+            None => self.b.term(&name, &args, reg(ret, 0)).unwrap(),
+            Some(body) => {
+                let (mut unify, mut proof) = (UnifyStream::new(), ProofStream::new());
+                RandomEnv::unify(body, &mut unify);
+                RandomEnv::proof(body, &mut proof);
+                self.b.def(&name, &args, reg(ret, 0), &unify, &proof, false).unwrap()
+            }
+        };
+        let def = body.map(|body| self.sexpr(&body));
+        self.cert.terms.push(CertTerm { num, name: Some(name), args, ret: reg(ret, 0), def });
+    }
+
+    /// An axiom `ax<k>` and a theorem `th<k>` proving the same statement with it.
+    fn axiom(&mut self, k: usize) {
+        let vars = self.sorts(3);
+        let args = vars.iter().map(|s| reg(*s, 0)).collect::<Vec<_>>();
+        let n = args.len() as u32;
+        let hyps = (0..self.below(3)).map(|_| self.expr(0, 2, &vars)).collect::<Vec<_>>();
+        let concl = self.expr(0, 3, &vars);
+        let mut unify = UnifyStream::new();
+        RandomEnv::unify(&concl, &mut unify);
+        for h in hyps.iter().rev() {
+            unify.push(UnifyCmd::Hyp);
+            RandomEnv::unify(h, &mut unify);
+        }
+        // Both proofs start by adding the hypotheses.
+        let mut proof = ProofStream::new();
+        for h in hyps.iter() {
+            RandomEnv::proof(h, &mut proof);
+            proof.push(ProofCmd::Hyp);
+        }
+        let mut ax_proof = proof.clone();
+        RandomEnv::proof(&concl, &mut ax_proof);
+        let ax = self.b.axiom(&format!("ax{}", k), &args, &unify, &ax_proof).unwrap();
+        for j in 0..hyps.len() as u32 {
+            proof.push(ProofCmd::Ref(n + j));
+        }
+        for i in 0..n {
+            proof.push(ProofCmd::Ref(i));
+        }
+        RandomEnv::proof(&concl, &mut proof);
+        proof.push(ProofCmd::Thm { thm_num: ax, save: false });
+        let th = self.b.theorem(&format!("th{}", k), &args, &unify, &proof, false).unwrap();
+        let hyps = hyps.iter().map(|h| self.sexpr(h)).collect::<Vec<_>>();
+        let concl = self.sexpr(&concl);
+        for (num, axiom) in [(ax, true), (th, false)] {
+            let name = Some(format!("{}{}", if axiom { "ax" } else { "th" }, k));
+            self.cert.thms.push(CertThm { num, name, axiom, args: args.clone(), hyps: hyps.clone(), concl: concl.clone() });
+        }
+    }
+}
+
+/// A random file written with `Builder`, and the certificate it should verify to. It has
+/// up to three sorts (the first provable, since it's the one statements are in), a
+/// constant of each, some terms and defs over them, and some axioms with random
+/// statements, each followed by a theorem proving the same statement with it.
+pub fn random_env(seed: u64) -> (Builder, Certificate) {
+    let mut env = RandomEnv { rng: SplitMix64::new(seed), b: Builder::new(), cert: Certificate::default() };
+    let num_sorts = 1 + env.below(3);
+    for i in 0..num_sorts {
+        let mods = if i == 0 { SORT_PROVABLE } else { [0, SORT_PROVABLE, SORT_STRICT][env.below(3)] };
+        let name = format!("s{}", i);
+        let num = env.b.sort(&name, mods).unwrap();
+        env.cert.sorts.push(CertSort { num, name: Some(name), mods: Mods { inner: mods } });
+    }
+    for i in 0..num_sorts {
+        env.term(format!("c{}", i), &[], i as u8, None);
+    }
+    for k in 0..1 + env.below(4) {
+        let args = env.sorts(3);
+        let ret = env.below(num_sorts) as u8;
+        env.term(format!("t{}", k), &args, ret, None);
+    }
+    for k in 0..env.below(3) {
+        let args = env.sorts(3);
+        let ret = env.below(num_sorts) as u8;
+        let body = env.expr(ret, 2, &args);
+        env.term(format!("f{}", k), &args, ret, Some(body));
+    }
+    for k in 0..1 + env.below(3) {
+        env.axiom(k);
+    }
+    (env.b, env.cert)
+}
+
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: &'static str,
+    pub mmb: Vec<u8>,
+    /// The kind of the first error, or `None` if the file should verify.
+    pub expect: Option<ErrKind>,
+}
+
+/// One valid file, and one broken file for each way of breaking it.
+pub fn corpus() -> Vec<Case> {
+    let mut cases = Vec::new();
+    let mut add = |name, fx: Fixture, expect| cases.push(Case { name, mmb: fx.to_bytes(), expect });
+
+    add("valid", Fixture::valid(), None);
+    add("valid_binder", Fixture::with_binder(0, 1), None);
+    // Long enough that the statements need a 16 bit length.
+    add("valid_chain", Fixture::chain(200), None);
+    add("valid_unfolding", Fixture::unfolding(50), None);
+    add("valid_bound_vars", Fixture::bound_vars(MAX_BOUND_VARS - 1), None);
+
+    add("bad_magic", Fixture { magic: 0x4D4D_3042, ..Fixture::valid() }, Some(ErrKind::Msg));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 1), (PROOF_TERM_SAVE, 7)]);
+    add("term_out_of_range", fx, Some(ErrKind::MakeSure));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0), (PROOF_THM, 5)]);
+    add("thm_out_of_range", fx, Some(ErrKind::MakeSure));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 9)]);
+    add("bad_backref", fx, Some(ErrKind::BadBackref));
+
+    add("proof_as_expr", Fixture::proof_as_expr(), Some(ErrKind::WrongItem));
+
+    // The axiom uses `im`, which is now declared after it.
+    let mut fx = Fixture::valid();
+    fx.stmts.swap(1, 2);
+    add("forward_term", fx, Some(ErrKind::ForwardReference));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0), (PROOF_THM, 1)]);
+    add("self_reference", fx, Some(ErrKind::ForwardReference));
+
+    add("bv_order", Fixture::with_binder(0, 2), Some(ErrKind::MakeSure));
+    add("strict_binder", Fixture::with_binder(SORT_STRICT, 1), Some(ErrKind::MakeSure));
+    add("too_many_bound_vars", Fixture::bound_vars(MAX_BOUND_VARS), Some(ErrKind::TooManyBoundVars));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[3].1 = stream(&[
+        (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM_SAVE, 0),
+        (PROOF_REF, 2), (PROOF_REF, 2), (PROOF_TERM, 0),
+        (PROOF_THM, 0),
+    ]);
+    add("wrong_concl", fx, Some(ErrKind::Msg));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = stream(&[(PROOF_REF, 0), (PROOF_REF, 0), (PROOF_REF, 0), (PROOF_TERM, 0)]);
+    add("leftover_stack", fx, Some(ErrKind::MakeSure));
+
+    // `im` with one argument.
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = stream(&[(PROOF_REF, 0), (PROOF_TERM, 0)]);
+    add("stack_underflow", fx, Some(ErrKind::StackUnderflow));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = Vec::new();
+    add("empty_proof", fx, Some(ErrKind::StackUnderflow));
+
+    // A `Hyp` with a data field.
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = vec![PROOF_REF, PROOF_REF, PROOF_TERM, PROOF_HYP | 0x40, 0, 0];
+    add("malformed_proof", fx, Some(ErrKind::MalformedProof));
+
+    let mut fx = Fixture::valid();
+    fx.asserts[0].unify = stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0)]);
+    add("unify_too_short", fx, Some(ErrKind::MakeSure));
+
+    // The axiom has one argument, so there's no heap entry 1.
+    let mut fx = Fixture::valid();
+    fx.asserts[0].unify = stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 1)]);
+    add("unify_bad_ref", fx, Some(ErrKind::MalformedUnify));
+
+    // The axiom's statement gains a hypothesis its proof doesn't introduce.
+    let mut fx = Fixture::valid();
+    fx.asserts[0].unify = stream(&[(UNIFY_TERM, 0), (UNIFY_REF, 0), (UNIFY_REF, 0), (UNIFY_HYP, 0), (UNIFY_REF, 0)]);
+    add("hyp_count", fx, Some(ErrKind::MalformedUnify));
+
+    let mut fx = Fixture::valid();
+    fx.stmts[1].1 = stream(&[(PROOF_REF, 0)]);
+    add("term_with_proof", fx, Some(ErrKind::Msg));
+
+    let mut fx = Fixture::valid();
+    fx.stmts.pop();
+    add("missing_thm", fx, Some(ErrKind::DeclCount));
+
+    // The extra axiom can't be verified, since it has no entry in the theorem table.
+    let mut fx = Fixture::valid();
+    fx.stmts.push(fx.stmts[2].clone());
+    add("extra_axiom", fx, Some(ErrKind::MakeSure));
+
+    cases
+}
+
+/// Verify `case.mmb` and compare the outcome with `case.expect`, describing any mismatch.
+pub fn check(case: &Case) -> Result<(), String> {
+    let file_data = FileData::new_from_bytes(case.mmb.clone(), Vec::new());
+    let errs = match Outline::new_from(&file_data) {
+        Ok(outline) => crate::verify_serial(&outline, &outline.declarations),
+        Err(e) => vec![e],
+    };
+    match (errs.first(), case.expect) {
+        (None, None) => Ok(()),
+        (Some(e), Some(kind)) if e.kind() == kind => Ok(()),
+        (Some(e), expect) => Err(format!("{}: expected {:?}, got {:?}", case.name, expect, e)),
+        (None, Some(kind)) => Err(format!("{}: expected {:?}, but it verified", case.name, kind)),
+    }
+}
+
+#[test]
+fn corpus1() {
+    let failures = corpus().iter().filter_map(|case| check(case).err()).collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{:#?}", failures);
+}
+
+#[test]
+fn wrong_item1() {
+    use crate::util::VerifErr;
+    Fixture::proof_as_expr().with_outline(|outline| {
+        match crate::verify_serial(outline, &outline.declarations).first() {
+            // The error points at the `Term` using the proof.
+            Some(VerifErr::WrongItem { pos, expected, found, .. }) => {
+                assert_eq!(outline.mmb()[*pos] & 0x3F, PROOF_TERM);
+                assert_eq!((*expected, *found), ("an expression", "a proof"));
+            }
+            owise => panic!("{:?}", owise),
+        }
+    });
+}
+
+#[test]
+fn stack_underflow1() {
+    use crate::util::VerifErr;
+    let mut fx = Fixture::valid();
+    fx.stmts[2].1 = stream(&[(PROOF_REF, 0), (PROOF_TERM, 0)]);
+    fx.with_outline(|outline| {
+        match crate::verify_serial(outline, &outline.declarations).first() {
+            Some(VerifErr::StackUnderflow { opcode, pos, needed, depth, top, .. }) => {
+                assert_eq!(outline.mmb()[*pos] & 0x3F, PROOF_TERM);
+                assert!(opcode.starts_with("Term "), "{}", opcode);
+                assert_eq!((*needed, *depth), (2, 1));
+                assert_eq!(top, &["v0".to_string()]);
+            }
+            owise => panic!("{:?}", owise),
+        }
+    });
+}
+
+#[test]
+fn bound_vars1() {
+    use crate::util::VerifErr;
+    Fixture::bound_vars(MAX_BOUND_VARS).with_outline(|outline| {
+        match crate::verify_serial(outline, &outline.declarations).first() {
+            // The dummy is one past the last bound variable.
+            Some(VerifErr::TooManyBoundVars { var, .. }) => assert_eq!(*var, MAX_BOUND_VARS + 1),
+            owise => panic!("{:?}", owise),
+        }
+    });
+}
@@ -1,13 +1,18 @@
-use std::convert::{ TryFrom, TryInto };
-use std::marker::PhantomData;
-use std::fmt::{ Debug, Formatter, Result as FmtResult };
-use std::sync::atomic::{ AtomicU8, AtomicU32, Ordering::Relaxed };
+use crate::prelude::*;
+use core::convert::{ TryFrom, TryInto };
+use core::marker::PhantomData;
+use core::fmt::{ Debug, Formatter, Result as FmtResult };
+use core::sync::atomic::{ AtomicU8, AtomicU32, Ordering::Relaxed };
 
 use crate::mmb::unify::UnifyIter;
 use crate::mmb::proof::ProofIter;
 use crate::mmb::stmt::StmtCmd;
 
 
+/// The most bound variables a declaration can have, counting dummies. Each one gets a bit
+/// in the low 56 bits of a `Type`, below the sort.
+pub const MAX_BOUND_VARS: u32 = 56;
+
 /// 10000000_11111111_11111111_11111111_11111111_11111111_11111111_11111111
 const TYPE_SORT_MASK: u64 = (1 << 63) | ((1 << 56) - 1);
 
@@ -22,7 +27,7 @@ macro_rules! localize {
 #[macro_export]
 macro_rules! io_err {
     ( $e:expr ) => {
-        $e.map_err(|e| VerifErr::IoErr(file!(), line!(), Box::new(e)))
+        $e.map_err(|e| VerifErr::IoErr(file!(), line!(), e))
     }
 }
 
@@ -55,12 +60,12 @@ macro_rules! bin_parser {
             pub fn $name(source: &[u8]) -> Res<($t, &[u8])> {
                 let int_bytes =
                     source
-                    .get(0..std::mem::size_of::<$t>())
+                    .get(0..core::mem::size_of::<$t>())
                     .ok_or_else(|| {
                         VerifErr::Msg(format!("binary parser ran out of input @ {}: {}", file!(), line!()))
                     })?;
 
-                let new_pos = std::mem::size_of::<$t>();
+                let new_pos = core::mem::size_of::<$t>();
                 Ok(
                     ($t::from_le_bytes(int_bytes.try_into().unwrap()), &source[new_pos..])
                 )
@@ -79,33 +84,33 @@ bin_parser! {
 macro_rules! bitwise_inner {
     ( $($t:ident),* ) => {
         $(
-            impl std::ops::BitAnd<$t> for $t {
+            impl core::ops::BitAnd<$t> for $t {
                 type Output = Self;
                 fn bitand(self, rhs: Self) -> Self::Output {
                     $t { inner: self.inner & rhs.inner }
                 }
             }
 
-            impl std::ops::BitAndAssign<$t> for $t {
+            impl core::ops::BitAndAssign<$t> for $t {
                 fn bitand_assign(&mut self, other: Self) {
                     self.inner &= other.inner
                 }
             }
 
-            impl std::ops::BitOr<$t> for $t {
+            impl core::ops::BitOr<$t> for $t {
                 type Output = Self;
                 fn bitor(self, rhs: Self) -> Self::Output {
                     $t { inner: self.inner | rhs.inner }
                 }
             }
 
-            impl std::ops::BitOrAssign<$t> for $t {
+            impl core::ops::BitOrAssign<$t> for $t {
                 fn bitor_assign(&mut self, other: Self) {
                     self.inner |= other.inner
                 }
             }
 
-            impl std::ops::Not for $t {
+            impl core::ops::Not for $t {
                 type Output = $t;
                 fn not(self) -> Self::Output {
                     $t { inner: !self.inner }
@@ -132,9 +137,84 @@ pub type AssertIdent<'a> = Str<'a>;
 pub struct Ptr<'a, A>(pub u32, pub PhantomData<&'a A>);
 
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Str<'a>(pub &'a [u8]);
 
+/// The map used by the mm0 parser. There's no `HashMap` without `std`, so that falls back
+/// to a `BTreeMap`, which is why the key types are `Ord` as well as `Hash`.
+#[cfg(feature = "std")]
+pub use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+pub use alloc::collections::BTreeMap as HashMap;
+
+/// State in the `Outline` that every worker thread writes to (warnings, statistics).
+/// Without `std` there are no threads (and no `Mutex`), so a `RefCell` does.
+#[derive(Debug, Default)]
+pub struct Shared<A> {
+    #[cfg(feature = "std")]
+    inner: std::sync::Mutex<A>,
+    #[cfg(not(feature = "std"))]
+    inner: core::cell::RefCell<A>,
+}
+
+impl<A> Shared<A> {
+    pub fn new(a: A) -> Self {
+        Shared { inner: a.into() }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn with<R>(&self, f: impl FnOnce(&mut A) -> R) -> R {
+        f(&mut *self.inner.lock().unwrap())
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn with<R>(&self, f: impl FnOnce(&mut A) -> R) -> R {
+        f(&mut *self.inner.borrow_mut())
+    }
+}
+
+/// A set of item numbers that any number of workers can add to at the same time without
+/// taking a lock. Used to record which declarations have been verified.
+#[derive(Debug, Default)]
+pub struct DoneSet {
+    len: usize,
+    words: Vec<AtomicU32>,
+}
+
+impl DoneSet {
+    pub fn new(len: usize) -> Self {
+        DoneSet { len, words: (0..len.div_ceil(32)).map(|_| AtomicU32::new(0)).collect() }
+    }
+
+    /// Add `n` to the set, returning whether it was already there.
+    pub fn insert(&self, n: usize) -> Res<bool> {
+        make_sure!(n < self.len);
+        let bit = 1 << (n % 32);
+        let prev = none_err!(self.words.get(n / 32))?.fetch_or(bit, Relaxed);
+        Ok(prev & bit != 0)
+    }
+
+    pub fn contains(&self, n: usize) -> bool {
+        self.words.get(n / 32).map(|w| w.load(Relaxed) & (1 << (n % 32)) != 0).unwrap_or(false)
+    }
+}
+
+#[test]
+fn done_set1() {
+    let set = DoneSet::new(100);
+    crossbeam_utils::thread::scope(|sco| {
+        for t in 0..4 {
+            let set = &set;
+            sco.spawn(move |_| for n in (t..100).step_by(4) {
+                assert!(!set.insert(n).unwrap());
+            });
+        }
+    }).unwrap();
+    assert!((0..100).all(|n| set.contains(n)));
+    assert!(set.insert(37).unwrap());
+    assert!(set.insert(100).is_err());
+}
+
 impl<'b, 'a: 'b> Str<'a> {
     pub fn as_bytes(&self) -> &'a [u8] {
         self.0
@@ -160,18 +240,56 @@ pub enum Either<A, B> {
 
 pub type Arg = Type;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// A `Type` is a `u64` laid out as in the mmb format: bit 63 says whether it's a bound
+/// variable, bits 56-62 are the sort, and the low `MAX_BOUND_VARS` bits are either the bit
+/// for the bound variable's own number (exactly one of them) or the bits for the bound
+/// variables a regular variable depends on.
+/// `from_parts` and `from_bits` only make well-formed ones; types read from a file aren't
+/// checked until they're validated (see `mmb::validate`), so `bits` can be anything.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Type {
-    pub inner: u64
+    pub(crate) inner: u64
 }
 
-impl std::default::Default for Type {
-    fn default() -> Self {
-        Type { inner: 0 }
-    }
-}
+/// The low bits of a `Type` that can name bound variables: the one bit of a bound
+/// variable's own number, or the ones a regular variable depends on.
+const TYPE_VAR_MASK: u64 = (1 << MAX_BOUND_VARS) - 1;
 
 impl Type {
+    /// A type with sort `sort`, which is bound variable number `vars` (from 1) if `bound`
+    /// and otherwise depends on each bound variable whose bit is set in `vars`.
+    pub fn from_parts(sort: u8, bound: bool, vars: u64) -> Res<Self> {
+        make_sure!(sort < 0x80);
+        make_sure!(vars & !TYPE_VAR_MASK == 0);
+        make_sure!(!bound || vars.is_power_of_two());
+        let bound_bit = if bound { crate::mmb::TYPE_BOUND_MASK } else { 0 };
+        Ok(Type { inner: bound_bit | ((sort as u64) << 56) | vars })
+    }
+
+    /// `bits` as a type, if it's well-formed (see `from_parts`).
+    pub fn from_bits(bits: u64) -> Res<Self> {
+        let ty = Type { inner: bits };
+        Type::from_parts(ty.sort(), ty.is_bound(), bits & crate::mmb::TYPE_DEPS_MASK)
+    }
+
+    /// The word as it's written in the file.
+    pub fn bits(self) -> u64 {
+        self.inner
+    }
+
+    /// The low bits: a bound variable's own bit, or a regular variable's dependencies.
+    pub fn var_bits(self) -> u64 {
+        self.inner & crate::mmb::TYPE_DEPS_MASK
+    }
+
+    /// The numbers (from 1) of the bound variables a regular variable depends on, in
+    /// increasing order. A bound variable doesn't depend on anything.
+    pub fn deps_iter(self) -> impl Iterator<Item = u64> {
+        let deps = if self.is_bound() { 0 } else { self.var_bits() };
+        (0..56u64).filter(move |i| deps & (1 << i) != 0).map(|i| i + 1)
+    }
+
     pub fn high_bit(self) -> Self {
         Type { inner: self.inner & (!crate::mmb::TYPE_DEPS_MASK) }
     }
@@ -200,6 +318,26 @@ impl Type {
     pub fn is_bound(self) -> bool {
         self.inner & (1 << 63) != 0
     }
+
+    /// Whether a value of type `self` can go where type `to` is expected: the sorts have
+    /// to be the same, and if `to` is a bound variable then so does `self` have to be.
+    /// Dependencies don't matter, and a bound variable can go anywhere a regular one of
+    /// its sort can. This isn't symmetric, so the value's type always comes first.
+    pub fn coercible_to(self, to: Type) -> bool {
+        let diff = self.inner ^ to.inner;
+        let same_sort = (diff & !crate::mmb::TYPE_BOUND_MASK & !crate::mmb::TYPE_DEPS_MASK) == 0;
+        same_sort && (self.is_bound() || !to.is_bound())
+    }
+
+    /// `self.coercible_to(to)`, for a value's type and a binder's. Debug builds also check
+    /// the answer against the bit-level `mmb::sorts_compatible` the kernel used to call.
+    #[inline]
+    pub fn coercible_to_binder(self, binder: Type) -> bool {
+        let ok = self.coercible_to(binder);
+        debug_assert_eq!(ok, crate::mmb::sorts_compatible(self, binder),
+            "coercible_to disagrees with sorts_compatible on {:?} and {:?}", self, binder);
+        ok
+    }
     
     pub fn add_sort(&mut self, sort_id: u8) {
         // clear existing sort if any;
@@ -343,7 +481,282 @@ pub enum VerifErr {
     // Crate a rough backtrace; use the `localize!` macro to make this.
     Local(&'static str, u32, Box<VerifErr>),
     Unreachable(&'static str, u32),
-    IoErr(&'static str, u32, std::io::Error)
+    #[cfg(feature = "std")]
+    IoErr(&'static str, u32, std::io::Error),
+    /// A declaration ran past its step or wall-clock budget.
+    Timeout {
+        decl: StmtCmd,
+        steps: u64,
+        elapsed_ms: Option<u128>,
+    },
+    /// A declaration needed more than `options.max_memory` bytes of arena; `bytes` is how
+    /// much it was using when it was stopped.
+    OutOfMemory {
+        decl: StmtCmd,
+        bytes: usize,
+    },
+    /// A declaration's proof built an expression bigger than `options.max_expr_size` (as a
+    /// tree) or deeper than `options.max_expr_depth`.
+    ExpressionTooLarge {
+        decl: StmtCmd,
+        size: u64,
+        depth: u32,
+    },
+    /// A declaration has more than `MAX_BOUND_VARS` bound variables; `var` is the
+    /// (1-based) number of the first one over the limit.
+    TooManyBoundVars {
+        decl: StmtCmd,
+        var: u32,
+    },
+    /// The proof stream declares a different number of sorts, terms, or assertions
+    /// (`table`) than the header says there are.
+    DeclCount {
+        table: &'static str,
+        header: u32,
+        declared: u32,
+    },
+    /// Something only strict mode rejects, at offset `pos` in the mmb file.
+    Strict {
+        pos: usize,
+        msg: String,
+    },
+    /// Applying assertion `thm` failed while unifying its hypothesis number `hyp` (counting
+    /// from 0 in declaration order), which is called `name` in the mm0 file if it has one.
+    Hyp {
+        thm: u32,
+        hyp: usize,
+        name: Option<String>,
+        err: Box<VerifErr>,
+    },
+    /// With `options.unify_delta`, a failed unification: `path` leads (by argument
+    /// positions, from 0) from the root of the expression being unified to the first
+    /// subterm that doesn't match, where the head `expected` was found to be `actual`.
+    UnifyDelta {
+        path: Vec<u32>,
+        expected: String,
+        actual: String,
+    },
+    /// A `Ref` or `ConvRef` past the end of the heap, or a `URef` past the end of the
+    /// unify heap, which has `heap_len` entries.
+    BadBackref {
+        decl: StmtCmd,
+        index: u32,
+        heap_len: usize,
+    },
+    /// The proof command at offset `pos` in the mmb file needed an item of kind `expected`
+    /// (see `MmbItem::kind_name`) on the stack or heap, and found one of kind `found`.
+    WrongItem {
+        decl: StmtCmd,
+        pos: usize,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// A declaration used a term or assertion (`referenced`) that isn't declared before it.
+    ForwardReference {
+        decl: StmtCmd,
+        referenced: StmtCmd,
+    },
+    /// Defs (by term number) each of whose bodies uses the next, and the last the first.
+    DefCycle {
+        cycle: Vec<u32>,
+    },
+    /// With `options.dual`, the second checker came to a different verdict on `decl` than
+    /// the first: `second` is its error, or `None` if it accepted `decl` (and the first
+    /// checker's error is reported separately).
+    Disagreement {
+        decl: StmtCmd,
+        second: Option<Box<VerifErr>>,
+    },
+    /// With a reference verifier (see `reference`), it came to a different verdict on the
+    /// files than this one: `ours` and `theirs` say which accepted them, and `said` is the
+    /// first line the reference verifier printed, if any.
+    Divergence {
+        ours: bool,
+        theirs: bool,
+        said: Option<String>,
+    },
+    /// The trusted kernel (see `kernel`) rejected `decl` while running the command at `pos`.
+    Kernel {
+        decl: StmtCmd,
+        pos: usize,
+        reason: &'static str,
+    },
+    /// A `Hyp` command at `pos` assumed an expression of `sort`, whose modifiers (`mods`)
+    /// don't make it provable.
+    HypNotProvable {
+        decl: StmtCmd,
+        pos: usize,
+        sort: u8,
+        mods: u8,
+    },
+    /// The proof stream of `decl` isn't well formed at offset `pos`; see `ProofIter::prescan`.
+    MalformedProof {
+        decl: StmtCmd,
+        pos: usize,
+        reason: &'static str,
+    },
+    /// The unify stream of `decl` is wrong at offset `pos` in a way that doesn't depend on
+    /// what it's unified with; see `UnifyIter::prescan`.
+    MalformedUnify {
+        decl: StmtCmd,
+        pos: usize,
+        reason: String,
+    },
+    /// The proof command `opcode` (as the disassembler writes it, or `end of proof` for the
+    /// checks after the last one) at offset `pos` needed `needed` items on the stack, and it
+    /// had `depth`. `top` is what it had, from the bottom, as `Outline::item_str` writes it;
+    /// only the top `mmb::STACK_DUMP_LEN` items are shown.
+    StackUnderflow {
+        decl: StmtCmd,
+        opcode: String,
+        pos: usize,
+        needed: usize,
+        depth: usize,
+        top: Vec<String>,
+    },
+    /// A warning (see `mmb::warn`), with `--deny warnings`.
+    Warning(String),
+    /// With `options.keep_going`, `decl` passed, but it uses `failed`, which didn't (maybe
+    /// through other declarations that use it).
+    DependsOnFailed {
+        decl: StmtCmd,
+        failed: StmtCmd,
+    },
+    /// The theorem `decl` uses `axioms` (by name, or `#` and the number), directly or
+    /// through other theorems, and the axiom policy (see `mmb::axioms`) doesn't permit them.
+    AxiomNotAllowed {
+        decl: StmtCmd,
+        axioms: Vec<String>,
+    },
+    /// The mm0 file and the public declarations of the mmb file don't line up. `decl` is
+    /// the mmb declaration and `name` the name (from the mm0 file, or failing that the
+    /// index), where there is one.
+    Conformance {
+        decl: Option<StmtCmd>,
+        name: Option<String>,
+        problem: Mismatch,
+    },
+}
+
+/// How a declaration of the mm0 file and one of the mmb file disagree; see `mmz::conform`.
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The mm0 statement isn't the one the mmb file declares in its place.
+    Statement(Box<VerifErr>),
+    /// The mm0 file declares something after the mmb file's last public declaration.
+    MissingFromMmb,
+    /// The mmb file declares something after the mm0 file's last statement.
+    MissingFromMm0,
+    /// The mm0 file declares something the mmb file has as a local declaration, which
+    /// isn't exported.
+    Local,
+}
+
+impl Mismatch {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mismatch::Statement(_) => "statement",
+            Mismatch::MissingFromMmb => "missing-from-mmb",
+            Mismatch::MissingFromMm0 => "missing-from-mm0",
+            Mismatch::Local => "local",
+        }
+    }
+}
+
+/// Which variant a `VerifErr` is, looking through `Local` and `Hyp`. For tests (and tools) that
+/// care about how something failed but not where.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrKind {
+    MakeSure,
+    NoneErr,
+    ConvErr,
+    Msg,
+    Unreachable,
+    IoErr,
+    Timeout,
+    OutOfMemory,
+    ExpressionTooLarge,
+    TooManyBoundVars,
+    DeclCount,
+    Strict,
+    UnifyDelta,
+    BadBackref,
+    WrongItem,
+    HypNotProvable,
+    StackUnderflow,
+    MalformedProof,
+    MalformedUnify,
+    ForwardReference,
+    DefCycle,
+    Disagreement,
+    Divergence,
+    Kernel,
+    Warning,
+    DependsOnFailed,
+    AxiomNotAllowed,
+    Conformance,
+}
+
+impl VerifErr {
+    pub fn kind(&self) -> ErrKind {
+        match self {
+            VerifErr::MakeSure(..) => ErrKind::MakeSure,
+            VerifErr::NoneErr(..) => ErrKind::NoneErr,
+            VerifErr::ConvErr(..) => ErrKind::ConvErr,
+            VerifErr::Msg(..) => ErrKind::Msg,
+            VerifErr::Local(_, _, e) => e.kind(),
+            VerifErr::Unreachable(..) => ErrKind::Unreachable,
+            #[cfg(feature = "std")]
+            VerifErr::IoErr(..) => ErrKind::IoErr,
+            VerifErr::Timeout { .. } => ErrKind::Timeout,
+            VerifErr::OutOfMemory { .. } => ErrKind::OutOfMemory,
+            VerifErr::ExpressionTooLarge { .. } => ErrKind::ExpressionTooLarge,
+            VerifErr::TooManyBoundVars { .. } => ErrKind::TooManyBoundVars,
+            VerifErr::DeclCount { .. } => ErrKind::DeclCount,
+            VerifErr::Strict { .. } => ErrKind::Strict,
+            VerifErr::Hyp { err, .. } => err.kind(),
+            VerifErr::UnifyDelta { .. } => ErrKind::UnifyDelta,
+            VerifErr::BadBackref { .. } => ErrKind::BadBackref,
+            VerifErr::WrongItem { .. } => ErrKind::WrongItem,
+            VerifErr::HypNotProvable { .. } => ErrKind::HypNotProvable,
+            VerifErr::StackUnderflow { .. } => ErrKind::StackUnderflow,
+            VerifErr::MalformedProof { .. } => ErrKind::MalformedProof,
+            VerifErr::MalformedUnify { .. } => ErrKind::MalformedUnify,
+            VerifErr::ForwardReference { .. } => ErrKind::ForwardReference,
+            VerifErr::DefCycle { .. } => ErrKind::DefCycle,
+            VerifErr::Disagreement { .. } => ErrKind::Disagreement,
+            VerifErr::Divergence { .. } => ErrKind::Divergence,
+            VerifErr::Kernel { .. } => ErrKind::Kernel,
+            VerifErr::Warning(..) => ErrKind::Warning,
+            VerifErr::DependsOnFailed { .. } => ErrKind::DependsOnFailed,
+            VerifErr::AxiomNotAllowed { .. } => ErrKind::AxiomNotAllowed,
+            VerifErr::Conformance { .. } => ErrKind::Conformance,
+        }
+    }
+
+    /// The declaration the error is about, for the kinds that say.
+    pub fn decl(&self) -> Option<StmtCmd> {
+        match self {
+            VerifErr::Local(_, _, e) | VerifErr::Hyp { err: e, .. } => e.decl(),
+            | VerifErr::Timeout { decl, .. }
+            | VerifErr::OutOfMemory { decl, .. }
+            | VerifErr::ExpressionTooLarge { decl, .. }
+            | VerifErr::TooManyBoundVars { decl, .. }
+            | VerifErr::BadBackref { decl, .. }
+            | VerifErr::WrongItem { decl, .. }
+            | VerifErr::HypNotProvable { decl, .. }
+            | VerifErr::StackUnderflow { decl, .. }
+            | VerifErr::MalformedProof { decl, .. }
+            | VerifErr::MalformedUnify { decl, .. }
+            | VerifErr::ForwardReference { decl, .. }
+            | VerifErr::Disagreement { decl, .. }
+            | VerifErr::Kernel { decl, .. }
+            | VerifErr::DependsOnFailed { decl, .. }
+            | VerifErr::AxiomNotAllowed { decl, .. } => Some(*decl),
+            VerifErr::Conformance { decl, .. } => *decl,
+            _ => None,
+        }
+    }
 }
 
 impl Debug for VerifErr {
@@ -379,6 +792,7 @@ impl Debug for VerifErr {
                 d.field("line", &l);
                 d.finish()
             },
+            #[cfg(feature = "std")]
             VerifErr::IoErr(fi, l, e) => {
                 let mut d = f.debug_struct("VerifErr::IoErr");
                 d.field("file", &fi);
@@ -392,10 +806,254 @@ impl Debug for VerifErr {
                 d.field("line", &l);
                 d.finish()
             },
+            VerifErr::Timeout { decl, steps, elapsed_ms } => {
+                let mut d = f.debug_struct("VerifErr::Timeout");
+                d.field("decl", &decl);
+                d.field("steps", &steps);
+                d.field("elapsed_ms", &elapsed_ms);
+                d.finish()
+            },
+            VerifErr::OutOfMemory { decl, bytes } => {
+                let mut d = f.debug_struct("VerifErr::OutOfMemory");
+                d.field("decl", &decl);
+                d.field("bytes", &bytes);
+                d.finish()
+            },
+            VerifErr::ExpressionTooLarge { decl, size, depth } => {
+                let mut d = f.debug_struct("VerifErr::ExpressionTooLarge");
+                d.field("decl", &decl);
+                d.field("size", &size);
+                d.field("depth", &depth);
+                d.finish()
+            },
+            VerifErr::TooManyBoundVars { decl, var } => {
+                let mut d = f.debug_struct("VerifErr::TooManyBoundVars");
+                d.field("decl", &decl);
+                d.field("var", &var);
+                d.field("limit", &MAX_BOUND_VARS);
+                d.finish()
+            },
+            VerifErr::DeclCount { table, header, declared } => {
+                let mut d = f.debug_struct("VerifErr::DeclCount");
+                d.field("table", &table);
+                d.field("header", &header);
+                d.field("declared", &declared);
+                d.finish()
+            },
+            VerifErr::Strict { pos, msg } => {
+                let mut d = f.debug_struct("VerifErr::Strict");
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("{}", msg));
+                d.finish()
+            },
+            VerifErr::Hyp { thm, hyp, name, err } => {
+                let mut d = f.debug_struct("VerifErr::Hyp");
+                match name {
+                    Some(name) => d.field("Msg", &format_args!("hypothesis '{}' of assertion {} failed to unify", name, thm)),
+                    None => d.field("Msg", &format_args!("hypothesis {} of assertion {} failed to unify", hyp, thm)),
+                };
+                d.field("err", &err);
+                d.finish()
+            },
+            VerifErr::UnifyDelta { path, expected, actual } => {
+                let mut d = f.debug_struct("VerifErr::UnifyDelta");
+                d.field("Msg", &format_args!("unification failed at {:?}: expected {}, found {}", path, expected, actual));
+                d.finish()
+            },
+            VerifErr::BadBackref { decl, index, heap_len } => {
+                let mut d = f.debug_struct("VerifErr::BadBackref");
+                d.field("decl", &decl);
+                d.field("index", &index);
+                d.field("heap_len", &heap_len);
+                d.finish()
+            },
+            VerifErr::WrongItem { decl, pos, expected, found } => {
+                let mut d = f.debug_struct("VerifErr::WrongItem");
+                d.field("decl", &decl);
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("expected {}, found {}", expected, found));
+                d.finish()
+            },
+            VerifErr::ForwardReference { decl, referenced } => {
+                let mut d = f.debug_struct("VerifErr::ForwardReference");
+                d.field("decl", &decl);
+                d.field("referenced", &referenced);
+                d.finish()
+            },
+            VerifErr::DefCycle { cycle } => {
+                let mut d = f.debug_struct("VerifErr::DefCycle");
+                d.field("Msg", &format_args!("the defs {:?} unfold to each other", cycle));
+                d.finish()
+            },
+            VerifErr::Disagreement { decl, second } => {
+                let mut d = f.debug_struct("VerifErr::Disagreement");
+                d.field("decl", &decl);
+                match second {
+                    Some(err) => d.field("Msg", &format_args!("only the second checker rejected this: {:?}", err)),
+                    None => d.field("Msg", &format_args!("only the first checker rejected this")),
+                };
+                d.finish()
+            },
+            VerifErr::Divergence { ours, theirs, said } => {
+                let verdict = |accepted: bool| if accepted { "accepts" } else { "rejects" };
+                let mut d = f.debug_struct("VerifErr::Divergence");
+                d.field("Msg", &format_args!("verifier divergence: second_opinion {} the files but the reference verifier {} them", verdict(*ours), verdict(*theirs)));
+                if let Some(said) = said {
+                    d.field("said", &said);
+                }
+                d.finish()
+            },
+            VerifErr::Kernel { decl, pos, reason } => {
+                let mut d = f.debug_struct("VerifErr::Kernel");
+                d.field("decl", &decl);
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("the kernel rejected this: {}", reason));
+                d.finish()
+            },
+            VerifErr::HypNotProvable { decl, pos, sort, mods } => {
+                let mut d = f.debug_struct("VerifErr::HypNotProvable");
+                d.field("decl", &decl);
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("hypothesis of sort {}, whose modifiers ({:#04x}) aren't provable", sort, mods));
+                d.finish()
+            },
+            VerifErr::MalformedProof { decl, pos, reason } => {
+                let mut d = f.debug_struct("VerifErr::MalformedProof");
+                d.field("decl", &decl);
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("malformed proof stream: {}", reason));
+                d.finish()
+            },
+            VerifErr::MalformedUnify { decl, pos, reason } => {
+                let mut d = f.debug_struct("VerifErr::MalformedUnify");
+                d.field("decl", &decl);
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("bad unify stream: {}", reason));
+                d.finish()
+            },
+            VerifErr::StackUnderflow { decl, opcode, pos, needed, depth, top } => {
+                let mut d = f.debug_struct("VerifErr::StackUnderflow");
+                d.field("decl", &decl);
+                d.field("pos", &pos);
+                d.field("Msg", &format_args!("{} needs {} items on the stack, but there are {}", opcode, needed, depth));
+                d.field("top", &top);
+                d.finish()
+            },
+            VerifErr::Warning(msg) => {
+                let mut d = f.debug_struct("VerifErr::Warning");
+                d.field("Msg", &format_args!("{} (denied)", msg));
+                d.finish()
+            },
+            VerifErr::DependsOnFailed { decl, failed } => {
+                let mut d = f.debug_struct("VerifErr::DependsOnFailed");
+                d.field("decl", &decl);
+                d.field("Msg", &format_args!("this uses {:?}, which failed", failed));
+                d.finish()
+            },
+            VerifErr::AxiomNotAllowed { decl, axioms } => {
+                let mut d = f.debug_struct("VerifErr::AxiomNotAllowed");
+                d.field("decl", &decl);
+                d.field("Msg", &format_args!("this uses the axioms {}, which aren't allowed", axioms.join(", ")));
+                d.finish()
+            },
+            VerifErr::Conformance { decl, name, problem } => {
+                let mut d = f.debug_struct("VerifErr::Conformance");
+                if let Some(decl) = decl {
+                    d.field("decl", &decl);
+                }
+                let name = name.as_deref().unwrap_or("a declaration");
+                match problem {
+                    Mismatch::Statement(err) => d.field("Msg", &format_args!("the mm0 statement {} doesn't match the mmb file", name)).field("err", &err),
+                    Mismatch::MissingFromMmb => d.field("Msg", &format_args!("the mm0 file declares {}, but the mmb file doesn't", name)),
+                    Mismatch::MissingFromMm0 => d.field("Msg", &format_args!("the mmb file declares {}, but the mm0 file doesn't", name)),
+                    Mismatch::Local => d.field("Msg", &format_args!("the mm0 file declares {}, which is local in the mmb file", name)),
+                };
+                d.finish()
+            },
         }
     }
 }
 
+/// Errors are serialized as `{"kind": .., ..}` with the same fields as their `Debug` output.
+#[cfg(feature = "serde")]
+impl serde::Serialize for VerifErr {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        #[derive(serde::Serialize)]
+        #[serde(tag = "kind")]
+        enum Ser<'e> {
+            MakeSure { file: &'static str, line: u32 },
+            NoneErr { file: &'static str, line: u32 },
+            ConvErr { file: &'static str, line: u32 },
+            Msg { msg: &'e str },
+            Local { file: &'static str, line: u32, err: &'e VerifErr },
+            Unreachable { file: &'static str, line: u32 },
+            IoErr { file: &'static str, line: u32, err: String },
+            Timeout { decl: StmtCmd, steps: u64, elapsed_ms: Option<u128> },
+            OutOfMemory { decl: StmtCmd, bytes: usize },
+            ExpressionTooLarge { decl: StmtCmd, size: u64, depth: u32 },
+            TooManyBoundVars { decl: StmtCmd, var: u32, limit: u32 },
+            DeclCount { table: &'static str, header: u32, declared: u32 },
+            Strict { pos: usize, msg: &'e str },
+            Hyp { thm: u32, hyp: usize, name: Option<&'e str>, err: &'e VerifErr },
+            UnifyDelta { path: &'e [u32], expected: &'e str, actual: &'e str },
+            BadBackref { decl: StmtCmd, index: u32, heap_len: usize },
+            WrongItem { decl: StmtCmd, pos: usize, expected: &'static str, found: &'static str },
+            ForwardReference { decl: StmtCmd, referenced: StmtCmd },
+            DefCycle { cycle: &'e [u32] },
+            Disagreement { decl: StmtCmd, second: Option<&'e VerifErr> },
+            Divergence { ours: bool, theirs: bool, said: Option<&'e str> },
+            Kernel { decl: StmtCmd, pos: usize, reason: &'static str },
+            Warning { msg: &'e str },
+            DependsOnFailed { decl: StmtCmd, failed: StmtCmd },
+            AxiomNotAllowed { decl: StmtCmd, axioms: &'e [String] },
+            Conformance { decl: Option<StmtCmd>, name: Option<&'e str>, problem: &'static str, err: Option<&'e VerifErr> },
+            HypNotProvable { decl: StmtCmd, pos: usize, sort: u8, mods: u8 },
+            MalformedProof { decl: StmtCmd, pos: usize, reason: &'static str },
+            MalformedUnify { decl: StmtCmd, pos: usize, reason: &'e str },
+            StackUnderflow { decl: StmtCmd, opcode: &'e str, pos: usize, needed: usize, depth: usize, top: &'e [String] },
+        }
+        match self {
+            VerifErr::MakeSure(file, line) => Ser::MakeSure { file, line: *line },
+            VerifErr::NoneErr(file, line) => Ser::NoneErr { file, line: *line },
+            VerifErr::ConvErr(file, line) => Ser::ConvErr { file, line: *line },
+            VerifErr::Msg(msg) => Ser::Msg { msg },
+            VerifErr::Local(file, line, err) => Ser::Local { file, line: *line, err },
+            VerifErr::Unreachable(file, line) => Ser::Unreachable { file, line: *line },
+            #[cfg(feature = "std")]
+            VerifErr::IoErr(file, line, err) => Ser::IoErr { file, line: *line, err: err.to_string() },
+            VerifErr::Timeout { decl, steps, elapsed_ms } => Ser::Timeout { decl: *decl, steps: *steps, elapsed_ms: *elapsed_ms },
+            VerifErr::OutOfMemory { decl, bytes } => Ser::OutOfMemory { decl: *decl, bytes: *bytes },
+            VerifErr::ExpressionTooLarge { decl, size, depth } => Ser::ExpressionTooLarge { decl: *decl, size: *size, depth: *depth },
+            VerifErr::TooManyBoundVars { decl, var } => Ser::TooManyBoundVars { decl: *decl, var: *var, limit: MAX_BOUND_VARS },
+            VerifErr::DeclCount { table, header, declared } => Ser::DeclCount { table, header: *header, declared: *declared },
+            VerifErr::Strict { pos, msg } => Ser::Strict { pos: *pos, msg },
+            VerifErr::Hyp { thm, hyp, name, err } => Ser::Hyp { thm: *thm, hyp: *hyp, name: name.as_deref(), err },
+            VerifErr::UnifyDelta { path, expected, actual } => Ser::UnifyDelta { path, expected, actual },
+            VerifErr::BadBackref { decl, index, heap_len } => Ser::BadBackref { decl: *decl, index: *index, heap_len: *heap_len },
+            VerifErr::WrongItem { decl, pos, expected, found } => Ser::WrongItem { decl: *decl, pos: *pos, expected, found },
+            VerifErr::ForwardReference { decl, referenced } => Ser::ForwardReference { decl: *decl, referenced: *referenced },
+            VerifErr::DefCycle { cycle } => Ser::DefCycle { cycle },
+            VerifErr::Disagreement { decl, second } => Ser::Disagreement { decl: *decl, second: second.as_deref() },
+            VerifErr::Divergence { ours, theirs, said } => Ser::Divergence { ours: *ours, theirs: *theirs, said: said.as_deref() },
+            VerifErr::Kernel { decl, pos, reason } => Ser::Kernel { decl: *decl, pos: *pos, reason: *reason },
+            VerifErr::Warning(msg) => Ser::Warning { msg },
+            VerifErr::DependsOnFailed { decl, failed } => Ser::DependsOnFailed { decl: *decl, failed: *failed },
+            VerifErr::AxiomNotAllowed { decl, axioms } => Ser::AxiomNotAllowed { decl: *decl, axioms },
+            VerifErr::Conformance { decl, name, problem } => Ser::Conformance {
+                decl: *decl,
+                name: name.as_deref(),
+                problem: problem.name(),
+                err: match problem { Mismatch::Statement(err) => Some(err), _ => None },
+            },
+            VerifErr::HypNotProvable { decl, pos, sort, mods } => Ser::HypNotProvable { decl: *decl, pos: *pos, sort: *sort, mods: *mods },
+            VerifErr::MalformedProof { decl, pos, reason } => Ser::MalformedProof { decl: *decl, pos: *pos, reason },
+            VerifErr::MalformedUnify { decl, pos, reason } => Ser::MalformedUnify { decl: *decl, pos: *pos, reason },
+            VerifErr::StackUnderflow { decl, opcode, pos, needed, depth, top } =>
+                Ser::StackUnderflow { decl: *decl, opcode, pos: *pos, needed: *needed, depth: *depth, top },
+        }.serialize(s)
+    }
+}
+
  /// A reference to an entry in the term table.
 #[derive(Debug, Clone, Copy)]
 pub struct Term<'a> {
@@ -410,11 +1068,36 @@ pub struct Term<'a> {
 
 /// An iterator over the arguments of a term/assertion that doesn't require allocation
 /// of a vector, instead reading them from the source file each time.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 pub struct Args<'a> {
     source: &'a [u8],
 }
 
+impl<'a> Args<'a> {
+    /// Argument number `idx` (from 0), read straight from the file without going through
+    /// the ones before it.
+    pub fn get(&self, idx: usize) -> Option<Type> {
+        let start = idx.checked_mul(8)?;
+        let bytes = self.source.get(start..start.checked_add(8)?)?;
+        parse_u64(bytes).ok().map(|(inner, _)| Type { inner })
+    }
+
+    /// The last type, which for `Term::args` is the return type.
+    pub fn ret(&self) -> Option<Type> {
+        self.get(self.len().checked_sub(1)?)
+    }
+
+    /// Check that `given` arguments are the right number for these, failing with a message
+    /// saying both numbers if not. `what` says whose arguments they are.
+    pub fn expect_len(&self, given: usize, what: impl core::fmt::Display) -> Res<()> {
+        if self.len() == given {
+            Ok(())
+        } else {
+            Err(VerifErr::Msg(format!("{} takes {} arguments, but was given {}", what, self.len(), given)))
+        }
+    }
+}
+
 impl<'a> DoubleEndedIterator for Args<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
         if self.source.is_empty() {
@@ -437,7 +1120,7 @@ impl<'a> DoubleEndedIterator for Args<'a> {
 
 impl<'a> ExactSizeIterator for Args<'a> {
     fn len(&self) -> usize {
-        self.source.len() / std::mem::size_of::<u64>()
+        self.source.len() / core::mem::size_of::<u64>()
     }
 }
 
@@ -459,10 +1142,20 @@ impl<'a> Iterator for Args<'a> {
     // Needed for `len` to work correctly.
     fn size_hint(&self) -> (usize, Option<usize>) {
         (
-            self.source.len() / std::mem::size_of::<u64>(),
-            Some(self.source.len() / std::mem::size_of::<u64>())
+            self.source.len() / core::mem::size_of::<u64>(),
+            Some(self.source.len() / core::mem::size_of::<u64>())
         )
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let skip = n.saturating_mul(8).min(self.source.len());
+        self.source = &self.source[skip..];
+        self.next()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
 }
 
 
@@ -497,6 +1190,28 @@ fn args_back1() {
 }
 
 
+#[test]
+fn args_get1() {
+    let words = [3u64, 5, 7];
+    let source = words.iter().flat_map(|w| w.to_le_bytes()).collect::<Vec<u8>>();
+    let args = Args { source: &source };
+    assert_eq!(args.get(1), Some(Type { inner: 5 }));
+    assert_eq!(args.get(3), None);
+    assert_eq!(args.get(usize::MAX), None);
+    assert_eq!(args.ret(), Some(Type { inner: 7 }));
+    // `Args` is `Copy`, so each of these iterates its own copy and `args` stays put.
+    let mut third = args;
+    assert_eq!(third.nth(2), Some(Type { inner: 7 }));
+    let mut rest = args;
+    assert_eq!(rest.next(), Some(Type { inner: 3 }));
+    assert_eq!(rest.len(), 2);
+    let mut past_end = args;
+    assert_eq!(past_end.nth(5), None);
+    assert_eq!(Args::default().ret(), None);
+    assert!(args.expect_len(3, "t").is_ok());
+    assert!(format!("{:?}", args.expect_len(2, "t")).contains("t takes 3 arguments, but was given 2"));
+}
+
 #[test]
 fn args_back_err1() {
     let s1 = &[10, 11, 12, 13, 14, 15, 16];
@@ -507,7 +1222,7 @@ fn args_back_err1() {
 
 #[test]
 fn args_no_ret1() {
-    let fake_unify = UnifyIter { buf: &[], pos: 0 };
+    let fake_unify = UnifyIter::new(&[], 0);
     let s1 = &[
         10, 11, 12, 13, 14, 15, 16, 17,
         18, 19, 20, 21, 22, 23, 24, 25,
@@ -525,14 +1240,14 @@ fn args_no_ret1() {
 
 #[test]
 fn args_no_ret2() {
-    let fake_unify = UnifyIter { buf: &[], pos: 0 };
+    let fake_unify = UnifyIter::new(&[], 0);
     let s1 = &[
         10, 11, 12, 13, 14, 15, 16, 17,
     ];
     let t = Term { term_num: 0, sort: 0, args_start: s1, unify: fake_unify };
 
     let args_no_ret = t.args_no_ret();
-    assert_eq!(args_no_ret.source, &[]);
+    assert_eq!(args_no_ret.source, &[] as &[u8]);
 }
 
 impl<'a> Term<'a> {
@@ -563,7 +1278,7 @@ impl<'a> Term<'a> {
     #[inline]
     pub fn args_no_ret(&self) -> Args<'a> {
         Args {
-            source: &self.args_start[..(self.args_start.len() - std::mem::size_of::<u64>())],
+            source: &self.args_start[..(self.args_start.len() - core::mem::size_of::<u64>())],
         }
     }    
 
@@ -573,7 +1288,7 @@ impl<'a> Term<'a> {
 
     /// The return sort and dependencies.
     pub fn ret(&self) -> Type {
-        self.args().last().unwrap()
+        self.args().ret().unwrap()
     }
 
     /// The beginning of the unify stream for the term.
@@ -615,11 +1330,50 @@ impl<'a> Assert<'a> {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(transparent)]
 pub struct Mods {
     pub inner: u8,
 }
 
+#[test]
+fn type_parts1() {
+    let ty = Type::from_parts(3, false, 0b1010).unwrap();
+    assert_eq!((ty.sort(), ty.is_bound(), ty.deps_iter().collect::<Vec<_>>()), (3, false, vec![2, 4]));
+    assert_eq!(Type::from_bits(ty.bits()).unwrap(), ty);
+    assert_eq!(format!("{:?}", ty), "Type { sort: 3, deps: [2, 4] }");
+    let bv = Type::from_parts(1, true, 1 << 4).unwrap();
+    assert_eq!((bv.bound_pos().unwrap(), bv.deps_iter().count()), (5, 0));
+    assert_eq!(format!("{:?}", bv), "Type { sort: 1, bound: 5 }");
+    // Two bits for a bound variable, a variable past the last, and a sort that doesn't fit.
+    assert!(Type::from_parts(0, true, 0b11).is_err());
+    assert!(Type::from_parts(0, false, 1 << MAX_BOUND_VARS).is_err());
+    assert!(Type::from_parts(0x80, false, 0).is_err());
+    let two_bits = crate::mmb::TYPE_BOUND_MASK | 0b11;
+    assert!(Type::from_bits(two_bits).is_err());
+    assert!(format!("{:?}", Type { inner: two_bits }).contains("bits"));
+}
+
+#[test]
+fn coercible_to1() {
+    // Every sort and bound bit on both sides, with dependencies that should be ignored.
+    let deps = [0u64, 1, crate::mmb::TYPE_DEPS_MASK];
+    for from_sort in 0..128u8 {
+        for to_sort in 0..128u8 {
+            for (from_bound, to_bound) in [(false, false), (false, true), (true, false), (true, true)] {
+                for (from_deps, to_deps) in deps.iter().zip(deps.iter().rev()) {
+                    let from = Type { inner: Type::new(from_bound).inner | Type::new_with_sort(from_sort).inner | from_deps };
+                    let to = Type { inner: Type::new(to_bound).inner | Type::new_with_sort(to_sort).inner | to_deps };
+                    let expected = from_sort == to_sort && (from_bound || !to_bound);
+                    assert_eq!(from.coercible_to(to), expected, "{:?} to {:?}", from, to);
+                    assert_eq!(from.coercible_to_binder(to), expected);
+                    assert_eq!(crate::mmb::sorts_compatible(from, to), expected);
+                }
+            }
+        }
+    }
+}
+
 #[test]
 fn test_mods1() {
     let m = Mods { inner: 1 };
@@ -678,6 +1432,10 @@ pub struct DeclIter<'a> {
     pub next_sort_num: u8,
     pub next_termdef_num: u32,
     pub next_assert_num: u32,
+    /// Statements with command codes outside the spec, which are passed over
+    /// here and checked later by whatever handler is registered for them.
+    pub ext_stmts: Vec<crate::mmb::ext::ExtStmt<'a>>,
+    pub num_decls: usize,
 }
 
 /// We pair the item number (sortnum, termnum, assertnum) with the statement
@@ -686,9 +1444,26 @@ pub struct DeclIter<'a> {
 impl<'a> Iterator for DeclIter<'a> {
     type Item = Result<(StmtCmd, ProofIter<'a>), VerifErr>;
     fn next(&mut self) -> Option<Self::Item> {
-        match try_next_decl(self.mmb, self.pos)? {
+        let mut next = try_next_decl(self.mmb, self.pos)?;
+        while let Ok((Err(cmd), pr, rest)) = next {
+            let body = match self.mmb.get(pr.pos..pr.ends_at) {
+                None => return Some(Err(VerifErr::Msg(format!("statement @ pos {} runs past the end of the file", self.pos)))),
+                Some(body) => body
+            };
+            self.ext_stmts.push(crate::mmb::ext::ExtStmt {
+                cmd,
+                pos: self.pos,
+                decls_before: self.num_decls,
+                body,
+            });
+            self.pos = rest;
+            next = try_next_decl(self.mmb, self.pos)?;
+        }
+        match next {
             Err(e) => Some(Err(e)),
-            Ok((stmt, pr, rest)) => {
+            Ok((Err(_), ..)) => unreachable!(),
+            Ok((Ok(stmt), pr, rest)) => {
+                self.num_decls += 1;
                 let stmt = match stmt {
                     StmtCmd::Sort {..} => {
                         let num = self.next_sort_num;
@@ -718,9 +1493,12 @@ impl<'a> Iterator for DeclIter<'a> {
     }
 }
 
+/// A statement command, or the code of a statement outside the spec.
+type MaybeStmt = Result<StmtCmd, u8>;
+
 /// is only used for `DeclIter::next`.
-/// This always gets the full mmb file
-fn try_next_decl(mmb: &[u8], pos: usize) -> Option<Res<(StmtCmd, ProofIter<'_>, usize)>> {
+/// This always gets the full mmb file. Unknown statement codes are returned as `Err(cmd)`.
+fn try_next_decl(mmb: &[u8], pos: usize) -> Option<Res<(MaybeStmt, ProofIter<'_>, usize)>> {
     let (cmd, data, rest) = match try_next_cmd(mmb, pos) {
         // Means cmd == 0, but here is unreachable
         Ok(None) => return None,
@@ -732,13 +1510,9 @@ fn try_next_decl(mmb: &[u8], pos: usize) -> Option<Res<(StmtCmd, ProofIter<'_>,
     let next2 = pos + data as usize;
 
 
-    let pr = ProofIter {
-        buf: mmb,
-        pos: rest,
-        ends_at: pos + (data as usize)
-    };
+    let pr = ProofIter::new(mmb, rest, pos + (data as usize));
     Some(Ok((
-        StmtCmd::try_from(cmd).ok()?,
+        StmtCmd::try_from(cmd).map_err(|_| cmd),
         pr, 
         next2
     )))
@@ -762,19 +1536,12 @@ where T: TryFrom<(u8, u32)> {
 
 
 
-/// Constants used in the MMB specification.
+/// Constants used in the MMB specification; see `mmb::format` for the rest.
 pub mod cmd {
-    /// `DATA_8 = 0x40`, used as a command mask for an 8 bit data field
-    pub const DATA_8: u8 = 0x40;
-    /// `DATA_16 = 0x80`, used as a command mask for a 16 bit data field
-    pub const DATA_16: u8 = 0x80;
-    /// `DATA_32 = 0xC0`, used as a command mask for a 32 bit data field
-    pub const DATA_32: u8 = 0xC0;
-    /// `DATA_MASK = 0xC0`, selects one of `DATA_8`, `DATA_16`, or `DATA_32` for data size
-    pub const DATA_MASK: u8 = 0xC0;
+    pub use crate::mmb::format::{ DATA_8, DATA_16, DATA_32, DATA_MASK };
 }
 
-fn parse_cmd(mmb: &[u8], start_at: usize) -> Res<(u8, u32, usize)> {
+pub(crate) fn parse_cmd(mmb: &[u8], start_at: usize) -> Res<(u8, u32, usize)> {
     match mmb.get(start_at..) {
         None | Some([]) => Err(VerifErr::Msg("Parse cmd exhausted".to_string())),
         Some([n, tl @ ..]) => {
@@ -810,41 +1577,130 @@ pub struct Outline<'a> {
     /// Get the proof stream for the file.
     /// Has the whole mmb file, and the position at which the proof stream starts (taken from the header)
     pub declarations: Vec<(StmtCmd, ProofIter<'a>)>,
+    /// The position of the 0 that ends the proof stream.
+    pub proof_stream_end: usize,
+    /// How many terms the proof stream declares before each assertion, by assertion number,
+    /// and how many assertions before each term. A declaration can only refer to the ones
+    /// before it.
+    pub terms_before: Vec<u32>,
+    pub thms_before: Vec<u32>,
+    pub options: crate::options::VerifyOptions,
+    /// Statements in the proof stream with command codes outside the spec.
+    pub ext_stmts: Vec<crate::mmb::ext::ExtStmt<'a>>,
+    /// Handlers for `ext_stmts`, registered by whoever embeds the verifier.
+    pub extensions: crate::mmb::ext::StmtExtensions,
+    /// Which axioms theorems may use; see `mmb::axioms`.
+    pub axiom_policy: crate::mmb::axioms::AxiomPolicy,
+    /// The mm0 file's notation, for writing expressions in errors and listings; see
+    /// `mmz::notation`. Without it, they're written as s-expressions.
+    pub notations: Option<crate::mmz::notation::Notations>,
+    warnings: Shared<Vec<String>>,
+    /// Filled in when `options.arena_stats` is set.
+    pub arena_stats: Shared<crate::stats::ArenaStats>,
+    /// See `PhaseTimes` for which are always filled in.
+    pub phase_times: Shared<crate::stats::PhaseTimes>,
+    /// Arenas for the workers to borrow, kept between runs.
+    pub arenas: crate::arena::BumpPool,
+    /// The hypothesis names of each assertion that names any, in declaration order, filled
+    /// in as the mm0 file is checked. The mmb file has nowhere to put them.
+    pub hyp_names: Shared<HashMap<u32, Vec<Option<String>>>>,
+    /// How term and assertion lookups are done: see `mmb::tables`.
+    pub tables: crate::mmb::tables::TableCache<'a>,
     mmb_num_sorts_done: AtomicU8,
     mmb_num_termdefs_done: AtomicU32,
     mmb_num_asserts_done: AtomicU32,
+    /// Which sorts, terms, and assertions the mmb workers have verified, so that
+    /// verifying one twice is caught even when workers run concurrently.
+    mmb_done: [DoneSet; 3],
 }
 
 impl<'a> Outline<'a> {
     pub fn new_from(file_data: &'a crate::fs::FileData) -> Res<Self> {
+        let started = crate::options::clock();
         let header = crate::mmb::parse_header(file_data.mmb_file.as_slice())?;
+        let index_started = crate::options::clock();
         let index  = crate::mmb::index::parse_index(file_data.mmb_file.as_slice(), header)?;
-        let declars = DeclIter {
+        let index_time = crate::options::since(index_started);
+        let mut declars = DeclIter {
             mmb: file_data.mmb_file.as_slice(),
             pos: header.proof_stream_start as usize,
             next_sort_num: 0,
             next_termdef_num: 0,
             next_assert_num: 0,
+            ext_stmts: Vec::new(),
+            num_decls: 0,
         };
         
-        let declarations: Vec<(StmtCmd, ProofIter)> = declars.collect::<Result<Vec<(StmtCmd, ProofIter)>, VerifErr>>()?;
+        let declarations: Vec<(StmtCmd, ProofIter)> = declars.by_ref().collect::<Result<Vec<(StmtCmd, ProofIter)>, VerifErr>>()?;
+        let (mut terms_before, mut thms_before) = (Vec::new(), Vec::new());
+        for (stmt, _) in declarations.iter() {
+            match stmt {
+                StmtCmd::Sort {..} => {}
+                StmtCmd::TermDef {..} => thms_before.push(terms_before.len() as u32),
+                StmtCmd::Axiom {..} | StmtCmd::Thm {..} => terms_before.push(thms_before.len() as u32),
+            }
+        }
+        let phase_times = crate::stats::PhaseTimes {
+            tables: crate::options::since(started) - index_time,
+            index: index_time,
+            ..Default::default()
+        };
         Ok(Outline {
             file_data,
             header,
             index,
             declarations,
+            proof_stream_end: declars.pos,
+            terms_before,
+            thms_before,
+            options: crate::options::VerifyOptions::default(),
+            ext_stmts: declars.ext_stmts,
+            extensions: crate::mmb::ext::StmtExtensions::default(),
+            axiom_policy: crate::mmb::axioms::AxiomPolicy::default(),
+            notations: None,
+            warnings: Shared::default(),
+            arena_stats: Shared::default(),
+            phase_times: Shared::new(phase_times),
+            arenas: crate::arena::BumpPool::default(),
+            hyp_names: Shared::default(),
+            tables: Default::default(),
             mmb_num_sorts_done: AtomicU8::new(0),
             mmb_num_termdefs_done: AtomicU32::new(0),
             mmb_num_asserts_done: AtomicU32::new(0),
+            mmb_done: [
+                DoneSet::new(header.num_sorts as usize),
+                DoneSet::new(header.num_terms as usize),
+                DoneSet::new(header.num_thms as usize),
+            ],
         })
     }    
 
-    pub fn add_declar(&self, stmt: StmtCmd) {
+    /// Record that `stmt` has been verified. This can be called from several workers at
+    /// once; each declaration is only counted the first time.
+    pub fn add_declar(&self, stmt: StmtCmd) -> Res<()> {
+        let (table, num) = match stmt {
+            StmtCmd::Sort { num } => (0, none_err!(num)? as usize),
+            StmtCmd::TermDef { num, .. } => (1, none_err!(num)? as usize),
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => (2, none_err!(num)? as usize),
+        };
+        if self.mmb_done[table].insert(num)? {
+            return Err(VerifErr::Msg(format!("{:?} was verified more than once", stmt)))
+        }
+        match table {
+            0 => { self.next_sort(); },
+            1 => { self.next_termdef(); },
+            _ => { self.next_assert(); },
+        }
+        Ok(())
+    }
+
+    /// Whether the mmb workers have verified `stmt` yet.
+    pub fn is_declar_done(&self, stmt: StmtCmd) -> bool {
         match stmt {
-            StmtCmd::Sort {..} => { self.next_sort(); },
-            StmtCmd::TermDef {..} => { self.next_termdef(); },
-            StmtCmd::Axiom {..} | StmtCmd::Thm {..} => { self.next_assert(); },
-        }        
+            StmtCmd::Sort { num } => num.map(|n| self.mmb_done[0].contains(n as usize)),
+            StmtCmd::TermDef { num, .. } => num.map(|n| self.mmb_done[1].contains(n as usize)),
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => num.map(|n| self.mmb_done[2].contains(n as usize)),
+        }.unwrap_or(false)
     }
 
     pub fn assert_mmz_done(&self, mmz: &crate::mmz::MmzMem<'a>, errs: &mut Vec<VerifErr>) {
@@ -873,8 +1729,61 @@ impl<'a> Outline<'a> {
         }        
     }
 
+    /// The number of sorts, terms, and assertions the proof stream declares.
+    pub fn num_declared(&self) -> (u32, u32, u32) {
+        let (mut sorts, mut termdefs, mut asserts) = (0, 0, 0);
+        for (stmt, _) in self.declarations.iter() {
+            match stmt {
+                StmtCmd::Sort {..} => sorts += 1,
+                StmtCmd::TermDef {..} => termdefs += 1,
+                StmtCmd::Axiom {..} | StmtCmd::Thm {..} => asserts += 1,
+            }
+        }
+        (sorts, termdefs, asserts)
+    }
+
+    /// Check that the proof stream declares every entry of the sort, term, and theorem
+    /// tables exactly once. Declarations are numbered in the order they appear, so this
+    /// comes down to the counts matching the header. This doesn't depend on what's been
+    /// verified, so it's checked even when only some of the declarations are.
+    pub fn check_decl_counts(&self, errs: &mut Vec<VerifErr>) {
+        let (sorts, termdefs, asserts) = self.num_declared();
+        let tables = [
+            ("sorts", self.header.num_sorts as u32, sorts),
+            ("terms", self.header.num_terms, termdefs),
+            ("theorems", self.header.num_thms, asserts),
+        ];
+        for (table, header, declared) in tables.iter() {
+            if header != declared {
+                errs.push(VerifErr::DeclCount { table, header: *header, declared: *declared })
+            }
+        }
+    }
+
+    /// The checks on the file as a whole that don't depend on what's been verified:
+    /// `check_decl_counts`, `check_def_cycles`, and `check_strict` if `options.strict` is set.
+    pub fn check_layout(&self, errs: &mut Vec<VerifErr>) {
+        self.check_decl_counts(errs);
+        self.check_def_cycles(errs);
+        if self.options.strict {
+            self.check_strict(errs);
+        }
+    }
+
+    /// Whether `stmt`'s number is within its table.
+    pub fn in_table(&self, stmt: StmtCmd) -> bool {
+        match stmt {
+            StmtCmd::Sort { num } => num.map(|n| n < self.header.num_sorts),
+            StmtCmd::TermDef { num, .. } => num.map(|n| n < self.header.num_terms),
+            StmtCmd::Axiom { num } | StmtCmd::Thm { num, .. } => num.map(|n| n < self.header.num_thms),
+        }.unwrap_or(false)
+    }
+
+    /// Check that everything the proof stream declares was verified. A table whose count
+    /// is wrong has already been reported by `check_decl_counts`, so it's skipped here.
     pub fn assert_mmb_done(&self, errs: &mut Vec<VerifErr>) {
-        if self.mmb_num_sorts_done() != self.header.num_sorts {
+        let (sorts, termdefs, asserts) = self.num_declared();
+        if sorts == self.header.num_sorts as u32 && self.mmb_num_sorts_done() != self.header.num_sorts {
             errs.push(VerifErr::Msg(format!(
                 "mmb verified fewer sorts than specified in the header. Verified {}, header specified {}", 
                 self.mmb_num_sorts_done(), 
@@ -882,7 +1791,7 @@ impl<'a> Outline<'a> {
             )))
         }
 
-        if self.mmb_num_termdefs_done() != self.header.num_terms {
+        if termdefs == self.header.num_terms && self.mmb_num_termdefs_done() != self.header.num_terms {
             errs.push(VerifErr::Msg(format!(
                 "mmb verified fewer terms than specified in the header. Verified {}, header specified {}", 
                 self.mmb_num_termdefs_done(), 
@@ -890,7 +1799,7 @@ impl<'a> Outline<'a> {
             )))
         }
 
-        if self.mmb_num_asserts_done() != self.header.num_thms {
+        if asserts == self.header.num_thms && self.mmb_num_asserts_done() != self.header.num_thms {
             errs.push(VerifErr::Msg(format!(
                 "mmb verified fewer assertions than specified in the header. Verified {}, header specified {}", 
                 self.mmb_num_asserts_done(), 
@@ -898,6 +1807,22 @@ impl<'a> Outline<'a> {
             )))
         }         
     }
+
+    /// The checks that follow verifying `decls`, in every way of running it:
+    /// `flag_dependents` if `options.keep_going` is set, the ext statements, the axiom
+    /// policy, `check_layout`, and, unless `decls` leaves some declarations out (as a
+    /// spot-check does), `assert_mmb_done`.
+    pub fn finish_checks(&self, decls: &[(StmtCmd, ProofIter<'a>)], errs: &mut Vec<VerifErr>) {
+        if self.options.keep_going {
+            self.flag_dependents(decls, errs);
+        }
+        self.verify_ext_stmts(errs);
+        self.check_axioms(decls, errs);
+        self.check_layout(errs);
+        if decls.len() == self.declarations.len() {
+            self.assert_mmb_done(errs);
+        }
+    }
 }
 
 impl<'a> Outline<'a> {
@@ -905,6 +1830,15 @@ impl<'a> Outline<'a> {
         self.file_data.mmb_file.as_slice()
     }
 
+    /// Record something worth telling the user that doesn't make verification fail.
+    pub fn warn(&self, msg: String) {
+        self.warnings.with(|ws| ws.push(msg))
+    }
+
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.with(core::mem::take)
+    }
+
     pub fn mmb_num_sorts_done(&self) -> u8 {
         self.mmb_num_sorts_done.load(Relaxed)
     }
@@ -917,6 +1851,19 @@ impl<'a> Outline<'a> {
         self.mmb_num_asserts_done.load(Relaxed)
     }
 
+    /// Set the mmb counters, for resuming from a checkpoint. Items are numbered in the order
+    /// they're declared, so the first `sorts` sorts (and so on) are the ones that were done.
+    pub(crate) fn restore_done(&self, sorts: u8, termdefs: u32, asserts: u32) {
+        for (set, n) in self.mmb_done.iter().zip([sorts as u32, termdefs, asserts].iter()) {
+            for i in 0..*n {
+                let _ = set.insert(i as usize);
+            }
+        }
+        self.mmb_num_sorts_done.store(sorts, Relaxed);
+        self.mmb_num_termdefs_done.store(termdefs, Relaxed);
+        self.mmb_num_asserts_done.store(asserts, Relaxed);
+    }
+
     fn next_sort(&self) -> u8 {
         self.mmb_num_sorts_done.fetch_add(1, Relaxed)
     }
@@ -940,7 +1887,12 @@ impl<'a> Outline<'a> {
 
     /// Get a term (by number) from the mmb file
     pub fn get_term_by_num(&self, term_num: u32) -> Res<Term<'a>> {
-        let start_point = (self.header.terms_start as usize) + ((term_num as usize) * 8);
+        self.lookup_term(term_num)
+    }
+
+    /// Read entry `term_num` of the term table.
+    pub(crate) fn parse_term(&self, term_num: u32) -> Res<Term<'a>> {
+        let start_point = (self.header.terms_start as usize) + ((term_num as usize) * crate::mmb::format::TABLE_ENTRY_SIZE);
         let source = none_err!(self.mmb().get(start_point..))?;
         let (num_args, source) = parse_u16(source)?;
         let (sort, source) = parse_u8(source)?;
@@ -948,20 +1900,23 @@ impl<'a> Outline<'a> {
         let (term_args_start, _) = parse_u32(source)?;
 
         let args_start = none_err!(self.mmb().get(term_args_start as usize..))?;
-        let split_point = none_err!{ std::mem::size_of::<u64>().checked_mul((num_args + 1) as usize) }?;
+        let split_point = none_err!{ core::mem::size_of::<u64>().checked_mul((num_args + 1) as usize) }?;
+        make_sure!(split_point <= args_start.len());
         let (args_start, unify_start) = args_start.split_at(split_point);
 
-        let unify = UnifyIter {
-            buf: self.mmb(),
-            pos: self.mmb().len() - unify_start.len(),
-        };
+        let unify = UnifyIter::new(self.mmb(), self.mmb().len() - unify_start.len());
 
         Ok(Term { term_num, sort, args_start, unify })
     }
 
     /// Get an assertion (by number) from the mmb file
     pub fn get_assert_by_num(&self, assert_num: u32) -> Res<Assert<'a>> {
-        let thm_start = (self.header.thms_start + (assert_num * 8)) as usize;
+        self.lookup_assert(assert_num)
+    }
+
+    /// Read entry `assert_num` of the theorem table.
+    pub(crate) fn parse_assert(&self, assert_num: u32) -> Res<Assert<'a>> {
+        let thm_start = self.header.thms_start as usize + (assert_num as usize * crate::mmb::format::TABLE_ENTRY_SIZE);
         let source = self.mmb().get(thm_start..).ok_or(VerifErr::Msg(format!("Bad index")))?;
         let (num_args, source) = parse_u16(source)?;
         let (_reserved, source) = parse_u16(source)?;
@@ -969,13 +1924,11 @@ impl<'a> Outline<'a> {
 
         let args_slice = none_err!(self.mmb().get(args_start as usize..))?;
 
-        let split_point = none_err! { std::mem::size_of::<u64>().checked_mul(num_args as usize) }?;
+        let split_point = none_err! { core::mem::size_of::<u64>().checked_mul(num_args as usize) }?;
+        make_sure!(split_point <= args_slice.len());
         let (args_start, unify_start) = args_slice.split_at(split_point);
 
-        let unify = UnifyIter {
-            buf: self.mmb(),
-            pos: self.mmb().len() - unify_start.len(),
-        };
+        let unify = UnifyIter::new(self.mmb(), self.mmb().len() - unify_start.len());
 
         Ok(Assert { assert_num, args_start, unify })        
     }      
@@ -1036,8 +1989,58 @@ pub fn view8(n: u8) -> String {
     acc
 }
 
+/// The fields of the type, like `Type { sort: 2, bound: 1 }` or `Type { sort: 0, deps: [1, 3] }`,
+/// and all the bits too if it isn't well-formed.
 impl Debug for Type {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}", view64(self.inner))
+        let mut d = f.debug_struct("Type");
+        d.field("sort", &self.sort());
+        match (self.is_bound(), self.bound_pos()) {
+            (true, Ok(pos)) => { d.field("bound", &pos); }
+            (true, Err(_)) => { d.field("bound", &true); }
+            (false, _) => { d.field("deps", &self.deps_iter().collect::<Vec<_>>()); }
+        }
+        if Type::from_bits(self.inner).is_err() {
+            d.field("bits", &format_args!("{}", view64(self.inner)));
+        }
+        d.finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde1() {
+    let file_data = crate::fs::FileData::new_from("./test_resources/short.mmb", None::<String>).unwrap();
+    let outline = Outline::new_from(&file_data).unwrap();
+    let header: crate::mmb::Header = serde_json::from_str(&serde_json::to_string(&outline.header).unwrap()).unwrap();
+    assert_eq!(header.num_thms, outline.header.num_thms);
+    assert_eq!(header.index_start, outline.header.index_start);
+    for (stmt, _) in outline.declarations.iter() {
+        assert_eq!(serde_json::from_str::<StmtCmd>(&serde_json::to_string(stmt).unwrap()).unwrap(), *stmt);
     }
+    let ty = outline.get_term_by_num(0).unwrap().ret();
+    assert_eq!(serde_json::from_str::<Type>(&serde_json::to_string(&ty).unwrap()).unwrap(), ty);
+
+    let err = VerifErr::Local("util.rs", 1, Box::new(VerifErr::Msg("oops".to_string())));
+    assert_eq!(
+        serde_json::to_string(&err).unwrap(),
+        r#"{"kind":"Local","file":"util.rs","line":1,"err":{"kind":"Msg","msg":"oops"}}"#
+    );
+}
+
+#[test]
+fn decl_counts1() {
+    use crate::testgen::Fixture;
+    let mut fx = Fixture::valid();
+    let extra = fx.stmts[2].clone();
+    fx.stmts.push(extra);
+    fx.with_outline(|outline| {
+        assert_eq!(outline.num_declared(), (1, 1, 3));
+        let errs = crate::verify_serial(outline, &outline.declarations);
+        assert_eq!(errs.len(), 2);
+        match errs.last() {
+            Some(VerifErr::DeclCount { table: "theorems", header: 2, declared: 3 }) => {}
+            owise => panic!("expected a theorem count mismatch, got {:?}", owise),
+        }
+    });
 }
@@ -0,0 +1,30 @@
+//! Bindings for running the verifier in a browser (for example inside a web-based MM0 IDE).
+//! There's no file system to resolve mm0 imports against, so only the mmb file is checked.
+#![allow(unsafe_code)]
+
+use wasm_bindgen::prelude::*;
+use crate::Outline;
+use crate::fs::FileData;
+use crate::json::Json;
+
+/// Verify an mmb file given as bytes. Returns an object of the form
+/// `{ ok: bool, sorts: number, terms: number, thms: number, errors: [string] }`.
+#[wasm_bindgen]
+pub fn verify(bytes: &[u8]) -> JsValue {
+    let file_data = FileData::new_from_bytes(bytes.to_vec(), Vec::new());
+    let report = match Outline::new_from(&file_data) {
+        Err(e) => Json::obj()
+            .with("ok", false)
+            .with("errors", vec![format!("{:?}", e)]),
+        Ok(outline) => {
+            let errs = crate::verify_serial(&outline, outline.declarations.as_slice());
+            Json::obj()
+                .with("ok", errs.is_empty())
+                .with("sorts", outline.header.num_sorts)
+                .with("terms", outline.header.num_terms)
+                .with("thms", outline.header.num_thms)
+                .with("errors", errs.iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>())
+        }
+    };
+    js_sys::JSON::parse(&report.to_string()).unwrap_or(JsValue::NULL)
+}
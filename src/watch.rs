@@ -0,0 +1,117 @@
+//! Re-verifying a file that keeps changing, like an mmb file being rebuilt during
+//! development, without re-checking the declarations that can't have changed.
+//!
+//! Declarations are matched up between versions by name, the way `diff` does it. One is
+//! skipped if it verified last time with the same statement and proof, and everything it
+//! uses comes before it and has the same statement as last time. Anything else, including
+//! every declaration after a change to a sort, is checked again. The mmz files and the
+//! checks on the whole file are always redone.
+
+use crate::Outline;
+use crate::diff::{ table, Fingerprint };
+use crate::lsp::Located;
+use crate::mmb::stmt::StmtCmd;
+use crate::mmb::proof::ProofIter;
+use crate::util::HashMap;
+
+type Key = (&'static str, String);
+
+/// What's remembered about the last version of the file.
+#[derive(Debug, Default)]
+pub struct Incremental {
+    /// The statement of every item, by table and name.
+    statements: HashMap<Key, String>,
+    /// The fingerprints of the declarations that verified.
+    passed: HashMap<Key, Fingerprint>,
+}
+
+impl Incremental {
+    fn keyed<'a>(outline: &Outline<'a>) -> Vec<Option<(Key, Fingerprint)>> {
+        outline.declarations
+            .iter()
+            .map(|(stmt, proof)| Some(((table(*stmt), outline.key(*stmt).ok()?), outline.fingerprint(*stmt, *proof).ok()?)))
+            .collect()
+    }
+
+    /// The declarations of `outline` that have to be checked again, in order.
+    pub fn stale<'a>(&self, outline: &Outline<'a>) -> Vec<(StmtCmd, ProofIter<'a>)> {
+        let keyed = Incremental::keyed(outline);
+        let unchanged = |idx: usize| match keyed.get(idx) {
+            Some(Some((key, fp))) => self.statements.get(key) == Some(&fp.statement),
+            _ => false,
+        };
+        let names = keyed.iter().flatten().map(|(key, _)| key).collect::<Vec<_>>();
+        let distinct = names.iter().collect::<std::collections::HashSet<_>>().len() == names.len();
+        let sorts_unchanged = outline.declarations
+            .iter()
+            .enumerate()
+            .all(|(idx, (stmt, _))| !matches!(stmt, StmtCmd::Sort {..}) || unchanged(idx));
+        if !distinct || !sorts_unchanged {
+            return outline.declarations.clone()
+        }
+        let (term_pos, thm_pos) = outline.decl_positions();
+        let dep_ok = |idx: usize, pos: Option<&usize>| pos.map(|pos| *pos < idx && unchanged(*pos)).unwrap_or(false);
+        outline.declarations
+            .iter()
+            .enumerate()
+            .filter(|(idx, (stmt, proof))| {
+                let fresh = match &keyed[*idx] {
+                    Some((key, fp)) => self.passed.get(key) == Some(fp),
+                    None => false,
+                };
+                let deps_fresh = || match outline.direct_deps(*stmt, *proof) {
+                    Ok(deps) =>
+                        deps.terms.iter().all(|t| dep_ok(*idx, term_pos.get(*t as usize)))
+                        && deps.thms.iter().all(|t| dep_ok(*idx, thm_pos.get(*t as usize))),
+                    Err(_) => false,
+                };
+                !(fresh && deps_fresh())
+            })
+            .map(|(_, decl)| *decl)
+            .collect()
+    }
+
+    /// Verify the stale declarations of `outline` and remember the outcome for next time.
+    /// Returns the errors and the number of declarations that were checked.
+    pub fn verify<'a>(&mut self, outline: &'a Outline<'a>) -> (Vec<Located>, usize) {
+        let stale = self.stale(outline);
+        let errs = outline.verify_located(&stale);
+        let failed = errs.iter().filter_map(|l| l.decl).collect::<Vec<_>>();
+        self.statements.clear();
+        self.passed.clear();
+        for ((stmt, _), keyed) in outline.declarations.iter().zip(Incremental::keyed(outline)) {
+            if let Some((key, fp)) = keyed {
+                self.statements.insert(key.clone(), fp.statement.clone());
+                if !failed.contains(stmt) {
+                    self.passed.insert(key, fp);
+                }
+            }
+        }
+        (errs, stale.len())
+    }
+}
+
+#[test]
+fn watch1() {
+    let file_data = crate::fs::FileData::new_from_bytes(std::fs::read("./test_resources/short.mmb").unwrap(), Vec::new());
+    let outline = Outline::new_from(&file_data).unwrap();
+    let mut inc = Incremental::default();
+    let (errs, checked) = inc.verify(&outline);
+    assert!(errs.is_empty());
+    assert_eq!(checked, outline.declarations.len());
+    assert!(inc.stale(&outline).is_empty());
+
+    // Renumbering by dropping declarations doesn't make the rest stale.
+    let ax_2 = outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some("ax_2")).unwrap();
+    let ax_mp = outline.declarations.iter().position(|(s, _)| outline.stmt_name(*s) == Some("ax_mp")).unwrap();
+    let sliced = crate::fs::FileData::new_from_bytes(outline.slice(&[ax_2, ax_mp]).unwrap(), Vec::new());
+    let sliced = Outline::new_from(&sliced).unwrap();
+    assert!(inc.stale(&sliced).is_empty());
+
+    // Changing a sort makes everything stale.
+    let mut bytes = file_data.mmb_file.clone();
+    bytes[outline.header.sort_data_start as usize] ^= crate::mmb::SORT_STRICT;
+    let changed = crate::fs::FileData::new_from_bytes(bytes, Vec::new());
+    let changed = Outline::new_from(&changed).unwrap();
+    assert_eq!(inc.stale(&changed).len(), changed.declarations.len());
+}